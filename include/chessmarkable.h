@@ -0,0 +1,76 @@
+/* Generated with cbindgen from src/ffi.rs (see cbindgen.toml). Don't edit by hand. */
+
+#ifndef CHESSMARKABLE_H
+#define CHESSMARKABLE_H
+
+#include <stdint.h>
+
+/*
+ * No function lets a Rust panic unwind into C. Functions that panic anyway
+ * return like for invalid input: NULL instead of a pointer and -1 instead
+ * of a number.
+ */
+
+#define CHESSMARKABLE_WHITE 0
+#define CHESSMARKABLE_BLACK 1
+
+/* Outcomes returned by chessmarkable_game_outcome() */
+#define CHESSMARKABLE_RUNNING -1
+#define CHESSMARKABLE_CHECKMATE 0
+#define CHESSMARKABLE_RESIGNATION 1
+#define CHESSMARKABLE_TIMEOUT 2
+#define CHESSMARKABLE_STALEMATE 3
+#define CHESSMARKABLE_REPETITION 4
+#define CHESSMARKABLE_FIFTY_MOVES 5
+#define CHESSMARKABLE_INSUFFICIENT_MATERIAL 6
+#define CHESSMARKABLE_AGREEMENT 7
+#define CHESSMARKABLE_ABORTED 8
+
+typedef struct ChessGame ChessGame;
+
+/**
+ * A new game from `fen` or the starting position if it's `NULL`.
+ * `NULL` if the FEN is invalid or the position impossible (e.g. without
+ * kings).
+ */
+ChessGame *chessmarkable_game_new(const char *fen);
+
+void chessmarkable_game_free(ChessGame *game);
+
+void chessmarkable_string_free(char *string);
+
+char *chessmarkable_game_fen(const ChessGame *game);
+
+/**
+ * 0 for white and 1 for black
+ */
+int chessmarkable_game_turn(const ChessGame *game);
+
+/**
+ * The legal moves separated by spaces (e.g. "e2e4 g1f3")
+ */
+char *chessmarkable_game_possible_moves(const ChessGame *game);
+
+/**
+ * 0 if the move was played and -1 if it isn't legal
+ */
+int chessmarkable_game_move(ChessGame *game, const char *uci);
+
+/**
+ * Let the bot play the next move with the given search depth (capped
+ * to 8). 0 if a move was played and -1 if the game is over.
+ */
+int chessmarkable_game_bot_move(ChessGame *game, uint16_t depth);
+
+/**
+ * `CHESSMARKABLE_RUNNING` (-1) while the game goes on, one of the other
+ * `CHESSMARKABLE_*` outcomes otherwise
+ */
+int chessmarkable_game_outcome(const ChessGame *game);
+
+/**
+ * 0 for white, 1 for black and -1 if nobody won (yet)
+ */
+int chessmarkable_game_winner(const ChessGame *game);
+
+#endif /* CHESSMARKABLE_H */
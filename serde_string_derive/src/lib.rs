@@ -85,7 +85,7 @@ pub fn serde_display_fromstr(input: TokenStream) -> TokenStream {
             where
                 D: Deserializer<'de>,
             {
-                deserializer.deserialize_any(#accompanying_visitor { })
+                deserializer.deserialize_str(#accompanying_visitor { })
             }
         }
         // --------------------------------------------------
@@ -0,0 +1,172 @@
+use crate::game::ChessGame;
+use crate::replay::{positions, ReplayMove};
+use crate::Square;
+use anyhow::Result;
+use chess_pgn_parser::Game;
+use pleco::bot_prelude::AlphaBetaSearcher;
+use pleco::tools::eval::Eval;
+use pleco::tools::Searcher;
+use pleco::{Board, Player as PlecoPlayer, SQ};
+
+/// A game always gets at least this many critical moments (if it has enough moves)
+const MIN_CRITICAL_MOMENTS: usize = 3;
+const MAX_CRITICAL_MOMENTS: usize = 5;
+/// Swings (in centipawns) below this only count to reach `MIN_CRITICAL_MOMENTS`
+const SIGNIFICANT_SWING: i32 = 100;
+/// Search depth used to find the move that should have been played instead
+const BEST_MOVE_DEPTH: u16 = 4;
+
+#[derive(Clone, Debug)]
+pub struct CriticalMoment {
+    /// Index of the half-move in the game (starting at 0)
+    pub ply: usize,
+    /// Position before the move was played
+    pub fen: String,
+    pub played_move: (Square, Square),
+    /// What the engine would have played instead
+    pub best_move: Option<(Square, Square)>,
+    /// Evaluation change caused by the played move in centipawns (positive favors white)
+    pub swing: i32,
+}
+
+/// How many pieces of each side attack a square
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SquareControl {
+    pub white: u8,
+    pub black: u8,
+}
+
+impl SquareControl {
+    /// Positive if white has more attackers
+    pub fn balance(self) -> i8 {
+        self.white as i8 - self.black as i8
+    }
+}
+
+/// Attackers of every square of the position (indexed like `Square`).
+/// Pinned pieces and the side not to move count as well.
+pub fn square_control(board: &Board) -> Vec<SquareControl> {
+    let occupied = board.occupied();
+    let white = board.get_occupied_player(PlecoPlayer::White);
+    let black = board.get_occupied_player(PlecoPlayer::Black);
+    (0..64)
+        .map(|index| {
+            let attackers = board.attackers_to(SQ(index), occupied);
+            SquareControl {
+                white: (attackers & white).count_bits(),
+                black: (attackers & black).count_bits(),
+            }
+        })
+        .collect()
+}
+
+/// Evaluation of the position without searching in centipawns from whites
+/// point of view. Only rough, but cheap enough to show after every move.
+pub fn static_eval(board: &Board) -> i32 {
+    let eval = Eval::eval_low(board);
+    match board.turn() {
+        PlecoPlayer::White => eval,
+        PlecoPlayer::Black => -eval,
+    }
+}
+
+/// Evaluation after every move in centipawns from whites point of view
+/// (e.g. for `critical_moments()` or a graph). Taken from the `[%eval]`
+/// comments of the moves, the positions of moves without one are
+/// evaluated statically.
+pub fn move_evals(moves: &[ReplayMove]) -> Vec<i32> {
+    moves
+        .iter()
+        .map(|replay_move| match replay_move.eval {
+            Some(eval) => eval.centipawns(),
+            None => Board::from_fen(&replay_move.fen)
+                .map(|board| static_eval(&board))
+                .unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Find the largest evaluation swings of a game.
+///
+/// `evals` contains the evaluation after every half-move in centipawns from
+/// whites point of view. Moves without an evaluation are ignored.
+/// The critical moments are returned in the order they were played.
+pub fn critical_moments(game: &Game, evals: &[i32]) -> Result<Vec<CriticalMoment>> {
+    let mut positions = positions(game)?;
+    let mut candidates = Vec::new();
+    let mut previous_eval = 0;
+    for (ply, eval) in evals.iter().enumerate().take(game.moves.len()) {
+        let position = positions
+            .next()
+            .ok_or_else(|| anyhow!("Failed to replay half-move {}", ply + 1))?;
+        candidates.push(CriticalMoment {
+            ply,
+            fen: position.fen_before,
+            played_move: (
+                position.bit_move.get_src().into(),
+                position.bit_move.get_dest().into(),
+            ),
+            best_move: None,
+            swing: eval - previous_eval,
+        });
+        previous_eval = *eval;
+    }
+
+    candidates.sort_by_key(|moment| -moment.swing.abs());
+    let mut moments: Vec<CriticalMoment> = candidates
+        .into_iter()
+        .enumerate()
+        .take_while(|(i, moment)| {
+            *i < MIN_CRITICAL_MOMENTS
+                || (*i < MAX_CRITICAL_MOMENTS && moment.swing.abs() >= SIGNIFICANT_SWING)
+        })
+        .map(|(_, moment)| moment)
+        .collect();
+    moments.sort_by_key(|moment| moment.ply);
+
+    for moment in moments.iter_mut() {
+        let best_move = AlphaBetaSearcher::best_move(
+            ChessGame::from_fen(&moment.fen)?.board(),
+            BEST_MOVE_DEPTH,
+        );
+        if !best_move.is_null() {
+            moment.best_move = Some((best_move.get_src().into(), best_move.get_dest().into()));
+        }
+    }
+    Ok(moments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::Replay;
+
+    #[test]
+    fn square_control_of_starting_position() {
+        let control = square_control(&Board::default());
+        let at = |square: SQ| control[square.0 as usize];
+        assert_eq!(at(SQ::E3), SquareControl { white: 2, black: 0 });
+        // Pawns on e2 and g2 and the knight on g1
+        assert_eq!(at(SQ::F3), SquareControl { white: 3, black: 0 });
+        assert_eq!(at(SQ::F6), SquareControl { white: 0, black: 3 });
+        assert_eq!(at(SQ::E4), SquareControl::default());
+        assert_eq!(at(SQ::F6).balance(), -3);
+    }
+
+    #[test]
+    fn move_evals_prefer_comments() {
+        let game =
+            chess_pgn_parser::read_games("1. e4 { [%eval 0.3] } d5 2. exd5 { [%eval #4] } *")
+                .unwrap()
+                .remove(0);
+        let moves = Replay::new(game).unwrap().moves();
+        let evals = move_evals(&moves);
+        assert_eq!(evals.len(), 3);
+        assert_eq!(evals[0], 30);
+        assert_eq!(
+            evals[1],
+            static_eval(&Board::from_fen(&moves[1].fen).unwrap())
+        );
+        assert_eq!(evals[2], 10_000);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::Square;
+
+/// Mark drawn onto the board while analysing a position
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Annotation {
+    Arrow { from: Square, to: Square },
+    Circle { square: Square },
+}
+
+/// Everything written down about a single position of a game
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PositionAnnotations {
+    pub marks: Vec<Annotation>,
+    pub note: Option<String>,
+}
+
+impl PositionAnnotations {
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty() && self.note.is_none()
+    }
+}
+
+/// Add `annotation` or remove it if it already exists (drawing it twice erases it)
+pub fn toggle(annotations: &mut Vec<Annotation>, annotation: Annotation) {
+    match annotations.iter().position(|a| *a == annotation) {
+        Some(index) => {
+            annotations.remove(index);
+        }
+        None => annotations.push(annotation),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggles_annotations() {
+        let arrow = Annotation::Arrow {
+            from: "E2".parse().unwrap(),
+            to: "E4".parse().unwrap(),
+        };
+        let circle = Annotation::Circle {
+            square: "D5".parse().unwrap(),
+        };
+        let mut annotations = vec![];
+        toggle(&mut annotations, arrow);
+        toggle(&mut annotations, circle);
+        assert_eq!(annotations, vec![arrow, circle]);
+        toggle(&mut annotations, arrow);
+        assert_eq!(annotations, vec![circle]);
+    }
+}
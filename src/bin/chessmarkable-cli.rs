@@ -0,0 +1,213 @@
+//! Plays games in the terminal, against the bot or between two engines.
+//! Meant to try the chess logic off the device.
+
+use anyhow::{bail, ensure, Context, Result};
+use chessmarkable::game::ChessGame;
+use chessmarkable::notation::{parse_san, san};
+use chessmarkable::scoresheet;
+use chessmarkable::tournament::{Engine, Participant};
+use chessmarkable::Player;
+use clap::{crate_authors, crate_version, Parser};
+use std::io::{self, BufRead, Write};
+
+#[derive(Parser)]
+#[clap(version = crate_version ! (), author = crate_authors ! ())]
+struct Opts {
+    #[clap(
+        long,
+        default_value = "human",
+        about = "Who plays white: human, alphabeta, jamboree or iterative"
+    )]
+    white: String,
+
+    #[clap(
+        long,
+        default_value = "alphabeta",
+        about = "Who plays black: human, alphabeta, jamboree or iterative"
+    )]
+    black: String,
+
+    #[clap(long, default_value = "4", about = "Search depth of the engines")]
+    depth: u16,
+
+    #[clap(long, about = "Start from this position instead of the starting one")]
+    fen: Option<String>,
+}
+
+fn participant(name: &str, depth: u16) -> Result<Option<Participant>> {
+    let engine = match name.to_lowercase().as_str() {
+        "human" => return Ok(None),
+        "alphabeta" => Engine::AlphaBeta,
+        "jamboree" => Engine::Jamboree,
+        "iterative" => Engine::Iterative,
+        _ => bail!("Unknown player \"{}\"", name),
+    };
+    Ok(Some(Participant { engine, depth }))
+}
+
+/// The board with white at the bottom, uppercase letters being white pieces
+fn ascii_board(fen: &str) -> String {
+    let mut board = String::new();
+    let placement = fen.split(' ').next().unwrap_or_default();
+    for (row, rank) in placement.split('/').enumerate() {
+        board.push_str(&format!("{} ", 8 - row));
+        for piece in rank.chars() {
+            match piece.to_digit(10) {
+                Some(empty) => (0..empty).for_each(|_| board.push_str(" .")),
+                None => board.push_str(&format!(" {}", piece)),
+            }
+        }
+        board.push('\n');
+    }
+    board.push_str("   a b c d e f g h\n");
+    board
+}
+
+/// A line of the human to move. `None` when stdin was closed.
+fn read_line(prompt: &str) -> Result<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush().context("Flush stdout")?;
+    let mut line = String::new();
+    if io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("Read stdin")?
+        == 0
+    {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_owned()))
+}
+
+/// Let the human play a move (in UCI or SAN) or run a command. Undo takes
+/// back `undo_moves` (two against an engine). Returns false when the game
+/// should end.
+fn human_turn(game: &mut ChessGame, moves: &mut Vec<String>, undo_moves: u16) -> Result<bool> {
+    loop {
+        let line = match read_line(&format!("{} to move> ", game.turn()))? {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+        match line.as_str() {
+            "" => continue,
+            "quit" | "exit" => return Ok(false),
+            "help" => println!(
+                "Moves are written as UCI (e2e4, e7e8q) or SAN (Nf3, O-O). \
+                 Commands: moves, undo, fen, quit"
+            ),
+            "fen" => println!("{}", game.fen()),
+            "moves" => {
+                let board = game.board();
+                let possible_moves: Vec<String> = game
+                    .possible_moves()
+                    .iter()
+                    .map(|bit_move| san(&board, *bit_move))
+                    .collect();
+                println!("{}", possible_moves.join(" "));
+            }
+            "undo" => match game.undo(undo_moves) {
+                Ok(()) => {
+                    moves.truncate(moves.len().saturating_sub(undo_moves as usize));
+                    println!("{}", ascii_board(&game.fen()));
+                }
+                Err(err) => println!("{}", err),
+            },
+            written => {
+                let board = game.board();
+                let bit_move = match game
+                    .possible_moves()
+                    .into_iter()
+                    .find(|bit_move| bit_move.stringify() == written.to_lowercase())
+                {
+                    Some(bit_move) => bit_move,
+                    None => match parse_san(&board, written) {
+                        Ok(bit_move) => bit_move,
+                        Err(err) => {
+                            println!("{} (type help for help)", err);
+                            continue;
+                        }
+                    },
+                };
+                moves.push(san(&board, bit_move));
+                game.move_piece_uci(&bit_move.stringify())?;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let players = [
+        participant(&opts.white, opts.depth)?,
+        participant(&opts.black, opts.depth)?,
+    ];
+    let mut game = match opts.fen {
+        Some(ref fen) => ChessGame::from_fen(fen)?,
+        None => ChessGame::default(),
+    };
+    let starting_fen = game.fen();
+    let mut moves: Vec<String> = vec![];
+
+    println!("{}", ascii_board(&game.fen()));
+    while game.outcome().is_none() {
+        let player = match game.turn() {
+            Player::White => &players[0],
+            Player::Black => &players[1],
+        };
+        match player {
+            Some(participant) => {
+                let board = game.board();
+                let bit_move = participant
+                    .engine
+                    .best_move(board.shallow_clone(), participant.depth);
+                ensure!(!bit_move.is_null(), "{} found no move", participant.name());
+                let written = san(&board, bit_move);
+                game.move_piece_uci(&bit_move.stringify())?;
+                println!(
+                    "{} ({}) plays {}",
+                    game.turn().other_player(),
+                    participant.name(),
+                    written
+                );
+                moves.push(written);
+            }
+            None => {
+                let undo_moves = if players.iter().any(Option::is_some) {
+                    2
+                } else {
+                    1
+                };
+                if !human_turn(&mut game, &mut moves, undo_moves)? {
+                    break;
+                }
+            }
+        }
+        println!("{}", ascii_board(&game.fen()));
+        println!("FEN: {}\n", game.fen());
+    }
+
+    let result = match game.outcome() {
+        Some(outcome) => {
+            println!("{}", outcome.description());
+            outcome.pgn_result()
+        }
+        None => "*",
+    };
+    let set_up = starting_fen != ChessGame::default().fen();
+    print!(
+        "\n{}",
+        scoresheet::to_pgn(
+            &moves,
+            &[
+                ("Event", "chessmarkable-cli"),
+                ("White", opts.white.as_str()),
+                ("Black", opts.black.as_str()),
+                ("Result", result),
+                ("SetUp", if set_up { "1" } else { "" }),
+                ("FEN", if set_up { starting_fen.as_str() } else { "" }),
+            ],
+        )
+    );
+    Ok(())
+}
@@ -0,0 +1,557 @@
+//! Terminal UI to play against the bot through the same game loop as the
+//! app (`proto::create_game()` and `create_bot()`), so the whole game flow
+//! (undos, clocks, outcomes) can be tried without a reMarkable:
+//!
+//!   cargo run --bin chessmarkable-tui --no-default-features --features tui
+
+use anyhow::{bail, Context, Result};
+use chessmarkable::clock::format_time;
+use chessmarkable::game::ALL_PROMOTION_PIECES;
+use chessmarkable::proto::*;
+use chessmarkable::scoresheet;
+use chessmarkable::search;
+use chessmarkable::{Player, Square};
+use clap::{crate_authors, crate_version, Parser};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use pleco::{Board, Piece};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Span, Spans};
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::runtime;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+#[derive(Parser)]
+#[clap(version = crate_version ! (), author = crate_authors ! ())]
+struct Opts {
+    #[clap(long, default_value = "white", about = "The side you play")]
+    color: Player,
+
+    #[clap(
+        long,
+        default_value = "alphabeta",
+        about = "Engine of the bot: alphabeta or jamboree (multithreaded)"
+    )]
+    engine: String,
+
+    #[clap(long, default_value = "3", about = "Search depth of the bot")]
+    depth: u16,
+
+    #[clap(long, about = "Play with a clock of this many minutes per player")]
+    minutes: Option<u64>,
+
+    #[clap(
+        long,
+        default_value = "0",
+        about = "Seconds added to the clock per move"
+    )]
+    increment: u64,
+
+    #[clap(long, about = "Start from this position instead of the starting one")]
+    fen: Option<String>,
+    #[clap(long, about = "Let the bot play the same moves every run")]
+    seed: Option<u64>,
+}
+
+/// How long to wait for a key before redrawing (so the clock keeps running)
+const TICK: Duration = Duration::from_millis(100);
+
+struct App {
+    me: Player,
+    board: Board,
+    turn: Player,
+    in_check: bool,
+    /// Only filled on the own turn
+    possible_moves: Vec<PossibleMove>,
+    last_move: Option<(Square, Square)>,
+    /// File and rank (from 0) of the square the cursor is on
+    cursor: (u8, u8),
+    selected: Option<Square>,
+    promotion: PromotionPiece,
+    /// Played moves in SAN
+    moves: Vec<String>,
+    outcome: Option<ChessOutcome>,
+    /// Last snapshot and when it arrived
+    clock: Option<(ClockSnapshot, Instant)>,
+    message: String,
+    request_tx: Sender<ChessRequest>,
+}
+
+impl App {
+    fn new(me: Player, request_tx: Sender<ChessRequest>) -> Self {
+        Self {
+            me,
+            board: Board::default(),
+            turn: Player::White,
+            in_check: false,
+            possible_moves: vec![],
+            last_move: None,
+            cursor: match me {
+                Player::White => (4, 1),
+                Player::Black => (4, 6),
+            },
+            selected: None,
+            promotion: PromotionPiece::Queen,
+            moves: vec![],
+            outcome: None,
+            clock: None,
+            message: "Arrows to move the cursor, enter to pick up and put down a piece. \
+                      p: promotion piece, u: undo, d: offer draw, r: resign, q: quit"
+                .to_owned(),
+            request_tx,
+        }
+    }
+
+    fn send(&mut self, request: ChessRequest) {
+        if self.request_tx.try_send(request).is_err() {
+            self.message = "The game doesn't take requests anymore".to_owned();
+        }
+    }
+
+    fn update_board(&mut self, fen: &str) {
+        match Board::from_fen(fen) {
+            Ok(board) => self.board = board,
+            Err(err) => self.message = format!("Received an invalid board: {:?}", err),
+        }
+    }
+
+    fn handle_update(&mut self, update: ChessUpdate) {
+        match update {
+            ChessUpdate::Board { fen } => self.update_board(&fen),
+            ChessUpdate::PlayerMovedAPiece {
+                player,
+                moved_piece_source,
+                moved_piece_destination,
+                san,
+            } => {
+                self.last_move = Some((moved_piece_source, moved_piece_destination));
+                let written = san.unwrap_or_else(|| {
+                    format!("{}{}", moved_piece_source, moved_piece_destination).to_lowercase()
+                });
+                if player != self.me {
+                    self.message = format!("{} played {}", player, written);
+                }
+                self.moves.push(written);
+            }
+            ChessUpdate::PlayerSwitch {
+                player,
+                fen,
+                in_check,
+            } => {
+                self.update_board(&fen);
+                self.turn = player;
+                self.in_check = in_check;
+                if player != self.me {
+                    self.possible_moves.clear();
+                }
+            }
+            ChessUpdate::FullSync {
+                fen,
+                turn,
+                outcome,
+                in_check,
+                possible_moves,
+                ..
+            } => {
+                self.update_board(&fen);
+                self.turn = turn;
+                self.outcome = outcome;
+                self.in_check = in_check;
+                self.possible_moves = possible_moves;
+            }
+            ChessUpdate::PossibleMoves { possible_moves } => self.possible_moves = possible_moves,
+            ChessUpdate::MovePieceFailedResponse { message, fen } => {
+                self.update_board(&fen);
+                self.message = message;
+            }
+            ChessUpdate::Outcome { outcome } => {
+                if let Some(outcome) = outcome {
+                    self.message =
+                        format!("{} Undo to continue or q to quit.", outcome.description());
+                }
+                self.outcome = outcome;
+            }
+            ChessUpdate::MovesUndone {
+                who,
+                moves,
+                last_move,
+            } => {
+                let played = self.moves.len().saturating_sub(moves as usize);
+                self.moves.truncate(played);
+                self.last_move = last_move;
+                self.selected = None;
+                self.message = format!("{} took back {} move(s)", who, moves);
+            }
+            ChessUpdate::GenericErrorResponse { message }
+            | ChessUpdate::UndoMovesFailedResponse { message } => self.message = message,
+            ChessUpdate::DrawOffered { player } => {
+                if player != self.me {
+                    self.message = format!("{} offers a draw (d to accept)", player);
+                }
+            }
+            ChessUpdate::Clock { clock } => self.clock = Some((clock, Instant::now())),
+            ChessUpdate::Thinking {
+                player,
+                depth,
+                eval,
+                ..
+            } => {
+                if player != self.me {
+                    self.message = format!(
+                        "{} is thinking... depth {}, eval {:+.1}",
+                        player,
+                        depth,
+                        eval as f32 / 100.0
+                    );
+                }
+            }
+            ChessUpdate::CurrentTotalMovesReponse { .. }
+            | ChessUpdate::CurrentVariantResponse { .. }
+            | ChessUpdate::PossibleMovesFromResponse { .. }
+            | ChessUpdate::PlayerDroppedAPiece { .. }
+            | ChessUpdate::Pockets { .. }
+            | ChessUpdate::PossibleDrops { .. } => {}
+        }
+    }
+
+    fn cursor_square(&self) -> Square {
+        Square::new(self.cursor.0 as usize, self.cursor.1 as usize).unwrap()
+    }
+
+    /// Moves of the selected piece
+    fn targets(&self) -> Vec<Square> {
+        match self.selected {
+            Some(selected) => self
+                .possible_moves
+                .iter()
+                .filter(|possible_move| possible_move.source == selected)
+                .map(|possible_move| possible_move.destination)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Pick up the piece under the cursor or put the picked up one there
+    fn select(&mut self) {
+        let square = self.cursor_square();
+        if self.selected == Some(square) {
+            self.selected = None;
+        } else if self.targets().contains(&square) {
+            let source = self.selected.take().unwrap();
+            let is_promotion = self.possible_moves.iter().any(|possible_move| {
+                possible_move.source == source
+                    && possible_move.destination == square
+                    && possible_move.promotion
+            });
+            self.send(ChessRequest::MovePiece {
+                source,
+                destination: square,
+                promotion: if is_promotion {
+                    Some(self.promotion)
+                } else {
+                    None
+                },
+            });
+        } else if self
+            .possible_moves
+            .iter()
+            .any(|possible_move| possible_move.source == square)
+        {
+            self.selected = Some(square);
+        } else {
+            self.selected = None;
+        }
+    }
+
+    /// Take back the own last move (and the answer of the bot to it)
+    fn undo(&mut self) {
+        let moves = if self.turn == self.me { 2 } else { 1 };
+        self.selected = None;
+        self.send(ChessRequest::UndoMoves { moves });
+    }
+
+    /// Returns false to quit
+    fn on_key(&mut self, key: KeyCode) -> bool {
+        // Rank 0 is at the bottom for white
+        let (up, right): (i8, i8) = match self.me {
+            Player::White => (1, 1),
+            Player::Black => (-1, -1),
+        };
+        let (file, rank) = self.cursor;
+        let step = |value: u8, by: i8| (value as i8 + by).max(0).min(7) as u8;
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = (file, step(rank, up)),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor = (file, step(rank, -up)),
+            KeyCode::Right | KeyCode::Char('l') => self.cursor = (step(file, right), rank),
+            KeyCode::Left | KeyCode::Char('h') => self.cursor = (step(file, -right), rank),
+            KeyCode::Enter | KeyCode::Char(' ') => self.select(),
+            KeyCode::Char('p') => {
+                let index = ALL_PROMOTION_PIECES
+                    .iter()
+                    .position(|piece| *piece == self.promotion)
+                    .unwrap_or_default();
+                self.promotion = ALL_PROMOTION_PIECES[(index + 1) % ALL_PROMOTION_PIECES.len()];
+                self.message = format!("Pawns get promoted to a {}", self.promotion.label());
+            }
+            KeyCode::Char('u') => self.undo(),
+            KeyCode::Char('d') => self.send(ChessRequest::OfferDraw),
+            KeyCode::Char('r') => self.send(ChessRequest::Resign),
+            _ => {}
+        }
+        true
+    }
+}
+
+fn board_lines(app: &App) -> Vec<Spans<'static>> {
+    let targets = app.targets();
+    let mut ranks: Vec<u8> = (0..8).collect();
+    let mut files: Vec<u8> = (0..8).collect();
+    match app.me {
+        Player::White => ranks.reverse(),
+        Player::Black => files.reverse(),
+    }
+
+    let mut lines = vec![];
+    for rank in ranks {
+        let mut spans = vec![Span::raw(format!("{} ", rank + 1))];
+        for &file in &files {
+            let square = Square::new(file as usize, rank as usize).unwrap();
+            let piece = app.board.piece_at_sq(*square);
+            let is_light = (file + rank) % 2 == 1;
+            let background = if app.cursor == (file, rank) {
+                Color::LightBlue
+            } else if app.selected == Some(square) {
+                Color::Blue
+            } else if targets.contains(&square) {
+                Color::Green
+            } else if app.in_check
+                && piece.character_lossy() == if app.turn == Player::White { 'K' } else { 'k' }
+            {
+                Color::Red
+            } else if app.last_move.map_or(false, |(source, destination)| {
+                source == square || destination == square
+            }) {
+                Color::Yellow
+            } else if is_light {
+                Color::Gray
+            } else {
+                Color::DarkGray
+            };
+            let (text, foreground) = if piece == Piece::None {
+                ("   ".to_owned(), Color::Black)
+            } else {
+                let character = piece.character_lossy();
+                let foreground = if character.is_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                (format!(" {} ", character.to_ascii_uppercase()), foreground)
+            };
+            spans.push(Span::styled(
+                text,
+                Style::default()
+                    .fg(foreground)
+                    .bg(background)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        }
+        lines.push(Spans::from(spans));
+    }
+    let file_names: String = files
+        .iter()
+        .map(|file| format!(" {} ", (b'a' + file) as char))
+        .collect();
+    lines.push(Spans::from(format!("  {}", file_names)));
+    lines
+}
+
+fn info_lines(app: &App) -> Vec<Spans<'static>> {
+    let mut lines = vec![];
+    if let Some((snapshot, received)) = app.clock {
+        let elapsed = received.elapsed();
+        for &player in &[Player::White, Player::Black] {
+            let mut text = format!(
+                "{:<6} {:>8}",
+                player.to_string(),
+                format_time(snapshot.remaining(player, elapsed))
+            );
+            if snapshot.running == Some(player) {
+                text.push_str(" <");
+            }
+            lines.push(Spans::from(text));
+        }
+        lines.push(Spans::from(""));
+    }
+    let status = match app.outcome {
+        Some(outcome) => outcome.description(),
+        None if app.turn == app.me => format!("Your turn ({})", app.me),
+        None => "The bot is thinking...".to_owned(),
+    };
+    lines.push(Spans::from(Span::styled(
+        status,
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+    lines.push(Spans::from(format!(
+        "Promotion to: {}",
+        app.promotion.label()
+    )));
+    lines.push(Spans::from(""));
+    for (number, pair) in app.moves.chunks(2).enumerate() {
+        lines.push(Spans::from(format!(
+            "{:>3}. {:<8}{}",
+            number + 1,
+            pair[0],
+            pair.get(1).map(String::as_str).unwrap_or_default()
+        )));
+    }
+    lines
+}
+
+fn draw<B: Backend>(frame: &mut Frame<B>, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(11), Constraint::Length(4)].as_ref())
+        .split(frame.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(30), Constraint::Min(20)].as_ref())
+        .split(rows[0]);
+
+    frame.render_widget(
+        Paragraph::new(board_lines(app)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("chessmarkable"),
+        ),
+        columns[0],
+    );
+    frame.render_widget(
+        Paragraph::new(info_lines(app)).block(Block::default().borders(Borders::ALL)),
+        columns[1],
+    );
+    frame.render_widget(
+        Paragraph::new(app.message.clone())
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL)),
+        rows[1],
+    );
+}
+
+fn create_engine_bot(
+    runtime: &mut runtime::Runtime,
+    threads: usize,
+    me: Player,
+    depth: u16,
+    seed: Option<u64>,
+) -> Result<PlayerEndpoint> {
+    let reaction_delay = Duration::from_millis(300);
+    runtime.block_on(create_bot(me, depth, threads, reaction_delay, seed))
+}
+
+fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    update_rx: &mut Receiver<ChessUpdate>,
+) -> Result<()> {
+    loop {
+        while let Ok(update) = update_rx.try_recv() {
+            app.handle_update(update);
+        }
+        terminal.draw(|frame| draw(frame, app))?;
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.on_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    // Both search like pleco's engines of the same name, but can be stopped
+    let threads = match opts.engine.to_lowercase().as_str() {
+        "alphabeta" => 1,
+        "jamboree" => search::all_cores(),
+        _ => bail!("Unknown engine \"{}\"", opts.engine),
+    };
+
+    let mut runtime = runtime::Builder::new()
+        .thread_name("tokio_tui")
+        .threaded_scheduler()
+        .enable_time() // For the clock
+        .build()
+        .context("Failed to create tokio runtime")?;
+
+    let (update_tx, mut update_rx) = channel::<ChessUpdate>(256);
+    let (request_tx, request_rx) = channel::<ChessRequest>(256);
+    let bot = create_engine_bot(
+        &mut runtime,
+        threads,
+        opts.color.other_player(),
+        opts.depth,
+        opts.seed,
+    )?;
+    let (white, black) = match opts.color {
+        Player::White => ((update_tx, request_rx), bot),
+        Player::Black => (bot, (update_tx, request_rx)),
+    };
+    runtime.spawn(create_game(
+        white,
+        black,
+        stubbed_spectator(),
+        ChessConfig {
+            starting_fen: opts.fen.clone(),
+            can_black_undo: opts.color == Player::Black,
+            can_white_undo: opts.color == Player::White,
+            allow_undo_after_loose: true,
+            variant: VariantKind::Standard,
+            time_control: opts
+                .minutes
+                .map(|minutes| TimeControl::fischer(minutes, opts.increment)),
+            starting_clock: None,
+        },
+    ));
+
+    enable_raw_mode().context("Enable raw mode")?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let mut app = App::new(opts.color, request_tx);
+    let result = run(&mut terminal, &mut app, &mut update_rx);
+
+    disable_raw_mode().ok();
+    execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
+    terminal.show_cursor().ok();
+    result?;
+
+    let result = match app.outcome {
+        Some(outcome) => {
+            println!("{}", outcome.description());
+            outcome.pgn_result()
+        }
+        None => "*",
+    };
+    if !app.moves.is_empty() {
+        print!(
+            "\n{}",
+            scoresheet::to_pgn(
+                &app.moves,
+                &[("Event", "chessmarkable-tui"), ("Result", result)]
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use crate::savestates::SavedClock;
+use crate::scene::{GameMode, SavestateSlot};
+use anyhow::{Context, Result};
+use chessmarkable::game::VariantKind;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::Mutex;
+
+lazy_static! {
+    /// What was last written, to write it again in case of a crash
+    static ref LAST_WRITTEN: Mutex<Option<Autosave>> = Mutex::new(None);
+}
+
+/// The game in progress. It's written after every move (apart from the
+/// savestate slots) and offered to be continued in the main menu.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Autosave {
+    pub game_mode: GameMode,
+    /// Where the game gets saved to on "Save & Quit"
+    pub slot: SavestateSlot,
+    pub variant: VariantKind,
+    pub pvp_piece_rotation_enabled: bool,
+    pub fen: String,
+    /// As of the last move
+    #[serde(default)]
+    pub clock: Option<SavedClock>,
+}
+
+impl Autosave {
+    /// E.g. "Continue: Player vs Bot (Normal), move 24"
+    pub fn label(&self) -> String {
+        format!(
+            "Continue: {}, move {}",
+            self.game_mode.label(),
+            crate::savestates::move_number(&self.fen)
+        )
+    }
+}
+
+pub fn read() -> Result<Option<Autosave>> {
+    let ref file_path = crate::config::autosave_file();
+    if !file_path.exists() {
+        return Ok(None);
+    }
+    let file = std::fs::File::open(file_path).context("Open file")?;
+    let autosave = serde_yaml::from_reader(file).context("Deserialize file")?;
+    Ok(Some(autosave))
+}
+
+/// Written to a temporary file first and renamed to replace the old
+/// autosave. A crash or empty battery while writing can't corrupt it.
+pub fn write(autosave: &Autosave) -> Result<()> {
+    *LAST_WRITTEN.lock().unwrap() = Some(autosave.clone());
+    write_file(autosave)
+}
+
+fn write_file(autosave: &Autosave) -> Result<()> {
+    let ref file_path = crate::config::autosave_file();
+    let directory = file_path.parent().ok_or(anyhow!("No parent directory"))?;
+    if !directory.exists() {
+        std::fs::create_dir_all(directory).context("Create directory for file")?;
+    }
+
+    let temp_path = file_path.with_extension("tmp");
+    let mut file = std::fs::File::create(&temp_path).context("Create temporary file")?;
+    let yaml = serde_yaml::to_string(autosave).context("Serialize")?;
+    file.write_all(yaml.as_bytes())
+        .context("Write temporary file")?;
+    file.sync_all().context("Sync temporary file")?;
+    std::fs::rename(&temp_path, file_path).context("Replace file")
+}
+
+/// Nothing left to continue (e.g. the game is over)
+pub fn clear() -> Result<()> {
+    *LAST_WRITTEN.lock().unwrap() = None;
+    let ref file_path = crate::config::autosave_file();
+    if file_path.exists() {
+        std::fs::remove_file(file_path).context("Remove file")?;
+    }
+    Ok(())
+}
+
+/// Write the last autosave again (in case writing it failed). Called while
+/// panicking, so it doesn't wait for anything and only logs errors.
+pub fn flush() {
+    let autosave = match LAST_WRITTEN.try_lock() {
+        Ok(autosave) => autosave.clone(),
+        Err(_) => return,
+    };
+    if let Some(autosave) = autosave {
+        if let Err(err) = write_file(&autosave) {
+            eprintln!("Failed to write autosave file: {:?}", err);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::settings::Orientation;
+use anyhow::{Context, Result};
 use libremarkable::cgmath::vec2;
 pub use libremarkable::framebuffer::{
     cgmath::Point2, cgmath::Vector2, common::color, common::mxcfb_rect, common::DISPLAYHEIGHT,
@@ -8,16 +10,138 @@ use libremarkable::framebuffer::{
     common::display_temp, common::dither_mode, common::waveform_mode, refresh::PartialRefreshMode,
 };
 use libremarkable::image;
+use libremarkable::input::{multitouch::MultitouchEvent, wacom::WacomEvent, InputEvent};
+use rusttype::{point, Font, Scale};
 use std::ops::DerefMut;
+use std::path::Path;
+
+/// Distance between the baselines of wrapped lines relative to the font size
+const LINE_SPACING: f32 = 1.25;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FontStyle {
+    Regular,
+    /// Bold font if one was loaded or a thickened regular font otherwise
+    Bold,
+    /// Italic font if one was loaded or the regular font otherwise
+    Italic,
+}
 
 pub struct Canvas<'a> {
     framebuffer: Box<Framebuffer<'a>>,
+    /// Grayscale partial refreshes since the last full refresh. They
+    /// slowly leave ghosting behind.
+    partial_refreshes: u32,
+    /// Regions that were drawn but not refreshed yet
+    dirty_regions: Vec<mxcfb_rect>,
+    bold_font: Option<Font<'static>>,
+    italic_font: Option<Font<'static>>,
+    /// Scenes draw upright for this orientation. Positions are turned into
+    /// the ones of the framebuffer (which is always portrait) here.
+    orientation: Orientation,
 }
 
 impl<'a> Canvas<'a> {
     pub fn new() -> Self {
         Self {
             framebuffer: Box::new(Framebuffer::from_path("/dev/fb0")),
+            partial_refreshes: 0,
+            dirty_regions: vec![],
+            bold_font: None,
+            italic_font: None,
+            orientation: Orientation::Portrait,
+        }
+    }
+
+    /// Draw for the device being held in `orientation` from here on. Input
+    /// has to be turned the same way with `orient_input()`.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// Size of the screen in the current orientation
+    pub fn size(&self) -> Vector2<u32> {
+        match self.orientation {
+            Orientation::Portrait => Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: DISPLAYHEIGHT as u32,
+            },
+            Orientation::Landscape => Vector2 {
+                x: DISPLAYHEIGHT as u32,
+                y: DISPLAYWIDTH as u32,
+            },
+        }
+    }
+
+    /// Turn the positions of touch and pen input into the current orientation
+    pub fn orient_input(&self, mut event: InputEvent) -> InputEvent {
+        if self.orientation == Orientation::Landscape {
+            match event {
+                InputEvent::MultitouchEvent {
+                    event:
+                        MultitouchEvent::Press { ref mut finger }
+                        | MultitouchEvent::Release { ref mut finger }
+                        | MultitouchEvent::Move { ref mut finger },
+                } => {
+                    finger.pos = Point2 {
+                        x: finger.pos.y,
+                        y: (DISPLAYWIDTH - 1).saturating_sub(finger.pos.x),
+                    };
+                }
+                InputEvent::WacomEvent {
+                    event:
+                        WacomEvent::Hover {
+                            ref mut position, ..
+                        }
+                        | WacomEvent::Draw {
+                            ref mut position, ..
+                        },
+                } => {
+                    *position = Point2 {
+                        x: position.y,
+                        y: (DISPLAYWIDTH - 1) as f32 - position.x,
+                    };
+                }
+                _ => {}
+            }
+        }
+        event
+    }
+
+    /// Pixel of the framebuffer at `pos` of the current orientation
+    fn framebuffer_point(&self, pos: Point2<i32>) -> Point2<i32> {
+        match self.orientation {
+            Orientation::Portrait => pos,
+            Orientation::Landscape => Point2 {
+                x: DISPLAYWIDTH as i32 - 1 - pos.y,
+                y: pos.x,
+            },
+        }
+    }
+
+    /// Area of the framebuffer covered by `rect` of the current orientation
+    fn framebuffer_rect(&self, rect: &mxcfb_rect) -> mxcfb_rect {
+        match self.orientation {
+            Orientation::Portrait => *rect,
+            Orientation::Landscape => mxcfb_rect {
+                left: (DISPLAYWIDTH as u32).saturating_sub(rect.top + rect.height),
+                top: rect.left,
+                width: rect.height,
+                height: rect.width,
+            },
+        }
+    }
+
+    /// Inverse of `framebuffer_rect()`
+    fn oriented_rect(&self, rect: &mxcfb_rect) -> mxcfb_rect {
+        match self.orientation {
+            Orientation::Portrait => *rect,
+            Orientation::Landscape => mxcfb_rect {
+                left: rect.top,
+                top: (DISPLAYWIDTH as u32).saturating_sub(rect.left + rect.width),
+                width: rect.height,
+                height: rect.width,
+            },
         }
     }
 
@@ -34,6 +158,8 @@ impl<'a> Canvas<'a> {
     }
 
     pub fn update_full(&mut self) -> u32 {
+        self.partial_refreshes = 0;
+        self.dirty_regions.clear();
         self.framebuffer_mut().full_refresh(
             waveform_mode::WAVEFORM_MODE_GC16,
             display_temp::TEMP_USE_REMARKABLE_DRAW,
@@ -44,6 +170,13 @@ impl<'a> Canvas<'a> {
     }
 
     pub fn update_partial(&mut self, region: &mxcfb_rect) -> u32 {
+        let region = self.framebuffer_rect(region);
+        self.refresh_partial(&region)
+    }
+
+    /// `update_partial()` of a region of the framebuffer
+    fn refresh_partial(&mut self, region: &mxcfb_rect) -> u32 {
+        self.partial_refreshes += 1;
         self.framebuffer_mut().partial_refresh(
             region,
             PartialRefreshMode::Async,
@@ -55,87 +188,234 @@ impl<'a> Canvas<'a> {
         )
     }
 
+    /// Grayscale refresh for small regions (like single squares) which is
+    /// quicker than `update_partial()` but ghosts a bit more
+    pub fn update_partial_quick(&mut self, region: &mxcfb_rect) -> u32 {
+        let region = self.framebuffer_rect(region);
+        self.partial_refreshes += 1;
+        self.framebuffer_mut().partial_refresh(
+            &region,
+            PartialRefreshMode::Async,
+            waveform_mode::WAVEFORM_MODE_GL16_FAST,
+            display_temp::TEMP_USE_REMARKABLE_DRAW,
+            dither_mode::EPDC_FLAG_USE_REMARKABLE_DITHER,
+            0,
+            false,
+        )
+    }
+
+    /// Black and white only refresh that is quick enough for pen input
+    pub fn update_partial_fast(&mut self, region: &mxcfb_rect) -> u32 {
+        let region = self.framebuffer_rect(region);
+        self.framebuffer_mut().partial_refresh(
+            &region,
+            PartialRefreshMode::Async,
+            waveform_mode::WAVEFORM_MODE_DU,
+            display_temp::TEMP_USE_REMARKABLE_DRAW,
+            dither_mode::EPDC_FLAG_USE_REMARKABLE_DITHER,
+            0,
+            false,
+        )
+    }
+
+    /// Refresh a region at the end of the frame (see `flush_dirty()`).
+    /// Regions marked in the same frame are merged where they overlap.
+    pub fn mark_dirty(&mut self, region: &mxcfb_rect) {
+        if region.width > 0 && region.height > 0 {
+            self.dirty_regions.push(self.framebuffer_rect(region));
+        }
+    }
+
+    /// Refresh all regions marked as dirty. Called by the main loop after
+    /// the scene was drawn.
+    pub fn flush_dirty(&mut self) {
+        for region in coalesce_regions(std::mem::take(&mut self.dirty_regions)) {
+            self.refresh_partial(&region);
+        }
+    }
+
+    /// Do a full (flashing) refresh once `max_partial_refreshes` partial
+    /// refreshes happened since the last one (0 to never do it)
+    pub fn update_full_if_ghosting(&mut self, max_partial_refreshes: u32) {
+        if max_partial_refreshes > 0 && self.partial_refreshes >= max_partial_refreshes {
+            debug!(
+                "Full refresh after {} partial refreshes",
+                self.partial_refreshes
+            );
+            self.update_full();
+        }
+    }
+
     pub fn wait_for_update(&mut self, update_marker: u32) {
         self.framebuffer_mut().wait_refresh_complete(update_marker);
     }
 
-    //Long text with draw_text layers on top of each other ending up in garbled output
-    //This is a quick hack which tries to prevent word clipping
-    //I've found that the remarkable can do about a 95 characters at 35.0 font size
-    //if you're looking for a nice default that fits the whole screen
-    pub fn draw_multi_line_text(
+    /// Use the fonts of `folder` instead of the built-in one: `regular.ttf`,
+    /// `bold.ttf` and `italic.ttf`. Missing fonts are fine (see `FontStyle`).
+    pub fn load_fonts(&mut self, folder: &Path) {
+        for (file_name, style) in &[
+            ("regular.ttf", FontStyle::Regular),
+            ("bold.ttf", FontStyle::Bold),
+            ("italic.ttf", FontStyle::Italic),
+        ] {
+            let path = folder.join(file_name);
+            if !path.exists() {
+                continue;
+            }
+            match load_font(&path) {
+                Ok(font) => {
+                    info!("Using font {:?}", path);
+                    match style {
+                        FontStyle::Regular => self.framebuffer_mut().default_font = font,
+                        FontStyle::Bold => self.bold_font = Some(font),
+                        FontStyle::Italic => self.italic_font = Some(font),
+                    }
+                }
+                Err(err) => error!("Failed to load font {:?}: {:?}", path, err),
+            }
+        }
+    }
+
+    /// Run `f` with the font of `style` being the one libremarkable draws with
+    fn with_font_style<T>(&mut self, style: FontStyle, f: impl FnOnce(&mut Self) -> T) -> T {
+        let slot = match style {
+            FontStyle::Regular => return f(self),
+            FontStyle::Bold => &mut self.bold_font,
+            FontStyle::Italic => &mut self.italic_font,
+        };
+        let mut font = match slot.take() {
+            Some(font) => font,
+            None => return f(self),
+        };
+        std::mem::swap(&mut self.framebuffer_mut().default_font, &mut font);
+        let result = f(self);
+        std::mem::swap(&mut self.framebuffer_mut().default_font, &mut font);
+        match style {
+            FontStyle::Bold => self.bold_font = Some(font),
+            _ => self.italic_font = Some(font),
+        }
+        result
+    }
+
+    /// Size of `text` if it was drawn
+    pub fn measure_text(&mut self, text: &str, size: f32, style: FontStyle) -> Vector2<u32> {
+        let rect = self.with_font_style(style, |canvas| {
+            canvas.framebuffer_mut().draw_text(
+                Point2 {
+                    x: 0.0,
+                    y: DISPLAYHEIGHT as f32,
+                },
+                text,
+                size,
+                color::BLACK,
+                true,
+            )
+        });
+        Vector2 {
+            x: rect.width,
+            y: rect.height,
+        }
+    }
+
+    /// Like `draw_text()` in another style. Without a bold font, bold text
+    /// is drawn twice next to each other.
+    pub fn draw_styled_text(
+        &mut self,
+        pos: Point2<Option<i32>>,
+        text: &str,
+        size: f32,
+        style: FontStyle,
+    ) -> mxcfb_rect {
+        if style == FontStyle::Bold && self.bold_font.is_none() {
+            let x = pos.x.unwrap_or_else(|| {
+                let width = self.measure_text(text, size, style).x;
+                self.size().x as i32 / 2 - width as i32 / 2
+            });
+            let mut rect = self.draw_text(
+                Point2 {
+                    x: Some(x),
+                    y: pos.y,
+                },
+                text,
+                size,
+            );
+            self.draw_text(
+                Point2 {
+                    x: Some(x + 1),
+                    y: pos.y,
+                },
+                text,
+                size,
+            );
+            rect.width += 1;
+            return rect;
+        }
+        self.with_font_style(style, |canvas| canvas.draw_text(pos, text, size))
+    }
+
+    /// Draw `text` on as many lines of at most `max_width` pixels as needed
+    /// (but not more than `max_lines`). Lines are broken between words and
+    /// centered if `x_pos` is `None`. `y_pos` is the baseline of the first line.
+    pub fn draw_wrapped_text(
         &mut self,
         x_pos: Option<i32>,
         y_pos: i32,
-        text: &str,
-        max_chars_per_line: usize,
+        max_width: u32,
         max_lines: usize,
+        text: &str,
         size: f32,
-        line_spacing: f32,
+        style: FontStyle,
     ) -> mxcfb_rect {
-        let text_length = text.chars().count();
-
-        if text_length > 0 {
-            let spaces_in_text = text.chars().enumerate().fold(vec![], |mut acc, p| {
-                if p.1 == ' ' {
-                    acc.push(p.0);
-                    acc
-                } else {
-                    acc
-                }
-            });
-            let mut text_rects = Vec::new();
-            let mut peekable = text.chars().peekable();
-
-            let mut last_text_height = 0;
-            let mut last_text_y = y_pos;
-            let mut chars_taken_so_far = 0;
-
-            while peekable.peek().is_some() {
-                let mut chars_to_take: usize = max_chars_per_line;
-                if chars_taken_so_far + max_chars_per_line >= text_length {
-                    chars_to_take = (text_length + 1) - chars_taken_so_far;
-                } else if text_rects.len() != max_lines - 1 {
-                    chars_to_take = match spaces_in_text
-                        .iter()
-                        .rev()
-                        .find(|char| **char < chars_taken_so_far + max_chars_per_line)
-                    {
-                        None => max_chars_per_line,
-                        Some(chars) => chars - (chars_taken_so_far - 1),
-                    };
-                    chars_taken_so_far = chars_taken_so_far + chars_to_take;
-                }
-                let chunk: String = peekable.by_ref().take(chars_to_take).collect();
-                let text_rect = self.draw_text(
-                    Point2 {
-                        x: x_pos,
-                        y: Some(last_text_y + last_text_height),
-                    },
-                    chunk.as_str(),
-                    size,
-                );
-                last_text_height = text_rect.height as i32 + (size * line_spacing) as i32;
-                last_text_y = text_rect.top as i32;
-                text_rects.push(text_rect);
-                if text_rects.len() == max_lines {
-                    break;
-                }
-            }
-            mxcfb_rect {
-                top: text_rects.first().unwrap().top,
-                left: text_rects.iter().map(|&rec| rec.left).min().unwrap(),
-                width: text_rects.iter().map(|&rec| rec.width).max().unwrap(),
-                height: text_rects.iter().map(|&rec| rec.height).sum(),
+        let mut lines: Vec<String> = vec![];
+        let mut line = String::new();
+        let mut words = text.split_whitespace().peekable();
+        while let Some(word) = words.next() {
+            let candidate = if line.is_empty() {
+                word.to_owned()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if line.is_empty() || self.measure_text(&candidate, size, style).x <= max_width {
+                line = candidate;
+                continue;
             }
-        } else {
-            mxcfb_rect {
-                top: 0,
-                left: 0,
-                width: 0,
-                height: 0,
+            lines.push(std::mem::replace(&mut line, word.to_owned()));
+            if lines.len() == max_lines {
+                // Show that the text goes on
+                if let Some(last_line) = lines.last_mut() {
+                    last_line.push_str(" ...");
+                }
+                line.clear();
+                break;
             }
         }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+
+        let line_height = (size * LINE_SPACING) as i32;
+        let mut text_rect: Option<mxcfb_rect> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let rect = self.draw_styled_text(
+                Point2 {
+                    x: x_pos,
+                    y: Some(y_pos + line_height * i as i32),
+                },
+                line,
+                size,
+                style,
+            );
+            text_rect = Some(match text_rect {
+                Some(text_rect) => union(&text_rect, &rect),
+                None => rect,
+            });
+        }
+        text_rect.unwrap_or(mxcfb_rect {
+            top: 0,
+            left: 0,
+            width: 0,
+            height: 0,
+        })
     }
 
     pub fn draw_text(&mut self, pos: Point2<Option<i32>>, text: &str, size: f32) -> mxcfb_rect {
@@ -155,12 +435,12 @@ impl<'a> Canvas<'a> {
 
             if pos.x.is_none() {
                 // Center horizontally
-                pos.x = Some(DISPLAYWIDTH as i32 / 2 - rect.width as i32 / 2);
+                pos.x = Some(self.size().x as i32 / 2 - rect.width as i32 / 2);
             }
 
             if pos.y.is_none() {
                 // Center vertically
-                pos.y = Some(DISPLAYHEIGHT as i32 / 2 - rect.height as i32 / 2);
+                pos.y = Some(self.size().y as i32 / 2 - rect.height as i32 / 2);
             }
         }
         let pos = Point2 {
@@ -168,8 +448,59 @@ impl<'a> Canvas<'a> {
             y: pos.y.unwrap() as f32,
         };
 
-        self.framebuffer_mut()
-            .draw_text(pos, text, size, color::BLACK, false)
+        match self.orientation {
+            Orientation::Portrait => {
+                self.framebuffer_mut()
+                    .draw_text(pos, text, size, color::BLACK, false)
+            }
+            Orientation::Landscape => self.draw_turned_text(pos, text, size),
+        }
+    }
+
+    /// `draw_text()` of libremarkable with every pixel turned into the
+    /// current orientation
+    fn draw_turned_text(&mut self, pos: Point2<f32>, text: &str, size: f32) -> mxcfb_rect {
+        let rect = self
+            .framebuffer_mut()
+            .draw_text(pos, text, size, color::BLACK, true);
+        let font = self.framebuffer_mut().default_font.clone();
+        for glyph in font.layout(text, Scale::uniform(size), point(pos.x, pos.y)) {
+            if let Some(bounding_box) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, v| {
+                    let shade = (255.0 * (1.0 - v).min(1.0)) as u8;
+                    let pixel = self.framebuffer_point(Point2 {
+                        x: bounding_box.min.x + x as i32,
+                        y: bounding_box.min.y + y as i32,
+                    });
+                    self.framebuffer_mut()
+                        .write_pixel(pixel, color::RGB(shade, shade, shade));
+                });
+            }
+        }
+        rect
+    }
+
+    /// Draw text centered inside of `rect`
+    pub fn draw_text_centered(&mut self, rect: mxcfb_rect, text: &str, size: f32) -> mxcfb_rect {
+        // Do dryrun to get text size
+        let text_rect = self.framebuffer_mut().draw_text(
+            Point2 {
+                x: 0.0,
+                y: DISPLAYHEIGHT as f32,
+            },
+            text,
+            size,
+            color::BLACK,
+            true,
+        );
+        self.draw_text(
+            Point2 {
+                x: Some(rect.left as i32 + rect.width as i32 / 2 - text_rect.width as i32 / 2),
+                y: Some(rect.top as i32 + rect.height as i32 / 2 + text_rect.height as i32 / 2),
+            },
+            text,
+            size,
+        )
     }
 
     fn draw_box(
@@ -185,11 +516,9 @@ impl<'a> Canvas<'a> {
         let bottom_right = bottom_left + vec2(size.x as i32, 0);
 
         // top horizontal
-        self.framebuffer_mut()
-            .draw_line(top_left, top_right, border_px, c);
+        self.draw_colored_line(top_left, top_right, border_px, c);
 
-        self.framebuffer_mut()
-            .draw_line(bottom_left, bottom_right, border_px, c);
+        self.draw_colored_line(bottom_left, bottom_right, border_px, c);
         mxcfb_rect {
             top: pos.y as u32,
             left: pos.x as u32,
@@ -208,12 +537,12 @@ impl<'a> Canvas<'a> {
         if pos.x.is_none() || pos.y.is_none() {
             if pos.x.is_none() {
                 // Center horizontally
-                pos.x = Some(DISPLAYWIDTH as i32 / 2 - size.x as i32 / 2);
+                pos.x = Some(self.size().x as i32 / 2 - size.x as i32 / 2);
             }
 
             if pos.y.is_none() {
                 // Center vertically
-                pos.y = Some(DISPLAYHEIGHT as i32 / 2 - size.y as i32 / 2);
+                pos.y = Some(self.size().y as i32 / 2 - size.y as i32 / 2);
             }
         }
         let pos = Point2 {
@@ -221,8 +550,19 @@ impl<'a> Canvas<'a> {
             y: pos.y.unwrap(),
         };
 
+        // The corners are turned (both are part of the border)
+        let (corner, turned_size) = match self.orientation {
+            Orientation::Portrait => (pos, size),
+            Orientation::Landscape => (
+                self.framebuffer_point(pos + vec2(0, size.y as i32)),
+                Vector2 {
+                    x: size.y,
+                    y: size.x,
+                },
+            ),
+        };
         self.framebuffer_mut()
-            .draw_rect(pos, size, border_px, color::BLACK);
+            .draw_rect(corner, turned_size, border_px, color::BLACK);
         mxcfb_rect {
             top: pos.y as u32,
             left: pos.x as u32,
@@ -241,12 +581,12 @@ impl<'a> Canvas<'a> {
         if pos.x.is_none() || pos.y.is_none() {
             if pos.x.is_none() {
                 // Center horizontally
-                pos.x = Some(DISPLAYWIDTH as i32 / 2 - size.x as i32 / 2);
+                pos.x = Some(self.size().x as i32 / 2 - size.x as i32 / 2);
             }
 
             if pos.y.is_none() {
                 // Center vertically
-                pos.y = Some(DISPLAYHEIGHT as i32 / 2 - size.y as i32 / 2);
+                pos.y = Some(self.size().y as i32 / 2 - size.y as i32 / 2);
             }
         }
         let pos = Point2 {
@@ -254,13 +594,59 @@ impl<'a> Canvas<'a> {
             y: pos.y.unwrap(),
         };
 
-        self.framebuffer_mut().fill_rect(pos, size, clr);
-        mxcfb_rect {
+        let rect = mxcfb_rect {
             top: pos.y as u32,
             left: pos.x as u32,
             width: size.x,
             height: size.y,
+        };
+        let area = self.framebuffer_rect(&rect);
+        self.framebuffer_mut().fill_rect(
+            Point2 {
+                x: area.left as i32,
+                y: area.top as i32,
+            },
+            Vector2 {
+                x: area.width,
+                y: area.height,
+            },
+            clr,
+        );
+        rect
+    }
+
+    pub fn draw_line(&mut self, start: Point2<i32>, end: Point2<i32>, width: u32) -> mxcfb_rect {
+        self.draw_colored_line(start, end, width, color::BLACK)
+    }
+
+    fn draw_colored_line(
+        &mut self,
+        start: Point2<i32>,
+        end: Point2<i32>,
+        width: u32,
+        c: color,
+    ) -> mxcfb_rect {
+        let (start, end) = (self.framebuffer_point(start), self.framebuffer_point(end));
+        let rect = self.framebuffer_mut().draw_line(start, end, width, c);
+        self.oriented_rect(&rect)
+    }
+
+    pub fn draw_circle(&mut self, center: Point2<i32>, radius: u32, width: u32) -> mxcfb_rect {
+        let center = self.framebuffer_point(center);
+        let rect = self
+            .framebuffer_mut()
+            .draw_circle(center, radius, color::BLACK);
+        for i in 1..width.min(radius) {
+            self.framebuffer_mut()
+                .draw_circle(center, radius - i, color::BLACK);
         }
+        self.oriented_rect(&rect)
+    }
+
+    pub fn fill_circle(&mut self, center: Point2<i32>, radius: u32, clr: color) -> mxcfb_rect {
+        let center = self.framebuffer_point(center);
+        let rect = self.framebuffer_mut().fill_circle(center, radius, clr);
+        self.oriented_rect(&rect)
     }
 
     pub fn draw_button(
@@ -298,7 +684,7 @@ impl<'a> Canvas<'a> {
         let button_hitbox = self.draw_box(
             Point2 { x: 0, y: y_pos },
             Vector2 {
-                x: DISPLAYWIDTH as u32,
+                x: self.size().x,
                 y: y_height,
             },
             5,
@@ -315,6 +701,43 @@ impl<'a> Canvas<'a> {
         button_hitbox
     }
 
+    /// Like `draw_box_button()` but with a smaller second line of text.
+    pub fn draw_box_button_with_subtitle(
+        &mut self,
+        y_pos: i32,
+        y_height: u32,
+        text: &str,
+        subtitle: &str,
+        font_size: f32,
+    ) -> mxcfb_rect {
+        let button_hitbox = self.draw_box(
+            Point2 { x: 0, y: y_pos },
+            Vector2 {
+                x: self.size().x,
+                y: y_height,
+            },
+            5,
+            color::BLACK,
+        );
+        self.draw_text(
+            Point2 {
+                x: None,
+                y: Some((button_hitbox.top + y_height * 9 / 20) as i32),
+            },
+            text,
+            font_size,
+        );
+        self.draw_text(
+            Point2 {
+                x: None,
+                y: Some((button_hitbox.top + y_height * 4 / 5) as i32),
+            },
+            subtitle,
+            font_size * 0.7,
+        );
+        button_hitbox
+    }
+
     /// Image that can be overlayed white respecting the previous pixels.
     /// This way transparent images can work.
     fn calc_overlay_image(
@@ -325,20 +748,14 @@ impl<'a> Canvas<'a> {
         let rgba = img.to_rgba();
         let mut rgb = img.to_rgb();
 
-        let orig_rgb888 = rgbimage_from_u8_slice(
-            rgba.width(),
-            rgba.height(),
-            &self
-                .framebuffer_mut()
-                .dump_region(mxcfb_rect {
-                    top: pos.y as u32,
-                    left: pos.x as u32,
-                    width: rgba.width(),
-                    height: rgba.height(),
-                })
-                .unwrap(),
-        )
-        .unwrap();
+        let orig_rgb888 = self
+            .dump_image(&mxcfb_rect {
+                top: pos.y as u32,
+                left: pos.x as u32,
+                width: rgba.width(),
+                height: rgba.height(),
+            })
+            .unwrap();
 
         for (x, y, pixel) in rgba.enumerate_pixels() {
             let color_pix = [
@@ -378,7 +795,16 @@ impl<'a> Canvas<'a> {
             img.to_rgb()
         };
 
-        self.framebuffer_mut().draw_image(&rgb_img, pos);
+        match self.orientation {
+            Orientation::Portrait => {
+                self.framebuffer_mut().draw_image(&rgb_img, pos);
+            }
+            Orientation::Landscape => {
+                let corner = self.framebuffer_point(pos + vec2(0, rgb_img.height() as i32 - 1));
+                let turned = image::imageops::rotate90(&rgb_img);
+                self.framebuffer_mut().draw_image(&turned, corner);
+            }
+        }
         mxcfb_rect {
             top: pos.y as u32,
             left: pos.x as u32,
@@ -387,6 +813,27 @@ impl<'a> Canvas<'a> {
         }
     }
 
+    /// What is currently shown in `region` (upright)
+    fn dump_image(&mut self, region: &mxcfb_rect) -> Result<image::RgbImage> {
+        let area = self.framebuffer_rect(region);
+        let pixels = self
+            .framebuffer_mut()
+            .dump_region(area)
+            .map_err(|err| anyhow!("Failed to read the framebuffer: {}", err))?;
+        let img = rgbimage_from_u8_slice(area.width, area.height, &pixels)
+            .ok_or(anyhow!("Unexpected size of the framebuffer dump"))?;
+        Ok(match self.orientation {
+            Orientation::Portrait => img,
+            Orientation::Landscape => image::imageops::rotate270(&img),
+        })
+    }
+
+    /// Save what is currently shown in `region` as PNG
+    pub fn save_png(&mut self, region: &mxcfb_rect, path: &Path) -> Result<()> {
+        let img = self.dump_image(region)?;
+        img.save(path).context("Write image")
+    }
+
     pub fn is_hitting(pos: Point2<u16>, hitbox: mxcfb_rect) -> bool {
         (pos.x as u32) >= hitbox.left
             && (pos.x as u32) < (hitbox.left + hitbox.width)
@@ -394,3 +841,39 @@ impl<'a> Canvas<'a> {
             && (pos.y as u32) < (hitbox.top + hitbox.height)
     }
 }
+
+/// Merge regions that overlap or touch each other
+fn coalesce_regions(regions: Vec<mxcfb_rect>) -> Vec<mxcfb_rect> {
+    let mut merged: Vec<mxcfb_rect> = vec![];
+    for mut region in regions {
+        // A grown region might now touch one that was merged before
+        while let Some(index) = merged.iter().position(|other| touching(&region, other)) {
+            region = union(&region, &merged.swap_remove(index));
+        }
+        merged.push(region);
+    }
+    merged
+}
+
+fn touching(a: &mxcfb_rect, b: &mxcfb_rect) -> bool {
+    a.left <= b.left + b.width
+        && b.left <= a.left + a.width
+        && a.top <= b.top + b.height
+        && b.top <= a.top + a.height
+}
+
+fn union(a: &mxcfb_rect, b: &mxcfb_rect) -> mxcfb_rect {
+    let left = a.left.min(b.left);
+    let top = a.top.min(b.top);
+    mxcfb_rect {
+        left,
+        top,
+        width: (a.left + a.width).max(b.left + b.width) - left,
+        height: (a.top + a.height).max(b.top + b.height) - top,
+    }
+}
+
+fn load_font(path: &Path) -> Result<Font<'static>> {
+    let bytes = std::fs::read(path).context("Read file")?;
+    Font::from_bytes(bytes).context("Parse font")
+}
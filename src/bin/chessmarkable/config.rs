@@ -0,0 +1,244 @@
+//! Setup of the device that isn't changed in the app (paths, bot and
+//! drawing defaults, network). Read from `config.toml` at startup and
+//! again when the settings are saved or on SIGHUP. Options given on the
+//! command line take precedence over it.
+//!
+//! ```toml
+//! [paths]
+//! pgn_location = "/home/root/chess/pgn"
+//!
+//! [bot]
+//! bot_vs_bot_min_delay = 800
+//!
+//! [ui]
+//! fps = 20
+//!
+//! [network]
+//! web_listen = "10.11.99.1:8080"
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Where everything is kept unless configured otherwise
+const CONFIG_DIRECTORY: &str = "/home/root/.config/chessmarkable";
+
+lazy_static! {
+    static ref CONFIG: RwLock<Config> = RwLock::new(Default::default());
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub paths: PathConfig,
+    pub bot: BotConfig,
+    pub ui: UiConfig,
+    pub network: NetworkConfig,
+}
+
+/// Files and folders, relative ones to the config directory
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct PathConfig {
+    pub savestates_file: Option<PathBuf>,
+    pub settings_file: Option<PathBuf>,
+    pub autosave_file: Option<PathBuf>,
+    pub review_queue_file: Option<PathBuf>,
+    pub pgn_location: Option<PathBuf>,
+    pub pieces_location: Option<PathBuf>,
+    pub fonts_location: Option<PathBuf>,
+    pub images_location: Option<PathBuf>,
+    pub documents_location: Option<PathBuf>,
+    pub scoresheets_location: Option<PathBuf>,
+    pub correspondence_location: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct BotConfig {
+    /// Make the bots and random middlegames the same every run
+    pub seed: Option<u64>,
+    /// Milliseconds bots playing each other wait at least, so the moves can
+    /// be followed
+    pub bot_vs_bot_min_delay: u64,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            bot_vs_bot_min_delay: 1500,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct UiConfig {
+    /// Frames per second of the main loop (input and drawing)
+    pub fps: u16,
+    /// Never merge the updates of many fields into one partial draw of the board
+    pub no_merge: bool,
+    /// Up to this many changed squares (e.g. of a castling together with the
+    /// hints of the last move) are refreshed one by one. More are merged into
+    /// one refresh of the whole board.
+    pub max_separate_square_refreshes: usize,
+    /// Seconds "Pass the tablet" is shown before the board is turned
+    pub pass_tablet_duration: u64,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            fps: 30,
+            no_merge: false,
+            max_separate_square_refreshes: 10,
+            pass_tablet_duration: 3,
+        }
+    }
+}
+
+/// Only read at startup, changes need a restart
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub rpc_listen: Option<String>,
+    pub web_listen: Option<String>,
+}
+
+fn read() -> Result<Config> {
+    let ref file_path = crate::CLI_OPTS.config_file;
+    if !file_path.exists() {
+        return Ok(Config::default());
+    }
+    let contents = std::fs::read_to_string(file_path).context("Read file")?;
+    toml::from_str(&contents).context("Parse TOML")
+}
+
+/// Read the file (again). A broken file keeps the previous config.
+pub fn reload() {
+    match read() {
+        Ok(config) => {
+            debug!("Loaded config: {:?}", config);
+            *CONFIG.write().unwrap() = config;
+        }
+        Err(err) => error!(
+            "Failed to read config file at {:?} (keeping the previous one): {:?}",
+            crate::CLI_OPTS.config_file,
+            err
+        ),
+    }
+}
+
+/// Command line, then config file, then `default` in the config directory
+fn path(
+    cli: &Option<PathBuf>,
+    configured: impl Fn(&PathConfig) -> &Option<PathBuf>,
+    default: &str,
+) -> PathBuf {
+    let directory = Path::new(CONFIG_DIRECTORY);
+    match cli {
+        Some(path) => path.clone(),
+        None => match configured(&CONFIG.read().unwrap().paths) {
+            Some(path) => directory.join(path),
+            None => directory.join(default),
+        },
+    }
+}
+
+pub fn savestates_file() -> PathBuf {
+    let cli = &crate::CLI_OPTS.savestates_file;
+    path(cli, |paths| &paths.savestates_file, "savestates.yml")
+}
+
+pub fn settings_file() -> PathBuf {
+    let cli = &crate::CLI_OPTS.settings_file;
+    path(cli, |paths| &paths.settings_file, "settings.yml")
+}
+
+pub fn autosave_file() -> PathBuf {
+    let cli = &crate::CLI_OPTS.autosave_file;
+    path(cli, |paths| &paths.autosave_file, "autosave.yml")
+}
+
+pub fn review_queue_file() -> PathBuf {
+    let cli = &crate::CLI_OPTS.review_queue_file;
+    path(cli, |paths| &paths.review_queue_file, "review_queue.yml")
+}
+
+pub fn pgn_location() -> PathBuf {
+    let cli = &crate::CLI_OPTS.pgn_location;
+    path(cli, |paths| &paths.pgn_location, "pgn")
+}
+
+pub fn pieces_location() -> PathBuf {
+    let cli = &crate::CLI_OPTS.pieces_location;
+    path(cli, |paths| &paths.pieces_location, "pieces")
+}
+
+pub fn fonts_location() -> PathBuf {
+    let cli = &crate::CLI_OPTS.fonts_location;
+    path(cli, |paths| &paths.fonts_location, "fonts")
+}
+
+pub fn images_location() -> PathBuf {
+    let cli = &crate::CLI_OPTS.images_location;
+    path(cli, |paths| &paths.images_location, "images")
+}
+
+pub fn documents_location() -> PathBuf {
+    let cli = &crate::CLI_OPTS.documents_location;
+    path(cli, |paths| &paths.documents_location, "documents")
+}
+
+pub fn scoresheets_location() -> PathBuf {
+    let cli = &crate::CLI_OPTS.scoresheets_location;
+    path(cli, |paths| &paths.scoresheets_location, "scoresheets")
+}
+
+pub fn correspondence_location() -> PathBuf {
+    let cli = &crate::CLI_OPTS.correspondence_location;
+    path(
+        cli,
+        |paths| &paths.correspondence_location,
+        "correspondence",
+    )
+}
+
+pub fn bot_seed() -> Option<u64> {
+    crate::CLI_OPTS.bot_seed.or(CONFIG.read().unwrap().bot.seed)
+}
+
+pub fn bot_vs_bot_min_delay() -> Duration {
+    Duration::from_millis(CONFIG.read().unwrap().bot.bot_vs_bot_min_delay)
+}
+
+pub fn frame_duration() -> Duration {
+    let fps = CONFIG.read().unwrap().ui.fps.max(1);
+    Duration::from_millis(1000 / fps as u64)
+}
+
+pub fn no_merge() -> bool {
+    crate::CLI_OPTS.no_merge || CONFIG.read().unwrap().ui.no_merge
+}
+
+pub fn max_separate_square_refreshes() -> usize {
+    CONFIG.read().unwrap().ui.max_separate_square_refreshes
+}
+
+pub fn pass_tablet_duration() -> Duration {
+    Duration::from_secs(CONFIG.read().unwrap().ui.pass_tablet_duration)
+}
+
+pub fn rpc_listen() -> Option<String> {
+    let configured = || CONFIG.read().unwrap().network.rpc_listen.clone();
+    crate::CLI_OPTS.rpc_listen.clone().or_else(configured)
+}
+
+pub fn web_listen() -> Option<String> {
+    let configured = || CONFIG.read().unwrap().network.web_listen.clone();
+    crate::CLI_OPTS.web_listen.clone().or_else(configured)
+}
@@ -0,0 +1,146 @@
+use crate::scene::GameMode;
+use anyhow::{Context, Result};
+use chessmarkable::Player;
+use pleco::Board;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+const HOUR_SECS: u64 = 60 * 60;
+
+/// Offered when starting a correspondence game
+pub const DAYS_PER_MOVE_CHOICES: &[u16] = &[1, 2, 3, 5, 7, 14];
+
+/// A game played over days that is saved after every move. Every game
+/// is a file in the correspondence folder named by its id (the time it
+/// was started).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CorrespondenceGame {
+    #[serde(skip)]
+    pub id: u64,
+    pub game_mode: GameMode,
+    pub fen: String,
+    pub days_per_move: u16,
+    /// Unix time of the last move (or the start)
+    pub last_move_at: u64,
+    pub finished: bool,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl CorrespondenceGame {
+    pub fn new(game_mode: GameMode, days_per_move: u16) -> Self {
+        let mut id = now();
+        while path(id).exists() {
+            id += 1;
+        }
+        Self {
+            id,
+            game_mode,
+            fen: Board::default().fen(),
+            days_per_move,
+            last_move_at: now(),
+            finished: false,
+        }
+    }
+
+    pub fn turn(&self) -> Player {
+        match self.fen.split(' ').nth(1) {
+            Some("b") => Player::Black,
+            _ => Player::White,
+        }
+    }
+
+    /// Number of the move to play next
+    pub fn move_number(&self) -> u16 {
+        crate::savestates::move_number(&self.fen)
+    }
+
+    /// It's the turn of someone at this tablet (and not of the bot)
+    pub fn awaits_local_move(&self) -> bool {
+        !(self.finished || self.game_mode.has_bot() && self.turn() == Player::Black)
+    }
+
+    /// E.g. "Your move, 2 days left" or "Bot to move"
+    pub fn status(&self) -> String {
+        if self.finished {
+            return "Finished".to_owned();
+        }
+        if !self.awaits_local_move() {
+            return "Bot to move".to_owned();
+        }
+        let to_move = if self.game_mode.has_bot() {
+            "Your move".to_owned()
+        } else {
+            format!("{} to move", self.turn())
+        };
+        let deadline = self.last_move_at + self.days_per_move as u64 * DAY_SECS;
+        let left = deadline.saturating_sub(now());
+        if left == 0 {
+            format!("{}, overdue", to_move)
+        } else if left >= DAY_SECS {
+            format!("{}, {} day(s) left", to_move, left / DAY_SECS)
+        } else {
+            format!(
+                "{}, {} hour(s) left",
+                to_move,
+                (left + HOUR_SECS - 1) / HOUR_SECS
+            )
+        }
+    }
+}
+
+fn path(id: u64) -> PathBuf {
+    crate::config::correspondence_location().join(format!("{}.yml", id))
+}
+
+pub fn read(id: u64) -> Result<CorrespondenceGame> {
+    let file = std::fs::File::open(path(id)).context("Open file")?;
+    let mut game: CorrespondenceGame = serde_yaml::from_reader(file).context("Deserialize file")?;
+    game.id = id;
+    Ok(game)
+}
+
+/// Games awaiting a local move first, otherwise the most recently played first
+pub fn read_all() -> Result<Vec<CorrespondenceGame>> {
+    let ref folder = crate::config::correspondence_location();
+    if !folder.exists() {
+        return Ok(vec![]);
+    }
+    let mut games = vec![];
+    for entry in std::fs::read_dir(folder).context("Read correspondence folder")? {
+        let path = entry.context("Read folder entry")?.path();
+        let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) if path.extension().map_or(false, |ext| ext == "yml") => stem.parse(),
+            _ => continue,
+        };
+        match id {
+            Ok(id) => games.push(read(id).with_context(|| format!("Read {:?}", path))?),
+            Err(_) => warn!("Ignoring {:?} in the correspondence folder", path),
+        }
+    }
+    games.sort_by_key(|game| {
+        (
+            !game.awaits_local_move(),
+            std::cmp::Reverse(game.last_move_at),
+        )
+    });
+    Ok(games)
+}
+
+pub fn write(game: &CorrespondenceGame) -> Result<()> {
+    let ref folder = crate::config::correspondence_location();
+    std::fs::create_dir_all(folder).context("Create correspondence folder")?;
+    let file = std::fs::File::create(path(game.id)).context("Create file")?;
+    serde_yaml::to_writer(file, game).context("Serialize and writing file")
+}
+
+pub fn delete(id: u64) -> Result<()> {
+    std::fs::remove_file(path(id)).context("Remove file")
+}
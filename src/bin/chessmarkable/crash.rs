@@ -0,0 +1,129 @@
+//! Panics of the main thread are recorded (and the autosave written once
+//! more) so the next start can offer to restore the game and export a
+//! report.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::panic::PanicInfo;
+use std::path::PathBuf;
+
+/// Log lines kept in the report
+const REPORT_LOG_LINES: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrashReport {
+    /// Unix time of the panic
+    pub time: u64,
+    pub version: String,
+    pub message: String,
+    /// File, line and column of the panic
+    #[serde(default)]
+    pub location: Option<String>,
+    /// The last log lines before the panic
+    #[serde(default)]
+    pub log: Vec<String>,
+}
+
+impl CrashReport {
+    fn new(info: &PanicInfo) -> Self {
+        let payload = info.payload();
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => "Unknown panic".to_owned(),
+            },
+        };
+        let log = match crate::logs::try_recent_lines() {
+            Some(lines) => {
+                let skipped = lines.len().saturating_sub(REPORT_LOG_LINES);
+                lines[skipped..]
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect()
+            }
+            None => vec![],
+        };
+        Self {
+            time: crate::correspondence::now(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            message,
+            location: info.location().map(|location| location.to_string()),
+            log,
+        }
+    }
+
+    /// Plain text to attach to a bug report
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "chessmarkable {} crashed at {} (unix time)\n\n{}\n",
+            self.version, self.time, self.message
+        );
+        if let Some(ref location) = self.location {
+            text.push_str(&format!("at {}\n", location));
+        }
+        text.push_str("\nLast log lines:\n");
+        for line in &self.log {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Next to the autosave
+fn file_path() -> PathBuf {
+    crate::config::autosave_file().with_file_name("crash.yml")
+}
+
+/// Keeps the default hook (which prints the panic)
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Other threads (e.g. the bot) don't take the app down
+        if std::thread::current().name() == Some("main") {
+            crate::autosave::flush();
+            if let Err(err) = write(&CrashReport::new(info)) {
+                eprintln!("Failed to write crash report: {:?}", err);
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+fn write(report: &CrashReport) -> Result<()> {
+    let ref file_path = file_path();
+    let directory = file_path.parent().ok_or(anyhow!("No parent directory"))?;
+    std::fs::create_dir_all(directory).context("Create directory for file")?;
+    let file = std::fs::File::create(file_path).context("Create file")?;
+    serde_yaml::to_writer(file, report).context("Serialize and writing file")
+}
+
+/// The crash of the last run (if it crashed)
+pub fn read() -> Result<Option<CrashReport>> {
+    let ref file_path = file_path();
+    if !file_path.exists() {
+        return Ok(None);
+    }
+    let file = std::fs::File::open(file_path).context("Open file")?;
+    let report = serde_yaml::from_reader(file).context("Deserialize file")?;
+    Ok(Some(report))
+}
+
+/// Don't offer the restore again
+pub fn clear() -> Result<()> {
+    let ref file_path = file_path();
+    if file_path.exists() {
+        std::fs::remove_file(file_path).context("Remove file")?;
+    }
+    Ok(())
+}
+
+/// Write the report as text file into the documents folder
+pub fn export(report: &CrashReport) -> Result<PathBuf> {
+    let ref folder = crate::config::documents_location();
+    std::fs::create_dir_all(folder).context("Create documents folder")?;
+    let path = folder.join(format!("crash-{}.txt", report.time));
+    std::fs::write(&path, report.to_text()).with_context(|| format!("Write {:?}", path))?;
+    Ok(path)
+}
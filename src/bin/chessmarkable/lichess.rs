@@ -0,0 +1,117 @@
+use crate::wget;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::path::PathBuf;
+
+const API_URL: &str = "https://lichess.org/api";
+/// How many of the most recent games get fetched per download
+const MAX_GAMES: u32 = 50;
+/// Subfolder of the pgn location the games are written to
+const FOLDER_NAME: &str = "lichess";
+
+lazy_static! {
+    static ref SITE_TAG_REGEX: Regex = Regex::new(r#"\[Site "[^"]*/(\w+)"\]"#).unwrap();
+}
+
+/// Downloads the recent games of the account the token belongs to and
+/// writes each one as a PGN into the lichess folder. Games that were
+/// downloaded before are kept as they are.
+///
+/// Returns the number of newly written games.
+pub fn download_recent_games(token: &str) -> Result<usize> {
+    let account = fetch(&format!("{}/account", API_URL), token, "application/json")
+        .context("Fetch account")?;
+    let account: serde_json::Value =
+        serde_json::from_str(&account).context("Deserialize account")?;
+    let username = account["username"]
+        .as_str()
+        .ok_or(anyhow!("Account has no username"))?;
+
+    let games = fetch(
+        &format!(
+            "{}/games/user/{}?max={}&clocks=true&evals=true",
+            API_URL, username, MAX_GAMES
+        ),
+        token,
+        "application/x-chess-pgn",
+    )
+    .context("Fetch games")?;
+
+    let directory = folder();
+    if !directory.exists() {
+        std::fs::create_dir_all(&directory).context("Create lichess folder")?;
+    }
+    let mut written = 0;
+    for game in split_games(&games) {
+        let id = match SITE_TAG_REGEX.captures(&game) {
+            Some(captures) => captures[1].to_owned(),
+            None => {
+                warn!("Skipping lichess game without a site tag");
+                continue;
+            }
+        };
+        let path = directory.join(format!("{}.pgn", id));
+        if path.exists() {
+            continue;
+        }
+        std::fs::write(&path, game).with_context(|| format!("Write {:?}", path))?;
+        written += 1;
+    }
+    info!(
+        "Downloaded {} new games of {} from lichess.",
+        written, username
+    );
+    Ok(written)
+}
+
+pub fn folder() -> PathBuf {
+    crate::config::pgn_location().join(FOLDER_NAME)
+}
+
+/// Any GNU wget reads the token from the wgetrc
+fn fetch(url: &str, token: &str, accept: &str) -> Result<String> {
+    let output = wget::run(
+        &[
+            "-q".to_owned(),
+            "-O".to_owned(),
+            "-".to_owned(),
+            format!("--header=Accept: {}", accept),
+            url.to_owned(),
+        ],
+        &[("header", &format!("Authorization: Bearer {}", token))],
+        (1, 0),
+    )?;
+    if !output.status.success() {
+        bail!(
+            "Request failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).context("Response is not valid UTF-8")
+}
+
+/// The export contains all games in one PGN. A new game starts
+/// with the first tag after the moves of the previous one.
+fn split_games(pgn: &str) -> Vec<String> {
+    let mut games = vec![];
+    let mut current = String::new();
+    let mut has_moves = false;
+    for line in pgn.lines() {
+        let is_tag = line.starts_with('[');
+        if is_tag && has_moves {
+            games.push(current.trim().to_owned() + "\n");
+            current.clear();
+            has_moves = false;
+        }
+        if !is_tag && !line.trim().is_empty() {
+            has_moves = true;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if has_moves {
+        games.push(current.trim().to_owned() + "\n");
+    }
+    games
+}
@@ -0,0 +1,112 @@
+//! Keeps the most recent log lines in memory (besides printing them like
+//! env_logger) so they can be read and exported on the device.
+
+use anyhow::{Context, Result};
+use log::{Level, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Older lines are dropped
+const MAX_LINES: usize = 1000;
+
+lazy_static! {
+    static ref RECENT_LINES: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+    static ref STARTED: Instant = Instant::now();
+}
+
+#[derive(Clone)]
+pub struct LogLine {
+    /// Seconds since the start of the app
+    pub time: f32,
+    pub level: Level,
+    /// Module path, e.g. "chessmarkable::scene::game_scene"
+    pub target: String,
+    pub message: String,
+}
+
+impl LogLine {
+    /// The last part of the target, e.g. "game_scene"
+    pub fn module(&self) -> &str {
+        self.target.rsplit("::").next().unwrap_or(&self.target)
+    }
+}
+
+impl std::fmt::Display for LogLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>9.3}s {:<5} {}: {}",
+            self.time, self.level, self.target, self.message
+        )
+    }
+}
+
+struct BufferedLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for BufferedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        let line = LogLine {
+            time: STARTED.elapsed().as_secs_f32(),
+            level: record.level(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+        };
+        let mut lines = RECENT_LINES.lock().unwrap();
+        if lines.len() == MAX_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Use instead of `env_logger::init()` (configured by RUST_LOG as well)
+pub fn init() {
+    lazy_static::initialize(&STARTED);
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter();
+    log::set_boxed_logger(Box::new(BufferedLogger { inner })).expect("Logger set twice");
+    log::set_max_level(max_level);
+}
+
+/// The buffered lines, oldest first
+pub fn recent_lines() -> Vec<LogLine> {
+    RECENT_LINES.lock().unwrap().iter().cloned().collect()
+}
+
+/// Like `recent_lines()`, but `None` instead of waiting for the buffer
+/// (e.g. when panicking while logging)
+pub fn try_recent_lines() -> Option<Vec<LogLine>> {
+    let lines = RECENT_LINES.try_lock().ok()?;
+    Some(lines.iter().cloned().collect())
+}
+
+/// Write `lines` into a text file in the documents folder (where the web
+/// page offers it for download)
+pub fn export(lines: &[LogLine]) -> Result<PathBuf> {
+    let ref folder = crate::config::documents_location();
+    std::fs::create_dir_all(folder).context("Create documents folder")?;
+    let path = folder.join(format!("log-{}.txt", crate::correspondence::now()));
+    let mut text = String::new();
+    for line in lines {
+        writeln!(text, "{}", line).ok();
+    }
+    std::fs::write(&path, text).with_context(|| format!("Write {:?}", path))?;
+    Ok(path)
+}
@@ -7,22 +7,42 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 
+mod autosave;
 mod canvas;
+mod config;
+mod correspondence;
+mod crash;
+mod lichess;
+mod logs;
 mod pgns;
+mod review_queue;
+mod rpc;
 mod savestates;
 mod scene;
+mod scoresheets;
+mod settings;
+mod sync;
+mod web;
+mod wget;
 
 use crate::canvas::Canvas;
 use crate::scene::*;
+use anyhow::Context;
+use chessmarkable::epd;
+use chessmarkable::game::{ChessGame, VariantKind};
 use clap::{crate_authors, crate_version, Parser};
 use lazy_static::lazy_static;
 use libremarkable::device::{Model, CURRENT_DEVICE};
 use libremarkable::input::{ev::EvDevContext, InputDevice, InputEvent};
+use pleco::bot_prelude::{AlphaBetaSearcher, JamboreeSearcher};
 use savestates::Savestates;
+use settings::Settings;
 use std::env;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread::sleep;
-use std::time::{Duration, SystemTime};
+use std::time::SystemTime;
 
 #[derive(Parser)]
 #[clap(version = crate_version ! (), author = crate_authors ! ())]
@@ -34,42 +54,171 @@ pub struct Opts {
     )]
     kill_xochitl: bool,
 
+    #[clap(
+        long,
+        about = "Path to the file configuring paths, bot, drawing and network (reread on SIGHUP)",
+        default_value = "/home/root/.config/chessmarkable/config.toml"
+    )]
+    config_file: std::path::PathBuf,
+
     #[clap(
         long,
         short = 'd',
-        default_value = "1500",
-        about = "Minimum amount of time, the bots wait before it makes its move in milliseconds"
+        about = "Minimum amount of time, the bots wait before it makes its move in milliseconds (overrides the setting, default 1500)"
+    )]
+    bot_reaction_delay: Option<u16>,
+
+    #[clap(
+        long,
+        short = 't',
+        about = "Training: Minimum amount of time in seconds a player has to think before a move is accepted (0 to disable, overrides the setting)"
     )]
-    bot_reaction_delay: u16,
+    min_think_time: Option<u16>,
 
     #[clap(
         long,
         short = 'M',
-        about = "Disable merging individual field updates into one big partial draw"
+        about = "Never merge the updates of many fields into one partial draw of the whole board"
     )]
     no_merge: bool,
 
     #[clap(
         long,
         short = 'f',
-        about = "Path to the file containing the savestates",
-        default_value = "/home/root/.config/chessmarkable/savestates.yml"
+        about = "Path to the file containing the savestates (overrides config.toml)"
+    )]
+    savestates_file: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        short = 's',
+        about = "Path to the file containing persisted settings (overrides config.toml)"
     )]
-    savestates_file: std::path::PathBuf,
+    settings_file: Option<std::path::PathBuf>,
 
     #[clap(
         long,
         short = 'p',
-        about = "Path to the file containing the PGNs for PGN viewer",
-        default_value = "/home/root/.config/chessmarkable/pgn"
+        about = "Path to the file containing the PGNs for PGN viewer (overrides config.toml)"
+    )]
+    pgn_location: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the folder containing installed piece sets (one folder per set, overrides config.toml)"
+    )]
+    pieces_location: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the folder containing own fonts (regular.ttf, bold.ttf and italic.ttf, overrides config.toml)"
+    )]
+    fonts_location: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the folder where images of positions are saved (overrides config.toml)"
+    )]
+    images_location: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the folder where games exported as PDF are saved (overrides config.toml)"
+    )]
+    documents_location: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the folder containing the handwritten scoresheets (overrides config.toml)"
+    )]
+    scoresheets_location: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the folder containing the correspondence games (overrides config.toml)"
+    )]
+    correspondence_location: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the file the game in progress is autosaved to (overrides config.toml)"
+    )]
+    autosave_file: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Path to the file containing the mistakes waiting to be reviewed (overrides config.toml)"
+    )]
+    review_queue_file: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Run the bot against an EPD test suite (e.g. WAC), print the score and exit"
+    )]
+    epd_suite: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        default_value = "4",
+        about = "Search depth of the bot when running an EPD test suite"
+    )]
+    epd_depth: u16,
+
+    #[clap(
+        long,
+        about = "Count the moves from the starting position this many plies deep (perft), print how long it took and exit"
+    )]
+    perft: Option<u16>,
+
+    #[clap(
+        long,
+        about = "Address to accept JSON-RPC clients on (e.g. 10.11.99.1:7337 for the USB network)"
+    )]
+    rpc_listen: Option<String>,
+
+    #[clap(
+        long,
+        about = "Address to serve the web page for uploading PGNs and downloading exports on (e.g. 10.11.99.1:8080 for the USB network). It has no password, so everyone on that network can use it"
+    )]
+    web_listen: Option<String>,
+
+    #[clap(
+        long,
+        about = "Make the bots and random middlegames the same every run (to reproduce a game)"
     )]
-    pgn_location: std::path::PathBuf,
+    bot_seed: Option<u64>,
+
+    #[clap(
+        long,
+        about = "Start in the PGN viewer with the first game of this PGN"
+    )]
+    replay: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        about = "Start a game against the bot searching this deep (2 easy, 4 normal, 6 hard)"
+    )]
+    vs_bot: Option<u16>,
+
+    #[clap(
+        long,
+        about = "Start a game from this position (against the bot with --vs-bot, otherwise player vs player)"
+    )]
+    fen: Option<String>,
+
+    #[clap(
+        long,
+        about = "Continue the autosaved game (the main menu if there is none)"
+    )]
+    resume: bool,
 }
 
 lazy_static! {
     pub static ref CLI_OPTS: Opts = Opts::parse();
     pub static ref SAVESTATES: std::sync::Mutex<Savestates> =
         std::sync::Mutex::new(Default::default());
+    pub static ref SETTINGS: std::sync::Mutex<Settings> =
+        std::sync::Mutex::new(Default::default());
         // Underlays / Background layers
 }
 
@@ -82,7 +231,7 @@ fn main() {
     } else {
         false
     };
-    env_logger::init();
+    logs::init();
     if show_log_info {
         debug!(concat!(
             "Debug Mode is enabled by default.\n",
@@ -90,6 +239,26 @@ fn main() {
             "(e.g. info, warn, error or comma separated list of \"[module=]<level>\")."
         ));
     }
+    config::reload();
+    crash::install_panic_hook();
+
+    if let Some(ref epd_suite) = CLI_OPTS.epd_suite {
+        match run_epd_suite(epd_suite) {
+            Ok(summary) => {
+                info!("{}", summary);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                error!("Failed to run EPD suite {:?}: {:?}", epd_suite, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(depth) = CLI_OPTS.perft {
+        info!("{}", run_perft(depth));
+        std::process::exit(0);
+    }
 
     if CURRENT_DEVICE.model == Model::Gen2 && std::env::var_os("LD_PRELOAD").is_none() {
         warn!(concat!(
@@ -126,41 +295,219 @@ fn main() {
         Err(err) => {
             error!(
                 "Failed to read savestates file at {:?}: {:?}",
-                &CLI_OPTS.savestates_file, err
+                config::savestates_file(),
+                err
             );
             std::process::exit(1);
         }
     };
+    *SETTINGS.lock().unwrap() = match settings::read() {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!(
+                "Failed to read settings file at {:?} (using defaults): {:?}",
+                config::settings_file(),
+                err
+            );
+            Default::default()
+        }
+    };
+
+    if let Some(ref address) = config::rpc_listen() {
+        if let Err(err) = rpc::start(address) {
+            error!("Failed to start the JSON-RPC server: {:?}", err);
+        }
+    }
+    if let Some(ref address) = config::web_listen() {
+        if let Err(err) = web::start(address) {
+            error!("Failed to start the web page: {:?}", err);
+        }
+    }
 
     let mut canvas = Canvas::new();
+    canvas.load_fonts(&config::fonts_location());
 
     let (input_tx, input_rx) = std::sync::mpsc::channel::<InputEvent>();
     EvDevContext::new(InputDevice::GPIO, input_tx.clone()).start();
-    EvDevContext::new(InputDevice::Multitouch, input_tx).start();
-    //EvDevContext::new(InputDevice::Wacom, input_tx.clone()).start();
-    const FPS: u16 = 30;
-    const FRAME_DURATION: Duration = Duration::from_millis(1000 / FPS as u64);
+    EvDevContext::new(InputDevice::Multitouch, input_tx.clone()).start();
+    EvDevContext::new(InputDevice::Wacom, input_tx).start();
 
-    let mut current_scene: Box<dyn Scene> =
-        Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+    let reload_config = Arc::new(AtomicBool::new(false));
+    if let Err(err) =
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, reload_config.clone())
+    {
+        warn!(
+            "Failed to listen for SIGHUP (no reloading of the config): {:?}",
+            err
+        );
+    }
+
+    let mut current_scene = match launch_scene() {
+        Ok(Some(scene)) => scene,
+        Ok(None) => Box::new(MainMenuScene::new(only_exit_to_xochitl, false)),
+        Err(err) => {
+            error!("Failed to start as requested: {:?}", err);
+            std::process::exit(1);
+        }
+    };
 
     loop {
         let before_input = SystemTime::now();
+        if reload_config.swap(false, Ordering::Relaxed) {
+            info!("Received SIGHUP, reloading the config");
+            config::reload();
+        }
+        canvas.set_orientation(current_scene.orientation());
         for event in input_rx.try_iter() {
-            current_scene.on_input(event);
+            current_scene.on_input(canvas.orient_input(event));
         }
 
         current_scene.draw(&mut canvas);
+        canvas.flush_dirty();
         current_scene = update(current_scene, &mut canvas, only_exit_to_xochitl);
+        canvas.update_full_if_ghosting(SETTINGS.lock().unwrap().full_refresh_after.into());
 
         // Wait remaining frame time
+        let frame_duration = config::frame_duration();
         let elapsed = before_input.elapsed().unwrap();
-        if elapsed < FRAME_DURATION {
-            sleep(FRAME_DURATION - elapsed);
+        if elapsed < frame_duration {
+            sleep(frame_duration - elapsed);
         }
     }
 }
 
+/// The autosaved game (if any)
+fn resume_autosave() -> anyhow::Result<Option<Box<dyn Scene>>> {
+    let autosave = match autosave::read()? {
+        Some(autosave) => autosave,
+        None => return Ok(None),
+    };
+    // The autosave is newer than the savestate unless
+    // the game was saved and quit properly
+    SAVESTATES
+        .lock()
+        .unwrap()
+        .set(autosave.slot, autosave.fen, autosave.clock);
+    Ok(Some(Box::new(GameScene::new(
+        autosave.game_mode,
+        autosave.slot,
+        autosave.pvp_piece_rotation_enabled,
+        autosave.variant,
+    ))))
+}
+
+/// Offers to restore the game if the last run crashed
+fn crash_scene() -> Option<Box<dyn Scene>> {
+    match crash::read() {
+        Ok(Some(report)) => Some(Box::new(CrashScene::new(report))),
+        Ok(None) => None,
+        Err(err) => {
+            error!("Failed to read crash report: {:?}", err);
+            None
+        }
+    }
+}
+
+/// The scene asked for on the command line (`None` for the main menu)
+fn launch_scene() -> anyhow::Result<Option<Box<dyn Scene>>> {
+    if let Some(ref path) = CLI_OPTS.replay {
+        let pgn = pgns::Pgn { path: path.clone() };
+        let index = pgns::read_index(&pgn).with_context(|| format!("Read {:?}", path))?;
+        let first_game = index
+            .games
+            .first()
+            .ok_or(anyhow!("No game found in {:?}", path))?;
+        let game = pgns::read_game(&pgn, first_game)?;
+        return Ok(Some(Box::new(ReplayScene::new(
+            Some(game),
+            Some(pgn),
+            first_game.offset,
+        )?)));
+    }
+    if CLI_OPTS.resume {
+        let scene = resume_autosave().context("Read autosave file")?;
+        if scene.is_none() {
+            info!("No autosaved game to resume");
+        }
+        return Ok(scene);
+    }
+    if CLI_OPTS.fen.is_none() && CLI_OPTS.vs_bot.is_none() {
+        return Ok(crash_scene());
+    }
+
+    let game_mode = match CLI_OPTS.vs_bot {
+        // The level searching closest to that depth
+        Some(depth) => {
+            *GameMode::ALL
+                .iter()
+                .filter_map(|mode| mode.bot_depth().map(|bot_depth| (mode, bot_depth)))
+                .min_by_key(|(_, bot_depth)| (*bot_depth as i32 - depth as i32).abs())
+                .unwrap()
+                .0
+        }
+        None => GameMode::PvP,
+    };
+    let fen = match CLI_OPTS.fen {
+        Some(ref fen) => ChessGame::from_fen(fen)
+            .with_context(|| format!("Invalid FEN {:?}", fen))?
+            .fen(),
+        None => ChessGame::default().fen(),
+    };
+    let mut savestates = SAVESTATES.lock().unwrap();
+    let name = savestates.default_name();
+    let id = savestates.add(name, game_mode, VariantKind::Standard, fen);
+    savestates::write(&savestates).context("Write savestates file")?;
+    Ok(Some(Box::new(GameScene::new(
+        game_mode,
+        SavestateSlot::Saved(id),
+        false,
+        VariantKind::Standard,
+    ))))
+}
+
+/// Uses the same search algorithm as the bot would on this device
+fn run_epd_suite(path: &std::path::Path) -> anyhow::Result<String> {
+    let contents = std::fs::read_to_string(path)?;
+    let positions = epd::parse_suite(&contents)?;
+    info!(
+        "Running {} positions with depth {}..",
+        positions.len(),
+        CLI_OPTS.epd_depth
+    );
+    let report = if CURRENT_DEVICE.model == Model::Gen1 {
+        epd::run_suite::<AlphaBetaSearcher>(&positions, CLI_OPTS.epd_depth)?
+    } else {
+        epd::run_suite::<JamboreeSearcher>(&positions, CLI_OPTS.epd_depth)?
+    };
+    for result in report.results.iter().filter(|result| !result.solved) {
+        info!(
+            "Failed {} (played {})",
+            result.id.as_deref().unwrap_or("?"),
+            result.played_move
+        );
+    }
+    Ok(report.summary())
+}
+
+/// Benchmark of the move generation
+fn run_perft(depth: u16) -> String {
+    let game = ChessGame::default();
+    let started = std::time::Instant::now();
+    let divided = game.perft_divide(depth);
+    let elapsed = started.elapsed();
+    for (bit_move, nodes) in &divided {
+        info!("{}: {}", bit_move.stringify(), nodes);
+    }
+    let nodes: u64 = divided.iter().map(|(_, nodes)| nodes).sum();
+    format!(
+        "Perft {}: {} nodes in {:.2}s ({:.0} nodes/s)",
+        depth,
+        nodes,
+        elapsed.as_secs_f64(),
+        nodes as f64 / elapsed.as_secs_f64().max(0.001)
+    )
+}
+
 fn update(
     scene: Box<dyn Scene>,
     canvas: &mut Canvas,
@@ -172,16 +519,55 @@ fn update(
         }
     } else if let Some(main_menu_scene) = scene.downcast_ref::<MainMenuScene>() {
         let pvp_rot_en = main_menu_scene.pvp_piece_rotation_enabled;
+        if main_menu_scene.continue_button_pressed {
+            match resume_autosave() {
+                Ok(Some(scene)) => return scene,
+                Ok(None) => {}
+                Err(err) => error!("Failed to read autosave file: {:?}", err),
+            }
+        }
         if main_menu_scene.play_pvp_button_pressed {
             return Box::new(BoardSelectScene::new(GameMode::PvP, pvp_rot_en));
+        } else if main_menu_scene.play_analysis_button_pressed {
+            return Box::new(BoardSelectScene::new(GameMode::Analysis, pvp_rot_en));
+        } else if main_menu_scene.play_recording_button_pressed {
+            return Box::new(BoardSelectScene::new(GameMode::Recording, pvp_rot_en));
         } else if main_menu_scene.play_easy_button_pressed {
             return Box::new(BoardSelectScene::new(GameMode::EasyBot, pvp_rot_en));
         } else if main_menu_scene.play_normal_button_pressed {
             return Box::new(BoardSelectScene::new(GameMode::NormalBot, pvp_rot_en));
         } else if main_menu_scene.play_hard_button_pressed {
             return Box::new(BoardSelectScene::new(GameMode::HardBot, pvp_rot_en));
+        } else if main_menu_scene.bot_vs_bot_button_pressed {
+            return Box::new(BoardSelectScene::new(GameMode::BotVsBot, pvp_rot_en));
+        } else if main_menu_scene.review_button_pressed {
+            return Box::new(ReviewScene::new());
         } else if main_menu_scene.viewer_button_pressed {
             return Box::new(PgnSelectScene::new(None));
+        } else if let Some((ref game, ref recent_game)) = main_menu_scene.opened_recent_game {
+            match ReplayScene::new(
+                Some(game.clone()),
+                Some(recent_game.pgn.clone()),
+                recent_game.game.offset,
+            ) {
+                Ok(scene) => return Box::new(scene),
+                Err(err) => {
+                    error!("Failed to open recent game: {:?}", err);
+                    return Box::new(MainMenuScene::new(only_exit_to_xochitl, pvp_rot_en));
+                }
+            }
+        } else if main_menu_scene.diagnostics_button_pressed {
+            return Box::new(DiagnosticsScene::new());
+        } else if main_menu_scene.settings_button_pressed {
+            return Box::new(SettingsScene::new());
+        } else if main_menu_scene.scoresheet_button_pressed {
+            return Box::new(ScoresheetScene::new());
+        } else if main_menu_scene.clock_button_pressed {
+            return Box::new(ClockScene::new());
+        } else if main_menu_scene.correspondence_button_pressed {
+            return Box::new(CorrespondenceScene::new());
+        } else if main_menu_scene.saves_button_pressed {
+            return Box::new(SavesScene::new(pvp_rot_en));
         } else if main_menu_scene.exit_xochitl_button_pressed {
             canvas.clear();
             canvas.update_full();
@@ -197,43 +583,28 @@ fn update(
             std::process::exit(0);
         }
     } else if let Some(board_select_scene) = scene.downcast_ref::<BoardSelectScene>() {
-        if board_select_scene.select_slot_1_button_pressed {
+        if let Some(id) = board_select_scene.started_save {
             return Box::new(GameScene::new(
                 board_select_scene.selected_gamemode,
-                SavestateSlot::First,
+                SavestateSlot::Saved(id),
                 board_select_scene.pvp_piece_rotation_enabled,
+                board_select_scene.variant,
             ));
-        } else if board_select_scene.select_slot_2_button_pressed {
-            return Box::new(GameScene::new(
+        } else if board_select_scene.editor_button_pressed {
+            return Box::new(EditorScene::new(
                 board_select_scene.selected_gamemode,
-                SavestateSlot::Second,
                 board_select_scene.pvp_piece_rotation_enabled,
             ));
-        } else if board_select_scene.select_slot_3_button_pressed {
-            return Box::new(GameScene::new(
-                board_select_scene.selected_gamemode,
-                SavestateSlot::Third,
-                board_select_scene.pvp_piece_rotation_enabled,
-            ));
-        } else if board_select_scene.reset_slot_1_button_pressed {
-            SAVESTATES.lock().unwrap().slot_1 = None;
-            return Box::new(GameScene::new(
+        } else if board_select_scene.tournament_button_pressed {
+            return Box::new(TournamentScene::new());
+        } else if board_select_scene.simul_button_pressed {
+            return Box::new(SimulScene::new(
                 board_select_scene.selected_gamemode,
-                SavestateSlot::First,
                 board_select_scene.pvp_piece_rotation_enabled,
             ));
-        } else if board_select_scene.reset_slot_2_button_pressed {
-            SAVESTATES.lock().unwrap().slot_2 = None;
-            return Box::new(GameScene::new(
+        } else if board_select_scene.endgames_button_pressed {
+            return Box::new(EndgameScene::new(
                 board_select_scene.selected_gamemode,
-                SavestateSlot::Second,
-                board_select_scene.pvp_piece_rotation_enabled,
-            ));
-        } else if board_select_scene.reset_slot_3_button_pressed {
-            SAVESTATES.lock().unwrap().slot_3 = None;
-            return Box::new(GameScene::new(
-                board_select_scene.selected_gamemode,
-                SavestateSlot::Third,
                 board_select_scene.pvp_piece_rotation_enabled,
             ));
         } else if board_select_scene.back_button_pressed {
@@ -242,78 +613,137 @@ fn update(
                 board_select_scene.pvp_piece_rotation_enabled,
             ));
         }
-    } else if let Some(board_select_scene) = scene.downcast_ref::<PgnSelectScene>() {
-        let index_of_first_game =
-            (board_select_scene.current_page_number * REPLAYS_PER_PAGE) as usize;
-        if board_select_scene.return_to_main_menu {
+    } else if let Some(pgn_select_scene) = scene.downcast_ref::<PgnSelectScene>() {
+        if pgn_select_scene.return_to_main_menu {
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        } else if let Some(game) = pgn_select_scene.pressed_game() {
+            match ReplayScene::new(
+                Some(game.clone()),
+                pgn_select_scene.selected_pgn.clone(),
+                pgn_select_scene.pressed_game_offset(),
+            ) {
+                Ok(scene) => return Box::new(scene),
+                Err(err) => {
+                    error!("Failed to open game: {:?}", err);
+                    return Box::new(PgnSelectScene::new(pgn_select_scene.selected_pgn.clone()));
+                }
+            }
+        }
+    } else if let Some(editor_scene) = scene.downcast_ref::<EditorScene>() {
+        if let Some(slot) = editor_scene.start_on_slot {
+            return Box::new(GameScene::new(
+                editor_scene.game_mode,
+                slot,
+                editor_scene.pvp_piece_rotation_enabled,
+                VariantKind::Standard,
+            ));
+        } else if editor_scene.back_button_pressed {
+            return Box::new(BoardSelectScene::new(
+                editor_scene.game_mode,
+                editor_scene.pvp_piece_rotation_enabled,
+            ));
+        }
+    } else if let Some(diagnostics_scene) = scene.downcast_ref::<DiagnosticsScene>() {
+        if diagnostics_scene.back_button_pressed {
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        } else if diagnostics_scene.logs_button_pressed {
+            return Box::new(LogScene::new());
+        }
+    } else if let Some(crash_scene) = scene.downcast_ref::<CrashScene>() {
+        if crash_scene.restore_button_pressed {
+            match resume_autosave() {
+                Ok(Some(scene)) => return scene,
+                Ok(None) => {}
+                Err(err) => error!("Failed to read autosave file: {:?}", err),
+            }
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        } else if crash_scene.dismiss_button_pressed {
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        }
+    } else if let Some(log_scene) = scene.downcast_ref::<LogScene>() {
+        if log_scene.back_button_pressed {
+            return Box::new(DiagnosticsScene::new());
+        }
+    } else if let Some(settings_scene) = scene.downcast_ref::<SettingsScene>() {
+        if settings_scene.back_button_pressed {
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        }
+    } else if let Some(scoresheet_scene) = scene.downcast_ref::<ScoresheetScene>() {
+        if scoresheet_scene.back_button_pressed {
             return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
-        } else if board_select_scene.button_1_pressed {
-            return Box::new(ReplayScene::new(
-                Some(
-                    board_select_scene
-                        .game_vec
-                        .get(index_of_first_game)
-                        .unwrap()
-                        .clone(),
-                ),
-                board_select_scene.selected_pgn.clone(),
+        }
+    } else if let Some(tournament_scene) = scene.downcast_ref::<TournamentScene>() {
+        if tournament_scene.back_button_pressed {
+            return Box::new(BoardSelectScene::new(GameMode::BotVsBot, false));
+        }
+    } else if let Some(simul_scene) = scene.downcast_ref::<SimulScene>() {
+        if simul_scene.back_button_pressed {
+            return Box::new(BoardSelectScene::new(
+                simul_scene.game_mode,
+                simul_scene.pvp_piece_rotation_enabled,
             ));
-        } else if board_select_scene.button_2_pressed {
-            return Box::new(ReplayScene::new(
-                Some(
-                    board_select_scene
-                        .game_vec
-                        .get(index_of_first_game + 1)
-                        .unwrap()
-                        .clone(),
-                ),
-                board_select_scene.selected_pgn.clone(),
+        }
+    } else if let Some(endgame_scene) = scene.downcast_ref::<EndgameScene>() {
+        if let Some(id) = endgame_scene.started_save {
+            return Box::new(GameScene::new(
+                endgame_scene.game_mode,
+                SavestateSlot::Saved(id),
+                endgame_scene.pvp_piece_rotation_enabled,
+                VariantKind::Standard,
+            ));
+        } else if let Some(endgame) = endgame_scene.started_drill {
+            return Box::new(DrillScene::new(
+                endgame.name,
+                endgame.fen,
+                endgame_scene.game_mode,
+                endgame_scene.pvp_piece_rotation_enabled,
             ));
-        } else if board_select_scene.button_3_pressed {
-            return Box::new(ReplayScene::new(
-                Some(
-                    board_select_scene
-                        .game_vec
-                        .get(index_of_first_game + 2)
-                        .unwrap()
-                        .clone(),
-                ),
-                board_select_scene.selected_pgn.clone(),
+        } else if endgame_scene.back_button_pressed {
+            return Box::new(BoardSelectScene::new(
+                endgame_scene.game_mode,
+                endgame_scene.pvp_piece_rotation_enabled,
             ));
-        } else if board_select_scene.button_4_pressed {
-            return Box::new(ReplayScene::new(
-                Some(
-                    board_select_scene
-                        .game_vec
-                        .get(index_of_first_game + 3)
-                        .unwrap()
-                        .clone(),
-                ),
-                board_select_scene.selected_pgn.clone(),
+        }
+    } else if let Some(drill_scene) = scene.downcast_ref::<DrillScene>() {
+        if drill_scene.back_button_pressed {
+            return Box::new(EndgameScene::new(
+                drill_scene.game_mode,
+                drill_scene.pvp_piece_rotation_enabled,
+            ));
+        }
+    } else if let Some(review_scene) = scene.downcast_ref::<ReviewScene>() {
+        if review_scene.back_button_pressed {
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        }
+    } else if let Some(saves_scene) = scene.downcast_ref::<SavesScene>() {
+        if let Some(ref save) = saves_scene.opened_save {
+            return Box::new(GameScene::new(
+                save.game_mode,
+                SavestateSlot::Saved(save.id),
+                saves_scene.pvp_piece_rotation_enabled,
+                save.variant,
             ));
-        } else if board_select_scene.button_5_pressed {
-            return Box::new(ReplayScene::new(
-                Some(
-                    board_select_scene
-                        .game_vec
-                        .get(index_of_first_game + 4)
-                        .unwrap()
-                        .clone(),
-                ),
-                board_select_scene.selected_pgn.clone(),
+        } else if saves_scene.back_button_pressed {
+            return Box::new(MainMenuScene::new(
+                only_exit_to_xochitl,
+                saves_scene.pvp_piece_rotation_enabled,
             ));
-        } else if board_select_scene.button_6_pressed {
-            return Box::new(ReplayScene::new(
-                Some(
-                    board_select_scene
-                        .game_vec
-                        .get(index_of_first_game + 5)
-                        .unwrap()
-                        .clone(),
-                ),
-                board_select_scene.selected_pgn.clone(),
+        }
+    } else if let Some(correspondence_scene) = scene.downcast_ref::<CorrespondenceScene>() {
+        if let Some((id, game_mode)) = correspondence_scene.opened_game {
+            return Box::new(GameScene::new(
+                game_mode,
+                SavestateSlot::Correspondence(id),
+                false,
+                VariantKind::Standard,
             ));
-        };
+        } else if correspondence_scene.back_button_pressed {
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        }
+    } else if let Some(clock_scene) = scene.downcast_ref::<ClockScene>() {
+        if clock_scene.back_button_pressed {
+            return Box::new(MainMenuScene::new(only_exit_to_xochitl, false));
+        }
     } else if let Some(board_select_scene) = scene.downcast_ref::<ReplayScene>() {
         if board_select_scene.return_to_main_menu {
             return Box::new(PgnSelectScene::new(board_select_scene.selected_pgn.clone()));
@@ -1,58 +1,496 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chess_pgn_parser::{read_games, Game};
+use chessmarkable::annotation::PositionAnnotations;
+use chessmarkable::Player;
 use glob::glob;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Smaller files are scanned quickly enough that
+/// writing their index to disk isn't worth it
+const MIN_FILE_SIZE_FOR_INDEX_FILE: u64 = 1024 * 1024;
+
+lazy_static! {
+    static ref TAG_PAIR_REGEX: Regex = Regex::new(r#"^\s*\[(\w+)\s+"(.*)"\]\s*$"#).unwrap();
+    //Library doesn't play nice with comments inside brackets
+    //This gets rid of up to two levels of bracket nesting
+    static ref NESTED_BRACKETS_REGEX: Regex =
+        Regex::new(r"\((?:[^)(]|\((?:[^)(]|\([^)(]*\))*\))*\)").unwrap();
+    static ref WHITESPACE_REGEX: Regex = Regex::new(r"\s+").unwrap();
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Pgn {
     pub path: PathBuf,
 }
 
-pub fn read(from: usize, to: usize) -> Result<Vec<Pgn>> {
-    let ref pgn_loc = crate::CLI_OPTS.pgn_location;
-    if from > to {
-        Err(anyhow!("from cant be greater than to"))
-    } else if !pgn_loc.exists() {
-        info!("No pgn directory found");
-        Ok(Vec::new())
-    } else {
-        let elements_to_fetch = to - from + 1;
-        let mut requested_pgns: Vec<Pgn> = Vec::with_capacity(elements_to_fetch);
-        for entry in glob(&construct_pgn_loc_pattern_string(pgn_loc))
-            .expect("Failed to read glob pattern")
-            .skip(from)
-            .take(elements_to_fetch)
-        {
-            match entry {
-                Ok(path) => requested_pgns.push(Pgn { path }),
-                Err(e) => println!("{:?}", e),
+/// Something that can be chosen in the PGN browser
+#[derive(Clone)]
+pub enum PgnEntry {
+    Folder(PathBuf),
+    Pgn(Pgn),
+}
+
+/// Header tags of every game in a PGN file. Much faster to get
+/// than parsing all games which is why it's used for listing and searching.
+#[derive(Clone, Default)]
+pub struct PgnMetadata {
+    pub games: Vec<Vec<(String, String)>>,
+}
+
+/// Location and header tags of a single game inside a PGN file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexedGame {
+    /// Byte offset of the first tag of the game
+    pub offset: u64,
+    pub length: u64,
+    pub tags: Vec<(String, String)>,
+}
+
+/// A game of the PGN folder, e.g. for the recent games in the main menu
+#[derive(Clone)]
+pub struct RecentGame {
+    pub pgn: Pgn,
+    pub game: IndexedGame,
+}
+
+impl RecentGame {
+    /// E.g. "Won vs DrNykterstein" if `player_name` played in it,
+    /// otherwise "Carlsen - Nakamura" (or the event without players)
+    pub fn title(&self, player_name: Option<&str>) -> String {
+        let (white, black) = match (
+            tag_value(&self.game.tags, "White"),
+            tag_value(&self.game.tags, "Black"),
+        ) {
+            (None, None) => {
+                return tag_value(&self.game.tags, "Event")
+                    .unwrap_or("Game")
+                    .to_owned()
             }
+            (white, black) => (white.unwrap_or("?"), black.unwrap_or("?")),
+        };
+        let result = tag_value(&self.game.tags, "Result");
+        let me = match player_name {
+            Some(name) if white.eq_ignore_ascii_case(name) => Player::White,
+            Some(name) if black.eq_ignore_ascii_case(name) => Player::Black,
+            _ => return format!("{} - {}", white, black),
+        };
+        let (opponent, won, lost) = match me {
+            Player::White => (black, "1-0", "0-1"),
+            Player::Black => (white, "0-1", "1-0"),
+        };
+        let outcome = match result {
+            Some(result) if result == won => "Won",
+            Some(result) if result == lost => "Lost",
+            Some("1/2-1/2") => "Draw",
+            _ => "Played",
+        };
+        format!("{} vs {}", outcome, opponent)
+    }
+
+    /// Result and date, e.g. "1-0, 2021.03.04"
+    pub fn details(&self) -> String {
+        let result = tag_value(&self.game.tags, "Result").unwrap_or("*");
+        match tag_value(&self.game.tags, "Date") {
+            Some(date) => format!("{}, {}", result, date),
+            None => result.to_owned(),
         }
-        Ok(requested_pgns)
     }
 }
 
-pub fn total_number_of_pgn() -> u32 {
-    let mut pages: u32 = 0;
-    let ref pgn_loc = crate::CLI_OPTS.pgn_location;
-    if !pgn_loc.exists() {
-        info!("No pgn directory found");
-        0
-    } else {
-        for entry in
-            glob(&construct_pgn_loc_pattern_string(pgn_loc)).expect("Failed to read glob pattern")
-        {
-            match entry {
-                Ok(_) => pages = pages + 1,
-                Err(e) => println!("{:?}", e),
+/// Allows to list and open single games of huge PGN databases
+/// without parsing the whole file. Large files get their index
+/// cached in a hidden file next to them.
+#[derive(Serialize, Deserialize, Default)]
+pub struct PgnIndex {
+    /// Detects whether the PGN was changed after indexing it
+    file_size: u64,
+    modified: Option<SystemTime>,
+    pub games: Vec<IndexedGame>,
+}
+
+/// Pen marks and notes of the games in a PGN. Stored in a
+/// hidden file next to it so the PGN itself stays untouched.
+#[derive(Serialize, Deserialize, Default)]
+pub struct PgnAnnotations {
+    /// By offset of the game (see `IndexedGame`) and number of moves played
+    pub games: BTreeMap<u64, BTreeMap<usize, PositionAnnotations>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PgnSortOrder {
+    FileName,
+    /// Newest first
+    DatePlayed,
+    /// By the name of the white player
+    PlayerName,
+    /// White wins, draws, black wins, unknown
+    Result,
+}
+
+impl Default for PgnSortOrder {
+    fn default() -> Self {
+        PgnSortOrder::FileName
+    }
+}
+
+impl PgnSortOrder {
+    pub fn next(self) -> Self {
+        match self {
+            PgnSortOrder::FileName => PgnSortOrder::DatePlayed,
+            PgnSortOrder::DatePlayed => PgnSortOrder::PlayerName,
+            PgnSortOrder::PlayerName => PgnSortOrder::Result,
+            PgnSortOrder::Result => PgnSortOrder::FileName,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PgnSortOrder::FileName => "Filename",
+            PgnSortOrder::DatePlayed => "Date",
+            PgnSortOrder::PlayerName => "Player",
+            PgnSortOrder::Result => "Result",
+        }
+    }
+
+    /// Compare the tags of two games. Games missing the relevant tag are sorted last.
+    /// `FileName` considers all games equal (the caller should keep the file order).
+    pub fn compare(self, a: &[(String, String)], b: &[(String, String)]) -> Ordering {
+        let key = |tags: &[(String, String)]| -> Option<String> {
+            match self {
+                PgnSortOrder::FileName => None,
+                PgnSortOrder::DatePlayed => tag_value(tags, "Date").map(str::to_owned),
+                PgnSortOrder::PlayerName => tag_value(tags, "White").map(str::to_lowercase),
+                PgnSortOrder::Result => match tag_value(tags, "Result") {
+                    Some("1-0") => Some("0".to_owned()),
+                    Some("1/2-1/2") => Some("1".to_owned()),
+                    Some("0-1") => Some("2".to_owned()),
+                    _ => None,
+                },
             }
+        };
+        match (key(a), key(b)) {
+            (Some(a), Some(b)) if self == PgnSortOrder::DatePlayed => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
         }
-        pages
     }
 }
 
-fn construct_pgn_loc_pattern_string(pgn_loc: &PathBuf) -> String {
+/// Value of a tag unless it is missing or unknown (e.g. "?" or "????.??.??")
+pub fn tag_value<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(tag, _)| tag == name)
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty() && value.chars().any(|c| c.is_alphanumeric()))
+}
+
+fn construct_pgn_loc_pattern_string(pgn_loc: &Path) -> String {
     let mut pgn_loc_str = pgn_loc.to_owned().into_os_string().into_string().unwrap();
     pgn_loc_str.push_str("/*.pgn");
     pgn_loc_str
 }
+
+/// All PGNs directly inside `directory`
+pub fn read_all(directory: &Path) -> Result<Vec<Pgn>> {
+    if !directory.exists() {
+        info!("No pgn directory found");
+        return Ok(Vec::new());
+    }
+    let mut pgns = Vec::new();
+    for entry in glob(&construct_pgn_loc_pattern_string(directory)).context("Read glob pattern")? {
+        match entry {
+            Ok(path) => pgns.push(Pgn { path }),
+            Err(e) => println!("{:?}", e),
+        }
+    }
+    Ok(pgns)
+}
+
+/// Subdirectories of `directory` sorted by name. Hidden ones are skipped.
+pub fn read_folders(directory: &Path) -> Result<Vec<PathBuf>> {
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+    let mut folders = Vec::new();
+    for entry in std::fs::read_dir(directory).context("Read directory")? {
+        let path = entry.context("Read directory entry")?.path();
+        let is_hidden = path
+            .file_name()
+            .map(|name| name.to_string_lossy().starts_with('.'))
+            .unwrap_or(true);
+        if path.is_dir() && !is_hidden {
+            folders.push(path);
+        }
+    }
+    folders.sort();
+    Ok(folders)
+}
+
+/// The last games of the most recently changed PGNs in `directory` and
+/// its subfolders (e.g. downloaded from lichess or transcribed scoresheets)
+pub fn read_recent_games(directory: &Path, count: usize) -> Result<Vec<RecentGame>> {
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+    let pattern = format!("{}/**/*.pgn", directory.display());
+    let mut pgns: Vec<(SystemTime, Pgn)> = vec![];
+    for path in glob(&pattern).context("Read glob pattern")? {
+        let path = match path {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("Skipping unreadable PGN: {:?}", e);
+                continue;
+            }
+        };
+        let is_hidden = path.strip_prefix(directory).map_or(true, |relative| {
+            relative
+                .components()
+                .any(|part| part.as_os_str().to_string_lossy().starts_with('.'))
+        });
+        if is_hidden {
+            continue;
+        }
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        pgns.push((modified, Pgn { path }));
+    }
+    pgns.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut recent_games = vec![];
+    for (_, pgn) in pgns {
+        if recent_games.len() >= count {
+            break;
+        }
+        let index = match read_index(&pgn) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("Failed to read {:?}: {:?}", pgn.path, e);
+                continue;
+            }
+        };
+        // Games are usually appended, so the last ones are the newest
+        for game in index.games.into_iter().rev() {
+            if recent_games.len() >= count {
+                break;
+            }
+            recent_games.push(RecentGame {
+                pgn: pgn.clone(),
+                game,
+            });
+        }
+    }
+    Ok(recent_games)
+}
+
+pub fn delete(pgn: &Pgn) -> Result<()> {
+    remove_index_file(pgn);
+    let annotations_path = annotations_file_path(pgn);
+    if annotations_path.exists() {
+        std::fs::remove_file(&annotations_path).context("Remove annotations")?;
+    }
+    std::fs::remove_file(&pgn.path).context("Remove file")
+}
+
+/// Rename the file inside its folder. The ".pgn" extension is added if missing.
+pub fn rename(pgn: &Pgn, new_name: &str) -> Result<Pgn> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() || new_name.contains('/') || new_name.starts_with('.') {
+        bail!("\"{}\" is not a valid file name", new_name);
+    }
+    let mut new_path = pgn.path.with_file_name(new_name);
+    if new_path.extension().map(|ext| ext != "pgn").unwrap_or(true) {
+        new_path = pgn.path.with_file_name(format!("{}.pgn", new_name));
+    }
+    move_file(pgn, new_path)
+}
+
+pub fn move_to_folder(pgn: &Pgn, folder: &Path) -> Result<Pgn> {
+    let file_name = pgn.path.file_name().context("Get file name")?;
+    move_file(pgn, folder.join(file_name))
+}
+
+fn move_file(pgn: &Pgn, new_path: PathBuf) -> Result<Pgn> {
+    if new_path.exists() {
+        bail!(
+            "{:?} already exists",
+            new_path.file_name().unwrap_or_default()
+        );
+    }
+    std::fs::rename(&pgn.path, &new_path).context("Rename file")?;
+    // The index would be left behind otherwise
+    remove_index_file(pgn);
+    let new_pgn = Pgn { path: new_path };
+    let annotations_path = annotations_file_path(pgn);
+    if annotations_path.exists() {
+        std::fs::rename(&annotations_path, annotations_file_path(&new_pgn))
+            .context("Move annotations")?;
+    }
+    Ok(new_pgn)
+}
+
+/// Only reads the tag pairs without parsing any moves
+pub fn read_metadata(pgn: &Pgn) -> Result<PgnMetadata> {
+    let index = read_index(pgn)?;
+    Ok(PgnMetadata {
+        games: index.games.into_iter().map(|game| game.tags).collect(),
+    })
+}
+
+/// Reuses the index file if it's still up to date. Otherwise the PGN
+/// gets scanned (and the index file written if the PGN is large).
+pub fn read_index(pgn: &Pgn) -> Result<PgnIndex> {
+    let file_metadata = std::fs::metadata(&pgn.path).context("Read file metadata")?;
+    let file_size = file_metadata.len();
+    let modified = file_metadata.modified().ok();
+    let use_index_file = file_size >= MIN_FILE_SIZE_FOR_INDEX_FILE;
+    let index_file_path = index_file_path(pgn);
+
+    if use_index_file && index_file_path.exists() {
+        match read_index_file(&index_file_path) {
+            Ok(index) if index.file_size == file_size && index.modified == modified => {
+                return Ok(index);
+            }
+            Ok(_) => info!("Index of {:?} is outdated", pgn.path),
+            Err(e) => warn!("Failed to read index of {:?}: {:?}", pgn.path, e),
+        }
+    }
+
+    let index = PgnIndex {
+        file_size,
+        modified,
+        games: scan_games(&pgn.path)?,
+    };
+    if use_index_file {
+        if let Err(e) = write_index_file(&index_file_path, &index) {
+            warn!("Failed to write index of {:?}: {:?}", pgn.path, e);
+        }
+    }
+    Ok(index)
+}
+
+/// Only reads and parses the given game of the PGN
+pub fn read_game(pgn: &Pgn, game: &IndexedGame) -> Result<Game> {
+    let mut file = File::open(&pgn.path).context("Open file")?;
+    file.seek(SeekFrom::Start(game.offset))
+        .context("Seek to game")?;
+    let mut contents = vec![0; game.length as usize];
+    file.read_exact(&mut contents).context("Read game")?;
+    parse_games(&String::from_utf8_lossy(&contents))?
+        .into_iter()
+        .next()
+        .ok_or(anyhow!("No game found at offset {}", game.offset))
+}
+
+pub fn parse_games(contents: &str) -> Result<Vec<Game>> {
+    let result = NESTED_BRACKETS_REGEX.replace_all(contents, "");
+    let result = WHITESPACE_REGEX.replace_all(&result, " ");
+    read_games(&result).map_err(|e| anyhow!("Failed to parse PGN: {:?}", e))
+}
+
+/// Finds where each game starts by looking for tags following movetext
+fn scan_games(path: &Path) -> Result<Vec<IndexedGame>> {
+    let mut reader = BufReader::new(File::open(path).context("Open file")?);
+    let mut games: Vec<IndexedGame> = vec![];
+    let mut in_tag_section = false;
+    let mut offset = 0;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).context("Read line")? as u64;
+        if read == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        if let Some(captures) = TAG_PAIR_REGEX.captures(&text) {
+            if !in_tag_section {
+                // Tags after movetext belong to the next game
+                if let Some(previous_game) = games.last_mut() {
+                    previous_game.length = offset - previous_game.offset;
+                }
+                games.push(IndexedGame {
+                    offset,
+                    length: 0,
+                    tags: vec![],
+                });
+                in_tag_section = true;
+            }
+            games
+                .last_mut()
+                .unwrap()
+                .tags
+                .push((captures[1].to_owned(), captures[2].to_owned()));
+        } else if !text.trim().is_empty() {
+            in_tag_section = false;
+        }
+        offset += read;
+    }
+    if let Some(last_game) = games.last_mut() {
+        last_game.length = offset - last_game.offset;
+    }
+    Ok(games)
+}
+
+/// E.g. "games.pgn" has its index in ".games.pgn.index"
+fn index_file_path(pgn: &Pgn) -> PathBuf {
+    let file_name = pgn.path.file_name().unwrap_or_default().to_string_lossy();
+    pgn.path.with_file_name(format!(".{}.index", file_name))
+}
+
+fn read_index_file(path: &Path) -> Result<PgnIndex> {
+    let file = File::open(path).context("Open file")?;
+    serde_json::from_reader(BufReader::new(file)).context("Deserialize file")
+}
+
+fn write_index_file(path: &Path, index: &PgnIndex) -> Result<()> {
+    let file = File::create(path).context("Create file")?;
+    serde_json::to_writer(BufWriter::new(file), index).context("Serialize and writing file")
+}
+
+fn annotations_file_path(pgn: &Pgn) -> PathBuf {
+    let file_name = pgn.path.file_name().unwrap_or_default().to_string_lossy();
+    pgn.path
+        .with_file_name(format!(".{}.annotations", file_name))
+}
+
+/// Empty if nothing was annotated yet
+pub fn read_annotations(pgn: &Pgn) -> Result<PgnAnnotations> {
+    let path = annotations_file_path(pgn);
+    if !path.exists() {
+        return Ok(Default::default());
+    }
+    let file = File::open(path).context("Open file")?;
+    serde_json::from_reader(BufReader::new(file)).context("Deserialize file")
+}
+
+pub fn write_annotations(pgn: &Pgn, annotations: &PgnAnnotations) -> Result<()> {
+    let file = File::create(annotations_file_path(pgn)).context("Create file")?;
+    serde_json::to_writer(BufWriter::new(file), annotations).context("Serialize and writing file")
+}
+
+fn remove_index_file(pgn: &Pgn) {
+    let path = index_file_path(pgn);
+    if path.exists() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            warn!("Failed to remove index {:?}: {:?}", path, e);
+        }
+    }
+}
+
+/// Case insensitive check whether every word of `query` is found in any of `texts`
+pub fn matches_query<'a>(query: &str, texts: impl Iterator<Item = &'a str>) -> bool {
+    let texts: Vec<String> = texts.map(|text| text.to_lowercase()).collect();
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .all(|word| texts.iter().any(|text| text.contains(word)))
+}
@@ -0,0 +1,22 @@
+use anyhow::{Context, Result};
+use chessmarkable::review::ReviewQueue;
+
+pub fn read() -> Result<ReviewQueue> {
+    let ref file_path = crate::config::review_queue_file();
+    if !file_path.exists() {
+        return Ok(ReviewQueue::default());
+    }
+    let file = std::fs::File::open(file_path).context("Open file")?;
+    serde_yaml::from_reader(file).context("Deserialize file")
+}
+
+pub fn write(queue: &ReviewQueue) -> Result<()> {
+    let ref file_path = crate::config::review_queue_file();
+    let directory = file_path.parent().ok_or(anyhow!("No parent directory"))?;
+    if !directory.exists() {
+        std::fs::create_dir_all(directory).context("Create directory for file")?;
+    }
+
+    let file = std::fs::File::create(file_path).context("Create file")?;
+    serde_yaml::to_writer(file, queue).context("Serialize and writing file")
+}
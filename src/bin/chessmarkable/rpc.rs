@@ -0,0 +1,378 @@
+//! JSON-RPC 2.0 server to play games from scripts or other tools (e.g.
+//! over the USB network). Every line sent is one request and gets
+//! answered with one line. The games only live as long as the app runs.
+//!
+//! Methods:
+//! - `new_game` `{"fen"?, "variant"?}` -> `{"id"}`
+//! - `games` -> list of ids
+//! - `state` `{"id"}` -> fen, turn, outcome, moves and possible moves
+//! - `move` `{"id", "source", "destination", "promotion"?}` -> state
+//! - `drop` `{"id", "piece", "square"}` -> state (crazyhouse)
+//! - `bot_move` `{"id", "depth"?}` -> state after the bot moved. The bot
+//!   doesn't drop pieces.
+//! - `undo` `{"id", "moves"?}` -> state
+//! - `pgn` `{"id"}` -> `{"pgn"}`
+
+use anyhow::{Context, Result};
+use chessmarkable::crazyhouse::DropPiece;
+use chessmarkable::game::{ChessGame, PossibleMove, PromotionPiece, VariantKind};
+use chessmarkable::scoresheet;
+use chessmarkable::{Player, Square};
+use pleco::bot_prelude::AlphaBetaSearcher;
+use pleco::tools::Searcher;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const DEFAULT_BOT_DEPTH: u16 = 3;
+const MAX_BOT_DEPTH: u16 = 6;
+
+// Error codes of the JSON-RPC spec
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The request was fine but the game refused it (e.g. an illegal move)
+const GAME_ERROR: i64 = -32000;
+
+struct RpcGame {
+    game: ChessGame,
+    starting_fen: String,
+    /// Played moves in SAN
+    moves: Vec<String>,
+}
+
+impl RpcGame {
+    fn state(&self) -> Value {
+        json!({
+            "fen": self.game.fen(),
+            "variant": self.game.variant(),
+            "turn": self.game.turn(),
+            "in_check": self.game.in_check(),
+            "outcome": self.game.outcome(),
+            "moves": self.moves,
+            "possible_moves": PossibleMove::from_bit_moves(&self.game.possible_moves()),
+            "pockets": self.game.pockets(),
+            "possible_drops": self.game.possible_drops(),
+        })
+    }
+
+    fn play(
+        &mut self,
+        source: Square,
+        destination: Square,
+        promotion: Option<PromotionPiece>,
+    ) -> Result<()> {
+        let san = self
+            .game
+            .san(source, destination, promotion)
+            .ok_or(anyhow!("Move not found as possibility"))?;
+        self.game
+            .move_piece_with_promotion(source, destination, promotion)?;
+        self.moves.push(san);
+        Ok(())
+    }
+
+    fn drop(&mut self, piece: DropPiece, square: Square) -> Result<()> {
+        self.game.drop_piece(piece, square)?;
+        self.moves.push(format!(
+            "{}@{}",
+            piece.fen_char(Player::White),
+            square.to_string().to_lowercase()
+        ));
+        Ok(())
+    }
+
+    fn pgn(&self) -> String {
+        let set_up = self.starting_fen != ChessGame::default().fen();
+        let result = self
+            .game
+            .outcome()
+            .map(|outcome| outcome.pgn_result())
+            .unwrap_or("*");
+        scoresheet::to_pgn(
+            &self.moves,
+            &[
+                ("Event", "chessmarkable"),
+                ("Result", result),
+                (
+                    "Variant",
+                    match self.game.variant() {
+                        VariantKind::Standard => "",
+                        variant => variant.label(),
+                    },
+                ),
+                ("SetUp", if set_up { "1" } else { "" }),
+                (
+                    "FEN",
+                    if set_up {
+                        self.starting_fen.as_str()
+                    } else {
+                        ""
+                    },
+                ),
+            ],
+        )
+    }
+}
+
+#[derive(Default)]
+struct Games {
+    next_id: u64,
+    games: BTreeMap<u64, RpcGame>,
+}
+
+#[derive(Deserialize)]
+struct NewGameParams {
+    fen: Option<String>,
+    #[serde(default)]
+    variant: VariantKind,
+}
+
+#[derive(Deserialize)]
+struct GameParams {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct MoveParams {
+    id: u64,
+    source: Square,
+    destination: Square,
+    promotion: Option<PromotionPiece>,
+}
+
+#[derive(Deserialize)]
+struct DropParams {
+    id: u64,
+    piece: DropPiece,
+    square: Square,
+}
+
+#[derive(Deserialize)]
+struct BotMoveParams {
+    id: u64,
+    depth: Option<u16>,
+}
+
+#[derive(Deserialize)]
+struct UndoParams {
+    id: u64,
+    moves: Option<u16>,
+}
+
+/// Error object of a JSON-RPC response
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err))
+}
+
+fn game_error(err: anyhow::Error) -> RpcError {
+    RpcError::new(GAME_ERROR, format!("{:#}", err))
+}
+
+impl Games {
+    fn game(&mut self, id: u64) -> Result<&mut RpcGame, RpcError> {
+        self.games
+            .get_mut(&id)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("No game with the id {}", id)))
+    }
+
+    fn call(&mut self, method: &str, params_value: Value) -> Result<Value, RpcError> {
+        match method {
+            "games" => Ok(json!(self.games.keys().collect::<Vec<_>>())),
+            "state" => {
+                let GameParams { id } = params(params_value)?;
+                Ok(self.game(id)?.state())
+            }
+            "move" => {
+                let MoveParams {
+                    id,
+                    source,
+                    destination,
+                    promotion,
+                } = params(params_value)?;
+                let game = self.game(id)?;
+                game.play(source, destination, promotion)
+                    .map_err(game_error)?;
+                Ok(game.state())
+            }
+            "drop" => {
+                let DropParams { id, piece, square } = params(params_value)?;
+                let game = self.game(id)?;
+                game.drop(piece, square).map_err(game_error)?;
+                Ok(game.state())
+            }
+            "undo" => {
+                let UndoParams { id, moves } = params(params_value)?;
+                let moves = moves.unwrap_or(1);
+                let game = self.game(id)?;
+                game.game.undo(moves).map_err(game_error)?;
+                let played = game.moves.len().saturating_sub(moves as usize);
+                game.moves.truncate(played);
+                Ok(game.state())
+            }
+            "pgn" => {
+                let GameParams { id } = params(params_value)?;
+                Ok(json!({ "pgn": self.game(id)?.pgn() }))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method \"{}\"", method),
+            )),
+        }
+    }
+}
+
+/// Reads the FEN before taking the lock of `games`, so a FEN that is
+/// refused (or even panics pleco) doesn't affect the other clients.
+fn new_game(games: &Mutex<Games>, params_value: Value) -> Result<Value, RpcError> {
+    let NewGameParams { fen, variant } = params(params_value)?;
+    let game = match fen {
+        Some(ref fen) => ChessGame::from_untrusted_fen(fen, variant),
+        None => ChessGame::with_variant(variant),
+    }
+    .map_err(game_error)?;
+    let mut games = games.lock().unwrap();
+    let id = games.next_id;
+    games.next_id += 1;
+    games.games.insert(
+        id,
+        RpcGame {
+            starting_fen: game.fen(),
+            game,
+            moves: vec![],
+        },
+    );
+    Ok(json!({ "id": id }))
+}
+
+/// Searches without holding the lock of `games`, so other requests don't
+/// wait for it. The move is only played if the game didn't change meanwhile.
+fn bot_move(games: &Mutex<Games>, params_value: Value) -> Result<Value, RpcError> {
+    let BotMoveParams { id, depth } = params(params_value)?;
+    let depth = depth.unwrap_or(DEFAULT_BOT_DEPTH).min(MAX_BOT_DEPTH);
+    let (board, fen) = {
+        let mut games = games.lock().unwrap();
+        let game = games.game(id)?;
+        if game.game.outcome().is_some() {
+            return Err(RpcError::new(GAME_ERROR, "The game is over"));
+        }
+        (game.game.board(), game.game.fen())
+    };
+    let bit_move = AlphaBetaSearcher::best_move(board, depth);
+    if bit_move.is_null() {
+        // Only drops are left
+        return Err(RpcError::new(
+            GAME_ERROR,
+            "The bot has no move (it doesn't drop pieces)",
+        ));
+    }
+    let mut games = games.lock().unwrap();
+    let game = games.game(id)?;
+    if game.game.fen() != fen {
+        return Err(RpcError::new(
+            GAME_ERROR,
+            "The game changed while the bot was thinking",
+        ));
+    }
+    let promotion = if bit_move.is_promo() {
+        PromotionPiece::from_piece_type(bit_move.promo_piece())
+    } else {
+        None
+    };
+    game.play(
+        bit_move.get_src().into(),
+        bit_move.get_dest().into(),
+        promotion,
+    )
+    .map_err(game_error)?;
+    Ok(game.state())
+}
+
+/// Answer to one line
+fn handle_line(games: &Mutex<Games>, line: &str) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => {
+            return json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": PARSE_ERROR, "message": err.to_string() },
+            })
+        }
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = request["method"].as_str().unwrap_or_default();
+    let params = match request.get("params") {
+        Some(params) => params.clone(),
+        None => json!({}),
+    };
+    let result = match method {
+        "new_game" => new_game(games, params),
+        "bot_move" => bot_move(games, params),
+        _ => games.lock().unwrap().call(method, params),
+    };
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": err.code, "message": err.message },
+        }),
+    }
+}
+
+fn serve_client(games: Arc<Mutex<Games>>, stream: TcpStream) -> Result<()> {
+    let mut writer = stream.try_clone().context("Clone stream")?;
+    for line in BufReader::new(stream).lines() {
+        let line = line.context("Read request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle_line(&games, &line);
+        writeln!(writer, "{}", response).context("Write response")?;
+    }
+    Ok(())
+}
+
+/// Accept clients on `address` (e.g. "127.0.0.1:7337") in the background
+pub fn start(address: &str) -> Result<()> {
+    let listener = TcpListener::bind(address).with_context(|| format!("Listen on {}", address))?;
+    info!("JSON-RPC server listening on {}", address);
+    let games = Arc::new(Mutex::new(Games::default()));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept JSON-RPC client: {:?}", err);
+                    continue;
+                }
+            };
+            let games = games.clone();
+            thread::spawn(move || {
+                if let Err(err) = serve_client(games, stream) {
+                    warn!("JSON-RPC client disconnected: {:?}", err);
+                }
+            });
+        }
+    });
+    Ok(())
+}
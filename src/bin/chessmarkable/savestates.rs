@@ -1,18 +1,239 @@
+use crate::correspondence::now;
+use crate::scene::{GameMode, SavestateSlot};
 use anyhow::{Context, Result};
+use chess_pgn_parser::Game;
+use chessmarkable::game::{ChessGame, VariantKind};
+use chessmarkable::proto::{ClockSnapshot, TimeControl};
+use chessmarkable::scoresheet;
+use pleco::Board;
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
+/// File in the PGN folder saves are exported to and imported from
+const PGN_FILE_NAME: &str = "savestates.pgn";
+/// Keeps the game mode (e.g. the opponent) in exported PGNs
+const MODE_TAG: &str = "Mode";
+
+/// A game saved under a name to be continued later
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Savestate {
+    /// Unique among the saves (the time it was created)
+    pub id: u64,
+    pub name: String,
+    /// Also the opponent
+    pub game_mode: GameMode,
+    pub variant: VariantKind,
+    pub fen: String,
+    /// Unix time it was saved last
+    pub saved_at: u64,
+    /// `None` for games without a clock
+    #[serde(default)]
+    pub clock: Option<SavedClock>,
+}
+
+/// Clock of a game that was left to be continued later
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SavedClock {
+    pub time_control: TimeControl,
+    /// Remaining times when the game was left
+    pub times: ClockSnapshot,
+}
+
+impl Savestate {
+    /// Number of the move to play next
+    pub fn move_number(&self) -> u16 {
+        move_number(&self.fen)
+    }
+
+    /// E.g. "Player vs Bot (Normal), move 24, saved 3 hours ago"
+    pub fn summary(&self) -> String {
+        format!(
+            "{}, move {}, saved {}",
+            self.game_mode.label(),
+            self.move_number(),
+            format_age(now().saturating_sub(self.saved_at))
+        )
+    }
+
+    /// A game without moves starting at the saved position (the moves
+    /// leading to it aren't saved). The FEN is left out for new games.
+    pub fn to_pgn(&self) -> String {
+        let date = scoresheet::pgn_date(self.saved_at);
+        let set_up = self.fen != Board::default().fen();
+        scoresheet::to_pgn(
+            &[],
+            &[
+                ("Event", self.name.as_str()),
+                ("Date", date.as_str()),
+                (
+                    "Variant",
+                    match self.variant {
+                        VariantKind::Standard => "",
+                        variant => variant.label(),
+                    },
+                ),
+                ("SetUp", if set_up { "1" } else { "" }),
+                ("FEN", if set_up { self.fen.as_str() } else { "" }),
+                (MODE_TAG, self.game_mode.label()),
+            ],
+        )
+    }
+}
+
+fn tag<'a>(game: &'a Game, name: &str) -> Option<&'a str> {
+    game.tags
+        .iter()
+        .find(|(tag_name, _)| tag_name == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Position after the last move of `game`
+fn final_fen(game: &Game) -> Result<String> {
+    Ok(ChessGame::from_pgn_game(game)?.fen())
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Savestates {
-    pub slot_1: Option<String>,
-    pub slot_2: Option<String>,
-    pub slot_3: Option<String>,
+    #[serde(default)]
+    pub saves: Vec<Savestate>,
+    // Fixed slots of older versions. Moved into `saves` when reading.
+    #[serde(default, skip_serializing)]
+    slot_1: Option<String>,
+    #[serde(default, skip_serializing)]
+    slot_2: Option<String>,
+    #[serde(default, skip_serializing)]
+    slot_3: Option<String>,
+}
+
+impl Savestates {
+    pub fn get(&self, id: u64) -> Option<&Savestate> {
+        self.saves.iter().find(|save| save.id == id)
+    }
+
+    /// Most recently saved first
+    pub fn sorted(&self) -> Vec<&Savestate> {
+        let mut saves: Vec<&Savestate> = self.saves.iter().collect();
+        saves.sort_by_key(|save| std::cmp::Reverse(save.saved_at));
+        saves
+    }
+
+    /// Suggested for a new save, e.g. "Game 4"
+    pub fn default_name(&self) -> String {
+        format!("Game {}", self.saves.len() + 1)
+    }
+
+    /// Add a new save and return its id
+    pub fn add(
+        &mut self,
+        name: String,
+        game_mode: GameMode,
+        variant: VariantKind,
+        fen: String,
+    ) -> u64 {
+        let mut id = now();
+        while self.get(id).is_some() {
+            id += 1;
+        }
+        self.saves.push(Savestate {
+            id,
+            name,
+            game_mode,
+            variant,
+            fen,
+            saved_at: now(),
+            clock: None,
+        });
+        id
+    }
+
+    /// Correspondence games are saved in their own files instead
+    pub fn set(&mut self, slot: SavestateSlot, fen: String, clock: Option<SavedClock>) {
+        if let SavestateSlot::Saved(id) = slot {
+            if let Some(save) = self.saves.iter_mut().find(|save| save.id == id) {
+                save.fen = fen;
+                save.clock = clock;
+                save.saved_at = now();
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        self.saves.retain(|save| save.id != id);
+    }
+
+    /// Continue the final position of a game as a new save. The mode
+    /// is taken from the "Mode" tag of exported saves.
+    fn add_from_pgn(&mut self, game: &Game) -> Result<bool> {
+        let fen = final_fen(game)?;
+        let name = match tag(game, "Event") {
+            Some(name) if name != "?" && !name.is_empty() => name.to_owned(),
+            _ => self.default_name(),
+        };
+        if self
+            .saves
+            .iter()
+            .any(|save| save.name == name && save.fen == fen)
+        {
+            return Ok(false); // Imported before
+        }
+        let game_mode = tag(game, MODE_TAG)
+            .and_then(|label| GameMode::ALL.iter().find(|mode| mode.label() == label))
+            .cloned()
+            .unwrap_or(GameMode::PvP);
+        let variant = match tag(game, "Variant") {
+            Some(variant) if variant.eq_ignore_ascii_case("crazyhouse") => VariantKind::Crazyhouse,
+            _ => VariantKind::Standard,
+        };
+        self.add(name, game_mode, variant, fen);
+        Ok(true)
+    }
+
+    /// The game mode of the old slots isn't known. They are continued
+    /// as games between players.
+    fn migrate_slots(&mut self) {
+        let slots = vec![self.slot_1.take(), self.slot_2.take(), self.slot_3.take()];
+        for (i, fen) in slots.into_iter().enumerate() {
+            if let Some(fen) = fen {
+                let variant = if fen.contains('[') {
+                    VariantKind::Crazyhouse
+                } else {
+                    VariantKind::Standard
+                };
+                self.add(format!("Slot {}", i + 1), GameMode::PvP, variant, fen);
+            }
+        }
+    }
+}
+
+/// E.g. "just now" or "2 days ago"
+fn format_age(secs: u64) -> String {
+    let (amount, unit) = match secs {
+        0..=59 => return "just now".to_owned(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!(
+        "{} {}{} ago",
+        amount,
+        unit,
+        if amount == 1 { "" } else { "s" }
+    )
+}
+
+/// Number of the move to play next in the position of `fen`
+pub fn move_number(fen: &str) -> u16 {
+    fen.split(' ')
+        .nth(5)
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(1)
 }
 
 impl Default for Savestates {
     fn default() -> Self {
         Self {
+            saves: vec![],
             slot_1: None,
             slot_2: None,
             slot_3: None,
@@ -21,28 +242,70 @@ impl Default for Savestates {
 }
 
 pub fn read() -> Result<Savestates> {
-    let ref file_path = crate::CLI_OPTS.savestates_file;
+    let ref file_path = crate::config::savestates_file();
 
     if !file_path.exists() {
         info!("Savestates file doesn't exist (yet).");
         Ok(Savestates::default())
     } else {
         let file = std::fs::File::open(file_path).context("Open file")?;
-        let savestates: Savestates = serde_yaml::from_reader(file).context("Deserialize file")?;
+        let mut savestates: Savestates =
+            serde_yaml::from_reader(file).context("Deserialize file")?;
+        savestates.migrate_slots();
         Ok(savestates)
     }
 }
 
 pub fn write(savestates: &Savestates) -> Result<()> {
-    let directory = crate::CLI_OPTS
-        .savestates_file
-        .parent()
-        .ok_or(anyhow!("No parent directory"))?;
+    let ref file_path = crate::config::savestates_file();
+    let directory = file_path.parent().ok_or(anyhow!("No parent directory"))?;
     if !directory.exists() {
         std::fs::create_dir_all(directory).context("Create directory for file")?;
         info!("Created directory for savestate file.");
     }
 
-    let file = std::fs::File::create(&crate::CLI_OPTS.savestates_file).context("Create file")?;
+    let file = std::fs::File::create(file_path).context("Create file")?;
     serde_yaml::to_writer(file, savestates).context("Serialize and writing file")
 }
+
+fn pgn_path() -> PathBuf {
+    crate::config::pgn_location().join(PGN_FILE_NAME)
+}
+
+/// Write all saves (the most recent first) into one PGN in the PGN folder
+pub fn export_pgn(savestates: &Savestates) -> Result<PathBuf> {
+    let pgn = savestates
+        .sorted()
+        .iter()
+        .map(|save| save.to_pgn())
+        .collect::<Vec<String>>()
+        .join("\n");
+    std::fs::create_dir_all(&crate::config::pgn_location()).context("Create PGN folder")?;
+    let path = pgn_path();
+    std::fs::write(&path, pgn).with_context(|| format!("Write {:?}", path))?;
+    Ok(path)
+}
+
+/// Add the games of the PGN written by `export_pgn()` (or any other PGN
+/// put there) as saves. Games that were imported before are skipped.
+/// Returns the number of added games and of games that couldn't be read.
+pub fn import_pgn(savestates: &mut Savestates) -> Result<(usize, usize)> {
+    let path = pgn_path();
+    let mut contents = String::new();
+    std::fs::File::open(&path)
+        .with_context(|| format!("Open {:?}", path))?
+        .read_to_string(&mut contents)
+        .context("Read file")?;
+    let (mut added, mut failed) = (0, 0);
+    for game in crate::pgns::parse_games(&contents)? {
+        match savestates.add_from_pgn(&game) {
+            Ok(true) => added += 1,
+            Ok(false) => {}
+            Err(err) => {
+                warn!("Failed to import game of {:?}: {:?}", path, err);
+                failed += 1;
+            }
+        }
+    }
+    Ok((added, failed))
+}
@@ -1,27 +1,93 @@
+use super::keyboard::{Keyboard, KeyboardEvent, FEN_LAYOUT, SEARCH_LAYOUT};
+use super::mini_board::MiniBoard;
 use super::Scene;
 use crate::canvas::*;
-use crate::savestates::Savestates;
-use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+use crate::scene::GameMode;
+use crate::settings::BotVsBotSettings;
+use chessmarkable::clock::PRESETS;
+use chessmarkable::game::{ChessGame, Odds};
+use chessmarkable::middlegames::{self, Middlegame};
+use chessmarkable::proto::VariantKind;
+use chessmarkable::setup::PositionSetup;
+use chessmarkable::share::read_shared;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+
+const KEYBOARD_Y_POS: i32 = 900;
+/// Deeper searches take too long on the device
+const MAX_BOT_DEPTH: u16 = 6;
+/// Leaves room for the preview of the position above it
+const FEN_KEYBOARD_Y_POS: i32 = 1180;
+const FEN_PREVIEW_TOP: i32 = 440;
+const FEN_PREVIEW_SQUARE_SIZE: u32 = 70;
+
+/// The position of a typed FEN, or after typed moves (e.g. "1. e4 e5" as
+/// in a shared PGN) which are played from the usual position
+fn typed_fen(text: &str) -> String {
+    match read_shared(text) {
+        Ok(game) => game.fen(),
+        // Reported by `fen_error()`
+        Err(_) => text.trim().to_owned(),
+    }
+}
+
+/// Why a game can't start from `fen` (`None` if it can). The setup gives
+/// more helpful reasons than the board (e.g. a missing king).
+fn fen_error(fen: &str, variant: VariantKind) -> Option<String> {
+    PositionSetup::from_fen(fen)
+        .and_then(|setup| setup.validate())
+        .and_then(|_| ChessGame::from_fen_with_variant(fen, variant))
+        .err()
+        .map(|err| err.to_string())
+}
 
 pub struct BoardSelectScene {
     drawn: bool,
 
     pub selected_gamemode: crate::scene::GameMode,
     pub pvp_piece_rotation_enabled: bool,
+    /// Rules for new games. Bots only play standard chess.
+    pub variant: VariantKind,
+    variant_button_hitbox: Option<mxcfb_rect>,
+    /// Handicap of the bot when starting a new game
+    pub odds: Odds,
+    odds_button_hitbox: Option<mxcfb_rect>,
 
-    select_slot_1_button_hitbox: Option<mxcfb_rect>,
-    pub select_slot_1_button_pressed: bool,
-    select_slot_2_button_hitbox: Option<mxcfb_rect>,
-    pub select_slot_2_button_pressed: bool,
-    select_slot_3_button_hitbox: Option<mxcfb_rect>,
-    pub select_slot_3_button_pressed: bool,
+    /// Of the save the new game is played on
+    name: String,
+    name_button_hitbox: Option<mxcfb_rect>,
+    keyboard: Option<Keyboard>,
+    keyboard_drawn: bool,
+    /// Position the new game starts from instead of the usual one
+    fen: Option<String>,
+    fen_button_hitbox: Option<mxcfb_rect>,
+    /// Set while the keyboard is used for the FEN
+    fen_preview: Option<MiniBoard>,
+    redraw_fen_preview: bool,
+    /// Where `fen` came from if it's a random middlegame
+    middlegame: Option<&'static Middlegame>,
+    middlegame_button_hitbox: Option<mxcfb_rect>,
+    start_button_hitbox: Option<mxcfb_rect>,
+    /// Set once the save for the new game was added
+    pub started_save: Option<u64>,
 
-    reset_slot_1_button_hitbox: Option<mxcfb_rect>,
-    pub reset_slot_1_button_pressed: bool,
-    reset_slot_2_button_hitbox: Option<mxcfb_rect>,
-    pub reset_slot_2_button_pressed: bool,
-    reset_slot_3_button_hitbox: Option<mxcfb_rect>,
-    pub reset_slot_3_button_pressed: bool,
+    editor_button_hitbox: Option<mxcfb_rect>,
+    pub editor_button_pressed: bool,
+    endgames_button_hitbox: Option<mxcfb_rect>,
+    pub endgames_button_pressed: bool,
+    auto_flip_button_hitbox: Option<mxcfb_rect>,
+    time_control_button_hitbox: Option<mxcfb_rect>,
+    /// Set up the bots of bot vs bot games
+    white_bot_button_hitbox: Option<mxcfb_rect>,
+    black_bot_button_hitbox: Option<mxcfb_rect>,
+    eval_button_hitbox: Option<mxcfb_rect>,
+    tournament_button_hitbox: Option<mxcfb_rect>,
+    pub tournament_button_pressed: bool,
+    simul_button_hitbox: Option<mxcfb_rect>,
+    pub simul_button_pressed: bool,
 
     back_button_hitbox: Option<mxcfb_rect>,
     pub back_button_pressed: bool,
@@ -38,25 +104,190 @@ impl BoardSelectScene {
             drawn: false,
             selected_gamemode,
             pvp_piece_rotation_enabled,
-            select_slot_1_button_hitbox: None,
-            select_slot_1_button_pressed: false,
-            select_slot_2_button_hitbox: None,
-            select_slot_2_button_pressed: false,
-            select_slot_3_button_hitbox: None,
-            select_slot_3_button_pressed: false,
-
-            reset_slot_1_button_hitbox: None,
-            reset_slot_1_button_pressed: false,
-            reset_slot_2_button_hitbox: None,
-            reset_slot_2_button_pressed: false,
-            reset_slot_3_button_hitbox: None,
-            reset_slot_3_button_pressed: false,
+            variant: VariantKind::Standard,
+            variant_button_hitbox: None,
+            odds: Odds::None,
+            odds_button_hitbox: None,
+            name: crate::SAVESTATES.lock().unwrap().default_name(),
+            name_button_hitbox: None,
+            keyboard: None,
+            keyboard_drawn: false,
+            fen: None,
+            fen_button_hitbox: None,
+            fen_preview: None,
+            redraw_fen_preview: false,
+            middlegame: None,
+            middlegame_button_hitbox: None,
+            start_button_hitbox: None,
+            started_save: None,
+            editor_button_hitbox: None,
+            editor_button_pressed: false,
+            endgames_button_hitbox: None,
+            endgames_button_pressed: false,
+            auto_flip_button_hitbox: None,
+            time_control_button_hitbox: None,
+            white_bot_button_hitbox: None,
+            black_bot_button_hitbox: None,
+            eval_button_hitbox: None,
+            tournament_button_hitbox: None,
+            tournament_button_pressed: false,
+            simul_button_hitbox: None,
+            simul_button_pressed: false,
             back_button_hitbox: None,
             back_button_pressed: false,
             indicate_loading: false,
         }
     }
 
+    /// Switch to the next preset (after the last one the clock is off)
+    fn next_time_control(&mut self) {
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        let next = match settings.time_control {
+            None => 0,
+            Some(time_control) => PRESETS
+                .iter()
+                .position(|preset| *preset == time_control)
+                .map_or(0, |index| index + 1),
+        };
+        settings.time_control = PRESETS.get(next).cloned();
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+        self.drawn = false;
+    }
+
+    /// The choices are kept for the next bot vs bot game
+    fn change_bot_vs_bot(&mut self, change: impl FnOnce(&mut BotVsBotSettings)) {
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        change(&mut settings.bot_vs_bot);
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+        self.drawn = false;
+    }
+
+    fn draw_bot_vs_bot_buttons(&mut self, canvas: &mut Canvas) {
+        let settings = crate::SETTINGS.lock().unwrap().bot_vs_bot;
+        self.white_bot_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(40),
+                y: Some(1460),
+            },
+            &format!("White bot: depth {}", settings.white_depth),
+            40.0,
+            10,
+            20,
+        ));
+        self.black_bot_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(520),
+                y: Some(1460),
+            },
+            &format!("Black bot: depth {}", settings.black_depth),
+            40.0,
+            10,
+            20,
+        ));
+        self.eval_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(1000),
+                y: Some(1460),
+            },
+            if settings.show_eval {
+                "Eval: On"
+            } else {
+                "Eval: Off"
+            },
+            40.0,
+            10,
+            20,
+        ));
+        self.tournament_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 300),
+                y: Some(1580),
+            },
+            "Tournament",
+            40.0,
+            10,
+            20,
+        ));
+    }
+
+    /// Add a save for the new game. Odds only apply against the bot
+    /// and are ignored when starting from a FEN.
+    fn start(&mut self) {
+        let fen = if let Some(ref fen) = self.fen {
+            fen.clone()
+        } else if self.selected_gamemode.has_bot() {
+            self.odds.starting_fen()
+        } else {
+            Odds::None.starting_fen()
+        };
+        let mut savestates = crate::SAVESTATES.lock().unwrap();
+        let name = match self.name.trim() {
+            "" => savestates.default_name(),
+            name => name.to_owned(),
+        };
+        let id = savestates.add(name, self.selected_gamemode, self.variant, fen);
+        if let Err(err) = crate::savestates::write(&savestates) {
+            error!("Failed to write savestates file: {:?}", err);
+        }
+        self.started_save = Some(id);
+        self.indicate_loading = true;
+    }
+
+    fn open_fen_keyboard(&mut self) {
+        let fen = self
+            .fen
+            .clone()
+            .unwrap_or_else(|| Odds::None.starting_fen());
+        self.keyboard = Some(Keyboard::new(FEN_LAYOUT, &fen, FEN_KEYBOARD_Y_POS));
+        self.keyboard_drawn = false;
+        self.fen_preview = Some(MiniBoard::new(FEN_PREVIEW_SQUARE_SIZE));
+        self.redraw_fen_preview = true;
+    }
+
+    /// The typed position and whether it can be played
+    fn draw_fen_preview(&self, canvas: &mut Canvas) {
+        let (preview, keyboard) = match (&self.fen_preview, &self.keyboard) {
+            (Some(preview), Some(keyboard)) => (preview, keyboard),
+            _ => return,
+        };
+        let area = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(FEN_PREVIEW_TOP),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: (FEN_KEYBOARD_Y_POS - FEN_PREVIEW_TOP) as u32,
+            },
+            color::WHITE,
+        );
+        preview.draw(
+            canvas,
+            Point2 {
+                x: (DISPLAYWIDTH as u32 - preview.size()) as i32 / 2,
+                y: FEN_PREVIEW_TOP + 20,
+            },
+            &typed_fen(&keyboard.text),
+        );
+        let status = match fen_error(&typed_fen(&keyboard.text), self.variant) {
+            Some(err) => format!("Invalid: {}", err),
+            None => "Valid position, tap Done to use it".to_owned(),
+        };
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(FEN_KEYBOARD_Y_POS - 30),
+            },
+            &status,
+            40.0,
+        );
+        canvas.mark_dirty(&area);
+    }
+
     fn indicate_loading(&self, canvas: &mut Canvas) {
         let rect = canvas.draw_text(
             Point2 {
@@ -78,6 +309,19 @@ impl Scene for BoardSelectScene {
             return;
         }
 
+        if self.redraw_fen_preview {
+            self.redraw_fen_preview = false;
+            self.draw_fen_preview(canvas);
+        }
+        if let Some(ref mut keyboard) = self.keyboard {
+            if !self.keyboard_drawn {
+                let rect = keyboard.draw(canvas);
+                canvas.mark_dirty(&rect);
+                self.keyboard_drawn = true;
+            }
+            keyboard.update(canvas);
+            return;
+        }
         if self.drawn {
             return;
         }
@@ -92,155 +336,347 @@ impl Scene for BoardSelectScene {
             "chessMarkable",
             150.0,
         );
-
         canvas.draw_text(
             Point2 {
                 x: None,
-                y: Some(500),
+                y: Some(400),
             },
-            "Continue on...",
-            75.0,
+            self.selected_gamemode.label(),
+            50.0,
         );
 
-        self.select_slot_1_button_hitbox = Some(canvas.draw_button(
+        canvas.draw_text(
             Point2 {
                 x: None,
                 y: Some(600),
             },
-            "Slot 1",
+            "New game",
             75.0,
-            25,
-            50,
-        ));
-
-        self.select_slot_2_button_hitbox = Some(canvas.draw_button(
+        );
+        self.name_button_hitbox = Some(canvas.draw_button(
             Point2 {
                 x: None,
-                y: Some(
-                    100 + self.select_slot_1_button_hitbox.unwrap().top as i32
-                        + self.select_slot_1_button_hitbox.unwrap().height as i32,
-                ),
+                y: Some(750),
             },
-            "Slot 2",
-            75.0,
-            25,
-            50,
+            &format!("Name: {}", self.name),
+            60.0,
+            20,
+            40,
         ));
-
-        self.select_slot_3_button_hitbox = Some(canvas.draw_button(
+        self.start_button_hitbox = Some(canvas.draw_button(
             Point2 {
                 x: None,
-                y: Some(
-                    100 + self.select_slot_2_button_hitbox.unwrap().top as i32
-                        + self.select_slot_2_button_hitbox.unwrap().height as i32,
-                ),
+                y: Some(1000),
             },
-            "Slot 3",
-            75.0,
+            "Start",
+            125.0,
             25,
             50,
         ));
-
         canvas.draw_text(
             Point2 {
                 x: None,
-                y: Some(1000),
+                y: Some(1200),
             },
-            "Start over on...",
-            75.0,
+            "Saved games are continued in the main menu",
+            40.0,
         );
+        if self.fen.is_some() {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(1270),
+                },
+                &match self.middlegame {
+                    Some(middlegame) => format!("Starts from a {} position", middlegame.name),
+                    None => "Starts from the entered FEN".to_owned(),
+                },
+                40.0,
+            );
+        }
 
-        self.reset_slot_1_button_hitbox = Some(canvas.draw_button(
+        self.back_button_hitbox = Some(canvas.draw_button(
             Point2 {
                 x: None,
-                y: Some(1100),
+                y: Some(1700),
             },
-            "Slot 1",
-            75.0,
+            "Back",
+            125.0,
             25,
             50,
         ));
 
-        self.reset_slot_2_button_hitbox = Some(canvas.draw_button(
+        let clock_label = match crate::SETTINGS.lock().unwrap().time_control {
+            Some(time_control) => format!(
+                "Clock: {} {}",
+                time_control.label(),
+                time_control.speed().label()
+            ),
+            None => "Clock: Off".to_owned(),
+        };
+        self.time_control_button_hitbox = Some(canvas.draw_button(
             Point2 {
-                x: None,
-                y: Some(
-                    100 + self.reset_slot_1_button_hitbox.unwrap().top as i32
-                        + self.reset_slot_1_button_hitbox.unwrap().height as i32,
-                ),
+                x: Some(40),
+                y: Some(1700),
             },
-            "Slot 2",
-            75.0,
-            25,
-            50,
+            &clock_label,
+            40.0,
+            10,
+            20,
         ));
 
-        self.reset_slot_3_button_hitbox = Some(canvas.draw_button(
+        self.fen_button_hitbox = Some(canvas.draw_button(
             Point2 {
-                x: None,
-                y: Some(
-                    100 + self.reset_slot_2_button_hitbox.unwrap().top as i32
-                        + self.reset_slot_2_button_hitbox.unwrap().height as i32,
-                ),
+                x: Some(DISPLAYWIDTH as i32 - 250),
+                y: Some(1700),
             },
-            "Slot 3",
-            75.0,
-            25,
-            50,
+            if self.fen.is_some() {
+                "FEN: Set"
+            } else {
+                "Enter FEN"
+            },
+            40.0,
+            10,
+            20,
         ));
 
-        self.back_button_hitbox = Some(canvas.draw_button(
+        self.editor_button_hitbox = Some(canvas.draw_button(
             Point2 {
-                x: None,
-                y: Some(1700),
+                x: Some(40),
+                y: Some(1840),
             },
-            "Back",
-            125.0,
-            25,
-            50,
+            "Set up position",
+            40.0,
+            10,
+            20,
         ));
+        self.auto_flip_button_hitbox = if self.selected_gamemode == GameMode::PvP {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: Some(450),
+                    y: Some(1840),
+                },
+                if crate::SETTINGS.lock().unwrap().pvp_auto_flip {
+                    "Auto flip: On"
+                } else {
+                    "Auto flip: Off"
+                },
+                40.0,
+                10,
+                20,
+            ))
+        } else {
+            None
+        };
+        if self.selected_gamemode == GameMode::BotVsBot {
+            self.draw_bot_vs_bot_buttons(canvas);
+        }
+        self.middlegame_button_hitbox = if self.selected_gamemode.has_bot() {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: Some(40),
+                    y: Some(1580),
+                },
+                "Random position",
+                40.0,
+                10,
+                20,
+            ))
+        } else {
+            None
+        };
+        self.simul_button_hitbox =
+            if self.selected_gamemode.has_bot() && self.selected_gamemode != GameMode::BotVsBot {
+                Some(canvas.draw_button(
+                    Point2 {
+                        x: Some(DISPLAYWIDTH as i32 - 300),
+                        y: Some(1580),
+                    },
+                    "Simul",
+                    40.0,
+                    10,
+                    20,
+                ))
+            } else {
+                None
+            };
+        self.endgames_button_hitbox = if self.selected_gamemode.has_bot() {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: Some(450),
+                    y: Some(1840),
+                },
+                "Endgames",
+                40.0,
+                10,
+                20,
+            ))
+        } else {
+            None
+        };
+        self.odds_button_hitbox = if self.selected_gamemode.has_bot() {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: Some(900),
+                    y: Some(1840),
+                },
+                &format!("Bot odds: {}", self.odds.label()),
+                40.0,
+                10,
+                20,
+            ))
+        } else {
+            None
+        };
+        self.variant_button_hitbox = if self.selected_gamemode.has_bot() {
+            None
+        } else {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: Some(1000),
+                    y: Some(1840),
+                },
+                &format!("Rules: {}", self.variant.label()),
+                40.0,
+                10,
+                20,
+            ))
+        };
 
         canvas.update_full();
     }
 
     fn on_input(&mut self, event: InputEvent) {
+        if let Some(ref mut keyboard) = self.keyboard {
+            match keyboard.on_input(event) {
+                Some(KeyboardEvent::Done) => {
+                    let text = keyboard.text.trim().to_owned();
+                    if self.fen_preview.is_some() {
+                        let text = typed_fen(&text);
+                        if fen_error(&text, self.variant).is_some() {
+                            return; // The reason is shown above the keyboard
+                        }
+                        self.fen = if text == Odds::None.starting_fen() {
+                            None
+                        } else {
+                            Some(text)
+                        };
+                        self.fen_preview = None;
+                        self.middlegame = None;
+                    } else {
+                        self.name = text;
+                    }
+                    self.keyboard = None;
+                    self.drawn = false;
+                }
+                Some(KeyboardEvent::Cancelled) => {
+                    self.keyboard = None;
+                    self.fen_preview = None;
+                    self.drawn = false;
+                }
+                Some(KeyboardEvent::Edited) => {
+                    self.redraw_fen_preview = self.fen_preview.is_some();
+                }
+                None => {}
+            }
+            return;
+        }
+        if let InputEvent::GPIO {
+            event: GPIOEvent::Press {
+                button: PhysicalButton::MIDDLE,
+            },
+        } = event
+        {
+            self.back_button_pressed = true;
+        }
         if let InputEvent::MultitouchEvent { event } = event {
             if let MultitouchEvent::Release { finger, .. } = event {
                 let position = finger.pos;
-                if self.select_slot_1_button_hitbox.is_some()
-                    && Canvas::is_hitting(position, self.select_slot_1_button_hitbox.unwrap())
-                {
-                    self.select_slot_1_button_pressed = true;
-                    self.indicate_loading = true;
-                } else if self.select_slot_2_button_hitbox.is_some()
-                    && Canvas::is_hitting(position, self.select_slot_2_button_hitbox.unwrap())
-                {
-                    self.select_slot_2_button_pressed = true;
-                    self.indicate_loading = true;
-                } else if self.select_slot_3_button_hitbox.is_some()
-                    && Canvas::is_hitting(position, self.select_slot_3_button_hitbox.unwrap())
-                {
-                    self.select_slot_3_button_pressed = true;
-                    self.indicate_loading = true;
-                } else if self.reset_slot_1_button_hitbox.is_some()
-                    && Canvas::is_hitting(position, self.reset_slot_1_button_hitbox.unwrap())
-                {
-                    self.reset_slot_1_button_pressed = true;
-                    self.indicate_loading = true;
-                } else if self.reset_slot_2_button_hitbox.is_some()
-                    && Canvas::is_hitting(position, self.reset_slot_2_button_hitbox.unwrap())
-                {
-                    self.reset_slot_2_button_pressed = true;
-                    self.indicate_loading = true;
-                } else if self.reset_slot_3_button_hitbox.is_some()
-                    && Canvas::is_hitting(position, self.reset_slot_3_button_hitbox.unwrap())
-                {
-                    self.reset_slot_3_button_pressed = true;
-                    self.indicate_loading = true;
+                if self.start_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.start_button_hitbox.unwrap())
+                {
+                    self.start();
+                } else if self.name_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.name_button_hitbox.unwrap())
+                {
+                    self.keyboard = Some(Keyboard::new(SEARCH_LAYOUT, &self.name, KEYBOARD_Y_POS));
+                    self.keyboard_drawn = false;
+                } else if self.fen_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.fen_button_hitbox.unwrap())
+                {
+                    self.open_fen_keyboard();
                 } else if self.back_button_hitbox.is_some()
                     && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
                 {
                     self.back_button_pressed = true;
+                } else if self.variant_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.variant_button_hitbox.unwrap())
+                {
+                    self.variant = match self.variant {
+                        VariantKind::Standard => VariantKind::Crazyhouse,
+                        VariantKind::Crazyhouse => VariantKind::Standard,
+                    };
+                    self.drawn = false;
+                } else if self.editor_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.editor_button_hitbox.unwrap())
+                {
+                    self.editor_button_pressed = true;
+                } else if self.endgames_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.endgames_button_hitbox.unwrap())
+                {
+                    self.endgames_button_pressed = true;
+                } else if self.simul_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.simul_button_hitbox.unwrap())
+                {
+                    self.simul_button_pressed = true;
+                } else if self.middlegame_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.middlegame_button_hitbox.unwrap())
+                {
+                    let middlegame = match crate::config::bot_seed() {
+                        Some(seed) => middlegames::pick(seed, self.middlegame),
+                        None => middlegames::random(self.middlegame),
+                    };
+                    self.fen = Some(middlegame.fen.to_owned());
+                    self.middlegame = Some(middlegame);
+                    self.drawn = false;
+                } else if self.auto_flip_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.auto_flip_button_hitbox.unwrap())
+                {
+                    let mut settings = crate::SETTINGS.lock().unwrap();
+                    settings.pvp_auto_flip = !settings.pvp_auto_flip;
+                    if let Err(err) = crate::settings::write(&settings) {
+                        error!("Failed to write settings file: {:?}", err);
+                    }
+                    self.drawn = false;
+                } else if self.odds_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.odds_button_hitbox.unwrap())
+                {
+                    self.odds = self.odds.next();
+                    self.drawn = false;
+                } else if self.time_control_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.time_control_button_hitbox.unwrap())
+                {
+                    self.next_time_control();
+                } else if self.white_bot_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.white_bot_button_hitbox.unwrap())
+                {
+                    self.change_bot_vs_bot(|bots| {
+                        bots.white_depth = bots.white_depth % MAX_BOT_DEPTH + 1
+                    });
+                } else if self.black_bot_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.black_bot_button_hitbox.unwrap())
+                {
+                    self.change_bot_vs_bot(|bots| {
+                        bots.black_depth = bots.black_depth % MAX_BOT_DEPTH + 1
+                    });
+                } else if self.eval_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.eval_button_hitbox.unwrap())
+                {
+                    self.change_bot_vs_bot(|bots| bots.show_eval = !bots.show_eval);
+                } else if self.tournament_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.tournament_button_hitbox.unwrap())
+                {
+                    self.tournament_button_pressed = true;
                 }
             }
         }
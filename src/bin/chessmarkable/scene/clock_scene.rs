@@ -0,0 +1,281 @@
+use super::clock_widget::ClockWidget;
+use super::Scene;
+use crate::canvas::*;
+use chessmarkable::clock::{ChessClock, TimeControl, PRESETS};
+use chessmarkable::Player;
+use libremarkable::input::{
+    gpio::GPIOEvent, gpio::PhysicalButton, multitouch::MultitouchEvent, InputEvent,
+};
+use std::time::Instant;
+
+/// Height of the strip with the buttons between both clocks
+const MIDDLE_STRIP_HEIGHT: u32 = 200;
+const CLOCK_FONT_SIZE: f32 = 250.0;
+const PRESET_BUTTONS_TOP: i32 = 450;
+const PRESET_ROW_HEIGHT: i32 = 170;
+
+/// Button of a fixed size (so buttons line up regardless of their text)
+fn draw_fixed_button(
+    canvas: &mut Canvas,
+    pos: Point2<i32>,
+    size: Vector2<u32>,
+    text: &str,
+    font_size: f32,
+) -> mxcfb_rect {
+    let pos = Point2 {
+        x: Some(pos.x),
+        y: Some(pos.y),
+    };
+    canvas.fill_rect(pos, size, color::WHITE);
+    let rect = canvas.draw_rect(pos, size, 5);
+    canvas.draw_text_centered(rect, text, font_size);
+    rect
+}
+
+/// The tablet as chess clock for games on a real board. It lies between
+/// the players: black's clock is at the top, white's at the bottom.
+/// Every player taps their own half after their move.
+pub struct ClockScene {
+    drawn: bool,
+    /// `None` while the time control is being chosen
+    clock: Option<ChessClock>,
+    white_clock: ClockWidget,
+    black_clock: ClockWidget,
+    preset_hitboxes: Vec<mxcfb_rect>,
+    pause_button_hitbox: Option<mxcfb_rect>,
+    pause_button_redraw: bool,
+    reset_button_hitbox: Option<mxcfb_rect>,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl ClockScene {
+    pub fn new() -> Self {
+        let half_height = (DISPLAYHEIGHT as u32 - MIDDLE_STRIP_HEIGHT) / 2;
+        let clock_area = |top: u32| mxcfb_rect {
+            left: 30,
+            top: top + 30,
+            width: DISPLAYWIDTH as u32 - 60,
+            height: half_height - 60,
+        };
+        Self {
+            drawn: false,
+            clock: None,
+            black_clock: ClockWidget::new(clock_area(0), CLOCK_FONT_SIZE),
+            white_clock: ClockWidget::new(
+                clock_area(half_height + MIDDLE_STRIP_HEIGHT),
+                CLOCK_FONT_SIZE,
+            ),
+            preset_hitboxes: vec![],
+            pause_button_hitbox: None,
+            pause_button_redraw: false,
+            reset_button_hitbox: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    fn draw_presets(&mut self, canvas: &mut Canvas) {
+        canvas.draw_styled_text(
+            Point2 {
+                x: None,
+                y: Some(200),
+            },
+            "Chess Clock",
+            125.0,
+            FontStyle::Bold,
+        );
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(330),
+            },
+            "Minutes + increment, delay (d) or Bronstein delay (b) in seconds",
+            40.0,
+        );
+        self.preset_hitboxes = PRESETS
+            .iter()
+            .enumerate()
+            .map(|(i, time_control)| {
+                let column = (i % 3) as i32;
+                let row = (i / 3) as i32;
+                draw_fixed_button(
+                    canvas,
+                    Point2 {
+                        x: DISPLAYWIDTH as i32 / 6 * (1 + column * 2) - 180,
+                        y: PRESET_BUTTONS_TOP + row * PRESET_ROW_HEIGHT,
+                    },
+                    Vector2 { x: 360, y: 130 },
+                    &time_control.label(),
+                    75.0,
+                )
+            })
+            .collect();
+        self.back_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1750),
+            },
+            "Main Menu",
+            75.0,
+            25,
+            50,
+        ));
+    }
+
+    fn middle_strip_top() -> i32 {
+        (DISPLAYHEIGHT as i32 - MIDDLE_STRIP_HEIGHT as i32) / 2
+    }
+
+    fn draw_pause_button(&mut self, canvas: &mut Canvas) {
+        let paused = self.clock.as_ref().map_or(false, |clock| clock.is_paused());
+        let rect = draw_fixed_button(
+            canvas,
+            Point2 {
+                x: DISPLAYWIDTH as i32 / 2 - 350,
+                y: Self::middle_strip_top() + 40,
+            },
+            Vector2 { x: 300, y: 120 },
+            if paused { "Resume" } else { "Pause" },
+            60.0,
+        );
+        canvas.mark_dirty(&rect);
+        self.pause_button_hitbox = Some(rect);
+    }
+
+    fn draw_clock_buttons(&mut self, canvas: &mut Canvas) {
+        self.draw_pause_button(canvas);
+        self.reset_button_hitbox = Some(draw_fixed_button(
+            canvas,
+            Point2 {
+                x: DISPLAYWIDTH as i32 / 2 + 50,
+                y: Self::middle_strip_top() + 40,
+            },
+            Vector2 { x: 300, y: 120 },
+            "Reset",
+            60.0,
+        ));
+        self.black_clock.invalidate();
+        self.white_clock.invalidate();
+    }
+
+    fn start(&mut self, time_control: TimeControl) {
+        self.clock = Some(ChessClock::new(time_control));
+        self.drawn = false;
+    }
+
+    fn reset(&mut self) {
+        self.clock = None;
+        self.drawn = false;
+    }
+
+    fn press(&mut self, player: Player) {
+        if let Some(ref mut clock) = self.clock {
+            clock.press(player, Instant::now());
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        if let Some(ref mut clock) = self.clock {
+            if clock.is_paused() {
+                clock.resume(Instant::now());
+            } else {
+                clock.pause(Instant::now());
+            }
+            self.pause_button_redraw = true;
+        }
+    }
+}
+
+impl Scene for ClockScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            canvas.clear();
+            if self.clock.is_some() {
+                self.draw_clock_buttons(canvas);
+            } else {
+                self.draw_presets(canvas);
+            }
+            canvas.update_full();
+        }
+
+        if self.pause_button_redraw {
+            self.pause_button_redraw = false;
+            self.draw_pause_button(canvas);
+        }
+
+        if let Some(ref clock) = self.clock {
+            let snapshot = clock.snapshot(Instant::now());
+            self.black_clock.draw(
+                canvas,
+                snapshot.black,
+                snapshot.running == Some(Player::Black),
+                snapshot.delay,
+            );
+            self.white_clock.draw(
+                canvas,
+                snapshot.white,
+                snapshot.running == Some(Player::White),
+                snapshot.delay,
+            );
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::MultitouchEvent { event } => match event {
+                // Pressing the clock has to react right away
+                MultitouchEvent::Press { finger } if self.clock.is_some() => {
+                    if Canvas::is_hitting(finger.pos, self.black_clock.area()) {
+                        self.press(Player::Black);
+                    } else if Canvas::is_hitting(finger.pos, self.white_clock.area()) {
+                        self.press(Player::White);
+                    }
+                }
+                MultitouchEvent::Release { finger, .. } => {
+                    if self.clock.is_some() {
+                        if self.pause_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.pause_button_hitbox.unwrap())
+                        {
+                            self.toggle_pause();
+                        } else if self.reset_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.reset_button_hitbox.unwrap())
+                        {
+                            self.reset();
+                        }
+                    } else if self.back_button_hitbox.is_some()
+                        && Canvas::is_hitting(finger.pos, self.back_button_hitbox.unwrap())
+                    {
+                        self.back_button_pressed = true;
+                    } else if let Some(index) = self
+                        .preset_hitboxes
+                        .iter()
+                        .position(|hitbox| Canvas::is_hitting(finger.pos, *hitbox))
+                    {
+                        self.start(PRESETS[index]);
+                    }
+                }
+                _ => {}
+            },
+            InputEvent::GPIO { event } => {
+                if let GPIOEvent::Press { button } = event {
+                    match button {
+                        PhysicalButton::LEFT => self.press(Player::White),
+                        PhysicalButton::RIGHT => self.press(Player::Black),
+                        PhysicalButton::MIDDLE => {
+                            if self.clock.is_some() {
+                                self.toggle_pause();
+                            } else {
+                                self.back_button_pressed = true;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::canvas::*;
+use chessmarkable::clock::format_time;
+use std::time::Duration;
+
+const ACTIVE_BORDER: u32 = 12;
+/// Of the delay countdown compared to the time
+const DELAY_FONT_SCALE: f32 = 0.6;
+
+/// The remaining time of one player. It's only redrawn when what is
+/// shown changes (at most every tenth of a second).
+pub struct ClockWidget {
+    area: mxcfb_rect,
+    font_size: f32,
+    /// Text, whether the clock was running and the delay countdown
+    /// when it was last drawn
+    shown: Option<(String, bool, Option<String>)>,
+}
+
+impl ClockWidget {
+    pub fn new(area: mxcfb_rect, font_size: f32) -> Self {
+        Self {
+            area,
+            font_size,
+            shown: None,
+        }
+    }
+
+    pub fn area(&self) -> mxcfb_rect {
+        self.area
+    }
+
+    /// Draw it again on the next `draw()` (e.g. after the screen was cleared)
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// A running clock gets a thick border. A clock without time left
+    /// shows "Flag" instead of the time. While the time doesn't run yet
+    /// because of a delay, the seconds left of it are counted down in
+    /// the top left corner (e.g. "d3").
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        remaining: Duration,
+        running: bool,
+        delay: Duration,
+    ) {
+        let text = if remaining == Duration::default() {
+            "Flag".to_owned()
+        } else {
+            format_time(remaining)
+        };
+        let delay_text = if running && delay > Duration::default() {
+            // Rounded up like the time
+            Some(format!("d{}", (delay.as_millis() + 999) / 1000))
+        } else {
+            None
+        };
+        let shown = (text, running, delay_text);
+        if self.shown.as_ref() == Some(&shown) {
+            return;
+        }
+        canvas.fill_rect(
+            Point2 {
+                x: Some(self.area.left as i32),
+                y: Some(self.area.top as i32),
+            },
+            Vector2 {
+                x: self.area.width,
+                y: self.area.height,
+            },
+            color::WHITE,
+        );
+        canvas.draw_rect(
+            Point2 {
+                x: Some(self.area.left as i32),
+                y: Some(self.area.top as i32),
+            },
+            Vector2 {
+                x: self.area.width,
+                y: self.area.height,
+            },
+            if running { ACTIVE_BORDER } else { 2 },
+        );
+        canvas.draw_text_centered(self.area, &shown.0, self.font_size);
+        if let Some(ref delay_text) = shown.2 {
+            let delay_font_size = self.font_size * DELAY_FONT_SCALE;
+            canvas.draw_text(
+                Point2 {
+                    x: Some((self.area.left + ACTIVE_BORDER) as i32 + 5),
+                    y: Some((self.area.top + ACTIVE_BORDER) as i32 + delay_font_size as i32),
+                },
+                delay_text,
+                delay_font_size,
+            );
+        }
+        // Black and white only, so the fast waveform is good enough and
+        // the frequent updates don't cause full refreshes
+        canvas.update_partial_fast(&self.area);
+        self.shown = Some(shown);
+    }
+}
@@ -0,0 +1,325 @@
+use super::dialog::Dialog;
+use super::Scene;
+use crate::canvas::*;
+use crate::correspondence::{self, CorrespondenceGame, DAYS_PER_MOVE_CHOICES};
+use crate::scene::GameMode;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+
+const FIRST_ROW_TOP: i32 = 280;
+const ROW_HEIGHT: u32 = 120;
+const ROW_GAP: i32 = 20;
+/// More games don't fit above the buttons to start new ones
+const MAX_ROWS: usize = 9;
+const DELETE_BUTTON_WIDTH: u32 = 200;
+
+/// Opponents of new correspondence games
+const NEW_GAME_MODES: &[(GameMode, &str)] = &[
+    (GameMode::PvP, "Player"),
+    (GameMode::EasyBot, "Easy Bot"),
+    (GameMode::NormalBot, "Normal Bot"),
+    (GameMode::HardBot, "Hard Bot"),
+];
+
+/// All correspondence games with whose turn it is. Games awaiting a
+/// move at this tablet are listed first.
+pub struct CorrespondenceScene {
+    drawn: bool,
+    games: Vec<CorrespondenceGame>,
+    error: Option<String>,
+    /// Game and the hitboxes to open and to delete it
+    game_hitboxes: Vec<(usize, mxcfb_rect, mxcfb_rect)>,
+    new_game_hitboxes: Vec<(GameMode, mxcfb_rect)>,
+    days_per_move: u16,
+    days_per_move_button_hitbox: Option<mxcfb_rect>,
+    /// Waiting for the deletion to be confirmed
+    deleting: Option<u64>,
+    dialog: Option<Dialog>,
+    dialog_drawn: bool,
+    /// Id and mode of the game to play
+    pub opened_game: Option<(u64, GameMode)>,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl CorrespondenceScene {
+    pub fn new() -> Self {
+        let mut scene = Self {
+            drawn: false,
+            games: vec![],
+            error: None,
+            game_hitboxes: vec![],
+            new_game_hitboxes: vec![],
+            days_per_move: 3,
+            days_per_move_button_hitbox: None,
+            deleting: None,
+            dialog: None,
+            dialog_drawn: false,
+            opened_game: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        };
+        scene.reload();
+        scene
+    }
+
+    fn reload(&mut self) {
+        match correspondence::read_all() {
+            Ok(games) => self.games = games,
+            Err(err) => {
+                error!("Failed to read correspondence games: {:?}", err);
+                self.error = Some(format!("Failed to read the games: {}", err));
+            }
+        }
+        self.drawn = false;
+    }
+
+    fn draw_games(&mut self, canvas: &mut Canvas) {
+        self.game_hitboxes.clear();
+        if self.games.is_empty() {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(FIRST_ROW_TOP + 100),
+                },
+                "No correspondence games yet",
+                50.0,
+            );
+        }
+        for (index, game) in self.games.iter().take(MAX_ROWS).enumerate() {
+            let top = FIRST_ROW_TOP + index as i32 * (ROW_HEIGHT as i32 + ROW_GAP);
+            let row = canvas.draw_rect(
+                Point2 {
+                    x: Some(40),
+                    y: Some(top),
+                },
+                Vector2 {
+                    x: DISPLAYWIDTH as u32 - 80 - DELETE_BUTTON_WIDTH - 20,
+                    y: ROW_HEIGHT,
+                },
+                if game.awaits_local_move() { 6 } else { 2 },
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: Some(row.left as i32 + 25),
+                    y: Some(top + 50),
+                },
+                &format!("{}, move {}", game.game_mode.label(), game.move_number()),
+                40.0,
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: Some(row.left as i32 + 25),
+                    y: Some(top + 100),
+                },
+                &game.status(),
+                35.0,
+            );
+            let delete = canvas.draw_rect(
+                Point2 {
+                    x: Some((row.left + row.width + 20) as i32),
+                    y: Some(top),
+                },
+                Vector2 {
+                    x: DELETE_BUTTON_WIDTH,
+                    y: ROW_HEIGHT,
+                },
+                2,
+            );
+            canvas.draw_text_centered(delete, "Delete", 40.0);
+            self.game_hitboxes.push((index, row, delete));
+        }
+        if self.games.len() > MAX_ROWS {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(FIRST_ROW_TOP + MAX_ROWS as i32 * (ROW_HEIGHT as i32 + ROW_GAP) + 20),
+                },
+                &format!(
+                    "and {} more (delete finished games)",
+                    self.games.len() - MAX_ROWS
+                ),
+                35.0,
+            );
+        }
+    }
+
+    fn draw_new_game_buttons(&mut self, canvas: &mut Canvas) {
+        canvas.draw_text(
+            Point2 {
+                x: Some(40),
+                y: Some(1660),
+            },
+            "New game against:",
+            45.0,
+        );
+        self.days_per_move_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 420),
+                y: Some(1660),
+            },
+            &format!("{} day(s) per move", self.days_per_move),
+            40.0,
+            10,
+            20,
+        ));
+        self.new_game_hitboxes.clear();
+        let mut left = 40;
+        for (game_mode, label) in NEW_GAME_MODES {
+            let hitbox = canvas.draw_button(
+                Point2 {
+                    x: Some(left + 20),
+                    y: Some(1760),
+                },
+                label,
+                50.0,
+                15,
+                20,
+            );
+            left = (hitbox.left + hitbox.width) as i32 + 40;
+            self.new_game_hitboxes.push((*game_mode, hitbox));
+        }
+    }
+
+    fn next_days_per_move(&mut self) {
+        let index = DAYS_PER_MOVE_CHOICES
+            .iter()
+            .position(|days| *days == self.days_per_move)
+            .map_or(0, |index| (index + 1) % DAYS_PER_MOVE_CHOICES.len());
+        self.days_per_move = DAYS_PER_MOVE_CHOICES[index];
+        self.drawn = false;
+    }
+
+    fn start_game(&mut self, game_mode: GameMode) {
+        let game = CorrespondenceGame::new(game_mode, self.days_per_move);
+        match correspondence::write(&game) {
+            Ok(()) => self.opened_game = Some((game.id, game_mode)),
+            Err(err) => {
+                error!("Failed to write correspondence game: {:?}", err);
+                self.error = Some(format!("Failed to start the game: {}", err));
+                self.drawn = false;
+            }
+        }
+    }
+
+    fn on_dialog_choice(&mut self, choice: usize) {
+        self.dialog = None;
+        if let Some(id) = self.deleting.take() {
+            if choice == 1 {
+                if let Err(err) = correspondence::delete(id) {
+                    error!("Failed to delete correspondence game {}: {:?}", id, err);
+                    self.error = Some(format!("Failed to delete the game: {}", err));
+                }
+            }
+        }
+        self.reload();
+    }
+}
+
+impl Scene for CorrespondenceScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if self.drawn {
+            if let Some(ref mut dialog) = self.dialog {
+                if !self.dialog_drawn {
+                    let rect = dialog.draw(canvas);
+                    canvas.mark_dirty(&rect);
+                    self.dialog_drawn = true;
+                }
+            }
+            return;
+        }
+        self.drawn = true;
+        self.dialog_drawn = false;
+
+        canvas.clear();
+        canvas.draw_styled_text(
+            Point2 {
+                x: None,
+                y: Some(150),
+            },
+            "Correspondence",
+            100.0,
+            FontStyle::Bold,
+        );
+        if let Some(ref error) = self.error {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(230),
+                },
+                error,
+                35.0,
+            );
+        }
+        self.draw_games(canvas);
+        self.draw_new_game_buttons(canvas);
+        self.back_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1870),
+            },
+            "Main Menu",
+            50.0,
+            10,
+            30,
+        ));
+        canvas.update_full();
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        if let Some(ref mut dialog) = self.dialog {
+            if let Some(choice) = dialog.on_input(event) {
+                self.on_dialog_choice(choice);
+            }
+            return;
+        }
+        match event {
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => self.back_button_pressed = true,
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                } else if self.days_per_move_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.days_per_move_button_hitbox.unwrap())
+                {
+                    self.next_days_per_move();
+                } else if let Some(game_mode) = self
+                    .new_game_hitboxes
+                    .iter()
+                    .find(|(_, hitbox)| Canvas::is_hitting(position, *hitbox))
+                    .map(|(game_mode, _)| *game_mode)
+                {
+                    self.start_game(game_mode);
+                } else {
+                    for (index, row, delete) in self.game_hitboxes.iter() {
+                        let game = &self.games[*index];
+                        if Canvas::is_hitting(position, *row) {
+                            self.opened_game = Some((game.id, game.game_mode));
+                        } else if Canvas::is_hitting(position, *delete) {
+                            self.deleting = Some(game.id);
+                            self.dialog = Some(Dialog::new(
+                                "Delete this game? This can't be undone.",
+                                &["Cancel", "Delete"],
+                            ));
+                            self.dialog_drawn = false;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
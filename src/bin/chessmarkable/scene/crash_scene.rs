@@ -0,0 +1,183 @@
+use super::Scene;
+use crate::autosave::Autosave;
+use crate::canvas::*;
+use crate::crash::{self, CrashReport};
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+
+const STATUS_TOP: i32 = 1500;
+const STATUS_HEIGHT: u32 = 120;
+
+/// Shown at the start after the last run crashed. The crash report is
+/// removed when leaving it, so it's only offered once.
+pub struct CrashScene {
+    drawn: bool,
+    redraw_status: bool,
+    report: CrashReport,
+    autosave: Option<Autosave>,
+    status: Option<String>,
+
+    restore_button_hitbox: Option<mxcfb_rect>,
+    export_button_hitbox: Option<mxcfb_rect>,
+    dismiss_button_hitbox: Option<mxcfb_rect>,
+    pub restore_button_pressed: bool,
+    pub dismiss_button_pressed: bool,
+}
+
+impl CrashScene {
+    pub fn new(report: CrashReport) -> Self {
+        let autosave = crate::autosave::read().unwrap_or_else(|err| {
+            error!("Failed to read autosave file: {:?}", err);
+            None
+        });
+        Self {
+            drawn: false,
+            redraw_status: false,
+            report,
+            autosave,
+            status: None,
+            restore_button_hitbox: None,
+            export_button_hitbox: None,
+            dismiss_button_hitbox: None,
+            restore_button_pressed: false,
+            dismiss_button_pressed: false,
+        }
+    }
+
+    fn leave(&mut self) {
+        if let Err(err) = crash::clear() {
+            error!("Failed to remove crash report: {:?}", err);
+        }
+    }
+
+    fn draw_status(&mut self, canvas: &mut Canvas) {
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(STATUS_TOP),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: STATUS_HEIGHT,
+            },
+            color::WHITE,
+        );
+        if let Some(ref status) = self.status {
+            canvas.draw_wrapped_text(
+                None,
+                STATUS_TOP + 45,
+                DISPLAYWIDTH as u32 - 100,
+                2,
+                status,
+                40.0,
+                FontStyle::Regular,
+            );
+        }
+        canvas.mark_dirty(&rect);
+    }
+}
+
+impl Scene for CrashScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            canvas.clear();
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(250),
+                },
+                "chessMarkable crashed",
+                100.0,
+            );
+            canvas.draw_wrapped_text(
+                None,
+                400,
+                DISPLAYWIDTH as u32 - 200,
+                5,
+                &self.report.message,
+                45.0,
+                FontStyle::Regular,
+            );
+            if let Some(ref location) = self.report.location {
+                canvas.draw_text(
+                    Point2 {
+                        x: None,
+                        y: Some(700),
+                    },
+                    location,
+                    35.0,
+                );
+            }
+            self.restore_button_hitbox = self.autosave.as_ref().map(|autosave| {
+                let label = autosave.label().replacen("Continue", "Restore", 1);
+                canvas.draw_button(
+                    Point2 {
+                        x: None,
+                        y: Some(950),
+                    },
+                    &label,
+                    55.0,
+                    20,
+                    40,
+                )
+            });
+            self.export_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: None,
+                    y: Some(1150),
+                },
+                "Export crash report",
+                55.0,
+                20,
+                40,
+            ));
+            self.dismiss_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: None,
+                    y: Some(1750),
+                },
+                "Main Menu",
+                75.0,
+                25,
+                50,
+            ));
+            canvas.update_full();
+        }
+
+        if self.redraw_status {
+            self.redraw_status = false;
+            self.draw_status(canvas);
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        if let InputEvent::MultitouchEvent {
+            event: MultitouchEvent::Release { finger, .. },
+        } = event
+        {
+            let position = finger.pos;
+            if self.restore_button_hitbox.is_some()
+                && Canvas::is_hitting(position, self.restore_button_hitbox.unwrap())
+            {
+                self.leave();
+                self.restore_button_pressed = true;
+            } else if self.export_button_hitbox.is_some()
+                && Canvas::is_hitting(position, self.export_button_hitbox.unwrap())
+            {
+                self.status = Some(match crash::export(&self.report) {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(err) => {
+                        error!("Failed to export crash report: {:?}", err);
+                        format!("Failed to export: {}", err)
+                    }
+                });
+                self.redraw_status = true;
+            } else if self.dismiss_button_hitbox.is_some()
+                && Canvas::is_hitting(position, self.dismiss_button_hitbox.unwrap())
+            {
+                self.leave();
+                self.dismiss_button_pressed = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use super::Scene;
+use crate::canvas::*;
+use crate::scene::game_scene::ALL_PIECES;
+use crate::scene::piece_images::get_piece_img;
+use anyhow::{Context, Result};
+use libremarkable::image::GenericImageView;
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+use pleco::bot_prelude::AlphaBetaSearcher;
+use pleco::tools::Searcher;
+use pleco::Board;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// How long to wait for the user to tap the screen
+const INPUT_TEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Refreshing a small region should never take longer than this
+const MAX_REFRESH_DURATION: Duration = Duration::from_millis(1500);
+/// Mate in one (Ra8#) that even a shallow search has to find
+const ENGINE_TEST_FEN: &str = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1";
+const ENGINE_TEST_EXPECTED_MOVE: &str = "a1a8";
+const ENGINE_TEST_DEPTH: u16 = 3;
+const PIECE_TEST_SIZE: u32 = 100;
+
+const FIRST_RESULT_Y_POS: i32 = 400;
+const RESULT_HEIGHT: i32 = 200;
+
+enum TestStatus {
+    Pending(String),
+    Passed(String),
+    Failed(String),
+}
+
+impl TestStatus {
+    fn from_result(result: Result<String>) -> Self {
+        match result {
+            Ok(details) => TestStatus::Passed(details),
+            Err(err) => TestStatus::Failed(format!("{:#}", err)),
+        }
+    }
+}
+
+pub struct DiagnosticsScene {
+    drawn: bool,
+    redraw_results: bool,
+    /// Name and status of each self-test
+    results: Vec<(&'static str, TestStatus)>,
+    input_test_started: Option<SystemTime>,
+
+    /// Hidden button to the logs
+    title_hitbox: Option<mxcfb_rect>,
+    rerun_button_hitbox: Option<mxcfb_rect>,
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+    pub logs_button_pressed: bool,
+}
+
+impl DiagnosticsScene {
+    pub fn new() -> Self {
+        Self {
+            drawn: false,
+            redraw_results: false,
+            results: vec![],
+            input_test_started: None,
+            title_hitbox: None,
+            rerun_button_hitbox: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+            logs_button_pressed: false,
+        }
+    }
+
+    fn run_tests(&mut self, canvas: &mut Canvas) {
+        self.results = vec![
+            ("Display", TestStatus::from_result(test_framebuffer(canvas))),
+            (
+                "Touch input",
+                TestStatus::Pending("Tap anywhere on the screen...".to_owned()),
+            ),
+            ("Storage", TestStatus::from_result(test_storage())),
+            ("Engine", TestStatus::from_result(test_engine())),
+            ("Piece images", TestStatus::from_result(test_piece_images())),
+        ];
+        for (name, status) in &self.results {
+            match status {
+                TestStatus::Passed(details) => info!("Self-test \"{}\" passed: {}", name, details),
+                TestStatus::Failed(details) => warn!("Self-test \"{}\" failed: {}", name, details),
+                TestStatus::Pending(_) => {}
+            }
+        }
+        self.input_test_started = Some(SystemTime::now());
+        self.redraw_results = true;
+    }
+
+    fn set_input_test_status(&mut self, status: TestStatus) {
+        if let Some((_, ref mut input_status)) = self.results.get_mut(1) {
+            if let TestStatus::Pending(_) = input_status {
+                *input_status = status;
+                self.input_test_started = None;
+                self.redraw_results = true;
+            }
+        }
+    }
+
+    fn draw_results(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(FIRST_RESULT_Y_POS - 100),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: (RESULT_HEIGHT * self.results.len() as i32) as u32,
+            },
+            color::WHITE,
+        );
+        for (i, (name, status)) in self.results.iter().enumerate() {
+            let y = FIRST_RESULT_Y_POS + RESULT_HEIGHT * i as i32;
+            let (label, details) = match status {
+                TestStatus::Pending(details) => ("...", details),
+                TestStatus::Passed(details) => ("PASS", details),
+                TestStatus::Failed(details) => ("FAIL", details),
+            };
+            canvas.draw_text(
+                Point2 {
+                    x: Some(50),
+                    y: Some(y),
+                },
+                &format!("{}: {}", label, name),
+                60.0,
+            );
+            canvas.draw_wrapped_text(
+                Some(50),
+                y + 60,
+                DISPLAYWIDTH as u32 - 100,
+                2,
+                details,
+                40.0,
+                FontStyle::Regular,
+            );
+        }
+        rect
+    }
+}
+
+impl Scene for DiagnosticsScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            canvas.clear();
+            self.title_hitbox = Some(canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(200),
+                },
+                "Diagnostics",
+                125.0,
+            ));
+            self.back_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: Some(150),
+                    y: Some(1750),
+                },
+                "Main Menu",
+                75.0,
+                25,
+                50,
+            ));
+            self.rerun_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: Some(DISPLAYWIDTH as i32 - 450),
+                    y: Some(1750),
+                },
+                "Run again",
+                75.0,
+                25,
+                50,
+            ));
+            canvas.update_full();
+            self.run_tests(canvas);
+        }
+
+        if let Some(started) = self.input_test_started {
+            if started.elapsed().unwrap_or_default() > INPUT_TEST_TIMEOUT {
+                self.set_input_test_status(TestStatus::Failed(
+                    "No touch or button event was received".to_owned(),
+                ));
+            }
+        }
+
+        if self.redraw_results {
+            let rect = self.draw_results(canvas);
+            canvas.mark_dirty(&rect);
+            self.redraw_results = false;
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::MultitouchEvent { event } => {
+                if let MultitouchEvent::Press { finger } = event {
+                    self.set_input_test_status(TestStatus::Passed(format!(
+                        "Touch received at ({}, {})",
+                        finger.pos.x, finger.pos.y
+                    )));
+                }
+                if let MultitouchEvent::Release { finger, .. } = event {
+                    if self.back_button_hitbox.is_some()
+                        && Canvas::is_hitting(finger.pos, self.back_button_hitbox.unwrap())
+                    {
+                        self.back_button_pressed = true;
+                    } else if self.rerun_button_hitbox.is_some()
+                        && Canvas::is_hitting(finger.pos, self.rerun_button_hitbox.unwrap())
+                    {
+                        self.drawn = false;
+                    } else if self.title_hitbox.is_some()
+                        && Canvas::is_hitting(finger.pos, self.title_hitbox.unwrap())
+                    {
+                        self.logs_button_pressed = true;
+                    }
+                }
+            }
+            InputEvent::GPIO { .. } => {
+                self.set_input_test_status(TestStatus::Passed("Button press received".to_owned()))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Draw and refresh a small region while measuring the time
+fn test_framebuffer(canvas: &mut Canvas) -> Result<String> {
+    let started = SystemTime::now();
+    let pos = Point2 {
+        x: Some(DISPLAYWIDTH as i32 - 150),
+        y: Some(50),
+    };
+    let size = Vector2 { x: 100, y: 100 };
+    let rect = canvas.fill_rect(pos, size, color::BLACK);
+    let marker = canvas.update_partial(&rect);
+    canvas.wait_for_update(marker);
+    canvas.fill_rect(pos, size, color::WHITE);
+    let marker = canvas.update_partial(&rect);
+    canvas.wait_for_update(marker);
+
+    let elapsed = started.elapsed().unwrap_or_default() / 2;
+    if elapsed > MAX_REFRESH_DURATION {
+        bail!("A partial refresh took {} ms", elapsed.as_millis());
+    }
+    Ok(format!("A partial refresh took {} ms", elapsed.as_millis()))
+}
+
+/// Every directory that chessmarkable writes to has to be writable
+fn test_storage() -> Result<String> {
+    let savestates_file = crate::config::savestates_file();
+    let settings_file = crate::config::settings_file();
+    let pgn_location = crate::config::pgn_location();
+    let directories = [
+        savestates_file.parent(),
+        settings_file.parent(),
+        Some(pgn_location.as_path()),
+    ];
+    for directory in directories.iter().flatten() {
+        test_directory_writable(directory)
+            .with_context(|| format!("{:?} is not writable", directory))?;
+    }
+    Ok("Config and PGN directories are writable".to_owned())
+}
+
+fn test_directory_writable(directory: &Path) -> Result<()> {
+    std::fs::create_dir_all(directory).context("Create directory")?;
+    let test_file = directory.join(".chessmarkable-selftest");
+    std::fs::write(&test_file, b"selftest").context("Write file")?;
+    let content = std::fs::read(&test_file).context("Read file")?;
+    std::fs::remove_file(&test_file).context("Remove file")?;
+    if content != b"selftest" {
+        bail!("Read back different content than written");
+    }
+    Ok(())
+}
+
+fn test_engine() -> Result<String> {
+    let board = Board::from_fen(ENGINE_TEST_FEN)
+        .map_err(|e| anyhow!("Failed to parse test position: {:?}", e))?;
+    let started = SystemTime::now();
+    let best_move = AlphaBetaSearcher::best_move(board, ENGINE_TEST_DEPTH).stringify();
+    let elapsed = started.elapsed().unwrap_or_default();
+    if best_move != ENGINE_TEST_EXPECTED_MOVE {
+        bail!(
+            "Expected {} but the engine played {}",
+            ENGINE_TEST_EXPECTED_MOVE,
+            best_move
+        );
+    }
+    Ok(format!(
+        "Found the mate in one in {} ms",
+        elapsed.as_millis()
+    ))
+}
+
+fn test_piece_images() -> Result<String> {
+    // The images are decoded lazily and panic if broken
+    let sizes = std::panic::catch_unwind(|| {
+        ALL_PIECES
+            .iter()
+            .map(|piece| get_piece_img(piece, PIECE_TEST_SIZE).dimensions())
+            .collect::<Vec<_>>()
+    })
+    .map_err(|_| anyhow!("An embedded piece image could not be decoded"))?;
+    if let Some(_) = sizes
+        .iter()
+        .find(|(width, height)| *width == 0 || *height == 0)
+    {
+        bail!("An embedded piece image is empty");
+    }
+    Ok(format!("All {} piece images are fine", sizes.len()))
+}
@@ -0,0 +1,76 @@
+use crate::canvas::*;
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+
+const DIALOG_WIDTH: u32 = DISPLAYWIDTH as u32 - 200;
+const DIALOG_HEIGHT: u32 = 420;
+const OPTION_HEIGHT: u32 = 110;
+
+/// Modal box with a message and a row of options (e.g. to confirm an action).
+/// Scenes forward input to it while it's open.
+pub struct Dialog {
+    message: String,
+    options: Vec<&'static str>,
+    option_hitboxes: Vec<mxcfb_rect>,
+}
+
+impl Dialog {
+    pub fn new(message: &str, options: &[&'static str]) -> Self {
+        Self {
+            message: message.to_owned(),
+            options: options.to_vec(),
+            option_hitboxes: vec![],
+        }
+    }
+
+    /// Draw the dialog centered on the screen. The caller has to refresh the returned region.
+    pub fn draw(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let size = Vector2 {
+            x: DIALOG_WIDTH,
+            y: DIALOG_HEIGHT,
+        };
+        let rect = canvas.fill_rect(Point2 { x: None, y: None }, size, color::WHITE);
+        canvas.draw_rect(Point2 { x: None, y: None }, size, 5);
+        canvas.draw_wrapped_text(
+            None,
+            rect.top as i32 + 110,
+            rect.width - 60,
+            3,
+            &self.message,
+            50.0,
+            FontStyle::Regular,
+        );
+
+        self.option_hitboxes.clear();
+        let option_width = DIALOG_WIDTH / self.options.len() as u32;
+        for (i, option) in self.options.iter().enumerate() {
+            let option_rect = canvas.draw_rect(
+                Point2 {
+                    x: Some((rect.left + option_width * i as u32 + 20) as i32),
+                    y: Some((rect.top + DIALOG_HEIGHT - OPTION_HEIGHT - 30) as i32),
+                },
+                Vector2 {
+                    x: option_width - 40,
+                    y: OPTION_HEIGHT,
+                },
+                3,
+            );
+            canvas.draw_text_centered(option_rect, option, 50.0);
+            self.option_hitboxes.push(option_rect);
+        }
+        rect
+    }
+
+    /// Index of the chosen option
+    pub fn on_input(&mut self, event: InputEvent) -> Option<usize> {
+        if let InputEvent::MultitouchEvent {
+            event: MultitouchEvent::Release { finger, .. },
+        } = event
+        {
+            return self
+                .option_hitboxes
+                .iter()
+                .position(|hitbox| Canvas::is_hitting(finger.pos, *hitbox));
+        }
+        None
+    }
+}
@@ -0,0 +1,299 @@
+use super::mini_board::MiniBoard;
+use super::Scene;
+use crate::canvas::*;
+use crate::scene::game_scene::{board_hitboxes, board_rect};
+use crate::scene::GameMode;
+use chessmarkable::drill::{Drill, DrillFeedback};
+use chessmarkable::Square;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+
+const SQUARE_SIZE: u32 = 175;
+const SELECTED_FRAME: u32 = 8;
+const STATUS_TOP: u32 = 1640;
+const STATUS_HEIGHT: u32 = 95;
+const STATUS_FONT_SIZE: f32 = 40.0;
+
+/// Converting a won endgame against the tablebase. Every move of the
+/// player is answered with the best defense and judged right away.
+pub struct DrillScene {
+    drawn: bool,
+    started: bool,
+    redraw_board: bool,
+    name: &'static str,
+    fen: &'static str,
+    /// Created on the second frame, building the tables takes a moment
+    drill: Option<Drill>,
+    board: MiniBoard,
+    piece_hitboxes: Vec<Vec<mxcfb_rect>>,
+    selected_square: Option<Square>,
+    status: String,
+    /// The last move lost the win or was too slow and has to be undone
+    failed: bool,
+
+    /// Passed on to the endgame list when going back
+    pub game_mode: GameMode,
+    pub pvp_piece_rotation_enabled: bool,
+
+    undo_button_hitbox: Option<mxcfb_rect>,
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl DrillScene {
+    pub fn new(
+        name: &'static str,
+        fen: &'static str,
+        game_mode: GameMode,
+        pvp_piece_rotation_enabled: bool,
+    ) -> Self {
+        Self {
+            drawn: false,
+            started: false,
+            redraw_board: false,
+            name,
+            fen,
+            drill: None,
+            board: MiniBoard::new(SQUARE_SIZE),
+            piece_hitboxes: board_hitboxes(board_rect(SQUARE_SIZE), false),
+            selected_square: None,
+            status: "Preparing the tablebase...".to_owned(),
+            failed: false,
+            game_mode,
+            pvp_piece_rotation_enabled,
+            undo_button_hitbox: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    fn start(&mut self) {
+        match Drill::new(self.fen) {
+            Ok(drill) => {
+                self.status = format!(
+                    "Mate in {} with best play, {} moves until the 50-move rule",
+                    drill.mate_in(),
+                    drill.moves_left()
+                );
+                self.drill = Some(drill);
+            }
+            Err(err) => {
+                error!("Failed to start the drill: {:?}", err);
+                self.status = format!("{}", err);
+            }
+        }
+        self.redraw_board = true;
+    }
+
+    fn on_feedback(&mut self, feedback: DrillFeedback) {
+        self.failed = feedback.is_failed();
+        self.status = if self.failed {
+            format!("{}. Undo and try again.", feedback.description())
+        } else {
+            feedback.description()
+        };
+    }
+
+    fn on_square(&mut self, square: Square) {
+        let drill = match self.drill {
+            Some(ref mut drill) => drill,
+            None => return,
+        };
+        if self.failed || drill.game().outcome().is_some() {
+            return;
+        }
+        let source = match self.selected_square.take() {
+            Some(source) if source != square => source,
+            Some(_) => {
+                self.redraw_board = true;
+                return;
+            }
+            None => {
+                if !drill.game().possible_moves_from(square).is_empty() {
+                    self.selected_square = Some(square);
+                    self.redraw_board = true;
+                }
+                return;
+            }
+        };
+        match drill.play(source, square, None) {
+            Ok(feedback) => self.on_feedback(feedback),
+            Err(err) => {
+                debug!("Move not played: {:?}", err);
+                if !drill.game().possible_moves_from(square).is_empty() {
+                    self.selected_square = Some(square);
+                }
+            }
+        }
+        self.redraw_board = true;
+    }
+
+    fn undo(&mut self) {
+        if let Some(ref mut drill) = self.drill {
+            if let Err(err) = drill.undo() {
+                warn!("Undo failed: {:?}", err);
+                return;
+            }
+            self.failed = false;
+            self.selected_square = None;
+            self.status = format!("Taken back, mate in {}", drill.mate_in());
+            self.redraw_board = true;
+        }
+    }
+
+    fn draw_board(&mut self, canvas: &mut Canvas) {
+        let board = board_rect(SQUARE_SIZE);
+        let fen = match self.drill {
+            Some(ref drill) => drill.game().fen(),
+            None => self.fen.to_owned(),
+        };
+        let rect = self.board.draw(
+            canvas,
+            Point2 {
+                x: board.left as i32,
+                y: board.top as i32,
+            },
+            &fen,
+        );
+        if let Some(square) = self.selected_square {
+            let bounds = self.piece_hitboxes[square.x() as usize][square.y() as usize];
+            canvas.draw_rect(
+                Point2 {
+                    x: Some(bounds.left as i32),
+                    y: Some(bounds.top as i32),
+                },
+                bounds.size().cast().unwrap(),
+                SELECTED_FRAME,
+            );
+        }
+        canvas.mark_dirty(&rect);
+    }
+
+    fn draw_status(&mut self, canvas: &mut Canvas) {
+        let area = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(STATUS_TOP as i32),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: STATUS_HEIGHT,
+            },
+            color::WHITE,
+        );
+        canvas.draw_wrapped_text(
+            None,
+            (STATUS_TOP + 40) as i32,
+            DISPLAYWIDTH as u32 - 100,
+            2,
+            &self.status,
+            STATUS_FONT_SIZE,
+            FontStyle::Regular,
+        );
+        canvas.mark_dirty(&area);
+    }
+}
+
+impl Scene for DrillScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            canvas.clear();
+            canvas.draw_styled_text(
+                Point2 {
+                    x: None,
+                    y: Some(120),
+                },
+                &format!("Drill: {}", self.name),
+                70.0,
+                FontStyle::Bold,
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(190),
+                },
+                "Win against perfect defense",
+                35.0,
+            );
+            self.undo_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: Some(40),
+                    y: Some(1830),
+                },
+                "Undo",
+                50.0,
+                10,
+                20,
+            ));
+            self.back_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: None,
+                    y: Some(1830),
+                },
+                "Back",
+                75.0,
+                15,
+                40,
+            ));
+            self.draw_board(canvas);
+            self.draw_status(canvas);
+            canvas.update_full();
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            self.start();
+        }
+        if self.redraw_board {
+            self.redraw_board = false;
+            self.draw_board(canvas);
+            self.draw_status(canvas);
+            canvas.flush_dirty();
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => self.back_button_pressed = true,
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::LEFT,
+                    },
+            } => self.undo(),
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                } else if self.undo_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.undo_button_hitbox.unwrap())
+                {
+                    self.undo();
+                } else {
+                    for x in 0..8 {
+                        for y in 0..8 {
+                            if Canvas::is_hitting(position, self.piece_hitboxes[x][y]) {
+                                self.on_square(Square::new(x, y).unwrap());
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,510 @@
+use super::Scene;
+use crate::canvas::*;
+use crate::scene::game_scene::{
+    draw_board_border, draw_square_background, GameMode, SavestateSlot, ALL_PIECES,
+};
+use crate::scene::piece_images::get_piece_img;
+use chessmarkable::game::VariantKind;
+use chessmarkable::setup::PositionSetup;
+use chessmarkable::Square;
+use fxhash::FxHashMap;
+use libremarkable::image;
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+use pleco::Piece;
+
+const SQUARE_SIZE: u32 = 130;
+const BOARD_LEFT: u32 = (DISPLAYWIDTH as u32 - SQUARE_SIZE * 8) / 2;
+const BOARD_TOP: u32 = 150;
+const PIECE_PADDING: u32 = 13;
+/// Pieces to choose from below the board (one row per player)
+const PALETTE_TOP: u32 = BOARD_TOP + SQUARE_SIZE * 8 + 20;
+const PALETTE_PIECE_SIZE: u32 = 100;
+const PALETTE: &[&[Piece]] = &[
+    &[
+        Piece::WhiteKing,
+        Piece::WhiteQueen,
+        Piece::WhiteRook,
+        Piece::WhiteBishop,
+        Piece::WhiteKnight,
+        Piece::WhitePawn,
+    ],
+    &[
+        Piece::BlackKing,
+        Piece::BlackQueen,
+        Piece::BlackRook,
+        Piece::BlackBishop,
+        Piece::BlackKnight,
+        Piece::BlackPawn,
+    ],
+];
+/// Everything below the palette (options, validation and starting the game)
+const CONTROLS_TOP: u32 = PALETTE_TOP + SQUARE_SIZE * 2 + 10;
+
+/// Interactive FEN builder. The position can be played on
+/// any savestate slot once it's valid.
+pub struct EditorScene {
+    drawn: bool,
+    redraw_board: bool,
+    redraw_palette: bool,
+    redraw_controls: bool,
+
+    setup: PositionSetup,
+    /// Piece placed by tapping a square (`Piece::None` removes pieces)
+    selected_piece: Piece,
+    img_pieces: FxHashMap</* Piece */ char, image::DynamicImage>,
+    img_palette_pieces: FxHashMap</* Piece */ char, image::DynamicImage>,
+
+    pub game_mode: GameMode,
+    pub pvp_piece_rotation_enabled: bool,
+
+    palette_hitboxes: Vec<(Piece, mxcfb_rect)>,
+    turn_button_hitbox: Option<mxcfb_rect>,
+    en_passant_button_hitbox: Option<mxcfb_rect>,
+    /// K, Q, k and q
+    castling_button_hitboxes: Vec<mxcfb_rect>,
+    clear_button_hitbox: Option<mxcfb_rect>,
+    initial_button_hitbox: Option<mxcfb_rect>,
+    play_button_hitbox: Option<mxcfb_rect>,
+    /// Set once the position was written to this new save
+    pub start_on_slot: Option<SavestateSlot>,
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+    /// Shown instead of the validation result once
+    error: Option<String>,
+}
+
+impl EditorScene {
+    pub fn new(game_mode: GameMode, pvp_piece_rotation_enabled: bool) -> Self {
+        let mut img_pieces: FxHashMap<char, image::DynamicImage> = Default::default();
+        let mut img_palette_pieces: FxHashMap<char, image::DynamicImage> = Default::default();
+        for piece in ALL_PIECES.iter() {
+            img_pieces.insert(
+                piece.character_lossy(),
+                get_piece_img(piece, SQUARE_SIZE - PIECE_PADDING * 2),
+            );
+            img_palette_pieces.insert(
+                piece.character_lossy(),
+                get_piece_img(piece, PALETTE_PIECE_SIZE),
+            );
+        }
+
+        Self {
+            drawn: false,
+            redraw_board: false,
+            redraw_palette: false,
+            redraw_controls: false,
+            setup: PositionSetup::default(),
+            selected_piece: Piece::WhitePawn,
+            img_pieces,
+            img_palette_pieces,
+            game_mode,
+            pvp_piece_rotation_enabled,
+            palette_hitboxes: vec![],
+            turn_button_hitbox: None,
+            en_passant_button_hitbox: None,
+            castling_button_hitboxes: vec![],
+            clear_button_hitbox: None,
+            initial_button_hitbox: None,
+            play_button_hitbox: None,
+            start_on_slot: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+            error: None,
+        }
+    }
+
+    fn square_rect(x: usize, y: usize) -> mxcfb_rect {
+        mxcfb_rect {
+            left: BOARD_LEFT + SQUARE_SIZE * x as u32,
+            top: BOARD_TOP + SQUARE_SIZE * (7 - y as u32),
+            width: SQUARE_SIZE,
+            height: SQUARE_SIZE,
+        }
+    }
+
+    fn draw_board(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let board_theme = crate::SETTINGS.lock().unwrap().board_theme;
+        for x in 0..8 {
+            for y in 0..8 {
+                let bounds = Self::square_rect(x, y);
+                draw_square_background(canvas, &bounds, x % 2 == y % 2, board_theme);
+                let piece = self.setup.piece_at(Square::new(x, y).unwrap());
+                if piece != Piece::None {
+                    canvas.draw_image(
+                        Point2 {
+                            x: (bounds.left + PIECE_PADDING) as i32,
+                            y: (bounds.top + PIECE_PADDING) as i32,
+                        },
+                        self.img_pieces
+                            .get(&piece.character_lossy())
+                            .expect("Failed to find resized piece img!"),
+                        true,
+                    );
+                }
+            }
+        }
+        let board = mxcfb_rect {
+            left: BOARD_LEFT,
+            top: BOARD_TOP,
+            width: SQUARE_SIZE * 8,
+            height: SQUARE_SIZE * 8,
+        };
+        draw_board_border(canvas, board, crate::SETTINGS.lock().unwrap().board_border);
+        board
+    }
+
+    fn draw_palette(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let area = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(PALETTE_TOP as i32),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: SQUARE_SIZE * 2,
+            },
+            color::WHITE,
+        );
+        self.palette_hitboxes.clear();
+        for (row, pieces) in PALETTE.iter().enumerate() {
+            for (column, piece) in pieces.iter().enumerate() {
+                let hitbox = mxcfb_rect {
+                    left: BOARD_LEFT + SQUARE_SIZE * column as u32,
+                    top: PALETTE_TOP + SQUARE_SIZE * row as u32,
+                    width: SQUARE_SIZE,
+                    height: SQUARE_SIZE,
+                };
+                let padding = (SQUARE_SIZE - PALETTE_PIECE_SIZE) / 2;
+                canvas.draw_image(
+                    Point2 {
+                        x: (hitbox.left + padding) as i32,
+                        y: (hitbox.top + padding) as i32,
+                    },
+                    self.img_palette_pieces
+                        .get(&piece.character_lossy())
+                        .expect("Failed to find resized piece img!"),
+                    true,
+                );
+                self.palette_hitboxes.push((*piece, hitbox));
+            }
+        }
+        let eraser = canvas.draw_button(
+            Point2 {
+                x: Some((BOARD_LEFT + SQUARE_SIZE * 6 + 20) as i32),
+                y: Some((PALETTE_TOP + SQUARE_SIZE - 40) as i32),
+            },
+            "Remove",
+            50.0,
+            15,
+            25,
+        );
+        self.palette_hitboxes.push((Piece::None, eraser));
+
+        // Mark the selected piece
+        if let Some((_, hitbox)) = self
+            .palette_hitboxes
+            .iter()
+            .find(|(piece, _)| *piece == self.selected_piece)
+        {
+            canvas.draw_rect(
+                Point2 {
+                    x: Some(hitbox.left as i32),
+                    y: Some(hitbox.top as i32),
+                },
+                Vector2 {
+                    x: hitbox.width,
+                    y: hitbox.height,
+                },
+                4,
+            );
+        }
+        area
+    }
+
+    fn draw_controls(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let area = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(CONTROLS_TOP as i32),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: DISPLAYHEIGHT as u32 - CONTROLS_TOP,
+            },
+            color::WHITE,
+        );
+
+        let row_1 = CONTROLS_TOP as i32 + 60;
+        self.turn_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(50),
+                y: Some(row_1),
+            },
+            &format!("{} to move", self.setup.turn),
+            50.0,
+            10,
+            20,
+        ));
+        self.en_passant_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(550),
+                y: Some(row_1),
+            },
+            &format!(
+                "En passant: {}",
+                self.setup
+                    .en_passant
+                    .map(|square| square.to_string())
+                    .unwrap_or("-".to_owned())
+            ),
+            50.0,
+            10,
+            20,
+        ));
+
+        let row_2 = row_1 + 90;
+        canvas.draw_text(
+            Point2 {
+                x: Some(50),
+                y: Some(row_2),
+            },
+            "Castling:",
+            50.0,
+        );
+        let castling = self.setup.castling;
+        self.castling_button_hitboxes.clear();
+        for (i, (name, allowed)) in [
+            ("K", castling.white_king_side),
+            ("Q", castling.white_queen_side),
+            ("k", castling.black_king_side),
+            ("q", castling.black_queen_side),
+        ]
+        .iter()
+        .enumerate()
+        {
+            self.castling_button_hitboxes.push(canvas.draw_button(
+                Point2 {
+                    x: Some(330 + i as i32 * 260),
+                    y: Some(row_2),
+                },
+                &format!("{}: {}", name, if *allowed { "yes" } else { "no" }),
+                50.0,
+                10,
+                20,
+            ));
+        }
+
+        let row_3 = row_2 + 90;
+        self.clear_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(50),
+                y: Some(row_3),
+            },
+            "Clear",
+            50.0,
+            10,
+            20,
+        ));
+        self.initial_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(250),
+                y: Some(row_3),
+            },
+            "Initial",
+            50.0,
+            10,
+            20,
+        ));
+        self.play_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(620),
+                y: Some(row_3),
+            },
+            "Play as new save",
+            50.0,
+            10,
+            30,
+        ));
+
+        let status = match self.error.take() {
+            Some(error) => error,
+            None => match self.setup.validate() {
+                Ok(_) => "Valid position".to_owned(),
+                Err(e) => format!("{}", e),
+            },
+        };
+        canvas.draw_text(
+            Point2 {
+                x: Some(50),
+                y: Some(row_3 + 90),
+            },
+            &status,
+            35.0,
+        );
+        area
+    }
+
+    fn on_square_pressed(&mut self, square: Square) {
+        let piece = if self.setup.piece_at(square) == self.selected_piece {
+            Piece::None // Tapping the same piece again removes it
+        } else {
+            self.selected_piece
+        };
+        self.setup.set_piece(square, piece);
+        self.redraw_board = true;
+        self.redraw_controls = true;
+    }
+
+    /// Write the position into a new save and start a game on it
+    fn play(&mut self) {
+        if let Err(e) = self.setup.validate() {
+            self.error = Some(format!("Can't play this position: {}", e));
+            self.redraw_controls = true;
+            return;
+        }
+        let fen = self.setup.fen();
+        let mut savestates = crate::SAVESTATES.lock().unwrap();
+        let name = savestates.default_name();
+        let id = savestates.add(name, self.game_mode, VariantKind::Standard, fen);
+        if let Err(err) = crate::savestates::write(&savestates) {
+            error!("Failed to write savestates file: {:?}", err);
+            self.error = Some(format!("Failed to save position: {}", err));
+            self.redraw_controls = true;
+            return;
+        }
+        self.start_on_slot = Some(SavestateSlot::Saved(id));
+    }
+}
+
+impl Scene for EditorScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            canvas.clear();
+            self.back_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: Some(50),
+                    y: Some(90),
+                },
+                "Back",
+                75.0,
+                10,
+                20,
+            ));
+            canvas.draw_text(
+                Point2 {
+                    x: Some(400),
+                    y: Some(90),
+                },
+                "Set up position",
+                75.0,
+            );
+            self.draw_board(canvas);
+            self.draw_palette(canvas);
+            self.draw_controls(canvas);
+            canvas.update_full();
+            return;
+        }
+
+        if self.redraw_board {
+            let rect = self.draw_board(canvas);
+            canvas.mark_dirty(&rect);
+            self.redraw_board = false;
+        }
+        if self.redraw_palette {
+            let rect = self.draw_palette(canvas);
+            canvas.mark_dirty(&rect);
+            self.redraw_palette = false;
+        }
+        if self.redraw_controls {
+            let rect = self.draw_controls(canvas);
+            canvas.mark_dirty(&rect);
+            self.redraw_controls = false;
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        if let InputEvent::MultitouchEvent { event } = event {
+            if let MultitouchEvent::Release { finger, .. } = event {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                    return;
+                }
+
+                for x in 0..8 {
+                    for y in 0..8 {
+                        if Canvas::is_hitting(position, Self::square_rect(x, y)) {
+                            self.on_square_pressed(Square::new(x, y).unwrap());
+                            return;
+                        }
+                    }
+                }
+
+                if let Some(piece) = self
+                    .palette_hitboxes
+                    .iter()
+                    .find(|(_, hitbox)| Canvas::is_hitting(position, *hitbox))
+                    .map(|(piece, _)| *piece)
+                {
+                    self.selected_piece = piece;
+                    self.redraw_palette = true;
+                } else if self.turn_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.turn_button_hitbox.unwrap())
+                {
+                    self.setup.turn = self.setup.turn.other_player();
+                    // The possible squares depend on who moved last
+                    self.setup.en_passant = None;
+                    self.redraw_controls = true;
+                } else if self.en_passant_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.en_passant_button_hitbox.unwrap())
+                {
+                    let squares = self.setup.en_passant_squares();
+                    self.setup.en_passant = match self.setup.en_passant {
+                        None => squares.first().copied(),
+                        Some(current) => squares
+                            .iter()
+                            .skip_while(|square| **square != current)
+                            .nth(1)
+                            .copied(),
+                    };
+                    if squares.is_empty() {
+                        self.error = Some("No pawn could have just skipped a square".to_owned());
+                    }
+                    self.redraw_controls = true;
+                } else if let Some(i) = self
+                    .castling_button_hitboxes
+                    .iter()
+                    .position(|hitbox| Canvas::is_hitting(position, *hitbox))
+                {
+                    let castling = &mut self.setup.castling;
+                    match i {
+                        0 => castling.white_king_side = !castling.white_king_side,
+                        1 => castling.white_queen_side = !castling.white_queen_side,
+                        2 => castling.black_king_side = !castling.black_king_side,
+                        _ => castling.black_queen_side = !castling.black_queen_side,
+                    }
+                    self.redraw_controls = true;
+                } else if self.clear_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.clear_button_hitbox.unwrap())
+                {
+                    self.setup = PositionSetup::empty();
+                    self.redraw_board = true;
+                    self.redraw_controls = true;
+                } else if self.initial_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.initial_button_hitbox.unwrap())
+                {
+                    self.setup = PositionSetup::default();
+                    self.redraw_board = true;
+                    self.redraw_controls = true;
+                } else if self.play_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.play_button_hitbox.unwrap())
+                {
+                    self.play();
+                }
+            }
+        }
+    }
+}
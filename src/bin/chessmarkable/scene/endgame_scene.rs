@@ -0,0 +1,235 @@
+use super::Scene;
+use crate::canvas::*;
+use crate::scene::GameMode;
+use chessmarkable::endgames::{by_theme, Endgame, Goal};
+use chessmarkable::proto::VariantKind;
+use chessmarkable::tablebase;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+use pleco::Board;
+
+const FIRST_ROW_TOP: i32 = 260;
+const THEME_HEIGHT: i32 = 80;
+const ROW_HEIGHT: u32 = 70;
+const ROW_GAP: i32 = 10;
+const DRILL_WIDTH: u32 = 150;
+
+/// Built-in endgame positions grouped by theme. Tapping one starts a new
+/// save from it against the bot. The basic mates the tablebase knows can
+/// also be drilled against perfect defense.
+pub struct EndgameScene {
+    drawn: bool,
+    /// Bot the endgames are played against
+    pub game_mode: GameMode,
+    pub pvp_piece_rotation_enabled: bool,
+    endgame_hitboxes: Vec<(&'static Endgame, mxcfb_rect)>,
+    drill_hitboxes: Vec<(&'static Endgame, mxcfb_rect)>,
+    bot_button_hitbox: Option<mxcfb_rect>,
+    /// Set once the save for the endgame was added
+    pub started_save: Option<u64>,
+    pub started_drill: Option<&'static Endgame>,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl EndgameScene {
+    pub fn new(game_mode: GameMode, pvp_piece_rotation_enabled: bool) -> Self {
+        Self {
+            drawn: false,
+            game_mode,
+            pvp_piece_rotation_enabled,
+            endgame_hitboxes: vec![],
+            drill_hitboxes: vec![],
+            bot_button_hitbox: None,
+            started_save: None,
+            started_drill: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    fn next_bot(&mut self) {
+        self.game_mode = match self.game_mode {
+            GameMode::EasyBot => GameMode::NormalBot,
+            GameMode::NormalBot => GameMode::HardBot,
+            _ => GameMode::EasyBot,
+        };
+        self.drawn = false;
+    }
+
+    fn start(&mut self, endgame: &Endgame) {
+        let mut savestates = crate::SAVESTATES.lock().unwrap();
+        let id = savestates.add(
+            endgame.name.to_owned(),
+            self.game_mode,
+            VariantKind::Standard,
+            endgame.fen.to_owned(),
+        );
+        if let Err(err) = crate::savestates::write(&savestates) {
+            error!("Failed to write savestates file: {:?}", err);
+        }
+        self.started_save = Some(id);
+    }
+
+    fn draw_endgames(&mut self, canvas: &mut Canvas) {
+        self.endgame_hitboxes.clear();
+        self.drill_hitboxes.clear();
+        let mut top = FIRST_ROW_TOP;
+        for (theme, endgames) in by_theme() {
+            canvas.draw_styled_text(
+                Point2 {
+                    x: Some(40),
+                    y: Some(top + 55),
+                },
+                theme.label(),
+                50.0,
+                FontStyle::Bold,
+            );
+            top += THEME_HEIGHT;
+            for endgame in endgames {
+                let row = canvas.draw_rect(
+                    Point2 {
+                        x: Some(40),
+                        y: Some(top),
+                    },
+                    Vector2 {
+                        x: DISPLAYWIDTH as u32 - 80,
+                        y: ROW_HEIGHT,
+                    },
+                    2,
+                );
+                canvas.draw_text(
+                    Point2 {
+                        x: Some(60),
+                        y: Some(top + 48),
+                    },
+                    &format!(
+                        "{} - {}, {}",
+                        endgame.name,
+                        endgame.difficulty.label(),
+                        endgame.goal.label()
+                    ),
+                    40.0,
+                );
+                if is_drillable(endgame) {
+                    let drill = canvas.draw_rect(
+                        Point2 {
+                            x: Some((row.left + row.width - DRILL_WIDTH) as i32),
+                            y: Some(top),
+                        },
+                        Vector2 {
+                            x: DRILL_WIDTH,
+                            y: ROW_HEIGHT,
+                        },
+                        2,
+                    );
+                    canvas.draw_text_centered(drill, "Drill", 40.0);
+                    self.drill_hitboxes.push((endgame, drill));
+                }
+                self.endgame_hitboxes.push((endgame, row));
+                top += ROW_HEIGHT as i32 + ROW_GAP;
+            }
+        }
+    }
+}
+
+impl Scene for EndgameScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if self.drawn {
+            return;
+        }
+        self.drawn = true;
+
+        canvas.clear();
+        canvas.draw_styled_text(
+            Point2 {
+                x: None,
+                y: Some(150),
+            },
+            "Endgames",
+            100.0,
+            FontStyle::Bold,
+        );
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(225),
+            },
+            "You play white, the goal is with best play",
+            35.0,
+        );
+        self.draw_endgames(canvas);
+        self.back_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1830),
+            },
+            "Back",
+            75.0,
+            15,
+            40,
+        ));
+        self.bot_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(40),
+                y: Some(1830),
+            },
+            self.game_mode.label(),
+            40.0,
+            10,
+            20,
+        ));
+        canvas.update_full();
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => self.back_button_pressed = true,
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                } else if self.bot_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.bot_button_hitbox.unwrap())
+                {
+                    self.next_bot();
+                } else if let Some((endgame, _)) = self
+                    .drill_hitboxes
+                    .iter()
+                    .find(|(_, drill)| Canvas::is_hitting(position, *drill))
+                {
+                    self.started_drill = Some(*endgame);
+                } else if let Some((endgame, _)) = self
+                    .endgame_hitboxes
+                    .iter()
+                    .find(|(_, row)| Canvas::is_hitting(position, *row))
+                {
+                    let endgame = *endgame;
+                    self.start(endgame);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether the tablebase has the position (and it's a win to convert)
+fn is_drillable(endgame: &Endgame) -> bool {
+    endgame.goal == Goal::Win
+        && Board::from_fen(endgame.fen)
+            .map(|board| tablebase::covers(&board))
+            .unwrap_or(false)
+}
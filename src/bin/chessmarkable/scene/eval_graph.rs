@@ -0,0 +1,128 @@
+use crate::canvas::*;
+
+/// Evaluations beyond this (in centipawns) are drawn at the edge
+const EVAL_LIMIT: i32 = 1000;
+const PADDING: u32 = 30;
+const LINE_WIDTH: u32 = 3;
+const POINT_RADIUS: u32 = 6;
+const CURRENT_POINT_RADIUS: u32 = 14;
+const FONT_SIZE: f32 = 30.0;
+
+/// Line chart of the evaluation after every move of a game. Tapping it
+/// picks the move closest to the finger.
+pub struct EvalGraph {
+    area: mxcfb_rect,
+    /// After every half-move in centipawns from whites point of view
+    evals: Vec<i32>,
+}
+
+impl EvalGraph {
+    pub fn new(area: mxcfb_rect, evals: Vec<i32>) -> Self {
+        Self { area, evals }
+    }
+
+    fn x(&self, index: usize) -> i32 {
+        let width = self.area.width - PADDING * 2;
+        let steps = self.evals.len().max(2) - 1;
+        (self.area.left + PADDING) as i32 + (width as usize * index / steps) as i32
+    }
+
+    fn y(&self, eval: i32) -> i32 {
+        let half_height = (self.area.height / 2 - PADDING) as i32;
+        let middle = (self.area.top + self.area.height / 2) as i32;
+        middle - eval.max(-EVAL_LIMIT).min(EVAL_LIMIT) * half_height / EVAL_LIMIT
+    }
+
+    fn point(&self, index: usize) -> Point2<i32> {
+        Point2 {
+            x: self.x(index),
+            y: self.y(self.evals[index]),
+        }
+    }
+
+    /// `current` is the number of moves played in the shown position
+    pub fn draw(&self, canvas: &mut Canvas, current: Option<usize>) -> mxcfb_rect {
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(self.area.left as i32),
+                y: Some(self.area.top as i32),
+            },
+            Vector2 {
+                x: self.area.width,
+                y: self.area.height,
+            },
+            color::WHITE,
+        );
+        canvas.draw_rect(
+            Point2 {
+                x: Some(self.area.left as i32),
+                y: Some(self.area.top as i32),
+            },
+            Vector2 {
+                x: self.area.width,
+                y: self.area.height,
+            },
+            2,
+        );
+        let middle = self.y(0);
+        canvas.draw_line(
+            Point2 {
+                x: (self.area.left + PADDING) as i32,
+                y: middle,
+            },
+            Point2 {
+                x: (self.area.left + self.area.width - PADDING) as i32,
+                y: middle,
+            },
+            1,
+        );
+        let label_left = Some((self.area.left + PADDING) as i32);
+        canvas.draw_text(
+            Point2 {
+                x: label_left,
+                y: Some(self.y(EVAL_LIMIT) + FONT_SIZE as i32),
+            },
+            &format!("White +{}", EVAL_LIMIT / 100),
+            FONT_SIZE,
+        );
+        canvas.draw_text(
+            Point2 {
+                x: label_left,
+                y: Some(self.y(-EVAL_LIMIT) - 10),
+            },
+            &format!("Black +{}", EVAL_LIMIT / 100),
+            FONT_SIZE,
+        );
+
+        if self.evals.is_empty() {
+            canvas.draw_text_centered(self.area, "No moves to evaluate", FONT_SIZE);
+            return rect;
+        }
+        for index in 1..self.evals.len() {
+            canvas.draw_line(self.point(index - 1), self.point(index), LINE_WIDTH);
+        }
+        for index in 0..self.evals.len() {
+            let radius = if current == Some(index + 1) {
+                CURRENT_POINT_RADIUS
+            } else {
+                POINT_RADIUS
+            };
+            canvas.fill_circle(self.point(index), radius, color::BLACK);
+        }
+        rect
+    }
+
+    pub fn contains(&self, pos: Point2<u16>) -> bool {
+        Canvas::is_hitting(pos, self.area)
+    }
+
+    /// Number of moves played in the position of the point closest to `pos`
+    pub fn position_at(&self, pos: Point2<u16>) -> Option<usize> {
+        if self.evals.is_empty() || !self.contains(pos) {
+            return None;
+        }
+        (0..self.evals.len())
+            .min_by_key(|index| (self.x(*index) - pos.x as i32).abs())
+            .map(|index| index + 1)
+    }
+}
@@ -1,16 +1,34 @@
+use super::clock_widget::ClockWidget;
 use super::Scene;
+use crate::autosave::{self, Autosave};
 use crate::canvas::*;
-use crate::scene::piece_images::get_orig_piece_img;
+use crate::correspondence::{self, CorrespondenceGame};
+use crate::savestates::SavedClock;
+use crate::scene::dialog::Dialog;
+use crate::scene::gestures::{Gesture, Gestures};
+use crate::scene::handwriting_pad::HandwritingPad;
+use crate::scene::move_list::{MoveList, MoveListEntry, MoveListEvent};
+use crate::scene::pen_annotations::{draw_arrow, draw_dashed_arrow, PenAnnotations};
+use crate::scene::piece_images::get_piece_img;
+use crate::scene::qr_code::draw_qr_screen;
+use crate::settings::{BoardBorder, BoardTheme, GestureAction, Orientation, UndoPermission};
 use crate::CLI_OPTS;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chessmarkable::analysis::{square_control, static_eval, SquareControl};
+use chessmarkable::coach::{played_move, review_move, threats, Mistake};
+use chessmarkable::game::ALL_PROMOTION_PIECES;
+use chessmarkable::handwriting::{self, Stroke};
+use chessmarkable::notation::san;
 use chessmarkable::proto::*;
 use chessmarkable::{Player, Square};
 use fxhash::{FxHashMap, FxHashSet};
 use libremarkable::image;
-use libremarkable::input::{multitouch, InputEvent};
-use pleco::bot_prelude::*;
+use libremarkable::input::{gpio, multitouch, wacom::WacomEvent, InputEvent};
 use pleco::{BitMove, Board, Piece};
-use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::runtime;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
@@ -47,30 +65,572 @@ pub const ALL_PIECES: &[Piece] = &[
     Piece::WhitePawn,
 ];
 
+/// Pieces in the pockets in the order they are shown
+const POCKET_PIECES: &[DropPiece] = &[
+    DropPiece::Queen,
+    DropPiece::Rook,
+    DropPiece::Bishop,
+    DropPiece::Knight,
+    DropPiece::Pawn,
+];
+const POCKET_PIECE_SIZE: u32 = 70;
+const POCKET_COUNT_FONT_SIZE: f32 = 40.0;
+const CLOCK_WIDTH: u32 = 220;
+const CLOCK_FONT_SIZE: f32 = 40.0;
+/// Left edge of everything right of the board in landscape
+const LANDSCAPE_PANEL_LEFT: u32 = 1415;
+/// The refutation of a mistake is played out one arrow at a time
+const COACH_ARROW_INTERVAL: Duration = Duration::from_millis(1500);
+const COACH_NUMBER_SIZE: f32 = 45.0;
+
+/// Where the parts of a game are on the screen
+struct Layout {
+    orientation: Orientation,
+    board: mxcfb_rect,
+    /// Left edge and baseline of the first button
+    buttons_start: Point2<i32>,
+    buttons_per_row: usize,
+    /// Distance of the baselines of the rows of buttons
+    button_row_height: i32,
+    /// Horizontal gap between the buttons
+    button_spacing: i32,
+    button_font_size: f32,
+    /// Pockets of the player at the top of the board (the opponent unless
+    /// the board is flipped) and at the bottom
+    pockets_above: mxcfb_rect,
+    pockets_below: mxcfb_rect,
+    /// Distance between the left edges of the pieces in a pocket
+    pocket_spacing: u32,
+    /// Baseline of the number of pieces relative to the piece in a pocket
+    pocket_count: Vector2<i32>,
+    clock_above: mxcfb_rect,
+    clock_below: mxcfb_rect,
+    move_list: mxcfb_rect,
+    /// Left edges of the "Confirm" and "Cancel" buttons which are shown
+    /// instead of the move list
+    confirm_left: i32,
+    cancel_left: i32,
+    confirm_font_size: f32,
+    /// Moves can be written with the pen here
+    handwriting: mxcfb_rect,
+    /// Where the text about the game (e.g. the result) is wrapped into in
+    /// landscape. It's centered at the bottom in portrait (`None`).
+    info: Option<mxcfb_rect>,
+}
+
+impl Layout {
+    fn new(orientation: Orientation) -> Self {
+        match orientation {
+            Orientation::Portrait => Self::portrait(),
+            Orientation::Landscape => Self::landscape(),
+        }
+    }
+
+    /// Buttons above and pockets, clocks and the move list below the board
+    fn portrait() -> Self {
+        let board = board_rect(DISPLAYWIDTH as u32 / 8);
+        let area = |left: u32, top: u32, width: u32, height: u32| mxcfb_rect {
+            left,
+            top,
+            width,
+            height,
+        };
+        Self {
+            orientation: Orientation::Portrait,
+            board,
+            buttons_start: Point2 { x: 50, y: 90 },
+            buttons_per_row: 4,
+            button_row_height: 0,
+            button_spacing: 50,
+            button_font_size: 75.0,
+            pockets_above: area(90, 150, 1054, POCKET_PIECE_SIZE),
+            pockets_below: area(90, 1650, 1054, POCKET_PIECE_SIZE),
+            pocket_spacing: 210,
+            pocket_count: Vector2 {
+                x: POCKET_PIECE_SIZE as i32 + 10,
+                y: POCKET_PIECE_SIZE as i32 - 15,
+            },
+            clock_above: area(1154, 150, CLOCK_WIDTH, POCKET_PIECE_SIZE),
+            clock_below: area(1154, 1650, CLOCK_WIDTH, POCKET_PIECE_SIZE),
+            move_list: area(0, 1722, DISPLAYWIDTH as u32, 70),
+            confirm_left: 100,
+            cancel_left: DISPLAYWIDTH as i32 - 300,
+            confirm_font_size: 50.0,
+            handwriting: area(0, 1640, DISPLAYWIDTH as u32, DISPLAYHEIGHT as u32 - 1640),
+            info: None,
+        }
+    }
+
+    /// Board on the left. Right of it from top to bottom: buttons, the
+    /// pockets and clock of the top player, the move list, the text about
+    /// the game, room for handwriting and the clock and pockets of the
+    /// bottom player.
+    fn landscape() -> Self {
+        let square_size = DISPLAYWIDTH as u32 / 8;
+        let board = mxcfb_rect {
+            left: 2,
+            top: (DISPLAYWIDTH as u32 - square_size * 8) / 2,
+            width: square_size * 8,
+            height: square_size * 8,
+        };
+        let panel_width = DISPLAYHEIGHT as u32 - LANDSCAPE_PANEL_LEFT;
+        let panel = |top: u32, height: u32| mxcfb_rect {
+            left: LANDSCAPE_PANEL_LEFT,
+            top,
+            width: panel_width,
+            height,
+        };
+        let clock = |top: u32| mxcfb_rect {
+            left: LANDSCAPE_PANEL_LEFT + 5,
+            top,
+            width: CLOCK_WIDTH,
+            height: POCKET_PIECE_SIZE,
+        };
+        Self {
+            orientation: Orientation::Landscape,
+            board,
+            buttons_start: Point2 {
+                x: LANDSCAPE_PANEL_LEFT as i32 + 15,
+                y: 70,
+            },
+            buttons_per_row: 2,
+            button_row_height: 80,
+            button_spacing: 30,
+            button_font_size: 40.0,
+            pockets_above: panel(190, 105),
+            pockets_below: panel(1290, 105),
+            pocket_spacing: 88,
+            pocket_count: Vector2 {
+                x: 10,
+                y: POCKET_PIECE_SIZE as i32 + 30,
+            },
+            clock_above: clock(310),
+            clock_below: clock(1195),
+            move_list: panel(410, 70),
+            confirm_left: LANDSCAPE_PANEL_LEFT as i32 + 15,
+            cancel_left: LANDSCAPE_PANEL_LEFT as i32 + 245,
+            confirm_font_size: 40.0,
+            handwriting: panel(710, 470),
+            info: Some(panel(500, 200)),
+        }
+    }
+
+    fn square_size(&self) -> u32 {
+        self.board.width / 8
+    }
+}
+
 #[inline]
 fn to_square(x: usize, y: usize) -> Square {
     Square::new(x, y).expect("to_square() failed")
 }
 
+/// Distance between the lines or dots of patterned squares
+const SQUARE_PATTERN_SPACING: i32 = 16;
+/// The frame of the square control heatmap gets this much thicker with
+/// every attacker one side has more (up to `MAX_CONTROL_FRAME` pixels)
+const CONTROL_FRAME_STEP: u32 = 4;
+const MAX_CONTROL_FRAME: u32 = 16;
+
+/// Area of the board which is centered on the screen
+pub fn board_rect(square_size: u32) -> mxcfb_rect {
+    mxcfb_rect {
+        left: (DISPLAYWIDTH as u32 - square_size * 8) / 2,
+        top: (DISPLAYHEIGHT as u32 - square_size * 8) / 2,
+        width: square_size * 8,
+        height: square_size * 8,
+    }
+}
+
+/// First field of a FEN
+fn board_placement(fen: &str) -> &str {
+    fen.split_whitespace().next().unwrap_or("")
+}
+
+/// Label the file on the bottom row and the rank on the left
+/// column of the board (in the corners of the squares).
+pub fn draw_square_coordinates(
+    canvas: &mut Canvas,
+    bounds: &mxcfb_rect,
+    square: Square,
+    flipped: bool,
+) {
+    let edge = if flipped { 7 } else { 0 };
+    let name = square.to_string().to_lowercase();
+    if square.y() == edge {
+        canvas.draw_text(
+            Point2 {
+                x: Some((bounds.left + bounds.width) as i32 - 25),
+                y: Some((bounds.top + bounds.height) as i32 - 8),
+            },
+            &name[..1],
+            30.0,
+        );
+    }
+    if square.x() == edge {
+        canvas.draw_text(
+            Point2 {
+                x: Some(bounds.left as i32 + 6),
+                y: Some(bounds.top as i32 + 30),
+            },
+            &name[1..],
+            30.0,
+        );
+    }
+}
+
+/// Heatmap of who attacks the square more often: a frame that is thicker
+/// the more attackers one side has than the other and the difference in
+/// the top right corner ("+2" if white attacks it twice more)
+pub fn draw_square_control(canvas: &mut Canvas, bounds: &mxcfb_rect, control: SquareControl) {
+    if control == SquareControl::default() {
+        return;
+    }
+    let balance = control.balance();
+    if balance != 0 {
+        let frame = (CONTROL_FRAME_STEP * balance.abs() as u32).min(MAX_CONTROL_FRAME);
+        canvas.draw_rect(
+            Point2 {
+                x: Some(bounds.left as i32),
+                y: Some(bounds.top as i32),
+            },
+            bounds.size().cast().unwrap(),
+            frame,
+        );
+    }
+    let label = if balance == 0 {
+        "=".to_owned()
+    } else {
+        format!("{:+}", balance)
+    };
+    canvas.draw_text(
+        Point2 {
+            x: Some((bounds.left + bounds.width) as i32 - 50),
+            y: Some(bounds.top as i32 + 30 + MAX_CONTROL_FRAME as i32),
+        },
+        &label,
+        30.0,
+    );
+}
+
+/// Background of a square in the style of `theme`
+pub fn draw_square_background(
+    canvas: &mut Canvas,
+    bounds: &mxcfb_rect,
+    is_bright: bool,
+    theme: BoardTheme,
+) {
+    let top_left = Point2 {
+        x: Some(bounds.left as i32),
+        y: Some(bounds.top as i32),
+    };
+    if theme == BoardTheme::Solid {
+        let color = if is_bright {
+            color::GRAY(100)
+        } else {
+            color::GRAY(50)
+        };
+        canvas.fill_rect(top_left, bounds.size().cast().unwrap(), color);
+        return;
+    }
+
+    canvas.fill_rect(top_left, bounds.size().cast().unwrap(), color::WHITE);
+    if is_bright {
+        return;
+    }
+    let (left, top) = (bounds.left as i32, bounds.top as i32);
+    let (width, height) = (bounds.width as i32, bounds.height as i32);
+    match theme {
+        BoardTheme::Solid => unreachable!(),
+        BoardTheme::Hatched => {
+            // Diagonal lines from the bottom left to the top right
+            // (two pixels wide without leaving the square)
+            for offset in (SQUARE_PATTERN_SPACING..width + height - 2)
+                .step_by(SQUARE_PATTERN_SPACING as usize)
+            {
+                for offset in &[offset, offset + 1] {
+                    let start_x = (*offset).min(width - 1);
+                    let end_y = (*offset).min(height - 1);
+                    canvas.draw_line(
+                        Point2 {
+                            x: left + start_x,
+                            y: top + offset - start_x,
+                        },
+                        Point2 {
+                            x: left + offset - end_y,
+                            y: top + end_y,
+                        },
+                        1,
+                    );
+                }
+            }
+        }
+        BoardTheme::Dotted => {
+            let dot_size = 4;
+            for (row, y) in (SQUARE_PATTERN_SPACING / 2..height - dot_size)
+                .step_by(SQUARE_PATTERN_SPACING as usize)
+                .enumerate()
+            {
+                // Every other row is shifted to look less like a grid
+                let shift = if row % 2 == 0 {
+                    0
+                } else {
+                    SQUARE_PATTERN_SPACING / 2
+                };
+                for x in (SQUARE_PATTERN_SPACING / 4 + shift..width - dot_size)
+                    .step_by(SQUARE_PATTERN_SPACING as usize)
+                {
+                    canvas.fill_rect(
+                        Point2 {
+                            x: Some(left + x),
+                            y: Some(top + y),
+                        },
+                        Vector2 {
+                            x: dot_size as u32,
+                            y: dot_size as u32,
+                        },
+                        color::BLACK,
+                    );
+                }
+            }
+        }
+        BoardTheme::Outlined => {
+            let inset = bounds.width / 12;
+            canvas.draw_rect(
+                Point2 {
+                    x: Some(left + inset as i32),
+                    y: Some(top + inset as i32),
+                },
+                Vector2 {
+                    x: bounds.width - inset * 2,
+                    y: bounds.height - inset * 2,
+                },
+                3,
+            );
+        }
+    }
+}
+
+/// Frame on the edge of the board (drawn over the outer squares)
+pub fn draw_board_border(canvas: &mut Canvas, board: mxcfb_rect, border: BoardBorder) {
+    if border == BoardBorder::None {
+        return;
+    }
+    canvas.draw_rect(
+        Point2 {
+            x: Some(board.left as i32),
+            y: Some(board.top as i32),
+        },
+        board.size().cast().unwrap(),
+        border.width(),
+    );
+}
+
+/// Refresh what `draw_board()` of a scene returned. Single squares use
+/// a quicker waveform than the whole board.
+pub fn refresh_board_regions(canvas: &mut Canvas, regions: &[mxcfb_rect], square_size: u32) {
+    for region in regions {
+        if region.width > square_size || region.height > square_size {
+            canvas.update_partial(region);
+        } else {
+            canvas.update_partial_quick(region);
+        }
+    }
+}
+
+/// Save the board as it's shown (with highlights, coordinates and marks)
+/// to a new PNG in the images location
+pub fn save_board_image(canvas: &mut Canvas, board: mxcfb_rect) -> Result<PathBuf> {
+    let ref folder = crate::config::images_location();
+    std::fs::create_dir_all(folder).context("Create images folder")?;
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = folder.join(format!("position-{}.png", timestamp));
+    canvas.save_png(&board, &path)?;
+    info!("Saved image of the board to {:?}", path);
+    Ok(path)
+}
+
+/// Hitboxes of all squares of `board` indexed by file and rank. A flipped
+/// board is viewed from black's side (h1 is in the top left).
+pub fn board_hitboxes(board: mxcfb_rect, flipped: bool) -> Vec<Vec<mxcfb_rect>> {
+    let square_size = board.width / 8;
+    let mut piece_hitboxes = Vec::new();
+    for x in 0..8 {
+        let mut y_axis = Vec::new();
+        for y in 0..8 {
+            let (column, row) = if flipped { (7 - x, y) } else { (x, 7 - y) };
+            y_axis.push(mxcfb_rect {
+                left: board.left + square_size * column,
+                top: board.top + square_size * row,
+                width: square_size,
+                height: square_size,
+            });
+        }
+        piece_hitboxes.push(y_axis);
+    }
+    piece_hitboxes
+}
+
+/// What the open dialog is about
+enum DialogAction {
+    Menu,
+    /// Resign or offer a draw
+    EndGame,
+    /// Show the position or the moves as QR code
+    Share,
+    /// Choose the piece a pawn becomes
+    Promotion {
+        source: Square,
+        destination: Square,
+    },
+}
+
 enum GameBottomInfo {
     GameEnded(String),
     Info(String),
     Error(String),
+    /// Explanation of a mistake (longer than the other infos)
+    Coach(String),
 }
 
-#[derive(Clone, Copy, PartialEq)]
+/// How a game is played. Scenes should ask the mode about what is allowed
+/// (see the methods below) instead of matching on single variants.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum GameMode {
-    PvP = 0,
-    EasyBot = 2,
-    NormalBot = 4,
-    HardBot = 6,
-    // Could go up to about 8-10 (depending on the algo) before getting too slow. But probably fairly unbeatable then.
+    /// Two players sharing the device
+    PvP,
+    EasyBot,
+    NormalBot,
+    HardBot,
+    /// Both sides are moved locally to explore a position. Undo is always
+    /// possible and the game doesn't need to make sense.
+    Analysis,
+    /// Transcribe a game played on a real board. Both sides are moved
+    /// locally but no help (move hints) is given.
+    Recording,
+    /// Two bots play each other (see `Settings::bot_vs_bot`) while
+    /// nobody plays locally
+    BotVsBot,
+}
+
+impl GameMode {
+    pub const ALL: &'static [GameMode] = &[
+        GameMode::PvP,
+        GameMode::EasyBot,
+        GameMode::NormalBot,
+        GameMode::HardBot,
+        GameMode::Analysis,
+        GameMode::Recording,
+        GameMode::BotVsBot,
+    ];
+
+    /// Search depth of the bot playing black or `None` if no bot is involved.
+    pub fn bot_depth(&self) -> Option<u16> {
+        match self {
+            GameMode::EasyBot => Some(2),
+            GameMode::NormalBot => Some(4),
+            GameMode::HardBot => Some(6),
+            GameMode::BotVsBot => Some(crate::SETTINGS.lock().unwrap().bot_vs_bot.black_depth),
+            // Could go up to about 8-10 (depending on the algo) before getting too slow. But probably fairly unbeatable then.
+            GameMode::PvP | GameMode::Analysis | GameMode::Recording => None,
+        }
+    }
+
+    pub fn has_bot(&self) -> bool {
+        self.bot_depth().is_some()
+    }
+
+    /// Whether the possible moves of a selected piece are shown
+    pub fn move_hints_allowed(&self) -> bool {
+        *self != GameMode::Recording
+    }
+
+    /// Whether `player` may take back moves (as long as it's their turn)
+    pub fn undo_allowed(&self, player: Player) -> bool {
+        if *self == GameMode::Analysis {
+            return true;
+        } else if self.has_bot() && player == Player::Black {
+            return false;
+        }
+        match crate::SETTINGS.lock().unwrap().undo {
+            UndoPermission::Always => true,
+            UndoPermission::NotAgainstBots => !self.has_bot(),
+            UndoPermission::Never => false,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GameMode::PvP => "Player vs Player",
+            GameMode::EasyBot => "Player vs Bot (Easy)",
+            GameMode::NormalBot => "Player vs Bot (Normal)",
+            GameMode::HardBot => "Player vs Bot (Hard)",
+            GameMode::Analysis => "Analysis",
+            GameMode::Recording => "Recording",
+            GameMode::BotVsBot => "Bot vs Bot",
+        }
+    }
+}
+
+/// How well the minimum think time (see `--min-think-time`) was respected
+#[derive(Default, Debug)]
+struct ThinkTimeStats {
+    moves: u32,
+    /// Moves that were attempted at least once before the think time elapsed
+    rushed_moves: u32,
+}
+
+fn min_think_time() -> Option<Duration> {
+    let secs = CLI_OPTS
+        .min_think_time
+        .unwrap_or_else(|| crate::SETTINGS.lock().unwrap().min_think_time);
+    match secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs.into())),
+    }
+}
+
+/// Search on every core when not on a rM 1
+fn create_device_bot(
+    runtime: &mut runtime::Runtime,
+    me: Player,
+    depth: u16,
+    reaction_delay: Duration,
+) -> PlayerEndpoint {
+    let threads =
+        if libremarkable::device::CURRENT_DEVICE.model == libremarkable::device::Model::Gen1 {
+            1
+        } else {
+            chessmarkable::search::all_cores()
+        };
+    debug!("The Bot will search on {} threads", threads);
+    runtime
+        .block_on(create_bot(
+            me,
+            depth,
+            threads,
+            reaction_delay,
+            crate::config::bot_seed(),
+        ))
+        .expect("Failed to initialize bot task")
+}
+
+fn bot_reaction_delay() -> Duration {
+    let millis = CLI_OPTS
+        .bot_reaction_delay
+        .unwrap_or_else(|| crate::SETTINGS.lock().unwrap().bot_reaction_delay);
+    Duration::from_millis(millis.into())
 }
 
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum SavestateSlot {
-    First,
-    Second,
-    Third,
+    /// Named save with this id
+    Saved(u64),
+    /// Saved after every move as correspondence game with this id
+    Correspondence(u64),
 }
 
 pub struct GameScene {
@@ -78,17 +638,61 @@ pub struct GameScene {
     /// May be above zero when a fen was imported. Used to prevent panic on undo.
     game_mode: GameMode,
     savestate_slot: SavestateSlot,
+    correspondence: Option<CorrespondenceGame>,
+    autosave: Autosave,
+    /// A move was accepted since the last autosave
+    autosave_pending: bool,
     first_draw: bool,
     back_button_hitbox: Option<mxcfb_rect>,
     undo_button_hitbox: Option<mxcfb_rect>,
     full_refresh_button_hitbox: Option<mxcfb_rect>,
+    flip_button_hitbox: Option<mxcfb_rect>,
     piece_hitboxes: Vec<Vec<mxcfb_rect>>,
+    /// Viewed from black's side
+    board_flipped: bool,
+    show_coordinates: bool,
+    /// Square control heatmap (only in analysis)
+    show_heatmap: bool,
+    /// Dashed arrows for what the opponent threatens if the local player
+    /// to move passed
+    show_threats: bool,
+    /// Threats in the current position (`None` until they're searched)
+    threats: Option<Vec<(Square, Square)>>,
+    board_theme: BoardTheme,
+    board_border: BoardBorder,
+    highlight_last_move: bool,
+    highlight_check: bool,
+    /// Turn the board to the local player to move (pass and play)
+    auto_flip: bool,
+    /// Hide the board after every move until the next player taps
+    hide_board: bool,
+    /// The board is hidden until the tablet was passed to this player
+    pass_tablet_to: Option<Player>,
+    pass_tablet_shown_at: Option<SystemTime>,
+    /// Pieces in hand (only in variants with drops)
+    pockets: Option<Pockets>,
+    possible_drops: Vec<(DropPiece, Square)>,
+    /// Piece of the pocket that is about to be dropped
+    selected_drop: Option<DropPiece>,
+    /// Square below the hovering pen
+    hovered_square: Option<Square>,
+    /// Move or drop that is shown but needs to be confirmed first together
+    /// with the board and last move to return to when it's cancelled
+    unconfirmed_turn: Option<(ChessRequest, Board, Option<Square>, Option<Square>)>,
+    confirm_buttons_drawn: bool,
+    confirm_button_hitbox: Option<mxcfb_rect>,
+    cancel_button_hitbox: Option<mxcfb_rect>,
+    pocket_hitboxes: Vec<(Player, DropPiece, mxcfb_rect)>,
+    redraw_pockets: bool,
+    img_pocket_pieces: FxHashMap</* Piece */ char, image::DynamicImage>,
     /// The squared that were visually affected and should be redrawn
     redraw_squares: FxHashSet<Square>,
     /// If the amount of changes squares cannot be easily decided this
     /// is a easy way to update everything. Has a performance hit though.
     redraw_all_squares: bool,
     /// Resized to fit selected_square
+    /// Where everything is drawn (for the orientation setting)
+    layout: Layout,
     square_size: u32,
     img_piece_moved_from: image::DynamicImage,
     img_piece_moved_to: image::DynamicImage,
@@ -100,8 +704,33 @@ pub struct GameScene {
     img_piece_movehint: image::DynamicImage,
     selected_square: Option<Square>,
     move_hints: FxHashSet<Square>,
+    /// The move hints that take a piece
+    capture_hints: FxHashSet<Square>,
     last_move_from: Option<Square>,
     last_move_to: Option<Square>,
+    /// King of the player to move if it's in check
+    checked_king: Option<Square>,
+    /// Pieces giving the check
+    checkers: Vec<Square>,
+    time_control: Option<TimeControl>,
+    /// Last state of the clock and when it arrived (`None` without a time control)
+    clock: Option<(ClockSnapshot, Instant)>,
+    clock_above: ClockWidget,
+    clock_below: ClockWidget,
+    move_list: MoveList,
+    pen_annotations: PenAnnotations,
+    handwriting_pad: HandwritingPad,
+    gestures: Gestures,
+    /// Opened with a gesture
+    dialog: Option<Dialog>,
+    dialog_drawn: bool,
+    dialog_action: Option<DialogAction>,
+    /// Entries for the move that was just played and the board
+    /// placement each leads to (`None` if unknown). Resolved once
+    /// the new board arrives.
+    pending_move_list_entries: Vec<(MoveListEntry, Option<String>)>,
+    /// Board and last move to return to after previewing an earlier position
+    preview_return: Option<(Board, Option<Square>, Option<Square>)>,
     pvp_piece_rotation_enabled: bool,
     pieces_rotated: bool,
     /// Remember a press to decide whether to show options or do a move at once
@@ -109,6 +738,11 @@ pub struct GameScene {
     pub back_button_pressed: bool,
     /// Do a full screen refresh on next draw
     force_full_refresh: Option<SystemTime>,
+    /// Save the board as an image on the next draw
+    save_image_requested: bool,
+    /// Title and data of the QR code shown instead of the game (until tapped)
+    qr_code: Option<(&'static str, String)>,
+    qr_code_drawn: bool,
     draw_game_bottom_info: Option<GameBottomInfo>,
     draw_game_bottom_info_delay_until: Option<SystemTime>,
     draw_game_bottom_info_last_rect: Option<mxcfb_rect>,
@@ -118,8 +752,30 @@ pub struct GameScene {
     black_request_sender: Option<Sender<ChessRequest>>,
     white_update_receiver: Option<Receiver<ChessUpdate>>,
     black_update_receiver: Option<Receiver<ChessUpdate>>,
-    possible_moves: Vec<(Square, Square)>,
-    runtime: runtime::Runtime,
+    possible_moves: Vec<PossibleMove>,
+    /// Start of the current turn of a local player (if a minimum think time is set)
+    thinking_since: Option<SystemTime>,
+    thinking_rushed: bool,
+    think_time_reached: bool,
+    think_time_stats: ThinkTimeStats,
+    /// Taken when leaving to shut the game down in the background
+    runtime: Option<runtime::Runtime>,
+    /// One of the boards of a simul. Leaving only returns to the overview
+    /// while the game keeps running, so it's saved after every move instead
+    /// of being autosaved.
+    simul: bool,
+    /// Review the moves of the local player (see `Settings::coach`)
+    coach: bool,
+    /// Position before the next move of the local player
+    coach_fen_before: Option<String>,
+    /// Review of the last move running in the background. Sends the
+    /// mistake together with the move in SAN if it was one.
+    coach_review: Option<std::sync::mpsc::Receiver<Option<(Mistake, String)>>>,
+    /// Mistake whose refutation is drawn onto the board
+    coach_mistake: Option<Mistake>,
+    /// Moves of the refutation shown so far
+    coach_arrows: usize,
+    coach_next_arrow_at: Option<SystemTime>,
 }
 
 impl GameScene {
@@ -127,37 +783,23 @@ impl GameScene {
         game_mode: GameMode,
         savestate_slot: SavestateSlot,
         pvp_piece_rotation_enabled: bool,
+        variant: VariantKind,
     ) -> Self {
+        let layout = Layout::new(crate::SETTINGS.lock().unwrap().orientation);
         // Size of board
-        let square_size = DISPLAYWIDTH as u32 / 8;
+        let square_size = layout.square_size();
         let piece_padding = square_size / 10;
         let overlay_padding = square_size / 20;
 
-        // Calculate hitboxes
-        let mut piece_hitboxes = Vec::new();
-        for x in 0..8 {
-            let mut y_axis = Vec::new();
-            for y in 0..8 {
-                y_axis.push(mxcfb_rect {
-                    left: ((DISPLAYWIDTH as u32 - square_size * 8) / 2) + square_size * x,
-                    top: ((DISPLAYHEIGHT as u32 - square_size * 8) / 2) + square_size * (7 - y),
-                    width: square_size,
-                    height: square_size,
-                });
-            }
-            piece_hitboxes.push(y_axis);
-        }
+        let board_flipped = crate::SETTINGS.lock().unwrap().board_flipped;
+        let piece_hitboxes = board_hitboxes(layout.board, board_flipped);
 
         // Create resized images
         let mut img_pieces: FxHashMap<char, image::DynamicImage> = Default::default();
         for piece in ALL_PIECES.iter() {
             img_pieces.insert(
                 piece.character_lossy(),
-                get_orig_piece_img(piece).resize(
-                    square_size - piece_padding * 2,
-                    square_size - piece_padding * 2,
-                    image::FilterType::Lanczos3,
-                ),
+                get_piece_img(piece, square_size - piece_padding * 2),
             );
         }
         // Create resized images
@@ -165,6 +807,13 @@ impl GameScene {
         img_pieces.iter().for_each(|(piece, img)| {
             img_pieces_rotated.insert(piece.clone(), img.rotate180());
         });
+        let mut img_pocket_pieces: FxHashMap<char, image::DynamicImage> = Default::default();
+        for piece in ALL_PIECES.iter() {
+            img_pocket_pieces.insert(
+                piece.character_lossy(),
+                get_piece_img(piece, POCKET_PIECE_SIZE),
+            );
+        }
         let img_piece_selected = IMG_PIECE_SELECTED.resize(
             square_size - overlay_padding * 2,
             square_size - overlay_padding * 2,
@@ -185,6 +834,7 @@ impl GameScene {
             .thread_name("tokio_game_scene")
             .threaded_scheduler()
             //.max_threads(2)
+            .enable_time() // For the clock
             .build()
             .expect("Failed to create tokio runtime");
 
@@ -193,77 +843,133 @@ impl GameScene {
         let mut white_update_receiver: Option<Receiver<ChessUpdate>> = None;
         let mut black_update_receiver: Option<Receiver<ChessUpdate>> = None;
 
+        let correspondence = match savestate_slot {
+            SavestateSlot::Correspondence(id) => match correspondence::read(id) {
+                Ok(game) => Some(game),
+                Err(err) => {
+                    error!("Failed to read correspondence game {}: {:?}", id, err);
+                    None
+                }
+            },
+            _ => None,
+        };
+
         let starting_fen = match savestate_slot {
-            SavestateSlot::First => crate::SAVESTATES.lock().unwrap().slot_1.clone(),
-            SavestateSlot::Second => crate::SAVESTATES.lock().unwrap().slot_2.clone(),
-            SavestateSlot::Third => crate::SAVESTATES.lock().unwrap().slot_3.clone(),
+            SavestateSlot::Saved(id) => crate::SAVESTATES
+                .lock()
+                .unwrap()
+                .get(id)
+                .map(|save| save.fen.clone()),
+            SavestateSlot::Correspondence(_) => {
+                correspondence.as_ref().map(|game| game.fen.clone())
+            }
+        };
+
+        let saved_clock = match savestate_slot {
+            SavestateSlot::Saved(id) => crate::SAVESTATES
+                .lock()
+                .unwrap()
+                .get(id)
+                .and_then(|save| save.clock),
+            SavestateSlot::Correspondence(_) => None,
+        };
+
+        // Savestates with pockets can only be continued as crazyhouse
+        let variant = match starting_fen {
+            Some(ref fen) if fen.contains('[') => VariantKind::Crazyhouse,
+            _ => variant,
+        };
+
+        // Written on the first player switch (and after every move)
+        let autosave = Autosave {
+            game_mode,
+            slot: savestate_slot,
+            variant,
+            pvp_piece_rotation_enabled,
+            fen: starting_fen
+                .clone()
+                .unwrap_or_else(|| Board::default().fen()),
+            clock: saved_clock,
+        };
+
+        let chess_config = ChessConfig {
+            starting_fen,
+            can_black_undo: game_mode.undo_allowed(Player::Black),
+            can_white_undo: game_mode.undo_allowed(Player::White),
+            allow_undo_after_loose: true,
+            variant,
+            // Correspondence games have days per move instead
+            time_control: match savestate_slot {
+                SavestateSlot::Correspondence(_) => None,
+                _ => saved_clock
+                    .map(|clock| clock.time_control)
+                    .or(crate::SETTINGS.lock().unwrap().time_control),
+            },
+            starting_clock: saved_clock.map(|clock| clock.times),
         };
+        let time_control = chess_config.time_control;
+
+        if game_mode == GameMode::BotVsBot {
+            let settings = crate::SETTINGS.lock().unwrap().bot_vs_bot;
+            let reaction_delay = bot_reaction_delay().max(crate::config::bot_vs_bot_min_delay());
+            let white_bot = create_device_bot(
+                &mut runtime,
+                Player::White,
+                settings.white_depth,
+                reaction_delay,
+            );
+            let black_bot = create_device_bot(
+                &mut runtime,
+                Player::Black,
+                settings.black_depth,
+                reaction_delay,
+            );
+
+            // Watched like a spectator. There are no local players to send requests.
+            let (spectator_update_tx, spectator_update_rx) = channel::<ChessUpdate>(256);
+            let (_, spectator_request_rx) = channel::<ChessRequest>(1);
+            runtime.spawn(create_game(
+                white_bot,
+                black_bot,
+                (spectator_update_tx, spectator_request_rx),
+                chess_config,
+            ));
 
-        if game_mode == GameMode::PvP {
+            white_update_receiver = Some(spectator_update_rx);
+        } else if let Some(bot_depth) = game_mode.bot_depth() {
             let (white_update_tx, white_update_rx) = channel::<ChessUpdate>(256);
             let (white_request_tx, white_request_rx) = channel::<ChessRequest>(256);
 
-            let (black_update_tx, black_update_rx) = channel::<ChessUpdate>(256);
-            let (black_request_tx, black_request_rx) = channel::<ChessRequest>(256);
+            let bot =
+                create_device_bot(&mut runtime, Player::Black, bot_depth, bot_reaction_delay());
 
             runtime.spawn(create_game(
                 (white_update_tx, white_request_rx),
-                (black_update_tx, black_request_rx),
+                bot,
                 stubbed_spectator(),
-                ChessConfig {
-                    starting_fen,
-                    can_black_undo: true,
-                    can_white_undo: true,
-                    allow_undo_after_loose: true,
-                },
+                chess_config,
             ));
 
             white_request_sender = Some(white_request_tx);
-            black_request_sender = Some(black_request_tx);
             white_update_receiver = Some(white_update_rx);
-            black_update_receiver = Some(black_update_rx);
-        //Self::spawn_bot_thread(bot_job_rx, bot_move_tx); // TODO
         } else {
             let (white_update_tx, white_update_rx) = channel::<ChessUpdate>(256);
             let (white_request_tx, white_request_rx) = channel::<ChessRequest>(256);
 
-            // Use multithreaded algo when not rM 1
-            let bot = if libremarkable::device::CURRENT_DEVICE.model
-                == libremarkable::device::Model::Gen1
-            {
-                debug!("The Bot will use the AlphaBeta algorithm (singlethreaded)");
-                runtime
-                    .block_on(create_bot::<AlphaBetaSearcher>(
-                        Player::Black,
-                        game_mode as u16,
-                        Duration::from_millis(CLI_OPTS.bot_reaction_delay.into()),
-                    ))
-                    .expect("Failed to initialize bot task")
-            } else {
-                debug!("The Bot will use the Jamboree algorithm (multithreaded)");
-                runtime
-                    .block_on(create_bot::<JamboreeSearcher>(
-                        Player::Black,
-                        game_mode as u16,
-                        Duration::from_millis(CLI_OPTS.bot_reaction_delay.into()),
-                    ))
-                    .expect("Failed to initialize bot task")
-            };
+            let (black_update_tx, black_update_rx) = channel::<ChessUpdate>(256);
+            let (black_request_tx, black_request_rx) = channel::<ChessRequest>(256);
 
             runtime.spawn(create_game(
                 (white_update_tx, white_request_rx),
-                bot,
+                (black_update_tx, black_request_rx),
                 stubbed_spectator(),
-                ChessConfig {
-                    starting_fen,
-                    can_black_undo: false,
-                    can_white_undo: true,
-                    allow_undo_after_loose: true,
-                },
+                chess_config,
             ));
 
             white_request_sender = Some(white_request_tx);
+            black_request_sender = Some(black_request_tx);
             white_update_receiver = Some(white_update_rx);
+            black_update_receiver = Some(black_update_rx);
         }
 
         Self {
@@ -271,14 +977,62 @@ impl GameScene {
             first_draw: true,
             game_mode,
             savestate_slot,
+            correspondence,
+            autosave,
+            autosave_pending: false,
             piece_hitboxes,
+            board_flipped,
+            show_coordinates: crate::SETTINGS.lock().unwrap().show_coordinates,
+            show_heatmap: false,
+            show_threats: false,
+            threats: None,
+            board_theme: crate::SETTINGS.lock().unwrap().board_theme,
+            board_border: crate::SETTINGS.lock().unwrap().board_border,
+            highlight_last_move: crate::SETTINGS.lock().unwrap().highlight_last_move,
+            highlight_check: crate::SETTINGS.lock().unwrap().highlight_check,
+            auto_flip: game_mode == GameMode::PvP
+                && !pvp_piece_rotation_enabled
+                && crate::SETTINGS.lock().unwrap().pvp_auto_flip,
+            hide_board: game_mode == GameMode::PvP
+                && crate::SETTINGS.lock().unwrap().pvp_hide_board,
+            pass_tablet_to: None,
+            pass_tablet_shown_at: None,
+            flip_button_hitbox: None,
+            pockets: None,
+            possible_drops: vec![],
+            selected_drop: None,
+            hovered_square: None,
+            unconfirmed_turn: None,
+            confirm_buttons_drawn: false,
+            confirm_button_hitbox: None,
+            cancel_button_hitbox: None,
+            pocket_hitboxes: vec![],
+            redraw_pockets: false,
+            img_pocket_pieces,
             square_size,
             piece_padding,
             overlay_padding,
             selected_square: None,
             move_hints: Default::default(),
+            capture_hints: Default::default(),
             last_move_from: None,
             last_move_to: None,
+            checked_king: None,
+            checkers: vec![],
+            time_control,
+            clock: None,
+            clock_above: ClockWidget::new(layout.clock_above, CLOCK_FONT_SIZE),
+            clock_below: ClockWidget::new(layout.clock_below, CLOCK_FONT_SIZE),
+            move_list: MoveList::new(layout.move_list),
+            pen_annotations: Default::default(),
+            handwriting_pad: HandwritingPad::new(layout.handwriting),
+            layout,
+            gestures: Default::default(),
+            dialog: None,
+            dialog_drawn: false,
+            dialog_action: None,
+            pending_move_list_entries: vec![],
+            preview_return: None,
             finger_down_square: None,
             img_pieces,
             img_pieces_rotated,
@@ -294,55 +1048,78 @@ impl GameScene {
             undo_button_hitbox: None,
             full_refresh_button_hitbox: None,
             back_button_pressed: false,
+            simul: false,
             force_full_refresh: None,
+            save_image_requested: false,
+            qr_code: None,
+            qr_code_drawn: false,
             draw_game_bottom_info_delay_until: Some(SystemTime::now() + Duration::from_secs(2)),
             draw_game_bottom_info: Some(GameBottomInfo::Info("White starts".to_owned())),
             draw_game_bottom_info_last_rect: None,
             draw_game_bottom_info_clear_at: None,
             is_game_over: false,
-            runtime,
+            runtime: Some(runtime),
             black_request_sender,
             black_update_receiver,
             white_request_sender,
             white_update_receiver,
             possible_moves: vec![],
+            thinking_since: min_think_time().map(|_| SystemTime::now()),
+            thinking_rushed: false,
+            think_time_reached: false,
+            think_time_stats: Default::default(),
+            coach: game_mode.has_bot()
+                && game_mode != GameMode::BotVsBot
+                && crate::SETTINGS.lock().unwrap().coach,
+            coach_fen_before: None,
+            coach_review: None,
+            coach_mistake: None,
+            coach_arrows: 0,
+            coach_next_arrow_at: None,
+        }
+    }
+
+    fn start_thinking(&mut self) {
+        if min_think_time().is_some() {
+            self.thinking_since = Some(SystemTime::now());
+            self.thinking_rushed = false;
+            self.think_time_reached = false;
+        }
+    }
+
+    /// Time left before the current player is allowed to move
+    fn remaining_think_time(&self) -> Option<Duration> {
+        let min_think_time = min_think_time()?;
+        let thought = self.thinking_since?.elapsed().unwrap_or_default();
+        if thought < min_think_time {
+            Some(min_think_time - thought)
+        } else {
+            None
         }
     }
 
     fn handle_outcome(&mut self, outcome: Option<ChessOutcome>) {
         debug!("Outcome: {:?}", outcome);
 
-        if let Some(outcome) = outcome {
-            if let ChessOutcome::Checkmate { winner } = outcome {
-                if self.is_game_over {
-                    return; // This is not new
-                }
-
-                let looser = match winner {
-                    Player::Black => "White",
-                    Player::White => "Black",
-                };
-                self.show_bottom_game_info(
-                    GameBottomInfo::GameEnded(format!("{} is checkmated!", looser)),
-                    None,
-                    None,
-                );
-                self.is_game_over = true;
-            } else if let ChessOutcome::Stalemate = outcome {
+        match outcome {
+            // Leaving the game isn't shown as its end
+            Some(ChessOutcome::Aborted { .. }) => {}
+            Some(outcome) => {
                 if self.is_game_over {
                     return; // This is not new
                 }
-
                 self.show_bottom_game_info(
-                    GameBottomInfo::GameEnded("Stalemate!".to_owned()),
+                    GameBottomInfo::GameEnded(outcome.description()),
                     None,
                     None,
                 );
                 self.is_game_over = true;
             }
-        } else if self.is_game_over {
-            // Probably undone a move. Is not gameover anymore
-            self.is_game_over = false;
+            None if self.is_game_over => {
+                // Probably undone a move. Is not gameover anymore
+                self.is_game_over = false;
+            }
+            None => {}
         }
     }
 
@@ -374,6 +1151,11 @@ impl GameScene {
     fn draw_board(&mut self, canvas: &mut Canvas) -> Vec<mxcfb_rect> {
         let start = SystemTime::now();
         let mut updated_regions = vec![];
+        let control = if self.show_heatmap {
+            Some(square_control(&self.board))
+        } else {
+            None
+        };
         for x in 0..8 {
             for y in 0..8 {
                 let square = to_square(x, y); // Flip board so white is at the bottom
@@ -386,31 +1168,20 @@ impl GameScene {
                 //
                 let is_bright_bg = x % 2 == y % 2;
                 let bounds = &self.piece_hitboxes[x][y];
-                canvas.fill_rect(
-                    Point2 {
-                        x: Some(bounds.left as i32),
-                        y: Some(bounds.top as i32),
-                    },
-                    self.piece_hitboxes[x][y].size().cast().unwrap(),
-                    if is_bright_bg {
-                        color::GRAY(100)
-                    } else {
-                        color::GRAY(50)
-                    },
-                );
+                draw_square_background(canvas, bounds, is_bright_bg, self.board_theme);
 
                 //
                 // Underlay / Background layers
                 //
                 // Also highlight squares from previous move
-                if self.last_move_from.is_some() && self.last_move_from.unwrap() == square {
+                if self.highlight_last_move && self.last_move_from == Some(square) {
                     canvas.draw_image(
                         bounds.top_left().cast().unwrap(),
                         &self.img_piece_moved_from,
                         true,
                     );
                 }
-                if self.last_move_to.is_some() && self.last_move_to.unwrap() == square {
+                if self.highlight_last_move && self.last_move_to == Some(square) {
                     canvas.draw_image(
                         bounds.top_left().cast().unwrap(),
                         &self.img_piece_moved_to,
@@ -441,6 +1212,46 @@ impl GameScene {
                     );
                 }
 
+                //
+                // Check
+                //
+                let check_border = if !self.highlight_check {
+                    None
+                } else if self.checked_king == Some(square) {
+                    Some(8)
+                } else if self.checkers.contains(&square) {
+                    Some(3)
+                } else {
+                    None
+                };
+                if let Some(border) = check_border {
+                    canvas.draw_rect(
+                        Point2 {
+                            x: Some((bounds.left + self.overlay_padding) as i32),
+                            y: Some((bounds.top + self.overlay_padding) as i32),
+                        },
+                        Vector2 {
+                            x: bounds.width - self.overlay_padding * 2,
+                            y: bounds.height - self.overlay_padding * 2,
+                        },
+                        border,
+                    );
+                }
+
+                //
+                // Square control heatmap
+                //
+                if let Some(ref control) = control {
+                    draw_square_control(canvas, bounds, control[square.0 as usize]);
+                }
+
+                //
+                // Coordinates
+                //
+                if self.show_coordinates {
+                    draw_square_coordinates(canvas, bounds, square, self.board_flipped);
+                }
+
                 //
                 // Overlay
                 //
@@ -460,22 +1271,50 @@ impl GameScene {
                 }
 
                 // Display postions a selected chess piece could move to
+                // (a dot on empty squares and the corners on captures)
                 if self.move_hints.contains(&square) {
-                    canvas.draw_image(
-                        Point2 {
-                            x: (bounds.left + self.overlay_padding) as i32,
-                            y: (bounds.top + self.overlay_padding) as i32,
-                        },
-                        &self.img_piece_movehint,
-                        true,
-                    );
+                    if !self.capture_hints.contains(&square) {
+                        canvas.fill_circle(
+                            Point2 {
+                                x: (bounds.left + bounds.width / 2) as i32,
+                                y: (bounds.top + bounds.height / 2) as i32,
+                            },
+                            bounds.width / 8,
+                            color::BLACK,
+                        );
+                    } else {
+                        canvas.draw_image(
+                            Point2 {
+                                x: (bounds.left + self.overlay_padding) as i32,
+                                y: (bounds.top + self.overlay_padding) as i32,
+                            },
+                            &self.img_piece_movehint,
+                            true,
+                        );
+                    }
                 }
 
                 updated_regions.push(bounds.clone());
             }
         }
 
-        if self.redraw_all_squares || !CLI_OPTS.no_merge {
+        if !updated_regions.is_empty() {
+            draw_board_border(canvas, self.full_board_rect(), self.board_border);
+        }
+
+        let has_pen_annotations = self.pen_annotations.draw(canvas, &self.piece_hitboxes);
+        let has_coach_arrows = self.draw_coach_arrows(canvas);
+        let has_threats = self.draw_threats(canvas);
+        if has_pen_annotations || has_coach_arrows || has_threats {
+            // Arrows can span many squares
+            updated_regions.clear();
+            updated_regions.push(self.full_board_rect());
+        }
+
+        if self.redraw_all_squares
+            || (!crate::config::no_merge()
+                && updated_regions.len() > crate::config::max_separate_square_refreshes())
+        {
             // Update full board instead of every single position
             updated_regions.clear();
             updated_regions.push(self.full_board_rect());
@@ -500,85 +1339,1216 @@ impl GameScene {
         updated_regions
     }
 
+    /// Draws the pieces in hand of both players above and below the board
+    fn draw_pockets(&mut self, canvas: &mut Canvas) -> Vec<mxcfb_rect> {
+        self.pocket_hitboxes.clear();
+        let pockets = match self.pockets {
+            Some(ref pockets) => pockets.clone(),
+            None => return vec![],
+        };
+
+        let mut updated_regions = vec![];
+        for player in &[Player::Black, Player::White] {
+            let pocket_area = if (*player == Player::White) != self.board_flipped {
+                self.layout.pockets_below
+            } else {
+                self.layout.pockets_above
+            };
+            let top = pocket_area.top;
+            let area = canvas.fill_rect(
+                Point2 {
+                    x: Some(pocket_area.left as i32),
+                    y: Some(top as i32),
+                },
+                Vector2 {
+                    x: pocket_area.width,
+                    y: pocket_area.height,
+                },
+                color::WHITE,
+            );
+            for (i, piece) in POCKET_PIECES.iter().enumerate() {
+                let count = pockets.count(*player, *piece);
+                if count == 0 {
+                    continue;
+                }
+                let left = pocket_area.left + 10 + i as u32 * self.layout.pocket_spacing;
+                canvas.draw_image(
+                    Point2 {
+                        x: left as i32,
+                        y: top as i32,
+                    },
+                    self.img_pocket_pieces
+                        .get(&piece.fen_char(*player))
+                        .expect("Failed to find resized pocket piece img!"),
+                    true,
+                );
+                canvas.draw_text(
+                    Point2 {
+                        x: Some(left as i32 + self.layout.pocket_count.x),
+                        y: Some(top as i32 + self.layout.pocket_count.y),
+                    },
+                    &format!("x{}", count),
+                    POCKET_COUNT_FONT_SIZE,
+                );
+                let hitbox = mxcfb_rect {
+                    left,
+                    top,
+                    width: (POCKET_PIECE_SIZE * 2).min(self.layout.pocket_spacing),
+                    height: pocket_area.height,
+                };
+                if self.selected_drop == Some(*piece) && Player::from(self.board.turn()) == *player
+                {
+                    canvas.draw_rect(
+                        Point2 {
+                            x: Some(hitbox.left as i32 - 5),
+                            y: Some(hitbox.top as i32 - 5),
+                        },
+                        Vector2 {
+                            x: hitbox.width + 10,
+                            y: hitbox.height + 10,
+                        },
+                        3,
+                    );
+                }
+                self.pocket_hitboxes.push((*player, *piece, hitbox));
+            }
+            updated_regions.push(area);
+        }
+        updated_regions
+    }
+
     fn full_board_rect(&self) -> mxcfb_rect {
-        let left = self.piece_hitboxes[0][7].left;
-        let top = self.piece_hitboxes[0][7].top;
-        let right = self.piece_hitboxes[7][0].left + self.piece_hitboxes[7][0].width;
-        let bottom = self.piece_hitboxes[7][0].top + self.piece_hitboxes[7][0].height;
-        mxcfb_rect {
-            left,
-            top,
-            width: right - left,
-            height: bottom - top,
+        self.layout.board
+    }
+
+    /// Toggle viewing the board from black's side and remember it
+    fn flip_board(&mut self) {
+        self.set_board_flipped(!self.board_flipped);
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        settings.board_flipped = self.board_flipped;
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+    }
+
+    fn set_board_flipped(&mut self, flipped: bool) {
+        self.board_flipped = flipped;
+        self.piece_hitboxes = board_hitboxes(self.layout.board, self.board_flipped);
+        self.redraw_all_squares = true;
+        self.redraw_pockets = true;
+        self.clock_above.invalidate();
+        self.clock_below.invalidate();
+    }
+
+    /// Black's clock is above the board unless it's flipped
+    fn draw_clocks(&mut self, canvas: &mut Canvas) {
+        if let Some((snapshot, received)) = self.clock {
+            let elapsed = received.elapsed();
+            let (above, below) = if self.board_flipped {
+                (Player::White, Player::Black)
+            } else {
+                (Player::Black, Player::White)
+            };
+            self.clock_above.draw(
+                canvas,
+                snapshot.remaining(above, elapsed),
+                snapshot.running == Some(above),
+                snapshot.delay_left(elapsed),
+            );
+            self.clock_below.draw(
+                canvas,
+                snapshot.remaining(below, elapsed),
+                snapshot.running == Some(below),
+                snapshot.delay_left(elapsed),
+            );
+        }
+    }
+
+    fn draw_pass_tablet(&mut self, canvas: &mut Canvas, player: Player) {
+        canvas.clear();
+        let middle = canvas.size().y as i32 / 2;
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(middle),
+            },
+            &format!("Pass the tablet to {}", player),
+            100.0,
+        );
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(middle + 150),
+            },
+            "(Tap to continue)",
+            50.0,
+        );
+        canvas.update_full();
+        self.pass_tablet_shown_at = Some(SystemTime::now());
+    }
+
+    /// Show the board again (from the side of the player to move with auto flip)
+    fn end_pass_tablet(&mut self) {
+        if let Some(player) = self.pass_tablet_to.take() {
+            self.pass_tablet_shown_at = None;
+            if self.auto_flip {
+                self.set_board_flipped(player == Player::Black);
+            }
+            self.draw_game_bottom_info_last_rect = None;
+            self.first_draw = true;
+        }
+    }
+
+    fn clear_move_hints(&mut self) {
+        for last_move_hint in &self.move_hints {
+            self.redraw_squares.insert(last_move_hint.clone());
+        }
+        self.move_hints.clear();
+        self.capture_hints.clear();
+    }
+
+    /// Preview the moves of the piece below the hovering pen (unless something is selected)
+    fn on_pen_hover(&mut self, square: Option<Square>) {
+        if square == self.hovered_square {
+            return;
+        }
+        self.hovered_square = square;
+        if self.selected_square.is_some()
+            || self.selected_drop.is_some()
+            || self.preview_return.is_some()
+        {
+            return;
+        }
+        match square {
+            Some(square)
+                if self
+                    .possible_moves
+                    .iter()
+                    .any(|possible_move| possible_move.source == square) =>
+            {
+                self.set_move_hints(square)
+            }
+            _ => self.clear_move_hints(),
+        }
+    }
+
+    fn square_at(&self, x: f32, y: f32) -> Option<Square> {
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let pos = Point2 {
+            x: x as u16,
+            y: y as u16,
+        };
+        for x in 0..8 {
+            for y in 0..8 {
+                if Canvas::is_hitting(pos, self.piece_hitboxes[x][y]) {
+                    return Some(to_square(x, y));
+                }
+            }
+        }
+        None
+    }
+
+    fn set_drop_hints(&mut self, piece: DropPiece) {
+        self.clear_move_hints();
+        if !self.game_mode.move_hints_allowed() {
+            return;
+        }
+
+        for (possible_piece, dest) in self.possible_drops.iter() {
+            if *possible_piece == piece {
+                self.move_hints.insert(*dest);
+                self.redraw_squares.insert(*dest);
+            }
+        }
+    }
+
+    fn deselect_drop(&mut self) {
+        if self.selected_drop.take().is_some() {
+            self.redraw_pockets = true;
+        }
+    }
+
+    fn set_move_hints(&mut self, square: Square) {
+        self.clear_move_hints();
+        if !self.game_mode.move_hints_allowed() {
+            return;
+        }
+
+        for possible_move in self.possible_moves.iter() {
+            if possible_move.source == square {
+                self.move_hints.insert(possible_move.destination);
+                if possible_move.capture {
+                    self.capture_hints.insert(possible_move.destination);
+                }
+                self.redraw_squares.insert(possible_move.destination);
+            }
+        }
+    }
+
+    /// Move a piece. The player is asked what a pawn on the last rank becomes
+    /// (unless always promoting to a queen).
+    fn on_user_move(&mut self, src: Square, dest: Square) {
+        let is_promotion = self.possible_moves.iter().any(|possible_move| {
+            possible_move.source == src
+                && possible_move.destination == dest
+                && possible_move.promotion
+        });
+        if !is_promotion {
+            self.send_move(src, dest, None);
+        } else if crate::SETTINGS.lock().unwrap().always_queen {
+            self.send_move(src, dest, Some(PromotionPiece::Queen));
+        } else {
+            let mut options: Vec<&'static str> = ALL_PROMOTION_PIECES
+                .iter()
+                .map(|piece| piece.label())
+                .collect();
+            options.push("Cancel");
+            self.open_dialog(
+                DialogAction::Promotion {
+                    source: src,
+                    destination: dest,
+                },
+                Dialog::new("Promote the pawn to", &options),
+            );
+        }
+    }
+
+    fn send_move(&mut self, src: Square, dest: Square, promotion: Option<PromotionPiece>) {
+        self.on_user_turn(ChessRequest::MovePiece {
+            source: src,
+            destination: dest,
+            promotion,
+        });
+    }
+
+    /// Play the legal move that was written with the pen (if it can be read)
+    fn on_handwritten_move(&mut self, strokes: Vec<Stroke>) {
+        if self.is_game_over || self.preview_return.is_some() {
+            return;
+        }
+        let moves: Vec<(String, BitMove)> = self
+            .board
+            .generate_moves()
+            .iter()
+            .filter(|bit_move| {
+                self.possible_moves.iter().any(|possible_move| {
+                    possible_move.source == Square::from(bit_move.get_src())
+                        && possible_move.destination == Square::from(bit_move.get_dest())
+                })
+            })
+            .map(|bit_move| (san(&self.board, *bit_move), *bit_move))
+            .collect();
+        let candidates: Vec<String> = moves.iter().map(|(san, _)| san.clone()).collect();
+        let written = match handwriting::recognize_move(&strokes, &candidates) {
+            Some(written) => written,
+            None => {
+                self.show_bottom_game_info(
+                    GameBottomInfo::Error(
+                        "Couldn't read a legal move. Write the letters apart.".to_owned(),
+                    ),
+                    None,
+                    Some(Duration::from_secs(5)),
+                );
+                return;
+            }
+        };
+        let (_, bit_move) = moves.iter().find(|(san, _)| san == written).unwrap();
+        let promotion = if bit_move.is_promo() {
+            PromotionPiece::from_piece_type(bit_move.promo_piece())
+        } else {
+            None
+        };
+        let (src, dest) = (bit_move.get_src().into(), bit_move.get_dest().into());
+        self.show_bottom_game_info(
+            GameBottomInfo::Info(format!("Read {}", written)),
+            None,
+            Some(Duration::from_secs(3)),
+        );
+        if let Some(selected_square) = self.selected_square {
+            self.redraw_squares.insert(selected_square);
+        }
+        self.send_move(src, dest, promotion);
+    }
+
+    /// Remove the ink of the handwriting and redraw what was below it
+    fn clear_handwriting(&mut self, canvas: &mut Canvas) {
+        let area = self.layout.handwriting;
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(area.left as i32),
+                y: Some(area.top as i32),
+            },
+            Vector2 {
+                x: area.width,
+                y: area.height,
+            },
+            color::WHITE,
+        );
+        canvas.mark_dirty(&rect);
+        self.draw_game_bottom_info_last_rect = None;
+        self.redraw_pockets = true;
+        self.move_list.request_redraw();
+    }
+
+    /// Play this game as one board of a simul
+    pub fn in_simul(mut self) -> Self {
+        self.simul = true;
+        self
+    }
+
+    /// Show the board again after it was left to the simul overview
+    pub fn reopen(&mut self) {
+        self.back_button_pressed = false;
+        self.first_draw = true;
+    }
+
+    /// Save the game and end it (e.g. when the simul is left)
+    pub fn adjourn(&mut self) {
+        self.save_and_quit();
+        self.stop_game();
+    }
+
+    pub fn fen(&self) -> String {
+        self.board.fen()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.is_game_over
+    }
+
+    /// A local player is to move
+    pub fn waiting_for_local_player(&self) -> bool {
+        !self.is_game_over && self.is_local_user(self.board.turn().into())
+    }
+
+    /// Process the updates of the game. Called on every draw but also for
+    /// simul boards that aren't shown.
+    pub fn poll_updates(&mut self) {
+        if self.white_update_receiver.is_some() {
+            let mut update_receiver = self.white_update_receiver.take().unwrap();
+            self.handle_updates(Player::White, &mut update_receiver);
+            self.white_update_receiver = Some(update_receiver);
+        }
+        if self.black_update_receiver.is_some() {
+            let mut update_receiver = self.black_update_receiver.take().unwrap();
+            self.handle_updates(Player::Black, &mut update_receiver);
+            self.black_update_receiver = Some(update_receiver);
+        }
+        // After all updates, so the pockets and outcome belong to the move
+        if self.autosave_pending {
+            self.autosave_pending = false;
+            if self.simul {
+                self.write_savestate();
+            } else {
+                self.write_autosave();
+            }
+        }
+    }
+
+    /// Store the position in the savestate slot. Returns whether it worked.
+    fn write_savestate(&mut self) -> bool {
+        let fen = match self.pockets {
+            Some(ref pockets) => pockets.add_to_fen(&self.board.fen()),
+            None => self.board.fen(),
+        };
+        let clock = self.saved_clock();
+        let mut savesstates = crate::SAVESTATES.lock().unwrap();
+        savesstates.set(self.savestate_slot, fen, clock);
+        let result = crate::savestates::write(&savesstates);
+        drop(savesstates);
+        if let Err(err) = result {
+            error!("Failed to write savestates file!");
+            self.show_bottom_game_info(
+                GameBottomInfo::Error(format!("{}", err)),
+                None,
+                Some(Duration::from_secs(10)),
+            );
+            false
+        } else {
+            true
+        }
+    }
+
+    fn save_and_quit(&mut self) {
+        if self.write_savestate() {
+            info!("Saved game to selected savestate slot");
+            if min_think_time().is_some() {
+                info!(
+                    "Think time respected for {} of {} moves",
+                    self.think_time_stats.moves - self.think_time_stats.rushed_moves,
+                    self.think_time_stats.moves
+                );
+            }
+            if !self.simul {
+                self.write_autosave();
+                self.stop_game();
+            }
+            self.back_button_pressed = true;
+        }
+    }
+
+    /// Leaving shouldn't wait for the bot to finish thinking. The game
+    /// ends and its runtime is shut down in the background instead.
+    fn stop_game(&mut self) {
+        crate::web::clear_position();
+        // White disconnecting aborts the game (and with it the bot)
+        self.white_request_sender = None;
+        self.black_request_sender = None;
+        if let Some(runtime) = self.runtime.take() {
+            std::thread::spawn(move || drop(runtime));
+        }
+    }
+
+    fn spawn<F: Future<Output = ()> + Send + 'static>(&self, future: F) {
+        if let Some(ref runtime) = self.runtime {
+            runtime.spawn(future);
+        }
+    }
+
+    /// Remaining times as of now (`None` without a time control)
+    fn saved_clock(&self) -> Option<SavedClock> {
+        let time_control = self.time_control?;
+        let (snapshot, received) = self.clock?;
+        let elapsed = received.elapsed();
+        Some(SavedClock {
+            time_control,
+            times: ClockSnapshot {
+                white: snapshot.remaining(Player::White, elapsed),
+                black: snapshot.remaining(Player::Black, elapsed),
+                running: None,
+                delay: Duration::default(),
+            },
+        })
+    }
+
+    /// Replace the autosave with the current position. A finished game
+    /// isn't offered to be continued anymore.
+    fn write_autosave(&mut self) {
+        let result = if self.is_game_over {
+            autosave::clear()
+        } else {
+            self.autosave.fen = match self.pockets {
+                Some(ref pockets) => pockets.add_to_fen(&self.board.fen()),
+                None => self.board.fen(),
+            };
+            self.autosave.clock = self.saved_clock();
+            autosave::write(&self.autosave)
+        };
+        if let Err(err) = result {
+            error!("Failed to write autosave file: {:?}", err);
+        }
+    }
+
+    /// Keep the correspondence game up to date after every move
+    fn save_correspondence(&mut self, fen: &str) {
+        let is_game_over = self.is_game_over;
+        let game = match self.correspondence {
+            Some(ref mut game) if game.fen != fen || game.finished != is_game_over => game,
+            _ => return,
+        };
+        if game.fen != fen {
+            game.fen = fen.to_owned();
+            game.last_move_at = correspondence::now();
+        }
+        game.finished = is_game_over;
+        if let Err(err) = correspondence::write(game) {
+            error!("Failed to write correspondence game: {:?}", err);
+            self.show_bottom_game_info(
+                GameBottomInfo::Error(format!("Failed to save the game: {}", err)),
+                None,
+                Some(Duration::from_secs(10)),
+            );
+        }
+    }
+
+    /// Take back the last move of the local player (or last two moves against a bot)
+    fn undo(&mut self) {
+        let undo_count: u16 = if !self.game_mode.has_bot() {
+            1
+        } else {
+            if let Player::Black = self.board.turn().into() {
+                1
+            } else {
+                2
+            }
+        };
+        let sender = if self.is_game_over {
+            // Find any player to send the event on
+            if let Some(ref sender) = self.black_request_sender {
+                Some(sender.clone())
+            } else {
+                if let Some(ref sender) = self.white_request_sender {
+                    Some(sender.clone())
+                } else {
+                    None
+                }
+            }
+        } else {
+            // Only undo when own turn
+            match self.board.turn().into() {
+                Player::Black => self.black_request_sender.clone(),
+                Player::White => self.white_request_sender.clone(),
+            }
+        };
+        if sender.is_none() {
+            error!("Undo failed because it cant be sent (not any local players turn).");
+            self.show_bottom_game_info(
+                GameBottomInfo::Info("You can't undo right now.".to_owned()),
+                None,
+                Some(Duration::from_secs(3)),
+            );
+        } else {
+            let mut sender = sender.unwrap();
+            self.spawn(async move {
+                sender
+                    .send(ChessRequest::UndoMoves { moves: undo_count })
+                    .await
+                    .ok();
+            });
+        }
+    }
+
+    fn on_gesture(&mut self, gesture: Gesture) {
+        debug!("Gesture: {:?}", gesture);
+        match gesture.action() {
+            GestureAction::Nothing => {}
+            GestureAction::Undo => self.undo(),
+            GestureAction::Redo => self.show_bottom_game_info(
+                GameBottomInfo::Info("Moves can only be redone in replays.".to_owned()),
+                None,
+                Some(Duration::from_secs(3)),
+            ),
+            GestureAction::Flip => self.flip_board(),
+            GestureAction::Menu => self.open_menu(),
+            GestureAction::Refresh => self.force_full_refresh = Some(SystemTime::now()),
+            GestureAction::Adjourn => self.save_and_quit(),
+        }
+    }
+
+    fn open_dialog(&mut self, action: DialogAction, dialog: Dialog) {
+        self.dialog_action = Some(action);
+        self.dialog = Some(dialog);
+        self.dialog_drawn = false;
+    }
+
+    fn open_menu(&mut self) {
+        let options: &[&'static str] = if self.game_mode == GameMode::Analysis {
+            &[
+                "Undo", "Flip", "Image", "End", "Quit", "QR code", "Heatmap", "Threats", "Close",
+            ]
+        } else if self.game_mode.move_hints_allowed() {
+            &[
+                "Undo", "Flip", "Image", "End", "Quit", "QR code", "Threats", "Close",
+            ]
+        } else {
+            &["Undo", "Flip", "Image", "End", "Quit", "QR code", "Close"]
+        };
+        self.open_dialog(
+            DialogAction::Menu,
+            Dialog::new("Game menu (quitting saves the game)", options),
+        );
+    }
+
+    fn open_share_dialog(&mut self) {
+        self.open_dialog(
+            DialogAction::Share,
+            Dialog::new(
+                "Show as QR code",
+                &["Position (FEN)", "Moves (PGN)", "Cancel"],
+            ),
+        );
+    }
+
+    fn show_qr_code(&mut self, share_moves: bool) {
+        self.qr_code = if share_moves {
+            self.move_list
+                .compact_pgn()
+                .map(|pgn| ("Moves of the game", pgn))
+        } else {
+            let fen = match self.pockets {
+                Some(ref pockets) => pockets.add_to_fen(&self.board.fen()),
+                None => self.board.fen(),
+            };
+            Some(("Position", fen))
+        };
+        self.qr_code_drawn = false;
+    }
+
+    fn close_qr_code(&mut self) {
+        self.qr_code = None;
+        self.draw_game_bottom_info_last_rect = None;
+        self.first_draw = true;
+    }
+
+    /// Show how often each side attacks every square
+    fn toggle_heatmap(&mut self) {
+        self.show_heatmap = !self.show_heatmap;
+        self.redraw_all_squares = true;
+    }
+
+    fn toggle_threats(&mut self) {
+        self.show_threats = !self.show_threats;
+        self.threats = None;
+        self.redraw_all_squares = true;
+    }
+
+    /// Dashed arrows of the threats (searched once per position). Returns
+    /// false if there are none.
+    fn draw_threats(&mut self, canvas: &mut Canvas) -> bool {
+        if !self.show_threats {
+            return false;
+        }
+        if self.threats.is_none() {
+            let threats = if self.is_local_user(self.board.turn().into()) {
+                threats(&self.board)
+            } else {
+                vec![]
+            };
+            self.threats = Some(threats);
+        }
+        let threats = self.threats.as_deref().unwrap_or_default();
+        for (source, destination) in threats {
+            draw_dashed_arrow(canvas, &self.piece_hitboxes, *source, *destination);
+        }
+        !threats.is_empty()
+    }
+
+    /// Bots don't accept draws, so they are only offered to other players
+    fn open_end_game_dialog(&mut self) {
+        if self.is_game_over {
+            return;
+        }
+        let options: &[&'static str] = if self.game_mode.has_bot() {
+            &["Resign", "Cancel"]
+        } else {
+            &["Resign", "Offer draw", "Cancel"]
+        };
+        self.open_dialog(
+            DialogAction::EndGame,
+            Dialog::new("End the game for the player to move?", options),
+        );
+    }
+
+    /// Sent for the local player to move (or the only local player)
+    fn send_local_request(&mut self, request: ChessRequest) {
+        let sender = match self.board.turn().into() {
+            Player::White => self.white_request_sender.clone(),
+            Player::Black => self.black_request_sender.clone(),
+        }
+        .or_else(|| self.white_request_sender.clone())
+        .or_else(|| self.black_request_sender.clone());
+        if let Some(mut sender) = sender {
+            self.spawn(async move {
+                sender.send(request).await.ok();
+            });
+        }
+    }
+
+    fn close_dialog(&mut self) -> Option<DialogAction> {
+        self.dialog = None;
+        // Redraw everything below the dialog
+        self.first_draw = true;
+        self.redraw_pockets = true;
+        let action = self.dialog_action.take();
+        if let Some(DialogAction::Promotion { .. }) = action {
+            self.selected_square = None;
+            self.clear_move_hints();
+        }
+        action
+    }
+
+    fn on_dialog_choice(&mut self, choice: usize) {
+        match self.close_dialog() {
+            Some(DialogAction::Menu) => match choice {
+                0 => self.undo(),
+                1 => self.flip_board(),
+                // Saved once the board is shown without the dialog again
+                2 => self.save_image_requested = true,
+                3 => self.open_end_game_dialog(),
+                4 => self.save_and_quit(),
+                5 => self.open_share_dialog(),
+                6 if self.game_mode == GameMode::Analysis => self.toggle_heatmap(),
+                7 if self.game_mode == GameMode::Analysis => self.toggle_threats(),
+                6 if self.game_mode.move_hints_allowed() => self.toggle_threats(),
+                _ => {}
+            },
+            Some(DialogAction::Share) => match choice {
+                0 => self.show_qr_code(false),
+                1 => self.show_qr_code(true),
+                _ => {}
+            },
+            Some(DialogAction::EndGame) => match choice {
+                0 => self.send_local_request(ChessRequest::Resign),
+                1 if !self.game_mode.has_bot() => self.send_local_request(ChessRequest::OfferDraw),
+                _ => {}
+            },
+            Some(DialogAction::Promotion {
+                source,
+                destination,
+            }) => {
+                if let Some(piece) = ALL_PROMOTION_PIECES.get(choice) {
+                    self.send_move(source, destination, Some(*piece));
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn on_user_drop(&mut self, piece: DropPiece, dest: Square) {
+        self.on_user_turn(ChessRequest::DropPiece {
+            piece,
+            destination: dest,
+        });
+    }
+
+    /// Send a move or drop of the player to move (once confirmed if enabled)
+    fn on_user_turn(&mut self, request: ChessRequest) {
+        if let Some(remaining) = self.remaining_think_time() {
+            // Keep the selection so the move can be done once allowed
+            self.thinking_rushed = true;
+            self.show_bottom_game_info(
+                GameBottomInfo::Error(format!(
+                    "Keep thinking for another {}s before moving",
+                    remaining.as_secs() + 1
+                )),
+                None,
+                Some(Duration::from_secs(3)),
+            );
+            return;
+        }
+
+        self.selected_square = None;
+        self.finger_down_square = None;
+        self.deselect_drop();
+        self.clear_move_hints();
+
+        if !self.is_local_user(self.board.turn().into()) {
+            self.show_bottom_game_info(
+                GameBottomInfo::Error(format!("You can't move {}", self.board.turn())),
+                None,
+                Some(Duration::from_secs(10)),
+            );
+            return;
+        }
+        if crate::SETTINGS.lock().unwrap().confirm_moves {
+            self.show_unconfirmed_turn(request);
+        } else {
+            self.submit_turn(request);
+        }
+    }
+
+    /// Show the move (or drop) on the board until it's confirmed or cancelled
+    fn show_unconfirmed_turn(&mut self, request: ChessRequest) {
+        self.unconfirmed_turn = Some((
+            request.clone(),
+            self.board.shallow_clone(),
+            self.last_move_from,
+            self.last_move_to,
+        ));
+        self.confirm_buttons_drawn = false;
+        match request {
+            ChessRequest::MovePiece {
+                source,
+                destination,
+                promotion,
+            } => {
+                let promotion = promotion.unwrap_or_default().piece_type();
+                let bit_move = self
+                    .board
+                    .generate_moves()
+                    .iter()
+                    .copied()
+                    .find(|bit_move| {
+                        Square::from(bit_move.get_src()) == source
+                            && Square::from(bit_move.get_dest()) == destination
+                            && (!bit_move.is_promo() || bit_move.promo_piece() == promotion)
+                    });
+                if let Some(bit_move) = bit_move {
+                    let mut board = self.board.shallow_clone();
+                    board.apply_move(bit_move);
+                    self.update_board(&board.fen());
+                }
+                self.set_last_moved_hints(Some(source), destination);
+            }
+            ChessRequest::DropPiece { destination, .. } => {
+                self.set_last_moved_hints(None, destination)
+            }
+            _ => {}
+        }
+    }
+
+    /// Return to the board before the unconfirmed turn. Returns the turn.
+    fn take_unconfirmed_turn(&mut self) -> Option<ChessRequest> {
+        let (request, board, last_move_from, last_move_to) = self.unconfirmed_turn.take()?;
+        self.update_board(&board.fen());
+        match last_move_to {
+            Some(destination) => self.set_last_moved_hints(last_move_from, destination),
+            None => self.clear_last_moved_hints(),
+        }
+        self.confirm_button_hitbox = None;
+        self.cancel_button_hitbox = None;
+        self.move_list.request_redraw();
+        Some(request)
+    }
+
+    fn confirm_turn(&mut self) {
+        if let Some(request) = self.take_unconfirmed_turn() {
+            self.submit_turn(request);
+        }
+    }
+
+    fn draw_confirm_buttons(&mut self, canvas: &mut Canvas) {
+        // Shown instead of the move list
+        let area = self.layout.move_list;
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(area.left as i32),
+                y: Some(area.top as i32),
+            },
+            Vector2 {
+                x: area.width,
+                y: area.height,
+            },
+            color::WHITE,
+        );
+        let baseline = (area.top + area.height - 15) as i32;
+        self.confirm_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(self.layout.confirm_left),
+                y: Some(baseline),
+            },
+            "Confirm",
+            self.layout.confirm_font_size,
+            5,
+            20,
+        ));
+        self.cancel_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(self.layout.cancel_left),
+                y: Some(baseline),
+            },
+            "Cancel",
+            self.layout.confirm_font_size,
+            5,
+            20,
+        ));
+        canvas.mark_dirty(&rect);
+        self.confirm_buttons_drawn = true;
+    }
+
+    /// Send a move or drop of the player to move
+    fn submit_turn(&mut self, request: ChessRequest) {
+        if self.thinking_since.take().is_some() {
+            self.think_time_stats.moves += 1;
+            if self.thinking_rushed {
+                self.think_time_stats.rushed_moves += 1;
+            }
+            debug!("Think time stats: {:?}", self.think_time_stats);
+        }
+
+        let sender = match self.board.turn().into() {
+            Player::Black => self.black_request_sender.clone(),
+            Player::White => self.white_request_sender.clone(),
+        };
+        let other_player = self.board.turn().other_player();
+        let mut sender = match sender {
+            Some(sender) => sender,
+            None => return,
+        };
+        self.spawn(async move {
+            sender.send(request).await.ok();
+        });
+
+        if !self.is_local_user(other_player.into()) {
+            self.show_bottom_game_info(
+                GameBottomInfo::Info("Waiting on your opponent...".to_owned()),
+                Some(bot_reaction_delay() + Duration::from_millis(100)),
+                Some(Duration::from_millis(100)),
+            );
+        }
+    }
+
+    fn clear_last_moved_hints(&mut self) {
+        for last_move_hint in self.last_move_from.iter().chain(self.last_move_to.iter()) {
+            self.redraw_squares.insert(last_move_hint.clone());
+        }
+        self.last_move_from = None;
+        self.last_move_to = None;
+    }
+
+    /// Mark the squares of the last move (of any player). Dropped pieces have no source.
+    fn set_last_moved_hints(&mut self, source: Option<Square>, destination: Square) {
+        self.clear_last_moved_hints();
+        self.last_move_from = source;
+        self.last_move_to = Some(destination);
+        for square in source.iter().chain(std::iter::once(&destination)) {
+            self.redraw_squares.insert(*square);
+        }
+    }
+
+    /// Mark the king of the player to move and its attackers. Needs the updated board.
+    fn set_check_hints(&mut self, in_check: bool) {
+        for square in self.checked_king.iter().chain(self.checkers.iter()) {
+            self.redraw_squares.insert(*square);
+        }
+        if in_check {
+            self.checked_king = Some(Square::from(self.board.king_sq(self.board.turn())));
+            self.checkers = self
+                .board
+                .checkers()
+                .into_iter()
+                .map(Square::from)
+                .collect();
+        } else {
+            self.checked_king = None;
+            self.checkers.clear();
+        }
+        for square in self.checked_king.iter().chain(self.checkers.iter()) {
+            self.redraw_squares.insert(*square);
         }
     }
 
-    fn clear_move_hints(&mut self) {
-        for last_move_hint in &self.move_hints {
-            self.redraw_squares.insert(last_move_hint.clone());
+    /// Remember the SAN of a move on the current board until the board after it arrives.
+    /// `sent_san` is the notation sent along with the move. Without it the
+    /// candidates are told apart by the position after them.
+    fn add_pending_move(&mut self, source: Square, destination: Square, sent_san: Option<String>) {
+        if let Some(san) = sent_san {
+            let entry = MoveListEntry {
+                san,
+                fen: String::new(),
+                source: Some(source),
+                destination,
+            };
+            self.pending_move_list_entries = vec![(entry, None)];
+            return;
         }
-        self.move_hints.clear();
+        self.pending_move_list_entries = self
+            .board
+            .generate_moves()
+            .iter()
+            .filter(|bit_move| {
+                Square::from(bit_move.get_src()) == source
+                    && Square::from(bit_move.get_dest()) == destination
+            })
+            .map(|bit_move| {
+                let mut board_after = self.board.shallow_clone();
+                board_after.apply_move(*bit_move);
+                let entry = MoveListEntry {
+                    san: san(&self.board, *bit_move),
+                    fen: String::new(),
+                    source: Some(source),
+                    destination,
+                };
+                (entry, Some(board_placement(&board_after.fen()).to_owned()))
+            })
+            .collect();
     }
 
-    fn set_move_hints(&mut self, square: Square) {
-        self.clear_move_hints();
+    fn add_pending_drop(&mut self, player: Player, piece: DropPiece, destination: Square) {
+        if self.board.piece_at_sq(*destination) != Piece::None {
+            return; // Already on the board
+        }
+        let entry = MoveListEntry {
+            san: format!(
+                "{}@{}",
+                piece.fen_char(Player::White),
+                destination.to_string().to_lowercase()
+            ),
+            fen: String::new(),
+            source: None,
+            destination,
+        };
+        debug!("{} dropped {}", player, entry.san);
+        self.pending_move_list_entries = vec![(entry, None)];
+    }
 
-        for (src, dest) in self.possible_moves.iter() {
-            if *src == square {
-                self.move_hints.insert(*dest);
-                self.redraw_squares.insert(*dest);
-            }
+    /// Add the pending move that lead to `fen` to the move list or
+    /// remove undone moves from it
+    fn sync_move_list(&mut self, fen: &str) {
+        if !self.move_list.is_started() {
+            self.move_list.reset(fen);
+            return;
+        }
+        if self.move_list.last_fen() == Some(fen) {
+            return;
+        }
+        let placement = board_placement(fen);
+        let pending = std::mem::take(&mut self.pending_move_list_entries);
+        let played = pending
+            .into_iter()
+            .find(|(_, expected)| expected.as_deref().map_or(true, |p| p == placement));
+        if let Some((mut entry, _)) = played {
+            entry.fen = fen.to_owned();
+            self.move_list.push(entry);
+        } else if !self.move_list.truncate_to(fen) {
+            // Unknown position (e.g. the board was reset)
+            self.move_list.reset(fen);
         }
     }
 
-    fn on_user_move(&mut self, src: Square, dest: Square) {
-        self.selected_square = None;
-        self.finger_down_square = None;
+    /// Show the board after an earlier move. Nothing can be done until returning.
+    fn preview_move(&mut self, index: usize) {
+        let entry = match self.move_list.entry(index) {
+            Some(entry) => (entry.fen.clone(), entry.source, entry.destination),
+            None => return,
+        };
+        if self.preview_return.is_none() {
+            self.preview_return = Some((
+                self.board.shallow_clone(),
+                self.last_move_from,
+                self.last_move_to,
+            ));
+        }
+        if let Some(selected_square) = self.selected_square.take() {
+            self.redraw_squares.insert(selected_square);
+        }
+        self.deselect_drop();
         self.clear_move_hints();
-        self.clear_last_moved_hints();
 
-        let sender = match self.board.turn().into() {
-            Player::Black => self.black_request_sender.clone(),
-            Player::White => self.white_request_sender.clone(),
-        };
-        let other_player = self.board.turn().other_player();
+        let (fen, source, destination) = entry;
+        self.update_board(&fen);
+        self.set_last_moved_hints(source, destination);
+        self.set_check_hints(self.board.in_check());
+        self.move_list.set_previewed(Some(index));
+        self.show_bottom_game_info(
+            GameBottomInfo::Info("Previewing. Tap anywhere to return.".to_owned()),
+            None,
+            None,
+        );
+    }
 
-        if sender.is_none() {
+    fn end_preview(&mut self) {
+        if let Some((board, last_move_from, last_move_to)) = self.preview_return.take() {
+            self.update_board(&board.fen());
+            match last_move_to {
+                Some(destination) => self.set_last_moved_hints(last_move_from, destination),
+                None => self.clear_last_moved_hints(),
+            }
+            self.set_check_hints(self.board.in_check());
+            self.move_list.set_previewed(None);
             self.show_bottom_game_info(
-                GameBottomInfo::Error(format!("You can't move {}", self.board.turn())),
+                GameBottomInfo::Info("Back to the game.".to_owned()),
                 None,
-                Some(Duration::from_secs(10)),
+                Some(Duration::from_secs(2)),
             );
+        }
+    }
+
+    /// Keep the position if the local player is to move in it
+    fn coach_remember(&mut self, fen: &str) {
+        if !self.coach {
             return;
         }
-        let mut sender = sender.unwrap();
-        self.runtime.spawn(async move {
-            sender
-                .send(ChessRequest::MovePiece {
-                    source: src,
-                    destination: dest,
-                })
-                .await
+        match Board::from_fen(fen) {
+            Ok(board) if self.is_local_user(board.turn().into()) => {
+                self.coach_fen_before = Some(fen.to_owned())
+            }
+            _ => {}
+        }
+    }
+
+    /// Start reviewing the move that led from the remembered position to `fen`
+    fn start_coach_review(&mut self, fen: &str) {
+        let before = match self.coach_fen_before.take() {
+            Some(before) if self.coach => before,
+            _ => return,
+        };
+        self.clear_coach_mistake();
+        let before = match Board::from_fen(&before) {
+            Ok(board) => board,
+            Err(_) => return,
+        };
+        let played = match played_move(&before, fen) {
+            Some(played) => played,
+            None => return,
+        };
+        let played_san = san(&before, played);
+        let (review_tx, review_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mistake = review_move(&before, played);
+            review_tx
+                .send(mistake.map(|mistake| (mistake, played_san)))
                 .ok();
         });
+        self.coach_review = Some(review_rx);
+    }
 
-        if !self.is_local_user(other_player.into()) {
-            self.show_bottom_game_info(
-                GameBottomInfo::Info("Waiting on your opponent...".to_owned()),
-                Some(Duration::from_millis(
-                    (CLI_OPTS.bot_reaction_delay + 100) as u64,
-                )),
-                Some(Duration::from_millis(100)),
-            );
+    fn clear_coach_mistake(&mut self) {
+        self.coach_review = None;
+        self.coach_next_arrow_at = None;
+        if self.coach_mistake.take().is_some() {
+            self.redraw_all_squares = true;
+            self.clear_bottom_game_info();
         }
     }
 
-    fn clear_last_moved_hints(&mut self) {
-        for last_move_hint in self.last_move_from.iter().chain(self.last_move_to.iter()) {
-            self.redraw_squares.insert(last_move_hint.clone());
+    /// Show a finished review and play out the refutation arrow by arrow
+    fn poll_coach(&mut self) {
+        let received = match self.coach_review {
+            Some(ref review) => review.try_recv(),
+            None => Err(std::sync::mpsc::TryRecvError::Empty),
+        };
+        match received {
+            Ok(Some((mistake, played_san))) => {
+                info!("Coach found a mistake: {:?}", mistake);
+                self.show_bottom_game_info(
+                    GameBottomInfo::Coach(mistake.explanation(&played_san)),
+                    None,
+                    None,
+                );
+                self.coach_mistake = Some(mistake);
+                self.coach_arrows = 0;
+                self.coach_next_arrow_at = Some(SystemTime::now());
+                self.coach_review = None;
+            }
+            Ok(None) | Err(std::sync::mpsc::TryRecvError::Disconnected) => self.coach_review = None,
+            Err(std::sync::mpsc::TryRecvError::Empty) => {}
         }
-        self.last_move_from = None;
-        self.last_move_to = None;
+
+        let refutation_length = match self.coach_mistake {
+            Some(ref mistake) => mistake.refutation.len(),
+            None => return,
+        };
+        if let Some(next_arrow_at) = self.coach_next_arrow_at {
+            if next_arrow_at <= SystemTime::now() {
+                self.coach_arrows += 1;
+                self.redraw_all_squares = true;
+                self.coach_next_arrow_at = if self.coach_arrows < refutation_length {
+                    Some(next_arrow_at + COACH_ARROW_INTERVAL)
+                } else {
+                    None
+                };
+            }
+        }
+    }
+
+    /// Numbered arrows of the refutation shown so far. Returns false if
+    /// there are none.
+    fn draw_coach_arrows(&self, canvas: &mut Canvas) -> bool {
+        let mistake = match self.coach_mistake {
+            Some(ref mistake) => mistake,
+            None => return false,
+        };
+        let shown = mistake.refutation.iter().take(self.coach_arrows);
+        for (number, line_move) in shown.enumerate() {
+            let tip = draw_arrow(
+                canvas,
+                &self.piece_hitboxes,
+                line_move.source,
+                line_move.destination,
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: Some(tip.x + 15),
+                    y: Some(tip.y - 15),
+                },
+                &(number + 1).to_string(),
+                COACH_NUMBER_SIZE,
+            );
+        }
+        self.coach_arrows > 0
     }
 
     fn update_board(&mut self, fen: &str) {
@@ -586,6 +2556,7 @@ impl GameScene {
             debug!("Ignored unchanged board");
         }
         info!("Updated FEN: {}", fen);
+        crate::web::show_position(fen);
 
         let new_board = match Board::from_fen(fen) {
             Ok(board) => board,
@@ -607,6 +2578,14 @@ impl GameScene {
                 }
             }
         }
+        if self.show_heatmap {
+            // Moves change what far away squares are attacked by
+            self.redraw_all_squares = true;
+        }
+        if self.show_threats {
+            self.threats = None;
+            self.redraw_all_squares = true;
+        }
 
         self.board = new_board;
     }
@@ -620,11 +2599,59 @@ impl GameScene {
         }
     }
 
+    /// Who is thinking and how the game stands (if the eval is shown)
+    fn bot_vs_bot_message(&self, player: Player) -> Option<String> {
+        if !crate::SETTINGS.lock().unwrap().bot_vs_bot.show_eval {
+            return None;
+        }
+        Some(format!(
+            "{} is thinking. Eval: {:+.2}",
+            player,
+            static_eval(&self.board) as f32 / 100.0
+        ))
+    }
+
+    /// Live progress of a bot searching its move, instead of a frozen screen
+    fn show_thinking(&mut self, player: Player, depth: u16, eval: i32) {
+        if self.is_game_over || self.is_local_user(player) || self.coach_mistake.is_some() {
+            return;
+        }
+        let eval = eval as f32 / 100.0;
+        let message = if self.game_mode != GameMode::BotVsBot {
+            format!("Thinking... depth {}, eval {:+.1}", depth, eval)
+        } else if crate::SETTINGS.lock().unwrap().bot_vs_bot.show_eval {
+            format!(
+                "{} is thinking... depth {}, eval {:+.1}",
+                player, depth, eval
+            )
+        } else {
+            format!("{} is thinking... depth {}", player, depth)
+        };
+        self.show_bottom_game_info(GameBottomInfo::Info(message), None, None);
+    }
+
     fn handle_updates(&mut self, player: Player, update_receiver: &mut Receiver<ChessUpdate>) {
         for update in update_receiver.try_recv() {
+            // Doesn't change the board, so a preview or unconfirmed turn stays
+            if let ChessUpdate::Thinking {
+                player,
+                depth,
+                eval,
+                ..
+            } = update
+            {
+                self.show_thinking(player, depth, eval);
+                continue;
+            }
+            // Changes always show the current board
+            self.end_preview();
+            self.take_unconfirmed_turn();
             //debug!("Got update for {}: {:#?}", player, update);
             match update {
-                ChessUpdate::Board { ref fen } => self.update_board(fen),
+                ChessUpdate::Board { ref fen } => {
+                    self.update_board(fen);
+                    self.coach_remember(fen);
+                }
                 ChessUpdate::GenericErrorResponse { message } => {
                     warn!(
                         "Received a GenericErrorResponse for {}: {}",
@@ -658,24 +2685,34 @@ impl GameScene {
                     player,
                     moved_piece_source,
                     moved_piece_destination,
+                    san,
                 } => {
                     let is_local_user = self.is_local_user(player);
-                    if !is_local_user {
-                        // This player is not controlled by this frontend.
-                        // Either a bot or an remote opponent whoses move
-                        // should get marked.
-                        self.last_move_from = Some(moved_piece_source);
-                        self.last_move_to = Some(moved_piece_destination);
-                        self.redraw_squares.insert(moved_piece_source);
-                        self.redraw_squares.insert(moved_piece_destination);
-                    }
+                    self.set_last_moved_hints(Some(moved_piece_source), moved_piece_destination);
+                    self.add_pending_move(moved_piece_source, moved_piece_destination, san);
                     info!("{} (is_local_user: {}) made a move", player, is_local_user);
                 }
-                ChessUpdate::PlayerSwitch { player, ref fen } => {
+                ChessUpdate::PlayerSwitch {
+                    player,
+                    ref fen,
+                    in_check,
+                } => {
                     self.update_board(fen);
+                    self.set_check_hints(in_check);
+                    self.sync_move_list(fen);
+                    if self.is_local_user(player) {
+                        self.coach_remember(fen);
+                    } else {
+                        self.start_coach_review(fen);
+                    }
+                    self.save_correspondence(fen);
+                    self.autosave_pending = true;
                     // TODO: Better message depending on game mode
                     if !self.is_game_over {
-                        let message = if !self.is_local_user(player) {
+                        let message = if self.game_mode == GameMode::BotVsBot {
+                            self.bot_vs_bot_message(player)
+                        } else if !self.is_local_user(player) || self.coach_mistake.is_some() {
+                            // The explanation of the coach stays
                             None
                         } else {
                             if !self.is_local_user(player.other_player()) {
@@ -690,6 +2727,12 @@ impl GameScene {
                         }
 
                         if self.is_local_user(player) {
+                            self.start_thinking();
+                            let flip =
+                                self.auto_flip && self.board_flipped != (player == Player::Black);
+                            if flip || self.hide_board {
+                                self.pass_tablet_to = Some(player);
+                            }
                             if self.pvp_piece_rotation_enabled {
                                 // Rotate when local player black plays
                                 let should_rotate_pieces = player == Player::Black;
@@ -703,13 +2746,24 @@ impl GameScene {
                         }
                     }
                 }
-                ChessUpdate::MovesUndone { who, moves } => {
+                ChessUpdate::MovesUndone {
+                    who,
+                    moves,
+                    last_move,
+                } => {
+                    self.clear_coach_mistake();
+                    self.coach_fen_before = None;
                     self.show_bottom_game_info(
                         GameBottomInfo::Info(format!("{} undid {} move(s).", who, moves)),
                         None,
                         Some(Duration::from_secs(3)),
                     );
-                    self.clear_last_moved_hints();
+                    match last_move {
+                        Some((source, destination)) => {
+                            self.set_last_moved_hints(Some(source), destination)
+                        }
+                        None => self.clear_last_moved_hints(),
+                    }
                 }
                 ChessUpdate::UndoMovesFailedResponse { message } => self.show_bottom_game_info(
                     GameBottomInfo::Error(format!("Undo failed: {}", message)),
@@ -717,13 +2771,178 @@ impl GameScene {
                     Some(Duration::from_secs(10)),
                 ),
                 ChessUpdate::CurrentTotalMovesReponse { .. } => {}
+                ChessUpdate::CurrentVariantResponse { .. } => {}
+                // The scene gets all possible moves anyway
+                ChessUpdate::PossibleMovesFromResponse { .. } => {}
+                // Handled before the board is touched
+                ChessUpdate::Thinking { .. } => {}
+                ChessUpdate::PlayerDroppedAPiece {
+                    player,
+                    piece,
+                    destination,
+                } => {
+                    self.set_last_moved_hints(None, destination);
+                    self.add_pending_drop(player, piece, destination);
+                    info!("{} dropped a piece", player);
+                }
+                ChessUpdate::Pockets { pockets } => {
+                    self.pockets = Some(pockets);
+                    self.redraw_pockets = true;
+                }
+                ChessUpdate::PossibleDrops { possible_drops } => {
+                    self.possible_drops = possible_drops;
+                    if let Some(selected_drop) = self.selected_drop {
+                        self.set_drop_hints(selected_drop);
+                    }
+                }
+                ChessUpdate::DrawOffered { player } => self.show_bottom_game_info(
+                    GameBottomInfo::Info(format!(
+                        "{} offers a draw (accept with End > Offer draw).",
+                        player
+                    )),
+                    None,
+                    Some(Duration::from_secs(10)),
+                ),
+                ChessUpdate::Clock { clock } => self.clock = Some((clock, Instant::now())),
+                ChessUpdate::FullSync {
+                    ref fen,
+                    outcome,
+                    ref possible_moves,
+                    ref pockets,
+                    ref possible_drops,
+                    in_check,
+                    ..
+                } => {
+                    self.update_board(fen);
+                    self.set_check_hints(in_check);
+                    self.move_list.reset(fen);
+                    self.coach_remember(fen);
+                    self.possible_moves = possible_moves.clone();
+                    self.pockets = pockets.clone();
+                    self.possible_drops = possible_drops.clone();
+                    self.redraw_pockets = true;
+                    self.handle_outcome(outcome);
+                }
             }
         }
     }
 }
 
 impl Scene for GameScene {
+    fn orientation(&self) -> Orientation {
+        self.layout.orientation
+    }
+
     fn on_input(&mut self, event: InputEvent) {
+        let gesture = self.gestures.on_input(event.clone());
+        if self.pass_tablet_to.is_some() {
+            if let InputEvent::MultitouchEvent {
+                event: multitouch::MultitouchEvent::Release { .. },
+            } = event
+            {
+                self.end_pass_tablet();
+            }
+            return;
+        }
+        if self.qr_code.is_some() {
+            match event {
+                InputEvent::MultitouchEvent {
+                    event: multitouch::MultitouchEvent::Release { .. },
+                }
+                | InputEvent::GPIO {
+                    event: gpio::GPIOEvent::Press { .. },
+                } => self.close_qr_code(),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(ref mut dialog) = self.dialog {
+            if let Some(choice) = dialog.on_input(event.clone()) {
+                self.on_dialog_choice(choice);
+            } else if let InputEvent::GPIO {
+                event:
+                    gpio::GPIOEvent::Press {
+                        button: gpio::PhysicalButton::MIDDLE,
+                    },
+            } = event
+            {
+                self.close_dialog();
+            }
+            return;
+        }
+        if let InputEvent::GPIO {
+            event: gpio::GPIOEvent::Press { button },
+        } = event
+        {
+            match button {
+                gpio::PhysicalButton::LEFT => self.undo(),
+                gpio::PhysicalButton::MIDDLE => self.open_menu(),
+                gpio::PhysicalButton::RIGHT => self.flip_board(),
+                _ => {}
+            }
+            return;
+        }
+        if self.handwriting_pad.on_input(event.clone()) {
+            return;
+        }
+        if let InputEvent::WacomEvent { event } = event {
+            match event {
+                WacomEvent::Hover { position, .. } => {
+                    self.on_pen_hover(self.square_at(position.x, position.y))
+                }
+                WacomEvent::InstrumentChange { state: false, .. } => self.on_pen_hover(None),
+                _ => {}
+            }
+        }
+        if let Some(gesture) = gesture {
+            self.on_gesture(gesture);
+        }
+        if self.gestures.is_consuming() {
+            self.finger_down_square = None;
+            self.move_list.cancel_touch();
+            return;
+        }
+        if self
+            .pen_annotations
+            .on_input(event.clone(), &self.piece_hitboxes)
+        {
+            self.redraw_all_squares = true;
+        }
+        if self.unconfirmed_turn.is_some() {
+            if let InputEvent::MultitouchEvent {
+                event: multitouch::MultitouchEvent::Release { finger },
+            } = event
+            {
+                if self.confirm_button_hitbox.is_some()
+                    && Canvas::is_hitting(finger.pos, self.confirm_button_hitbox.unwrap())
+                {
+                    self.confirm_turn();
+                } else {
+                    // Tapping anywhere else picks another move
+                    self.take_unconfirmed_turn();
+                }
+            }
+            return;
+        }
+        let touching_move_list = self.move_list.is_touched();
+        let move_list_event = self.move_list.on_input(event.clone());
+        if touching_move_list || self.move_list.is_touched() {
+            match move_list_event {
+                Some(MoveListEvent::Preview(index)) => self.preview_move(index),
+                Some(MoveListEvent::Latest) => self.end_preview(),
+                None => {}
+            }
+            return;
+        }
+        if self.preview_return.is_some() {
+            if let InputEvent::MultitouchEvent {
+                event: multitouch::MultitouchEvent::Release { .. },
+            } = event
+            {
+                self.end_preview();
+            }
+            return;
+        }
         match event {
             InputEvent::MultitouchEvent { event } => {
                 // Taps and buttons
@@ -741,72 +2960,12 @@ impl Scene for GameScene {
                         if self.back_button_hitbox.is_some()
                             && Canvas::is_hitting(finger.pos, self.back_button_hitbox.unwrap())
                         {
-                            // Save game
-                            let fen = self.board.fen();
-                            let mut savesstates = crate::SAVESTATES.lock().unwrap();
-                            match self.savestate_slot {
-                                SavestateSlot::First => savesstates.slot_1 = Some(fen),
-                                SavestateSlot::Second => savesstates.slot_2 = Some(fen),
-                                SavestateSlot::Third => savesstates.slot_3 = Some(fen),
-                            }
-                            if let Err(err) = crate::savestates::write(&savesstates) {
-                                error!("Failed to write savestates file!");
-                                self.show_bottom_game_info(
-                                    GameBottomInfo::Error(format!("{}", err)),
-                                    None,
-                                    Some(Duration::from_secs(10)),
-                                );
-                            } else {
-                                info!("Saved game to selected savestate slot");
-                                self.back_button_pressed = true;
-                            }
+                            self.save_and_quit();
                         }
                         if self.undo_button_hitbox.is_some()
                             && Canvas::is_hitting(finger.pos, self.undo_button_hitbox.unwrap())
                         {
-                            let undo_count: u16 = if self.game_mode == GameMode::PvP {
-                                1
-                            } else {
-                                if let Player::Black = self.board.turn().into() {
-                                    1
-                                } else {
-                                    2
-                                }
-                            };
-                            let sender = if self.is_game_over {
-                                // Find any player to send the event on
-                                if let Some(ref sender) = self.black_request_sender {
-                                    Some(sender.clone())
-                                } else {
-                                    if let Some(ref sender) = self.white_request_sender {
-                                        Some(sender.clone())
-                                    } else {
-                                        None
-                                    }
-                                }
-                            } else {
-                                // Only undo when own turn
-                                match self.board.turn().into() {
-                                    Player::Black => self.black_request_sender.clone(),
-                                    Player::White => self.white_request_sender.clone(),
-                                }
-                            };
-                            if sender.is_none() {
-                                error!("Undo failed because it cant be sent (not any local players turn).");
-                                self.show_bottom_game_info(
-                                    GameBottomInfo::Info("You can't undo right now.".to_owned()),
-                                    None,
-                                    Some(Duration::from_secs(3)),
-                                );
-                            } else {
-                                let mut sender = sender.unwrap();
-                                self.runtime.spawn(async move {
-                                    sender
-                                        .send(ChessRequest::UndoMoves { moves: undo_count })
-                                        .await
-                                        .ok();
-                                });
-                            }
+                            self.undo();
                         }
                         if self.full_refresh_button_hitbox.is_some()
                             && Canvas::is_hitting(
@@ -815,6 +2974,50 @@ impl Scene for GameScene {
                             )
                         {
                             self.force_full_refresh = Some(SystemTime::now());
+                        } else if self.flip_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.flip_button_hitbox.unwrap())
+                        {
+                            self.flip_board();
+                        } else if let Some((player, piece)) = self
+                            .pocket_hitboxes
+                            .iter()
+                            .find(|(_, _, hitbox)| Canvas::is_hitting(finger.pos, *hitbox))
+                            .map(|(player, piece, _)| (*player, *piece))
+                        {
+                            // Select a piece to drop (or cancel the selection)
+                            if let Some(selected_square) = self.selected_square.take() {
+                                self.redraw_squares.insert(selected_square);
+                            }
+                            if self.is_game_over
+                                || Player::from(self.board.turn()) != player
+                                || self.selected_drop == Some(piece)
+                            {
+                                self.deselect_drop();
+                                self.clear_move_hints();
+                            } else {
+                                self.selected_drop = Some(piece);
+                                self.redraw_pockets = true;
+                                self.set_drop_hints(piece);
+                            }
+                        } else if let Some(piece) = self.selected_drop {
+                            let dest = (0..8)
+                                .flat_map(|x| (0..8).map(move |y| (x, y)))
+                                .find(|(x, y)| {
+                                    Canvas::is_hitting(finger.pos, self.piece_hitboxes[*x][*y])
+                                })
+                                .map(|(x, y)| to_square(x, y));
+                            let is_possible_drop = dest
+                                .map_or(false, |dest| self.possible_drops.contains(&(piece, dest)));
+                            match dest {
+                                Some(dest) if is_possible_drop && !self.is_game_over => {
+                                    self.redraw_squares.insert(dest);
+                                    self.on_user_drop(piece, dest);
+                                }
+                                _ => {
+                                    self.deselect_drop();
+                                    self.clear_move_hints();
+                                }
+                            }
                         } else if !self.is_game_over {
                             for x in 0..8 {
                                 for y in 0..8 {
@@ -835,9 +3038,10 @@ impl Scene for GameScene {
                                                 let is_possible_move = self
                                                     .possible_moves
                                                     .iter()
-                                                    .any(|(possible_src, possible_dest)| {
-                                                        possible_src == &last_selected_square
-                                                            && possible_dest == &new_square
+                                                    .any(|possible_move| {
+                                                        possible_move.source == last_selected_square
+                                                            && possible_move.destination
+                                                                == new_square
                                                     });
                                                 if is_possible_move {
                                                     // Move
@@ -897,50 +3101,67 @@ impl Scene for GameScene {
     }
 
     fn draw(&mut self, canvas: &mut Canvas) {
+        if let Some((title, ref data)) = self.qr_code {
+            if !self.qr_code_drawn {
+                self.qr_code_drawn = true;
+                if let Err(err) = draw_qr_screen(canvas, title, data) {
+                    warn!("Failed to show the QR code: {:?}", err);
+                    self.close_qr_code();
+                    self.show_bottom_game_info(
+                        GameBottomInfo::Error(format!("No QR code: {}", err)),
+                        None,
+                        Some(Duration::from_secs(3)),
+                    );
+                }
+            }
+            return;
+        }
         if self.first_draw {
             // First frame
             canvas.clear();
 
-            self.back_button_hitbox = Some(canvas.draw_button(
-                Point2 {
-                    x: Some(50),
-                    y: Some(90),
-                },
-                "Save & Quit",
-                75.0,
-                10,
-                20,
-            ));
-            self.undo_button_hitbox = Some(canvas.draw_button(
-                Point2 {
-                    x: Some(
-                        self.back_button_hitbox.unwrap().left as i32
-                            + self.back_button_hitbox.unwrap().width as i32
-                            + 50,
-                    ),
-                    y: Some(90),
+            let labels = [
+                if self.simul {
+                    "All boards"
+                } else {
+                    "Save & Quit"
                 },
                 "Undo",
-                75.0,
-                10,
-                20,
-            ));
-            self.full_refresh_button_hitbox = Some(canvas.draw_button(
-                Point2 {
-                    x: Some(
-                        self.undo_button_hitbox.unwrap().left as i32
-                            + self.undo_button_hitbox.unwrap().width as i32
-                            + 50,
-                    ),
-                    y: Some(90),
-                },
-                "Refresh Screen",
-                75.0,
-                10,
-                20,
-            ));
+                "Refresh",
+                "Flip",
+            ];
+            let mut hitboxes = vec![];
+            let mut pos = self.layout.buttons_start;
+            for (i, label) in labels.iter().enumerate() {
+                if i > 0 && i % self.layout.buttons_per_row == 0 {
+                    pos = Point2 {
+                        x: self.layout.buttons_start.x,
+                        y: pos.y + self.layout.button_row_height,
+                    };
+                }
+                let hitbox = canvas.draw_button(
+                    Point2 {
+                        x: Some(pos.x),
+                        y: Some(pos.y),
+                    },
+                    label,
+                    self.layout.button_font_size,
+                    10,
+                    20,
+                );
+                pos.x = (hitbox.left + hitbox.width) as i32 + self.layout.button_spacing;
+                hitboxes.push(hitbox);
+            }
+            self.back_button_hitbox = Some(hitboxes[0]);
+            self.undo_button_hitbox = Some(hitboxes[1]);
+            self.full_refresh_button_hitbox = Some(hitboxes[2]);
+            self.flip_button_hitbox = Some(hitboxes[3]);
             self.redraw_all_squares = true;
             self.draw_board(canvas);
+            self.move_list.request_redraw();
+            self.clock_above.invalidate();
+            self.clock_below.invalidate();
+            self.confirm_buttons_drawn = false;
             canvas.update_full();
             self.first_draw = false;
             // Refresh again after 500ms
@@ -948,15 +3169,35 @@ impl Scene for GameScene {
         }
 
         // Handle received `ChessUpdate`s
-        if self.white_update_receiver.is_some() {
-            let mut update_receiver = self.white_update_receiver.take().unwrap();
-            self.handle_updates(Player::White, &mut update_receiver);
-            self.white_update_receiver = Some(update_receiver);
+        self.poll_updates();
+        self.poll_coach();
+
+        if let Some(player) = self.pass_tablet_to {
+            match self.pass_tablet_shown_at {
+                None => self.draw_pass_tablet(canvas, player),
+                // Only a tap ends it when the board is hidden on purpose
+                Some(shown_at)
+                    if !self.hide_board
+                        && shown_at.elapsed().unwrap_or_default()
+                            >= crate::config::pass_tablet_duration() =>
+                {
+                    self.end_pass_tablet()
+                }
+                Some(_) => {}
+            }
+            return;
         }
-        if self.black_update_receiver.is_some() {
-            let mut update_receiver = self.black_update_receiver.take().unwrap();
-            self.handle_updates(Player::Black, &mut update_receiver);
-            self.black_update_receiver = Some(update_receiver);
+
+        if let Some(ref mut dialog) = self.dialog {
+            if !self.dialog_drawn {
+                let rect = dialog.draw(canvas);
+                canvas.mark_dirty(&rect);
+                self.dialog_drawn = true;
+            }
+            return;
+        }
+        if let Some(gesture) = self.gestures.update() {
+            self.on_gesture(gesture);
         }
 
         // Apply bot move
@@ -992,14 +3233,61 @@ impl Scene for GameScene {
             self.ignore_user_moves = false;
         }*/
 
+        if let Some(strokes) = self.handwriting_pad.take_writing() {
+            self.clear_handwriting(canvas);
+            self.on_handwritten_move(strokes);
+        }
+
+        if self.redraw_pockets {
+            self.draw_pockets(canvas).iter().for_each(|r| {
+                canvas.mark_dirty(r);
+            });
+            self.redraw_pockets = false;
+        }
+
+        if self.unconfirmed_turn.is_none() {
+            self.move_list.update(canvas);
+        } else if !self.confirm_buttons_drawn {
+            self.draw_confirm_buttons(canvas);
+        }
+
+        self.pen_annotations.update(canvas);
+        self.handwriting_pad.update(canvas);
+        self.draw_clocks(canvas);
+
         // Update board
         if self.redraw_all_squares || self.redraw_squares.len() > 0 {
-            self.draw_board(canvas).iter().for_each(|r| {
-                canvas.update_partial(r);
-            });
+            let regions = self.draw_board(canvas);
+            refresh_board_regions(canvas, &regions, self.square_size);
             self.redraw_all_squares = false;
         }
 
+        if self.save_image_requested {
+            self.save_image_requested = false;
+            let info = match save_board_image(canvas, self.full_board_rect()) {
+                Ok(path) => GameBottomInfo::Info(format!("Saved image to {}", path.display())),
+                Err(err) => {
+                    error!("Failed to save image of the board: {:?}", err);
+                    GameBottomInfo::Error("Failed to save the image".to_owned())
+                }
+            };
+            self.show_bottom_game_info(info, None, Some(Duration::from_secs(5)));
+        }
+
+        // Tell the player once they are allowed to move
+        if self.thinking_since.is_some()
+            && !self.think_time_reached
+            && !self.is_game_over
+            && self.remaining_think_time().is_none()
+        {
+            self.think_time_reached = true;
+            self.show_bottom_game_info(
+                GameBottomInfo::Info("Think time is over. You may move now.".to_owned()),
+                None,
+                Some(Duration::from_secs(3)),
+            );
+        }
+
         // Do forced refresh on request
         if self.force_full_refresh.is_some() && self.force_full_refresh.unwrap() < SystemTime::now()
         {
@@ -1032,7 +3320,7 @@ impl Scene for GameScene {
                     },
                     color::WHITE,
                 );
-                canvas.update_partial(last_rect);
+                canvas.mark_dirty(last_rect);
                 self.draw_game_bottom_info_last_rect = None;
             }
         }
@@ -1042,33 +3330,53 @@ impl Scene for GameScene {
             if let Some(ref game_bottom_info) = self.draw_game_bottom_info {
                 // Old text was cleared above already
 
-                let rect = match game_bottom_info {
-                    GameBottomInfo::GameEnded(ref short_message) => canvas.draw_text(
-                        Point2 {
-                            x: None,
-                            y: Some(DISPLAYHEIGHT as i32 - 100),
-                        },
-                        short_message,
-                        100.0,
-                    ),
-                    GameBottomInfo::Info(ref message) => canvas.draw_text(
-                        Point2 {
-                            x: None,
-                            y: Some(DISPLAYHEIGHT as i32 - 20),
-                        },
-                        message,
-                        50.0,
-                    ),
-                    GameBottomInfo::Error(ref message) => canvas.draw_text(
-                        Point2 {
-                            x: Some(5),
-                            y: Some(DISPLAYHEIGHT as i32 - 10),
-                        },
-                        message,
-                        35.0,
-                    ),
-                };
-                canvas.update_partial(&rect);
+                let rect =
+                    if let Some(area) = self.layout.info {
+                        let (message, size) = match game_bottom_info {
+                            GameBottomInfo::GameEnded(ref short_message) => (short_message, 50.0),
+                            GameBottomInfo::Info(ref message) => (message, 40.0),
+                            GameBottomInfo::Error(ref message)
+                            | GameBottomInfo::Coach(ref message) => (message, 35.0),
+                        };
+                        canvas.draw_wrapped_text(
+                            Some(area.left as i32 + 5),
+                            area.top as i32 + size as i32,
+                            area.width - 10,
+                            3,
+                            message,
+                            size,
+                            FontStyle::Regular,
+                        )
+                    } else {
+                        match game_bottom_info {
+                            GameBottomInfo::GameEnded(ref short_message) => canvas.draw_text(
+                                Point2 {
+                                    x: None,
+                                    y: Some(DISPLAYHEIGHT as i32 - 15),
+                                },
+                                short_message,
+                                80.0,
+                            ),
+                            GameBottomInfo::Info(ref message) => canvas.draw_text(
+                                Point2 {
+                                    x: None,
+                                    y: Some(DISPLAYHEIGHT as i32 - 20),
+                                },
+                                message,
+                                50.0,
+                            ),
+                            GameBottomInfo::Error(ref message)
+                            | GameBottomInfo::Coach(ref message) => canvas.draw_text(
+                                Point2 {
+                                    x: Some(5),
+                                    y: Some(DISPLAYHEIGHT as i32 - 10),
+                                },
+                                message,
+                                35.0,
+                            ),
+                        }
+                    };
+                canvas.mark_dirty(&rect);
                 self.draw_game_bottom_info_last_rect = Some(rect);
                 self.draw_game_bottom_info = None;
                 if self.draw_game_bottom_info_clear_at.is_some()
@@ -0,0 +1,135 @@
+use crate::canvas::*;
+use crate::settings::GestureAction;
+use fxhash::{FxHashMap, FxHashSet};
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+use std::time::{Duration, SystemTime};
+
+/// Both fingers need to move at least this far sideways for a swipe
+const SWIPE_DISTANCE: i32 = 150;
+/// Fingers that moved less than this count as resting
+const TAP_SLOP: i32 = 30;
+const TAP_DURATION: Duration = Duration::from_millis(400);
+const LONG_PRESS_DURATION: Duration = Duration::from_millis(900);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Gesture {
+    TwoFingerSwipeLeft,
+    TwoFingerSwipeRight,
+    TwoFingerTap,
+    LongPress,
+}
+
+impl Gesture {
+    /// What the gesture should do according to the settings
+    pub fn action(self) -> GestureAction {
+        let gestures = crate::SETTINGS.lock().unwrap().gestures;
+        match self {
+            Gesture::TwoFingerSwipeLeft => gestures.two_finger_swipe_left,
+            Gesture::TwoFingerSwipeRight => gestures.two_finger_swipe_right,
+            Gesture::TwoFingerTap => gestures.two_finger_tap,
+            Gesture::LongPress => gestures.long_press,
+        }
+    }
+}
+
+/// Recognizes gestures in the multitouch input. Scenes forward input to it,
+/// call `update()` each frame and ignore touches while `is_consuming()`.
+#[derive(Default)]
+pub struct Gestures {
+    /// Start and latest position of every finger of the current touch
+    fingers: FxHashMap<i32, (Point2<u16>, Point2<u16>)>,
+    /// Fingers that still touch the screen
+    pressed: FxHashSet<i32>,
+    started_at: Option<SystemTime>,
+    /// The current touch is (or ended as) a gesture
+    consuming: bool,
+}
+
+fn moved(start: Point2<u16>, end: Point2<u16>) -> (i32, i32) {
+    (end.x as i32 - start.x as i32, end.y as i32 - start.y as i32)
+}
+
+impl Gestures {
+    pub fn on_input(&mut self, event: InputEvent) -> Option<Gesture> {
+        let event = match event {
+            InputEvent::MultitouchEvent { event } => event,
+            _ => return None,
+        };
+        match event {
+            MultitouchEvent::Press { finger } => {
+                if self.pressed.is_empty() {
+                    // New touch
+                    self.fingers.clear();
+                    self.started_at = Some(SystemTime::now());
+                    self.consuming = false;
+                }
+                self.pressed.insert(finger.tracking_id);
+                self.fingers
+                    .insert(finger.tracking_id, (finger.pos, finger.pos));
+                if self.fingers.len() > 1 {
+                    self.consuming = true;
+                }
+            }
+            MultitouchEvent::Move { finger } => {
+                if let Some((_, pos)) = self.fingers.get_mut(&finger.tracking_id) {
+                    *pos = finger.pos;
+                }
+            }
+            MultitouchEvent::Release { finger } => {
+                if let Some((_, pos)) = self.fingers.get_mut(&finger.tracking_id) {
+                    *pos = finger.pos;
+                }
+                self.pressed.remove(&finger.tracking_id);
+                if self.pressed.is_empty() && self.fingers.len() == 2 {
+                    return self.two_finger_gesture();
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn two_finger_gesture(&self) -> Option<Gesture> {
+        let moves: Vec<(i32, i32)> = self
+            .fingers
+            .values()
+            .map(|(start, end)| moved(*start, *end))
+            .collect();
+        let sideways = |(dx, dy): &(i32, i32)| dx.abs() >= SWIPE_DISTANCE && dx.abs() > dy.abs();
+        if moves.iter().all(|m| sideways(m) && m.0 < 0) {
+            Some(Gesture::TwoFingerSwipeLeft)
+        } else if moves.iter().all(|m| sideways(m) && m.0 > 0) {
+            Some(Gesture::TwoFingerSwipeRight)
+        } else if moves
+            .iter()
+            .all(|(dx, dy)| dx.abs() < TAP_SLOP && dy.abs() < TAP_SLOP)
+            && self.started_at?.elapsed().unwrap_or_default() < TAP_DURATION
+        {
+            Some(Gesture::TwoFingerTap)
+        } else {
+            None
+        }
+    }
+
+    /// Detects long presses
+    pub fn update(&mut self) -> Option<Gesture> {
+        if self.consuming || self.pressed.len() != 1 || self.fingers.len() != 1 {
+            return None;
+        }
+        let (start, pos) = self.fingers.values().next()?;
+        let (dx, dy) = moved(*start, *pos);
+        if dx.abs() >= TAP_SLOP || dy.abs() >= TAP_SLOP {
+            return None;
+        }
+        if self.started_at?.elapsed().unwrap_or_default() >= LONG_PRESS_DURATION {
+            self.consuming = true;
+            return Some(Gesture::LongPress);
+        }
+        None
+    }
+
+    /// Whether touches belong to a gesture and shouldn't be handled otherwise
+    pub fn is_consuming(&self) -> bool {
+        self.consuming
+    }
+}
@@ -0,0 +1,93 @@
+use crate::canvas::*;
+use crate::scene::ink::Ink;
+use chessmarkable::handwriting::Stroke;
+use libremarkable::input::{wacom::WacomEvent, InputEvent};
+use std::time::{Duration, SystemTime};
+
+const INK_RADIUS: u32 = 3;
+/// Writing is considered done when the pen wasn't used for this long
+const WRITING_PAUSE: Duration = Duration::from_millis(1200);
+
+/// Area where something can be written with the pen. Scenes forward
+/// input to it, call `update()` each frame and check `take_writing()`.
+pub struct HandwritingPad {
+    area: mxcfb_rect,
+    strokes: Vec<Stroke>,
+    /// Whether the pen currently draws a stroke on the pad
+    drawing: bool,
+    ink: Ink,
+    last_pen_up: Option<SystemTime>,
+}
+
+impl HandwritingPad {
+    pub fn new(area: mxcfb_rect) -> Self {
+        Self {
+            area,
+            strokes: vec![],
+            drawing: false,
+            ink: Default::default(),
+            last_pen_up: None,
+        }
+    }
+
+    /// Returns true if the event belongs to writing on the pad
+    /// (so it shouldn't be handled elsewhere)
+    pub fn on_input(&mut self, event: InputEvent) -> bool {
+        let event = match event {
+            InputEvent::WacomEvent { event } => event,
+            _ => return false,
+        };
+        match event {
+            WacomEvent::Draw { position, .. } => {
+                let pos = Point2 {
+                    x: position.x as u16,
+                    y: position.y as u16,
+                };
+                let inside = Canvas::is_hitting(pos, self.area);
+                if !self.drawing {
+                    if !inside {
+                        return false;
+                    }
+                    self.drawing = true;
+                    self.strokes.push(vec![]);
+                }
+                self.strokes
+                    .last_mut()
+                    .unwrap()
+                    .push((position.x, position.y));
+                if inside {
+                    self.ink.push(pos.cast().unwrap());
+                }
+                true
+            }
+            WacomEvent::InstrumentChange { state: false, .. } | WacomEvent::Hover { .. } => {
+                if !self.drawing {
+                    return false;
+                }
+                self.drawing = false;
+                self.last_pen_up = Some(SystemTime::now());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Show the ink of what is currently written
+    pub fn update(&mut self, canvas: &mut Canvas) {
+        self.ink.show_dots(canvas, INK_RADIUS);
+    }
+
+    /// The strokes once the writing is done. The pad is empty again
+    /// afterwards but its area still needs to be cleared.
+    pub fn take_writing(&mut self) -> Option<Vec<Stroke>> {
+        if self.drawing || self.strokes.is_empty() {
+            return None;
+        }
+        let last_pen_up = self.last_pen_up?;
+        if last_pen_up.elapsed().unwrap_or_default() < WRITING_PAUSE {
+            return None;
+        }
+        self.last_pen_up = None;
+        Some(std::mem::take(&mut self.strokes))
+    }
+}
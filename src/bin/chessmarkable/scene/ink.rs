@@ -0,0 +1,40 @@
+use crate::canvas::*;
+
+/// Points of a pen stroke that weren't shown yet. Owners push the points
+/// while the pen draws and show them once per frame.
+#[derive(Default)]
+pub struct Ink {
+    new_points: Vec<Point2<i32>>,
+}
+
+impl Ink {
+    pub fn push(&mut self, pos: Point2<i32>) {
+        self.new_points.push(pos);
+    }
+
+    /// Forget the points that weren't shown (e.g. when the stroke ended)
+    pub fn clear(&mut self) {
+        self.new_points.clear();
+    }
+
+    /// Show every new point as a dot
+    pub fn show_dots(&mut self, canvas: &mut Canvas, radius: u32) {
+        for pos in self.new_points.drain(..) {
+            let rect = canvas.fill_circle(pos, radius, color::BLACK);
+            canvas.update_partial_fast(&rect);
+        }
+    }
+
+    /// Connect the new points with lines. The last one is kept to continue
+    /// the line from it.
+    pub fn show_lines(&mut self, canvas: &mut Canvas, width: u32) {
+        for points in self.new_points.windows(2) {
+            let rect = canvas.draw_line(points[0], points[1], width);
+            canvas.update_partial_fast(&rect);
+        }
+        if let Some(last) = self.new_points.last().copied() {
+            self.new_points.clear();
+            self.new_points.push(last);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::canvas::*;
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+
+/// Rows of character keys. The row with the control keys is always added below.
+pub const SEARCH_LAYOUT: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl-", "zxcvbnm.,'"];
+pub const FILE_NAME_LAYOUT: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl-", "zxcvbnm_.+"];
+/// Pieces, squares and the other fields of a FEN, or moves in SAN
+pub const FEN_LAYOUT: &[&str] = &["1234567890", "KQRBNPO/-x", "kqrbnpw.+=", "abcdefgh#"];
+
+const KEY_HEIGHT: u32 = 120;
+const TEXT_FIELD_HEIGHT: u32 = 110;
+/// Only the end of longer texts (e.g. a FEN) is shown
+const MAX_VISIBLE_CHARS: usize = 48;
+/// Widest row in keys. Determines the width of a single key.
+const KEYS_PER_ROW: u32 = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Space,
+    Backspace,
+    Clear,
+    Cancel,
+    Done,
+}
+
+pub enum KeyboardEvent {
+    Edited,
+    Done,
+    Cancelled,
+}
+
+/// On-screen keyboard with a single line text field above it.
+/// Scenes forward input to it and call `update()` each frame.
+pub struct Keyboard {
+    layout: &'static [&'static str],
+    pub text: String,
+    top: i32,
+    key_hitboxes: Vec<(Key, mxcfb_rect)>,
+    text_field_rect: Option<mxcfb_rect>,
+    redraw_text_field: bool,
+}
+
+impl Keyboard {
+    pub fn new(layout: &'static [&'static str], text: &str, top: i32) -> Self {
+        Self {
+            layout,
+            text: text.to_owned(),
+            top,
+            key_hitboxes: vec![],
+            text_field_rect: None,
+            redraw_text_field: false,
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        TEXT_FIELD_HEIGHT + KEY_HEIGHT * (self.layout.len() as u32 + 1)
+    }
+
+    fn draw_text_field(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(self.top),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: TEXT_FIELD_HEIGHT,
+            },
+            color::WHITE,
+        );
+        canvas.draw_rect(
+            Point2 {
+                x: Some(20),
+                y: Some(self.top + 10),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32 - 40,
+                y: TEXT_FIELD_HEIGHT - 20,
+            },
+            3,
+        );
+        canvas.draw_text(
+            Point2 {
+                x: Some(40),
+                y: Some(self.top + TEXT_FIELD_HEIGHT as i32 - 35),
+            },
+            &format!("{}_", visible_text(&self.text)),
+            50.0,
+        );
+        self.text_field_rect = Some(rect);
+        rect
+    }
+
+    fn draw_key(&mut self, canvas: &mut Canvas, key: Key, pos: Point2<i32>, width: u32) {
+        let label = match key {
+            Key::Char(c) => c.to_string(),
+            Key::Space => "Space".to_owned(),
+            Key::Backspace => "Del".to_owned(),
+            Key::Clear => "Clear".to_owned(),
+            Key::Cancel => "Cancel".to_owned(),
+            Key::Done => "Done".to_owned(),
+        };
+        let rect = canvas.draw_rect(
+            Point2 {
+                x: Some(pos.x),
+                y: Some(pos.y),
+            },
+            Vector2 {
+                x: width,
+                y: KEY_HEIGHT,
+            },
+            2,
+        );
+        canvas.draw_text_centered(rect, &label, 50.0);
+        self.key_hitboxes.push((key, rect));
+    }
+
+    /// Draw the whole keyboard. The caller has to refresh the returned region.
+    pub fn draw(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let key_width = DISPLAYWIDTH as u32 / KEYS_PER_ROW;
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(self.top),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: self.height(),
+            },
+            color::WHITE,
+        );
+        self.key_hitboxes.clear();
+        self.draw_text_field(canvas);
+
+        let mut y = self.top + TEXT_FIELD_HEIGHT as i32;
+        for row in self.layout {
+            let row_width = key_width * row.chars().count() as u32;
+            let mut x = (DISPLAYWIDTH as u32 - row_width) as i32 / 2;
+            for c in row.chars() {
+                self.draw_key(canvas, Key::Char(c), Point2 { x, y }, key_width);
+                x += key_width as i32;
+            }
+            y += KEY_HEIGHT as i32;
+        }
+
+        let control_keys = [
+            Key::Cancel,
+            Key::Clear,
+            Key::Space,
+            Key::Backspace,
+            Key::Done,
+        ];
+        let control_key_width = DISPLAYWIDTH as u32 / control_keys.len() as u32;
+        for (i, key) in control_keys.iter().enumerate() {
+            let x = (control_key_width * i as u32) as i32;
+            self.draw_key(canvas, *key, Point2 { x, y }, control_key_width);
+        }
+        rect
+    }
+
+    /// Redraw the text field when it changed
+    pub fn update(&mut self, canvas: &mut Canvas) {
+        if self.redraw_text_field {
+            let rect = self.draw_text_field(canvas);
+            canvas.mark_dirty(&rect);
+            self.redraw_text_field = false;
+        }
+    }
+
+    pub fn on_input(&mut self, event: InputEvent) -> Option<KeyboardEvent> {
+        if let InputEvent::MultitouchEvent { event } = event {
+            if let MultitouchEvent::Release { finger, .. } = event {
+                let key = self
+                    .key_hitboxes
+                    .iter()
+                    .find(|(_, hitbox)| Canvas::is_hitting(finger.pos, *hitbox))
+                    .map(|(key, _)| *key)?;
+                match key {
+                    Key::Char(c) => self.text.push(c),
+                    Key::Space => self.text.push(' '),
+                    Key::Backspace => {
+                        self.text.pop();
+                    }
+                    Key::Clear => self.text.clear(),
+                    Key::Cancel => return Some(KeyboardEvent::Cancelled),
+                    Key::Done => return Some(KeyboardEvent::Done),
+                }
+                self.redraw_text_field = true;
+                return Some(KeyboardEvent::Edited);
+            }
+        }
+        None
+    }
+}
+
+fn visible_text(text: &str) -> String {
+    let chars = text.chars().count();
+    if chars <= MAX_VISIBLE_CHARS {
+        return text.to_owned();
+    }
+    let end: String = text.chars().skip(chars - MAX_VISIBLE_CHARS + 2).collect();
+    format!("..{}", end)
+}
@@ -0,0 +1,286 @@
+use super::Scene;
+use crate::canvas::*;
+use crate::logs::{self, LogLine};
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+use log::Level;
+
+const LINES_PER_PAGE: usize = 32;
+const LINE_HEIGHT: i32 = 40;
+const FIRST_LINE_Y_POS: i32 = 420;
+const LINE_FONT_SIZE: f32 = 30.0;
+/// Longer messages are cut off
+const MAX_LINE_LENGTH: usize = 90;
+
+/// Lines of at least this level are shown
+const LEVELS: &[Level] = &[Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+pub struct LogScene {
+    drawn: bool,
+    redraw_filters: bool,
+    redraw_lines: bool,
+    /// Copy of the buffered lines when the scene was opened (or refreshed)
+    lines: Vec<LogLine>,
+    level: usize,
+    /// Only lines of this module (all if `None`)
+    module: Option<String>,
+    /// 0 is the page with the newest lines
+    page: usize,
+    status: Option<String>,
+
+    level_button_hitbox: Option<mxcfb_rect>,
+    module_button_hitbox: Option<mxcfb_rect>,
+    refresh_button_hitbox: Option<mxcfb_rect>,
+    older_button_hitbox: Option<mxcfb_rect>,
+    newer_button_hitbox: Option<mxcfb_rect>,
+    export_button_hitbox: Option<mxcfb_rect>,
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl LogScene {
+    pub fn new() -> Self {
+        Self {
+            drawn: false,
+            redraw_filters: false,
+            redraw_lines: false,
+            lines: logs::recent_lines(),
+            level: 0,
+            module: None,
+            page: 0,
+            status: None,
+            level_button_hitbox: None,
+            module_button_hitbox: None,
+            refresh_button_hitbox: None,
+            older_button_hitbox: None,
+            newer_button_hitbox: None,
+            export_button_hitbox: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    fn filtered_lines(&self) -> Vec<&LogLine> {
+        self.lines
+            .iter()
+            .filter(|line| line.level <= LEVELS[self.level])
+            .filter(|line| self.module.as_deref().map_or(true, |m| line.module() == m))
+            .collect()
+    }
+
+    fn page_count(&self) -> usize {
+        ((self.filtered_lines().len() + LINES_PER_PAGE - 1) / LINES_PER_PAGE).max(1)
+    }
+
+    /// Modules that logged anything, sorted
+    fn modules(&self) -> Vec<String> {
+        let mut modules: Vec<String> = self
+            .lines
+            .iter()
+            .map(|line| line.module().to_owned())
+            .collect();
+        modules.sort();
+        modules.dedup();
+        modules
+    }
+
+    fn next_module(&mut self) {
+        let modules = self.modules();
+        let next_index = match self.module {
+            Some(ref module) => modules.iter().position(|m| m == module).map(|i| i + 1),
+            None => Some(0),
+        };
+        self.module = next_index.and_then(|i| modules.get(i).cloned());
+    }
+
+    fn draw_filters(&mut self, canvas: &mut Canvas) {
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(220),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: 120,
+            },
+            color::WHITE,
+        );
+        self.level_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(50),
+                y: Some(300),
+            },
+            &format!("Level: {}", LEVELS[self.level]),
+            50.0,
+            10,
+            25,
+        ));
+        self.module_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(480),
+                y: Some(300),
+            },
+            &format!("Module: {}", self.module.as_deref().unwrap_or("all")),
+            50.0,
+            10,
+            25,
+        ));
+        canvas.mark_dirty(&rect);
+    }
+
+    fn draw_lines(&mut self, canvas: &mut Canvas) {
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(FIRST_LINE_Y_POS - LINE_HEIGHT),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: (LINE_HEIGHT * (LINES_PER_PAGE as i32 + 2)) as u32,
+            },
+            color::WHITE,
+        );
+        let lines = self.filtered_lines();
+        let end = lines.len().saturating_sub(self.page * LINES_PER_PAGE);
+        let start = end.saturating_sub(LINES_PER_PAGE);
+        let mut texts: Vec<String> = lines[start..end]
+            .iter()
+            .map(|line| {
+                let text = format!(
+                    "{:.1} {} {}: {}",
+                    line.time,
+                    &line.level.as_str()[..1],
+                    line.module(),
+                    line.message.lines().next().unwrap_or_default()
+                );
+                match text.char_indices().nth(MAX_LINE_LENGTH) {
+                    Some((cut, _)) => format!("{}...", &text[..cut]),
+                    None => text,
+                }
+            })
+            .collect();
+        if texts.is_empty() {
+            texts.push("No log lines".to_owned());
+        }
+        for (i, text) in texts.iter().enumerate() {
+            canvas.draw_text(
+                Point2 {
+                    x: Some(30),
+                    y: Some(FIRST_LINE_Y_POS + LINE_HEIGHT * i as i32),
+                },
+                text,
+                LINE_FONT_SIZE,
+            );
+        }
+        let footer = match self.status {
+            Some(ref status) => status.clone(),
+            None => format!("Page {} of {}", self.page + 1, self.page_count()),
+        };
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(FIRST_LINE_Y_POS + LINE_HEIGHT * (LINES_PER_PAGE as i32 + 1)),
+            },
+            &footer,
+            35.0,
+        );
+        canvas.mark_dirty(&rect);
+    }
+
+    fn export(&mut self) {
+        let lines: Vec<LogLine> = self.filtered_lines().into_iter().cloned().collect();
+        self.status = Some(match logs::export(&lines) {
+            Ok(path) => format!("Exported {} lines to {}", lines.len(), path.display()),
+            Err(err) => {
+                error!("Failed to export log: {:?}", err);
+                format!("Failed to export: {}", err)
+            }
+        });
+    }
+
+    fn on_release(&mut self, position: Point2<u16>) {
+        let hit =
+            |hitbox: Option<mxcfb_rect>| hitbox.map_or(false, |h| Canvas::is_hitting(position, h));
+        self.status = None;
+        if hit(self.back_button_hitbox) {
+            self.back_button_pressed = true;
+            return;
+        } else if hit(self.level_button_hitbox) {
+            self.level = (self.level + 1) % LEVELS.len();
+            self.page = 0;
+            self.redraw_filters = true;
+        } else if hit(self.module_button_hitbox) {
+            self.next_module();
+            self.page = 0;
+            self.redraw_filters = true;
+        } else if hit(self.refresh_button_hitbox) {
+            self.lines = logs::recent_lines();
+            self.page = 0;
+        } else if hit(self.older_button_hitbox) {
+            self.page = (self.page + 1).min(self.page_count() - 1);
+        } else if hit(self.newer_button_hitbox) {
+            self.page = self.page.saturating_sub(1);
+        } else if hit(self.export_button_hitbox) {
+            self.export();
+        } else {
+            return;
+        }
+        self.redraw_lines = true;
+    }
+}
+
+impl Scene for LogScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            canvas.clear();
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(170),
+                },
+                "Logs",
+                125.0,
+            );
+            self.draw_filters(canvas);
+            for (hitbox, x, label) in [
+                (&mut self.back_button_hitbox, 50, "Back"),
+                (&mut self.older_button_hitbox, 280, "Older"),
+                (&mut self.newer_button_hitbox, 520, "Newer"),
+                (&mut self.refresh_button_hitbox, 770, "Refresh"),
+                (&mut self.export_button_hitbox, 1050, "Export"),
+            ] {
+                *hitbox = Some(canvas.draw_button(
+                    Point2 {
+                        x: Some(x),
+                        y: Some(1800),
+                    },
+                    label,
+                    50.0,
+                    10,
+                    25,
+                ));
+            }
+            self.draw_lines(canvas);
+            canvas.update_full();
+            return;
+        }
+
+        if self.redraw_filters {
+            self.redraw_filters = false;
+            self.draw_filters(canvas);
+        }
+        if self.redraw_lines {
+            self.redraw_lines = false;
+            self.draw_lines(canvas);
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        if let InputEvent::MultitouchEvent {
+            event: MultitouchEvent::Release { finger, .. },
+        } = event
+        {
+            self.on_release(finger.pos);
+        }
+    }
+}
@@ -1,12 +1,28 @@
 use super::Scene;
 use crate::canvas::*;
+use crate::pgns::RecentGame;
+use chess_pgn_parser::Game;
 use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
 
+/// Games of the PGN folder shown below the PGN viewer button
+const RECENT_GAMES: usize = 3;
+const RECENT_GAME_HEIGHT: u32 = 100;
+const RECENT_GAME_GAP: u32 = 20;
+/// Longer titles don't fit into the boxes
+const RECENT_GAME_MAX_CHARS: usize = 22;
+
 pub struct MainMenuScene {
     drawn: bool,
 
+    continue_button_hitbox: Option<mxcfb_rect>,
+    pub continue_button_pressed: bool,
+
     play_pvp_button_hitbox: Option<mxcfb_rect>,
     pub play_pvp_button_pressed: bool,
+    play_analysis_button_hitbox: Option<mxcfb_rect>,
+    pub play_analysis_button_pressed: bool,
+    play_recording_button_hitbox: Option<mxcfb_rect>,
+    pub play_recording_button_pressed: bool,
     pvp_toggle_piece_rotation_hitbox: Option<mxcfb_rect>,
     pvp_toggle_piece_rotation_redraw: bool,
     play_easy_button_hitbox: Option<mxcfb_rect>,
@@ -15,8 +31,27 @@ pub struct MainMenuScene {
     pub play_normal_button_pressed: bool,
     play_hard_button_hitbox: Option<mxcfb_rect>,
     pub play_hard_button_pressed: bool,
+    bot_vs_bot_button_hitbox: Option<mxcfb_rect>,
+    pub bot_vs_bot_button_pressed: bool,
+    review_button_hitbox: Option<mxcfb_rect>,
+    pub review_button_pressed: bool,
     viewer_button_hitbox: Option<mxcfb_rect>,
     pub viewer_button_pressed: bool,
+    recent_games: Vec<(RecentGame, mxcfb_rect)>,
+    /// Tapped game of the recent games (read and parsed)
+    pub opened_recent_game: Option<(Game, RecentGame)>,
+    diagnostics_button_hitbox: Option<mxcfb_rect>,
+    pub diagnostics_button_pressed: bool,
+    settings_button_hitbox: Option<mxcfb_rect>,
+    pub settings_button_pressed: bool,
+    scoresheet_button_hitbox: Option<mxcfb_rect>,
+    pub scoresheet_button_pressed: bool,
+    clock_button_hitbox: Option<mxcfb_rect>,
+    pub clock_button_pressed: bool,
+    correspondence_button_hitbox: Option<mxcfb_rect>,
+    pub correspondence_button_pressed: bool,
+    saves_button_hitbox: Option<mxcfb_rect>,
+    pub saves_button_pressed: bool,
 
     exit_button_hitbox: Option<mxcfb_rect>,
     pub exit_button_pressed: bool,
@@ -32,8 +67,14 @@ impl MainMenuScene {
     pub fn new(only_exit_to_xochitl: bool, pvp_piece_rotation_enabled: bool) -> Self {
         Self {
             drawn: false,
+            continue_button_hitbox: None,
+            continue_button_pressed: false,
             play_pvp_button_hitbox: None,
             play_pvp_button_pressed: false,
+            play_analysis_button_hitbox: None,
+            play_analysis_button_pressed: false,
+            play_recording_button_hitbox: None,
+            play_recording_button_pressed: false,
             pvp_toggle_piece_rotation_hitbox: None,
             pvp_toggle_piece_rotation_redraw: false,
             play_easy_button_hitbox: None,
@@ -42,8 +83,26 @@ impl MainMenuScene {
             play_normal_button_pressed: false,
             play_hard_button_hitbox: None,
             play_hard_button_pressed: false,
+            bot_vs_bot_button_hitbox: None,
+            bot_vs_bot_button_pressed: false,
+            review_button_hitbox: None,
+            review_button_pressed: false,
             viewer_button_hitbox: None,
             viewer_button_pressed: false,
+            recent_games: vec![],
+            opened_recent_game: None,
+            diagnostics_button_hitbox: None,
+            diagnostics_button_pressed: false,
+            settings_button_hitbox: None,
+            settings_button_pressed: false,
+            scoresheet_button_hitbox: None,
+            scoresheet_button_pressed: false,
+            clock_button_hitbox: None,
+            clock_button_pressed: false,
+            correspondence_button_hitbox: None,
+            correspondence_button_pressed: false,
+            saves_button_hitbox: None,
+            saves_button_pressed: false,
             exit_button_hitbox: None,
             exit_button_pressed: false,
             exit_xochitl_button_hitbox: None,
@@ -106,13 +165,66 @@ impl MainMenuScene {
         ext_hitbox.height += 20;
         self.pvp_toggle_piece_rotation_hitbox = Some(ext_hitbox);
     }
+
+    /// A row of boxes with the opponent (or players) and the result
+    fn draw_recent_games(&mut self, canvas: &mut Canvas, top: i32) {
+        let recent_games =
+            crate::pgns::read_recent_games(&crate::config::pgn_location(), RECENT_GAMES)
+                .unwrap_or_else(|err| {
+                    error!("Failed to read recent games: {:?}", err);
+                    vec![]
+                });
+        let player_name = crate::SETTINGS.lock().unwrap().player_name.clone();
+        let width = (DISPLAYWIDTH as u32 - 80 - (RECENT_GAMES as u32 - 1) * RECENT_GAME_GAP)
+            / RECENT_GAMES as u32;
+        self.recent_games.clear();
+        for (i, recent_game) in recent_games.into_iter().enumerate() {
+            let left = 40 + i as i32 * (width + RECENT_GAME_GAP) as i32;
+            let hitbox = canvas.draw_rect(
+                Point2 {
+                    x: Some(left),
+                    y: Some(top),
+                },
+                Vector2 {
+                    x: width,
+                    y: RECENT_GAME_HEIGHT,
+                },
+                2,
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: Some(left + 15),
+                    y: Some(top + 42),
+                },
+                &shorten(&recent_game.title(player_name.as_deref())),
+                32.0,
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: Some(left + 15),
+                    y: Some(top + 85),
+                },
+                &shorten(&recent_game.details()),
+                28.0,
+            );
+            self.recent_games.push((recent_game, hitbox));
+        }
+    }
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() <= RECENT_GAME_MAX_CHARS {
+        return text.to_owned();
+    }
+    let shortened: String = text.chars().take(RECENT_GAME_MAX_CHARS - 2).collect();
+    format!("{}..", shortened)
 }
 
 impl Scene for MainMenuScene {
     fn draw(&mut self, canvas: &mut Canvas) {
         if self.pvp_toggle_piece_rotation_redraw {
             self.draw_rotation_button(canvas);
-            canvas.update_partial(&self.pvp_toggle_piece_rotation_hitbox.unwrap());
+            canvas.mark_dirty(&self.pvp_toggle_piece_rotation_hitbox.unwrap());
             self.pvp_toggle_piece_rotation_redraw = false;
         }
 
@@ -131,6 +243,23 @@ impl Scene for MainMenuScene {
             150.0,
         );
 
+        let autosave = crate::autosave::read().unwrap_or_else(|err| {
+            error!("Failed to read autosave file: {:?}", err);
+            None
+        });
+        self.continue_button_hitbox = autosave.map(|autosave| {
+            canvas.draw_button(
+                Point2 {
+                    x: None,
+                    y: Some(295),
+                },
+                &autosave.label(),
+                45.0,
+                12,
+                30,
+            )
+        });
+
         canvas.draw_text(
             Point2 {
                 x: None,
@@ -151,6 +280,27 @@ impl Scene for MainMenuScene {
             50,
         ));
 
+        self.play_analysis_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(100),
+                y: Some(530),
+            },
+            "Analysis",
+            50.0,
+            15,
+            25,
+        ));
+        self.play_recording_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 320),
+                y: Some(530),
+            },
+            "Record",
+            50.0,
+            15,
+            25,
+        ));
+
         self.draw_rotation_button(canvas);
 
         canvas.draw_text(
@@ -172,6 +322,26 @@ impl Scene for MainMenuScene {
             25,
             50,
         ));
+        self.bot_vs_bot_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 340),
+                y: Some(930),
+            },
+            "Bot vs Bot",
+            50.0,
+            15,
+            25,
+        ));
+        self.review_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(100),
+                y: Some(930),
+            },
+            "Review",
+            50.0,
+            15,
+            25,
+        ));
         self.play_normal_button_hitbox = Some(canvas.draw_button(
             Point2 {
                 x: None,
@@ -211,6 +381,81 @@ impl Scene for MainMenuScene {
             25,
             50,
         ));
+        let viewer_button = self.viewer_button_hitbox.unwrap();
+        self.draw_recent_games(
+            canvas,
+            (viewer_button.top + viewer_button.height) as i32 + 25,
+        );
+
+        self.diagnostics_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(40),
+                y: Some(1840),
+            },
+            "Diagnostics",
+            35.0,
+            10,
+            15,
+        ));
+        self.settings_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 200),
+                y: Some(1840),
+            },
+            "Settings",
+            35.0,
+            10,
+            15,
+        ));
+        self.scoresheet_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1840),
+            },
+            "Scoresheet",
+            35.0,
+            10,
+            15,
+        ));
+        self.clock_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(400),
+                y: Some(1840),
+            },
+            "Clock",
+            35.0,
+            10,
+            15,
+        ));
+
+        // Number of correspondence games waiting for a move
+        let correspondence_label = match crate::correspondence::read_all() {
+            Ok(ref games) if games.iter().any(|game| game.awaits_local_move()) => format!(
+                "Correspondence ({})",
+                games.iter().filter(|game| game.awaits_local_move()).count()
+            ),
+            _ => "Correspondence".to_owned(),
+        };
+        self.correspondence_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(40),
+                y: Some(1740),
+            },
+            &correspondence_label,
+            40.0,
+            10,
+            20,
+        ));
+        self.saves_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 300),
+                y: Some(1740),
+            },
+            "Saved Games",
+            40.0,
+            10,
+            20,
+        ));
 
         if self.only_exit_to_xochitl {
             self.exit_xochitl_button_hitbox = Some(canvas.draw_button(
@@ -243,10 +488,22 @@ impl Scene for MainMenuScene {
         if let InputEvent::MultitouchEvent { event } = event {
             if let MultitouchEvent::Release { finger, .. } = event {
                 let position = finger.pos;
-                if self.play_pvp_button_hitbox.is_some()
+                if self.continue_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.continue_button_hitbox.unwrap())
+                {
+                    self.continue_button_pressed = true;
+                } else if self.play_pvp_button_hitbox.is_some()
                     && Canvas::is_hitting(position, self.play_pvp_button_hitbox.unwrap())
                 {
                     self.play_pvp_button_pressed = true;
+                } else if self.play_analysis_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.play_analysis_button_hitbox.unwrap())
+                {
+                    self.play_analysis_button_pressed = true;
+                } else if self.play_recording_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.play_recording_button_hitbox.unwrap())
+                {
+                    self.play_recording_button_pressed = true;
                 }
                 if self.pvp_toggle_piece_rotation_hitbox.is_some()
                     && Canvas::is_hitting(position, self.pvp_toggle_piece_rotation_hitbox.unwrap())
@@ -265,10 +522,51 @@ impl Scene for MainMenuScene {
                     && Canvas::is_hitting(position, self.play_hard_button_hitbox.unwrap())
                 {
                     self.play_hard_button_pressed = true;
+                } else if self.bot_vs_bot_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.bot_vs_bot_button_hitbox.unwrap())
+                {
+                    self.bot_vs_bot_button_pressed = true;
+                } else if self.review_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.review_button_hitbox.unwrap())
+                {
+                    self.review_button_pressed = true;
                 } else if self.viewer_button_hitbox.is_some()
                     && Canvas::is_hitting(position, self.viewer_button_hitbox.unwrap())
                 {
                     self.viewer_button_pressed = true;
+                } else if self.diagnostics_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.diagnostics_button_hitbox.unwrap())
+                {
+                    self.diagnostics_button_pressed = true;
+                } else if self.settings_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.settings_button_hitbox.unwrap())
+                {
+                    self.settings_button_pressed = true;
+                } else if self.scoresheet_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.scoresheet_button_hitbox.unwrap())
+                {
+                    self.scoresheet_button_pressed = true;
+                } else if self.clock_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.clock_button_hitbox.unwrap())
+                {
+                    self.clock_button_pressed = true;
+                } else if self.correspondence_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.correspondence_button_hitbox.unwrap())
+                {
+                    self.correspondence_button_pressed = true;
+                } else if self.saves_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.saves_button_hitbox.unwrap())
+                {
+                    self.saves_button_pressed = true;
+                } else if let Some((recent_game, _)) = self
+                    .recent_games
+                    .iter()
+                    .find(|(_, hitbox)| Canvas::is_hitting(position, *hitbox))
+                {
+                    match crate::pgns::read_game(&recent_game.pgn, &recent_game.game) {
+                        Ok(game) => self.opened_recent_game = Some((game, recent_game.clone())),
+                        Err(err) => error!("Failed to read recent game: {:?}", err),
+                    }
                 } else if self.exit_button_hitbox.is_some()
                     && Canvas::is_hitting(position, self.exit_button_hitbox.unwrap())
                 {
@@ -0,0 +1,87 @@
+use crate::canvas::*;
+use crate::scene::game_scene::{draw_square_background, ALL_PIECES};
+use crate::scene::piece_images::get_piece_img;
+use crate::settings::BoardTheme;
+use fxhash::FxHashMap;
+use libremarkable::image;
+
+/// Small diagram of a position, e.g. to tell games in a list apart.
+/// The piece images are scaled once when it is created.
+pub struct MiniBoard {
+    square_size: u32,
+    img_pieces: FxHashMap<char, image::DynamicImage>,
+}
+
+impl MiniBoard {
+    pub fn new(square_size: u32) -> Self {
+        let mut img_pieces: FxHashMap<char, image::DynamicImage> = Default::default();
+        for piece in ALL_PIECES.iter() {
+            img_pieces.insert(piece.character_lossy(), get_piece_img(piece, square_size));
+        }
+        Self {
+            square_size,
+            img_pieces,
+        }
+    }
+
+    /// Width and height of the whole board
+    pub fn size(&self) -> u32 {
+        self.square_size * 8
+    }
+
+    /// Draw the position of `fen` from white's side. Only the piece
+    /// placement is used (pockets in brackets are ignored). Solid squares
+    /// stay readable at any size, so the board theme isn't used.
+    pub fn draw(&self, canvas: &mut Canvas, top_left: Point2<i32>, fen: &str) -> mxcfb_rect {
+        let placement = fen
+            .split(|c: char| c.is_whitespace() || c == '[')
+            .next()
+            .unwrap_or("");
+        for y in 0..8 {
+            for x in 0..8 {
+                let bounds = mxcfb_rect {
+                    left: (top_left.x + (x * self.square_size) as i32) as u32,
+                    top: (top_left.y + (y * self.square_size) as i32) as u32,
+                    width: self.square_size,
+                    height: self.square_size,
+                };
+                draw_square_background(canvas, &bounds, x % 2 == y % 2, BoardTheme::Solid);
+            }
+        }
+        for (y, rank) in placement.split('/').take(8).enumerate() {
+            let mut x = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    x += empty;
+                    continue;
+                }
+                if let (Some(img), true) = (self.img_pieces.get(&c), x < 8) {
+                    canvas.draw_image(
+                        Point2 {
+                            x: top_left.x + (x * self.square_size) as i32,
+                            y: top_left.y + (y as u32 * self.square_size) as i32,
+                        },
+                        img,
+                        true,
+                    );
+                }
+                x += 1;
+            }
+        }
+        let board = mxcfb_rect {
+            left: top_left.x as u32,
+            top: top_left.y as u32,
+            width: self.size(),
+            height: self.size(),
+        };
+        canvas.draw_rect(
+            Point2 {
+                x: Some(top_left.x),
+                y: Some(top_left.y),
+            },
+            board.size().cast().unwrap(),
+            1,
+        );
+        board
+    }
+}
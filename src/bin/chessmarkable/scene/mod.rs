@@ -1,22 +1,66 @@
 mod board_select_scene;
+mod clock_scene;
+mod clock_widget;
+mod correspondence_scene;
+mod crash_scene;
+mod diagnostics_scene;
+mod dialog;
+mod drill_scene;
+mod editor_scene;
+mod endgame_scene;
+mod eval_graph;
 mod game_scene;
+mod gestures;
+mod handwriting_pad;
+mod ink;
+mod keyboard;
+mod log_scene;
 mod main_menu_scene;
+mod mini_board;
+mod move_list;
+mod pen_annotations;
 mod pgn_select_scene;
 mod piece_images;
+mod qr_code;
 mod replay_scene;
+mod review_scene;
+mod saves_scene;
+mod scoresheet_scene;
+mod settings_scene;
+mod simul_scene;
+mod tournament_scene;
 
 pub use board_select_scene::BoardSelectScene;
+pub use clock_scene::ClockScene;
+pub use correspondence_scene::CorrespondenceScene;
+pub use crash_scene::CrashScene;
+pub use diagnostics_scene::DiagnosticsScene;
+pub use drill_scene::DrillScene;
+pub use editor_scene::EditorScene;
+pub use endgame_scene::EndgameScene;
 pub use game_scene::{GameMode, GameScene, SavestateSlot};
+pub use log_scene::LogScene;
 pub use main_menu_scene::MainMenuScene;
 pub use pgn_select_scene::PgnSelectScene;
 pub use replay_scene::ReplayScene;
+pub use review_scene::ReviewScene;
+pub use saves_scene::SavesScene;
+pub use scoresheet_scene::ScoresheetScene;
+pub use settings_scene::SettingsScene;
+pub use simul_scene::SimulScene;
+pub use tournament_scene::TournamentScene;
 
 use crate::canvas::Canvas;
+use crate::settings::Orientation;
 use downcast_rs::Downcast;
 use libremarkable::input::InputEvent;
 
 pub trait Scene: Downcast {
     fn on_input(&mut self, _event: InputEvent) {}
     fn draw(&mut self, canvas: &mut Canvas);
+    /// Which way the device is held for the scene (see `Canvas::set_orientation()`)
+    fn orientation(&self) -> Orientation {
+        Orientation::Portrait
+    }
 }
 impl_downcast!(Scene);
@@ -0,0 +1,291 @@
+use crate::canvas::*;
+use chessmarkable::share::compact_pgn;
+use chessmarkable::Square;
+use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
+
+const NUMBER_WIDTH: u32 = 90;
+const PLY_WIDTH: u32 = 160;
+const FONT_SIZE: f32 = 40.0;
+/// A touch that moved less than this is a tap instead of a scroll
+const SCROLL_THRESHOLD: i32 = 30;
+
+pub struct MoveListEntry {
+    pub san: String,
+    /// Board after the move
+    pub fen: String,
+    /// `None` for dropped pieces
+    pub source: Option<Square>,
+    pub destination: Square,
+}
+
+pub enum MoveListEvent {
+    /// Show the position after the entry
+    Preview(usize),
+    /// The latest move was tapped
+    Latest,
+}
+
+/// Strip with the numbered moves (in SAN) of a game that can be
+/// scrolled horizontally. Scenes forward input to it and call `update()` each frame.
+pub struct MoveList {
+    /// Where the strip is drawn and touched
+    area: mxcfb_rect,
+    /// Position before the first entry
+    start_fen: Option<String>,
+    first_move_number: u16,
+    black_starts: bool,
+    entries: Vec<MoveListEntry>,
+    /// Entry whose position is shown instead of the latest one
+    previewed: Option<usize>,
+    /// In pixels from the left of the first entry
+    scroll: i32,
+    /// Finger x and scroll when the touch started
+    touch_start: Option<(i32, i32)>,
+    ply_hitboxes: Vec<(usize, mxcfb_rect)>,
+    redraw: bool,
+}
+
+impl MoveList {
+    pub fn new(area: mxcfb_rect) -> Self {
+        Self {
+            area,
+            start_fen: None,
+            first_move_number: 1,
+            black_starts: false,
+            entries: vec![],
+            previewed: None,
+            scroll: 0,
+            touch_start: None,
+            ply_hitboxes: vec![],
+            redraw: true,
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.start_fen.is_some()
+    }
+
+    /// Forget all moves and continue from `fen`
+    pub fn reset(&mut self, fen: &str) {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        self.black_starts = fields.get(1) == Some(&"b");
+        self.first_move_number = fields.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+        self.start_fen = Some(fen.to_owned());
+        self.entries.clear();
+        self.previewed = None;
+        self.scroll = 0;
+        self.redraw = true;
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&MoveListEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn last_fen(&self) -> Option<&str> {
+        match self.entries.last() {
+            Some(entry) => Some(&entry.fen),
+            None => self.start_fen.as_deref(),
+        }
+    }
+
+    /// The moves from the start as a PGN short enough to share
+    pub fn compact_pgn(&self) -> Option<String> {
+        let start_fen = self.start_fen.as_deref()?;
+        let moves: Vec<String> = self.entries.iter().map(|entry| entry.san.clone()).collect();
+        Some(compact_pgn(start_fen, &moves))
+    }
+
+    pub fn push(&mut self, entry: MoveListEntry) {
+        self.entries.push(entry);
+        self.scroll = self.max_scroll();
+        self.redraw = true;
+    }
+
+    /// Drop the moves after the position `fen` (e.g. after an undo).
+    /// Returns false if no entry has this position.
+    pub fn truncate_to(&mut self, fen: &str) -> bool {
+        let len = if self.start_fen.as_deref() == Some(fen) {
+            0
+        } else {
+            match self.entries.iter().rposition(|entry| entry.fen == fen) {
+                Some(index) => index + 1,
+                None => return false,
+            }
+        };
+        if len < self.entries.len() {
+            self.entries.truncate(len);
+            self.previewed = None;
+            self.scroll = self.max_scroll();
+            self.redraw = true;
+        }
+        true
+    }
+
+    pub fn set_previewed(&mut self, previewed: Option<usize>) {
+        if self.previewed != previewed {
+            self.previewed = previewed;
+            self.redraw = true;
+        }
+    }
+
+    /// Left edge of every entry (relative to the first one) and
+    /// the move number that is drawn in front of it (if any)
+    fn layout(&self) -> Vec<(i32, Option<String>)> {
+        let mut x = 0;
+        let mut layout = vec![];
+        for i in 0..self.entries.len() {
+            let ply = i + self.black_starts as usize;
+            let number = ply / 2 + self.first_move_number as usize;
+            let label = if ply % 2 == 0 {
+                Some(format!("{}.", number))
+            } else if i == 0 {
+                Some(format!("{}...", number))
+            } else {
+                None
+            };
+            if label.is_some() {
+                x += NUMBER_WIDTH as i32;
+            }
+            layout.push((x, label));
+            x += PLY_WIDTH as i32;
+        }
+        layout
+    }
+
+    fn max_scroll(&self) -> i32 {
+        let width = self
+            .layout()
+            .last()
+            .map_or(0, |(x, _)| x + PLY_WIDTH as i32);
+        (width - self.area.width as i32).max(0)
+    }
+
+    fn draw(&mut self, canvas: &mut Canvas) -> mxcfb_rect {
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(self.area.left as i32),
+                y: Some(self.area.top as i32),
+            },
+            Vector2 {
+                x: self.area.width,
+                y: self.area.height,
+            },
+            color::WHITE,
+        );
+        self.ply_hitboxes.clear();
+        let top = self.area.top as i32;
+        let baseline = top + self.area.height as i32 - 18;
+        let current = self.previewed.or(self.entries.len().checked_sub(1));
+        for (i, (x, label)) in self.layout().into_iter().enumerate() {
+            let offset = x - self.scroll;
+            if offset < 0 || offset + PLY_WIDTH as i32 > self.area.width as i32 {
+                continue; // Only draw entries that are fully visible
+            }
+            let left = self.area.left as i32 + offset;
+            if let Some(label) = label {
+                if offset >= NUMBER_WIDTH as i32 {
+                    canvas.draw_text(
+                        Point2 {
+                            x: Some(left - NUMBER_WIDTH as i32 + 10),
+                            y: Some(baseline),
+                        },
+                        &label,
+                        FONT_SIZE,
+                    );
+                }
+            }
+            let hitbox = mxcfb_rect {
+                top: self.area.top,
+                left: left as u32,
+                width: PLY_WIDTH,
+                height: self.area.height,
+            };
+            let style = if current == Some(i) {
+                FontStyle::Bold
+            } else {
+                FontStyle::Regular
+            };
+            canvas.draw_styled_text(
+                Point2 {
+                    x: Some(left + 15),
+                    y: Some(baseline),
+                },
+                &self.entries[i].san,
+                FONT_SIZE,
+                style,
+            );
+            if current == Some(i) {
+                canvas.draw_rect(
+                    Point2 {
+                        x: Some(left + 5),
+                        y: Some(top + 5),
+                    },
+                    Vector2 {
+                        x: PLY_WIDTH - 10,
+                        y: self.area.height - 10,
+                    },
+                    3,
+                );
+            }
+            self.ply_hitboxes.push((i, hitbox));
+        }
+        rect
+    }
+
+    /// Redraw the strip when it changed
+    pub fn update(&mut self, canvas: &mut Canvas) {
+        if self.redraw {
+            let rect = self.draw(canvas);
+            canvas.mark_dirty(&rect);
+            self.redraw = false;
+        }
+    }
+
+    /// Redraw on the next `update()` (e.g. after the screen was cleared)
+    pub fn request_redraw(&mut self) {
+        self.redraw = true;
+    }
+
+    pub fn on_input(&mut self, event: InputEvent) -> Option<MoveListEvent> {
+        if let InputEvent::MultitouchEvent { event } = event {
+            match event {
+                MultitouchEvent::Press { finger, .. }
+                    if Canvas::is_hitting(finger.pos, self.area) =>
+                {
+                    self.touch_start = Some((finger.pos.x as i32, self.scroll));
+                }
+                MultitouchEvent::Release { finger, .. } => {
+                    let (start_x, start_scroll) = self.touch_start.take()?;
+                    let moved = finger.pos.x as i32 - start_x;
+                    if moved.abs() >= SCROLL_THRESHOLD {
+                        self.scroll = (start_scroll - moved).max(0).min(self.max_scroll());
+                        self.redraw = true;
+                        return None;
+                    }
+                    let index = self
+                        .ply_hitboxes
+                        .iter()
+                        .find(|(_, hitbox)| Canvas::is_hitting(finger.pos, *hitbox))
+                        .map(|(index, _)| *index)?;
+                    return if index + 1 == self.entries.len() {
+                        Some(MoveListEvent::Latest)
+                    } else {
+                        Some(MoveListEvent::Preview(index))
+                    };
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Forget a touch that turned out to be something else (e.g. a gesture)
+    pub fn cancel_touch(&mut self) {
+        self.touch_start = None;
+    }
+
+    /// Whether a touch started on the strip (so it shouldn't be handled elsewhere)
+    pub fn is_touched(&self) -> bool {
+        self.touch_start.is_some()
+    }
+}
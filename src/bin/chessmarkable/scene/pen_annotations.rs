@@ -0,0 +1,201 @@
+use crate::canvas::*;
+use crate::scene::ink::Ink;
+use chessmarkable::annotation::{self, Annotation};
+use chessmarkable::Square;
+use libremarkable::input::{wacom::WacomEvent, wacom::WacomPen, InputEvent};
+
+const ARROW_WIDTH: u32 = 10;
+const ARROW_HEAD_LENGTH: f32 = 45.0;
+const DASH_LENGTH: f32 = 30.0;
+const DASH_GAP: f32 = 20.0;
+const CIRCLE_WIDTH: u32 = 6;
+const INK_RADIUS: u32 = 3;
+
+/// Arrows and circles drawn with the pen onto a board. A stroke
+/// from one square to another adds an arrow, a stroke within a
+/// square circles it. Drawing the same mark again removes it.
+/// Using the eraser or a stroke that ends outside of the board
+/// clears everything.
+#[derive(Default)]
+pub struct PenAnnotations {
+    pub annotations: Vec<Annotation>,
+    /// Points of the current stroke
+    stroke: Vec<Point2<i32>>,
+    ink: Ink,
+    erasing: bool,
+}
+
+fn square_at(hitboxes: &[Vec<mxcfb_rect>], pos: Point2<i32>) -> Option<Square> {
+    if pos.x < 0 || pos.y < 0 {
+        return None;
+    }
+    let pos = Point2 {
+        x: pos.x as u16,
+        y: pos.y as u16,
+    };
+    for (x, file) in hitboxes.iter().enumerate() {
+        for (y, hitbox) in file.iter().enumerate() {
+            if Canvas::is_hitting(pos, *hitbox) {
+                return Square::new(x, y).ok();
+            }
+        }
+    }
+    None
+}
+
+fn center(hitboxes: &[Vec<mxcfb_rect>], square: Square) -> Point2<i32> {
+    let bounds = hitboxes[square.x() as usize][square.y() as usize];
+    Point2 {
+        x: (bounds.left + bounds.width / 2) as i32,
+        y: (bounds.top + bounds.height / 2) as i32,
+    }
+}
+
+impl PenAnnotations {
+    pub fn new(annotations: Vec<Annotation>) -> Self {
+        Self {
+            annotations,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the annotations changed. The board then needs to be
+    /// redrawn completely (which also removes the ink of the stroke).
+    pub fn on_input(&mut self, event: InputEvent, hitboxes: &[Vec<mxcfb_rect>]) -> bool {
+        let event = match event {
+            InputEvent::WacomEvent { event } => event,
+            _ => return false,
+        };
+        match event {
+            WacomEvent::InstrumentChange { pen, state } => {
+                if pen == WacomPen::ToolRubber {
+                    self.erasing = state;
+                }
+                if !state {
+                    return self.finish_stroke(hitboxes);
+                }
+            }
+            WacomEvent::Draw { position, .. } => {
+                let pos = Point2 {
+                    x: position.x as i32,
+                    y: position.y as i32,
+                };
+                if self.erasing {
+                    if square_at(hitboxes, pos).is_some() && !self.annotations.is_empty() {
+                        self.annotations.clear();
+                        return true;
+                    }
+                } else {
+                    if square_at(hitboxes, pos).is_some() {
+                        self.ink.push(pos);
+                    }
+                    self.stroke.push(pos);
+                }
+            }
+            WacomEvent::Hover { .. } => return self.finish_stroke(hitboxes),
+            _ => {}
+        }
+        false
+    }
+
+    fn finish_stroke(&mut self, hitboxes: &[Vec<mxcfb_rect>]) -> bool {
+        if self.stroke.is_empty() {
+            return false;
+        }
+        let stroke = std::mem::take(&mut self.stroke);
+        self.ink.clear();
+        let start = match square_at(hitboxes, stroke[0]) {
+            Some(square) => square,
+            None => return true, // Only the ink needs to be removed
+        };
+        match square_at(hitboxes, *stroke.last().unwrap()) {
+            None => self.annotations.clear(),
+            Some(end) if end == start => {
+                annotation::toggle(&mut self.annotations, Annotation::Circle { square: start })
+            }
+            Some(end) => annotation::toggle(
+                &mut self.annotations,
+                Annotation::Arrow {
+                    from: start,
+                    to: end,
+                },
+            ),
+        }
+        true
+    }
+
+    /// Show the stroke that is currently drawn
+    pub fn update(&mut self, canvas: &mut Canvas) {
+        self.ink.show_dots(canvas, INK_RADIUS);
+    }
+
+    /// Draw all annotations on top of the board. Returns false if there are none.
+    pub fn draw(&self, canvas: &mut Canvas, hitboxes: &[Vec<mxcfb_rect>]) -> bool {
+        for annotation in &self.annotations {
+            match *annotation {
+                Annotation::Circle { square } => {
+                    let bounds = hitboxes[square.x() as usize][square.y() as usize];
+                    canvas.draw_circle(
+                        center(hitboxes, square),
+                        bounds.width / 2 - 8,
+                        CIRCLE_WIDTH,
+                    );
+                }
+                Annotation::Arrow { from, to } => {
+                    draw_arrow(canvas, hitboxes, from, to);
+                }
+            }
+        }
+        !self.annotations.is_empty()
+    }
+}
+
+/// Arrow from the center of `from` to the center of `to`. Returns its tip.
+pub fn draw_arrow(
+    canvas: &mut Canvas,
+    hitboxes: &[Vec<mxcfb_rect>],
+    from: Square,
+    to: Square,
+) -> Point2<i32> {
+    let start = center(hitboxes, from);
+    let end = center(hitboxes, to);
+    canvas.draw_line(start, end, ARROW_WIDTH);
+    draw_arrow_head(canvas, start, end);
+    end
+}
+
+/// Like `draw_arrow()`, but the line is dashed (e.g. for threats)
+pub fn draw_dashed_arrow(
+    canvas: &mut Canvas,
+    hitboxes: &[Vec<mxcfb_rect>],
+    from: Square,
+    to: Square,
+) {
+    let start = center(hitboxes, from);
+    let end = center(hitboxes, to);
+    let (dx, dy) = ((end.x - start.x) as f32, (end.y - start.y) as f32);
+    let length = (dx * dx + dy * dy).sqrt();
+    let point = |distance: f32| Point2 {
+        x: start.x + (dx * distance / length) as i32,
+        y: start.y + (dy * distance / length) as i32,
+    };
+    let mut distance = 0.0;
+    while distance < length {
+        let dash_end = (distance + DASH_LENGTH).min(length);
+        canvas.draw_line(point(distance), point(dash_end), ARROW_WIDTH);
+        distance = dash_end + DASH_GAP;
+    }
+    draw_arrow_head(canvas, start, end);
+}
+
+fn draw_arrow_head(canvas: &mut Canvas, start: Point2<i32>, end: Point2<i32>) {
+    let angle = ((end.y - start.y) as f32).atan2((end.x - start.x) as f32);
+    for side in &[-1.0f32, 1.0] {
+        let head_angle = angle + std::f32::consts::PI + side * 0.5;
+        let head_end = Point2 {
+            x: end.x + (head_angle.cos() * ARROW_HEAD_LENGTH) as i32,
+            y: end.y + (head_angle.sin() * ARROW_HEAD_LENGTH) as i32,
+        };
+        canvas.draw_line(end, head_end, ARROW_WIDTH);
+    }
+}
@@ -1,44 +1,86 @@
+use super::dialog::Dialog;
+use super::keyboard::{Keyboard, KeyboardEvent, FILE_NAME_LAYOUT, SEARCH_LAYOUT};
 use super::Scene;
 use crate::canvas::*;
 use crate::pgns::*;
 use crate::REPLAYS_PER_PAGE;
 use anyhow::Error;
-use chess_pgn_parser::{read_games, Game};
-use libremarkable::input::{multitouch::MultitouchEvent, InputEvent};
-use regex::Regex;
-use std::fs::File;
-use std::io::Read;
+use chess_pgn_parser::Game;
+use fxhash::FxHashMap;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 const BOX_HEIGHT: i32 = 180;
 const FIRST_BOX_Y_POS: i32 = 350;
+const KEYBOARD_Y_POS: i32 = 1000;
+/// Holding a PGN this long opens the actions to manage it
+const LONG_PRESS_DURATION: Duration = Duration::from_millis(600);
 
 const EVENT_TAG: &str = "Event";
 const WHITE_TAG: &str = "White";
 const BLACK_TAG: &str = "Black";
 const ROUND_TAG: &str = "Round";
+const RESULT_TAG: &str = "Result";
+const DATE_TAG: &str = "Date";
+
+/// Managing a PGN file. Most steps need to be confirmed in a dialog.
+enum PgnAction {
+    Menu(Pgn),
+    /// Waiting for the new name on the keyboard
+    Rename(Pgn),
+    ConfirmRename(Pgn, String),
+    ConfirmDelete(Pgn),
+    ConfirmMove(Pgn, PathBuf),
+    /// Only informs about the outcome of an action
+    Notice,
+}
 
 pub struct PgnSelectScene {
     drawn: bool,
-    pub game_vec: Vec<Game>,
-    pgn_vec: Vec<Pgn>,
+    /// Games of the selected PGN
+    games: Vec<IndexedGame>,
+    /// Indices into `games` matching the search query
+    visible_games: Vec<usize>,
+    /// Folders and PGNs on the current page
+    entries: Vec<PgnEntry>,
+    current_folder: PathBuf,
+    /// Lazily read for searching
+    pgn_metadata: FxHashMap<PathBuf, PgnMetadata>,
+    search_query: String,
+    search_button_hitbox: Option<mxcfb_rect>,
+    sort_button_hitbox: Option<mxcfb_rect>,
+    lichess_button_hitbox: Option<mxcfb_rect>,
+    downloading_from_lichess: bool,
+    sync_button_hitbox: Option<mxcfb_rect>,
+    syncing: bool,
+    keyboard: Option<Keyboard>,
+    keyboard_drawn: bool,
+    pgn_action: Option<PgnAction>,
+    dialog: Option<Dialog>,
+    dialog_drawn: bool,
+    /// PGN waiting for a folder to be moved to
+    moving_pgn: Option<Pgn>,
+    press_started: Option<SystemTime>,
     pub selected_pgn: Option<Pgn>,
     selected_pgn_changed: bool,
+    opened_game: Option<Game>,
+    /// Offset of the opened game inside the PGN
+    opened_game_offset: u64,
 
     pub current_page_number: u32,
     total_pages: u32,
 
     button_1_hitbox: Option<mxcfb_rect>,
-    pub button_1_pressed: bool,
     button_2_hitbox: Option<mxcfb_rect>,
-    pub button_2_pressed: bool,
     button_3_hitbox: Option<mxcfb_rect>,
-    pub button_3_pressed: bool,
     button_4_hitbox: Option<mxcfb_rect>,
-    pub button_4_pressed: bool,
     button_5_hitbox: Option<mxcfb_rect>,
-    pub button_5_pressed: bool,
     button_6_hitbox: Option<mxcfb_rect>,
-    pub button_6_pressed: bool,
 
     next_page_button_hitbox: Option<mxcfb_rect>,
     prev_page_button_hitbox: Option<mxcfb_rect>,
@@ -56,15 +98,10 @@ impl PgnSelectScene {
             current_page_number: 0,
             total_pages: 1,
             button_1_hitbox: None,
-            button_1_pressed: false,
             button_2_hitbox: None,
-            button_2_pressed: false,
             button_3_hitbox: None,
-            button_3_pressed: false,
             button_4_hitbox: None,
-            button_4_pressed: false,
             button_5_hitbox: None,
-            button_5_pressed: false,
             button_6_hitbox: None,
             next_page_button_hitbox: None,
             prev_page_button_hitbox: None,
@@ -73,9 +110,27 @@ impl PgnSelectScene {
             indicate_loading: false,
             selected_pgn_changed,
             selected_pgn,
-            pgn_vec: vec![],
-            game_vec: vec![],
-            button_6_pressed: false,
+            entries: vec![],
+            current_folder: last_pgn_folder(),
+            pgn_metadata: Default::default(),
+            search_query: String::new(),
+            search_button_hitbox: None,
+            sort_button_hitbox: None,
+            lichess_button_hitbox: None,
+            downloading_from_lichess: false,
+            sync_button_hitbox: None,
+            syncing: false,
+            keyboard: None,
+            keyboard_drawn: false,
+            pgn_action: None,
+            dialog: None,
+            dialog_drawn: false,
+            moving_pgn: None,
+            press_started: None,
+            opened_game: None,
+            opened_game_offset: 0,
+            games: vec![],
+            visible_games: vec![],
         }
     }
 
@@ -90,6 +145,76 @@ impl PgnSelectScene {
         );
         canvas.update_partial(&rect);
     }
+
+    fn download_from_lichess(&mut self, canvas: &mut Canvas) {
+        let rect = canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(1480),
+            },
+            "Downloading games from lichess..",
+            50.0,
+        );
+        canvas.update_partial(&rect);
+
+        let token = crate::SETTINGS.lock().unwrap().lichess_token.clone();
+        let result = match token {
+            Some(token) => crate::lichess::download_recent_games(&token),
+            None => Err(anyhow!("No lichess token configured")),
+        };
+        self.drawn = false;
+        match result {
+            Ok(downloaded) => {
+                self.pgn_metadata.clear();
+                self.open_folder(crate::lichess::folder());
+                let message = match downloaded {
+                    0 => "No new games on lichess".to_owned(),
+                    1 => "Downloaded 1 new game".to_owned(),
+                    num => format!("Downloaded {} new games", num),
+                };
+                self.open_dialog(PgnAction::Notice, Dialog::new(&message, &["OK"]));
+            }
+            Err(err) => self.show_error(format!("Failed to download games: {}", err)),
+        }
+    }
+
+    fn sync_with_webdav(&mut self, canvas: &mut Canvas) {
+        let rect = canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(1480),
+            },
+            "Syncing games and savestates..",
+            50.0,
+        );
+        canvas.update_partial(&rect);
+
+        let webdav = crate::SETTINGS.lock().unwrap().webdav.clone();
+        let result = match webdav {
+            Some(ref webdav) => crate::sync::sync(webdav),
+            None => Err(anyhow!("No WebDAV server configured")),
+        };
+        self.drawn = false;
+        match result {
+            Ok(summary) => {
+                let mut settings = crate::SETTINGS.lock().unwrap();
+                if let Some(ref mut webdav) = settings.webdav {
+                    webdav.last_sync = crate::correspondence::now();
+                }
+                if let Err(err) = crate::settings::write(&settings) {
+                    error!("Failed to write settings file: {:?}", err);
+                }
+                drop(settings);
+                self.pgn_metadata.clear();
+                let message = format!(
+                    "Pushed {} and downloaded {} file(s)",
+                    summary.pushed, summary.pulled
+                );
+                self.open_dialog(PgnAction::Notice, Dialog::new(&message, &["OK"]));
+            }
+            Err(err) => self.show_error(format!("Failed to sync: {}", err)),
+        }
+    }
 }
 
 impl Scene for PgnSelectScene {
@@ -98,105 +223,158 @@ impl Scene for PgnSelectScene {
             self.indicate_loading(canvas);
             return;
         }
+        if self.downloading_from_lichess {
+            self.downloading_from_lichess = false;
+            self.download_from_lichess(canvas);
+        }
+        if self.syncing {
+            self.syncing = false;
+            self.sync_with_webdav(canvas);
+        }
+        if let Some(ref mut keyboard) = self.keyboard {
+            if !self.keyboard_drawn {
+                let rect = keyboard.draw(canvas);
+                canvas.mark_dirty(&rect);
+                self.keyboard_drawn = true;
+            }
+            keyboard.update(canvas);
+            return;
+        }
         if self.drawn {
+            if let Some(ref mut dialog) = self.dialog {
+                if !self.dialog_drawn {
+                    let rect = dialog.draw(canvas);
+                    canvas.mark_dirty(&rect);
+                    self.dialog_drawn = true;
+                }
+            }
             return;
         }
         self.drawn = true;
+        self.dialog_drawn = false;
 
         canvas.clear();
         let choose_pgn_mode = !self.selected_pgn.is_some();
         if choose_pgn_mode {
-            self.total_pages = match crate::pgns::total_number_of_pgn() {
+            let matching_entries = self.matching_entries();
+            self.total_pages = match matching_entries.len() {
                 0 => 1,
                 num => (num as f64 / REPLAYS_PER_PAGE as f64).ceil() as u32,
             };
-            self.pgn_vec = match crate::pgns::read(
-                (self.current_page_number * REPLAYS_PER_PAGE) as usize,
-                ((self.current_page_number + 1) * REPLAYS_PER_PAGE - 1) as usize,
-            ) {
-                Ok(vec) => vec,
-                Err(_) => Vec::new(),
-            };
+            self.entries = matching_entries
+                .into_iter()
+                .skip((self.current_page_number * REPLAYS_PER_PAGE) as usize)
+                .take(REPLAYS_PER_PAGE as usize)
+                .collect();
             let mut no_pgn_found_str = "No PGNs found, please add them to: ".to_string();
             no_pgn_found_str.push_str(
-                &crate::CLI_OPTS
-                    .pgn_location
+                &crate::config::pgn_location()
                     .to_owned()
                     .into_os_string()
                     .into_string()
                     .unwrap(),
             );
-            if self.pgn_vec.len() == 0 {
-                canvas.draw_multi_line_text(None, 700, &no_pgn_found_str, 50, 2, 85.0, 0.8);
-            } else {
+            for entry in self.entries.clone().iter() {
+                if let PgnEntry::Pgn(pgn) = entry {
+                    self.metadata(pgn);
+                }
+            }
+            if self.entries.len() == 0 && !self.search_query.is_empty() {
                 canvas.draw_text(
                     Point2 {
                         x: None,
-                        y: Some(300),
+                        y: Some(700),
                     },
-                    "Choose PGN:",
+                    "No PGNs match your search",
+                    75.0,
+                );
+            } else if self.entries.len() == 0 && !self.is_in_subfolder() {
+                canvas.draw_wrapped_text(
+                    None,
+                    700,
+                    DISPLAYWIDTH as u32 - 100,
+                    2,
+                    &no_pgn_found_str,
+                    85.0,
+                    FontStyle::Regular,
+                );
+            } else if self.entries.len() == 0 {
+                canvas.draw_text(
+                    Point2 {
+                        x: None,
+                        y: Some(700),
+                    },
+                    "This folder is empty",
                     75.0,
                 );
             }
-            self.button_1_hitbox =
-                draw_button_for_pgn(canvas, self.pgn_vec.get(0), FIRST_BOX_Y_POS, 50.0);
-            self.button_2_hitbox = draw_button_for_pgn(
+            let heading = match self.relative_folder() {
+                Some(folder) if self.is_in_subfolder() => format!("{}/", folder.display()),
+                _ => "Choose PGN:".to_owned(),
+            };
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(300),
+                },
+                &heading,
+                75.0,
+            );
+            self.button_1_hitbox = draw_button_for_entry(
                 canvas,
-                self.pgn_vec.get(1),
+                self.entries.get(0),
+                &self.pgn_metadata,
+                FIRST_BOX_Y_POS,
+                50.0,
+            );
+            self.button_2_hitbox = draw_button_for_entry(
+                canvas,
+                self.entries.get(1),
+                &self.pgn_metadata,
                 FIRST_BOX_Y_POS + BOX_HEIGHT,
                 50.0,
             );
-            self.button_3_hitbox = draw_button_for_pgn(
+            self.button_3_hitbox = draw_button_for_entry(
                 canvas,
-                self.pgn_vec.get(2),
+                self.entries.get(2),
+                &self.pgn_metadata,
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 2,
                 50.0,
             );
-            self.button_4_hitbox = draw_button_for_pgn(
+            self.button_4_hitbox = draw_button_for_entry(
                 canvas,
-                self.pgn_vec.get(3),
+                self.entries.get(3),
+                &self.pgn_metadata,
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 3,
                 50.0,
             );
-            self.button_5_hitbox = draw_button_for_pgn(
+            self.button_5_hitbox = draw_button_for_entry(
                 canvas,
-                self.pgn_vec.get(4),
+                self.entries.get(4),
+                &self.pgn_metadata,
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 4,
                 50.0,
             );
-            self.button_6_hitbox = draw_button_for_pgn(
+            self.button_6_hitbox = draw_button_for_entry(
                 canvas,
-                self.pgn_vec.get(5),
+                self.entries.get(5),
+                &self.pgn_metadata,
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 5,
                 50.0,
             );
         } else {
             if self.selected_pgn_changed {
-                let mut file =
-                    File::open(self.selected_pgn.as_ref().unwrap().path.to_str().unwrap()).unwrap();
-                let mut png_file_contents = String::new();
-                file.read_to_string(&mut png_file_contents)
-                    .expect("Unable to read file");
-                //Library doesn't play nice with comments inside brackets
-                //This gets rid of up to two levels of bracket nesting
-                let re = Regex::new(r"\((?:[^)(]|\((?:[^)(]|\([^)(]*\))*\))*\)").unwrap();
-                let result = re.replace_all(png_file_contents.as_str(), "");
-                let re = Regex::new(r"\n").unwrap();
-                let result = re.replace_all(&result, " ");
-                let re = Regex::new(r"\s\s").unwrap();
-                let result = re.replace_all(&result, " ");
-                self.game_vec = match read_games(&result) {
-                    Ok(games) => games,
+                self.games = match read_index(self.selected_pgn.as_ref().unwrap()) {
+                    Ok(index) => index.games,
                     Err(e) => {
-                        println!("{:?}", e);
+                        error!("Failed to index PGN: {:?}", e);
                         vec![]
                     }
                 };
-                self.total_pages =
-                    (self.game_vec.len() as f64 / REPLAYS_PER_PAGE as f64).ceil() as u32;
                 self.selected_pgn_changed = false;
             }
-            if self.game_vec.len() == 0 {
+            self.update_visible_games();
+            if self.games.len() == 0 {
                 canvas.draw_text(
                     Point2 {
                         x: None,
@@ -205,6 +383,15 @@ impl Scene for PgnSelectScene {
                     "Couldn't parse any games from PGN",
                     75.0,
                 );
+            } else if self.visible_games.len() == 0 {
+                canvas.draw_text(
+                    Point2 {
+                        x: None,
+                        y: Some(700),
+                    },
+                    "No games match your search",
+                    75.0,
+                );
             } else {
                 canvas.draw_text(
                     Point2 {
@@ -215,40 +402,35 @@ impl Scene for PgnSelectScene {
                     75.0,
                 );
             }
-            let index_of_first_game = (self.current_page_number * REPLAYS_PER_PAGE) as usize;
-            self.button_1_hitbox = draw_button_for_game(
-                canvas,
-                self.game_vec.get(index_of_first_game),
-                FIRST_BOX_Y_POS,
-                50.0,
-            );
+            self.button_1_hitbox =
+                draw_button_for_game(canvas, self.visible_game(0), FIRST_BOX_Y_POS, 50.0);
             self.button_2_hitbox = draw_button_for_game(
                 canvas,
-                self.game_vec.get(index_of_first_game + 1),
+                self.visible_game(1),
                 FIRST_BOX_Y_POS + BOX_HEIGHT,
                 50.0,
             );
             self.button_3_hitbox = draw_button_for_game(
                 canvas,
-                self.game_vec.get(index_of_first_game + 2),
+                self.visible_game(2),
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 2,
                 50.0,
             );
             self.button_4_hitbox = draw_button_for_game(
                 canvas,
-                self.game_vec.get(index_of_first_game + 3),
+                self.visible_game(3),
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 3,
                 50.0,
             );
             self.button_5_hitbox = draw_button_for_game(
                 canvas,
-                self.game_vec.get(index_of_first_game + 4),
+                self.visible_game(4),
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 4,
                 50.0,
             );
             self.button_6_hitbox = draw_button_for_game(
                 canvas,
-                self.game_vec.get(index_of_first_game + 5),
+                self.visible_game(5),
                 FIRST_BOX_Y_POS + BOX_HEIGHT * 5,
                 50.0,
             );
@@ -262,6 +444,7 @@ impl Scene for PgnSelectScene {
             150.0,
         );
         let back_button_text = match choose_pgn_mode {
+            true if self.is_in_subfolder() => "Back",
             true => "Main Menu",
             false => "PGNs",
         };
@@ -308,78 +491,261 @@ impl Scene for PgnSelectScene {
             None
         };
 
+        let search_button_text = if self.moving_pgn.is_some() {
+            "Move here".to_owned()
+        } else if self.search_query.is_empty() {
+            "Search".to_owned()
+        } else if self.search_query.chars().count() > 12 {
+            let shortened_query: String = self.search_query.chars().take(11).collect();
+            format!("Search: {}..", shortened_query)
+        } else {
+            format!("Search: {}", self.search_query)
+        };
+        self.search_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(100),
+                y: Some(1560),
+            },
+            &search_button_text,
+            50.0,
+            15,
+            40,
+        ));
+        self.sort_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 / 2 + 100),
+                y: Some(1560),
+            },
+            &if self.moving_pgn.is_some() {
+                "Cancel move".to_owned()
+            } else {
+                format!(
+                    "Sort: {}",
+                    crate::SETTINGS.lock().unwrap().pgn_sort_order.label()
+                )
+            },
+            50.0,
+            15,
+            40,
+        ));
+        let has_lichess_token = crate::SETTINGS.lock().unwrap().lichess_token.is_some();
+        self.lichess_button_hitbox =
+            if choose_pgn_mode && self.moving_pgn.is_none() && has_lichess_token {
+                Some(canvas.draw_button(
+                    Point2 {
+                        x: Some(DISPLAYWIDTH as i32 - 250),
+                        y: Some(1840),
+                    },
+                    "lichess",
+                    40.0,
+                    10,
+                    20,
+                ))
+            } else {
+                None
+            };
+        let has_webdav = crate::SETTINGS.lock().unwrap().webdav.is_some();
+        self.sync_button_hitbox = if choose_pgn_mode && self.moving_pgn.is_none() && has_webdav {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: Some(40),
+                    y: Some(1840),
+                },
+                "Sync",
+                40.0,
+                10,
+                20,
+            ))
+        } else {
+            None
+        };
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(1830),
+            },
+            &if choose_pgn_mode && self.moving_pgn.is_none() {
+                format!(
+                    "Page {}/{} - Hold a PGN to manage it",
+                    self.current_page_number + 1,
+                    self.total_pages
+                )
+            } else {
+                format!("Page {}/{}", self.current_page_number + 1, self.total_pages)
+            },
+            40.0,
+        );
+
         canvas.update_full();
     }
 
     fn on_input(&mut self, event: InputEvent) {
+        if let Some(ref mut keyboard) = self.keyboard {
+            match keyboard.on_input(event) {
+                Some(KeyboardEvent::Done) => {
+                    let text = keyboard.text.trim().to_owned();
+                    self.close_keyboard();
+                    if let Some(PgnAction::Rename(pgn)) = self.pgn_action.take() {
+                        let message = format!("Rename {} to {}?", file_name(&pgn), text);
+                        self.open_dialog(
+                            PgnAction::ConfirmRename(pgn, text),
+                            Dialog::new(&message, &["Cancel", "Rename"]),
+                        );
+                    } else {
+                        self.search_query = text;
+                    }
+                }
+                Some(KeyboardEvent::Cancelled) => {
+                    self.pgn_action = None;
+                    self.close_keyboard();
+                }
+                Some(KeyboardEvent::Edited) | None => {}
+            }
+            return;
+        }
+        if let Some(ref mut dialog) = self.dialog {
+            if let Some(choice) = dialog.on_input(event) {
+                self.on_dialog_choice(choice);
+            }
+            return;
+        }
+        if let InputEvent::GPIO {
+            event: GPIOEvent::Press { button },
+        } = event
+        {
+            match button {
+                PhysicalButton::LEFT => self.go_to_prev_page(),
+                PhysicalButton::RIGHT => self.go_to_next_page(),
+                PhysicalButton::MIDDLE => self.go_back(),
+                _ => {}
+            }
+            return;
+        }
         if let InputEvent::MultitouchEvent { event } = event {
+            if let MultitouchEvent::Press { .. } = event {
+                self.press_started = Some(SystemTime::now());
+            }
             if let MultitouchEvent::Release { finger, .. } = event {
                 let position = finger.pos;
+                let is_long_press = self
+                    .press_started
+                    .take()
+                    .and_then(|started| started.elapsed().ok())
+                    .map(|held| held >= LONG_PRESS_DURATION)
+                    .unwrap_or(false);
+                if self.moving_pgn.is_some() {
+                    if self.search_button_hitbox.is_some()
+                        && Canvas::is_hitting(position, self.search_button_hitbox.unwrap())
+                    {
+                        let pgn = self.moving_pgn.clone().unwrap();
+                        let folder = self.current_folder.clone();
+                        let message = format!(
+                            "Move {} to {}/?",
+                            file_name(&pgn),
+                            folder.file_name().unwrap_or_default().to_string_lossy()
+                        );
+                        self.open_dialog(
+                            PgnAction::ConfirmMove(pgn, folder),
+                            Dialog::new(&message, &["Cancel", "Move"]),
+                        );
+                        return;
+                    }
+                    if self.sort_button_hitbox.is_some()
+                        && Canvas::is_hitting(position, self.sort_button_hitbox.unwrap())
+                    {
+                        self.moving_pgn = None;
+                        self.drawn = false;
+                        return;
+                    }
+                }
+                if self.search_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.search_button_hitbox.unwrap())
+                {
+                    self.keyboard = Some(Keyboard::new(
+                        SEARCH_LAYOUT,
+                        &self.search_query,
+                        KEYBOARD_Y_POS,
+                    ));
+                    self.keyboard_drawn = false;
+                    return;
+                }
+                if self.sort_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.sort_button_hitbox.unwrap())
+                {
+                    self.cycle_sort_order();
+                    return;
+                }
+                if self.lichess_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.lichess_button_hitbox.unwrap())
+                {
+                    self.downloading_from_lichess = true;
+                    return;
+                }
+                if self.sync_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.sync_button_hitbox.unwrap())
+                {
+                    self.syncing = true;
+                    return;
+                }
                 if self.selected_pgn.is_some() {
                     if self.back_button_hitbox.is_some()
                         && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
                     {
-                        self.unload_pgn();
+                        self.go_back();
                     } else if self.button_1_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_1_hitbox.unwrap())
                     {
-                        self.indicate_loading = true;
-                        self.button_1_pressed = true;
+                        self.open_game(0);
                     } else if self.button_2_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_2_hitbox.unwrap())
                     {
-                        self.indicate_loading = true;
-                        self.button_2_pressed = true;
+                        self.open_game(1);
                     } else if self.button_3_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_3_hitbox.unwrap())
                     {
-                        self.indicate_loading = true;
-                        self.button_3_pressed = true;
+                        self.open_game(2);
                     } else if self.button_4_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_4_hitbox.unwrap())
                     {
-                        self.indicate_loading = true;
-                        self.button_4_pressed = true;
+                        self.open_game(3);
                     } else if self.button_5_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_5_hitbox.unwrap())
                     {
-                        self.indicate_loading = true;
-                        self.button_5_pressed = true;
+                        self.open_game(4);
                     } else if self.button_6_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_6_hitbox.unwrap())
                     {
-                        self.indicate_loading = true;
-                        self.button_6_pressed = true;
+                        self.open_game(5);
                     }
                 } else {
                     if self.back_button_hitbox.is_some()
                         && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
                     {
-                        self.return_to_main_menu = true;
+                        self.go_back();
                     } else if self.button_1_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_1_hitbox.unwrap())
                     {
-                        self.load_pgn(self.pgn_vec[0].clone())
+                        self.open_entry(0, is_long_press)
                     } else if self.button_2_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_2_hitbox.unwrap())
                     {
-                        self.load_pgn(self.pgn_vec[1].clone())
+                        self.open_entry(1, is_long_press)
                     } else if self.button_3_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_3_hitbox.unwrap())
                     {
-                        self.load_pgn(self.pgn_vec[2].clone())
+                        self.open_entry(2, is_long_press)
                     } else if self.button_4_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_4_hitbox.unwrap())
                     {
-                        self.load_pgn(self.pgn_vec[3].clone())
+                        self.open_entry(3, is_long_press)
                     } else if self.button_5_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_5_hitbox.unwrap())
                     {
-                        self.load_pgn(self.pgn_vec[4].clone())
+                        self.open_entry(4, is_long_press)
                     } else if self.button_6_hitbox.is_some()
                         && Canvas::is_hitting(position, self.button_6_hitbox.unwrap())
                     {
-                        self.load_pgn(self.pgn_vec[5].clone())
+                        self.open_entry(5, is_long_press)
                     }
                 }
                 if self.next_page_button_hitbox.is_some()
@@ -411,88 +777,423 @@ impl PgnSelectScene {
         }
     }
 
+    /// Close the PGN or folder (or the scene when there is none)
+    fn go_back(&mut self) {
+        if self.selected_pgn.is_some() {
+            self.unload_pgn();
+        } else if self.is_in_subfolder() {
+            let parent_folder = self.current_folder.parent().unwrap().to_owned();
+            self.open_folder(parent_folder);
+        } else {
+            self.return_to_main_menu = true;
+        }
+    }
+
     fn load_pgn(&mut self, pgn: Pgn) {
         self.selected_pgn = Some(pgn);
         self.selected_pgn_changed = true;
         self.drawn = false;
         self.current_page_number = 0;
+        self.search_query.clear();
     }
 
     fn unload_pgn(&mut self) {
         self.selected_pgn = None;
         self.drawn = false;
         self.current_page_number = 0;
+        self.search_query.clear();
+    }
+
+    fn cycle_sort_order(&mut self) {
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        settings.pgn_sort_order = settings.pgn_sort_order.next();
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+        self.drawn = false;
+        self.current_page_number = 0;
+    }
+
+    fn close_keyboard(&mut self) {
+        self.keyboard = None;
+        self.drawn = false;
+        self.current_page_number = 0;
+    }
+
+    fn metadata(&mut self, pgn: &Pgn) -> &PgnMetadata {
+        if !self.pgn_metadata.contains_key(&pgn.path) {
+            let metadata = read_metadata(pgn).unwrap_or_else(|e| {
+                warn!("Failed to read metadata of {:?}: {:?}", pgn.path, e);
+                PgnMetadata::default()
+            });
+            self.pgn_metadata.insert(pgn.path.clone(), metadata);
+        }
+        &self.pgn_metadata[&pgn.path]
+    }
+
+    fn is_in_subfolder(&self) -> bool {
+        self.current_folder != crate::config::pgn_location()
+    }
+
+    fn relative_folder(&self) -> Option<&Path> {
+        self.current_folder
+            .strip_prefix(&crate::config::pgn_location())
+            .ok()
+    }
+
+    fn open_folder(&mut self, folder: PathBuf) {
+        self.current_folder = folder;
+        self.drawn = false;
+        self.current_page_number = 0;
+        self.search_query.clear();
+
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        settings.last_pgn_folder = self.relative_folder().map(|folder| folder.to_owned());
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+    }
+
+    fn open_entry(&mut self, index: usize, is_long_press: bool) {
+        match self.entries.get(index).cloned() {
+            Some(PgnEntry::Folder(folder)) => self.open_folder(folder),
+            Some(PgnEntry::Pgn(_)) if self.moving_pgn.is_some() => {}
+            Some(PgnEntry::Pgn(pgn)) if is_long_press => {
+                let message = format!("What do you want to do with {}?", file_name(&pgn));
+                self.open_dialog(
+                    PgnAction::Menu(pgn),
+                    Dialog::new(&message, &["Rename", "Move", "Delete", "Cancel"]),
+                );
+            }
+            Some(PgnEntry::Pgn(pgn)) => self.load_pgn(pgn),
+            None => {}
+        }
+    }
+
+    fn open_dialog(&mut self, action: PgnAction, dialog: Dialog) {
+        self.pgn_action = Some(action);
+        self.dialog = Some(dialog);
+        self.dialog_drawn = false;
+    }
+
+    fn show_error(&mut self, message: String) {
+        error!("{}", message);
+        self.open_dialog(PgnAction::Notice, Dialog::new(&message, &["OK"]));
+    }
+
+    fn on_dialog_choice(&mut self, choice: usize) {
+        self.dialog = None;
+        self.drawn = false;
+        match self.pgn_action.take() {
+            Some(PgnAction::Menu(pgn)) => match choice {
+                0 => {
+                    let name = pgn
+                        .path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned();
+                    self.keyboard = Some(Keyboard::new(FILE_NAME_LAYOUT, &name, KEYBOARD_Y_POS));
+                    self.keyboard_drawn = false;
+                    self.pgn_action = Some(PgnAction::Rename(pgn));
+                }
+                1 => self.moving_pgn = Some(pgn),
+                2 => {
+                    let message = format!("Delete {}? This can't be undone.", file_name(&pgn));
+                    self.open_dialog(
+                        PgnAction::ConfirmDelete(pgn),
+                        Dialog::new(&message, &["Cancel", "Delete"]),
+                    );
+                }
+                _ => {}
+            },
+            Some(PgnAction::ConfirmDelete(pgn)) if choice == 1 => {
+                self.pgn_metadata.remove(&pgn.path);
+                if let Err(err) = crate::pgns::delete(&pgn) {
+                    self.show_error(format!("Failed to delete {}: {}", file_name(&pgn), err));
+                }
+            }
+            Some(PgnAction::ConfirmRename(pgn, new_name)) if choice == 1 => {
+                self.pgn_metadata.remove(&pgn.path);
+                if let Err(err) = crate::pgns::rename(&pgn, &new_name) {
+                    self.show_error(format!("Failed to rename {}: {}", file_name(&pgn), err));
+                }
+            }
+            Some(PgnAction::ConfirmMove(pgn, folder)) if choice == 1 => {
+                self.moving_pgn = None;
+                self.pgn_metadata.remove(&pgn.path);
+                if let Err(err) = crate::pgns::move_to_folder(&pgn, &folder) {
+                    self.show_error(format!("Failed to move {}: {}", file_name(&pgn), err));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Folders followed by PGNs of the current folder matching the search query
+    fn matching_entries(&mut self) -> Vec<PgnEntry> {
+        let folders = crate::pgns::read_folders(&self.current_folder).unwrap_or_else(|e| {
+            warn!(
+                "Failed to read folders of {:?}: {:?}",
+                self.current_folder, e
+            );
+            vec![]
+        });
+        let query = &self.search_query;
+        let mut entries: Vec<PgnEntry> = folders
+            .into_iter()
+            .filter(|folder| {
+                let folder_name = folder.file_name().unwrap_or_default().to_string_lossy();
+                matches_query(query, std::iter::once(folder_name.as_ref()))
+            })
+            .map(PgnEntry::Folder)
+            .collect();
+        entries.extend(self.matching_pgns().into_iter().map(PgnEntry::Pgn));
+        entries
+    }
+
+    /// All PGNs whose file name or tags match the search query
+    /// in the chosen sort order
+    fn matching_pgns(&mut self) -> Vec<Pgn> {
+        let query = self.search_query.clone();
+        let sort_order = crate::SETTINGS.lock().unwrap().pgn_sort_order;
+        let pgns = crate::pgns::read_all(&self.current_folder).unwrap_or_default();
+        if query.is_empty() && sort_order == PgnSortOrder::FileName {
+            // No need to read any metadata
+            return pgns;
+        }
+        let mut matching_pgns = vec![];
+        for pgn in pgns {
+            let file_name = pgn
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let metadata = self.metadata(&pgn);
+            let tag_values = metadata
+                .games
+                .iter()
+                .flat_map(|tags| tags.iter().map(|(_, value)| value.as_str()));
+            if matches_query(
+                &query,
+                std::iter::once(file_name.as_str()).chain(tag_values),
+            ) {
+                matching_pgns.push(pgn);
+            }
+        }
+
+        let pgn_metadata = &self.pgn_metadata;
+        matching_pgns.sort_by(|a, b| {
+            sort_order.compare(
+                first_game_tags(pgn_metadata, a),
+                first_game_tags(pgn_metadata, b),
+            )
+        });
+        matching_pgns
+    }
+
+    fn update_visible_games(&mut self) {
+        let query = &self.search_query;
+        self.visible_games = self
+            .games
+            .iter()
+            .enumerate()
+            .filter(|(_, game)| {
+                matches_query(query, game.tags.iter().map(|(_, value)| value.as_str()))
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let sort_order = crate::SETTINGS.lock().unwrap().pgn_sort_order;
+        let games = &self.games;
+        self.visible_games
+            .sort_by(|a, b| sort_order.compare(&games[*a].tags, &games[*b].tags));
+        self.total_pages = match self.visible_games.len() {
+            0 => 1,
+            num => (num as f64 / REPLAYS_PER_PAGE as f64).ceil() as u32,
+        };
+    }
+
+    /// Game of the nth button on the current page
+    fn visible_game(&self, button: usize) -> Option<&IndexedGame> {
+        let index = (self.current_page_number * REPLAYS_PER_PAGE) as usize + button;
+        self.visible_games
+            .get(index)
+            .and_then(|game_index| self.games.get(*game_index))
+    }
+
+    /// Parses only the game of the nth button on the current page
+    fn open_game(&mut self, button: usize) {
+        let game = match self.visible_game(button) {
+            Some(game) => game.clone(),
+            None => return,
+        };
+        let offset = game.offset;
+        match read_game(self.selected_pgn.as_ref().unwrap(), &game) {
+            Ok(game) => {
+                self.indicate_loading = true;
+                self.opened_game = Some(game);
+                self.opened_game_offset = offset;
+            }
+            Err(e) => {
+                self.drawn = false;
+                self.show_error(format!("Failed to open game: {}", e));
+            }
+        }
+    }
+
+    /// The game whose button was pressed (if any)
+    pub fn pressed_game(&self) -> Option<&Game> {
+        self.opened_game.as_ref()
+    }
+
+    /// Identifies the pressed game inside the selected PGN
+    pub fn pressed_game_offset(&self) -> u64 {
+        self.opened_game_offset
+    }
+}
+
+fn file_name(pgn: &Pgn) -> String {
+    pgn.path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Last visited folder if it still exists
+fn last_pgn_folder() -> PathBuf {
+    let ref pgn_location = crate::config::pgn_location();
+    crate::SETTINGS
+        .lock()
+        .unwrap()
+        .last_pgn_folder
+        .as_ref()
+        .map(|folder| pgn_location.join(folder))
+        .filter(|folder| folder.is_dir())
+        .unwrap_or_else(|| pgn_location.to_owned())
+}
+
+/// Tags of the first game in a PGN (used to sort the PGNs)
+fn first_game_tags<'a>(
+    pgn_metadata: &'a FxHashMap<PathBuf, PgnMetadata>,
+    pgn: &Pgn,
+) -> &'a [(String, String)] {
+    pgn_metadata
+        .get(&pgn.path)
+        .and_then(|metadata| metadata.games.first())
+        .map(|tags| &tags[..])
+        .unwrap_or(&[])
+}
+
+/// E.g. "Carlsen vs Caruana (1-0)"
+fn construct_title_for_replay(tags: &[(String, String)]) -> String {
+    let mut title = format!(
+        "{} vs {}",
+        tag_value(tags, WHITE_TAG).unwrap_or("N/A"),
+        tag_value(tags, BLACK_TAG).unwrap_or("N/A")
+    );
+    if let Some(result) = tag_value(tags, RESULT_TAG) {
+        title.push_str(&format!(" ({})", result));
+    }
+    title
+}
+
+/// E.g. "World Championship, Round 6, 2018.11.19"
+fn construct_subtitle_for_replay(tags: &[(String, String)]) -> String {
+    let round = tag_value(tags, ROUND_TAG).map(|round| format!("Round {}", round));
+    let parts: Vec<&str> = vec![
+        tag_value(tags, EVENT_TAG),
+        round.as_ref().map(|round| round.as_str()),
+        tag_value(tags, DATE_TAG),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if parts.is_empty() {
+        "Unknown event".to_owned()
+    } else {
+        parts.join(", ")
     }
 }
 
-fn construct_text_for_replay(game: &Game) -> String {
-    let default_tuple: &(String, String) = &("N/A".parse().unwrap(), "N/A".parse().unwrap());
-    let white_tag: &(String, String) = game
-        .tags
-        .iter()
-        .find(|tag| tag.to_owned().0 == WHITE_TAG)
-        .unwrap_or(default_tuple);
-    let black_tag: &(String, String) = game
-        .tags
-        .iter()
-        .find(|tag| tag.to_owned().0 == BLACK_TAG)
-        .unwrap_or(default_tuple);
-    let event_tag: &(String, String) = game
-        .tags
-        .iter()
-        .find(|tag| tag.to_owned().0 == EVENT_TAG)
-        .unwrap_or(default_tuple);
-    let round_tag: &(String, String) = game
-        .tags
-        .iter()
-        .find(|tag| tag.to_owned().0 == ROUND_TAG)
-        .unwrap_or(default_tuple);
-    let mut replay_text = white_tag.to_owned().1;
-    replay_text.push_str(" vs ");
-    replay_text.push_str(&black_tag.1);
-    replay_text.push_str(" at ");
-    replay_text.push_str(&event_tag.1);
-    replay_text.push_str(" ");
-    replay_text.push_str(&round_tag.1);
-    replay_text
+fn draw_button_for_entry(
+    canvas: &mut Canvas,
+    maybe_entry_ref: Option<&PgnEntry>,
+    pgn_metadata: &FxHashMap<PathBuf, PgnMetadata>,
+    y_pos: i32,
+    font_size: f32,
+) -> Option<mxcfb_rect> {
+    match maybe_entry_ref? {
+        PgnEntry::Folder(folder) => Some(canvas.draw_box_button_with_subtitle(
+            y_pos,
+            BOX_HEIGHT as u32,
+            &format!(
+                "{}/",
+                folder.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            "Folder",
+            font_size,
+        )),
+        PgnEntry::Pgn(pgn) => {
+            draw_button_for_pgn(canvas, Some(pgn), pgn_metadata, y_pos, font_size)
+        }
+    }
 }
 
 fn draw_button_for_pgn(
     canvas: &mut Canvas,
     maybe_pgn_ref: Option<&Pgn>,
+    pgn_metadata: &FxHashMap<PathBuf, PgnMetadata>,
     y_pos: i32,
     font_size: f32,
 ) -> Option<mxcfb_rect> {
-    match maybe_pgn_ref {
-        Some(pgn_ref) => Some(
-            canvas.draw_box_button(
-                y_pos,
-                BOX_HEIGHT as u32,
-                &pgn_ref
-                    .path
-                    .file_name()
-                    .unwrap()
-                    .to_owned()
-                    .into_string()
-                    .unwrap_or("Can't read file name".to_string()),
-                font_size,
+    let pgn_ref = maybe_pgn_ref?;
+    let file_name = pgn_ref
+        .path
+        .file_name()
+        .unwrap()
+        .to_owned()
+        .into_string()
+        .unwrap_or("Can't read file name".to_string());
+    let games = pgn_metadata
+        .get(&pgn_ref.path)
+        .map(|metadata| &metadata.games[..])
+        .unwrap_or(&[]);
+    Some(match games {
+        [] => canvas.draw_box_button(y_pos, BOX_HEIGHT as u32, &file_name, font_size),
+        [tags] => canvas.draw_box_button_with_subtitle(
+            y_pos,
+            BOX_HEIGHT as u32,
+            &construct_title_for_replay(tags),
+            &format!("{} - {}", construct_subtitle_for_replay(tags), file_name),
+            font_size,
+        ),
+        [tags, ..] => canvas.draw_box_button_with_subtitle(
+            y_pos,
+            BOX_HEIGHT as u32,
+            &file_name,
+            &format!(
+                "{} games - {}",
+                games.len(),
+                construct_subtitle_for_replay(tags)
             ),
+            font_size,
         ),
-        None => None,
-    }
+    })
 }
 
 fn draw_button_for_game(
     canvas: &mut Canvas,
-    maybe_game_ref: Option<&Game>,
+    maybe_game_ref: Option<&IndexedGame>,
     y_pos: i32,
     font_size: f32,
 ) -> Option<mxcfb_rect> {
     match maybe_game_ref {
-        Some(game_ref) => Some(canvas.draw_box_button(
+        Some(game_ref) => Some(canvas.draw_box_button_with_subtitle(
             y_pos,
             BOX_HEIGHT as u32,
-            &construct_text_for_replay(game_ref),
+            &construct_title_for_replay(&game_ref.tags),
+            &construct_subtitle_for_replay(&game_ref.tags),
             font_size,
         )),
         None => None,
@@ -1,5 +1,9 @@
+use anyhow::{Context, Result};
 use chessmarkable::game::Piece;
+use fxhash::FxHashMap;
 use libremarkable::image;
+use std::path::Path;
+use std::sync::Mutex;
 
 lazy_static! {
 
@@ -42,9 +46,113 @@ lazy_static! {
     static ref IMG_PAWN_WHITE: image::DynamicImage =
         image::load_from_memory(include_bytes!("../../../../res/pawn-white.png"))
             .expect("Failed to load resource as image!");
+
+    /// Bitmaps of installed piece sets by set name and piece character
+    static ref USER_PIECE_IMAGES: Mutex<FxHashMap<(String, char), image::DynamicImage>> =
+        Mutex::new(Default::default());
+}
+
+/// Image of a piece from the selected piece set (see `Settings::piece_set`)
+/// that fits into a square of `size` pixels. Vector images (SVG) of the set
+/// are rendered at exactly that size so pieces stay sharp on any board size.
+/// Falls back to the built-in image if the set doesn't have a usable one.
+pub fn get_piece_img(piece: &Piece, size: u32) -> image::DynamicImage {
+    let piece_set = crate::SETTINGS.lock().unwrap().piece_set.clone();
+    if let Some(piece_set) = piece_set {
+        match get_user_piece_img(&piece_set, piece, size) {
+            Ok(img) => return img,
+            Err(err) => warn!(
+                "Using built-in image for {:?} instead of the one of piece set {:?}: {:?}",
+                piece, piece_set, err
+            ),
+        }
+    }
+    get_builtin_piece_img(piece).resize(size, size, image::FilterType::Lanczos3)
+}
+
+fn get_user_piece_img(piece_set: &str, piece: &Piece, size: u32) -> Result<image::DynamicImage> {
+    let folder = crate::config::pieces_location().join(piece_set);
+    let svg_path = folder.join(format!("{}.svg", file_stem(piece)));
+    if svg_path.exists() {
+        return render_svg(&svg_path, size).with_context(|| format!("Render {:?}", svg_path));
+    }
+
+    let key = (piece_set.to_owned(), piece.character_lossy());
+    let mut cache = USER_PIECE_IMAGES.lock().unwrap();
+    if let Some(img) = cache.get(&key) {
+        return Ok(img.resize(size, size, image::FilterType::Lanczos3));
+    }
+    let path = folder.join(format!("{}.png", file_stem(piece)));
+    let img = image::open(&path).with_context(|| format!("Load {:?}", path))?;
+    let resized = img.resize(size, size, image::FilterType::Lanczos3);
+    cache.insert(key, img);
+    Ok(resized)
+}
+
+/// Rasterize an SVG so that it fits into `size` x `size` pixels
+fn render_svg(path: &Path, size: u32) -> Result<image::DynamicImage> {
+    let data = std::fs::read(path).context("Read file")?;
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default())
+        .map_err(|err| anyhow!("Parse svg: {}", err))?;
+    let mut pixmap = tiny_skia::Pixmap::new(size, size).ok_or(anyhow!("Invalid size"))?;
+    let svg_size = tree.svg_node().size;
+    let fit_to = if svg_size.width() >= svg_size.height() {
+        usvg::FitTo::Width(size)
+    } else {
+        usvg::FitTo::Height(size)
+    };
+    resvg::render(&tree, fit_to, pixmap.as_mut()).ok_or(anyhow!("Failed to render svg"))?;
+
+    // The pixmap has premultiplied alpha which the images don't expect
+    let mut rgba = pixmap.take();
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        if alpha > 0 {
+            for channel in pixel[..3].iter_mut() {
+                *channel = (*channel as u32 * 255 / alpha).min(255) as u8;
+            }
+        }
+    }
+    let img = image::RgbaImage::from_raw(size, size, rgba)
+        .ok_or(anyhow!("Rendered svg has an unexpected size"))?;
+    Ok(image::DynamicImage::ImageRgba8(img))
+}
+
+/// Names of the installed piece sets (folders in the pieces location)
+pub fn piece_sets() -> Vec<String> {
+    let mut sets: Vec<String> = std::fs::read_dir(&crate::config::pieces_location())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    sets.sort();
+    sets
+}
+
+/// Same names as the built-in images in `res/` (without extension)
+fn file_stem(piece: &Piece) -> &'static str {
+    match *piece {
+        Piece::BlackKing => "king-black",
+        Piece::BlackQueen => "queen-black",
+        Piece::BlackBishop => "bishop-black",
+        Piece::BlackRook => "rook-black",
+        Piece::BlackKnight => "knight-black",
+        Piece::BlackPawn => "pawn-black",
+        Piece::WhiteKing => "king-white",
+        Piece::WhiteQueen => "queen-white",
+        Piece::WhiteBishop => "bishop-white",
+        Piece::WhiteRook => "rook-white",
+        Piece::WhiteKnight => "knight-white",
+        Piece::WhitePawn => "pawn-white",
+        Piece::None => panic!("Cannot get img for Piece::None"),
+    }
 }
 
-pub fn get_orig_piece_img(piece: &Piece) -> &'static image::DynamicImage {
+fn get_builtin_piece_img(piece: &Piece) -> &'static image::DynamicImage {
     match *piece {
         Piece::BlackKing => &IMG_KING_BLACK,
         Piece::BlackQueen => &IMG_QUEEN_BLACK,
@@ -0,0 +1,62 @@
+use crate::canvas::*;
+use anyhow::Result;
+use qrcode::{Color as QrColor, QrCode};
+
+/// Empty modules around the code. Readers need them to find it.
+const QUIET_ZONE: u32 = 4;
+/// Leaves room for the title above and the hint below the code
+const QR_CODE_SIZE: u32 = 1200;
+/// Room kept for the title and the hint on screens that are less high
+/// (in landscape)
+const MIN_TEXT_ROOM: u32 = 300;
+
+/// Replace the whole screen with `data` as QR code (e.g. to scan it with
+/// a phone) and `title` above it. Fails without drawing anything if the
+/// data doesn't fit into a QR code.
+pub fn draw_qr_screen(canvas: &mut Canvas, title: &str, data: &str) -> Result<()> {
+    let code = QrCode::new(data.as_bytes()).map_err(|err| anyhow!("{}", err))?;
+    let modules = code.width() as u32;
+    let screen = canvas.size();
+    let code_size = QR_CODE_SIZE.min(screen.y - MIN_TEXT_ROOM);
+    let module_size = code_size / (modules + QUIET_ZONE * 2);
+    ensure!(module_size > 0, "Too long for a QR code");
+    let left = (screen.x - modules * module_size) / 2;
+    let top = (screen.y - modules * module_size) / 2;
+
+    canvas.clear();
+    canvas.draw_text(
+        Point2 {
+            x: None,
+            y: Some(top as i32 - QUIET_ZONE as i32 * module_size as i32 - 60),
+        },
+        title,
+        75.0,
+    );
+    for y in 0..modules {
+        for x in 0..modules {
+            if code[(x as usize, y as usize)] == QrColor::Dark {
+                canvas.fill_rect(
+                    Point2 {
+                        x: Some((left + x * module_size) as i32),
+                        y: Some((top + y * module_size) as i32),
+                    },
+                    Vector2 {
+                        x: module_size,
+                        y: module_size,
+                    },
+                    color::BLACK,
+                );
+            }
+        }
+    }
+    canvas.draw_text(
+        Point2 {
+            x: None,
+            y: Some((top + (modules + QUIET_ZONE) * module_size) as i32 + 100),
+        },
+        "(Tap to continue)",
+        50.0,
+    );
+    canvas.update_full();
+    Ok(())
+}
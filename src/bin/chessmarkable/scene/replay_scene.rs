@@ -1,27 +1,63 @@
+use super::clock_widget::ClockWidget;
+use super::eval_graph::EvalGraph;
+use super::keyboard::{Keyboard, KeyboardEvent, SEARCH_LAYOUT};
 use super::Scene;
 use crate::canvas::*;
-use crate::pgns::Pgn;
+use crate::pgns::{self, Pgn};
 use crate::scene::game_scene::ALL_PIECES;
 use crate::scene::game_scene::IMG_PIECE_MOVED_FROM;
 use crate::scene::game_scene::IMG_PIECE_MOVED_TO;
 use crate::scene::game_scene::IMG_PIECE_MOVEHINT;
 use crate::scene::game_scene::IMG_PIECE_SELECTED;
-use crate::scene::piece_images::get_orig_piece_img;
-use crate::CLI_OPTS;
+use crate::scene::game_scene::{
+    board_hitboxes, board_rect, draw_board_border, draw_square_background, draw_square_control,
+    draw_square_coordinates, refresh_board_regions,
+};
+use crate::scene::gestures::{Gesture, Gestures};
+use crate::scene::pen_annotations::{draw_dashed_arrow, PenAnnotations};
+use crate::scene::piece_images::get_piece_img;
+use crate::settings::{BoardBorder, BoardTheme, GestureAction};
+use anyhow::{Context, Result};
 use chess_pgn_parser::Game;
-use chessmarkable::replay::{Replay, ReplayResponse};
-use chessmarkable::Square;
+use chessmarkable::analysis::{critical_moments, move_evals, square_control};
+use chessmarkable::annotation::PositionAnnotations;
+use chessmarkable::coach::threats;
+use chessmarkable::pdf::{self, Diagram};
+use chessmarkable::replay::{GuessOutcome, Replay, ReplayResponse};
+use chessmarkable::{Player, Square};
 use fxhash::{FxHashMap, FxHashSet};
 use libremarkable::image;
 use libremarkable::input::{gpio, multitouch, InputEvent};
 use pleco::{Board, Piece};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::time::{Duration, SystemTime};
 
+/// Keyboard for notes ends at the bottom of the screen
+const KEYBOARD_Y_POS: i32 = 1162;
+/// Clocks and evaluation of games with `[%clk]` and `[%eval]` comments
+/// are shown in the top right corner, next to the move comment
+const INFO_LEFT: u32 = DISPLAYWIDTH as u32 - 250;
+const INFO_WIDTH: u32 = 220;
+const INFO_CLOCK_HEIGHT: u32 = 60;
+const INFO_CLOCK_TOP_ABOVE: u32 = 15;
+const INFO_CLOCK_TOP_BELOW: u32 = 90;
+const INFO_EVAL_BASELINE: i32 = 205;
+const INFO_FONT_SIZE: f32 = 35.0;
+
 #[inline]
 fn to_square(x: usize, y: usize) -> Square {
     Square::new(x, y).expect("to_square() failed")
 }
 
+/// Score of the guess-the-move training
+#[derive(Default)]
+struct GuessScore {
+    exact: u16,
+    engine_approved: u16,
+    missed: u16,
+}
+
 pub struct ReplayScene {
     board: Board,
     first_draw: bool,
@@ -30,7 +66,47 @@ pub struct ReplayScene {
     next_move_button_hitbox: Option<mxcfb_rect>,
     reset_button_hitbox: Option<mxcfb_rect>,
     full_refresh_button_hitbox: Option<mxcfb_rect>,
+    guess_button_hitbox: Option<mxcfb_rect>,
+    guess_button_redraw: bool,
+    /// The side whose moves have to be guessed (if training is enabled)
+    guessing_player: Option<Player>,
+    guess_score: GuessScore,
     piece_hitboxes: Vec<Vec<mxcfb_rect>>,
+    /// Viewed from black's side
+    board_flipped: bool,
+    show_coordinates: bool,
+    /// Square control heatmap
+    show_heatmap: bool,
+    heatmap_button_hitbox: Option<mxcfb_rect>,
+    /// Dashed arrows for what the side not to move threatens
+    show_threats: bool,
+    /// Threats in the current position (`None` until they're searched)
+    threats: Option<Vec<(Square, Square)>>,
+    threats_button_hitbox: Option<mxcfb_rect>,
+    board_theme: BoardTheme,
+    board_border: BoardBorder,
+    pen_annotations: PenAnnotations,
+    gestures: Gestures,
+    /// Pen marks and notes of this game by number of replay moves played
+    annotations: BTreeMap<usize, PositionAnnotations>,
+    /// Identifies the game inside `selected_pgn`
+    game_offset: u64,
+    /// Position whose marks are shown (`None` after manual moves)
+    annotated_position: Option<usize>,
+    note_button_hitbox: Option<mxcfb_rect>,
+    pdf_button_hitbox: Option<mxcfb_rect>,
+    graph_button_hitbox: Option<mxcfb_rect>,
+    /// Shown instead of the board
+    eval_graph: Option<EvalGraph>,
+    eval_graph_drawn: bool,
+    /// Mistakes of the game are added to the review queue once, when the
+    /// graph is first opened. Receives how many were new.
+    queued_mistakes: Option<std::sync::mpsc::Receiver<Result<usize>>>,
+    mistakes_queued: bool,
+    /// Editing the note of the current position
+    keyboard: Option<Keyboard>,
+    keyboard_drawn: bool,
+    flip_button_hitbox: Option<mxcfb_rect>,
     /// The squared that were visually affected and should be redrawn
     redraw_squares: FxHashSet<Square>,
     /// If the amount of changes squares cannot be easily decided this
@@ -54,6 +130,13 @@ pub struct ReplayScene {
     force_full_refresh: Option<SystemTime>,
     move_comment: Option<String>,
     move_comment_last_rect: Option<mxcfb_rect>,
+    /// The game has clocks or evaluations in its comments
+    show_move_info: bool,
+    move_info_redraw: bool,
+    /// Clock of the player at the top of the board
+    clock_above: ClockWidget,
+    clock_below: ClockWidget,
+    eval_last_rect: Option<mxcfb_rect>,
     is_game_over: bool,
     possible_moves: Vec<(Square, Square)>,
     replay: Replay,
@@ -61,37 +144,26 @@ pub struct ReplayScene {
 }
 
 impl ReplayScene {
-    pub fn new(replay_info: Option<Game>, selected_pgn: Option<Pgn>) -> Self {
+    /// Fails if the starting position of the game can't be read
+    pub fn new(
+        replay_info: Option<Game>,
+        selected_pgn: Option<Pgn>,
+        game_offset: u64,
+    ) -> Result<Self> {
         // Size of board
         let square_size = DISPLAYWIDTH as u32 / 8;
         let piece_padding = square_size / 10;
         let overlay_padding = square_size / 20;
 
-        // Calculate hitboxes
-        let mut piece_hitboxes = Vec::new();
-        for x in 0..8 {
-            let mut y_axis = Vec::new();
-            for y in 0..8 {
-                y_axis.push(mxcfb_rect {
-                    left: ((DISPLAYWIDTH as u32 - square_size * 8) / 2) + square_size * x,
-                    top: ((DISPLAYHEIGHT as u32 - square_size * 8) / 2) + square_size * (7 - y),
-                    width: square_size,
-                    height: square_size,
-                });
-            }
-            piece_hitboxes.push(y_axis);
-        }
+        let board_flipped = crate::SETTINGS.lock().unwrap().board_flipped;
+        let piece_hitboxes = board_hitboxes(board_rect(square_size), board_flipped);
 
         // Create resized images
         let mut img_pieces: FxHashMap<char, image::DynamicImage> = Default::default();
         for piece in ALL_PIECES.iter() {
             img_pieces.insert(
                 piece.character_lossy(),
-                get_orig_piece_img(piece).resize(
-                    square_size - piece_padding * 2,
-                    square_size - piece_padding * 2,
-                    image::FilterType::Lanczos3,
-                ),
+                get_piece_img(piece, square_size - piece_padding * 2),
             );
         }
         let img_piece_selected = IMG_PIECE_SELECTED.resize(
@@ -109,11 +181,63 @@ impl ReplayScene {
         let img_piece_moved_to =
             IMG_PIECE_MOVED_TO.resize(square_size, square_size, image::FilterType::Lanczos3);
 
+        let mut annotations = match selected_pgn {
+            Some(ref pgn) => match pgns::read_annotations(pgn) {
+                Ok(mut pgn_annotations) => pgn_annotations
+                    .games
+                    .remove(&game_offset)
+                    .unwrap_or_default(),
+                Err(e) => {
+                    warn!("Failed to read annotations of {:?}: {:?}", pgn.path, e);
+                    Default::default()
+                }
+            },
+            None => Default::default(),
+        };
+        let pen_annotations = PenAnnotations::new(
+            annotations
+                .get(&0)
+                .map(|position| position.marks.clone())
+                .unwrap_or_default(),
+        );
+        annotations.retain(|_, position| !position.is_empty());
+        let replay = Replay::new(replay_info.expect("Couldn't read Replay Info"))?;
+        let info_clock_area = |top: u32| mxcfb_rect {
+            left: INFO_LEFT,
+            top,
+            width: INFO_WIDTH,
+            height: INFO_CLOCK_HEIGHT,
+        };
+
         //Replay Info
-        Self {
+        Ok(Self {
             board: Board::default(), // Temporary default (usually stays that but will change when having a custom fen)
             first_draw: true,
             piece_hitboxes,
+            board_flipped,
+            show_coordinates: crate::SETTINGS.lock().unwrap().show_coordinates,
+            show_heatmap: false,
+            heatmap_button_hitbox: None,
+            show_threats: false,
+            threats: None,
+            threats_button_hitbox: None,
+            board_theme: crate::SETTINGS.lock().unwrap().board_theme,
+            board_border: crate::SETTINGS.lock().unwrap().board_border,
+            pen_annotations,
+            gestures: Default::default(),
+            annotations,
+            game_offset,
+            annotated_position: Some(0),
+            note_button_hitbox: None,
+            pdf_button_hitbox: None,
+            graph_button_hitbox: None,
+            eval_graph: None,
+            eval_graph_drawn: false,
+            queued_mistakes: None,
+            mistakes_queued: false,
+            keyboard: None,
+            keyboard_drawn: false,
+            flip_button_hitbox: None,
             piece_padding,
             overlay_padding,
             selected_square: None,
@@ -133,20 +257,34 @@ impl ReplayScene {
             next_move_button_hitbox: None,
             reset_button_hitbox: None,
             full_refresh_button_hitbox: None,
+            guess_button_hitbox: None,
+            guess_button_redraw: false,
+            guessing_player: None,
+            guess_score: Default::default(),
             move_comment: None,
+            show_move_info: replay.has_clocks() || replay.has_evals(),
+            move_info_redraw: true,
+            clock_above: ClockWidget::new(info_clock_area(INFO_CLOCK_TOP_ABOVE), INFO_FONT_SIZE),
+            clock_below: ClockWidget::new(info_clock_area(INFO_CLOCK_TOP_BELOW), INFO_FONT_SIZE),
+            eval_last_rect: None,
             return_to_main_menu: false,
             force_full_refresh: None,
             is_game_over: false,
             possible_moves: vec![],
-            replay: Replay::new(replay_info.expect("Couldn't read Replay Info")),
+            replay,
             move_comment_last_rect: None,
             selected_pgn,
-        }
+        })
     }
 
     fn draw_board(&mut self, canvas: &mut Canvas) -> Vec<mxcfb_rect> {
         let start = SystemTime::now();
         let mut updated_regions = vec![];
+        let control = if self.show_heatmap {
+            Some(square_control(&self.board))
+        } else {
+            None
+        };
         for x in 0..8 {
             for y in 0..8 {
                 let square = to_square(x, y); // Flip board so white is at the bottom
@@ -159,18 +297,7 @@ impl ReplayScene {
                 //
                 let is_bright_bg = x % 2 == y % 2;
                 let bounds = &self.piece_hitboxes[x][y];
-                canvas.fill_rect(
-                    Point2 {
-                        x: Some(bounds.left as i32),
-                        y: Some(bounds.top as i32),
-                    },
-                    self.piece_hitboxes[x][y].size().cast().unwrap(),
-                    if is_bright_bg {
-                        color::GRAY(100)
-                    } else {
-                        color::GRAY(50)
-                    },
-                );
+                draw_square_background(canvas, bounds, is_bright_bg, self.board_theme);
 
                 //
                 // Underlay / Background layers
@@ -211,6 +338,20 @@ impl ReplayScene {
                     );
                 }
 
+                //
+                // Square control heatmap
+                //
+                if let Some(ref control) = control {
+                    draw_square_control(canvas, bounds, control[square.0 as usize]);
+                }
+
+                //
+                // Coordinates
+                //
+                if self.show_coordinates {
+                    draw_square_coordinates(canvas, bounds, square, self.board_flipped);
+                }
+
                 //
                 // Overlay
                 //
@@ -230,22 +371,48 @@ impl ReplayScene {
                 }
 
                 // Display postions a selected chess piece could move to
+                // (a dot on empty squares and the corners on captures)
                 if self.move_hints.contains(&square) {
-                    canvas.draw_image(
-                        Point2 {
-                            x: (bounds.left + self.overlay_padding) as i32,
-                            y: (bounds.top + self.overlay_padding) as i32,
-                        },
-                        &self.img_piece_movehint,
-                        true,
-                    );
+                    if piece == Piece::None {
+                        canvas.fill_circle(
+                            Point2 {
+                                x: (bounds.left + bounds.width / 2) as i32,
+                                y: (bounds.top + bounds.height / 2) as i32,
+                            },
+                            bounds.width / 8,
+                            color::BLACK,
+                        );
+                    } else {
+                        canvas.draw_image(
+                            Point2 {
+                                x: (bounds.left + self.overlay_padding) as i32,
+                                y: (bounds.top + self.overlay_padding) as i32,
+                            },
+                            &self.img_piece_movehint,
+                            true,
+                        );
+                    }
                 }
 
                 updated_regions.push(bounds.clone());
             }
         }
 
-        if self.redraw_all_squares || !CLI_OPTS.no_merge {
+        if !updated_regions.is_empty() {
+            draw_board_border(canvas, self.full_board_rect(), self.board_border);
+        }
+
+        let has_pen_annotations = self.pen_annotations.draw(canvas, &self.piece_hitboxes);
+        if has_pen_annotations || self.draw_threats(canvas) {
+            // Arrows can span many squares
+            updated_regions.clear();
+            updated_regions.push(self.full_board_rect());
+        }
+
+        if self.redraw_all_squares
+            || (!crate::config::no_merge()
+                && updated_regions.len() > crate::config::max_separate_square_refreshes())
+        {
             // Update full board instead of every single position
             updated_regions.clear();
             updated_regions.push(self.full_board_rect());
@@ -271,15 +438,401 @@ impl ReplayScene {
     }
 
     fn full_board_rect(&self) -> mxcfb_rect {
-        let left = self.piece_hitboxes[0][7].left;
-        let top = self.piece_hitboxes[0][7].top;
-        let right = self.piece_hitboxes[7][0].left + self.piece_hitboxes[7][0].width;
-        let bottom = self.piece_hitboxes[7][0].top + self.piece_hitboxes[7][0].height;
-        mxcfb_rect {
-            left,
-            top,
-            width: right - left,
-            height: bottom - top,
+        board_rect(self.piece_hitboxes[0][0].width)
+    }
+
+    /// Toggle viewing the board from black's side and remember it
+    fn flip_board(&mut self) {
+        self.board_flipped = !self.board_flipped;
+        self.piece_hitboxes = board_hitboxes(self.full_board_rect(), self.board_flipped);
+        self.redraw_all_squares = true;
+        self.move_info_redraw = true;
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        settings.board_flipped = self.board_flipped;
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+    }
+
+    fn draw_flip_button(&mut self, canvas: &mut Canvas) {
+        self.flip_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 250),
+                y: Some(1685),
+            },
+            "Flip board",
+            35.0,
+            5,
+            15,
+        ));
+    }
+
+    /// Clocks of both players as written in the comments (the one to move
+    /// has a thick border) and the evaluation of the current position
+    fn draw_move_info(&mut self, canvas: &mut Canvas) {
+        let (above, below) = if self.board_flipped {
+            (Player::White, Player::Black)
+        } else {
+            (Player::Black, Player::White)
+        };
+        let turn: Player = self.board.turn().into();
+        if self.replay.has_clocks() {
+            draw_replay_clock(
+                canvas,
+                &mut self.clock_above,
+                self.replay.clock(above),
+                turn == above,
+            );
+            draw_replay_clock(
+                canvas,
+                &mut self.clock_below,
+                self.replay.clock(below),
+                turn == below,
+            );
+        }
+
+        if let Some(last_rect) = self.eval_last_rect.take() {
+            canvas.fill_rect(
+                Point2 {
+                    x: Some(last_rect.left as i32),
+                    y: Some(last_rect.top as i32),
+                },
+                Vector2 {
+                    x: last_rect.width,
+                    y: last_rect.height,
+                },
+                color::WHITE,
+            );
+            canvas.mark_dirty(&last_rect);
+        }
+        if let Some(eval) = self.replay.eval() {
+            let rect = canvas.draw_text(
+                Point2 {
+                    x: Some(INFO_LEFT as i32),
+                    y: Some(INFO_EVAL_BASELINE),
+                },
+                &format!("Eval {}", eval),
+                INFO_FONT_SIZE,
+            );
+            canvas.mark_dirty(&rect);
+            self.eval_last_rect = Some(rect);
+        }
+    }
+
+    fn draw_note_button(&mut self, canvas: &mut Canvas) {
+        self.note_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(740),
+                y: Some(1685),
+            },
+            "Note",
+            35.0,
+            5,
+            15,
+        ));
+    }
+
+    fn draw_pdf_button(&mut self, canvas: &mut Canvas) {
+        self.pdf_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(865),
+                y: Some(1685),
+            },
+            "PDF",
+            35.0,
+            5,
+            15,
+        ));
+    }
+
+    fn draw_heatmap_button(&mut self, canvas: &mut Canvas) {
+        self.heatmap_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(380),
+                y: Some(1685),
+            },
+            "Heatmap",
+            35.0,
+            5,
+            15,
+        ));
+    }
+
+    /// Show how often each side attacks every square
+    fn toggle_heatmap(&mut self) {
+        self.show_heatmap = !self.show_heatmap;
+        self.redraw_all_squares = true;
+        self.move_comment = Some(
+            if self.show_heatmap {
+                "Thick frames show who attacks a square more often (+2: white has two attackers more)."
+            } else {
+                "Heatmap hidden."
+            }
+            .to_owned(),
+        );
+    }
+
+    fn draw_threats_button(&mut self, canvas: &mut Canvas) {
+        self.threats_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(560),
+                y: Some(1685),
+            },
+            "Threats",
+            35.0,
+            5,
+            15,
+        ));
+    }
+
+    /// Show what the opponent of the side to move threatens
+    fn toggle_threats(&mut self) {
+        self.show_threats = !self.show_threats;
+        self.threats = None;
+        self.redraw_all_squares = true;
+        self.move_comment = Some(
+            if self.show_threats {
+                "Dashed arrows show what the other side threatens if the side to move passed."
+            } else {
+                "Threats hidden."
+            }
+            .to_owned(),
+        );
+    }
+
+    /// Dashed arrows of the threats (searched once per position). Returns
+    /// false if there are none.
+    fn draw_threats(&mut self, canvas: &mut Canvas) -> bool {
+        if !self.show_threats {
+            return false;
+        }
+        if self.threats.is_none() {
+            self.threats = Some(threats(&self.board));
+        }
+        let threats = self.threats.as_deref().unwrap_or_default();
+        for (source, destination) in threats {
+            draw_dashed_arrow(canvas, &self.piece_hitboxes, *source, *destination);
+        }
+        !threats.is_empty()
+    }
+
+    fn draw_graph_button(&mut self, canvas: &mut Canvas) {
+        self.graph_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(975),
+                y: Some(1685),
+            },
+            "Graph",
+            35.0,
+            5,
+            15,
+        ));
+    }
+
+    /// Show the evaluation after every move instead of the board (or the
+    /// board again). The evaluations come from the comments of the game
+    /// if it has them, otherwise the positions are evaluated.
+    fn toggle_eval_graph(&mut self) {
+        if self.eval_graph.take().is_some() {
+            self.redraw_all_squares = true;
+            return;
+        }
+        let evals = move_evals(&self.replay.moves());
+        if !self.mistakes_queued {
+            self.queue_mistakes(evals.clone());
+        }
+        self.eval_graph = Some(EvalGraph::new(self.full_board_rect(), evals));
+        self.eval_graph_drawn = false;
+        self.clear_move_hints();
+        self.selected_square = None;
+    }
+
+    /// Add the critical moments that were mistakes to the review queue.
+    /// Only those of the player if the name in the settings is one of the
+    /// players of the game.
+    fn queue_mistakes(&mut self, evals: Vec<i32>) {
+        self.mistakes_queued = true;
+        let game = self.replay.game().clone();
+        let player_name = crate::SETTINGS.lock().unwrap().player_name.clone();
+        let tags = self.replay.tags();
+        let white = pgns::tag_value(tags, "White").unwrap_or("?").to_owned();
+        let black = pgns::tag_value(tags, "Black").unwrap_or("?").to_owned();
+        let only = match player_name {
+            Some(ref name) if *name == white => Some(Player::White),
+            Some(ref name) if *name == black => Some(Player::Black),
+            _ => None,
+        };
+        let (queued_tx, queued_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let queued = critical_moments(&game, &evals).and_then(|moments| {
+                let mut queue = crate::review_queue::read()?;
+                let added = queue.add_mistakes(&moments, only, &format!("{} - {}", white, black));
+                if added > 0 {
+                    crate::review_queue::write(&queue)?;
+                }
+                Ok(added)
+            });
+            queued_tx.send(queued).ok();
+        });
+        self.queued_mistakes = Some(queued_rx);
+    }
+
+    fn poll_queued_mistakes(&mut self) {
+        let queued = match self.queued_mistakes {
+            Some(ref queued) => match queued.try_recv() {
+                Ok(queued) => queued,
+                Err(std::sync::mpsc::TryRecvError::Empty) => return,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.queued_mistakes = None;
+                    return;
+                }
+            },
+            None => return,
+        };
+        self.queued_mistakes = None;
+        match queued {
+            Ok(0) => {}
+            Ok(added) => {
+                self.move_comment = Some(format!(
+                    "{} mistake{} added to the review queue",
+                    added,
+                    if added == 1 { "" } else { "s" }
+                ))
+            }
+            Err(e) => {
+                error!("Failed to queue the mistakes of the game: {:?}", e);
+                self.move_comment = Some(format!("Failed to queue the mistakes: {}", e));
+            }
+        }
+    }
+
+    /// Jump to a move tapped in the graph. Returns whether the input was
+    /// meant for the graph (anything on it while it's shown).
+    fn on_eval_graph_input(&mut self, event: InputEvent) -> bool {
+        let eval_graph = match self.eval_graph {
+            Some(ref eval_graph) => eval_graph,
+            None => return false,
+        };
+        let (finger, released) = match event {
+            InputEvent::WacomEvent { .. } => return true, // No pen marks on the graph
+            InputEvent::MultitouchEvent { event } => match event {
+                multitouch::MultitouchEvent::Press { finger }
+                | multitouch::MultitouchEvent::Move { finger } => (finger, false),
+                multitouch::MultitouchEvent::Release { finger } => (finger, true),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        if !eval_graph.contains(finger.pos) {
+            return false;
+        }
+        if released {
+            if let Some(position) = eval_graph.position_at(finger.pos) {
+                let response = self.replay.go_to(position);
+                self.toggle_eval_graph();
+                self.play_replay_move(response);
+            }
+        }
+        true
+    }
+
+    /// Save the game as PDF with diagrams of the annotated positions and the final one
+    fn export_pdf(&self) -> Result<PathBuf> {
+        let moves = self.replay.moves();
+        let mut diagrams: Vec<Diagram> = self
+            .annotations
+            .iter()
+            .filter(|(position, _)| **position > 0)
+            .map(|(position, annotations)| Diagram {
+                after_move: *position,
+                note: annotations
+                    .note
+                    .clone()
+                    .or_else(|| moves.get(*position - 1)?.comment.clone()),
+            })
+            .collect();
+        if !moves.is_empty() && !self.annotations.contains_key(&moves.len()) {
+            diagrams.push(Diagram {
+                after_move: moves.len(),
+                note: moves.last().unwrap().comment.clone(),
+            });
+        }
+        let document =
+            pdf::export_game(self.replay.tags(), self.replay.result(), &moves, &diagrams);
+
+        let ref folder = crate::config::documents_location();
+        std::fs::create_dir_all(folder).context("Create documents folder")?;
+        let name = self
+            .selected_pgn
+            .as_ref()
+            .and_then(|pgn| pgn.path.file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "game".to_owned());
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = folder.join(format!("{}-{}.pdf", name, timestamp));
+        std::fs::write(&path, document).context("Write PDF")?;
+        info!("Exported game to {:?}", path);
+        Ok(path)
+    }
+
+    /// Show the marks of the current position of the replay
+    fn show_position_annotations(&mut self) {
+        let position = self.replay.replay_position();
+        if position == self.annotated_position {
+            return;
+        }
+        self.annotated_position = position;
+        let marks = position
+            .and_then(|position| self.annotations.get(&position))
+            .map(|annotations| annotations.marks.clone())
+            .unwrap_or_default();
+        if !marks.is_empty() || !self.pen_annotations.annotations.is_empty() {
+            self.redraw_all_squares = true;
+        }
+        self.pen_annotations.annotations = marks;
+    }
+
+    fn note(&self) -> Option<&str> {
+        self.annotated_position
+            .and_then(|position| self.annotations.get(&position))
+            .and_then(|annotations| annotations.note.as_deref())
+    }
+
+    /// Store the marks and the note of the current position (an empty note is removed)
+    fn save_annotations(&mut self, note: Option<String>) {
+        let position = match self.annotated_position {
+            Some(position) => position,
+            None => return, // Manual moves aren't part of the game
+        };
+        let annotations = self.annotations.entry(position).or_default();
+        annotations.marks = self.pen_annotations.annotations.clone();
+        if let Some(note) = note {
+            annotations.note = Some(note).filter(|note| !note.is_empty());
+        }
+        if annotations.is_empty() {
+            self.annotations.remove(&position);
+        }
+
+        let pgn = match self.selected_pgn {
+            Some(ref pgn) => pgn,
+            None => return,
+        };
+        let result = pgns::read_annotations(pgn).and_then(|mut pgn_annotations| {
+            if self.annotations.is_empty() {
+                pgn_annotations.games.remove(&self.game_offset);
+            } else {
+                pgn_annotations
+                    .games
+                    .insert(self.game_offset, self.annotations.clone());
+            }
+            pgns::write_annotations(pgn, &pgn_annotations)
+        });
+        if let Err(e) = result {
+            error!("Failed to save annotations: {:?}", e);
+            self.move_comment = Some(format!("Failed to save annotations: {}", e));
         }
     }
 
@@ -302,10 +855,111 @@ impl ReplayScene {
     }
 
     fn on_user_move(&mut self, src: Square, dest: Square) {
+        if self.guessing_player == Some(self.board.turn().into()) {
+            if let Some(outcome) = self.replay.guess_replay_move(src, dest) {
+                self.on_guess(outcome);
+                return;
+            }
+        }
         let response = self.replay.player_move(src, dest);
         self.play_replay_move(response);
     }
 
+    /// Score the guess and reveal the actual move
+    fn on_guess(&mut self, outcome: GuessOutcome) {
+        let feedback = match outcome {
+            GuessOutcome::Exact => {
+                self.guess_score.exact += 1;
+                "Correct!"
+            }
+            GuessOutcome::EngineApproved => {
+                self.guess_score.engine_approved += 1;
+                "Not played, but the engine likes your move."
+            }
+            GuessOutcome::Miss => {
+                self.guess_score.missed += 1;
+                "Miss. The highlighted move was played."
+            }
+        };
+        let mut response = self.replay.play_replay_move();
+        let mut comment = format!(
+            "{} Score: {} correct, {} good, {} missed.",
+            feedback,
+            self.guess_score.exact,
+            self.guess_score.engine_approved,
+            self.guess_score.missed
+        );
+        if let Some(ref move_comment) = response.comment {
+            comment.push_str(" ");
+            comment.push_str(move_comment);
+        }
+        response.comment = Some(comment);
+        self.play_replay_move(response);
+    }
+
+    fn toggle_guessing(&mut self) {
+        self.guessing_player = match self.guessing_player {
+            Some(_) => None,
+            None => Some(self.board.turn().into()),
+        };
+        self.guess_score = Default::default();
+        self.guess_button_redraw = true;
+        self.move_comment = Some(match self.guessing_player {
+            Some(player) => format!("Guess the moves of {}. Press > to see the replies.", player),
+            None => "Guessing stopped.".to_owned(),
+        });
+    }
+
+    fn draw_guess_button(&mut self, canvas: &mut Canvas) {
+        if let Some(hitbox) = self.guess_button_hitbox {
+            canvas.fill_rect(
+                Point2 {
+                    x: Some(hitbox.left as i32 - 5),
+                    y: Some(hitbox.top as i32 - 5),
+                },
+                Vector2 {
+                    x: hitbox.width + 10,
+                    y: hitbox.height + 10,
+                },
+                color::WHITE,
+            );
+            canvas.mark_dirty(&hitbox);
+        }
+        let text = if self.guessing_player.is_some() {
+            "Guess moves: On"
+        } else {
+            "Guess moves: Off"
+        };
+        self.guess_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(50),
+                y: Some(1685),
+            },
+            text,
+            35.0,
+            5,
+            15,
+        ));
+    }
+
+    fn on_gesture(&mut self, gesture: Gesture) {
+        debug!("Gesture: {:?}", gesture);
+        match gesture.action() {
+            GestureAction::Undo => {
+                let response = self.replay.undo_move();
+                self.play_replay_move(response);
+            }
+            GestureAction::Redo => {
+                let response = self.replay.play_replay_move();
+                self.play_replay_move(response);
+            }
+            GestureAction::Flip => self.flip_board(),
+            GestureAction::Refresh => self.force_full_refresh = Some(SystemTime::now()),
+            // Replays have no menu and nothing to save
+            GestureAction::Menu | GestureAction::Adjourn | GestureAction::Nothing => {}
+        }
+    }
+
     fn clear_state_post_move(&mut self) {
         self.selected_square = None;
         self.finger_down_square = None;
@@ -353,6 +1007,14 @@ impl ReplayScene {
                 }
             }
         }
+        if self.show_heatmap {
+            // Moves change what far away squares are attacked by
+            self.redraw_all_squares = true;
+        }
+        if self.show_threats {
+            self.threats = None;
+            self.redraw_all_squares = true;
+        }
 
         self.board = new_board;
     }
@@ -363,11 +1025,60 @@ impl ReplayScene {
         self.move_comment = replay_response.comment;
         self.last_move_from = replay_response.last_move_from;
         self.last_move_to = replay_response.last_move_to;
+        self.move_info_redraw = true;
+        self.eval_graph_drawn = false;
+        for last_move_hint in self.last_move_from.iter().chain(self.last_move_to.iter()) {
+            self.redraw_squares.insert(last_move_hint.clone());
+        }
+        self.show_position_annotations();
+        if let Some(note) = self.note().map(str::to_owned) {
+            let comment = match self.move_comment.take() {
+                Some(comment) => format!("{} Note: {}", comment, note),
+                None => format!("Note: {}", note),
+            };
+            self.move_comment = Some(comment);
+        }
     }
 }
 
 impl Scene for ReplayScene {
     fn on_input(&mut self, event: InputEvent) {
+        let gesture = self.gestures.on_input(event.clone());
+        if let Some(ref mut keyboard) = self.keyboard {
+            match keyboard.on_input(event.clone()) {
+                Some(KeyboardEvent::Done) => {
+                    let note = keyboard.text.trim().to_owned();
+                    self.keyboard = None;
+                    self.first_draw = true;
+                    self.save_annotations(Some(note));
+                    self.move_comment = self.note().map(|note| format!("Note: {}", note));
+                }
+                Some(KeyboardEvent::Cancelled) => {
+                    self.keyboard = None;
+                    self.first_draw = true;
+                }
+                Some(KeyboardEvent::Edited) | None => {}
+            }
+            return;
+        }
+        if let Some(gesture) = gesture {
+            self.on_gesture(gesture);
+        }
+        if self.gestures.is_consuming() {
+            self.finger_down_square = None;
+            return;
+        }
+        if self.on_eval_graph_input(event.clone()) {
+            self.finger_down_square = None;
+            return;
+        }
+        if self
+            .pen_annotations
+            .on_input(event.clone(), &self.piece_hitboxes)
+        {
+            self.redraw_all_squares = true;
+            self.save_annotations(None);
+        }
         match event {
             InputEvent::GPIO { event } => match event {
                 gpio::GPIOEvent::Press { button } => match button {
@@ -379,6 +1090,7 @@ impl Scene for ReplayScene {
                         let response = self.replay.undo_move();
                         self.play_replay_move(response);
                     }
+                    gpio::PhysicalButton::MIDDLE => self.return_to_main_menu = true,
                     _ => {}
                 },
                 _ => {}
@@ -398,6 +1110,51 @@ impl Scene for ReplayScene {
                             && Canvas::is_hitting(finger.pos, self.back_button_hitbox.unwrap())
                         {
                             self.return_to_main_menu = true;
+                        } else if self.guess_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.guess_button_hitbox.unwrap())
+                        {
+                            self.toggle_guessing();
+                        } else if self.flip_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.flip_button_hitbox.unwrap())
+                        {
+                            self.flip_board();
+                        } else if self.note_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.note_button_hitbox.unwrap())
+                        {
+                            if self.annotated_position.is_none() {
+                                self.move_comment = Some(
+                                    "Notes can only be added to positions of the game.".to_owned(),
+                                );
+                            } else {
+                                self.keyboard = Some(Keyboard::new(
+                                    SEARCH_LAYOUT,
+                                    self.note().unwrap_or(""),
+                                    KEYBOARD_Y_POS,
+                                ));
+                                self.keyboard_drawn = false;
+                            }
+                        } else if self.heatmap_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.heatmap_button_hitbox.unwrap())
+                        {
+                            self.toggle_heatmap();
+                        } else if self.threats_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.threats_button_hitbox.unwrap())
+                        {
+                            self.toggle_threats();
+                        } else if self.graph_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.graph_button_hitbox.unwrap())
+                        {
+                            self.toggle_eval_graph();
+                        } else if self.pdf_button_hitbox.is_some()
+                            && Canvas::is_hitting(finger.pos, self.pdf_button_hitbox.unwrap())
+                        {
+                            self.move_comment = Some(match self.export_pdf() {
+                                Ok(path) => format!("Saved PDF to {}", path.display()),
+                                Err(err) => {
+                                    error!("Failed to export the game as PDF: {:?}", err);
+                                    "Failed to save the PDF".to_owned()
+                                }
+                            });
                         } else if self.full_refresh_button_hitbox.is_some()
                             && Canvas::is_hitting(
                                 finger.pos,
@@ -414,6 +1171,7 @@ impl Scene for ReplayScene {
                             && Canvas::is_hitting(finger.pos, self.reset_button_hitbox.unwrap())
                         {
                             let response = self.replay.reset();
+                            self.guess_score = Default::default();
                             self.play_replay_move(response);
                         } else if self.undo_button_hitbox.is_some()
                             && Canvas::is_hitting(finger.pos, self.undo_button_hitbox.unwrap())
@@ -504,6 +1262,18 @@ impl Scene for ReplayScene {
     }
 
     fn draw(&mut self, canvas: &mut Canvas) {
+        if let Some(ref mut keyboard) = self.keyboard {
+            if !self.keyboard_drawn {
+                let rect = keyboard.draw(canvas);
+                canvas.mark_dirty(&rect);
+                self.keyboard_drawn = true;
+            }
+            keyboard.update(canvas);
+            return;
+        }
+        if let Some(gesture) = self.gestures.update() {
+            self.on_gesture(gesture);
+        }
         if self.first_draw {
             // First frame
             canvas.clear();
@@ -565,6 +1335,18 @@ impl Scene for ReplayScene {
                 60,
                 100,
             ));
+            self.draw_guess_button(canvas);
+            self.draw_flip_button(canvas);
+            self.draw_note_button(canvas);
+            self.draw_pdf_button(canvas);
+            self.draw_graph_button(canvas);
+            self.draw_heatmap_button(canvas);
+            self.draw_threats_button(canvas);
+            self.eval_graph_drawn = false;
+            self.clock_above.invalidate();
+            self.clock_below.invalidate();
+            self.eval_last_rect = None;
+            self.move_info_redraw = true;
             self.redraw_all_squares = true;
             self.draw_board(canvas);
             canvas.update_full();
@@ -573,14 +1355,33 @@ impl Scene for ReplayScene {
             self.force_full_refresh = Some(SystemTime::now() + Duration::from_millis(250));
         }
 
-        // Update board
-        if self.redraw_all_squares || self.redraw_squares.len() > 0 {
-            self.draw_board(canvas).iter().for_each(|r| {
-                canvas.update_partial(r);
-            });
+        self.pen_annotations.update(canvas);
+        self.poll_queued_mistakes();
+
+        // Update board (or the graph covering it)
+        if let Some(ref eval_graph) = self.eval_graph {
+            if !self.eval_graph_drawn {
+                let rect = eval_graph.draw(canvas, self.replay.replay_position());
+                canvas.mark_dirty(&rect);
+                self.eval_graph_drawn = true;
+            }
+        } else if self.redraw_all_squares || self.redraw_squares.len() > 0 {
+            let regions = self.draw_board(canvas);
+            refresh_board_regions(canvas, &regions, self.piece_hitboxes[0][0].width);
             self.redraw_all_squares = false;
         }
 
+        if self.show_move_info && self.move_info_redraw {
+            self.draw_move_info(canvas);
+            self.move_info_redraw = false;
+        }
+
+        if self.guess_button_redraw {
+            self.draw_guess_button(canvas);
+            canvas.mark_dirty(&self.guess_button_hitbox.unwrap());
+            self.guess_button_redraw = false;
+        }
+
         // Do forced refresh on request
         if self.force_full_refresh.is_some() && self.force_full_refresh.unwrap() < SystemTime::now()
         {
@@ -603,7 +1404,7 @@ impl Scene for ReplayScene {
                     },
                     color::WHITE,
                 );
-                canvas.update_partial(last_rect);
+                canvas.mark_dirty(last_rect);
                 self.move_comment_last_rect = None;
             }
         }
@@ -613,15 +1414,53 @@ impl Scene for ReplayScene {
             if let Some(ref comment) = self.move_comment {
                 // Old text was cleared above already
 
-                let rect = canvas.draw_multi_line_text(
-                    None, 40, comment, 95,
-                    7, //Comments longer than this will cut into the game screen - Hence a ~660ish characters limit
-                    35.0, 0.6,
+                // Left of the clocks and evaluation if they are shown
+                let (x_pos, max_width) = if self.show_move_info {
+                    (Some(40), INFO_LEFT - 80)
+                } else {
+                    (None, DISPLAYWIDTH as u32 - 80)
+                };
+                let rect = canvas.draw_wrapped_text(
+                    x_pos,
+                    40,
+                    max_width,
+                    7, // Longer comments would cut into the game screen
+                    comment,
+                    35.0,
+                    FontStyle::Italic,
                 );
-                canvas.update_partial(&rect);
+                canvas.mark_dirty(&rect);
                 self.move_comment_last_rect = Some(rect);
                 self.move_comment = None;
             }
         }
     }
 }
+
+/// Nothing is shown until the clock of the player is known
+fn draw_replay_clock(
+    canvas: &mut Canvas,
+    widget: &mut ClockWidget,
+    clock: Option<Duration>,
+    running: bool,
+) {
+    match clock {
+        Some(clock) => widget.draw(canvas, clock, running, Duration::default()),
+        None => {
+            let area = widget.area();
+            canvas.fill_rect(
+                Point2 {
+                    x: Some(area.left as i32),
+                    y: Some(area.top as i32),
+                },
+                Vector2 {
+                    x: area.width,
+                    y: area.height,
+                },
+                color::WHITE,
+            );
+            canvas.mark_dirty(&area);
+            widget.invalidate();
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use super::mini_board::MiniBoard;
+use super::Scene;
+use crate::canvas::*;
+use crate::scene::game_scene::{board_hitboxes, board_rect};
+use anyhow::Result;
+use chessmarkable::game::ChessGame;
+use chessmarkable::review::{Exercise, ReviewQueue, Verdict, SOLVED_TIMES};
+use chessmarkable::Square;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+const SQUARE_SIZE: u32 = 175;
+const SELECTED_FRAME: u32 = 8;
+const STATUS_TOP: u32 = 1640;
+const STATUS_HEIGHT: u32 = 95;
+const STATUS_FONT_SIZE: f32 = 40.0;
+/// Between the title and the board
+const TASK_TOP: u32 = 115;
+const TASK_HEIGHT: u32 = 115;
+
+/// The mistakes of analysed games as "find the better move" exercises.
+/// Each one stays in the queue until it was solved `SOLVED_TIMES` times.
+pub struct ReviewScene {
+    drawn: bool,
+    redraw: bool,
+    queue: ReviewQueue,
+    /// Exercise on the board
+    index: usize,
+    board: MiniBoard,
+    piece_hitboxes: Vec<Vec<mxcfb_rect>>,
+    selected_square: Option<Square>,
+    /// Framed on the board while it's checked and after it was correct
+    answer: Option<(Square, Square)>,
+    /// The engine judging the answer
+    checking: Option<Receiver<Result<Verdict>>>,
+    /// Answered correctly, waiting for the next exercise
+    solved: bool,
+    /// The solved exercise after it left the queue (the next one moved
+    /// to `index`), still shown until the next one is asked for
+    removed: Option<Exercise>,
+    status: String,
+
+    next_button_hitbox: Option<mxcfb_rect>,
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl ReviewScene {
+    pub fn new() -> Self {
+        let queue = crate::review_queue::read().unwrap_or_else(|err| {
+            error!("Failed to read the review queue: {:?}", err);
+            Default::default()
+        });
+        let status = if queue.exercises.is_empty() {
+            "No mistakes to review. Open the evaluation graph of a game to collect them.".to_owned()
+        } else {
+            "Tap the piece and where it should go".to_owned()
+        };
+        Self {
+            drawn: false,
+            redraw: false,
+            queue,
+            index: 0,
+            board: MiniBoard::new(SQUARE_SIZE),
+            piece_hitboxes: board_hitboxes(board_rect(SQUARE_SIZE), false),
+            selected_square: None,
+            answer: None,
+            checking: None,
+            solved: false,
+            removed: None,
+            status,
+            next_button_hitbox: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    fn next(&mut self) {
+        if self.checking.is_some() || self.queue.exercises.is_empty() {
+            return;
+        }
+        if self.removed.take().is_none() {
+            self.index += 1;
+        }
+        if self.index >= self.queue.exercises.len() {
+            self.index = 0;
+        }
+        self.selected_square = None;
+        self.answer = None;
+        self.solved = false;
+        self.status = "Tap the piece and where it should go".to_owned();
+        self.redraw = true;
+    }
+
+    fn on_square(&mut self, square: Square) {
+        let exercise = match self.queue.exercises.get(self.index) {
+            Some(exercise) if !self.solved && self.checking.is_none() => exercise.clone(),
+            _ => return,
+        };
+        self.redraw = true;
+        let source = match self.selected_square.take() {
+            Some(source) if source != square => source,
+            Some(_) => return,
+            None => {
+                let game = match ChessGame::from_fen(&exercise.fen) {
+                    Ok(game) => game,
+                    Err(_) => return,
+                };
+                if !game.possible_moves_from(square).is_empty() {
+                    self.selected_square = Some(square);
+                    self.answer = None;
+                }
+                return;
+            }
+        };
+        self.answer = Some((source, square));
+        self.status = "Checking...".to_owned();
+        let (verdict_tx, verdict_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            verdict_tx.send(exercise.check(source, square)).ok();
+        });
+        self.checking = Some(verdict_rx);
+    }
+
+    fn poll_verdict(&mut self) {
+        let verdict = match self.checking {
+            Some(ref checking) => match checking.try_recv() {
+                Ok(verdict) => verdict,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.checking = None;
+                    return;
+                }
+            },
+            None => return,
+        };
+        self.checking = None;
+        self.redraw = true;
+        let answer_san = self.answer_san();
+        match verdict {
+            Ok(Verdict::Better) => self.on_solved(),
+            Ok(Verdict::SameMistake) => {
+                self.answer = None;
+                self.status = format!("{} is the move of the game. Try again.", answer_san);
+            }
+            Ok(Verdict::AnotherMistake(mistake)) => {
+                self.answer = None;
+                self.status = format!("{}. Try again.", mistake.explanation(&answer_san));
+            }
+            Err(err) => {
+                debug!("Answer not checked: {:?}", err);
+                self.answer = None;
+                self.status = format!("{}", err);
+            }
+        }
+    }
+
+    /// The exercise on the board
+    fn exercise(&self) -> Option<&Exercise> {
+        self.removed
+            .as_ref()
+            .or_else(|| self.queue.exercises.get(self.index))
+    }
+
+    fn on_solved(&mut self) {
+        self.solved = true;
+        let exercise = self.queue.exercises.get(self.index).cloned();
+        if self.queue.mark_solved(self.index) {
+            self.removed = exercise.map(|mut exercise| {
+                exercise.solved = SOLVED_TIMES;
+                exercise
+            });
+        }
+        if let Err(err) = crate::review_queue::write(&self.queue) {
+            error!("Failed to write the review queue: {:?}", err);
+        }
+        self.status = if self.removed.is_some() {
+            format!(
+                "Correct! Solved {} times, it leaves the queue.",
+                SOLVED_TIMES
+            )
+        } else {
+            let solved = self.queue.exercises[self.index].solved;
+            format!("Correct! Solved {} of {} times.", solved, SOLVED_TIMES)
+        };
+    }
+
+    fn answer_san(&self) -> String {
+        let (source, destination) = match self.answer {
+            Some(answer) => answer,
+            None => return String::new(),
+        };
+        self.exercise()
+            .and_then(|exercise| ChessGame::from_fen(&exercise.fen).ok())
+            .and_then(|game| game.san(source, destination, None))
+            .unwrap_or_else(|| format!("{}{}", source, destination))
+    }
+
+    fn draw_task(&mut self, canvas: &mut Canvas) {
+        let area = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(TASK_TOP as i32),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: TASK_HEIGHT,
+            },
+            color::WHITE,
+        );
+        if let Some(exercise) = self.exercise() {
+            let task = match exercise.player() {
+                Ok(player) => format!("Find a better move for {}", player),
+                Err(_) => "Find a better move".to_owned(),
+            };
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(TASK_TOP as i32 + 45),
+                },
+                &task,
+                45.0,
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(TASK_TOP as i32 + 95),
+                },
+                &format!(
+                    "{}, solved {}/{} ({} in the queue)",
+                    exercise.source,
+                    exercise.solved,
+                    SOLVED_TIMES,
+                    self.queue.exercises.len()
+                ),
+                30.0,
+            );
+        }
+        canvas.mark_dirty(&area);
+    }
+
+    fn draw_board(&mut self, canvas: &mut Canvas) {
+        let fen = match self.exercise() {
+            Some(exercise) => exercise.fen.clone(),
+            None => return,
+        };
+        let board = board_rect(SQUARE_SIZE);
+        let rect = self.board.draw(
+            canvas,
+            Point2 {
+                x: board.left as i32,
+                y: board.top as i32,
+            },
+            &fen,
+        );
+        let framed: Vec<Square> = match self.answer {
+            Some((source, destination)) => vec![source, destination],
+            None => self.selected_square.into_iter().collect(),
+        };
+        for square in framed {
+            let bounds = self.piece_hitboxes[square.x() as usize][square.y() as usize];
+            canvas.draw_rect(
+                Point2 {
+                    x: Some(bounds.left as i32),
+                    y: Some(bounds.top as i32),
+                },
+                bounds.size().cast().unwrap(),
+                SELECTED_FRAME,
+            );
+        }
+        canvas.mark_dirty(&rect);
+    }
+
+    fn draw_status(&mut self, canvas: &mut Canvas) {
+        let area = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(STATUS_TOP as i32),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: STATUS_HEIGHT,
+            },
+            color::WHITE,
+        );
+        canvas.draw_wrapped_text(
+            None,
+            (STATUS_TOP + 40) as i32,
+            DISPLAYWIDTH as u32 - 100,
+            2,
+            &self.status,
+            STATUS_FONT_SIZE,
+            FontStyle::Regular,
+        );
+        canvas.mark_dirty(&area);
+    }
+}
+
+impl Scene for ReviewScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            canvas.clear();
+            canvas.draw_styled_text(
+                Point2 {
+                    x: None,
+                    y: Some(85),
+                },
+                "Review mistakes",
+                70.0,
+                FontStyle::Bold,
+            );
+            self.next_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: Some(40),
+                    y: Some(1830),
+                },
+                "Next",
+                50.0,
+                10,
+                20,
+            ));
+            self.back_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: None,
+                    y: Some(1830),
+                },
+                "Back",
+                75.0,
+                15,
+                40,
+            ));
+            self.draw_task(canvas);
+            self.draw_board(canvas);
+            self.draw_status(canvas);
+            canvas.update_full();
+            return;
+        }
+        self.poll_verdict();
+        if self.redraw {
+            self.redraw = false;
+            self.draw_task(canvas);
+            self.draw_board(canvas);
+            self.draw_status(canvas);
+            canvas.flush_dirty();
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => self.back_button_pressed = true,
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::RIGHT,
+                    },
+            } => self.next(),
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                } else if self.next_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.next_button_hitbox.unwrap())
+                {
+                    self.next();
+                } else {
+                    for x in 0..8 {
+                        for y in 0..8 {
+                            if Canvas::is_hitting(position, self.piece_hitboxes[x][y]) {
+                                self.on_square(Square::new(x, y).unwrap());
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
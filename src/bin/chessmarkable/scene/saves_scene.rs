@@ -0,0 +1,334 @@
+use super::dialog::Dialog;
+use super::mini_board::MiniBoard;
+use super::Scene;
+use crate::canvas::*;
+use crate::savestates::Savestate;
+use crate::scene::SavestateSlot;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+
+const FIRST_ROW_TOP: i32 = 280;
+const ROW_HEIGHT: u32 = 144;
+const ROW_GAP: i32 = 16;
+/// More saves don't fit above the back button
+const MAX_ROWS: usize = 8;
+/// Fits the thumbnail into a row
+const THUMBNAIL_SQUARE_SIZE: u32 = 17;
+const THUMBNAIL_PADDING: i32 = 4;
+const DELETE_BUTTON_WIDTH: u32 = 200;
+
+/// All named saves, the most recently saved first, with a thumbnail
+/// of their position. Tapping one continues it against the same opponent.
+pub struct SavesScene {
+    drawn: bool,
+    saves: Vec<Savestate>,
+    thumbnail: MiniBoard,
+    /// Shown below the title (errors and results of imports and exports)
+    message: Option<String>,
+    /// Save and the hitboxes to open and to delete it
+    save_hitboxes: Vec<(usize, mxcfb_rect, mxcfb_rect)>,
+    /// Waiting for the deletion to be confirmed
+    deleting: Option<u64>,
+    dialog: Option<Dialog>,
+    dialog_drawn: bool,
+    pub opened_save: Option<Savestate>,
+    export_button_hitbox: Option<mxcfb_rect>,
+    import_button_hitbox: Option<mxcfb_rect>,
+    pub pvp_piece_rotation_enabled: bool,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl SavesScene {
+    pub fn new(pvp_piece_rotation_enabled: bool) -> Self {
+        let mut scene = Self {
+            drawn: false,
+            saves: vec![],
+            thumbnail: MiniBoard::new(THUMBNAIL_SQUARE_SIZE),
+            message: None,
+            export_button_hitbox: None,
+            import_button_hitbox: None,
+            save_hitboxes: vec![],
+            deleting: None,
+            dialog: None,
+            dialog_drawn: false,
+            opened_save: None,
+            pvp_piece_rotation_enabled,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        };
+        scene.reload();
+        scene
+    }
+
+    fn reload(&mut self) {
+        self.saves = crate::SAVESTATES
+            .lock()
+            .unwrap()
+            .sorted()
+            .into_iter()
+            .cloned()
+            .collect();
+        self.drawn = false;
+    }
+
+    fn draw_saves(&mut self, canvas: &mut Canvas) {
+        self.save_hitboxes.clear();
+        if self.saves.is_empty() {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(FIRST_ROW_TOP + 100),
+                },
+                "No saved games yet",
+                50.0,
+            );
+        }
+        for (index, save) in self.saves.iter().take(MAX_ROWS).enumerate() {
+            let top = FIRST_ROW_TOP + index as i32 * (ROW_HEIGHT as i32 + ROW_GAP);
+            let row = canvas.draw_rect(
+                Point2 {
+                    x: Some(40),
+                    y: Some(top),
+                },
+                Vector2 {
+                    x: DISPLAYWIDTH as u32 - 80 - DELETE_BUTTON_WIDTH - 20,
+                    y: ROW_HEIGHT,
+                },
+                2,
+            );
+            let thumbnail = self.thumbnail.draw(
+                canvas,
+                Point2 {
+                    x: row.left as i32 + THUMBNAIL_PADDING,
+                    y: top + THUMBNAIL_PADDING,
+                },
+                &save.fen,
+            );
+            let text_left = (thumbnail.left + thumbnail.width) as i32 + 25;
+            canvas.draw_text(
+                Point2 {
+                    x: Some(text_left),
+                    y: Some(top + 60),
+                },
+                &save.name,
+                40.0,
+            );
+            canvas.draw_text(
+                Point2 {
+                    x: Some(text_left),
+                    y: Some(top + 115),
+                },
+                &save.summary(),
+                35.0,
+            );
+            let delete = canvas.draw_rect(
+                Point2 {
+                    x: Some((row.left + row.width + 20) as i32),
+                    y: Some(top),
+                },
+                Vector2 {
+                    x: DELETE_BUTTON_WIDTH,
+                    y: ROW_HEIGHT,
+                },
+                2,
+            );
+            canvas.draw_text_centered(delete, "Delete", 40.0);
+            self.save_hitboxes.push((index, row, delete));
+        }
+        if self.saves.len() > MAX_ROWS {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(FIRST_ROW_TOP + MAX_ROWS as i32 * (ROW_HEIGHT as i32 + ROW_GAP) + 20),
+                },
+                &format!("and {} older saves", self.saves.len() - MAX_ROWS),
+                35.0,
+            );
+        }
+    }
+
+    fn export_pgn(&mut self) {
+        self.message = Some(
+            match crate::savestates::export_pgn(&crate::SAVESTATES.lock().unwrap()) {
+                Ok(path) => format!("Exported to {}", path.display()),
+                Err(err) => {
+                    error!("Failed to export saves: {:?}", err);
+                    format!("Failed to export: {}", err)
+                }
+            },
+        );
+        self.drawn = false;
+    }
+
+    fn import_pgn(&mut self) {
+        let mut savestates = crate::SAVESTATES.lock().unwrap();
+        self.message = Some(match crate::savestates::import_pgn(&mut savestates) {
+            Ok((added, failed)) => {
+                if let Err(err) = crate::savestates::write(&savestates) {
+                    error!("Failed to write savestates file: {:?}", err);
+                }
+                if failed > 0 {
+                    format!("Imported {} game(s), {} failed (see log)", added, failed)
+                } else {
+                    format!("Imported {} game(s)", added)
+                }
+            }
+            Err(err) => {
+                error!("Failed to import saves: {:?}", err);
+                format!("Failed to import: {}", err)
+            }
+        });
+        drop(savestates);
+        self.reload();
+    }
+
+    fn on_dialog_choice(&mut self, choice: usize) {
+        self.dialog = None;
+        if let Some(id) = self.deleting.take() {
+            if choice == 1 {
+                let mut savestates = crate::SAVESTATES.lock().unwrap();
+                savestates.remove(id);
+                if let Err(err) = crate::savestates::write(&savestates) {
+                    error!("Failed to write savestates file: {:?}", err);
+                    self.message = Some(format!("Failed to delete the save: {}", err));
+                }
+                // Don't offer to continue it in the main menu anymore
+                if let Ok(Some(autosave)) = crate::autosave::read() {
+                    if autosave.slot == SavestateSlot::Saved(id) {
+                        if let Err(err) = crate::autosave::clear() {
+                            error!("Failed to remove autosave file: {:?}", err);
+                        }
+                    }
+                }
+            }
+        }
+        self.reload();
+    }
+}
+
+impl Scene for SavesScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if self.drawn {
+            if let Some(ref mut dialog) = self.dialog {
+                if !self.dialog_drawn {
+                    let rect = dialog.draw(canvas);
+                    canvas.mark_dirty(&rect);
+                    self.dialog_drawn = true;
+                }
+            }
+            return;
+        }
+        self.drawn = true;
+        self.dialog_drawn = false;
+
+        canvas.clear();
+        canvas.draw_styled_text(
+            Point2 {
+                x: None,
+                y: Some(150),
+            },
+            "Saved Games",
+            100.0,
+            FontStyle::Bold,
+        );
+        if let Some(ref message) = self.message {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(230),
+                },
+                message,
+                35.0,
+            );
+        }
+        self.draw_saves(canvas);
+        self.back_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1830),
+            },
+            "Main Menu",
+            75.0,
+            15,
+            40,
+        ));
+        self.export_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(40),
+                y: Some(1830),
+            },
+            "Export PGN",
+            40.0,
+            10,
+            20,
+        ));
+        self.import_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 300),
+                y: Some(1830),
+            },
+            "Import PGN",
+            40.0,
+            10,
+            20,
+        ));
+        canvas.update_full();
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        if let Some(ref mut dialog) = self.dialog {
+            if let Some(choice) = dialog.on_input(event) {
+                self.on_dialog_choice(choice);
+            }
+            return;
+        }
+        match event {
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => self.back_button_pressed = true,
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                    return;
+                } else if self.export_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.export_button_hitbox.unwrap())
+                {
+                    self.export_pgn();
+                    return;
+                } else if self.import_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.import_button_hitbox.unwrap())
+                {
+                    self.import_pgn();
+                    return;
+                }
+                for (index, row, delete) in self.save_hitboxes.iter() {
+                    let save = &self.saves[*index];
+                    if Canvas::is_hitting(position, *row) {
+                        self.opened_save = Some(save.clone());
+                    } else if Canvas::is_hitting(position, *delete) {
+                        self.deleting = Some(save.id);
+                        self.dialog = Some(Dialog::new(
+                            &format!("Delete \"{}\"? This can't be undone.", save.name),
+                            &["Cancel", "Delete"],
+                        ));
+                        self.dialog_drawn = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
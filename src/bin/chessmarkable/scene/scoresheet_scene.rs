@@ -0,0 +1,466 @@
+use super::Scene;
+use crate::canvas::*;
+use crate::scene::ink::Ink;
+use crate::scoresheets;
+use chessmarkable::handwriting::Stroke;
+use chessmarkable::scoresheet::Scoresheet;
+use libremarkable::input::{
+    gpio::GPIOEvent, gpio::PhysicalButton, multitouch::MultitouchEvent, wacom::WacomEvent,
+    wacom::WacomPen, InputEvent,
+};
+use std::path::PathBuf;
+
+const INK_WIDTH: u32 = 4;
+const GRID_TOP: i32 = 240;
+const GRID_LEFT: i32 = 40;
+const ROW_HEIGHT: i32 = 70;
+const ROWS_PER_COLUMN: usize = 20;
+/// Width of the move numbers and the cells of one column of moves
+const NUMBER_WIDTH: i32 = 82;
+const CELL_WIDTH: i32 = 280;
+/// Space between the two columns of moves
+const COLUMN_GAP: i32 = 36;
+/// Moves (of both players) per page
+const MOVES_PER_PAGE: usize = ROWS_PER_COLUMN * 2;
+
+/// A tournament scoresheet to write the moves of a game on a real board
+/// with the pen. Every stroke is saved right away.
+pub struct ScoresheetScene {
+    drawn: bool,
+    path: PathBuf,
+    scoresheet: Scoresheet,
+    page: usize,
+    erasing: bool,
+    /// Stroke being written and the cell it started in
+    stroke: Option<(usize, Stroke)>,
+    ink: Ink,
+    /// Cells that were erased and need to be redrawn
+    redraw_cells: Vec<usize>,
+    redraw_page: bool,
+    message: Option<String>,
+    message_last_rect: Option<mxcfb_rect>,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+    previous_page_button_hitbox: Option<mxcfb_rect>,
+    next_page_button_hitbox: Option<mxcfb_rect>,
+    new_button_hitbox: Option<mxcfb_rect>,
+    transcribe_button_hitbox: Option<mxcfb_rect>,
+}
+
+impl ScoresheetScene {
+    pub fn new() -> Self {
+        let (path, scoresheet) = match scoresheets::read_latest() {
+            Ok(Some(latest)) => latest,
+            Ok(None) => (scoresheets::new_path(), Scoresheet::default()),
+            Err(err) => {
+                error!("Failed to read the last scoresheet: {:?}", err);
+                (scoresheets::new_path(), Scoresheet::default())
+            }
+        };
+        // Continue on the page of the last written move
+        let page = scoresheet.cells.len().saturating_sub(1) / 2 / MOVES_PER_PAGE;
+        Self {
+            drawn: false,
+            path,
+            scoresheet,
+            page,
+            erasing: false,
+            stroke: None,
+            ink: Default::default(),
+            redraw_cells: vec![],
+            redraw_page: false,
+            message: None,
+            message_last_rect: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+            previous_page_button_hitbox: None,
+            next_page_button_hitbox: None,
+            new_button_hitbox: None,
+            transcribe_button_hitbox: None,
+        }
+    }
+
+    /// Area of a cell on the current page (`None` if it's on another one)
+    fn cell_rect(&self, cell: usize) -> Option<mxcfb_rect> {
+        let move_index = cell / 2;
+        if move_index / MOVES_PER_PAGE != self.page {
+            return None;
+        }
+        let column = (move_index % MOVES_PER_PAGE) / ROWS_PER_COLUMN;
+        let row = move_index % ROWS_PER_COLUMN;
+        let column_left = GRID_LEFT + column as i32 * (NUMBER_WIDTH + 2 * CELL_WIDTH + COLUMN_GAP);
+        Some(mxcfb_rect {
+            left: (column_left + NUMBER_WIDTH + (cell % 2) as i32 * CELL_WIDTH) as u32,
+            top: (GRID_TOP + row as i32 * ROW_HEIGHT) as u32,
+            width: CELL_WIDTH as u32,
+            height: ROW_HEIGHT as u32,
+        })
+    }
+
+    fn cell_at(&self, pos: Point2<i32>) -> Option<usize> {
+        let first_cell = self.page * MOVES_PER_PAGE * 2;
+        (first_cell..first_cell + MOVES_PER_PAGE * 2).find(|cell| {
+            let rect = self.cell_rect(*cell).unwrap();
+            pos.x >= rect.left as i32
+                && pos.x < (rect.left + rect.width) as i32
+                && pos.y >= rect.top as i32
+                && pos.y < (rect.top + rect.height) as i32
+        })
+    }
+
+    fn draw_page(&mut self, canvas: &mut Canvas) {
+        canvas.clear();
+        canvas.draw_styled_text(
+            Point2 {
+                x: None,
+                y: Some(130),
+            },
+            "Scoresheet",
+            90.0,
+            FontStyle::Bold,
+        );
+
+        for column in 0..2 {
+            let column_left = GRID_LEFT + column * (NUMBER_WIDTH + 2 * CELL_WIDTH + COLUMN_GAP);
+            for (i, player) in ["White", "Black"].iter().enumerate() {
+                let header = mxcfb_rect {
+                    left: (column_left + NUMBER_WIDTH + i as i32 * CELL_WIDTH) as u32,
+                    top: (GRID_TOP - ROW_HEIGHT) as u32,
+                    width: CELL_WIDTH as u32,
+                    height: ROW_HEIGHT as u32,
+                };
+                canvas.draw_text_centered(header, player, 40.0);
+            }
+            for row in 0..ROWS_PER_COLUMN {
+                let move_number =
+                    self.page * MOVES_PER_PAGE + column as usize * ROWS_PER_COLUMN + row + 1;
+                let number = mxcfb_rect {
+                    left: column_left as u32,
+                    top: (GRID_TOP + row as i32 * ROW_HEIGHT) as u32,
+                    width: NUMBER_WIDTH as u32,
+                    height: ROW_HEIGHT as u32,
+                };
+                canvas.draw_text_centered(number, &move_number.to_string(), 35.0);
+            }
+            canvas.draw_rect(
+                Point2 {
+                    x: Some(column_left),
+                    y: Some(GRID_TOP),
+                },
+                Vector2 {
+                    x: (NUMBER_WIDTH + 2 * CELL_WIDTH) as u32,
+                    y: (ROWS_PER_COLUMN as i32 * ROW_HEIGHT) as u32,
+                },
+                2,
+            );
+        }
+        let first_cell = self.page * MOVES_PER_PAGE * 2;
+        for cell in first_cell..first_cell + MOVES_PER_PAGE * 2 {
+            let rect = self.cell_rect(cell).unwrap();
+            self.draw_cell(canvas, cell, rect);
+        }
+
+        let buttons_y = GRID_TOP + ROWS_PER_COLUMN as i32 * ROW_HEIGHT + 180;
+        self.back_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(50),
+                y: Some(buttons_y),
+            },
+            "Main Menu",
+            50.0,
+            10,
+            20,
+        ));
+        self.previous_page_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(420),
+                y: Some(buttons_y),
+            },
+            "<",
+            50.0,
+            10,
+            30,
+        ));
+        canvas.draw_text(
+            Point2 {
+                x: Some(510),
+                y: Some(buttons_y),
+            },
+            &format!("Page {}", self.page + 1),
+            40.0,
+        );
+        self.next_page_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(690),
+                y: Some(buttons_y),
+            },
+            ">",
+            50.0,
+            10,
+            30,
+        ));
+        self.new_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(840),
+                y: Some(buttons_y),
+            },
+            "New",
+            50.0,
+            10,
+            20,
+        ));
+        self.transcribe_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(1040),
+                y: Some(buttons_y),
+            },
+            "To PGN",
+            50.0,
+            10,
+            20,
+        ));
+    }
+
+    /// Grid lines of the cell and what was written into it
+    fn draw_cell(&self, canvas: &mut Canvas, cell: usize, rect: mxcfb_rect) {
+        canvas.draw_rect(
+            Point2 {
+                x: Some(rect.left as i32),
+                y: Some(rect.top as i32),
+            },
+            Vector2 {
+                x: rect.width,
+                y: rect.height,
+            },
+            1,
+        );
+        for stroke in self.scoresheet.cells.get(cell).into_iter().flatten() {
+            for points in stroke.windows(2) {
+                canvas.draw_line(
+                    Point2 {
+                        x: points[0].0 as i32,
+                        y: points[0].1 as i32,
+                    },
+                    Point2 {
+                        x: points[1].0 as i32,
+                        y: points[1].1 as i32,
+                    },
+                    INK_WIDTH,
+                );
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        if let Err(err) = scoresheets::write(&self.path, &self.scoresheet) {
+            error!("Failed to save the scoresheet: {:?}", err);
+            self.message = Some("Failed to save the scoresheet".to_owned());
+        }
+    }
+
+    fn finish_stroke(&mut self) {
+        self.ink.clear();
+        if let Some((cell, stroke)) = self.stroke.take() {
+            self.scoresheet.add_stroke(cell, stroke);
+            self.save();
+        }
+    }
+
+    fn erase(&mut self, pos: Point2<i32>) {
+        if let Some(cell) = self.cell_at(pos) {
+            if self.scoresheet.clear_cell(cell) {
+                self.redraw_cells.push(cell);
+                self.save();
+            }
+        }
+    }
+
+    fn new_scoresheet(&mut self) {
+        if self.scoresheet.is_empty() {
+            return;
+        }
+        self.path = scoresheets::new_path();
+        self.scoresheet = Scoresheet::default();
+        self.page = 0;
+        self.redraw_page = true;
+        self.message = Some("Started a new scoresheet".to_owned());
+    }
+
+    fn transcribe(&mut self) {
+        let transcription = self.scoresheet.transcribe();
+        if transcription.moves.is_empty() {
+            self.message = Some("Couldn't read the first move".to_owned());
+            return;
+        }
+        self.message = Some(
+            match scoresheets::write_pgn(&self.path, &transcription.moves) {
+                Ok(_) => match transcription.unreadable_cell {
+                    Some(cell) => format!(
+                        "Saved {} moves to the PGN viewer. Couldn't read move {} of {}.",
+                        transcription.moves.len(),
+                        cell / 2 + 1,
+                        if cell % 2 == 0 { "white" } else { "black" }
+                    ),
+                    None => format!(
+                        "Saved {} moves to the PGN viewer",
+                        transcription.moves.len()
+                    ),
+                },
+                Err(err) => {
+                    error!("Failed to write the PGN of the scoresheet: {:?}", err);
+                    "Failed to write the PGN".to_owned()
+                }
+            },
+        );
+    }
+
+    fn change_page(&mut self, forward: bool) {
+        if forward {
+            self.page += 1;
+        } else if self.page > 0 {
+            self.page -= 1;
+        } else {
+            return;
+        }
+        self.redraw_page = true;
+    }
+}
+
+impl Scene for ScoresheetScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn || self.redraw_page {
+            self.drawn = true;
+            self.redraw_page = false;
+            self.redraw_cells.clear();
+            self.message_last_rect = None;
+            self.draw_page(canvas);
+            canvas.update_full();
+        }
+
+        for cell in std::mem::take(&mut self.redraw_cells) {
+            if let Some(rect) = self.cell_rect(cell) {
+                canvas.fill_rect(
+                    Point2 {
+                        x: Some(rect.left as i32),
+                        y: Some(rect.top as i32),
+                    },
+                    Vector2 {
+                        x: rect.width,
+                        y: rect.height,
+                    },
+                    color::WHITE,
+                );
+                self.draw_cell(canvas, cell, rect);
+                canvas.mark_dirty(&rect);
+            }
+        }
+
+        self.ink.show_lines(canvas, INK_WIDTH);
+
+        if let Some(message) = self.message.take() {
+            if let Some(ref last_rect) = self.message_last_rect {
+                canvas.fill_rect(
+                    Point2 {
+                        x: Some(last_rect.left as i32),
+                        y: Some(last_rect.top as i32),
+                    },
+                    Vector2 {
+                        x: last_rect.width,
+                        y: last_rect.height,
+                    },
+                    color::WHITE,
+                );
+                canvas.mark_dirty(last_rect);
+            }
+            let rect = canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(GRID_TOP + ROWS_PER_COLUMN as i32 * ROW_HEIGHT + 80),
+                },
+                &message,
+                35.0,
+            );
+            canvas.mark_dirty(&rect);
+            self.message_last_rect = Some(rect);
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::WacomEvent { event } => match event {
+                WacomEvent::InstrumentChange { pen, state } => {
+                    if pen == WacomPen::ToolRubber {
+                        self.erasing = state;
+                    }
+                    if !state {
+                        self.finish_stroke();
+                    }
+                }
+                WacomEvent::Draw { position, .. } => {
+                    let pos = Point2 {
+                        x: position.x as i32,
+                        y: position.y as i32,
+                    };
+                    if self.erasing {
+                        self.erase(pos);
+                        return;
+                    }
+                    let cell = match self.stroke {
+                        Some((cell, _)) => cell,
+                        None => match self.cell_at(pos) {
+                            Some(cell) => {
+                                self.stroke = Some((cell, vec![]));
+                                cell
+                            }
+                            None => return,
+                        },
+                    };
+                    let (_, stroke) = self.stroke.as_mut().unwrap();
+                    stroke.push((position.x, position.y));
+                    // Ink outside of the cell isn't shown (but still belongs to the stroke)
+                    if self.cell_at(pos) == Some(cell) {
+                        self.ink.push(pos);
+                    }
+                }
+                WacomEvent::Hover { .. } => self.finish_stroke(),
+                _ => {}
+            },
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                } else if self.previous_page_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.previous_page_button_hitbox.unwrap())
+                {
+                    self.change_page(false);
+                } else if self.next_page_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.next_page_button_hitbox.unwrap())
+                {
+                    self.change_page(true);
+                } else if self.new_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.new_button_hitbox.unwrap())
+                {
+                    self.new_scoresheet();
+                } else if self.transcribe_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.transcribe_button_hitbox.unwrap())
+                {
+                    self.transcribe();
+                }
+            }
+            InputEvent::GPIO {
+                event: GPIOEvent::Press { button },
+            } => match button {
+                PhysicalButton::LEFT => self.change_page(false),
+                PhysicalButton::RIGHT => self.change_page(true),
+                PhysicalButton::MIDDLE => self.back_button_pressed = true,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use super::Scene;
+use crate::canvas::*;
+use crate::scene::piece_images::piece_sets;
+use crate::settings::Settings;
+use libremarkable::input::{
+    gpio::GPIOEvent, gpio::PhysicalButton, multitouch::MultitouchEvent, InputEvent,
+};
+
+const FIRST_ENTRY_Y_POS: i32 = 300;
+const ENTRY_HEIGHT: i32 = 74;
+
+/// Values the bot reaction delay cycles through (in milliseconds)
+const BOT_REACTION_DELAYS: &[u16] = &[0, 500, 1000, 1500, 3000];
+/// Values the number of partial refreshes before a full one cycles through
+const FULL_REFRESH_INTERVALS: &[u16] = &[0, 25, 50, 100, 200];
+/// Values the minimum think time cycles through (in seconds)
+const MIN_THINK_TIMES: &[u16] = &[0, 5, 10, 30, 60];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Entry {
+    BoardFlipped,
+    PvpAutoFlip,
+    PvpHideBoard,
+    ShowCoordinates,
+    PieceSet,
+    BoardTheme,
+    BoardBorder,
+    Orientation,
+    FullRefreshAfter,
+    HighlightLastMove,
+    HighlightCheck,
+    AlwaysQueen,
+    ConfirmMoves,
+    Undo,
+    BotReactionDelay,
+    Coach,
+    MinThinkTime,
+}
+
+const ALL_ENTRIES: [Entry; 17] = [
+    Entry::BoardFlipped,
+    Entry::PvpAutoFlip,
+    Entry::PvpHideBoard,
+    Entry::ShowCoordinates,
+    Entry::PieceSet,
+    Entry::BoardTheme,
+    Entry::BoardBorder,
+    Entry::Orientation,
+    Entry::FullRefreshAfter,
+    Entry::HighlightLastMove,
+    Entry::HighlightCheck,
+    Entry::AlwaysQueen,
+    Entry::ConfirmMoves,
+    Entry::Undo,
+    Entry::BotReactionDelay,
+    Entry::Coach,
+    Entry::MinThinkTime,
+];
+
+impl Entry {
+    fn label(self) -> &'static str {
+        match self {
+            Entry::BoardFlipped => "View from black's side",
+            Entry::PvpAutoFlip => "Auto flip (PvP)",
+            Entry::PvpHideBoard => "Hide board between moves (PvP)",
+            Entry::ShowCoordinates => "Show coordinates",
+            Entry::PieceSet => "Pieces",
+            Entry::BoardTheme => "Squares",
+            Entry::BoardBorder => "Border",
+            Entry::Orientation => "Games in",
+            Entry::FullRefreshAfter => "Full refresh after",
+            Entry::HighlightLastMove => "Highlight last move",
+            Entry::HighlightCheck => "Highlight check",
+            Entry::AlwaysQueen => "Always promote to queen",
+            Entry::ConfirmMoves => "Confirm moves",
+            Entry::Undo => "Undo",
+            Entry::BotReactionDelay => "Bot reaction delay",
+            Entry::Coach => "Coach (vs bot)",
+            Entry::MinThinkTime => "Min. think time",
+        }
+    }
+
+    fn value(self, settings: &Settings) -> String {
+        let on_off = |enabled: bool| if enabled { "On" } else { "Off" }.to_owned();
+        match self {
+            Entry::BoardFlipped => on_off(settings.board_flipped),
+            Entry::PvpAutoFlip => on_off(settings.pvp_auto_flip),
+            Entry::PvpHideBoard => on_off(settings.pvp_hide_board),
+            Entry::ShowCoordinates => on_off(settings.show_coordinates),
+            Entry::PieceSet => settings
+                .piece_set
+                .clone()
+                .unwrap_or_else(|| "Built-in".to_owned()),
+            Entry::BoardTheme => settings.board_theme.label().to_owned(),
+            Entry::BoardBorder => settings.board_border.label().to_owned(),
+            Entry::Orientation => settings.orientation.label().to_owned(),
+            Entry::FullRefreshAfter => match settings.full_refresh_after {
+                0 => "Never".to_owned(),
+                updates => format!("{} updates", updates),
+            },
+            Entry::HighlightLastMove => on_off(settings.highlight_last_move),
+            Entry::HighlightCheck => on_off(settings.highlight_check),
+            Entry::AlwaysQueen => on_off(settings.always_queen),
+            Entry::ConfirmMoves => on_off(settings.confirm_moves),
+            Entry::Undo => settings.undo.label().to_owned(),
+            Entry::BotReactionDelay => format!("{} ms", settings.bot_reaction_delay),
+            Entry::Coach => on_off(settings.coach),
+            Entry::MinThinkTime => match settings.min_think_time {
+                0 => "Off".to_owned(),
+                secs => format!("{} s", secs),
+            },
+        }
+    }
+
+    /// Toggle or advance to the next value
+    fn change(self, settings: &mut Settings) {
+        match self {
+            Entry::BoardFlipped => settings.board_flipped = !settings.board_flipped,
+            Entry::PvpAutoFlip => settings.pvp_auto_flip = !settings.pvp_auto_flip,
+            Entry::PvpHideBoard => settings.pvp_hide_board = !settings.pvp_hide_board,
+            Entry::ShowCoordinates => settings.show_coordinates = !settings.show_coordinates,
+            Entry::PieceSet => {
+                // Built-in pieces come first, then the installed sets
+                let sets = piece_sets();
+                let next_index = match settings.piece_set {
+                    Some(ref current) => sets.iter().position(|set| set == current).map(|i| i + 1),
+                    None => Some(0),
+                };
+                settings.piece_set = next_index.and_then(|i| sets.get(i).cloned());
+            }
+            Entry::HighlightLastMove => {
+                settings.highlight_last_move = !settings.highlight_last_move
+            }
+            Entry::HighlightCheck => settings.highlight_check = !settings.highlight_check,
+            Entry::AlwaysQueen => settings.always_queen = !settings.always_queen,
+            Entry::ConfirmMoves => settings.confirm_moves = !settings.confirm_moves,
+            Entry::BoardTheme => settings.board_theme = settings.board_theme.next(),
+            Entry::BoardBorder => settings.board_border = settings.board_border.next(),
+            Entry::Orientation => settings.orientation = settings.orientation.next(),
+            Entry::FullRefreshAfter => {
+                settings.full_refresh_after =
+                    next_value(FULL_REFRESH_INTERVALS, settings.full_refresh_after)
+            }
+            Entry::Undo => settings.undo = settings.undo.next(),
+            Entry::BotReactionDelay => {
+                settings.bot_reaction_delay =
+                    next_value(BOT_REACTION_DELAYS, settings.bot_reaction_delay)
+            }
+            Entry::Coach => settings.coach = !settings.coach,
+            Entry::MinThinkTime => {
+                settings.min_think_time = next_value(MIN_THINK_TIMES, settings.min_think_time)
+            }
+        }
+    }
+}
+
+/// The next bigger value or the first one if there is none (e.g. after
+/// the settings file was edited by hand)
+fn next_value(values: &[u16], current: u16) -> u16 {
+    values
+        .iter()
+        .copied()
+        .find(|value| *value > current)
+        .unwrap_or(values[0])
+}
+
+pub struct SettingsScene {
+    drawn: bool,
+    /// Entries whose value changed and needs to be redrawn
+    redraw_entries: Vec<usize>,
+    entry_hitboxes: Vec<mxcfb_rect>,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl SettingsScene {
+    pub fn new() -> Self {
+        Self {
+            drawn: false,
+            redraw_entries: vec![],
+            entry_hitboxes: vec![],
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    fn draw_entry(&self, canvas: &mut Canvas, index: usize) -> mxcfb_rect {
+        let y = FIRST_ENTRY_Y_POS + ENTRY_HEIGHT * index as i32;
+        let rect = canvas.fill_rect(
+            Point2 {
+                x: Some(0),
+                y: Some(y - ENTRY_HEIGHT / 2 - 13),
+            },
+            Vector2 {
+                x: DISPLAYWIDTH as u32,
+                y: ENTRY_HEIGHT as u32,
+            },
+            color::WHITE,
+        );
+        let entry = ALL_ENTRIES[index];
+        let value = entry.value(&crate::SETTINGS.lock().unwrap());
+        canvas.draw_text(
+            Point2 {
+                x: Some(80),
+                y: Some(y),
+            },
+            entry.label(),
+            50.0,
+        );
+        canvas.draw_button(
+            Point2 {
+                x: Some(DISPLAYWIDTH as i32 - 380),
+                y: Some(y),
+            },
+            &value,
+            50.0,
+            10,
+            25,
+        );
+        rect
+    }
+
+    fn change_entry(&mut self, index: usize) {
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        ALL_ENTRIES[index].change(&mut settings);
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+        // Edits of config.toml apply from here on too
+        crate::config::reload();
+        self.redraw_entries.push(index);
+    }
+}
+
+impl Scene for SettingsScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if !self.drawn {
+            self.drawn = true;
+            self.redraw_entries.clear();
+            canvas.clear();
+            canvas.draw_styled_text(
+                Point2 {
+                    x: None,
+                    y: Some(200),
+                },
+                "Settings",
+                125.0,
+                FontStyle::Bold,
+            );
+            self.entry_hitboxes = (0..ALL_ENTRIES.len())
+                .map(|index| self.draw_entry(canvas, index))
+                .collect();
+
+            for (i, (name, path)) in [
+                ("Settings", crate::config::settings_file()),
+                ("Savestates", crate::config::savestates_file()),
+                ("PGNs", crate::config::pgn_location()),
+                ("Piece sets", crate::config::pieces_location()),
+            ]
+            .iter()
+            .enumerate()
+            {
+                canvas.draw_text(
+                    Point2 {
+                        x: Some(80),
+                        y: Some(1540 + 50 * i as i32),
+                    },
+                    &format!("{}: {}", name, path.display()),
+                    35.0,
+                );
+            }
+
+            self.back_button_hitbox = Some(canvas.draw_button(
+                Point2 {
+                    x: None,
+                    y: Some(1800),
+                },
+                "Main Menu",
+                75.0,
+                25,
+                50,
+            ));
+            canvas.update_full();
+        }
+
+        for index in std::mem::take(&mut self.redraw_entries) {
+            let rect = self.draw_entry(canvas, index);
+            canvas.mark_dirty(&rect);
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(finger.pos, self.back_button_hitbox.unwrap())
+                {
+                    self.back_button_pressed = true;
+                } else if let Some(index) = self
+                    .entry_hitboxes
+                    .iter()
+                    .position(|hitbox| Canvas::is_hitting(finger.pos, *hitbox))
+                {
+                    self.change_entry(index);
+                }
+            }
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => self.back_button_pressed = true,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use super::mini_board::MiniBoard;
+use super::{GameMode, GameScene, SavestateSlot, Scene};
+use crate::canvas::*;
+use crate::settings::Orientation;
+use chessmarkable::game::Odds;
+use chessmarkable::proto::VariantKind;
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+
+const MIN_BOARDS: usize = 2;
+const MAX_BOARDS: usize = 6;
+const DEFAULT_BOARDS: usize = 4;
+const BOARD_SQUARE_SIZE: u32 = 50;
+const COLUMNS: usize = 2;
+const FIRST_ROW_TOP: i32 = 260;
+/// Board and the status below it
+const ROW_HEIGHT: i32 = 510;
+const STATUS_HEIGHT: u32 = 70;
+
+/// Several games against the bot at once. Every board is its own game
+/// (and save) that keeps running while another one is open. The overview
+/// shows on which boards it's your move.
+pub struct SimulScene {
+    drawn: bool,
+    pub game_mode: GameMode,
+    pub pvp_piece_rotation_enabled: bool,
+    board_count: usize,
+    boards_button_hitbox: Option<mxcfb_rect>,
+    bot_button_hitbox: Option<mxcfb_rect>,
+    start_button_hitbox: Option<mxcfb_rect>,
+
+    games: Vec<GameScene>,
+    /// Board that is played on (the overview is shown otherwise)
+    opened: Option<usize>,
+    /// Position and status of every board as last drawn
+    shown: Vec<Option<(String, &'static str)>>,
+    board_hitboxes: Vec<mxcfb_rect>,
+    mini_board: MiniBoard,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl SimulScene {
+    pub fn new(game_mode: GameMode, pvp_piece_rotation_enabled: bool) -> Self {
+        Self {
+            drawn: false,
+            game_mode,
+            pvp_piece_rotation_enabled,
+            board_count: DEFAULT_BOARDS,
+            boards_button_hitbox: None,
+            bot_button_hitbox: None,
+            start_button_hitbox: None,
+            games: vec![],
+            opened: None,
+            shown: vec![],
+            board_hitboxes: vec![],
+            mini_board: MiniBoard::new(BOARD_SQUARE_SIZE),
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    fn next_bot(&mut self) {
+        self.game_mode = match self.game_mode {
+            GameMode::EasyBot => GameMode::NormalBot,
+            GameMode::NormalBot => GameMode::HardBot,
+            _ => GameMode::EasyBot,
+        };
+        self.drawn = false;
+    }
+
+    /// Add a save for every board and start their games
+    fn start(&mut self) {
+        let mut savestates = crate::SAVESTATES.lock().unwrap();
+        let ids: Vec<u64> = (0..self.board_count)
+            .map(|board| {
+                savestates.add(
+                    format!("Simul board {}", board + 1),
+                    self.game_mode,
+                    VariantKind::Standard,
+                    Odds::None.starting_fen(),
+                )
+            })
+            .collect();
+        if let Err(err) = crate::savestates::write(&savestates) {
+            error!("Failed to write savestates file: {:?}", err);
+        }
+        drop(savestates);
+
+        self.games = ids
+            .into_iter()
+            .map(|id| {
+                GameScene::new(
+                    self.game_mode,
+                    SavestateSlot::Saved(id),
+                    self.pvp_piece_rotation_enabled,
+                    VariantKind::Standard,
+                )
+                .in_simul()
+            })
+            .collect();
+        self.shown = vec![None; self.games.len()];
+        self.drawn = false;
+    }
+
+    fn status(game: &GameScene) -> &'static str {
+        if game.is_game_over() {
+            "Game over"
+        } else if game.waiting_for_local_player() {
+            "Your move"
+        } else {
+            "Bot is thinking"
+        }
+    }
+
+    fn board_top_left(&self, board: usize) -> Point2<i32> {
+        let gap =
+            (DISPLAYWIDTH as u32 - self.mini_board.size() * COLUMNS as u32) / (COLUMNS as u32 + 1);
+        let column = (board % COLUMNS) as u32;
+        Point2 {
+            x: (gap + column * (self.mini_board.size() + gap)) as i32,
+            y: FIRST_ROW_TOP + (board / COLUMNS) as i32 * ROW_HEIGHT,
+        }
+    }
+
+    /// Redraw the boards that changed since they were last drawn
+    fn draw_boards(&mut self, canvas: &mut Canvas, full_redraw: bool) {
+        self.board_hitboxes.clear();
+        for board in 0..self.games.len() {
+            let current = (self.games[board].fen(), Self::status(&self.games[board]));
+            let top_left = self.board_top_left(board);
+            let board_rect = mxcfb_rect {
+                left: top_left.x as u32,
+                top: top_left.y as u32,
+                width: self.mini_board.size(),
+                height: self.mini_board.size() + STATUS_HEIGHT,
+            };
+            self.board_hitboxes.push(board_rect);
+            if !full_redraw && self.shown[board].as_ref() == Some(&current) {
+                continue;
+            }
+
+            self.mini_board.draw(canvas, top_left, &current.0);
+            let status_rect = canvas.fill_rect(
+                Point2 {
+                    x: Some(top_left.x),
+                    y: Some(top_left.y + self.mini_board.size() as i32),
+                },
+                Vector2 {
+                    x: self.mini_board.size(),
+                    y: STATUS_HEIGHT,
+                },
+                color::WHITE,
+            );
+            canvas.draw_text_centered(status_rect, current.1, 40.0);
+            if !full_redraw {
+                canvas.update_partial(&board_rect);
+            }
+            self.shown[board] = Some(current);
+        }
+    }
+
+    fn draw_setup(&mut self, canvas: &mut Canvas) {
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(300),
+            },
+            "You play white on every board",
+            40.0,
+        );
+        self.boards_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(500),
+            },
+            &format!("Boards: {}", self.board_count),
+            75.0,
+            10,
+            20,
+        ));
+        self.bot_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(700),
+            },
+            self.game_mode.label(),
+            75.0,
+            10,
+            20,
+        ));
+        self.start_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1000),
+            },
+            "Start",
+            125.0,
+            25,
+            50,
+        ));
+    }
+
+    fn on_setup_tap(&mut self, position: Point2<u16>) {
+        if self.boards_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.boards_button_hitbox.unwrap())
+        {
+            self.board_count = if self.board_count >= MAX_BOARDS {
+                MIN_BOARDS
+            } else {
+                self.board_count + 1
+            };
+            self.drawn = false;
+        } else if self.bot_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.bot_button_hitbox.unwrap())
+        {
+            self.next_bot();
+        } else if self.start_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.start_button_hitbox.unwrap())
+        {
+            self.start();
+        }
+    }
+
+    fn open(&mut self, board: usize) {
+        self.games[board].reopen();
+        self.opened = Some(board);
+    }
+
+    /// Save every board and end their games
+    fn leave(&mut self) {
+        for game in self.games.iter_mut() {
+            game.adjourn();
+        }
+        self.back_button_pressed = true;
+    }
+}
+
+impl Scene for SimulScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        // The boards that aren't shown keep going as well
+        for (board, game) in self.games.iter_mut().enumerate() {
+            if self.opened != Some(board) {
+                game.poll_updates();
+            }
+        }
+
+        if let Some(board) = self.opened {
+            // Opened by the input of this frame
+            canvas.set_orientation(self.games[board].orientation());
+            self.games[board].draw(canvas);
+            if self.games[board].back_button_pressed {
+                self.opened = None;
+                self.drawn = false;
+                canvas.set_orientation(Orientation::Portrait);
+            } else {
+                return;
+            }
+        }
+
+        if self.drawn {
+            self.draw_boards(canvas, false);
+            return;
+        }
+        self.drawn = true;
+
+        canvas.clear();
+        canvas.draw_styled_text(
+            Point2 {
+                x: None,
+                y: Some(150),
+            },
+            "Simul",
+            100.0,
+            FontStyle::Bold,
+        );
+        if self.games.is_empty() {
+            self.draw_setup(canvas);
+        } else {
+            self.draw_boards(canvas, true);
+        }
+        self.back_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1830),
+            },
+            if self.games.is_empty() {
+                "Back"
+            } else {
+                "Save & Quit"
+            },
+            75.0,
+            15,
+            40,
+        ));
+        canvas.update_full();
+    }
+
+    fn orientation(&self) -> Orientation {
+        match self.opened {
+            Some(board) => self.games[board].orientation(),
+            None => Orientation::Portrait,
+        }
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        if let Some(board) = self.opened {
+            self.games[board].on_input(event);
+            return;
+        }
+        match event {
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => self.leave(),
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    self.leave();
+                } else if self.games.is_empty() {
+                    self.on_setup_tap(position);
+                } else if let Some(board) = self
+                    .board_hitboxes
+                    .iter()
+                    .position(|rect| Canvas::is_hitting(position, *rect))
+                {
+                    self.open(board);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,591 @@
+use super::mini_board::MiniBoard;
+use super::Scene;
+use crate::canvas::*;
+use anyhow::{Context, Result};
+use chessmarkable::scoresheet::pgn_date;
+use chessmarkable::tournament::{Crosstable, Engine, Participant, Tournament, TournamentConfig};
+use libremarkable::input::{
+    gpio::{GPIOEvent, PhysicalButton},
+    multitouch::MultitouchEvent,
+    InputEvent,
+};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_PARTICIPANTS: usize = 6;
+/// Deeper searches take too long on the device
+const MAX_DEPTH: u16 = 6;
+const FIRST_ROW_TOP: i32 = 280;
+const ROW_HEIGHT: i32 = 110;
+/// Pause between the moves when watching
+const WATCH_MOVE_DELAY: Duration = Duration::from_millis(1500);
+const BOARD_TOP: i32 = 290;
+const BOARD_SQUARE_SIZE: u32 = 50;
+const CROSSTABLE_TOP: i32 = 780;
+const CROSSTABLE_ROW_HEIGHT: i32 = 70;
+/// Subfolder of the PGN folder
+const PGN_FOLDER_NAME: &str = "tournaments";
+
+/// Sent from the thread playing the tournament
+enum TournamentEvent {
+    /// Position of the game being played (only when watching)
+    Position(String),
+    GameStarted {
+        number: usize,
+        white: String,
+        black: String,
+    },
+    GameFinished(Crosstable),
+    /// With where the games were saved
+    Finished(Result<PathBuf, String>),
+}
+
+/// Played games (even of a stopped tournament) go into the PGN folder
+fn save_games(tournament: &Tournament) -> Result<PathBuf> {
+    let now = crate::correspondence::now();
+    let folder = crate::config::pgn_location().join(PGN_FOLDER_NAME);
+    std::fs::create_dir_all(&folder).context("Create tournament folder")?;
+    let path = folder.join(format!("tournament_{}.pgn", now));
+    std::fs::write(&path, tournament.to_pgn(&pgn_date(now)))
+        .with_context(|| format!("Write {:?}", path))?;
+    Ok(path)
+}
+
+/// Round-robin between engines of different depths. The games are played
+/// in the background (at a watchable speed or as fast as possible) and
+/// the crosstable fills up as they finish.
+pub struct TournamentScene {
+    drawn: bool,
+    config: TournamentConfig,
+    /// Wait between the moves and show the board
+    watch: bool,
+    /// Of every participant: engine, depth and remove buttons
+    participant_hitboxes: Vec<(mxcfb_rect, mxcfb_rect, mxcfb_rect)>,
+    add_button_hitbox: Option<mxcfb_rect>,
+    double_button_hitbox: Option<mxcfb_rect>,
+    openings_button_hitbox: Option<mxcfb_rect>,
+    watch_button_hitbox: Option<mxcfb_rect>,
+    start_button_hitbox: Option<mxcfb_rect>,
+
+    /// Set while the tournament runs (and after it finished)
+    events: Option<Receiver<TournamentEvent>>,
+    stop: Arc<AtomicBool>,
+    total_games: usize,
+    current_game: Option<String>,
+    position: Option<String>,
+    redraw_position: bool,
+    board: MiniBoard,
+    crosstable: Option<Crosstable>,
+    finished: Option<Result<PathBuf, String>>,
+
+    back_button_hitbox: Option<mxcfb_rect>,
+    pub back_button_pressed: bool,
+}
+
+impl TournamentScene {
+    pub fn new() -> Self {
+        Self {
+            drawn: false,
+            config: crate::SETTINGS.lock().unwrap().tournament.clone(),
+            watch: true,
+            participant_hitboxes: vec![],
+            add_button_hitbox: None,
+            double_button_hitbox: None,
+            openings_button_hitbox: None,
+            watch_button_hitbox: None,
+            start_button_hitbox: None,
+            events: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            total_games: 0,
+            current_game: None,
+            position: None,
+            redraw_position: false,
+            board: MiniBoard::new(BOARD_SQUARE_SIZE),
+            crosstable: None,
+            finished: None,
+            back_button_hitbox: None,
+            back_button_pressed: false,
+        }
+    }
+
+    /// Keep the changed setup for the next tournament
+    fn config_changed(&mut self) {
+        let mut settings = crate::SETTINGS.lock().unwrap();
+        settings.tournament = self.config.clone();
+        if let Err(err) = crate::settings::write(&settings) {
+            error!("Failed to write settings file: {:?}", err);
+        }
+        self.drawn = false;
+    }
+
+    fn start(&mut self) {
+        let mut tournament = Tournament::new(self.config.clone());
+        self.total_games = tournament.pairings.len();
+        self.crosstable = Some(tournament.crosstable.clone());
+        let (events_tx, events_rx) = channel::<TournamentEvent>();
+        let stop = self.stop.clone();
+        let watch = self.watch;
+        std::thread::spawn(move || {
+            while let Some(pairing) = tournament.next_pairing() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                events_tx
+                    .send(TournamentEvent::GameStarted {
+                        number: tournament.games.len() + 1,
+                        white: tournament.crosstable.names[pairing.white].clone(),
+                        black: tournament.crosstable.names[pairing.black].clone(),
+                    })
+                    .ok();
+                let played = tournament.play_next(|fen| {
+                    if watch {
+                        events_tx
+                            .send(TournamentEvent::Position(fen.to_owned()))
+                            .ok();
+                        std::thread::sleep(WATCH_MOVE_DELAY);
+                    }
+                    !stop.load(Ordering::SeqCst)
+                });
+                match played {
+                    Ok(true) => {
+                        events_tx
+                            .send(TournamentEvent::GameFinished(tournament.crosstable.clone()))
+                            .ok();
+                    }
+                    Ok(false) => break,
+                    Err(err) => {
+                        error!("Tournament game failed: {:?}", err);
+                        break;
+                    }
+                }
+            }
+            let saved = if tournament.games.is_empty() {
+                Err("No game was finished".to_owned())
+            } else {
+                save_games(&tournament).map_err(|err| {
+                    error!("Failed to save tournament games: {:?}", err);
+                    err.to_string()
+                })
+            };
+            info!("Tournament ended after {} games", tournament.games.len());
+            events_tx.send(TournamentEvent::Finished(saved)).ok();
+        });
+        self.events = Some(events_rx);
+        self.drawn = false;
+    }
+
+    fn handle_events(&mut self) {
+        let events = match self.events {
+            Some(ref events) => events.try_iter().collect::<Vec<_>>(),
+            None => return,
+        };
+        for event in events {
+            match event {
+                TournamentEvent::Position(fen) => {
+                    self.position = Some(fen);
+                    self.redraw_position = true;
+                }
+                TournamentEvent::GameStarted {
+                    number,
+                    white,
+                    black,
+                } => {
+                    self.current_game = Some(format!(
+                        "Game {} of {}: {} vs {}",
+                        number, self.total_games, white, black
+                    ));
+                    self.position = None;
+                    self.drawn = false;
+                }
+                TournamentEvent::GameFinished(crosstable) => {
+                    self.crosstable = Some(crosstable);
+                    self.drawn = false;
+                }
+                TournamentEvent::Finished(saved) => {
+                    self.finished = Some(saved);
+                    self.current_game = None;
+                    self.position = None;
+                    self.drawn = false;
+                }
+            }
+        }
+    }
+
+    fn draw_config(&mut self, canvas: &mut Canvas) {
+        self.participant_hitboxes.clear();
+        for (index, participant) in self.config.participants.iter().enumerate() {
+            let top = FIRST_ROW_TOP + index as i32 * ROW_HEIGHT;
+            canvas.draw_text(
+                Point2 {
+                    x: Some(60),
+                    y: Some(top + 55),
+                },
+                &format!("{}.", index + 1),
+                50.0,
+            );
+            let engine = canvas.draw_button(
+                Point2 {
+                    x: Some(150),
+                    y: Some(top + 60),
+                },
+                participant.engine.label(),
+                45.0,
+                10,
+                20,
+            );
+            let depth = canvas.draw_button(
+                Point2 {
+                    x: Some(600),
+                    y: Some(top + 60),
+                },
+                &format!("Depth {}", participant.depth),
+                45.0,
+                10,
+                20,
+            );
+            let remove = canvas.draw_button(
+                Point2 {
+                    x: Some(DISPLAYWIDTH as i32 - 260),
+                    y: Some(top + 60),
+                },
+                "Remove",
+                45.0,
+                10,
+                20,
+            );
+            self.participant_hitboxes.push((engine, depth, remove));
+        }
+        let below_participants =
+            FIRST_ROW_TOP + self.config.participants.len() as i32 * ROW_HEIGHT + 80;
+        self.add_button_hitbox = if self.config.participants.len() < MAX_PARTICIPANTS {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: Some(150),
+                    y: Some(below_participants),
+                },
+                "Add engine",
+                45.0,
+                10,
+                20,
+            ))
+        } else {
+            None
+        };
+
+        self.double_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(40),
+                y: Some(1120),
+            },
+            if self.config.double_round_robin {
+                "Games per pair: 2"
+            } else {
+                "Games per pair: 1"
+            },
+            40.0,
+            10,
+            20,
+        ));
+        self.openings_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(520),
+                y: Some(1120),
+            },
+            if self.config.use_openings {
+                "Openings: On"
+            } else {
+                "Openings: Off"
+            },
+            40.0,
+            10,
+            20,
+        ));
+        self.watch_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: Some(950),
+                y: Some(1120),
+            },
+            if self.watch {
+                "Speed: Watch"
+            } else {
+                "Speed: Fast"
+            },
+            40.0,
+            10,
+            20,
+        ));
+        let games = chessmarkable::tournament::round_robin(
+            self.config.participants.len(),
+            self.config.double_round_robin,
+        )
+        .len();
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(1260),
+            },
+            &format!("{} games, saved to the PGN folder", games),
+            40.0,
+        );
+        self.start_button_hitbox = if games > 0 {
+            Some(canvas.draw_button(
+                Point2 {
+                    x: None,
+                    y: Some(1450),
+                },
+                "Start",
+                125.0,
+                25,
+                50,
+            ))
+        } else {
+            None
+        };
+    }
+
+    fn draw_position(&self, canvas: &mut Canvas) {
+        if let Some(ref fen) = self.position {
+            let rect = self.board.draw(
+                canvas,
+                Point2 {
+                    x: (DISPLAYWIDTH as u32 - self.board.size()) as i32 / 2,
+                    y: BOARD_TOP,
+                },
+                fen,
+            );
+            canvas.update_partial(&rect);
+        }
+    }
+
+    fn draw_crosstable(&self, canvas: &mut Canvas) {
+        let crosstable = match self.crosstable {
+            Some(ref crosstable) => crosstable,
+            None => return,
+        };
+        let column_x = |column: usize| 600 + column as i32 * 100;
+        let participants = crosstable.names.len();
+        for column in 0..participants {
+            canvas.draw_text(
+                Point2 {
+                    x: Some(column_x(column)),
+                    y: Some(CROSSTABLE_TOP),
+                },
+                &(column + 1).to_string(),
+                40.0,
+            );
+        }
+        canvas.draw_text(
+            Point2 {
+                x: Some(column_x(participants)),
+                y: Some(CROSSTABLE_TOP),
+            },
+            "Total",
+            40.0,
+        );
+        for (row, name) in crosstable.names.iter().enumerate() {
+            let y = CROSSTABLE_TOP + (row as i32 + 1) * CROSSTABLE_ROW_HEIGHT;
+            canvas.draw_text(
+                Point2 {
+                    x: Some(60),
+                    y: Some(y),
+                },
+                &format!("{}. {}", row + 1, name),
+                40.0,
+            );
+            for column in 0..participants {
+                canvas.draw_text(
+                    Point2 {
+                        x: Some(column_x(column)),
+                        y: Some(y),
+                    },
+                    &crosstable.cell(row, column),
+                    40.0,
+                );
+            }
+            canvas.draw_text(
+                Point2 {
+                    x: Some(column_x(participants)),
+                    y: Some(y),
+                },
+                &crosstable.total(row).to_string(),
+                40.0,
+            );
+        }
+        let winner = crosstable.standings().first().copied();
+        if let (Some(Ok(_)), Some(winner)) = (&self.finished, winner) {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(CROSSTABLE_TOP + (participants as i32 + 2) * CROSSTABLE_ROW_HEIGHT),
+                },
+                &format!("Winner: {}", crosstable.names[winner]),
+                50.0,
+            );
+        }
+    }
+
+    fn draw_progress(&mut self, canvas: &mut Canvas) {
+        let status = match (&self.finished, &self.current_game) {
+            (Some(Ok(path)), _) => format!("Finished. Saved to {}", path.display()),
+            (Some(Err(err)), _) => format!("Stopped: {}", err),
+            (None, Some(game)) => game.clone(),
+            (None, None) => "Starting...".to_owned(),
+        };
+        canvas.draw_text(
+            Point2 {
+                x: None,
+                y: Some(240),
+            },
+            &status,
+            40.0,
+        );
+        if !self.watch && self.finished.is_none() {
+            canvas.draw_text(
+                Point2 {
+                    x: None,
+                    y: Some(BOARD_TOP + 200),
+                },
+                "Playing as fast as possible",
+                50.0,
+            );
+        }
+        self.draw_crosstable(canvas);
+    }
+
+    fn on_config_tap(&mut self, position: Point2<u16>) {
+        if self.start_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.start_button_hitbox.unwrap())
+        {
+            self.start();
+            return;
+        } else if self.add_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.add_button_hitbox.unwrap())
+        {
+            let depth = self.config.participants.last().map_or(2, |last| last.depth);
+            self.config.participants.push(Participant {
+                engine: Engine::AlphaBeta,
+                depth,
+            });
+        } else if self.double_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.double_button_hitbox.unwrap())
+        {
+            self.config.double_round_robin = !self.config.double_round_robin;
+        } else if self.openings_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.openings_button_hitbox.unwrap())
+        {
+            self.config.use_openings = !self.config.use_openings;
+        } else if self.watch_button_hitbox.is_some()
+            && Canvas::is_hitting(position, self.watch_button_hitbox.unwrap())
+        {
+            self.watch = !self.watch;
+            self.drawn = false;
+            return;
+        } else if let Some(index) =
+            self.participant_hitboxes
+                .iter()
+                .position(|(engine, depth, remove)| {
+                    Canvas::is_hitting(position, *engine)
+                        || Canvas::is_hitting(position, *depth)
+                        || Canvas::is_hitting(position, *remove)
+                })
+        {
+            let (engine, depth, _) = self.participant_hitboxes[index];
+            let participant = &mut self.config.participants[index];
+            if Canvas::is_hitting(position, engine) {
+                participant.engine = participant.engine.next();
+            } else if Canvas::is_hitting(position, depth) {
+                participant.depth = participant.depth % MAX_DEPTH + 1;
+            } else {
+                self.config.participants.remove(index);
+            }
+        } else {
+            return;
+        }
+        self.config_changed();
+    }
+}
+
+impl Scene for TournamentScene {
+    fn draw(&mut self, canvas: &mut Canvas) {
+        self.handle_events();
+        if self.drawn {
+            if self.redraw_position {
+                self.redraw_position = false;
+                self.draw_position(canvas);
+            }
+            return;
+        }
+        self.drawn = true;
+
+        canvas.clear();
+        canvas.draw_styled_text(
+            Point2 {
+                x: None,
+                y: Some(150),
+            },
+            "Engine Tournament",
+            100.0,
+            FontStyle::Bold,
+        );
+        if self.events.is_some() {
+            self.draw_progress(canvas);
+            self.draw_position(canvas);
+        } else {
+            self.draw_config(canvas);
+        }
+        self.back_button_hitbox = Some(canvas.draw_button(
+            Point2 {
+                x: None,
+                y: Some(1830),
+            },
+            if self.events.is_some() && self.finished.is_none() {
+                "Stop"
+            } else {
+                "Back"
+            },
+            75.0,
+            15,
+            40,
+        ));
+        canvas.update_full();
+    }
+
+    fn on_input(&mut self, event: InputEvent) {
+        let back = match event {
+            InputEvent::GPIO {
+                event:
+                    GPIOEvent::Press {
+                        button: PhysicalButton::MIDDLE,
+                    },
+            } => true,
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Release { finger, .. },
+            } => {
+                let position = finger.pos;
+                if self.back_button_hitbox.is_some()
+                    && Canvas::is_hitting(position, self.back_button_hitbox.unwrap())
+                {
+                    true
+                } else {
+                    if self.events.is_none() {
+                        self.on_config_tap(position);
+                    }
+                    false
+                }
+            }
+            _ => false,
+        };
+        if !back {
+            return;
+        }
+        if self.events.is_some() && self.finished.is_none() {
+            // Ends after the current move. The finished games get saved.
+            self.stop.store(true, Ordering::SeqCst);
+        } else {
+            self.back_button_pressed = true;
+        }
+    }
+}
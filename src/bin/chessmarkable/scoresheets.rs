@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use chessmarkable::scoresheet::{self, Scoresheet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Subfolder of the pgn location transcribed scoresheets are written to
+const PGN_FOLDER_NAME: &str = "scoresheets";
+
+/// Path for a new scoresheet. The file is named by the time it was started.
+pub fn new_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    crate::config::scoresheets_location().join(format!("{}.json", timestamp))
+}
+
+/// The scoresheet started last (to continue writing on it)
+pub fn read_latest() -> Result<Option<(PathBuf, Scoresheet)>> {
+    let ref folder = crate::config::scoresheets_location();
+    if !folder.exists() {
+        return Ok(None);
+    }
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(folder)
+        .context("Read scoresheets folder")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .collect();
+    paths.sort();
+    let path = match paths.pop() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let file = File::open(&path).context("Open file")?;
+    let scoresheet = serde_json::from_reader(BufReader::new(file)).context("Deserialize file")?;
+    Ok(Some((path, scoresheet)))
+}
+
+pub fn write(path: &Path, scoresheet: &Scoresheet) -> Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).context("Create scoresheets folder")?;
+    }
+    let file = File::create(path).context("Create file")?;
+    serde_json::to_writer(BufWriter::new(file), scoresheet).context("Serialize and writing file")
+}
+
+/// Write the transcribed moves of the scoresheet at `path` as PGN into the
+/// scoresheets folder of the PGN viewer. An earlier transcription is replaced.
+pub fn write_pgn(path: &Path, moves: &[String]) -> Result<PathBuf> {
+    let name = path
+        .file_stem()
+        .ok_or(anyhow!("Scoresheet without name"))?
+        .to_string_lossy();
+    let date = name.parse().map(scoresheet::pgn_date).unwrap_or_default();
+    let pgn = scoresheet::to_pgn(moves, &[("Event", "Scoresheet"), ("Date", date.as_str())]);
+
+    let directory = crate::config::pgn_location().join(PGN_FOLDER_NAME);
+    std::fs::create_dir_all(&directory).context("Create PGN folder")?;
+    let pgn_path = directory.join(format!("{}.pgn", name));
+    std::fs::write(&pgn_path, pgn).with_context(|| format!("Write {:?}", pgn_path))?;
+    Ok(pgn_path)
+}
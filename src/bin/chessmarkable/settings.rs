@@ -0,0 +1,321 @@
+use crate::pgns::PgnSortOrder;
+use anyhow::{Context, Result};
+use chessmarkable::clock::TimeControl;
+use chessmarkable::tournament::TournamentConfig;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Preferences that are remembered between launches. Most of them can be
+/// changed in the settings scene.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub pgn_sort_order: PgnSortOrder,
+    /// Relative to the pgn location
+    pub last_pgn_folder: Option<PathBuf>,
+    /// Personal API token to download own games from lichess.org
+    pub lichess_token: Option<String>,
+    /// Own name in PGNs (e.g. the lichess username) to show the
+    /// recent games in the main menu as won or lost against someone
+    pub player_name: Option<String>,
+    /// Server to sync games and savestates with (see `sync.rs`)
+    pub webdav: Option<WebdavSettings>,
+    /// View boards from black's side (toggled in game and replay)
+    pub board_flipped: bool,
+    /// Turn the board to the player to move in local player vs player games
+    pub pvp_auto_flip: bool,
+    /// Hide the board between the moves of local player vs player games
+    /// until the next player taps
+    pub pvp_hide_board: bool,
+    /// Label files and ranks on the edge of the board
+    pub show_coordinates: bool,
+    /// Folder name of an installed piece set (built-in pieces if `None`)
+    pub piece_set: Option<String>,
+    pub board_theme: BoardTheme,
+    pub board_border: BoardBorder,
+    /// Which way the device is held in games
+    pub orientation: Orientation,
+    /// Promote pawns to queens without asking (e.g. for blitz games)
+    pub always_queen: bool,
+    /// Show moves first and only play them after tapping "Confirm"
+    pub confirm_moves: bool,
+    /// What multitouch gestures do in games and replays
+    pub gestures: GestureSettings,
+    /// Mark the squares of the last move
+    pub highlight_last_move: bool,
+    /// Mark a king in check and the pieces giving it
+    pub highlight_check: bool,
+    /// In which games moves can be taken back
+    pub undo: UndoPermission,
+    /// Minimum time in milliseconds the bots wait before moving
+    /// (`--bot-reaction-delay` takes precedence)
+    pub bot_reaction_delay: u16,
+    /// Do a full refresh against ghosting after this many partial
+    /// refreshes (0 to disable)
+    pub full_refresh_after: u16,
+    /// Coach mode: explain the mistakes in games against the bot with the
+    /// engine's refutation
+    pub coach: bool,
+    /// Training: Minimum time in seconds to think before a move is accepted,
+    /// 0 to disable (`--min-think-time` takes precedence)
+    pub min_think_time: u16,
+    /// Clock of new games (`None` to play without one). The last choice
+    /// is kept.
+    pub time_control: Option<TimeControl>,
+    /// The bots of `GameMode::BotVsBot`
+    pub bot_vs_bot: BotVsBotSettings,
+    /// Participants and rules of the last engine tournament
+    pub tournament: TournamentConfig,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            pgn_sort_order: Default::default(),
+            last_pgn_folder: None,
+            lichess_token: None,
+            player_name: None,
+            webdav: None,
+            board_flipped: false,
+            pvp_auto_flip: false,
+            pvp_hide_board: false,
+            show_coordinates: false,
+            piece_set: None,
+            board_theme: Default::default(),
+            board_border: Default::default(),
+            orientation: Default::default(),
+            always_queen: false,
+            confirm_moves: false,
+            gestures: Default::default(),
+            highlight_last_move: true,
+            highlight_check: true,
+            undo: Default::default(),
+            bot_reaction_delay: 1500,
+            full_refresh_after: 100,
+            coach: false,
+            min_think_time: 0,
+            time_control: None,
+            bot_vs_bot: Default::default(),
+            tournament: Default::default(),
+        }
+    }
+}
+
+/// How the squares of the board are drawn. Everything but `Solid` avoids
+/// gray areas which some displays show unevenly.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum BoardTheme {
+    /// Light and dark gray squares
+    #[default]
+    Solid,
+    /// Diagonal lines on the dark squares
+    Hatched,
+    /// Dots on the dark squares
+    Dotted,
+    /// White squares with a frame inside of the dark ones
+    Outlined,
+}
+
+impl BoardTheme {
+    pub fn next(self) -> Self {
+        match self {
+            BoardTheme::Solid => BoardTheme::Hatched,
+            BoardTheme::Hatched => BoardTheme::Dotted,
+            BoardTheme::Dotted => BoardTheme::Outlined,
+            BoardTheme::Outlined => BoardTheme::Solid,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BoardTheme::Solid => "Solid",
+            BoardTheme::Hatched => "Hatched",
+            BoardTheme::Dotted => "Dotted",
+            BoardTheme::Outlined => "Outlined",
+        }
+    }
+}
+
+/// Frame drawn on the edge of the board
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum BoardBorder {
+    #[default]
+    None,
+    Thin,
+    Thick,
+}
+
+impl BoardBorder {
+    pub fn next(self) -> Self {
+        match self {
+            BoardBorder::None => BoardBorder::Thin,
+            BoardBorder::Thin => BoardBorder::Thick,
+            BoardBorder::Thick => BoardBorder::None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BoardBorder::None => "None",
+            BoardBorder::Thin => "Thin",
+            BoardBorder::Thick => "Thick",
+        }
+    }
+
+    /// Width of the frame in pixels
+    pub fn width(self) -> u32 {
+        match self {
+            BoardBorder::None => 0,
+            BoardBorder::Thin => 3,
+            BoardBorder::Thick => 8,
+        }
+    }
+}
+
+/// Which way the device is held in games. The other scenes are always
+/// upright.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    /// Turned to the left (the top edge is on the left) with the board on
+    /// the left and the clocks, the move list etc. right of it
+    Landscape,
+}
+
+impl Orientation {
+    pub fn next(self) -> Self {
+        match self {
+            Orientation::Portrait => Orientation::Landscape,
+            Orientation::Landscape => Orientation::Portrait,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Orientation::Portrait => "Portrait",
+            Orientation::Landscape => "Landscape",
+        }
+    }
+}
+
+/// Analysis games can always take back moves and bots never do
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum UndoPermission {
+    #[default]
+    Always,
+    /// Only in games without a bot
+    NotAgainstBots,
+    Never,
+}
+
+impl UndoPermission {
+    pub fn label(self) -> &'static str {
+        match self {
+            UndoPermission::Always => "Always",
+            UndoPermission::NotAgainstBots => "Not vs bots",
+            UndoPermission::Never => "Never",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            UndoPermission::Always => UndoPermission::NotAgainstBots,
+            UndoPermission::NotAgainstBots => UndoPermission::Never,
+            UndoPermission::Never => UndoPermission::Always,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum GestureAction {
+    Nothing,
+    /// Take back a move (the previous move in replays)
+    Undo,
+    /// Next move in replays
+    Redo,
+    Flip,
+    /// Menu of the game
+    Menu,
+    Refresh,
+    /// Save the game (with the clocks) and go to the main menu at once
+    Adjourn,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct GestureSettings {
+    pub two_finger_swipe_left: GestureAction,
+    pub two_finger_swipe_right: GestureAction,
+    pub two_finger_tap: GestureAction,
+    pub long_press: GestureAction,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        Self {
+            two_finger_swipe_left: GestureAction::Undo,
+            two_finger_swipe_right: GestureAction::Redo,
+            two_finger_tap: GestureAction::Flip,
+            long_press: GestureAction::Menu,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct BotVsBotSettings {
+    /// Search depths (see `GameMode::bot_depth()` for the usual ones)
+    pub white_depth: u16,
+    pub black_depth: u16,
+    /// Show a rough evaluation after every move
+    pub show_eval: bool,
+}
+
+impl Default for BotVsBotSettings {
+    fn default() -> Self {
+        Self {
+            white_depth: 4,
+            black_depth: 4,
+            show_eval: true,
+        }
+    }
+}
+
+/// Only set up by editing the settings file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebdavSettings {
+    /// Folder on the server, e.g. "https://cloud.example.com/remote.php/dav/files/me/chess"
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Unix time of the last successful sync. Only files changed since
+    /// then are pushed again.
+    #[serde(default)]
+    pub last_sync: u64,
+}
+
+pub fn read() -> Result<Settings> {
+    let ref file_path = crate::config::settings_file();
+
+    if !file_path.exists() {
+        info!("Settings file doesn't exist (yet).");
+        Ok(Settings::default())
+    } else {
+        let file = std::fs::File::open(file_path).context("Open file")?;
+        let settings: Settings = serde_yaml::from_reader(file).context("Deserialize file")?;
+        Ok(settings)
+    }
+}
+
+pub fn write(settings: &Settings) -> Result<()> {
+    let ref file_path = crate::config::settings_file();
+    let directory = file_path.parent().ok_or(anyhow!("No parent directory"))?;
+    if !directory.exists() {
+        std::fs::create_dir_all(directory).context("Create directory for file")?;
+        info!("Created directory for settings file.");
+    }
+
+    let file = std::fs::File::create(file_path).context("Create file")?;
+    serde_yaml::to_writer(file, settings).context("Serialize and writing file")
+}
@@ -0,0 +1,306 @@
+use crate::settings::WebdavSettings;
+use crate::wget;
+use anyhow::{Context, Result};
+use glob::glob;
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Remote folders. The layout is the same as locally, so the folder can
+/// also be mirrored with rsync instead.
+const PGN_FOLDER_NAME: &str = "pgn";
+const CORRESPONDENCE_FOLDER_NAME: &str = "correspondence";
+/// Bytes of paths that are sent as they are
+const UNRESERVED: &[u8] = b"-_.~/";
+/// The first with `--method` and `--body-file`
+const MIN_WGET_VERSION: (u32, u32) = (1, 15);
+
+lazy_static! {
+    // With or without a namespace prefix (e.g. "<d:href>")
+    static ref HREF_REGEX: Regex = Regex::new(r"<(?:\w+:)?href>([^<]*)</(?:\w+:)?href>").unwrap();
+}
+
+#[derive(Default)]
+pub struct SyncSummary {
+    pub pushed: usize,
+    pub pulled: usize,
+}
+
+/// Pushes the savestates, correspondence games and PGNs (including the
+/// exported ones) that changed since the last sync to the WebDAV folder.
+/// Then downloads PGNs of the remote PGN folder that don't exist here.
+pub fn sync(settings: &WebdavSettings) -> Result<SyncSummary> {
+    let mut webdav = Webdav::new(settings);
+    let mut summary = SyncSummary::default();
+
+    let ref savestates_file = crate::config::savestates_file();
+    if changed_since(savestates_file, settings.last_sync) {
+        let name = savestates_file.file_name().unwrap_or_default();
+        webdav
+            .put(&name.to_string_lossy(), savestates_file)
+            .context("Push savestates")?;
+        summary.pushed += 1;
+    }
+
+    let ref correspondence_folder = crate::config::correspondence_location();
+    summary.pushed += webdav
+        .push_folder(
+            correspondence_folder,
+            CORRESPONDENCE_FOLDER_NAME,
+            "yml",
+            settings.last_sync,
+        )
+        .context("Push correspondence games")?;
+
+    let ref pgn_folder = crate::config::pgn_location();
+    summary.pushed += webdav
+        .push_folder(pgn_folder, PGN_FOLDER_NAME, "pgn", settings.last_sync)
+        .context("Push PGNs")?;
+    summary.pulled += webdav
+        .pull_new_pgns(pgn_folder, PGN_FOLDER_NAME)
+        .context("Pull PGNs")?;
+
+    info!(
+        "Synced with {}: pushed {} and pulled {} files.",
+        settings.url, summary.pushed, summary.pulled
+    );
+    Ok(summary)
+}
+
+fn changed_since(path: &Path, since: u64) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| {
+            modified
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                >= since
+        })
+        .unwrap_or(false)
+}
+
+/// Files with the extension in `folder` and its subfolders. Hidden files
+/// (e.g. indexes of PGNs) are left out.
+fn files_in(folder: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let pattern = format!("{}/**/*.{}", folder.display(), extension);
+    Ok(glob(&pattern)
+        .context("Invalid pattern")?
+        .filter_map(|path| path.ok())
+        .filter(|path| {
+            path.strip_prefix(folder).map_or(false, |relative| {
+                !relative
+                    .components()
+                    .any(|part| part.as_os_str().to_string_lossy().starts_with('.'))
+            })
+        })
+        .collect())
+}
+
+/// "a b/c.pgn" becomes "a%20b/c.pgn"
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || UNRESERVED.contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Path part of an url, e.g. "/dav/chess" of "https://example.com/dav/chess"
+fn url_path(url: &str) -> &str {
+    let without_scheme = url.splitn(2, "://").last().unwrap_or(url);
+    without_scheme
+        .find('/')
+        .map_or("", |start| &without_scheme[start..])
+}
+
+struct Webdav<'a> {
+    settings: &'a WebdavSettings,
+    /// Remote folders that are known to exist
+    folders: HashSet<String>,
+}
+
+impl<'a> Webdav<'a> {
+    fn new(settings: &'a WebdavSettings) -> Self {
+        Self {
+            settings,
+            folders: Default::default(),
+        }
+    }
+
+    fn url(&self, remote_path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.settings.url.trim_end_matches('/'),
+            encode_path(remote_path)
+        )
+    }
+
+    /// Uses wget like the lichess download. The credentials go into the
+    /// wgetrc.
+    fn request(&self, method: &str, remote_path: &str, args: &[String]) -> Result<Vec<u8>> {
+        let mut wget_args = vec![
+            "-q".to_owned(),
+            "-O".to_owned(),
+            "-".to_owned(),
+            format!("--method={}", method),
+        ];
+        wget_args.extend_from_slice(args);
+        wget_args.push(self.url(remote_path));
+        let mut secrets = vec![];
+        if let Some(ref username) = self.settings.username {
+            secrets.push(("user", username.as_str()));
+        }
+        if let Some(ref password) = self.settings.password {
+            secrets.push(("password", password.as_str()));
+        }
+        let output = wget::run(&wget_args, &secrets, MIN_WGET_VERSION)?;
+        if !output.status.success() {
+            bail!(
+                "{} {} failed ({}): {}",
+                method,
+                remote_path,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+
+    /// Creates the folder and its parents unless they are known to exist
+    fn create_folder(&mut self, remote_path: &str) {
+        let mut path = String::new();
+        for part in remote_path.split('/').filter(|part| !part.is_empty()) {
+            path = if path.is_empty() {
+                part.to_owned()
+            } else {
+                format!("{}/{}", path, part)
+            };
+            if self.folders.contains(&path) {
+                continue;
+            }
+            // Fails if the folder already exists
+            if let Err(err) = self.request("MKCOL", &format!("{}/", path), &[]) {
+                debug!("Didn't create {}: {:?}", path, err);
+            }
+            self.folders.insert(path.clone());
+        }
+    }
+
+    fn put(&mut self, remote_path: &str, local_path: &Path) -> Result<()> {
+        if let Some(end) = remote_path.rfind('/') {
+            self.create_folder(&remote_path[..end]);
+        }
+        self.request(
+            "PUT",
+            remote_path,
+            &[format!("--body-file={}", local_path.display())],
+        )?;
+        Ok(())
+    }
+
+    /// Pushes the files with the extension that changed since `since`.
+    /// Returns the number of pushed files.
+    fn push_folder(
+        &mut self,
+        local_folder: &Path,
+        remote_folder: &str,
+        extension: &str,
+        since: u64,
+    ) -> Result<usize> {
+        if !local_folder.exists() {
+            return Ok(0);
+        }
+        let mut pushed = 0;
+        for path in files_in(local_folder, extension)? {
+            if !changed_since(&path, since) {
+                continue;
+            }
+            let relative = path.strip_prefix(local_folder)?.to_string_lossy();
+            self.put(&format!("{}/{}", remote_folder, relative), &path)
+                .with_context(|| format!("Push {:?}", path))?;
+            pushed += 1;
+        }
+        Ok(pushed)
+    }
+
+    /// Paths (relative to the synced folder) of the files and folders
+    /// (ending with "/") directly inside the remote folder
+    fn list(&self, remote_folder: &str) -> Result<Vec<String>> {
+        let response = self.request(
+            "PROPFIND",
+            &format!("{}/", remote_folder),
+            &["--header=Depth: 1".to_owned()],
+        )?;
+        let response = String::from_utf8_lossy(&response);
+        let base = format!(
+            "{}/",
+            decode_path(url_path(&self.settings.url)).trim_end_matches('/')
+        );
+        Ok(HREF_REGEX
+            .captures_iter(&response)
+            .filter_map(|captures| {
+                let path = decode_path(url_path(&captures[1]));
+                path.strip_prefix(&base).map(str::to_owned)
+            })
+            // The folder itself is listed too
+            .filter(|path| path.trim_end_matches('/') != remote_folder)
+            .collect())
+    }
+
+    /// Downloads PGNs that don't exist locally (in all subfolders).
+    /// Returns the number of downloaded files.
+    fn pull_new_pgns(&self, local_folder: &Path, remote_folder: &str) -> Result<usize> {
+        let mut pulled = 0;
+        for path in self.list(remote_folder)? {
+            if path.ends_with('/') {
+                pulled += self.pull_new_pgns(local_folder, path.trim_end_matches('/'))?;
+                continue;
+            }
+            let relative = match path.strip_prefix(&format!("{}/", PGN_FOLDER_NAME)) {
+                Some(relative) if relative.ends_with(".pgn") => relative,
+                _ => continue,
+            };
+            let local_path = local_folder.join(relative);
+            if local_path.exists() {
+                continue;
+            }
+            let contents = self.request("GET", &path, &[])?;
+            if let Some(directory) = local_path.parent() {
+                std::fs::create_dir_all(directory).context("Create PGN folder")?;
+            }
+            std::fs::write(&local_path, contents)
+                .with_context(|| format!("Write {:?}", local_path))?;
+            pulled += 1;
+        }
+        Ok(pulled)
+    }
+}
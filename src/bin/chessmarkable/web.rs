@@ -0,0 +1,448 @@
+//! Minimal web page (e.g. over the USB network) to upload PGNs, download
+//! exported games and images and watch the game in progress. Every
+//! connection gets one response and is closed.
+//!
+//! Paths:
+//! - `/` the page
+//! - `/board` the current board, reloading itself
+//! - `/files/<folder>/<name>` download (`pgn`, `images` or `documents`)
+//! - `PUT /upload/<name>.pgn` store the body in the PGN folder (as
+//!   "<name> (2).pgn" and so on if the name is taken)
+//!
+//! There is no password. Everyone who can reach the address can download
+//! the files and upload PGNs, so on any address but the USB network or
+//! 127.0.0.1 the whole Wi-Fi can.
+
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Larger uploads are refused
+const MAX_UPLOAD_SIZE: usize = 10 * 1024 * 1024;
+/// Longer request and header lines are refused
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+/// Requests with more headers are refused
+const MAX_HEADERS: usize = 100;
+/// Clients that stop sending (or reading) for this long are dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Seconds until `/board` is reloaded
+const BOARD_RELOAD: u32 = 3;
+
+lazy_static! {
+    /// FEN of the game in progress (`None` outside of games)
+    static ref LIVE_POSITION: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Shown on `/board` until the next one (or `clear_position()`)
+pub fn show_position(fen: &str) {
+    *LIVE_POSITION.lock().unwrap() = Some(fen.to_owned());
+}
+
+pub fn clear_position() {
+    *LIVE_POSITION.lock().unwrap() = None;
+}
+
+/// Folders whose files can be downloaded, by their name in the path
+fn folder(name: &str) -> Option<PathBuf> {
+    match name {
+        "pgn" => Some(crate::config::pgn_location()),
+        "images" => Some(crate::config::images_location()),
+        "documents" => Some(crate::config::documents_location()),
+        _ => None,
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn html(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn text(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: message.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Decode %XX escapes of a path segment
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A file name without any way to leave its folder
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains('/') && !name.contains('\\')
+}
+
+/// Names of the files directly inside `directory`, sorted
+fn file_names(directory: &Path) -> Vec<String> {
+    let mut names: Vec<String> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| is_safe_name(name))
+            .collect(),
+        Err(_) => vec![],
+    };
+    names.sort();
+    names
+}
+
+fn index_page() -> String {
+    let mut page = String::from(concat!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">",
+        "<title>chessMarkable</title></head><body>",
+        "<h1>chessMarkable</h1>",
+        "<p><a href=\"/board\">Current board</a></p>",
+        "<h2>Upload PGN</h2>",
+        "<input type=\"file\" id=\"pgn\" accept=\".pgn\" multiple>",
+        "<button onclick=\"upload()\">Upload</button> <span id=\"status\"></span>",
+        "<script>async function upload() {",
+        "for (const file of document.getElementById('pgn').files) {",
+        "const response = await fetch('/upload/' + encodeURIComponent(file.name), ",
+        "{method: 'PUT', body: file});",
+        "document.getElementById('status').textContent = await response.text(); }",
+        "location.reload(); }</script>",
+    ));
+    for (name, title) in &[
+        ("pgn", "PGNs"),
+        ("images", "Images"),
+        ("documents", "Documents"),
+    ] {
+        page.push_str(&format!("<h2>{}</h2><ul>", title));
+        if let Some(directory) = folder(name) {
+            for file_name in file_names(&directory) {
+                page.push_str(&format!(
+                    "<li><a href=\"/files/{}/{}\">{}</a></li>",
+                    name,
+                    percent_encode(&file_name),
+                    escape_html(&file_name)
+                ));
+            }
+        }
+        page.push_str("</ul>");
+    }
+    page.push_str("</body></html>");
+    page
+}
+
+fn piece_symbol(piece: char) -> char {
+    match piece {
+        'K' => '\u{2654}',
+        'Q' => '\u{2655}',
+        'R' => '\u{2656}',
+        'B' => '\u{2657}',
+        'N' => '\u{2658}',
+        'P' => '\u{2659}',
+        'k' => '\u{265A}',
+        'q' => '\u{265B}',
+        'r' => '\u{265C}',
+        'b' => '\u{265D}',
+        'n' => '\u{265E}',
+        'p' => '\u{265F}',
+        _ => ' ',
+    }
+}
+
+fn board_page() -> String {
+    let mut page = format!(
+        concat!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\">",
+            "<meta http-equiv=\"refresh\" content=\"{}\">",
+            "<title>chessMarkable board</title><style>",
+            "td {{ width: 48px; height: 48px; font-size: 40px; text-align: center; }}",
+            ".dark {{ background: #aaa; }}</style></head><body>",
+        ),
+        BOARD_RELOAD
+    );
+    let fen = LIVE_POSITION.lock().unwrap().clone();
+    match fen {
+        Some(fen) => {
+            page.push_str("<table cellspacing=\"0\">");
+            // Without the pockets of crazyhouse
+            let placement = fen
+                .split(|c: char| c.is_whitespace() || c == '[')
+                .next()
+                .unwrap_or("");
+            for (y, rank) in placement.split('/').take(8).enumerate() {
+                page.push_str("<tr>");
+                let mut x = 0;
+                for c in rank.chars() {
+                    let squares = c.to_digit(10).unwrap_or(1);
+                    for _ in 0..squares {
+                        let class = if (x + y) % 2 == 1 { "dark" } else { "light" };
+                        let symbol = if c.is_ascii_digit() {
+                            ' '
+                        } else {
+                            piece_symbol(c)
+                        };
+                        page.push_str(&format!("<td class=\"{}\">{}</td>", class, symbol));
+                        x += 1;
+                    }
+                }
+                page.push_str("</tr>");
+            }
+            page.push_str(&format!(
+                "</table><p><code>{}</code></p>",
+                escape_html(&fen)
+            ));
+        }
+        None => page.push_str("<p>No game in progress</p>"),
+    }
+    page.push_str("<p><a href=\"/\">Back</a></p></body></html>");
+    page
+}
+
+fn download(folder_name: &str, name: &str) -> Response {
+    let name = percent_decode(name);
+    let directory = match folder(folder_name) {
+        Some(directory) if is_safe_name(&name) => directory,
+        _ => return Response::text("404 Not Found", "Not found"),
+    };
+    let content_type = match name.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("pdf") => "application/pdf",
+        _ => "text/plain; charset=utf-8",
+    };
+    match std::fs::read(directory.join(&name)) {
+        Ok(body) => Response {
+            status: "200 OK",
+            content_type,
+            body,
+        },
+        Err(_) => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+/// Write `body` to a new file in `directory` named `name` or, if it's
+/// taken, "<stem> (2).pgn" and so on. Returns the name used.
+fn write_new_file(directory: &Path, name: &str, body: &[u8]) -> io::Result<String> {
+    let stem = name.trim_end_matches(".pgn");
+    let mut number = 1;
+    loop {
+        let candidate = match number {
+            1 => name.to_owned(),
+            _ => format!("{} ({}).pgn", stem, number),
+        };
+        let created = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(directory.join(&candidate));
+        match created {
+            Ok(mut file) => return file.write_all(body).map(|_| candidate),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => number += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn upload(name: &str, body: &[u8]) -> Response {
+    let name = percent_decode(name);
+    if !is_safe_name(&name) || !name.ends_with(".pgn") {
+        return Response::text("400 Bad Request", "Only .pgn files can be uploaded");
+    }
+    let directory = &crate::config::pgn_location();
+    let written =
+        std::fs::create_dir_all(directory).and_then(|_| write_new_file(directory, &name, body));
+    match written {
+        Ok(stored_name) if stored_name == name => {
+            info!("Received {} over the web page", name);
+            Response::text("200 OK", &format!("Uploaded {}", name))
+        }
+        Ok(stored_name) => {
+            info!("Received {} over the web page as {}", name, stored_name);
+            Response::text(
+                "200 OK",
+                &format!("Uploaded {} as {} (the name was taken)", name, stored_name),
+            )
+        }
+        Err(err) => {
+            error!("Failed to store uploaded {}: {:?}", name, err);
+            Response::text("500 Internal Server Error", "Failed to store the file")
+        }
+    }
+}
+
+fn respond(method: &str, path: &str, body: &[u8]) -> Response {
+    let segments: Vec<&str> = path
+        .split('?')
+        .next()
+        .unwrap_or("")
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match (method, segments.as_slice()) {
+        ("GET", []) => Response::html(index_page()),
+        ("GET", ["board"]) => Response::html(board_page()),
+        ("GET", ["files", folder_name, name]) => download(folder_name, name),
+        ("PUT", ["upload", name]) | ("POST", ["upload", name]) => upload(name, body),
+        _ => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+/// A line of the request head, `None` if it's longer than `MAX_LINE_LENGTH`
+fn read_head_line(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = String::new();
+    reader
+        .take(MAX_LINE_LENGTH as u64)
+        .read_line(&mut line)
+        .context("Read request head")?;
+    if line.len() == MAX_LINE_LENGTH && !line.ends_with('\n') {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+fn serve_client(stream: TcpStream) -> Result<()> {
+    stream
+        .set_read_timeout(Some(CLIENT_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(CLIENT_TIMEOUT)))
+        .context("Set timeouts")?;
+    let mut writer = stream.try_clone().context("Clone stream")?;
+    let mut reader = BufReader::new(stream);
+    let request_line = match read_head_line(&mut reader)? {
+        Some(request_line) => request_line,
+        None => return send(&mut writer, Response::text("414 URI Too Long", "Too long")),
+    };
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let path = parts.next().unwrap_or("/").to_owned();
+
+    let mut content_length = 0;
+    let mut headers = 0;
+    loop {
+        let header = match read_head_line(&mut reader)? {
+            Some(header) if headers < MAX_HEADERS => header,
+            _ => {
+                let response = Response::text(
+                    "431 Request Header Fields Too Large",
+                    "Too many or too long headers",
+                );
+                return send(&mut writer, response);
+            }
+        };
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        headers += 1;
+        let mut fields = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("Parse content length")?;
+            }
+        }
+    }
+
+    let response = if content_length > MAX_UPLOAD_SIZE {
+        Response::text("413 Payload Too Large", "The file is too large")
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).context("Read body")?;
+        respond(&method, &path, &body)
+    };
+    send(&mut writer, response)
+}
+
+fn send(writer: &mut TcpStream, response: Response) -> Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )
+    .context("Write response head")?;
+    writer
+        .write_all(&response.body)
+        .context("Write response body")
+}
+
+/// Serve the page on `address` (e.g. "10.11.99.1:8080") in the background
+pub fn start(address: &str) -> Result<()> {
+    let listener = TcpListener::bind(address).with_context(|| format!("Listen on {}", address))?;
+    info!("Web page served on http://{}", address);
+    let loopback = listener
+        .local_addr()
+        .map_or(false, |local| local.ip().is_loopback());
+    if !loopback {
+        warn!(
+            "The web page on {} has no password, everyone on that network can use it",
+            address
+        );
+    }
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept web client: {:?}", err);
+                    continue;
+                }
+            };
+            thread::spawn(move || {
+                if let Err(err) = serve_client(stream) {
+                    warn!("Failed to answer web client: {:?}", err);
+                }
+            });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Requests with wget, which is available on the device and spares pulling
+//! in a http client with tls support. It has to be GNU wget, busybox wget
+//! lacks most of the options used.
+//!
+//! Secrets (tokens and passwords) are written to a wgetrc only the user can
+//! read instead of the command line, which every process can see (e.g. with
+//! `ps`).
+
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_CONFIG_ID: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// `(major, minor)` of GNU wget. Asked once since it doesn't change.
+    static ref GNU_VERSION: Result<(u32, u32), String> = gnu_version();
+}
+
+/// A wgetrc that is removed when dropped
+struct Config {
+    path: PathBuf,
+}
+
+impl Config {
+    /// `commands` are name and value, e.g. `("password", "...")`
+    fn write(commands: &[(&str, &str)]) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "chessmarkable-wgetrc-{}-{}",
+            std::process::id(),
+            NEXT_CONFIG_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Create {:?}", path))?;
+        // Removed from here on, also on errors
+        let config = Self { path };
+        for (name, value) in commands {
+            // Would start another command
+            ensure!(
+                !value.contains(|c| c == '\n' || c == '\r'),
+                "The {} contains a line break",
+                name
+            );
+            writeln!(file, "{} = {}", name, value).context("Write wgetrc")?;
+        }
+        Ok(config)
+    }
+}
+
+impl Drop for Config {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("Failed to remove {:?}: {:?}", self.path, err);
+        }
+    }
+}
+
+fn gnu_version() -> Result<(u32, u32), String> {
+    let output = Command::new("wget")
+        .arg("--version")
+        .output()
+        .map_err(|err| format!("Failed to run wget: {}", err))?;
+    // "GNU Wget 1.21.1 built on linux-gnu."
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout
+        .strip_prefix("GNU Wget ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .ok_or("wget isn't GNU wget (busybox wget doesn't work)")?;
+    let mut numbers = version.split('.').map(|number| number.parse::<u32>());
+    match (numbers.next(), numbers.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Ok((major, minor)),
+        _ => Err(format!("Unknown GNU wget version \"{}\"", version)),
+    }
+}
+
+/// Runs GNU wget `min_version` or newer with `args` and the wgetrc
+/// commands in `secrets` (e.g. `("header", "Authorization: ...")`)
+pub fn run(args: &[String], secrets: &[(&str, &str)], min_version: (u32, u32)) -> Result<Output> {
+    let version = GNU_VERSION.clone().map_err(|err| anyhow!(err))?;
+    ensure!(
+        version >= min_version,
+        "GNU wget {}.{} or newer is needed, found {}.{}",
+        min_version.0,
+        min_version.1,
+        version.0,
+        version.1
+    );
+    let config = Config::write(secrets)?;
+    let output = Command::new("wget")
+        .env("WGETRC", &config.path)
+        .args(args)
+        .output()
+        .context("Run wget")?;
+    Ok(output)
+}
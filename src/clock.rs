@@ -0,0 +1,506 @@
+/// Chess clock for two players with the usual ways of giving extra time
+/// per move. Times are passed in so the clock can be driven by any loop.
+use crate::Player;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockType {
+    /// The bonus is added after every move
+    Fischer,
+    /// The time only starts to run after the bonus passed (US delay)
+    SimpleDelay,
+    /// The time used for a move is given back after it, but at most
+    /// the bonus (so the time never grows unlike with Fischer)
+    Bronstein,
+}
+
+/// How fast a game is played (with the same limits as lichess.org)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+}
+
+impl Speed {
+    pub fn label(self) -> &'static str {
+        match self {
+            Speed::Bullet => "Bullet",
+            Speed::Blitz => "Blitz",
+            Speed::Rapid => "Rapid",
+            Speed::Classical => "Classical",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub bonus: Duration,
+    pub clock_type: ClockType,
+}
+
+/// Time controls offered for games and in the clock mode
+pub const PRESETS: &[TimeControl] = &[
+    TimeControl::fischer(1, 0),
+    TimeControl::fischer(3, 0),
+    TimeControl::fischer(3, 2),
+    TimeControl::fischer(5, 0),
+    TimeControl::fischer(5, 3),
+    TimeControl::fischer(10, 0),
+    TimeControl::fischer(10, 5),
+    TimeControl::fischer(15, 10),
+    TimeControl::fischer(30, 0),
+    TimeControl::fischer(90, 30),
+    TimeControl::simple_delay(5, 5),
+    TimeControl::simple_delay(25, 5),
+    TimeControl::bronstein(5, 3),
+    TimeControl::bronstein(15, 10),
+    TimeControl::bronstein(90, 30),
+];
+
+impl TimeControl {
+    pub const fn fischer(minutes: u64, increment_secs: u64) -> Self {
+        Self {
+            base: Duration::from_secs(minutes * 60),
+            bonus: Duration::from_secs(increment_secs),
+            clock_type: ClockType::Fischer,
+        }
+    }
+
+    pub const fn simple_delay(minutes: u64, delay_secs: u64) -> Self {
+        Self {
+            base: Duration::from_secs(minutes * 60),
+            bonus: Duration::from_secs(delay_secs),
+            clock_type: ClockType::SimpleDelay,
+        }
+    }
+
+    pub const fn bronstein(minutes: u64, delay_secs: u64) -> Self {
+        Self {
+            base: Duration::from_secs(minutes * 60),
+            bonus: Duration::from_secs(delay_secs),
+            clock_type: ClockType::Bronstein,
+        }
+    }
+
+    /// E.g. "3+2" (minutes and increment), "5 d5" (minutes and delay)
+    /// or "5 b3" (minutes and Bronstein delay)
+    pub fn label(&self) -> String {
+        let secs = self.base.as_secs();
+        let base = if secs % 60 == 0 {
+            (secs / 60).to_string()
+        } else {
+            format!("{}s", secs)
+        };
+        match self.clock_type {
+            ClockType::Fischer => format!("{}+{}", base, self.bonus.as_secs()),
+            ClockType::SimpleDelay => format!("{} d{}", base, self.bonus.as_secs()),
+            ClockType::Bronstein => format!("{} b{}", base, self.bonus.as_secs()),
+        }
+    }
+
+    /// Judged by the estimated duration of a game with 40 moves
+    pub fn speed(&self) -> Speed {
+        match self.base.as_secs() + 40 * self.bonus.as_secs() {
+            0..=179 => Speed::Bullet,
+            180..=479 => Speed::Blitz,
+            480..=1499 => Speed::Rapid,
+            _ => Speed::Classical,
+        }
+    }
+}
+
+/// State of a clock at one moment. Sent to the players of a game
+/// so they can show the clock running without asking all the time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockSnapshot {
+    pub white: Duration,
+    pub black: Duration,
+    /// Whose time runs (`None` while paused or before the clock started)
+    pub running: Option<Player>,
+    /// Delay that is left before the time of the running player runs
+    pub delay: Duration,
+}
+
+impl ClockSnapshot {
+    /// Time of `player` when `elapsed` passed since the snapshot was taken
+    pub fn remaining(&self, player: Player, elapsed: Duration) -> Duration {
+        let remaining = match player {
+            Player::White => self.white,
+            Player::Black => self.black,
+        };
+        if self.running != Some(player) {
+            return remaining;
+        }
+        remaining.saturating_sub(elapsed.saturating_sub(self.delay))
+    }
+
+    /// Delay left of the running player when `elapsed` passed since the
+    /// snapshot was taken
+    pub fn delay_left(&self, elapsed: Duration) -> Duration {
+        self.delay.saturating_sub(elapsed)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChessClock {
+    time_control: TimeControl,
+    /// Time of each player at the start of the current turn (by `Player as usize`)
+    remaining: [Duration; 2],
+    /// Whose time runs. `None` before the clock was started.
+    turn: Option<Player>,
+    /// `None` while paused
+    running_since: Option<Instant>,
+    /// Time of the current turn before it was paused
+    turn_elapsed: Duration,
+}
+
+impl ChessClock {
+    pub fn new(time_control: TimeControl) -> Self {
+        Self {
+            time_control,
+            remaining: [time_control.base; 2],
+            turn: None,
+            running_since: None,
+            turn_elapsed: Duration::default(),
+        }
+    }
+
+    /// Continue with the times of an adjourned game. Nobody's time runs
+    /// until the clock is switched or pressed.
+    pub fn resumed(time_control: TimeControl, times: ClockSnapshot) -> Self {
+        Self {
+            remaining: [times.white, times.black],
+            ..Self::new(time_control)
+        }
+    }
+
+    pub fn time_control(&self) -> TimeControl {
+        self.time_control
+    }
+
+    pub fn turn(&self) -> Option<Player> {
+        self.turn
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.turn.is_some() && self.running_since.is_none()
+    }
+
+    fn elapsed_in_turn(&self, now: Instant) -> Duration {
+        self.turn_elapsed
+            + self
+                .running_since
+                .map(|since| now.saturating_duration_since(since))
+                .unwrap_or_default()
+    }
+
+    pub fn remaining(&self, player: Player, now: Instant) -> Duration {
+        let remaining = self.remaining[player as usize];
+        if self.turn != Some(player) {
+            return remaining;
+        }
+        let elapsed = self.elapsed_in_turn(now);
+        let used = match self.time_control.clock_type {
+            ClockType::Fischer | ClockType::Bronstein => elapsed,
+            ClockType::SimpleDelay => elapsed.saturating_sub(self.time_control.bonus),
+        };
+        remaining.saturating_sub(used)
+    }
+
+    /// The player who ran out of time
+    pub fn flagged(&self, now: Instant) -> Option<Player> {
+        self.turn
+            .filter(|player| self.remaining(*player, now) == Duration::default())
+    }
+
+    /// `player` finished their move (pressed their side of the clock). The
+    /// first press starts the clock of the opponent. Ignored while paused,
+    /// after a flag fell or if it's not the turn of `player`.
+    pub fn press(&mut self, player: Player, now: Instant) {
+        match self.turn {
+            None => {}
+            Some(turn) if turn == player && !self.is_paused() && self.flagged(now).is_none() => {
+                let remaining = self.remaining(player, now);
+                self.remaining[player as usize] = match self.time_control.clock_type {
+                    ClockType::Fischer => remaining + self.time_control.bonus,
+                    ClockType::SimpleDelay => remaining,
+                    ClockType::Bronstein => {
+                        remaining + self.elapsed_in_turn(now).min(self.time_control.bonus)
+                    }
+                };
+            }
+            _ => return,
+        }
+        self.turn = Some(player.other_player());
+        self.running_since = Some(now);
+        self.turn_elapsed = Duration::default();
+    }
+
+    /// Let the time of `player` run without giving a bonus for the turn
+    /// that ends (e.g. after moves were taken back)
+    pub fn switch_to(&mut self, player: Player, now: Instant) {
+        if self.turn == Some(player) {
+            return;
+        }
+        let paused = self.is_paused();
+        if let Some(turn) = self.turn {
+            self.remaining[turn as usize] = self.remaining(turn, now);
+        }
+        self.turn = Some(player);
+        self.turn_elapsed = Duration::default();
+        if !paused {
+            self.running_since = Some(now);
+        }
+    }
+
+    pub fn snapshot(&self, now: Instant) -> ClockSnapshot {
+        let running = self.turn.filter(|_| !self.is_paused());
+        let delay = match (running, self.time_control.clock_type) {
+            (Some(_), ClockType::SimpleDelay) => self
+                .time_control
+                .bonus
+                .saturating_sub(self.elapsed_in_turn(now)),
+            _ => Duration::default(),
+        };
+        ClockSnapshot {
+            white: self.remaining(Player::White, now),
+            black: self.remaining(Player::Black, now),
+            running,
+            delay,
+        }
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        if let Some(since) = self.running_since.take() {
+            self.turn_elapsed += now.saturating_duration_since(since);
+        }
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        if self.is_paused() && self.flagged(now).is_none() {
+            self.running_since = Some(now);
+        }
+    }
+}
+
+/// Remaining time as shown on a clock ("1:05:00", "4:59" and with
+/// tenths of a second below ten seconds like "9.4"). Times are rounded
+/// up so the clock only shows zero once the time is over.
+pub fn format_time(remaining: Duration) -> String {
+    let millis = remaining.as_millis() as u64;
+    if millis < 10_000 {
+        let tenths = (millis + 99) / 100;
+        return format!("{}.{}", tenths / 10, tenths % 10);
+    }
+    let secs = (millis + 999) / 1000;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn first_press_starts_opponent() {
+        let start = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::fischer(3, 2));
+        clock.press(Player::Black, start);
+        assert_eq!(clock.turn(), Some(Player::White));
+        assert_eq!(
+            clock.remaining(Player::White, start + 10 * SECOND),
+            170 * SECOND
+        );
+        assert_eq!(
+            clock.remaining(Player::Black, start + 10 * SECOND),
+            180 * SECOND
+        );
+
+        // Pressing the clock of the player not on turn does nothing
+        clock.press(Player::Black, start + 10 * SECOND);
+        assert_eq!(clock.turn(), Some(Player::White));
+        clock.press(Player::White, start + 10 * SECOND);
+        assert_eq!(clock.turn(), Some(Player::Black));
+        assert_eq!(
+            clock.remaining(Player::White, start + 20 * SECOND),
+            172 * SECOND
+        );
+        assert_eq!(
+            clock.remaining(Player::Black, start + 20 * SECOND),
+            170 * SECOND
+        );
+    }
+
+    #[test]
+    fn delay_runs_before_the_time() {
+        let start = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::simple_delay(5, 5));
+        clock.press(Player::Black, start);
+        assert_eq!(
+            clock.remaining(Player::White, start + 3 * SECOND),
+            300 * SECOND
+        );
+        assert_eq!(
+            clock.remaining(Player::White, start + 8 * SECOND),
+            297 * SECOND
+        );
+        clock.press(Player::White, start + 8 * SECOND);
+        assert_eq!(
+            clock.remaining(Player::White, start + 20 * SECOND),
+            297 * SECOND
+        );
+    }
+
+    #[test]
+    fn bronstein_gives_back_the_used_time() {
+        let start = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::bronstein(5, 3));
+        clock.press(Player::Black, start);
+        assert_eq!(
+            clock.remaining(Player::White, start + 2 * SECOND),
+            298 * SECOND
+        );
+        // A fast move gets everything back, but not more
+        clock.press(Player::White, start + 2 * SECOND);
+        assert_eq!(
+            clock.remaining(Player::White, start + 2 * SECOND),
+            300 * SECOND
+        );
+        // A slow move only gets the delay back
+        clock.press(Player::Black, start + 12 * SECOND);
+        assert_eq!(
+            clock.remaining(Player::Black, start + 12 * SECOND),
+            293 * SECOND
+        );
+        assert_eq!(
+            clock.snapshot(start + 13 * SECOND).delay,
+            Duration::default()
+        );
+    }
+
+    #[test]
+    fn resumed_clock_keeps_the_times() {
+        let start = Instant::now();
+        let mut clock = ChessClock::resumed(
+            TimeControl::fischer(5, 3),
+            ClockSnapshot {
+                white: 100 * SECOND,
+                black: 42 * SECOND,
+                running: Some(Player::Black),
+                delay: Duration::default(),
+            },
+        );
+        assert_eq!(clock.turn(), None);
+        clock.switch_to(Player::Black, start);
+        assert_eq!(
+            clock.remaining(Player::Black, start + 2 * SECOND),
+            40 * SECOND
+        );
+        clock.press(Player::Black, start + 2 * SECOND);
+        assert_eq!(
+            clock.remaining(Player::Black, start + 10 * SECOND),
+            43 * SECOND
+        );
+        assert_eq!(
+            clock.remaining(Player::White, start + 2 * SECOND),
+            100 * SECOND
+        );
+    }
+
+    #[test]
+    fn pause_stops_the_time() {
+        let start = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::fischer(1, 0));
+        clock.press(Player::Black, start);
+        clock.pause(start + 10 * SECOND);
+        assert!(clock.is_paused());
+        clock.press(Player::White, start + 20 * SECOND);
+        assert_eq!(clock.turn(), Some(Player::White));
+        assert_eq!(
+            clock.remaining(Player::White, start + 30 * SECOND),
+            50 * SECOND
+        );
+        clock.resume(start + 30 * SECOND);
+        assert_eq!(
+            clock.remaining(Player::White, start + 40 * SECOND),
+            40 * SECOND
+        );
+    }
+
+    #[test]
+    fn flag_falls() {
+        let start = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::fischer(1, 2));
+        clock.press(Player::Black, start);
+        assert_eq!(clock.flagged(start + 59 * SECOND), None);
+        assert_eq!(clock.flagged(start + 61 * SECOND), Some(Player::White));
+        // No increment after the flag fell
+        clock.press(Player::White, start + 61 * SECOND);
+        assert_eq!(clock.turn(), Some(Player::White));
+    }
+
+    #[test]
+    fn snapshot_keeps_running() {
+        let start = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::simple_delay(5, 5));
+        clock.press(Player::Black, start);
+        let snapshot = clock.snapshot(start + 2 * SECOND);
+        assert_eq!(snapshot.running, Some(Player::White));
+        assert_eq!(snapshot.delay, 3 * SECOND);
+        assert_eq!(snapshot.delay_left(SECOND), 2 * SECOND);
+        assert_eq!(snapshot.delay_left(10 * SECOND), Duration::default());
+        for elapsed in &[SECOND, 10 * SECOND] {
+            assert_eq!(
+                snapshot.remaining(Player::White, *elapsed),
+                clock.remaining(Player::White, start + 2 * SECOND + *elapsed)
+            );
+        }
+        assert_eq!(snapshot.remaining(Player::Black, 10 * SECOND), 300 * SECOND);
+    }
+
+    #[test]
+    fn switch_gives_no_bonus() {
+        let start = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::fischer(3, 2));
+        clock.press(Player::Black, start);
+        clock.switch_to(Player::Black, start + 10 * SECOND);
+        assert_eq!(clock.turn(), Some(Player::Black));
+        assert_eq!(
+            clock.remaining(Player::White, start + 20 * SECOND),
+            170 * SECOND
+        );
+        assert_eq!(
+            clock.remaining(Player::Black, start + 20 * SECOND),
+            170 * SECOND
+        );
+    }
+
+    #[test]
+    fn speeds() {
+        assert_eq!(TimeControl::fischer(1, 0).speed(), Speed::Bullet);
+        assert_eq!(TimeControl::fischer(3, 2).speed(), Speed::Blitz);
+        assert_eq!(TimeControl::fischer(10, 0).speed(), Speed::Rapid);
+        assert_eq!(TimeControl::fischer(15, 10).speed(), Speed::Rapid);
+        assert_eq!(TimeControl::fischer(30, 0).speed(), Speed::Classical);
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(Duration::from_secs(3900)), "1:05:00");
+        assert_eq!(format_time(Duration::from_millis(299_001)), "5:00");
+        assert_eq!(format_time(Duration::from_secs(61)), "1:01");
+        assert_eq!(format_time(Duration::from_millis(9_401)), "9.5");
+        assert_eq!(format_time(Duration::default()), "0.0");
+        assert_eq!(TimeControl::fischer(15, 10).label(), "15+10");
+        assert_eq!(TimeControl::simple_delay(5, 5).label(), "5 d5");
+        assert_eq!(TimeControl::bronstein(15, 10).label(), "15 b10");
+    }
+}
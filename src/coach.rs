@@ -0,0 +1,450 @@
+//! Explains why a move was a mistake: the engine's refutation line and
+//! what it leads to (lost material, a mate or a trapped piece). Also
+//! finds what the opponent threatens.
+
+use crate::analysis::static_eval;
+use crate::game::{is_stalemate, Material};
+use crate::notation::san;
+use crate::{Player, Square};
+use pleco::bot_prelude::AlphaBetaSearcher;
+use pleco::tools::Searcher;
+use pleco::{BitMove, Board, PieceType, Player as PlecoPlayer, SQ};
+
+/// Search depth of every move of the lines
+const COACH_DEPTH: u16 = 3;
+/// Length of the refutation line in half-moves
+const REFUTATION_PLIES: usize = 4;
+/// Moves that lose less than this (in centipawns) aren't mistakes
+const MISTAKE_THRESHOLD: i32 = 150;
+/// Material losses below this (in centipawns) aren't mentioned
+const MATERIAL_THRESHOLD: i32 = 100;
+const MATE_CENTIPAWNS: i32 = 10_000;
+/// Search depth for the threat of the opponent
+const THREAT_DEPTH: u16 = 3;
+/// The engine's move after passing is a threat if it wins this much
+/// (in centipawns) even after the best reply
+const THREAT_THRESHOLD: i32 = 100;
+
+/// What the refutation of a mistake leads to
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    /// In centipawns
+    MaterialLost(i32),
+    /// The opponent mates in this many moves
+    MateThreat(u8),
+    PieceTrapped {
+        piece: PieceType,
+        square: Square,
+    },
+}
+
+impl Reason {
+    pub fn description(&self) -> String {
+        match self {
+            Reason::MaterialLost(centipawns) => match (centipawns + 50) / 100 {
+                1 => "loses a pawn".to_owned(),
+                pawns => format!("loses {} pawns of material", pawns),
+            },
+            Reason::MateThreat(1) => "allows mate".to_owned(),
+            Reason::MateThreat(moves) => format!("allows mate in {}", moves),
+            Reason::PieceTrapped { piece, square } => format!(
+                "traps the {} on {}",
+                piece_name(*piece),
+                square.to_string().to_lowercase()
+            ),
+        }
+    }
+}
+
+/// A move of the refutation line
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineMove {
+    pub source: Square,
+    pub destination: Square,
+    pub san: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mistake {
+    pub played: (Square, Square),
+    /// What the engine would have played instead
+    pub best: Option<(Square, Square)>,
+    /// The engine's line after the mistake, starting with the reply
+    pub refutation: Vec<LineMove>,
+    /// In centipawns from the point of view of the player who made it
+    pub eval_drop: i32,
+    pub reasons: Vec<Reason>,
+}
+
+impl Mistake {
+    /// E.g. "Nc3 loses a knight: Bxc3 bxc3"
+    pub fn explanation(&self, played_san: &str) -> String {
+        let reasons: Vec<String> = self.reasons.iter().map(Reason::description).collect();
+        let why = if reasons.is_empty() {
+            format!("costs {:.1} pawns", self.eval_drop as f32 / 100.0)
+        } else {
+            reasons.join(" and ")
+        };
+        let line: Vec<&str> = self.refutation.iter().map(|m| m.san.as_str()).collect();
+        format!("{} {}: {}", played_san, why, line.join(" "))
+    }
+}
+
+fn piece_name(piece: PieceType) -> &'static str {
+    match piece {
+        PieceType::P => "pawn",
+        PieceType::N => "knight",
+        PieceType::B => "bishop",
+        PieceType::R => "rook",
+        PieceType::Q => "queen",
+        _ => "king",
+    }
+}
+
+/// In centipawns (the king can't be traded)
+fn piece_value(piece: PieceType) -> i32 {
+    match piece {
+        PieceType::P => 100,
+        PieceType::N | PieceType::B => 300,
+        PieceType::R => 500,
+        PieceType::Q => 900,
+        _ => MATE_CENTIPAWNS,
+    }
+}
+
+fn opponent(player: PlecoPlayer) -> PlecoPlayer {
+    match player {
+        PlecoPlayer::White => PlecoPlayer::Black,
+        PlecoPlayer::Black => PlecoPlayer::White,
+    }
+}
+
+/// The move that leads from `before` to the piece placement of `fen_after`
+pub fn played_move(before: &Board, fen_after: &str) -> Option<BitMove> {
+    let placement = fen_after.split_whitespace().next()?;
+    let mut board = before.shallow_clone();
+    for bit_move in before.generate_moves().iter().copied() {
+        board.apply_move(bit_move);
+        let found = board.fen().split_whitespace().next() == Some(placement);
+        board.undo_move();
+        if found {
+            return Some(bit_move);
+        }
+    }
+    None
+}
+
+/// Play the engine's moves for up to `plies` half-moves (less if the game
+/// ends). `board` is left at the end of the line.
+fn engine_line(board: &mut Board, plies: usize) -> Vec<BitMove> {
+    let mut line = vec![];
+    while line.len() < plies && !board.checkmate() && !is_stalemate(board) {
+        let bit_move = AlphaBetaSearcher::best_move(board.shallow_clone(), COACH_DEPTH);
+        if bit_move.is_null() {
+            break;
+        }
+        board.apply_move(bit_move);
+        line.push(bit_move);
+    }
+    line
+}
+
+/// Centipawns from the point of view of `player`
+fn eval_for(board: &Board, player: PlecoPlayer) -> i32 {
+    if board.checkmate() {
+        return if board.turn() == player {
+            -MATE_CENTIPAWNS
+        } else {
+            MATE_CENTIPAWNS
+        };
+    }
+    match player {
+        PlecoPlayer::White => static_eval(board),
+        PlecoPlayer::Black => -static_eval(board),
+    }
+}
+
+fn material_for(board: &Board, player: PlecoPlayer) -> i32 {
+    match Player::from(player) {
+        Player::White => Material::of(board).balance(),
+        Player::Black => -Material::of(board).balance(),
+    }
+}
+
+/// Whether a piece worth `value` of `owner` on `sq` can be taken
+/// without getting as much back
+fn is_unsafe(board: &Board, sq: SQ, value: i32, owner: PlecoPlayer) -> bool {
+    let attackers = board.attackers_to(sq, board.occupied());
+    let enemies = attackers & board.get_occupied_player(opponent(owner));
+    if enemies.count_bits() == 0 {
+        return false;
+    }
+    if (attackers & board.get_occupied_player(owner)).count_bits() == 0 {
+        return true;
+    }
+    board
+        .get_piece_locations()
+        .into_iter()
+        .filter(|(from, _)| (enemies & from.to_bb()).count_bits() > 0)
+        .any(|(_, piece)| piece_value(piece.type_of()) < value)
+}
+
+/// Pieces of the player to move that are attacked and can't go anywhere
+/// safe (taking something at least as valuable counts as safe)
+fn trapped_pieces(board: &Board) -> Vec<(PieceType, SQ)> {
+    let owner = board.turn();
+    let moves: Vec<BitMove> = board.generate_moves().iter().copied().collect();
+    let mut trapped = vec![];
+    for (sq, piece) in board.get_piece_locations() {
+        let piece_type = piece.type_of();
+        if piece.player_lossy() != owner || piece_type == PieceType::P || piece_type == PieceType::K
+        {
+            continue;
+        }
+        let value = piece_value(piece_type);
+        if !is_unsafe(board, sq, value, owner) {
+            continue;
+        }
+        let mut escapes = false;
+        let mut after = board.shallow_clone();
+        for bit_move in moves.iter().filter(|bit_move| bit_move.get_src() == sq) {
+            let captured = board.piece_at_sq(bit_move.get_dest()).type_of();
+            if bit_move.is_capture() && piece_value(captured) >= value {
+                escapes = true;
+                break;
+            }
+            after.apply_move(*bit_move);
+            let safe = !is_unsafe(&after, bit_move.get_dest(), value, owner);
+            after.undo_move();
+            if safe {
+                escapes = true;
+                break;
+            }
+        }
+        if !escapes {
+            trapped.push((piece_type, sq));
+        }
+    }
+    trapped
+}
+
+/// The position with the other side to move (en passant is lost). `None`
+/// if it can't be set up.
+fn null_move(board: &Board) -> Option<Board> {
+    let fen = board.fen();
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 4 {
+        return None;
+    }
+    fields[1] = if fields[1] == "w" { "b" } else { "w" };
+    fields[3] = "-";
+    Board::from_fen(&fields.join(" ")).ok()
+}
+
+/// What the opponent threatens if the player to move passed (a null
+/// move): the engine's move if it wins something, and taking any piece
+/// that isn't safe. Empty if the player is in check and can't pass.
+pub fn threats(board: &Board) -> Vec<(Square, Square)> {
+    if board.in_check() {
+        return vec![];
+    }
+    let passed = match null_move(board) {
+        Some(passed) => passed,
+        None => return vec![],
+    };
+    let player = board.turn();
+    let mut threats: Vec<BitMove> = vec![];
+
+    let bit_move = AlphaBetaSearcher::best_move(passed.shallow_clone(), THREAT_DEPTH);
+    if !bit_move.is_null() {
+        let mut after = passed.shallow_clone();
+        after.apply_move(bit_move);
+        engine_line(&mut after, 1);
+        if eval_for(&passed, player) - eval_for(&after, player) >= THREAT_THRESHOLD {
+            threats.push(bit_move);
+        }
+    }
+
+    // The cheapest attacker of every piece that can be won
+    let mut captures: Vec<BitMove> = passed
+        .generate_moves()
+        .iter()
+        .copied()
+        .filter(|bit_move| bit_move.is_capture())
+        .collect();
+    captures.sort_by_key(|bit_move| piece_value(passed.piece_at_sq(bit_move.get_src()).type_of()));
+    for capture in captures {
+        let target = capture.get_dest();
+        let value = piece_value(passed.piece_at_sq(target).type_of());
+        if threats.iter().any(|threat| threat.get_dest() == target)
+            || !is_unsafe(&passed, target, value, player)
+        {
+            continue;
+        }
+        threats.push(capture);
+    }
+
+    threats
+        .iter()
+        .map(|bit_move| (bit_move.get_src().into(), bit_move.get_dest().into()))
+        .collect()
+}
+
+/// Compare `played` with the engine's choice in the position `before`.
+/// `None` if it wasn't a mistake. Takes a few engine searches, so better
+/// not on the thread that draws.
+pub fn review_move(before: &Board, played: BitMove) -> Option<Mistake> {
+    let mover = before.turn();
+    let mut best_board = before.shallow_clone();
+    let best_line = engine_line(&mut best_board, REFUTATION_PLIES + 1);
+    let best = best_line.first().copied();
+    if best == Some(played) {
+        return None;
+    }
+
+    let mut board = before.shallow_clone();
+    board.apply_move(played);
+    let after_mistake = board.shallow_clone();
+    let line = engine_line(&mut board, REFUTATION_PLIES);
+    let eval_drop = eval_for(&best_board, mover) - eval_for(&board, mover);
+    if eval_drop < MISTAKE_THRESHOLD {
+        return None;
+    }
+
+    let mut reasons = vec![];
+    if board.checkmate() && board.turn() == mover {
+        reasons.push(Reason::MateThreat(((line.len() + 1) / 2) as u8));
+    } else {
+        let lost = material_for(before, mover) - material_for(&board, mover);
+        if lost >= MATERIAL_THRESHOLD {
+            reasons.push(Reason::MaterialLost(lost));
+        }
+    }
+    if let Some(reply) = line.first() {
+        let mut replied = after_mistake.shallow_clone();
+        replied.apply_move(*reply);
+        let trapped = if replied.checkmate() {
+            vec![]
+        } else {
+            trapped_pieces(&replied)
+        };
+        if let Some((piece, sq)) = trapped.into_iter().next() {
+            reasons.push(Reason::PieceTrapped {
+                piece,
+                square: sq.into(),
+            });
+        }
+    }
+
+    let mut replay = after_mistake;
+    let refutation = line
+        .iter()
+        .map(|bit_move| {
+            let line_move = LineMove {
+                source: bit_move.get_src().into(),
+                destination: bit_move.get_dest().into(),
+                san: san(&replay, *bit_move),
+            };
+            replay.apply_move(*bit_move);
+            line_move
+        })
+        .collect();
+    Some(Mistake {
+        played: (played.get_src().into(), played.get_dest().into()),
+        best: best.map(|bit_move| (bit_move.get_src().into(), bit_move.get_dest().into())),
+        refutation,
+        eval_drop,
+        reasons,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(fen: &str, uci: &str) -> Option<Mistake> {
+        let board = Board::from_fen(fen).unwrap();
+        let played = board
+            .generate_moves()
+            .iter()
+            .copied()
+            .find(|bit_move| bit_move.stringify() == uci)
+            .unwrap();
+        review_move(&board, played)
+    }
+
+    #[test]
+    fn finds_the_played_move() {
+        let before = Board::default();
+        let played = played_move(
+            &before,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        )
+        .unwrap();
+        assert_eq!(played.stringify(), "e2e4");
+        assert!(played_move(&before, "8/8/8/4k3/8/8/8/R3K3 w - - 0 1").is_none());
+    }
+
+    #[test]
+    fn hanging_the_queen_loses_material() {
+        let mistake = review(
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            "d1g4",
+        )
+        .unwrap();
+        assert_eq!(mistake.refutation[0].san, "Bxg4");
+        assert!(mistake
+            .reasons
+            .iter()
+            .any(|reason| matches!(reason, Reason::MaterialLost(lost) if *lost >= 800)));
+    }
+
+    #[test]
+    fn allowing_mate_is_explained() {
+        // After f3 e5 g4?? Qh4 is mate
+        let mistake = review(
+            "rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq e6 0 2",
+            "g2g4",
+        )
+        .unwrap();
+        assert_eq!(mistake.refutation.len(), 1);
+        assert_eq!(mistake.refutation[0].san, "Qh4#");
+        assert_eq!(mistake.reasons, vec![Reason::MateThreat(1)]);
+        assert_eq!(mistake.explanation("g4"), "g4 allows mate: Qh4#");
+    }
+
+    #[test]
+    fn trapped_pieces_are_found() {
+        // Neither Bb8 nor Bxb6 gets the bishop out
+        let board = Board::from_fen("r3k3/B1p5/1p6/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(trapped_pieces(&board), vec![(PieceType::B, SQ::A7)]);
+        // Without the c-pawn Bxb6 is safe
+        let board = Board::from_fen("r3k3/B7/1p6/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(trapped_pieces(&board).is_empty());
+    }
+
+    #[test]
+    fn threats_of_the_opponent() {
+        let square = |name: &str| name.parse::<Square>().unwrap();
+        // Qxf7# is threatened
+        let board =
+            Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 3 3")
+                .unwrap();
+        assert!(threats(&board).contains(&(square("H5"), square("F7"))));
+        // The knight hangs
+        let board = Board::from_fen("4k3/8/8/3n4/8/8/8/3RK3 b - - 0 1").unwrap();
+        assert_eq!(threats(&board), vec![(square("D1"), square("D5"))]);
+        // Nobody can pass in check
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4RK2 b - - 0 1").unwrap();
+        assert!(threats(&board).is_empty());
+    }
+
+    #[test]
+    fn good_moves_are_no_mistakes() {
+        assert!(review(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "e2e4"
+        )
+        .is_none());
+    }
+}
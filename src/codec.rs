@@ -0,0 +1,164 @@
+//! Encodings of the protocol messages (`ChessRequest` and `ChessUpdate`)
+//! for transports that send them as bytes. Both ends start by sending a
+//! `Handshake`, which is always JSON, and then use the codec they agreed on.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Changes whenever the messages change in an incompatible way
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    /// Readable but the largest and slowest to parse
+    Json,
+    /// Smallest, but both ends need the exact same message types
+    Bincode,
+    Cbor,
+}
+
+/// Codecs this build supports, the preferred one first
+pub const SUPPORTED_CODECS: &[Codec] = &[Codec::Bincode, Codec::Cbor, Codec::Json];
+
+impl Codec {
+    pub fn encode<T: Serialize>(self, message: &T) -> Result<Vec<u8>> {
+        match self {
+            Codec::Json => serde_json::to_vec(message).context("Encode JSON"),
+            Codec::Bincode => bincode::serialize(message).context("Encode bincode"),
+            Codec::Cbor => serde_cbor::to_vec(message).context("Encode CBOR"),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        match self {
+            Codec::Json => serde_json::from_slice(bytes).context("Decode JSON"),
+            Codec::Bincode => bincode::deserialize(bytes).context("Decode bincode"),
+            Codec::Cbor => serde_cbor::from_slice(bytes).context("Decode CBOR"),
+        }
+    }
+}
+
+/// First message of both ends
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub version: u32,
+    /// Codecs the sender can use, the preferred one first
+    pub codecs: Vec<Codec>,
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            codecs: SUPPORTED_CODECS.to_vec(),
+        }
+    }
+}
+
+impl Handshake {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Codec::Json.encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Codec::Json.decode(bytes)
+    }
+
+    /// Codec both ends use after exchanging their handshakes (`self` being
+    /// the one of the client): the first one of the client that the server
+    /// supports. JSON is understood by everyone.
+    pub fn negotiate(&self, server: &Handshake) -> Result<Codec> {
+        ensure!(
+            self.version == server.version,
+            "Protocol version {} is not supported (expected {})",
+            self.version,
+            server.version
+        );
+        Ok(self
+            .codecs
+            .iter()
+            .copied()
+            .find(|codec| server.codecs.contains(codec))
+            .unwrap_or(Codec::Json))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ChessOutcome, PossibleMove};
+    use crate::proto::{ChessRequest, ChessUpdate, PromotionPiece};
+    use crate::Player;
+
+    #[test]
+    fn messages_survive_every_codec() {
+        let request = ChessRequest::MovePiece {
+            source: "B7".parse().unwrap(),
+            destination: "B8".parse().unwrap(),
+            promotion: Some(PromotionPiece::Knight),
+        };
+        let update = ChessUpdate::PossibleMoves {
+            possible_moves: vec![PossibleMove {
+                source: "E2".parse().unwrap(),
+                destination: "E4".parse().unwrap(),
+                capture: false,
+                promotion: false,
+                castle: false,
+                en_passant: false,
+            }],
+        };
+        let outcome = ChessUpdate::Outcome {
+            outcome: Some(ChessOutcome::Checkmate {
+                winner: Player::Black,
+            }),
+        };
+        for codec in SUPPORTED_CODECS {
+            let bytes = codec.encode(&request).unwrap();
+            assert_eq!(codec.decode::<ChessRequest>(&bytes).unwrap(), request);
+            for update in &[&update, &outcome] {
+                let bytes = codec.encode(update).unwrap();
+                assert_eq!(&codec.decode::<ChessUpdate>(&bytes).unwrap(), *update);
+            }
+        }
+    }
+
+    #[test]
+    fn bincode_is_smaller() {
+        let update = ChessUpdate::Board {
+            fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_owned(),
+        };
+        let json = Codec::Json.encode(&update).unwrap().len();
+        assert!(Codec::Bincode.encode(&update).unwrap().len() < json);
+    }
+
+    #[test]
+    fn negotiates_the_preferred_codec_of_the_client() {
+        let server = Handshake::default();
+        let client = Handshake {
+            codecs: vec![Codec::Cbor, Codec::Json],
+            ..Default::default()
+        };
+        assert_eq!(client.negotiate(&server).unwrap(), Codec::Cbor);
+
+        let json_only = Handshake {
+            codecs: vec![Codec::Json],
+            ..Default::default()
+        };
+        assert_eq!(client.negotiate(&json_only).unwrap(), Codec::Json);
+        let nothing = Handshake {
+            codecs: vec![],
+            ..Default::default()
+        };
+        assert_eq!(nothing.negotiate(&server).unwrap(), Codec::Json);
+
+        let other_version = Handshake {
+            version: PROTOCOL_VERSION + 1,
+            ..Default::default()
+        };
+        assert!(other_version.negotiate(&server).is_err());
+
+        let bytes = client.encode().unwrap();
+        assert_eq!(Handshake::decode(&bytes).unwrap(), client);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::game::{is_stalemate, ChessOutcome, Variant, VariantKind};
+use crate::{Player, Square};
+use anyhow::Result;
+use pleco::{BitMove, Board, Piece, PieceType, SQ};
+use serde::{Deserialize, Serialize};
+
+/// A captured piece that can be put back onto the board
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DropPiece {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+}
+
+pub const ALL_DROP_PIECES: &[DropPiece] = &[
+    DropPiece::Pawn,
+    DropPiece::Knight,
+    DropPiece::Bishop,
+    DropPiece::Rook,
+    DropPiece::Queen,
+];
+
+impl DropPiece {
+    pub fn from_piece_type(piece_type: PieceType) -> Option<Self> {
+        match piece_type {
+            PieceType::P => Some(DropPiece::Pawn),
+            PieceType::N => Some(DropPiece::Knight),
+            PieceType::B => Some(DropPiece::Bishop),
+            PieceType::R => Some(DropPiece::Rook),
+            PieceType::Q => Some(DropPiece::Queen),
+            _ => None,
+        }
+    }
+
+    /// Letter used in FENs (uppercase for white)
+    pub fn fen_char(self, player: Player) -> char {
+        let c = match self {
+            DropPiece::Pawn => 'p',
+            DropPiece::Knight => 'n',
+            DropPiece::Bishop => 'b',
+            DropPiece::Rook => 'r',
+            DropPiece::Queen => 'q',
+        };
+        match player {
+            Player::White => c.to_ascii_uppercase(),
+            Player::Black => c,
+        }
+    }
+
+    fn from_fen_char(c: char) -> Option<(Self, Player)> {
+        let piece = ALL_DROP_PIECES
+            .iter()
+            .find(|piece| piece.fen_char(Player::Black) == c.to_ascii_lowercase())?;
+        let player = if c.is_ascii_uppercase() {
+            Player::White
+        } else {
+            Player::Black
+        };
+        Some((*piece, player))
+    }
+}
+
+/// Pieces each player captured and may drop
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pockets {
+    pub white: Vec<DropPiece>,
+    pub black: Vec<DropPiece>,
+}
+
+impl Pockets {
+    pub fn of(&self, player: Player) -> &[DropPiece] {
+        match player {
+            Player::White => &self.white,
+            Player::Black => &self.black,
+        }
+    }
+
+    fn of_mut(&mut self, player: Player) -> &mut Vec<DropPiece> {
+        match player {
+            Player::White => &mut self.white,
+            Player::Black => &mut self.black,
+        }
+    }
+
+    pub fn count(&self, player: Player, piece: DropPiece) -> usize {
+        self.of(player).iter().filter(|p| **p == piece).count()
+    }
+
+    /// Add the pockets in brackets after the piece placement
+    /// (e.g. "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Qp] w KQkq - 0 1")
+    pub fn add_to_fen(&self, fen: &str) -> String {
+        let mut pockets = String::new();
+        for player in &[Player::White, Player::Black] {
+            for piece in self.of(*player) {
+                pockets.push(piece.fen_char(*player));
+            }
+        }
+        let mut fields = fen.splitn(2, ' ');
+        let placement = fields.next().unwrap_or_default();
+        match fields.next() {
+            Some(rest) => format!("{}[{}] {}", placement, pockets, rest),
+            None => format!("{}[{}]", placement, pockets),
+        }
+    }
+
+    /// Counterpart of `add_to_fen()`. FENs without pockets are returned as they are.
+    pub fn split_fen(fen: &str) -> Result<(String, Pockets)> {
+        let mut pockets = Pockets::default();
+        let start = match fen.find('[') {
+            Some(start) => start,
+            None => return Ok((fen.to_owned(), pockets)),
+        };
+        let end = fen[start..]
+            .find(']')
+            .map(|end| start + end)
+            .ok_or(anyhow!("Pockets in FEN are missing a closing bracket"))?;
+        for c in fen[start + 1..end].chars() {
+            let (piece, player) =
+                DropPiece::from_fen_char(c).ok_or(anyhow!("Invalid piece '{}' in pockets", c))?;
+            pockets.of_mut(player).push(piece);
+        }
+        Ok((format!("{}{}", &fen[..start], &fen[end + 1..]), pockets))
+    }
+}
+
+#[derive(Clone, Default)]
+struct CrazyhouseState {
+    pockets: Pockets,
+    /// Squares of promoted pieces (as bitboard). They become pawns again when captured.
+    promoted: u64,
+}
+
+/// Captured pieces change sides and can be dropped onto any empty
+/// square instead of moving (pawns not on the first or last rank).
+#[derive(Default)]
+pub struct Crazyhouse {
+    state: CrazyhouseState,
+    /// State before each move and drop to support undoing them
+    history: Vec<CrazyhouseState>,
+}
+
+impl Variant for Crazyhouse {
+    fn kind(&self) -> VariantKind {
+        VariantKind::Crazyhouse
+    }
+
+    fn setup(&mut self, fen: &str) -> Result<String> {
+        let (fen, pockets) = Pockets::split_fen(fen)?;
+        self.state = CrazyhouseState {
+            pockets,
+            promoted: 0,
+        };
+        self.history.clear();
+        Ok(fen)
+    }
+
+    fn outcome(&self, board: &Board) -> Option<ChessOutcome> {
+        let game_goes_on = !board.checkmate() && !is_stalemate(board);
+        // Dropping a piece may still block a check or avoid a stalemate
+        if game_goes_on || !self.possible_drops(board).is_empty() {
+            None
+        } else if board.checkmate() {
+            Some(ChessOutcome::Checkmate {
+                winner: Player::from(board.turn()).other_player(),
+            })
+        } else {
+            Some(ChessOutcome::Stalemate)
+        }
+    }
+
+    fn after_move(&mut self, board_before: &Board, bit_move: BitMove) {
+        self.history.push(self.state.clone());
+        let player: Player = board_before.turn().into();
+        let src = bit_move.get_src();
+        let dest = bit_move.get_dest();
+
+        if bit_move.is_capture() || bit_move.is_en_passant() {
+            let captured_sq = if bit_move.is_en_passant() {
+                // The captured pawn is behind the destination
+                SQ(src.rank() as u8 * 8 + dest.file() as u8)
+            } else {
+                dest
+            };
+            let captured_piece = if self.state.promoted & (1u64 << captured_sq.0) != 0 {
+                Some(DropPiece::Pawn)
+            } else {
+                DropPiece::from_piece_type(board_before.piece_at_sq(captured_sq).type_of())
+            };
+            if let Some(piece) = captured_piece {
+                self.state.pockets.of_mut(player).push(piece);
+            }
+            self.state.promoted &= !(1u64 << captured_sq.0);
+        }
+
+        let moved_promoted_piece = self.state.promoted & (1u64 << src.0) != 0;
+        self.state.promoted &= !(1u64 << src.0);
+        if moved_promoted_piece || bit_move.is_promo() {
+            self.state.promoted |= 1u64 << dest.0;
+        }
+    }
+
+    fn after_undo(&mut self, count: u16) {
+        for _ in 0..count {
+            if let Some(state) = self.history.pop() {
+                self.state = state;
+            }
+        }
+    }
+
+    fn pockets(&self) -> Option<&Pockets> {
+        Some(&self.state.pockets)
+    }
+
+    /// pleco can't hold more than 8 pawns of a player, so pawns can't be
+    /// dropped while all 8 are on the board.
+    fn possible_drops(&self, board: &Board) -> Vec<(DropPiece, Square)> {
+        let player: Player = board.turn().into();
+        let in_check = board.in_check();
+        let mut possible_drops = vec![];
+        for piece in ALL_DROP_PIECES {
+            if self.state.pockets.count(player, *piece) == 0 {
+                continue;
+            }
+            if *piece == DropPiece::Pawn && board.count_piece(board.turn(), PieceType::P) >= 8 {
+                continue;
+            }
+            for sq in 0..64 {
+                let sq = SQ(sq);
+                let is_back_rank = sq.rank() as u8 == 0 || sq.rank() as u8 == 7;
+                if board.piece_at_sq(sq) != Piece::None
+                    || (*piece == DropPiece::Pawn && is_back_rank)
+                {
+                    continue;
+                }
+                // Dropping can never expose the own king, only fail to block a check
+                if in_check && !drop_resolves_check(board, *piece, sq) {
+                    continue;
+                }
+                possible_drops.push((*piece, Square::from(sq)));
+            }
+        }
+        possible_drops
+    }
+
+    fn drop_piece(
+        &mut self,
+        board: &Board,
+        piece: DropPiece,
+        destination: Square,
+    ) -> Result<Board> {
+        ensure!(
+            self.possible_drops(board).contains(&(piece, destination)),
+            "Can't drop a {:?} on {}",
+            piece,
+            destination
+        );
+        let player: Player = board.turn().into();
+        let new_board = Board::from_fen(&fen_with_drop(board, piece, *destination, true))
+            .map_err(|e| anyhow!("Board got into illegal state after drop: {:?}", e))?;
+
+        self.history.push(self.state.clone());
+        let pocket = self.state.pockets.of_mut(player);
+        let index = pocket.iter().position(|p| *p == piece).unwrap();
+        pocket.remove(index);
+        Ok(new_board)
+    }
+}
+
+fn drop_resolves_check(board: &Board, piece: DropPiece, sq: SQ) -> bool {
+    match Board::from_fen(&fen_with_drop(board, piece, sq, false)) {
+        Ok(board) => !board.in_check(),
+        Err(_) => false,
+    }
+}
+
+/// FEN of `board` with `piece` of the player to move added on `sq`. Passes the
+/// turn to the other player if `switch_turn` is set (like making a move).
+fn fen_with_drop(board: &Board, piece: DropPiece, sq: SQ, switch_turn: bool) -> String {
+    let player: Player = board.turn().into();
+    let mut placement = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            let current = SQ(rank * 8 + file);
+            let c = if current == sq {
+                Some(piece.fen_char(player))
+            } else {
+                board.piece_at_sq(current).character()
+            };
+            match c {
+                Some(c) => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    placement.push(c);
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            placement.push('/');
+        }
+    }
+
+    let fen = board.fen();
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if !switch_turn {
+        return format!("{} {}", placement, fields[1..].join(" "));
+    }
+    let full_moves: u32 = fields.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+    format!(
+        "{} {} {} - 0 {}",
+        placement,
+        if player == Player::White { "b" } else { "w" },
+        fields.get(2).unwrap_or(&"-"),
+        if player == Player::Black {
+            full_moves + 1
+        } else {
+            full_moves
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ChessGame;
+
+    fn play(game: &mut ChessGame, moves: &[(&str, &str)]) {
+        for (source, destination) in moves {
+            game.move_piece(source.parse().unwrap(), destination.parse().unwrap())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn captured_pieces_can_be_dropped() {
+        let mut game = ChessGame::with_variant(VariantKind::Crazyhouse).unwrap();
+        play(
+            &mut game,
+            &[("E2", "E4"), ("D7", "D5"), ("E4", "D5"), ("D8", "D5")],
+        );
+        assert_eq!(game.pockets().unwrap().white, vec![DropPiece::Pawn]);
+        assert!(game
+            .possible_drops()
+            .contains(&(DropPiece::Pawn, "E4".parse().unwrap())));
+        assert!(!game
+            .possible_drops()
+            .contains(&(DropPiece::Pawn, "G8".parse().unwrap())));
+
+        game.drop_piece(DropPiece::Pawn, "E4".parse().unwrap())
+            .unwrap();
+        assert!(game.pockets().unwrap().white.is_empty());
+        assert_eq!(game.turn(), Player::Black);
+        assert!(game
+            .drop_piece(DropPiece::Knight, "E5".parse().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn pawns_cant_be_dropped_with_eight_on_the_board() {
+        let mut game = ChessGame::with_variant(VariantKind::Crazyhouse).unwrap();
+        play(
+            &mut game,
+            &[("E2", "E4"), ("D7", "D5"), ("E4", "D5"), ("G8", "F6")],
+        );
+        assert_eq!(game.pockets().unwrap().white, vec![DropPiece::Pawn]);
+        assert!(game.possible_drops().is_empty());
+    }
+
+    #[test]
+    fn drops_can_be_undone() {
+        let mut game = ChessGame::with_variant(VariantKind::Crazyhouse).unwrap();
+        play(
+            &mut game,
+            &[("E2", "E4"), ("D7", "D5"), ("E4", "D5"), ("D8", "D5")],
+        );
+        game.drop_piece(DropPiece::Pawn, "E4".parse().unwrap())
+            .unwrap();
+        play(&mut game, &[("D5", "A5")]);
+        assert_eq!(game.total_undoable_moves(), 6);
+
+        game.undo(2).unwrap();
+        assert_eq!(game.pockets().unwrap().white, vec![DropPiece::Pawn]);
+        assert_eq!(game.turn(), Player::White);
+        // Moves before the drop as well
+        game.undo(1).unwrap();
+        assert_eq!(game.pockets().unwrap().black, vec![]);
+        assert_eq!(
+            game.fen(),
+            "rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2"
+        );
+    }
+
+    #[test]
+    fn undo_returns_captured_pieces() {
+        let mut game = ChessGame::with_variant(VariantKind::Crazyhouse).unwrap();
+        play(&mut game, &[("E2", "E4"), ("D7", "D5"), ("E4", "D5")]);
+        game.undo(1).unwrap();
+        assert!(game.pockets().unwrap().white.is_empty());
+    }
+
+    #[test]
+    fn drop_can_prevent_checkmate() {
+        // Back rank "mate" that a knight drop on f8 blocks
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/R5K1[n] w - - 0 1";
+        let mut game = ChessGame::from_fen_with_variant(fen, VariantKind::Crazyhouse).unwrap();
+        play(&mut game, &[("A1", "A8")]);
+        assert_eq!(game.outcome(), None);
+        assert_eq!(
+            game.possible_drops(),
+            vec![
+                (DropPiece::Knight, "B8".parse().unwrap()),
+                (DropPiece::Knight, "C8".parse().unwrap()),
+                (DropPiece::Knight, "D8".parse().unwrap()),
+                (DropPiece::Knight, "E8".parse().unwrap()),
+                (DropPiece::Knight, "F8".parse().unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pockets_survive_fen_round_trip() {
+        let pockets = Pockets {
+            white: vec![DropPiece::Queen],
+            black: vec![DropPiece::Pawn, DropPiece::Knight],
+        };
+        let fen = pockets.add_to_fen("8/8/8/8/8/8/8/8 w - - 0 1");
+        assert_eq!(fen, "8/8/8/8/8/8/8/8[Qpn] w - - 0 1");
+        let (plain_fen, parsed) = Pockets::split_fen(&fen).unwrap();
+        assert_eq!(plain_fen, "8/8/8/8/8/8/8/8 w - - 0 1");
+        assert_eq!(parsed, pockets);
+    }
+
+    #[test]
+    fn game_fen_keeps_the_pockets() {
+        let mut game = ChessGame::with_variant(VariantKind::Crazyhouse).unwrap();
+        play(&mut game, &[("E2", "E4"), ("D7", "D5"), ("E4", "D5")]);
+        let fen = game.fen_with_pockets();
+        assert_eq!(
+            fen,
+            "rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR[P] b KQkq - 0 2"
+        );
+        let read = ChessGame::from_fen_with_variant(&fen, VariantKind::Crazyhouse).unwrap();
+        assert_eq!(read.pockets(), game.pockets());
+        assert_eq!(
+            ChessGame::default().fen_with_pockets(),
+            ChessGame::default().fen()
+        );
+    }
+}
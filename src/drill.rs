@@ -0,0 +1,206 @@
+//! Converting a won endgame against perfect defense. The player has to
+//! mate within the 50-move rule, the tablebase answers every move and
+//! tells right away when the win was thrown away.
+
+use crate::game::{ChessGame, PromotionPiece};
+use crate::tablebase::{self, Probe};
+use crate::{Player, Square};
+use anyhow::Result;
+
+/// Plies without capture or pawn move before the game is drawn
+const FIFTY_MOVES: u8 = 100;
+
+/// What the last move of the player did to the win
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrillFeedback {
+    /// Still winning. `optimal` if the mate didn't get any further away.
+    OnTrack { mate_in: u8, optimal: bool },
+    /// Mate, the drill is solved
+    Mated,
+    /// Still a win on the board, but not before the 50-move rule
+    TooSlow { mate_in: u8, moves_left: u8 },
+    /// The position is a draw now
+    WinLost,
+}
+
+impl DrillFeedback {
+    /// Whether the player has to take the move back to go on
+    pub fn is_failed(self) -> bool {
+        matches!(self, DrillFeedback::TooSlow { .. } | DrillFeedback::WinLost)
+    }
+
+    pub fn description(self) -> String {
+        match self {
+            DrillFeedback::OnTrack {
+                mate_in,
+                optimal: true,
+            } => format!("Best move, mate in {}", mate_in),
+            DrillFeedback::OnTrack {
+                mate_in,
+                optimal: false,
+            } => format!("Still winning, mate in {}", mate_in),
+            DrillFeedback::Mated => "Checkmate, well done!".to_owned(),
+            DrillFeedback::TooSlow {
+                mate_in,
+                moves_left,
+            } => format!(
+                "Too slow: mate in {}, but only {} moves left before the 50-move rule",
+                mate_in, moves_left
+            ),
+            DrillFeedback::WinLost => "The win is gone, it's a draw now".to_owned(),
+        }
+    }
+}
+
+/// Moves (not plies) of the player until mate
+fn mate_in(plies: u8) -> u8 {
+    plies / 2 + 1
+}
+
+/// A tablebase win for the player to move which the tablebase defends
+pub struct Drill {
+    game: ChessGame,
+    player: Player,
+    /// Plies to mate before the move of the player
+    plies_to_mate: u8,
+}
+
+impl Drill {
+    pub fn new(fen: &str) -> Result<Self> {
+        let game = ChessGame::from_fen(fen)?;
+        let plies_to_mate = match tablebase::probe(&game.board()) {
+            Some(Probe::Win(plies)) if plies % 2 == 1 => plies,
+            Some(_) => bail!("The side to move doesn't win the position"),
+            None => bail!("The tablebase doesn't have the position"),
+        };
+        Ok(Self {
+            player: game.turn(),
+            game,
+            plies_to_mate,
+        })
+    }
+
+    pub fn game(&self) -> &ChessGame {
+        &self.game
+    }
+
+    /// The side which has to win
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    /// Moves to mate with perfect play from here on
+    pub fn mate_in(&self) -> u8 {
+        mate_in(self.plies_to_mate)
+    }
+
+    /// Moves the player has before the 50-move rule draws the game
+    pub fn moves_left(&self) -> u8 {
+        let plies_left = (FIFTY_MOVES as i32 - self.game.board().rule_50() as i32).max(0);
+        ((plies_left + 1) / 2) as u8
+    }
+
+    /// Play the move of the player and answer it with the best defense
+    /// unless the win was lost with it
+    pub fn play(
+        &mut self,
+        source: Square,
+        destination: Square,
+        promotion: Option<PromotionPiece>,
+    ) -> Result<DrillFeedback> {
+        ensure!(
+            self.game.turn() == self.player,
+            "It's not the player's turn"
+        );
+        self.game
+            .move_piece_with_promotion(source, destination, promotion)?;
+        let board = self.game.board();
+        if board.checkmate() {
+            return Ok(DrillFeedback::Mated);
+        }
+        let plies = match tablebase::probe(&board) {
+            Some(Probe::Win(plies)) => plies,
+            _ => return Ok(DrillFeedback::WinLost),
+        };
+        let plies_left = FIFTY_MOVES as i32 - board.rule_50() as i32;
+        if plies as i32 > plies_left {
+            return Ok(DrillFeedback::TooSlow {
+                mate_in: mate_in(plies - 1),
+                moves_left: (plies_left / 2).max(0) as u8,
+            });
+        }
+
+        let optimal = plies + 1 == self.plies_to_mate;
+        let defense = tablebase::best_move(&board)
+            .ok_or_else(|| anyhow!("The defending side has no move"))?;
+        self.game.move_piece_uci(&defense.stringify())?;
+        self.plies_to_mate = plies - 1;
+        Ok(DrillFeedback::OnTrack {
+            mate_in: self.mate_in(),
+            optimal,
+        })
+    }
+
+    /// Take back the last move of the player (and the defense to it)
+    pub fn undo(&mut self) -> Result<()> {
+        let plies = if self.game.turn() == self.player {
+            2
+        } else {
+            1
+        };
+        self.game.undo(plies)?;
+        if let Some(Probe::Win(plies)) = tablebase::probe(&self.game.board()) {
+            self.plies_to_mate = plies;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn only_won_positions_are_drills() {
+        assert!(Drill::new("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").is_ok());
+        assert!(Drill::new("8/8/8/4k3/8/8/8/R3K3 b - - 0 1").is_err());
+        assert!(Drill::new("8/8/8/4k3/8/8/8/2B1KB2 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn mate_is_detected() {
+        let mut drill = Drill::new("k7/8/1K6/8/8/8/8/7R w - - 0 1").unwrap();
+        assert_eq!(drill.mate_in(), 1);
+        let feedback = drill.play(square("H1"), square("H8"), None).unwrap();
+        assert_eq!(feedback, DrillFeedback::Mated);
+    }
+
+    #[test]
+    fn hanging_the_rook_loses_the_win() {
+        let mut drill = Drill::new("8/8/8/4k3/8/8/8/3RK3 w - - 0 1").unwrap();
+        let feedback = drill.play(square("D1"), square("D4"), None).unwrap();
+        assert_eq!(feedback, DrillFeedback::WinLost);
+        assert!(feedback.is_failed());
+
+        drill.undo().unwrap();
+        assert_eq!(drill.game().turn(), Player::White);
+        let feedback = drill.play(square("D1"), square("D2"), None).unwrap();
+        assert!(!feedback.is_failed());
+        assert_eq!(drill.game().turn(), Player::White);
+
+        drill.undo().unwrap();
+        assert_eq!(drill.game().fen(), "8/8/8/4k3/8/8/8/3RK3 w - - 0 1");
+    }
+
+    #[test]
+    fn the_fifty_move_rule_counts() {
+        let mut drill = Drill::new("8/8/8/4k3/8/8/8/R3K3 w - - 90 50").unwrap();
+        assert_eq!(drill.moves_left(), 5);
+        let feedback = drill.play(square("A1"), square("A4"), None).unwrap();
+        assert!(matches!(feedback, DrillFeedback::TooSlow { .. }));
+    }
+}
@@ -0,0 +1,193 @@
+//! Well-known endgame positions to practice against the bot.
+//! The player is always white, the bot black.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    BasicMates,
+    PawnEndings,
+    RookEndings,
+    MinorPieces,
+    QueenEndings,
+}
+
+impl Theme {
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::BasicMates => "Basic mates",
+            Theme::PawnEndings => "Pawn endings",
+            Theme::RookEndings => "Rook endings",
+            Theme::MinorPieces => "Minor pieces",
+            Theme::QueenEndings => "Queen endings",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn label(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+}
+
+/// What white should get out of the position with best play
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Goal {
+    Win,
+    Draw,
+}
+
+impl Goal {
+    pub fn label(self) -> &'static str {
+        match self {
+            Goal::Win => "Win",
+            Goal::Draw => "Draw",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Endgame {
+    pub name: &'static str,
+    pub theme: Theme,
+    pub difficulty: Difficulty,
+    pub goal: Goal,
+    pub fen: &'static str,
+}
+
+/// Grouped by theme and the easier ones first
+pub const ENDGAMES: &[Endgame] = &[
+    Endgame {
+        name: "Queen vs king",
+        theme: Theme::BasicMates,
+        difficulty: Difficulty::Easy,
+        goal: Goal::Win,
+        fen: "8/8/8/4k3/8/8/8/3QK3 w - - 0 1",
+    },
+    Endgame {
+        name: "Rook vs king",
+        theme: Theme::BasicMates,
+        difficulty: Difficulty::Easy,
+        goal: Goal::Win,
+        fen: "8/8/8/4k3/8/8/8/R3K3 w - - 0 1",
+    },
+    Endgame {
+        name: "Two bishops vs king",
+        theme: Theme::BasicMates,
+        difficulty: Difficulty::Medium,
+        goal: Goal::Win,
+        fen: "8/8/8/4k3/8/8/8/2B1KB2 w - - 0 1",
+    },
+    Endgame {
+        name: "Bishop and knight vs king",
+        theme: Theme::BasicMates,
+        difficulty: Difficulty::Hard,
+        goal: Goal::Win,
+        fen: "8/8/8/4k3/8/8/8/2B1KN2 w - - 0 1",
+    },
+    Endgame {
+        name: "King and pawn vs king",
+        theme: Theme::PawnEndings,
+        difficulty: Difficulty::Easy,
+        goal: Goal::Win,
+        fen: "3k4/8/3K4/3P4/8/8/8/8 w - - 0 1",
+    },
+    Endgame {
+        name: "Rule of the square",
+        theme: Theme::PawnEndings,
+        difficulty: Difficulty::Easy,
+        goal: Goal::Draw,
+        fen: "7k/8/8/p7/5K2/8/8/8 w - - 0 1",
+    },
+    Endgame {
+        name: "Philidor position",
+        theme: Theme::RookEndings,
+        difficulty: Difficulty::Medium,
+        goal: Goal::Draw,
+        fen: "8/8/8/8/3kp3/R7/7r/4K3 b - - 0 1",
+    },
+    Endgame {
+        name: "Lucena position",
+        theme: Theme::RookEndings,
+        difficulty: Difficulty::Medium,
+        goal: Goal::Win,
+        fen: "1K6/1P1k4/8/8/8/8/r7/2R5 w - - 0 1",
+    },
+    Endgame {
+        name: "Rook vs bishop",
+        theme: Theme::MinorPieces,
+        difficulty: Difficulty::Medium,
+        goal: Goal::Draw,
+        fen: "8/8/8/8/8/2k5/7r/KB6 b - - 0 1",
+    },
+    Endgame {
+        name: "Queen vs pawn on the seventh",
+        theme: Theme::QueenEndings,
+        difficulty: Difficulty::Medium,
+        goal: Goal::Win,
+        fen: "8/1K6/8/8/8/8/3pk3/7Q w - - 0 1",
+    },
+    Endgame {
+        name: "Queen vs rook",
+        theme: Theme::QueenEndings,
+        difficulty: Difficulty::Hard,
+        goal: Goal::Win,
+        fen: "8/8/7r/4k3/8/8/8/3QK3 w - - 0 1",
+    },
+];
+
+/// The endgames of every theme in the order of `ENDGAMES`
+pub fn by_theme() -> Vec<(Theme, Vec<&'static Endgame>)> {
+    let mut themes: Vec<(Theme, Vec<&'static Endgame>)> = vec![];
+    for endgame in ENDGAMES {
+        match themes.iter_mut().find(|(theme, _)| *theme == endgame.theme) {
+            Some((_, endgames)) => endgames.push(endgame),
+            None => themes.push((endgame.theme, vec![endgame])),
+        }
+    }
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ChessGame;
+    use crate::setup::PositionSetup;
+
+    #[test]
+    fn all_positions_are_legal() {
+        for endgame in ENDGAMES {
+            let setup = PositionSetup::from_fen(endgame.fen).unwrap();
+            assert!(setup.validate().is_ok(), "{}", endgame.name);
+            assert!(ChessGame::from_fen(endgame.fen).is_ok(), "{}", endgame.name);
+        }
+    }
+
+    #[test]
+    fn groups_by_theme() {
+        let themes = by_theme();
+        assert_eq!(themes[0].0, Theme::BasicMates);
+        assert_eq!(themes[0].1.len(), 4);
+        assert_eq!(
+            themes
+                .iter()
+                .map(|(_, endgames)| endgames.len())
+                .sum::<usize>(),
+            ENDGAMES.len()
+        );
+        for (_, endgames) in themes {
+            assert!(endgames
+                .windows(2)
+                .all(|pair| pair[0].difficulty <= pair[1].difficulty));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::notation::parse_san;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::notation::san;
+use anyhow::{Context, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use pleco::tools::Searcher;
+use pleco::{BitMove, Board};
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::SystemTime;
+
+/// A single position of an EPD test suite (e.g. "Win at Chess")
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpdPosition {
+    /// Value of the "id" opcode
+    pub id: Option<String>,
+    /// The position with the move counters missing in EPD added
+    pub fen: String,
+    /// Moves in SAN of the "bm" opcode. Any of them solves the position.
+    pub best_moves: Vec<String>,
+    /// Moves in SAN of the "am" opcode. None of them may be played.
+    pub avoid_moves: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EpdResult {
+    pub id: Option<String>,
+    /// Move the engine chose in SAN
+    pub played_move: String,
+    pub solved: bool,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EpdReport {
+    pub results: Vec<EpdResult>,
+}
+
+impl EpdPosition {
+    /// Parse a line like `<4 FEN fields> bm Qg6; id "WAC.003";`
+    pub fn parse(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.trim().splitn(5, char::is_whitespace).collect();
+        ensure!(fields.len() >= 4, "Expected at least 4 FEN fields");
+        let mut position = EpdPosition {
+            id: None,
+            fen: format!("{} 0 1", fields[..4].join(" ")),
+            best_moves: vec![],
+            avoid_moves: vec![],
+        };
+        Board::from_fen(&position.fen)
+            .map_err(|e| anyhow!("Invalid position \"{}\": {:?}", position.fen, e))?;
+
+        for operation in fields.get(4).unwrap_or(&"").split(';') {
+            let mut operation = operation.trim().splitn(2, char::is_whitespace);
+            let opcode = operation.next().unwrap_or_default();
+            let operands = operation.next().unwrap_or_default().trim();
+            match opcode {
+                "id" => position.id = Some(operands.trim_matches('"').to_owned()),
+                "bm" => {
+                    position.best_moves = operands.split_whitespace().map(str::to_owned).collect()
+                }
+                "am" => {
+                    position.avoid_moves = operands.split_whitespace().map(str::to_owned).collect()
+                }
+                _ => {} // Other opcodes are irrelevant for testing the engine
+            }
+        }
+        ensure!(
+            !position.best_moves.is_empty() || !position.avoid_moves.is_empty(),
+            "Position has neither a \"bm\" nor an \"am\" opcode"
+        );
+        Ok(position)
+    }
+
+    /// Whether the engine playing `bit_move` solves this position
+    pub fn is_solved_by(&self, bit_move: BitMove) -> Result<bool> {
+        let board = self.board()?;
+        let matches_any = |moves: &[String]| -> Result<bool> {
+            for san in moves {
+                if parse_san(&board, san)? == bit_move {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        };
+        Ok(
+            (self.best_moves.is_empty() || matches_any(&self.best_moves)?)
+                && !matches_any(&self.avoid_moves)?,
+        )
+    }
+
+    fn board(&self) -> Result<Board> {
+        Board::from_fen(&self.fen).map_err(|e| anyhow!("Invalid position: {:?}", e))
+    }
+}
+
+impl EpdReport {
+    pub fn solved(&self) -> usize {
+        self.results.iter().filter(|result| result.solved).count()
+    }
+
+    pub fn total_duration(&self) -> Duration {
+        self.results.iter().map(|result| result.duration).sum()
+    }
+
+    /// E.g. "Solved 280/300 (93%) in 120.5s"
+    pub fn summary(&self) -> String {
+        let total = self.results.len();
+        format!(
+            "Solved {}/{} ({}%) in {:.1}s",
+            self.solved(),
+            total,
+            if total == 0 {
+                0
+            } else {
+                self.solved() * 100 / total
+            },
+            self.total_duration().as_secs_f32()
+        )
+    }
+}
+
+/// Parse all positions of an EPD file. Empty lines and lines
+/// starting with "#" are skipped.
+pub fn parse_suite(contents: &str) -> Result<Vec<EpdPosition>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| EpdPosition::parse(line).with_context(|| format!("Line {}", i + 1)))
+        .collect()
+}
+
+/// Not on wasm, which has no clock to time the search
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_position<T: Searcher>(position: &EpdPosition, depth: u16) -> Result<EpdResult> {
+    let board = position.board()?;
+    let started = SystemTime::now();
+    let bit_move = T::best_move(board.shallow_clone(), depth);
+    let duration = started.elapsed().unwrap_or_default();
+    ensure!(!bit_move.is_null(), "Engine found no move");
+    Ok(EpdResult {
+        id: position.id.clone(),
+        played_move: san(&board, bit_move),
+        solved: position.is_solved_by(bit_move)?,
+        duration,
+    })
+}
+
+/// Let the engine search every position with a fixed depth
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_suite<T: Searcher>(positions: &[EpdPosition], depth: u16) -> Result<EpdReport> {
+    let mut report = EpdReport::default();
+    for position in positions {
+        let result = run_position::<T>(position, depth)
+            .with_context(|| format!("Position {}", position.id.as_deref().unwrap_or("?")))?;
+        debug!(
+            "{}: played {} ({})",
+            result.id.as_deref().unwrap_or("?"),
+            result.played_move,
+            if result.solved { "solved" } else { "failed" }
+        );
+        report.results.push(result);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pleco::bot_prelude::AlphaBetaSearcher;
+
+    const SUITE: &str = r#"
+# Mate in one
+6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id "mate.001";
+6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - am Ra8; id "avoid.001";
+"#;
+
+    #[test]
+    fn parses_operations() {
+        let positions = parse_suite(SUITE).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].id.as_deref(), Some("mate.001"));
+        assert_eq!(positions[0].fen, "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        assert_eq!(positions[0].best_moves, vec!["Ra8#".to_owned()]);
+        assert_eq!(positions[1].avoid_moves, vec!["Ra8".to_owned()]);
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(EpdPosition::parse("8/8/8 w - - bm e4;").is_err());
+        assert!(EpdPosition::parse("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - id \"x\";").is_err());
+    }
+
+    #[test]
+    fn scores_engine() {
+        let positions = parse_suite(SUITE).unwrap();
+        let report = run_suite::<AlphaBetaSearcher>(&positions, 2).unwrap();
+        assert_eq!(report.results[0].played_move, "Ra8#");
+        assert!(report.results[0].solved);
+        assert!(!report.results[1].solved);
+        assert_eq!(report.solved(), 1);
+    }
+}
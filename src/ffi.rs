@@ -0,0 +1,241 @@
+//! C bindings of `ChessGame` (see `include/chessmarkable.h`).
+//!
+//! Games are opaque pointers that have to be freed with
+//! `chessmarkable_game_free()`. Returned strings are owned by the caller
+//! and freed with `chessmarkable_string_free()`. Squares and moves are
+//! given in UCI notation (e.g. "e2e4" or "e7e8q").
+//!
+//! # Safety
+//!
+//! All functions take `NULL` or pointers they returned and that weren't
+//! freed yet. Strings passed in have to end with a NUL byte.
+//!
+//! # Errors
+//!
+//! No panic unwinds into C. Functions that panic anyway answer like they
+//! would for invalid input: `NULL` instead of a pointer and -1 instead of
+//! a number.
+#![allow(clippy::missing_safety_doc)] // Documented once above
+
+use crate::game::{ChessGame, ChessOutcome, VariantKind};
+use crate::Player;
+use pleco::bot_prelude::AlphaBetaSearcher;
+use pleco::tools::Searcher;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Highest search depth of `chessmarkable_game_bot_move()`
+const MAX_BOT_DEPTH: u16 = 8;
+
+/// Runs `body` and returns `on_panic` if it panics, since unwinding
+/// into C is undefined behaviour
+fn catch_panic<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(on_panic)
+}
+
+fn to_c_string(string: String) -> *mut c_char {
+    CString::new(string)
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+unsafe fn from_c_string<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        None
+    } else {
+        CStr::from_ptr(string).to_str().ok()
+    }
+}
+
+/// A new game from `fen` or the starting position if it's `NULL`.
+/// `NULL` if the FEN is invalid or the position impossible (e.g. without
+/// kings).
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_new(fen: *const c_char) -> *mut ChessGame {
+    catch_panic(ptr::null_mut(), || {
+        let game = if fen.is_null() {
+            Ok(ChessGame::default())
+        } else {
+            match from_c_string(fen) {
+                Some(fen) => ChessGame::from_untrusted_fen(fen, VariantKind::Standard),
+                None => return ptr::null_mut(),
+            }
+        };
+        match game {
+            Ok(game) => Box::into_raw(Box::new(game)),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_free(game: *mut ChessGame) {
+    catch_panic((), || {
+        if !game.is_null() {
+            drop(Box::from_raw(game));
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_string_free(string: *mut c_char) {
+    catch_panic((), || {
+        if !string.is_null() {
+            drop(CString::from_raw(string));
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_fen(game: *const ChessGame) -> *mut c_char {
+    catch_panic(ptr::null_mut(), || match game.as_ref() {
+        Some(game) => to_c_string(game.fen()),
+        None => ptr::null_mut(),
+    })
+}
+
+/// 0 for white and 1 for black
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_turn(game: *const ChessGame) -> c_int {
+    catch_panic(-1, || match game.as_ref() {
+        Some(game) => game.turn() as c_int,
+        None => -1,
+    })
+}
+
+/// The legal moves separated by spaces (e.g. "e2e4 g1f3")
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_possible_moves(game: *const ChessGame) -> *mut c_char {
+    catch_panic(ptr::null_mut(), || match game.as_ref() {
+        Some(game) => to_c_string(
+            game.possible_moves()
+                .iter()
+                .map(|bit_move| bit_move.stringify())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        None => ptr::null_mut(),
+    })
+}
+
+/// 0 if the move was played and -1 if it isn't legal
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_move(
+    game: *mut ChessGame,
+    uci: *const c_char,
+) -> c_int {
+    catch_panic(-1, || match (game.as_mut(), from_c_string(uci)) {
+        (Some(game), Some(uci)) => match game.move_piece_uci(uci) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        },
+        _ => -1,
+    })
+}
+
+/// Let the bot play the next move with the given search depth (capped
+/// to 8). 0 if a move was played and -1 if the game is over.
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_bot_move(game: *mut ChessGame, depth: u16) -> c_int {
+    catch_panic(-1, || {
+        let game = match game.as_mut() {
+            Some(game) if game.outcome().is_none() => game,
+            _ => return -1,
+        };
+        let bit_move = AlphaBetaSearcher::best_move(game.board(), depth.max(1).min(MAX_BOT_DEPTH));
+        if bit_move.is_null() || game.move_piece_uci(&bit_move.stringify()).is_err() {
+            return -1;
+        }
+        0
+    })
+}
+
+/// `CHESSMARKABLE_RUNNING` (-1) while the game goes on, one of the other
+/// `CHESSMARKABLE_*` outcomes otherwise
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_outcome(game: *const ChessGame) -> c_int {
+    catch_panic(-1, || match game.as_ref().and_then(|game| game.outcome()) {
+        None => -1,
+        Some(ChessOutcome::Checkmate { .. }) => 0,
+        Some(ChessOutcome::Resignation { .. }) => 1,
+        Some(ChessOutcome::Timeout { .. }) => 2,
+        Some(ChessOutcome::Stalemate) => 3,
+        Some(ChessOutcome::Repetition) => 4,
+        Some(ChessOutcome::FiftyMoves) => 5,
+        Some(ChessOutcome::InsufficientMaterial) => 6,
+        Some(ChessOutcome::Agreement) => 7,
+        Some(ChessOutcome::Aborted { .. }) => 8,
+    })
+}
+
+/// 0 for white, 1 for black and -1 if nobody won (yet)
+#[no_mangle]
+pub unsafe extern "C" fn chessmarkable_game_winner(game: *const ChessGame) -> c_int {
+    catch_panic(-1, || {
+        match game
+            .as_ref()
+            .and_then(|game| game.outcome())
+            .and_then(|outcome| outcome.winner())
+        {
+            Some(Player::White) => 0,
+            Some(Player::Black) => 1,
+            None => -1,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take_string(string: *mut c_char) -> String {
+        let owned = unsafe { CStr::from_ptr(string) }
+            .to_str()
+            .unwrap()
+            .to_owned();
+        unsafe { chessmarkable_string_free(string) };
+        owned
+    }
+
+    #[test]
+    fn plays_a_game_through_the_c_functions() {
+        unsafe {
+            let game = chessmarkable_game_new(ptr::null());
+            assert!(!game.is_null());
+            assert_eq!(chessmarkable_game_turn(game), 0);
+            let possible_moves = take_string(chessmarkable_game_possible_moves(game));
+            assert_eq!(possible_moves.split(' ').count(), 20);
+
+            // Fool's mate
+            for uci in &["f2f3\0", "e7e5\0", "g2g4\0"] {
+                assert_eq!(
+                    chessmarkable_game_move(game, uci.as_ptr() as *const c_char),
+                    0
+                );
+            }
+            assert_eq!(
+                chessmarkable_game_move(game, "e1e2\0".as_ptr() as *const c_char),
+                -1
+            );
+            assert_eq!(chessmarkable_game_outcome(game), -1);
+            assert_eq!(chessmarkable_game_bot_move(game, 2), 0);
+            assert_eq!(chessmarkable_game_outcome(game), 0);
+            assert_eq!(chessmarkable_game_winner(game), 1);
+            assert_eq!(chessmarkable_game_bot_move(game, 2), -1);
+            assert_eq!(
+                take_string(chessmarkable_game_fen(game)),
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+            );
+            chessmarkable_game_free(game);
+
+            assert!(chessmarkable_game_new("not a fen\0".as_ptr() as *const c_char).is_null());
+            // Would panic in pleco
+            assert!(
+                chessmarkable_game_new("8/8/8/8/8/8/8/8 w - - 0 1\0".as_ptr() as *const c_char)
+                    .is_null()
+            );
+        }
+    }
+}
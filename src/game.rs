@@ -1,13 +1,453 @@
+use crate::crazyhouse::{Crazyhouse, DropPiece, Pockets};
+use crate::setup::PositionSetup;
 pub use crate::{Player, Square};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chess_pgn_parser::{Game as PgnGame, Move as PgnMove, Piece as PgnPiece};
 pub use pleco::{BitMove, Board, File, Piece, PieceType, Player as PlecoPlayer, Rank, SQ};
 use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ChessOutcome {
-    Checkmate { winner: Player },
+    Checkmate {
+        winner: Player,
+    },
+    /// The other player resigned
+    Resignation {
+        winner: Player,
+    },
+    Timeout {
+        winner: Player,
+    },
     Stalemate,
-    Aborted { who: Option<Player> },
+    /// The same position occurred for the third time
+    Repetition,
+    /// No capture or pawn move in the last 50 moves
+    FiftyMoves,
+    /// Neither player can checkmate anymore
+    InsufficientMaterial,
+    /// Both players offered a draw
+    Agreement,
+    /// A player left the game (which may be continued later)
+    Aborted {
+        who: Option<Player>,
+    },
+}
+
+impl ChessOutcome {
+    /// `None` for draws and aborted games
+    pub fn winner(self) -> Option<Player> {
+        match self {
+            ChessOutcome::Checkmate { winner }
+            | ChessOutcome::Resignation { winner }
+            | ChessOutcome::Timeout { winner } => Some(winner),
+            _ => None,
+        }
+    }
+
+    pub fn is_draw(self) -> bool {
+        matches!(
+            self,
+            ChessOutcome::Stalemate
+                | ChessOutcome::Repetition
+                | ChessOutcome::FiftyMoves
+                | ChessOutcome::InsufficientMaterial
+                | ChessOutcome::Agreement
+        )
+    }
+
+    /// Value of the PGN "Result" tag
+    pub fn pgn_result(self) -> &'static str {
+        match self.winner() {
+            Some(Player::White) => "1-0",
+            Some(Player::Black) => "0-1",
+            None if self.is_draw() => "1/2-1/2",
+            None => "*",
+        }
+    }
+
+    /// Value of the PGN "Termination" tag
+    pub fn pgn_termination(self) -> &'static str {
+        match self {
+            ChessOutcome::Timeout { .. } => "time forfeit",
+            ChessOutcome::Aborted { .. } => "abandoned",
+            _ => "normal",
+        }
+    }
+
+    /// E.g. "White is checkmated!"
+    pub fn description(self) -> String {
+        match self {
+            ChessOutcome::Checkmate { winner } => {
+                format!("{} is checkmated!", winner.other_player())
+            }
+            ChessOutcome::Resignation { winner } => {
+                format!("{} resigned!", winner.other_player())
+            }
+            ChessOutcome::Timeout { winner } => format!("{} lost on time!", winner.other_player()),
+            ChessOutcome::Stalemate => "Stalemate!".to_owned(),
+            ChessOutcome::Repetition => "Draw by threefold repetition!".to_owned(),
+            ChessOutcome::FiftyMoves => "Draw by the 50-move rule!".to_owned(),
+            ChessOutcome::InsufficientMaterial => "Draw by insufficient material!".to_owned(),
+            ChessOutcome::Agreement => "Draw by agreement!".to_owned(),
+            ChessOutcome::Aborted { who: Some(who) } => format!("{} left the game.", who),
+            ChessOutcome::Aborted { who: None } => "The game was aborted.".to_owned(),
+        }
+    }
+}
+
+/// Pieces of one side apart from the king
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceCounts {
+    pub pawns: u8,
+    pub knights: u8,
+    pub bishops: u8,
+    pub rooks: u8,
+    pub queens: u8,
+}
+
+impl PieceCounts {
+    /// In centipawns
+    pub fn value(&self) -> i32 {
+        self.pawns as i32 * 100
+            + (self.knights + self.bishops) as i32 * 300
+            + self.rooks as i32 * 500
+            + self.queens as i32 * 900
+    }
+
+    pub fn minor_pieces(&self) -> u8 {
+        self.knights + self.bishops
+    }
+
+    /// Pieces that were taken off the board compared to the starting position.
+    /// Promoted pawns count as missing pawns and not as captured.
+    pub fn missing(&self) -> PieceCounts {
+        let start = PieceCounts {
+            pawns: 8,
+            knights: 2,
+            bishops: 2,
+            rooks: 2,
+            queens: 1,
+        };
+        PieceCounts {
+            pawns: start.pawns.saturating_sub(self.pawns),
+            knights: start.knights.saturating_sub(self.knights),
+            bishops: start.bishops.saturating_sub(self.bishops),
+            rooks: start.rooks.saturating_sub(self.rooks),
+            queens: start.queens.saturating_sub(self.queens),
+        }
+    }
+}
+
+/// The pieces on the board of both sides. Pieces in crazyhouse pockets
+/// aren't included.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Material {
+    pub white: PieceCounts,
+    pub black: PieceCounts,
+    /// Bishops of both sides on dark and light squares
+    bishop_square_colors: [u8; 2],
+}
+
+impl Material {
+    pub fn of(board: &Board) -> Self {
+        let mut material = Material::default();
+        for (sq, piece) in board.get_piece_locations() {
+            let counts = match Player::from(piece.player_lossy()) {
+                Player::White => &mut material.white,
+                Player::Black => &mut material.black,
+            };
+            match piece.type_of() {
+                PieceType::P => counts.pawns += 1,
+                PieceType::N => counts.knights += 1,
+                PieceType::B => {
+                    counts.bishops += 1;
+                    material.bishop_square_colors[((sq.0 % 8 + sq.0 / 8) % 2) as usize] += 1;
+                }
+                PieceType::R => counts.rooks += 1,
+                PieceType::Q => counts.queens += 1,
+                _ => {}
+            }
+        }
+        material
+    }
+
+    pub fn of_player(&self, player: Player) -> &PieceCounts {
+        match player {
+            Player::White => &self.white,
+            Player::Black => &self.black,
+        }
+    }
+
+    /// In centipawns, positive if white is ahead
+    pub fn balance(&self) -> i32 {
+        self.white.value() - self.black.value()
+    }
+
+    /// Only kings and at most one knight or bishop, or only bishops that are
+    /// all on squares of the same color are left
+    pub fn is_insufficient(&self) -> bool {
+        let (white, black) = (&self.white, &self.black);
+        if white.pawns + white.rooks + white.queens + black.pawns + black.rooks + black.queens > 0 {
+            return false;
+        }
+        let knights = white.knights + black.knights;
+        knights + white.bishops + black.bishops <= 1
+            || (knights == 0
+                && (self.bishop_square_colors[0] == 0 || self.bishop_square_colors[1] == 0))
+    }
+}
+
+/// Identifies the rules a game is played with.
+/// Serializable counterpart of `Variant` used in configs and the protocol.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum VariantKind {
+    #[default]
+    Standard,
+    Crazyhouse,
+}
+
+impl VariantKind {
+    pub fn rules(self) -> Box<dyn Variant> {
+        match self {
+            VariantKind::Standard => Box::new(Standard),
+            VariantKind::Crazyhouse => Box::<Crazyhouse>::default(),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VariantKind::Standard => "Standard",
+            VariantKind::Crazyhouse => "Crazyhouse",
+        }
+    }
+}
+
+/// A legal move and what it does besides moving the piece
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PossibleMove {
+    pub source: Square,
+    pub destination: Square,
+    /// Takes a piece (also en passant)
+    pub capture: bool,
+    /// A piece to promote to has to be chosen
+    pub promotion: bool,
+    pub castle: bool,
+    pub en_passant: bool,
+}
+
+impl From<BitMove> for PossibleMove {
+    fn from(bit_move: BitMove) -> Self {
+        Self {
+            source: bit_move.get_src().into(),
+            destination: bit_move.get_dest().into(),
+            capture: bit_move.is_capture(),
+            promotion: bit_move.is_promo(),
+            castle: bit_move.is_castle(),
+            en_passant: bit_move.is_en_passant(),
+        }
+    }
+}
+
+impl PossibleMove {
+    /// The promotions to the different pieces become a single move
+    pub fn from_bit_moves(bit_moves: &[BitMove]) -> Vec<PossibleMove> {
+        let mut possible_moves: Vec<PossibleMove> = bit_moves
+            .iter()
+            .map(|bit_move| (*bit_move).into())
+            .collect();
+        possible_moves.dedup();
+        possible_moves
+    }
+}
+
+/// Piece a pawn turns into on the last rank
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PromotionPiece {
+    #[default]
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+}
+
+pub const ALL_PROMOTION_PIECES: &[PromotionPiece] = &[
+    PromotionPiece::Queen,
+    PromotionPiece::Rook,
+    PromotionPiece::Bishop,
+    PromotionPiece::Knight,
+];
+
+impl PromotionPiece {
+    pub fn from_piece_type(piece_type: PieceType) -> Option<Self> {
+        match piece_type {
+            PieceType::Q => Some(PromotionPiece::Queen),
+            PieceType::R => Some(PromotionPiece::Rook),
+            PieceType::B => Some(PromotionPiece::Bishop),
+            PieceType::N => Some(PromotionPiece::Knight),
+            _ => None,
+        }
+    }
+
+    pub fn piece_type(self) -> PieceType {
+        match self {
+            PromotionPiece::Queen => PieceType::Q,
+            PromotionPiece::Rook => PieceType::R,
+            PromotionPiece::Bishop => PieceType::B,
+            PromotionPiece::Knight => PieceType::N,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PromotionPiece::Queen => "Queen",
+            PromotionPiece::Rook => "Rook",
+            PromotionPiece::Bishop => "Bishop",
+            PromotionPiece::Knight => "Knight",
+        }
+    }
+}
+
+/// Material the bot (playing black) gives up so
+/// weaker players can have a competitive game.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Odds {
+    #[default]
+    None,
+    Pawn,
+    Knight,
+    Rook,
+    Queen,
+}
+
+impl Odds {
+    pub fn label(self) -> &'static str {
+        match self {
+            Odds::None => "None",
+            Odds::Pawn => "Pawn (f7)",
+            Odds::Knight => "Knight (b8)",
+            Odds::Rook => "Rook (a8)",
+            Odds::Queen => "Queen (d8)",
+        }
+    }
+
+    /// The next bigger handicap (wraps around to none)
+    pub fn next(self) -> Self {
+        match self {
+            Odds::None => Odds::Pawn,
+            Odds::Pawn => Odds::Knight,
+            Odds::Knight => Odds::Rook,
+            Odds::Rook => Odds::Queen,
+            Odds::Queen => Odds::None,
+        }
+    }
+
+    /// Starting position with the piece of black removed
+    pub fn starting_fen(self) -> String {
+        let (removed, castling) = match self {
+            Odds::None => return Board::default().fen(),
+            Odds::Pawn => (SQ::F7, "KQkq"),
+            Odds::Knight => (SQ::B8, "KQkq"),
+            Odds::Rook => (SQ::A8, "KQk"),
+            Odds::Queen => (SQ::D8, "KQkq"),
+        };
+        let board = Board::default();
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let sq = SQ(rank * 8 + file);
+                match board.piece_at_sq(sq).character() {
+                    Some(c) if sq != removed => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(c);
+                    }
+                    _ => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+        format!("{} w {} - 0 1", placement, castling)
+    }
+}
+
+/// Rules of a chess variant played on top of plecos board.
+/// Every hook defaults to standard chess so a variant
+/// only needs to override the rules it changes.
+pub trait Variant: Send + Sync {
+    fn kind(&self) -> VariantKind;
+
+    /// Position the game starts in (unless a FEN was given)
+    fn starting_fen(&self) -> String {
+        Board::default().fen()
+    }
+
+    /// Prepare for a game starting at `fen`. Variants may store extra
+    /// state in the FEN and return the part that pleco understands.
+    fn setup(&mut self, fen: &str) -> Result<String> {
+        Ok(fen.to_owned())
+    }
+
+    /// Every move the player whose turn it is may play
+    fn legal_moves(&self, board: &Board) -> Vec<BitMove> {
+        board.generate_moves().iter().copied().collect()
+    }
+
+    /// How the game ended in this position or `None` if it's still running
+    fn outcome(&self, board: &Board) -> Option<ChessOutcome> {
+        if board.checkmate() {
+            Some(ChessOutcome::Checkmate {
+                winner: Player::from(board.turn()).other_player(),
+            })
+        } else if is_stalemate(board) {
+            Some(ChessOutcome::Stalemate)
+        } else if Material::of(board).is_insufficient() {
+            Some(ChessOutcome::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+
+    /// Called after `bit_move` was played on `board_before`
+    fn after_move(&mut self, _board_before: &Board, _bit_move: BitMove) {}
+
+    /// Called after the last `count` moves were taken back
+    fn after_undo(&mut self, _count: u16) {}
+
+    /// Pieces in hand or `None` if the variant has no drops
+    fn pockets(&self) -> Option<&Pockets> {
+        None
+    }
+
+    /// Pieces of the player to move that can be dropped and where to
+    fn possible_drops(&self, _board: &Board) -> Vec<(DropPiece, Square)> {
+        vec![]
+    }
+
+    /// Board after the player to move dropped `piece` onto `destination`
+    fn drop_piece(
+        &mut self,
+        _board: &Board,
+        _piece: DropPiece,
+        _destination: Square,
+    ) -> Result<Board> {
+        bail!("Pieces can't be dropped in this variant")
+    }
+}
+
+pub struct Standard;
+
+impl Variant for Standard {
+    fn kind(&self) -> VariantKind {
+        VariantKind::Standard
+    }
 }
 
 /// Wrapper around plecos board.
@@ -18,21 +458,43 @@ pub struct ChessGame {
     board: pleco::Board,
     board_moves_played_offset: u16,
     outcome: Option<ChessOutcome>,
+    variant: Box<dyn Variant>,
+    /// Zobrist hashes of the positions since the moves can be undone
+    /// (the current one last) to find repetitions
+    position_hashes: Vec<u64>,
+    /// Boards before the drops that can still be undone. A drop builds a
+    /// new board (pleco has no drops), so `undo_move()` can't take it back.
+    boards_before_drops: Vec<Board>,
 }
 
 impl Default for ChessGame {
     fn default() -> Self {
+        let board = Board::default();
         Self {
-            board: Board::default(),
+            position_hashes: vec![board.zobrist()],
+            board,
             board_moves_played_offset: 0,
             outcome: None,
+            variant: Box::new(Standard),
+            boards_before_drops: vec![],
         }
     }
 }
 
 impl ChessGame {
     pub fn from_fen(fen: &str) -> Result<ChessGame> {
-        let board = match Board::from_fen(fen) {
+        Self::from_fen_with_variant(fen, VariantKind::Standard)
+    }
+
+    /// Start from the starting position of the variant
+    pub fn with_variant(variant: VariantKind) -> Result<ChessGame> {
+        Self::from_fen_with_variant(&variant.rules().starting_fen(), variant)
+    }
+
+    pub fn from_fen_with_variant(fen: &str, variant: VariantKind) -> Result<ChessGame> {
+        let mut rules = variant.rules();
+        let fen = rules.setup(fen)?;
+        let board = match Board::from_fen(&fen) {
             Ok(board) => Ok(board),
             Err(e) => Err(anyhow!(
                 "Failed to create game board from FEN. Reason: {:?}",
@@ -41,11 +503,71 @@ impl ChessGame {
         }?;
         Ok(Self {
             board_moves_played_offset: board.moves_played(),
+            position_hashes: vec![board.zobrist()],
             board,
+            variant: rules,
             ..Default::default()
         })
     }
 
+    /// `from_fen_with_variant()` for FENs from other programs. pleco panics on
+    /// some impossible positions (e.g. without kings), so the position is
+    /// checked the same way as in the board editor first.
+    pub fn from_untrusted_fen(fen: &str, variant: VariantKind) -> Result<ChessGame> {
+        let board_fen = variant.rules().setup(fen)?;
+        PositionSetup::from_fen(&board_fen)?.validate()?;
+        // In case the checks above miss something
+        std::panic::catch_unwind(|| Self::from_fen_with_variant(fen, variant))
+            .unwrap_or_else(|_| Err(anyhow!("Failed to create game board from FEN")))
+    }
+
+    /// The first game of a PGN with all of its moves played (so they can
+    /// be undone). It starts at the "FEN" tag if there is one and is
+    /// played as crazyhouse if the "Variant" tag says so.
+    pub fn from_pgn(pgn: &str) -> Result<ChessGame> {
+        let game = chess_pgn_parser::read_games(pgn)
+            .map_err(|err| anyhow!("Failed to parse PGN: {:?}", err))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("The PGN has no games"))?;
+        Self::from_pgn_game(&game)
+    }
+
+    /// Like `from_pgn()` for a game that was already parsed
+    pub fn from_pgn_game(game: &PgnGame) -> Result<ChessGame> {
+        let mut chess_game = Self::pgn_starting_position(game)?;
+        for (ply, game_move) in game.moves.iter().enumerate() {
+            chess_game
+                .play_pgn_move(&game_move.move_.move_)
+                .with_context(|| format!("Move {} can't be played", ply + 1))?;
+        }
+        Ok(chess_game)
+    }
+
+    /// The position a PGN game starts in (before its first move)
+    pub fn pgn_starting_position(game: &PgnGame) -> Result<ChessGame> {
+        let tag = |name: &str| {
+            game.tags
+                .iter()
+                .find(|(tag_name, _)| tag_name == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let variant = match tag("Variant") {
+            Some(variant) if variant.eq_ignore_ascii_case(VariantKind::Crazyhouse.label()) => {
+                VariantKind::Crazyhouse
+            }
+            _ => VariantKind::Standard,
+        };
+        match tag("FEN") {
+            Some(fen) => Self::from_untrusted_fen(fen, variant),
+            None => Self::with_variant(variant),
+        }
+    }
+
+    pub fn variant(&self) -> VariantKind {
+        self.variant.kind()
+    }
+
     pub fn board(&self) -> Board {
         self.board.shallow_clone()
     }
@@ -54,6 +576,15 @@ impl ChessGame {
         self.board.fen()
     }
 
+    /// `fen()` with the pockets of crazyhouse, which pleco can't read, so
+    /// it can be passed back to `from_fen_with_variant()`
+    pub fn fen_with_pockets(&self) -> String {
+        match self.pockets() {
+            Some(pockets) => pockets.add_to_fen(&self.fen()),
+            None => self.fen(),
+        }
+    }
+
     pub fn turn(&self) -> Player {
         self.board.turn().into()
     }
@@ -62,6 +593,31 @@ impl ChessGame {
         self.outcome
     }
 
+    /// Material of both players on the board
+    pub fn material(&self) -> Material {
+        Material::of(&self.board)
+    }
+
+    /// Zobrist hash of the board. Pieces in crazyhouse pockets aren't part of it.
+    pub fn zobrist(&self) -> u64 {
+        self.board.zobrist()
+    }
+
+    /// How often the current position occurred since the moves can be undone,
+    /// including the current one
+    pub fn repetition_count(&self) -> usize {
+        let current = self.board.zobrist();
+        self.position_hashes
+            .iter()
+            .filter(|hash| **hash == current)
+            .count()
+    }
+
+    /// Whether the king of the player to move is attacked
+    pub fn in_check(&self) -> bool {
+        self.board.in_check()
+    }
+
     pub fn total_moves(&self) -> u16 {
         self.board.moves_played()
     }
@@ -70,8 +626,86 @@ impl ChessGame {
         self.total_moves() - self.board_moves_played_offset
     }
 
-    pub fn possible_moves(&self) -> pleco::MoveList {
-        self.board.generate_moves()
+    pub fn possible_moves(&self) -> Vec<BitMove> {
+        self.variant.legal_moves(&self.board)
+    }
+
+    /// The moves of the piece on `square` (none if it's not its turn)
+    pub fn possible_moves_from(&self, square: Square) -> Vec<BitMove> {
+        self.possible_moves()
+            .into_iter()
+            .filter(|bit_move| bit_move.get_src() == *square)
+            .collect()
+    }
+
+    /// Number of move sequences `depth` plies deep from the current position
+    /// (to validate the move generation against known counts and to
+    /// benchmark it). Drops of variants aren't counted.
+    pub fn perft(&self, depth: u16) -> u64 {
+        perft(&*self.variant, &mut self.board.shallow_clone(), depth)
+    }
+
+    /// `perft()` split up by the first move (to find the move whose
+    /// count is off)
+    pub fn perft_divide(&self, depth: u16) -> Vec<(BitMove, u64)> {
+        let mut board = self.board.shallow_clone();
+        self.possible_moves()
+            .into_iter()
+            .map(|bit_move| {
+                board.apply_move(bit_move);
+                let nodes = perft(&*self.variant, &mut board, depth.saturating_sub(1));
+                board.undo_move();
+                (bit_move, nodes)
+            })
+            .collect()
+    }
+
+    /// Standard algebraic notation of a move in the current position
+    /// (`None` if it's not legal)
+    pub fn san(
+        &self,
+        source: Square,
+        destination: Square,
+        promotion: Option<PromotionPiece>,
+    ) -> Option<String> {
+        self.find_move(source, destination, promotion)
+            .map(|bit_move| crate::notation::san(&self.board, bit_move))
+    }
+
+    /// Source and destination of the last move that can still be undone
+    pub fn last_move(&self) -> Option<(Square, Square)> {
+        if self.total_undoable_moves() == 0 {
+            return None;
+        }
+        self.board
+            .last_move()
+            .map(|bit_move| (bit_move.get_src().into(), bit_move.get_dest().into()))
+    }
+
+    pub fn pockets(&self) -> Option<&Pockets> {
+        self.variant.pockets()
+    }
+
+    pub fn possible_drops(&self) -> Vec<(DropPiece, Square)> {
+        if self.outcome.is_some() {
+            return vec![];
+        }
+        self.variant.possible_drops(&self.board)
+    }
+
+    /// Put a piece from the pocket of the player to move onto the board.
+    /// It's undone like a move.
+    pub fn drop_piece(&mut self, piece: DropPiece, destination: Square) -> Result<()> {
+        ensure!(
+            self.outcome.is_none(),
+            "Can't drop a piece since the game has already ended."
+        );
+        let board_after = self.variant.drop_piece(&self.board, piece, destination)?;
+        let board_before = std::mem::replace(&mut self.board, board_after);
+        self.boards_before_drops.push(board_before);
+        self.position_hashes.push(self.board.zobrist());
+        self.update_game_outcome();
+        Ok(())
     }
 
     pub fn player_left(&mut self, player: Player) {
@@ -80,6 +714,30 @@ impl ChessGame {
         }
     }
 
+    /// The flag of `player` fell
+    pub fn time_over(&mut self, player: Player) {
+        if self.outcome.is_none() {
+            self.outcome = Some(ChessOutcome::Timeout {
+                winner: player.other_player(),
+            });
+        }
+    }
+
+    pub fn resign(&mut self, player: Player) {
+        if self.outcome.is_none() {
+            self.outcome = Some(ChessOutcome::Resignation {
+                winner: player.other_player(),
+            });
+        }
+    }
+
+    /// Both players agreed to a draw
+    pub fn agree_draw(&mut self) {
+        if self.outcome.is_none() {
+            self.outcome = Some(ChessOutcome::Agreement);
+        }
+    }
+
     pub fn undo(&mut self, count: u16) -> Result<()> {
         if count > self.board.moves_played() {
             return Err(anyhow!(
@@ -95,12 +753,42 @@ impl ChessGame {
         }
 
         for _ in 0..count {
-            self.board.undo_move();
+            let last_was_drop = self.boards_before_drops.last().map_or(false, |board| {
+                board.moves_played() + 1 == self.board.moves_played()
+            });
+            if last_was_drop {
+                self.board = self.boards_before_drops.pop().unwrap();
+            } else {
+                self.board.undo_move();
+            }
         }
+        let hashes = self
+            .position_hashes
+            .len()
+            .saturating_sub(count as usize)
+            .max(1);
+        self.position_hashes.truncate(hashes);
+        self.variant.after_undo(count);
         self.update_game_outcome();
         Ok(())
     }
 
+    /// The legal move for `source` and `destination`
+    /// (i.e. including promotions or other special data)
+    fn find_move(
+        &self,
+        source: Square,
+        destination: Square,
+        promotion: Option<PromotionPiece>,
+    ) -> Option<BitMove> {
+        let promotion = promotion.unwrap_or_default().piece_type();
+        self.possible_moves().into_iter().find(|legal_move| {
+            legal_move.get_src_u8() == source.0
+                && legal_move.get_dest_u8() == destination.0
+                && (!legal_move.is_promo() || legal_move.promo_piece() == promotion)
+        })
+    }
+
     fn piece_on_square(&self, player: Player, square: Square) -> bool {
         self.board
             .get_occupied_player(player.into())
@@ -108,85 +796,138 @@ impl ChessGame {
             .any(|sq| sq == *square)
     }
 
+    /// Draws by repetition and the 50-move rule. They don't apply to
+    /// variants with drops as the pockets aren't part of the board.
+    fn draw_by_rule(&self) -> Option<ChessOutcome> {
+        if self.variant.pockets().is_some() {
+            None
+        } else if self.repetition_count() >= 3 {
+            Some(ChessOutcome::Repetition)
+        } else if self.board.rule_50() >= 100 {
+            Some(ChessOutcome::FiftyMoves)
+        } else {
+            None
+        }
+    }
+
     fn update_game_outcome(&mut self) {
-        if self.board.checkmate() {
-            self.outcome = Some(ChessOutcome::Checkmate {
-                winner: self.turn().other_player(),
-            });
-        } else if self.board.stalemate() {
-            self.outcome = Some(ChessOutcome::Stalemate);
+        if let Some(outcome) = self
+            .variant
+            .outcome(&self.board)
+            .or_else(|| self.draw_by_rule())
+        {
+            self.outcome = Some(outcome);
         } else if let Some(outcome) = self.outcome {
             match outcome {
-                ChessOutcome::Aborted { .. } => {} // Abort is irreversible
+                // Decided by the players and not the position
+                ChessOutcome::Aborted { .. }
+                | ChessOutcome::Timeout { .. }
+                | ChessOutcome::Resignation { .. }
+                | ChessOutcome::Agreement => {}
                 _ => self.outcome = None,
             };
         }
     }
 
-    pub fn move_piece_by_type(
-        &mut self,
-        piece: Piece,
-        destination: Square,
-        src_col: Option<File>,
-        src_row: Option<Rank>,
-    ) -> Result<(Square, Square)> {
-        ensure!(
-            self.outcome.is_none(),
-            "Can't do move since the game has already ended."
-        );
-        let piece_locations = self.board.get_piece_locations();
-        let mut piece_type_locations = vec![];
-        for loc in piece_locations {
-            if loc.1 == piece {
-                piece_type_locations.push(loc.0 .0)
-            }
-        }
-        // Find a legal move for `source` and `destination`
-        // (i.e. including promotions or other special data)
-        let mut candidate_moves: Vec<BitMove> = Vec::new();
-        for legal_move in self.board.generate_moves().iter() {
-            if piece_type_locations.contains(&legal_move.get_src_u8())
-                && legal_move.get_dest_u8() == destination.0
-            {
-                candidate_moves.push(legal_move.clone());
-            }
-        }
-        if candidate_moves.is_empty() {
-            return Err(anyhow!("Move not found as possibility"));
-        }
-        let mut selected_move: Option<&BitMove> = None;
-        if candidate_moves.len() == 1 {
-            selected_move = candidate_moves.first();
-        }
-        if selected_move.is_none() && src_col.is_some() {
-            selected_move = candidate_moves
-                .iter()
-                .find(|bmove| bmove.src_col() == src_col.unwrap());
-        }
-        if selected_move.is_none() && src_row.is_some() {
-            selected_move = candidate_moves
-                .iter()
-                .find(|bmove| bmove.src_row() == src_row.unwrap());
-        }
-        if selected_move.is_none() {
-            return Err(anyhow!("Move not found as possibility"));
+    /// The legal move written as `played_move` in a PGN. Fails if there is
+    /// none or the move doesn't tell which of several pieces moved.
+    pub fn find_pgn_move(&self, played_move: &PgnMove) -> Result<BitMove> {
+        let candidates: Vec<BitMove> = self
+            .possible_moves()
+            .into_iter()
+            .filter(|bit_move| {
+                let (src, dest) = (bit_move.get_src(), bit_move.get_dest());
+                match played_move {
+                    PgnMove::CastleKingside => {
+                        bit_move.is_castle() && dest.file() as u8 > src.file() as u8
+                    }
+                    PgnMove::CastleQueenside => {
+                        bit_move.is_castle() && (dest.file() as u8) < src.file() as u8
+                    }
+                    PgnMove::BasicMove {
+                        piece,
+                        from,
+                        to,
+                        promoted_to,
+                        ..
+                    } => {
+                        let promotion_matches = match promoted_to {
+                            Some(promoted_to) => {
+                                bit_move.is_promo()
+                                    && bit_move.promo_piece() == pgn_piece_type(*promoted_to)
+                            }
+                            // Pawns become queens if the PGN doesn't say otherwise
+                            None => !bit_move.is_promo() || bit_move.promo_piece() == PieceType::Q,
+                        };
+                        !bit_move.is_castle()
+                            && self.board.piece_at_sq(src).type_of() == pgn_piece_type(*piece)
+                            && Some(dest.file() as u8) == to.file().map(|file| file as u8)
+                            && Some(dest.rank() as u8) == to.rank().map(|rank| rank as u8)
+                            && from
+                                .file()
+                                .map_or(true, |file| src.file() as u8 == file as u8)
+                            && from
+                                .rank()
+                                .map_or(true, |rank| src.rank() as u8 == rank as u8)
+                            && promotion_matches
+                    }
+                }
+            })
+            .collect();
+        match candidates.as_slice() {
+            [bit_move] => Ok(*bit_move),
+            [] => bail!("Move not found as possibility"),
+            _ => bail!("The move is ambiguous"),
         }
-        let selected_move = selected_move.unwrap();
+    }
 
-        self.board.apply_move(selected_move.to_owned());
-        if let Err(e) = self.board.is_okay() {
-            self.undo(1)?;
-            return Err(anyhow!(
-                "Board got into illegal state after move. Reason: \"{:?}\"",
-                e
-            ));
-        }
+    /// Play a move as written in a PGN. Returns its source and destination.
+    pub fn play_pgn_move(&mut self, played_move: &PgnMove) -> Result<(Square, Square)> {
+        let bit_move = self.find_pgn_move(played_move)?;
+        let (source, destination) = (bit_move.get_src().into(), bit_move.get_dest().into());
+        let promotion = if bit_move.is_promo() {
+            PromotionPiece::from_piece_type(bit_move.promo_piece())
+        } else {
+            None
+        };
+        self.move_piece_with_promotion(source, destination, promotion)?;
+        Ok((source, destination))
+    }
 
-        self.update_game_outcome();
-        Ok((Square::from(SQ(selected_move.get_src_u8())), destination))
+    /// Play a move in UCI notation (e.g. "e2e4"). A promotion needs the
+    /// piece as a suffix ("e7e8q", "e7e8n"); without one it isn't a legal move.
+    pub fn move_piece_uci(&mut self, uci: &str) -> Result<()> {
+        let uci = uci.trim().to_lowercase();
+        let bit_move = self
+            .possible_moves()
+            .into_iter()
+            .find(|bit_move| bit_move.stringify() == uci)
+            .ok_or_else(|| anyhow!("\"{}\" is not a legal move", uci))?;
+        let promotion = if bit_move.is_promo() {
+            PromotionPiece::from_piece_type(bit_move.promo_piece())
+        } else {
+            None
+        };
+        self.move_piece_with_promotion(
+            bit_move.get_src().into(),
+            bit_move.get_dest().into(),
+            promotion,
+        )
     }
 
+    /// Move a piece. Pawns reaching the last rank become queens.
     pub fn move_piece(&mut self, source: Square, destination: Square) -> Result<()> {
+        self.move_piece_with_promotion(source, destination, None)
+    }
+
+    /// Move a piece and promote a pawn on the last rank to
+    /// `promotion` (a queen if not given)
+    pub fn move_piece_with_promotion(
+        &mut self,
+        source: Square,
+        destination: Square,
+        promotion: Option<PromotionPiece>,
+    ) -> Result<()> {
         ensure!(
             self.piece_on_square(self.turn(), source),
             "The playing player has no piece on the source square!"
@@ -197,20 +938,14 @@ impl ChessGame {
             "Can't do move since the game has already ended."
         );
 
-        // Find a legal move for `source` and `destination`
-        // (i.e. including promotions or other special data)
-        let mut selected_move: Option<BitMove> = None;
-        for legal_move in self.board.generate_moves().iter() {
-            if legal_move.get_src_u8() == source.0 && legal_move.get_dest_u8() == destination.0 {
-                selected_move = Some(legal_move.clone());
-            }
-        }
-        if selected_move.is_none() {
-            return Err(anyhow!("Move not found as possibility"));
-        }
-        let selected_move = selected_move.unwrap();
+        let selected_move = self
+            .find_move(source, destination, promotion)
+            .ok_or_else(|| anyhow!("Move not found as possibility"))?;
 
+        let board_before = self.board.shallow_clone();
         self.board.apply_move(selected_move);
+        self.position_hashes.push(self.board.zobrist());
+        self.variant.after_move(&board_before, selected_move);
         if let Err(e) = self.board.is_okay() {
             self.undo(1)?;
             return Err(anyhow!(
@@ -223,3 +958,401 @@ impl ChessGame {
         Ok(())
     }
 }
+
+/// The player to move has no legal move but isn't in check. pleco's
+/// `stalemate()` also counts 50 plies without a capture or pawn move.
+pub(crate) fn is_stalemate(board: &Board) -> bool {
+    !board.in_check() && board.generate_moves().is_empty()
+}
+
+fn pgn_piece_type(piece: PgnPiece) -> PieceType {
+    match piece {
+        PgnPiece::Pawn => PieceType::P,
+        PgnPiece::Knight => PieceType::N,
+        PgnPiece::Bishop => PieceType::B,
+        PgnPiece::Rook => PieceType::R,
+        PgnPiece::Queen => PieceType::Q,
+        PgnPiece::King => PieceType::K,
+    }
+}
+
+fn perft(variant: &dyn Variant, board: &mut Board, depth: u16) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = variant.legal_moves(board);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves
+        .into_iter()
+        .map(|bit_move| {
+            board.apply_move(bit_move);
+            let nodes = perft(variant, board, depth - 1);
+            board.undo_move();
+            nodes
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odds_remove_black_material() {
+        assert_eq!(Odds::None.starting_fen(), Board::default().fen());
+        assert_eq!(
+            Odds::Rook.starting_fen(),
+            "1nbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQk - 0 1"
+        );
+        let mut odds = Odds::Pawn;
+        while odds != Odds::None {
+            let game = ChessGame::from_fen(&odds.starting_fen()).unwrap();
+            assert_eq!(game.possible_moves().len(), 20);
+            odds = odds.next();
+        }
+    }
+
+    #[test]
+    fn untrusted_fen_is_checked_before_pleco_reads_it() {
+        for fen in &[
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2K w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w KQkq - 0 1",
+        ] {
+            assert!(ChessGame::from_untrusted_fen(fen, VariantKind::Standard).is_err());
+        }
+        let game = ChessGame::from_untrusted_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Qp] w KQkq - 0 1",
+            VariantKind::Crazyhouse,
+        )
+        .unwrap();
+        assert_eq!(game.pockets().unwrap().white, vec![DropPiece::Queen]);
+    }
+
+    #[test]
+    fn detects_check() {
+        let mut game = ChessGame::default();
+        assert!(!game.in_check());
+        for (source, destination) in &[(SQ::E2, SQ::E4), (SQ::F7, SQ::F6), (SQ::D1, SQ::H5)] {
+            game.move_piece(Square::from(*source), Square::from(*destination))
+                .unwrap();
+        }
+        assert!(game.in_check());
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn promotes_to_chosen_piece() {
+        let fen = "8/4P1k1/8/8/8/8/8/4K3 w - - 0 1";
+        let mut game = ChessGame::from_fen(fen).unwrap();
+        game.move_piece(Square::from(SQ::E7), Square::from(SQ::E8))
+            .unwrap();
+        assert_eq!(game.board().piece_at_sq(SQ::E8), Piece::WhiteQueen);
+
+        let mut game = ChessGame::from_fen(fen).unwrap();
+        game.move_piece_with_promotion(
+            Square::from(SQ::E7),
+            Square::from(SQ::E8),
+            Some(PromotionPiece::Knight),
+        )
+        .unwrap();
+        assert_eq!(game.board().piece_at_sq(SQ::E8), Piece::WhiteKnight);
+    }
+
+    #[test]
+    fn last_move_follows_undo() {
+        let mut game = ChessGame::default();
+        assert_eq!(game.last_move(), None);
+        game.move_piece(Square::from(SQ::E2), Square::from(SQ::E4))
+            .unwrap();
+        game.move_piece(Square::from(SQ::E7), Square::from(SQ::E5))
+            .unwrap();
+        assert_eq!(
+            game.last_move(),
+            Some((Square::from(SQ::E7), Square::from(SQ::E5)))
+        );
+        game.undo(1).unwrap();
+        assert_eq!(
+            game.last_move(),
+            Some((Square::from(SQ::E2), Square::from(SQ::E4)))
+        );
+        game.undo(1).unwrap();
+        assert_eq!(game.last_move(), None);
+    }
+
+    fn play(game: &mut ChessGame, moves: &[(SQ, SQ)]) {
+        for (source, destination) in moves {
+            game.move_piece(Square::from(*source), Square::from(*destination))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn draws_by_repetition() {
+        let mut game = ChessGame::default();
+        let knights_out_and_back = [
+            (SQ::G1, SQ::F3),
+            (SQ::G8, SQ::F6),
+            (SQ::F3, SQ::G1),
+            (SQ::F6, SQ::G8),
+        ];
+        let start = game.zobrist();
+        assert_eq!(game.repetition_count(), 1);
+        play(&mut game, &knights_out_and_back);
+        assert_eq!(game.zobrist(), start);
+        assert_eq!(game.repetition_count(), 2);
+        assert_eq!(game.outcome(), None);
+        play(&mut game, &knights_out_and_back);
+        assert_eq!(game.outcome(), Some(ChessOutcome::Repetition));
+        game.undo(1).unwrap();
+        assert_eq!(game.repetition_count(), 2);
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn draws_by_fifty_move_rule() {
+        let mut game = ChessGame::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80").unwrap();
+        play(&mut game, &[(SQ::A1, SQ::A2)]);
+        assert_eq!(game.outcome(), Some(ChessOutcome::FiftyMoves));
+
+        // A pawn move resets the count
+        let mut game = ChessGame::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80").unwrap();
+        play(&mut game, &[(SQ::E2, SQ::E4)]);
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn possible_moves_tell_what_they_do() {
+        let game = ChessGame::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K2R w Kq d6 0 1").unwrap();
+        let possible_moves = PossibleMove::from_bit_moves(&game.possible_moves());
+        let find = |source: &str, destination: &str| {
+            *possible_moves
+                .iter()
+                .find(|possible_move| {
+                    possible_move.source == source.parse().unwrap()
+                        && possible_move.destination == destination.parse().unwrap()
+                })
+                .unwrap()
+        };
+
+        let promotion = find("B7", "B8");
+        assert!(promotion.promotion && !promotion.capture);
+        let capturing_promotion = find("B7", "A8");
+        assert!(capturing_promotion.promotion && capturing_promotion.capture);
+        let en_passant = find("E5", "D6");
+        assert!(en_passant.en_passant && en_passant.capture);
+        // pleco moves the king onto the rook when castling
+        assert!(find("E1", "H1").castle);
+        let quiet = find("E5", "E6");
+        assert!(!quiet.capture && !quiet.promotion && !quiet.castle && !quiet.en_passant);
+        // Every promotion piece is the same move
+        assert_eq!(
+            possible_moves
+                .iter()
+                .filter(|possible_move| possible_move.source == "B7".parse().unwrap())
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn moves_in_uci_notation() {
+        let mut game = ChessGame::from_fen("4k3/1P6/8/8/8/8/8/4K1NR w K - 0 1").unwrap();
+        game.move_piece_uci("b7b8n").unwrap();
+        assert_eq!(game.fen(), "1N2k3/8/8/8/8/8/8/4K1NR b K - 0 1");
+        assert!(game.move_piece_uci("e8e9").is_err());
+        assert!(game.move_piece_uci("e8d7").is_err());
+        assert!(game.move_piece_uci("E8F7").is_ok());
+        assert!(game.move_piece_uci("g1g3").is_err());
+    }
+
+    #[test]
+    fn san_of_moves() {
+        let game = ChessGame::from_fen("4k3/1P6/8/8/8/8/8/4K1NR w K - 0 1").unwrap();
+        let square = |name: &str| name.parse().unwrap();
+        assert_eq!(
+            game.san(square("G1"), square("F3"), None),
+            Some("Nf3".to_owned())
+        );
+        assert_eq!(
+            game.san(square("B7"), square("B8"), Some(PromotionPiece::Rook)),
+            Some("b8=R+".to_owned())
+        );
+        assert_eq!(game.san(square("G1"), square("G3"), None), None);
+    }
+
+    #[test]
+    fn possible_moves_from_one_square() {
+        let game = ChessGame::default();
+        assert_eq!(game.possible_moves_from("E2".parse().unwrap()).len(), 2);
+        assert_eq!(game.possible_moves_from("E1".parse().unwrap()).len(), 0);
+        assert_eq!(game.possible_moves_from("E7".parse().unwrap()).len(), 0);
+    }
+
+    #[test]
+    fn counts_material() {
+        let material = ChessGame::default().material();
+        assert_eq!(material.white, material.black);
+        assert_eq!(material.white.value(), 3900);
+        assert_eq!(material.balance(), 0);
+        assert_eq!(material.white.missing(), PieceCounts::default());
+
+        // A knight against two pawns
+        let game = ChessGame::from_fen("4k3/pp6/8/8/8/8/8/1N2K3 w - - 0 1").unwrap();
+        let material = game.material();
+        assert_eq!(material.of_player(Player::White).knights, 1);
+        assert_eq!(material.of_player(Player::Black).pawns, 2);
+        assert_eq!(material.balance(), 100);
+        assert_eq!(material.black.missing().queens, 1);
+    }
+
+    #[test]
+    fn draws_by_insufficient_material() {
+        for fen in &[
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert!(Material::of(&board).is_insufficient(), "{}", fen);
+        }
+        for fen in &[
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3NKN2 w - - 0 1",
+            "4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert!(!Material::of(&board).is_insufficient(), "{}", fen);
+        }
+
+        let mut game = ChessGame::from_fen("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1").unwrap();
+        play(&mut game, &[(SQ::E1, SQ::D2)]);
+        assert_eq!(game.outcome(), Some(ChessOutcome::InsufficientMaterial));
+    }
+
+    #[test]
+    fn resignation_is_kept_after_undo() {
+        let mut game = ChessGame::default();
+        play(&mut game, &[(SQ::E2, SQ::E4)]);
+        game.resign(Player::Black);
+        let outcome = game.outcome().unwrap();
+        assert_eq!(
+            outcome,
+            ChessOutcome::Resignation {
+                winner: Player::White
+            }
+        );
+        assert_eq!(outcome.pgn_result(), "1-0");
+        assert_eq!(outcome.pgn_termination(), "normal");
+        assert_eq!(outcome.description(), "Black resigned!");
+        game.undo(1).unwrap();
+        assert_eq!(game.outcome(), Some(outcome));
+    }
+
+    #[test]
+    fn outcomes_in_pgn() {
+        assert_eq!(ChessOutcome::Agreement.pgn_result(), "1/2-1/2");
+        assert_eq!(ChessOutcome::Aborted { who: None }.pgn_result(), "*");
+        assert_eq!(
+            ChessOutcome::Timeout {
+                winner: Player::Black
+            }
+            .pgn_termination(),
+            "time forfeit"
+        );
+    }
+
+    #[test]
+    fn perft_matches_known_counts() {
+        // From https://www.chessprogramming.org/Perft_Results
+        let positions: &[(&str, &[u64])] = &[
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &[1, 20, 400, 8902],
+            ),
+            // "Kiwipete" (castling, en passant and promotions)
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[1, 48, 2039],
+            ),
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                &[1, 14, 191, 2812],
+            ),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                &[1, 6, 264, 9467],
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                &[1, 44, 1486],
+            ),
+        ];
+        for (fen, counts) in positions {
+            let game = ChessGame::from_fen(fen).unwrap();
+            for (depth, count) in counts.iter().enumerate() {
+                assert_eq!(
+                    game.perft(depth as u16),
+                    *count,
+                    "{} at depth {}",
+                    fen,
+                    depth
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn perft_divide_adds_up() {
+        let game = ChessGame::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let divided = game.perft_divide(2);
+        assert_eq!(divided.len(), 48);
+        assert_eq!(divided.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+        let castle = divided
+            .iter()
+            .find(|(bit_move, _)| crate::notation::san(&game.board(), *bit_move) == "O-O-O")
+            .unwrap();
+        assert_eq!(castle.1, 43);
+        // The position doesn't change
+        assert_eq!(game.perft(1), 48);
+    }
+
+    #[test]
+    fn from_pgn_plays_all_moves() {
+        let game = ChessGame::from_pgn(
+            "[Event \"Test\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. O-O Be7 5. d4 exd4 6. Nxd4 O-O *",
+        )
+        .unwrap();
+        assert_eq!(
+            game.fen(),
+            "r1bq1rk1/ppppbppp/2n2n2/8/2BNP3/8/PPP2PPP/RNBQ1RK1 w - - 1 7"
+        );
+        assert_eq!(game.total_undoable_moves(), 12);
+    }
+
+    #[test]
+    fn from_pgn_starts_at_fen_and_promotes_to_written_piece() {
+        let mut game = ChessGame::from_pgn(
+            "[FEN \"8/1P2k3/8/8/8/8/4K3/8 w - - 0 1\"]\n[SetUp \"1\"]\n\n1. b8=N *",
+        )
+        .unwrap();
+        assert_eq!(game.fen(), "1N6/4k3/8/8/8/8/4K3/8 b - - 0 1");
+        game.undo(1).unwrap();
+        assert_eq!(game.fen(), "8/1P2k3/8/8/8/8/4K3/8 w - - 0 1");
+        assert!(game.undo(1).is_err());
+    }
+
+    #[test]
+    fn from_pgn_tells_which_move_failed() {
+        let err = match ChessGame::from_pgn("1. e4 e5 2. Ke3 *") {
+            Ok(_) => panic!("The PGN was read"),
+            Err(err) => err,
+        };
+        assert_eq!(err.to_string(), "Move 3 can't be played");
+    }
+}
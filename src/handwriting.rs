@@ -0,0 +1,539 @@
+/// Recognition of moves written with a pen. Only the legal moves of a
+/// position are considered which makes a simple point cloud matcher
+/// (the "$P recognizer") per written character good enough.
+use std::collections::HashMap;
+
+/// Points of one pen stroke in screen coordinates
+pub type Stroke = Vec<(f32, f32)>;
+
+type Template = &'static [&'static [(f32, f32)]];
+
+/// Points every glyph gets resampled to
+const CLOUD_SIZE: usize = 32;
+/// Average distance of the glyphs to their characters above which
+/// the writing is considered unreadable
+const MAX_GLYPH_DISTANCE: f32 = 4.0;
+
+/// Strokes of the characters used in SAN (y goes down)
+const TEMPLATES: &[(char, Template)] = &[
+    (
+        'K',
+        &[
+            &[(0.0, 0.0), (0.0, 1.0)],
+            &[(0.8, 0.0), (0.0, 0.55), (0.8, 1.0)],
+        ],
+    ),
+    (
+        'Q',
+        &[
+            &[
+                (0.5, 0.0),
+                (0.15, 0.15),
+                (0.0, 0.5),
+                (0.15, 0.85),
+                (0.5, 1.0),
+                (0.85, 0.85),
+                (1.0, 0.5),
+                (0.85, 0.15),
+                (0.5, 0.0),
+            ],
+            &[(0.6, 0.7), (1.0, 1.05)],
+        ],
+    ),
+    (
+        'R',
+        &[
+            &[
+                (0.0, 1.0),
+                (0.0, 0.0),
+                (0.6, 0.0),
+                (0.8, 0.15),
+                (0.8, 0.35),
+                (0.6, 0.5),
+                (0.0, 0.5),
+            ],
+            &[(0.3, 0.5), (0.85, 1.0)],
+        ],
+    ),
+    (
+        'B',
+        &[
+            &[(0.0, 0.0), (0.0, 1.0)],
+            &[
+                (0.0, 0.0),
+                (0.6, 0.0),
+                (0.75, 0.12),
+                (0.75, 0.35),
+                (0.6, 0.48),
+                (0.0, 0.48),
+            ],
+            &[
+                (0.0, 0.48),
+                (0.65, 0.48),
+                (0.8, 0.62),
+                (0.8, 0.86),
+                (0.65, 1.0),
+                (0.0, 1.0),
+            ],
+        ],
+    ),
+    ('N', &[&[(0.0, 1.0), (0.0, 0.0), (0.8, 1.0), (0.8, 0.0)]]),
+    (
+        'a',
+        &[
+            &[
+                (0.7, 0.3),
+                (0.4, 0.2),
+                (0.1, 0.4),
+                (0.1, 0.8),
+                (0.4, 1.0),
+                (0.7, 0.8),
+            ],
+            &[(0.7, 0.2), (0.7, 1.0)],
+        ],
+    ),
+    (
+        'b',
+        &[
+            &[(0.0, 0.0), (0.0, 1.0)],
+            &[
+                (0.0, 0.6),
+                (0.3, 0.4),
+                (0.6, 0.45),
+                (0.7, 0.7),
+                (0.6, 0.95),
+                (0.3, 1.0),
+                (0.0, 0.9),
+            ],
+        ],
+    ),
+    (
+        'c',
+        &[&[
+            (0.7, 0.4),
+            (0.4, 0.3),
+            (0.1, 0.5),
+            (0.1, 0.8),
+            (0.4, 1.0),
+            (0.7, 0.9),
+        ]],
+    ),
+    (
+        'd',
+        &[
+            &[(0.7, 0.0), (0.7, 1.0)],
+            &[
+                (0.7, 0.6),
+                (0.4, 0.4),
+                (0.1, 0.55),
+                (0.1, 0.85),
+                (0.4, 1.0),
+                (0.7, 0.9),
+            ],
+        ],
+    ),
+    (
+        'e',
+        &[&[
+            (0.1, 0.65),
+            (0.7, 0.65),
+            (0.6, 0.4),
+            (0.4, 0.3),
+            (0.1, 0.5),
+            (0.1, 0.8),
+            (0.4, 1.0),
+            (0.7, 0.9),
+        ]],
+    ),
+    (
+        'f',
+        &[
+            &[(0.7, 0.05), (0.5, 0.0), (0.35, 0.1), (0.35, 1.0)],
+            &[(0.1, 0.4), (0.65, 0.4)],
+        ],
+    ),
+    (
+        'g',
+        &[
+            &[
+                (0.7, 0.4),
+                (0.4, 0.3),
+                (0.1, 0.45),
+                (0.1, 0.65),
+                (0.4, 0.75),
+                (0.7, 0.6),
+            ],
+            &[(0.7, 0.3), (0.7, 1.1), (0.5, 1.3), (0.2, 1.2)],
+        ],
+    ),
+    (
+        'h',
+        &[
+            &[(0.1, 0.0), (0.1, 1.0)],
+            &[(0.1, 0.6), (0.4, 0.4), (0.7, 0.5), (0.7, 1.0)],
+        ],
+    ),
+    ('1', &[&[(0.2, 0.2), (0.5, 0.0), (0.5, 1.0)]]),
+    (
+        '2',
+        &[&[
+            (0.1, 0.2),
+            (0.4, 0.0),
+            (0.7, 0.15),
+            (0.7, 0.4),
+            (0.1, 1.0),
+            (0.8, 1.0),
+        ]],
+    ),
+    (
+        '3',
+        &[&[
+            (0.1, 0.1),
+            (0.4, 0.0),
+            (0.7, 0.15),
+            (0.65, 0.4),
+            (0.35, 0.5),
+            (0.7, 0.62),
+            (0.75, 0.85),
+            (0.4, 1.0),
+            (0.1, 0.9),
+        ]],
+    ),
+    ('4', &[&[(0.6, 1.0), (0.6, 0.0), (0.05, 0.7), (0.85, 0.7)]]),
+    (
+        '5',
+        &[&[
+            (0.75, 0.0),
+            (0.15, 0.0),
+            (0.1, 0.45),
+            (0.5, 0.4),
+            (0.75, 0.6),
+            (0.7, 0.9),
+            (0.4, 1.0),
+            (0.1, 0.9),
+        ]],
+    ),
+    (
+        '6',
+        &[&[
+            (0.7, 0.05),
+            (0.4, 0.0),
+            (0.15, 0.3),
+            (0.1, 0.7),
+            (0.35, 1.0),
+            (0.65, 0.95),
+            (0.75, 0.7),
+            (0.5, 0.5),
+            (0.15, 0.6),
+        ]],
+    ),
+    ('7', &[&[(0.1, 0.0), (0.8, 0.0), (0.35, 1.0)]]),
+    (
+        '8',
+        &[&[
+            (0.45, 0.5),
+            (0.15, 0.35),
+            (0.2, 0.1),
+            (0.45, 0.0),
+            (0.7, 0.1),
+            (0.72, 0.35),
+            (0.45, 0.5),
+            (0.15, 0.7),
+            (0.2, 0.95),
+            (0.45, 1.0),
+            (0.75, 0.9),
+            (0.75, 0.65),
+            (0.45, 0.5),
+        ]],
+    ),
+    (
+        'x',
+        &[&[(0.1, 0.35), (0.7, 1.0)], &[(0.7, 0.35), (0.1, 1.0)]],
+    ),
+    (
+        'O',
+        &[&[
+            (0.5, 0.0),
+            (0.15, 0.15),
+            (0.0, 0.5),
+            (0.15, 0.85),
+            (0.5, 1.0),
+            (0.85, 0.85),
+            (1.0, 0.5),
+            (0.85, 0.15),
+            (0.5, 0.0),
+        ]],
+    ),
+    ('-', &[&[(0.0, 0.5), (0.6, 0.5)]]),
+    ('=', &[&[(0.0, 0.4), (0.6, 0.4)], &[(0.0, 0.6), (0.6, 0.6)]]),
+];
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn path_length(stroke: &[(f32, f32)]) -> f32 {
+    stroke.windows(2).map(|w| distance(w[0], w[1])).sum()
+}
+
+/// Spread `CLOUD_SIZE` points evenly along the strokes
+fn resample(strokes: &[Stroke]) -> Vec<(f32, f32)> {
+    let strokes: Vec<&Stroke> = strokes.iter().filter(|s| !s.is_empty()).collect();
+    let first = match strokes.first() {
+        Some(stroke) => stroke[0],
+        None => return vec![],
+    };
+    let length: f32 = strokes.iter().map(|s| path_length(s)).sum();
+    if length == 0.0 {
+        return vec![first; CLOUD_SIZE];
+    }
+    let interval = length / (CLOUD_SIZE - 1) as f32;
+    let mut points = vec![first];
+    let mut accumulated = 0.0;
+    for stroke in strokes.iter() {
+        let mut previous = stroke[0];
+        let mut i = 1;
+        while i < stroke.len() {
+            let current = stroke[i];
+            let d = distance(previous, current);
+            if d > 0.0 && accumulated + d >= interval {
+                let t = (interval - accumulated) / d;
+                previous = (
+                    previous.0 + t * (current.0 - previous.0),
+                    previous.1 + t * (current.1 - previous.1),
+                );
+                points.push(previous);
+                accumulated = 0.0;
+            } else {
+                accumulated += d;
+                previous = current;
+                i += 1;
+            }
+        }
+    }
+    let last = *strokes.last().unwrap().last().unwrap();
+    points.resize(CLOUD_SIZE, last);
+    points
+}
+
+/// Scale to a unit box (keeping the aspect ratio) around the centroid
+fn normalize(points: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    let (min_x, max_x, min_y, max_y) = bounds(points.iter());
+    let size = (max_x - min_x).max(max_y - min_y).max(std::f32::EPSILON);
+    let count = points.len() as f32;
+    let center_x = points.iter().map(|p| p.0).sum::<f32>() / count;
+    let center_y = points.iter().map(|p| p.1).sum::<f32>() / count;
+    points
+        .into_iter()
+        .map(|(x, y)| ((x - center_x) / size, (y - center_y) / size))
+        .collect()
+}
+
+fn bounds<'a>(points: impl Iterator<Item = &'a (f32, f32)>) -> (f32, f32, f32, f32) {
+    points.fold(
+        (std::f32::MAX, std::f32::MIN, std::f32::MAX, std::f32::MIN),
+        |(min_x, max_x, min_y, max_y), (x, y)| {
+            (min_x.min(*x), max_x.max(*x), min_y.min(*y), max_y.max(*y))
+        },
+    )
+}
+
+fn cloud(strokes: &[Stroke]) -> Vec<(f32, f32)> {
+    normalize(resample(strokes))
+}
+
+fn cloud_distance(a: &[(f32, f32)], b: &[(f32, f32)], start: usize) -> f32 {
+    let n = a.len();
+    let mut matched = vec![false; n];
+    let mut sum = 0.0;
+    for offset in 0..n {
+        let i = (start + offset) % n;
+        let (index, d) = b
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !matched[*j])
+            .map(|(j, point)| (j, distance(a[i], *point)))
+            .fold((0, std::f32::MAX), |best, candidate| {
+                if candidate.1 < best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            });
+        matched[index] = true;
+        sum += (1.0 - offset as f32 / n as f32) * d;
+    }
+    sum
+}
+
+/// How different two point clouds look (0 if they're equal)
+fn cloud_match(a: &[(f32, f32)], b: &[(f32, f32)]) -> f32 {
+    let step = (a.len() as f32).sqrt() as usize;
+    (0..a.len())
+        .step_by(step.max(1))
+        .map(|start| cloud_distance(a, b, start).min(cloud_distance(b, a, start)))
+        .fold(std::f32::MAX, f32::min)
+}
+
+fn x_range(stroke: &[(f32, f32)]) -> (f32, f32) {
+    let (min_x, max_x, _, _) = bounds(stroke.iter());
+    (min_x, max_x)
+}
+
+/// Group strokes into characters. Characters need to be written
+/// separately (strokes of different ones shouldn't overlap much).
+fn split_glyphs(strokes: &[Stroke]) -> Vec<Vec<Stroke>> {
+    let mut strokes: Vec<&Stroke> = strokes.iter().filter(|s| !s.is_empty()).collect();
+    let (_, _, min_y, max_y) = bounds(strokes.iter().flat_map(|s| s.iter()));
+    let tolerance = (max_y - min_y) * 0.05;
+    strokes.sort_by(|a, b| {
+        x_range(a)
+            .0
+            .partial_cmp(&x_range(b).0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut glyphs: Vec<((f32, f32), Vec<Stroke>)> = vec![];
+    for stroke in strokes {
+        let (min_x, max_x) = x_range(stroke);
+        if let Some(((glyph_min_x, glyph_max_x), glyph)) = glyphs.last_mut() {
+            let overlap = *glyph_max_x - min_x;
+            let narrower = (max_x - min_x).min(*glyph_max_x - *glyph_min_x);
+            if overlap > narrower * 0.3 - tolerance {
+                *glyph_max_x = glyph_max_x.max(max_x);
+                glyph.push(stroke.clone());
+                continue;
+            }
+        }
+        glyphs.push(((min_x, max_x), vec![stroke.clone()]));
+    }
+    glyphs.into_iter().map(|(_, glyph)| glyph).collect()
+}
+
+/// Ways a move could be written (without check marks and optionally
+/// without the capture or promotion sign)
+fn spellings(san: &str) -> Vec<String> {
+    let base = san.trim_end_matches(|c| c == '+' || c == '#').to_owned();
+    let mut spellings = vec![
+        base.replace('x', ""),
+        base.replace('=', ""),
+        base.replace(['x', '='], ""),
+        base,
+    ];
+    spellings.sort();
+    spellings.dedup();
+    spellings
+}
+
+/// Find which of the `candidates` (moves in SAN) was written. Returns
+/// `None` if the writing doesn't look like any of them.
+pub fn recognize_move<'a>(strokes: &[Stroke], candidates: &'a [String]) -> Option<&'a str> {
+    let glyphs: Vec<Vec<(f32, f32)>> = split_glyphs(strokes)
+        .iter()
+        .map(|glyph| cloud(glyph))
+        .collect();
+    if glyphs.is_empty() {
+        return None;
+    }
+    let templates: HashMap<char, Vec<(f32, f32)>> = TEMPLATES
+        .iter()
+        .map(|(c, template)| {
+            let strokes: Vec<Stroke> = template.iter().map(|stroke| stroke.to_vec()).collect();
+            (*c, cloud(&strokes))
+        })
+        .collect();
+    let mut distances: HashMap<(usize, char), f32> = HashMap::new();
+
+    let mut best: Option<(&str, f32)> = None;
+    for candidate in candidates {
+        for spelling in spellings(candidate) {
+            if spelling.chars().count() != glyphs.len() {
+                continue;
+            }
+            let mut total = 0.0;
+            for (i, c) in spelling.chars().enumerate() {
+                let template = match templates.get(&c) {
+                    Some(template) => template,
+                    None => {
+                        total = std::f32::MAX;
+                        break;
+                    }
+                };
+                total += *distances
+                    .entry((i, c))
+                    .or_insert_with(|| cloud_match(&glyphs[i], template));
+            }
+            let average = total / glyphs.len() as f32;
+            if best.map_or(true, |(_, distance)| average < distance) {
+                best = Some((candidate, average));
+            }
+        }
+    }
+    debug!("Best handwriting match: {:?}", best);
+    best.filter(|(_, distance)| *distance <= MAX_GLYPH_DISTANCE)
+        .map(|(candidate, _)| candidate)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::notation::san;
+    use pleco::Board;
+
+    /// Write `text` like a person would (characters next to each other, slightly wobbly)
+    pub(crate) fn write(text: &str) -> Vec<Stroke> {
+        let mut seed = 7u32;
+        let mut wobble = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((seed >> 16) % 100) as f32 / 100.0 * 3.0 - 1.5
+        };
+        let mut strokes = vec![];
+        let mut left = 100.0;
+        for c in text.chars() {
+            let template = TEMPLATES.iter().find(|(t, _)| *t == c).unwrap().1;
+            for stroke in template.iter() {
+                strokes.push(
+                    stroke
+                        .iter()
+                        .map(|(x, y)| (left + x * 55.0 + wobble(), 200.0 + y * 60.0 + wobble()))
+                        .collect(),
+                );
+            }
+            left += 80.0;
+        }
+        strokes
+    }
+
+    fn legal_moves(board: &Board) -> Vec<String> {
+        board
+            .generate_moves()
+            .iter()
+            .map(|bit_move| san(board, *bit_move))
+            .collect()
+    }
+
+    #[test]
+    fn recognizes_written_moves() {
+        let board = Board::default();
+        let candidates = legal_moves(&board);
+        assert_eq!(recognize_move(&write("Nf3"), &candidates), Some("Nf3"));
+        assert_eq!(recognize_move(&write("e4"), &candidates), Some("e4"));
+        assert_eq!(recognize_move(&write("c3"), &candidates), Some("c3"));
+    }
+
+    #[test]
+    fn capture_sign_is_optional() {
+        let board = Board::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2")
+            .unwrap();
+        let candidates = legal_moves(&board);
+        assert_eq!(recognize_move(&write("exd5"), &candidates), Some("exd5"));
+        assert_eq!(recognize_move(&write("ed5"), &candidates), Some("exd5"));
+    }
+
+    #[test]
+    fn groups_strokes_into_characters() {
+        assert_eq!(split_glyphs(&write("Kxf8")).len(), 4);
+        assert_eq!(split_glyphs(&write("O-O")).len(), 3);
+        assert!(recognize_move(&[], &["e4".to_owned()]).is_none());
+    }
+}
@@ -1,5 +1,6 @@
 /// The chess logic
 /// Intended to be a seperatable lib (and support interop with other langs later).
+/// The messages of `proto` are described in `schema/chessmarkable.proto` for clients in other languages.
 
 #[macro_use]
 extern crate anyhow;
@@ -11,9 +12,33 @@ extern crate serde;
 mod player;
 mod square;
 
+pub mod analysis;
+pub mod annotation;
+pub mod clock;
+pub mod coach;
+pub mod codec;
+pub mod crazyhouse;
+pub mod drill;
+pub mod endgames;
+pub mod epd;
+pub mod ffi;
 pub mod game;
+pub mod handwriting;
+pub mod middlegames;
+pub mod notation;
+pub mod pdf;
 pub mod proto;
 pub mod replay;
+pub mod review;
+pub mod scoresheet;
+#[cfg(feature = "game-loop")]
+pub mod search;
+pub mod setup;
+pub mod share;
+pub mod tablebase;
+pub mod tournament;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use player::Player;
 pub use square::Square;
@@ -0,0 +1,111 @@
+//! Balanced positions out of well-known openings to start practice
+//! games against the bot from. White is always to move.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Middlegame {
+    /// The opening the position comes from
+    pub name: &'static str,
+    pub fen: &'static str,
+}
+
+pub const MIDDLEGAMES: &[Middlegame] = &[
+    Middlegame {
+        name: "Ruy Lopez, Chigorin",
+        fen: "r1bq1rk1/2p1bppp/p2p1n2/np2p3/4P3/1BP2N1P/PP1P1PP1/RNBQR1K1 w - - 1 10",
+    },
+    Middlegame {
+        name: "Italian Game, Giuoco Pianissimo",
+        fen: "r1bq1rk1/1pp2pp1/p1np1n1p/2b1p3/P1B1P3/2PP1N2/1P3PPP/RNBQR1K1 w - - 0 9",
+    },
+    Middlegame {
+        name: "Sicilian Najdorf, English Attack",
+        fen: "rn1q1rk1/1p2bppp/p2pbn2/4p3/4P3/1NN1BP2/PPPQ2PP/R3KB1R w KQ - 3 10",
+    },
+    Middlegame {
+        name: "French Defense, Classical",
+        fen: "rnb2rk1/pp1nqppp/4p3/2ppP3/3P1P2/2N2N2/PPP3PP/R2QKB1R w KQ - 0 9",
+    },
+    Middlegame {
+        name: "Caro-Kann Defense, Classical",
+        fen: "r2qkbnr/pp1n1pp1/2p1p2p/7P/3P4/3Q1NN1/PPP2PP1/R1B1K2R w KQkq - 0 11",
+    },
+    Middlegame {
+        name: "Queen's Gambit Declined, Orthodox",
+        fen: "r1bq1rk1/pp1nbppp/2p1p3/3n2B1/2BP4/2N1PN2/PP3PPP/2RQK2R w K - 1 10",
+    },
+    Middlegame {
+        name: "Slav Defense, Main Line",
+        fen: "r2q1rk1/pp1n1ppp/2p1pn2/5b2/PbBP4/2N1PN2/1P2QPPP/R1B2RK1 w - - 5 10",
+    },
+    Middlegame {
+        name: "Nimzo-Indian, Rubinstein",
+        fen: "r1b2rk1/ppq2ppp/2n1pn2/2p5/2BP4/P1P1PN2/5PPP/R1BQ1RK1 w - - 1 11",
+    },
+    Middlegame {
+        name: "King's Indian, Classical",
+        fen: "r1bq1rk1/ppp1npbp/3p1np1/3Pp3/2P1P3/2N2N2/PP2BPPP/R1BQ1RK1 w - - 1 9",
+    },
+    Middlegame {
+        name: "English Opening, Symmetrical",
+        fen: "r1bq1rk1/pp2npbp/2n1p1p1/2pp4/2P5/2NP1NP1/PP1BPPBP/R2Q1RK1 w - - 0 9",
+    },
+];
+
+/// The position for `seed`. The one after `previous` is taken instead of
+/// it, so asking again always gives another position.
+pub fn pick(seed: u64, previous: Option<&Middlegame>) -> &'static Middlegame {
+    let mut index = (seed % MIDDLEGAMES.len() as u64) as usize;
+    if previous == Some(&MIDDLEGAMES[index]) {
+        index = (index + 1) % MIDDLEGAMES.len();
+    }
+    &MIDDLEGAMES[index]
+}
+
+/// Any position but `previous`. Seeded from the clock, which is random
+/// enough to not start the same way every time. Not on wasm, which has no
+/// clock (use `pick()` with a seed from JavaScript).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn random(previous: Option<&Middlegame>) -> &'static Middlegame {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64 / 1000)
+        .unwrap_or_default();
+    pick(seed, previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ChessGame;
+    use crate::setup::PositionSetup;
+
+    #[test]
+    fn all_positions_are_legal() {
+        for middlegame in MIDDLEGAMES {
+            let setup = PositionSetup::from_fen(middlegame.fen).unwrap();
+            assert!(setup.validate().is_ok(), "{}", middlegame.name);
+            assert!(
+                ChessGame::from_fen(middlegame.fen).is_ok(),
+                "{}",
+                middlegame.name
+            );
+            assert_eq!(
+                middlegame.fen.split(' ').nth(1),
+                Some("w"),
+                "{}",
+                middlegame.name
+            );
+        }
+    }
+
+    #[test]
+    fn never_picks_the_previous_position() {
+        for seed in 0..MIDDLEGAMES.len() as u64 * 2 {
+            let first = pick(seed, None);
+            assert_ne!(pick(seed, Some(first)), first);
+        }
+    }
+}
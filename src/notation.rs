@@ -0,0 +1,155 @@
+use anyhow::Result;
+use pleco::{BitMove, Board, PieceType, SQ};
+
+/// Standard algebraic notation (e.g. "Nbd7", "exd5", "O-O" or "e8=Q+")
+/// of a legal move on the given board.
+pub fn san(board: &Board, bit_move: BitMove) -> String {
+    let mut san = if bit_move.is_king_castle() {
+        "O-O".to_owned()
+    } else if bit_move.is_queen_castle() {
+        "O-O-O".to_owned()
+    } else {
+        let src = bit_move.get_src();
+        let dest = bit_move.get_dest();
+        let piece_type = board.piece_at_sq(src).type_of();
+        let is_capture = bit_move.is_capture() || bit_move.is_en_passant();
+        let mut san = String::new();
+        if piece_type == PieceType::P {
+            if is_capture {
+                san.push(file_char(src));
+            }
+        } else {
+            san.push(piece_char(piece_type));
+            san.push_str(&disambiguation(board, bit_move, piece_type));
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+        if bit_move.is_promo() {
+            san.push('=');
+            san.push(piece_char(bit_move.promo_piece()));
+        }
+        san
+    };
+
+    let mut board_after_move = board.shallow_clone();
+    board_after_move.apply_move(bit_move);
+    if board_after_move.checkmate() {
+        san.push('#');
+    } else if board_after_move.in_check() {
+        san.push('+');
+    }
+    san
+}
+
+/// Find the legal move described by `san`. Check, mate and
+/// annotation symbols (e.g. "+", "#", "!?") are optional.
+pub fn parse_san(board: &Board, san: &str) -> Result<BitMove> {
+    let wanted = normalize(san);
+    board
+        .generate_moves()
+        .iter()
+        .find(|bit_move| normalize(&self::san(board, **bit_move)) == wanted)
+        .copied()
+        .ok_or(anyhow!("\"{}\" is not a legal move", san))
+}
+
+fn normalize(san: &str) -> String {
+    san.trim()
+        .replace('0', "O")
+        .trim_end_matches(|c| "+#!?".contains(c))
+        .to_owned()
+}
+
+/// File, rank or both of the source square if other pieces
+/// of the same type could move to the same square.
+fn disambiguation(board: &Board, bit_move: BitMove, piece_type: PieceType) -> String {
+    let src = bit_move.get_src();
+    let others: Vec<SQ> = board
+        .generate_moves()
+        .iter()
+        .filter(|other| {
+            other.get_dest() == bit_move.get_dest()
+                && other.get_src() != src
+                && board.piece_at_sq(other.get_src()).type_of() == piece_type
+        })
+        .map(|other| other.get_src())
+        .collect();
+    if others.is_empty() {
+        String::new()
+    } else if others.iter().all(|other| other.file() != src.file()) {
+        file_char(src).to_string()
+    } else if others.iter().all(|other| other.rank() != src.rank()) {
+        rank_char(src).to_string()
+    } else {
+        src.to_string()
+    }
+}
+
+fn file_char(sq: SQ) -> char {
+    sq.to_string().chars().next().unwrap()
+}
+
+fn rank_char(sq: SQ) -> char {
+    sq.to_string().chars().nth(1).unwrap()
+}
+
+fn piece_char(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::N => 'N',
+        PieceType::B => 'B',
+        PieceType::R => 'R',
+        PieceType::Q => 'Q',
+        PieceType::K => 'K',
+        _ => 'P',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san_of(fen: &str, uci: &str) -> String {
+        let board = Board::from_fen(fen).unwrap();
+        let bit_move = board
+            .generate_moves()
+            .iter()
+            .find(|bit_move| bit_move.stringify() == uci)
+            .copied()
+            .unwrap();
+        san(&board, bit_move)
+    }
+
+    #[test]
+    fn formats_common_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, "e2e4"), "e4");
+        assert_eq!(san_of(start, "g1f3"), "Nf3");
+        let italian = "r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        assert_eq!(san_of(italian, "c4f7"), "Bxf7+");
+        let board = Board::from_fen(italian).unwrap();
+        let castle = parse_san(&board, "0-0").unwrap();
+        assert!(castle.is_king_castle());
+        assert_eq!(san(&board, castle), "O-O");
+    }
+
+    #[test]
+    fn disambiguates_and_promotes() {
+        let fen = "k7/4P3/8/8/8/8/8/KR3R2 w - - 0 1";
+        assert_eq!(san_of(fen, "b1d1"), "Rbd1");
+        assert_eq!(san_of(fen, "e7e8q"), "e8=Q+");
+        assert_eq!(
+            san_of("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "a1a8"),
+            "Ra8#"
+        );
+    }
+
+    #[test]
+    fn parses_san_leniently() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "Ra8").unwrap().stringify(), "a1a8");
+        assert_eq!(parse_san(&board, "Ra8#!").unwrap().stringify(), "a1a8");
+        assert!(parse_san(&board, "Qd8").is_err());
+    }
+}
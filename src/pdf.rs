@@ -0,0 +1,474 @@
+use crate::replay::ReplayMove;
+use crate::Player;
+use std::fmt::Write;
+
+/// A4 in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+const COLUMN_GAP: f32 = 20.0;
+
+const TITLE_SIZE: f32 = 18.0;
+const TEXT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 14.0;
+const DIAGRAM_SQUARE_SIZE: f32 = 22.0;
+const DIAGRAM_PIECE_SIZE: f32 = 16.0;
+/// Lines of the note shown below a diagram
+const MAX_CAPTION_LINES: usize = 3;
+
+/// Position to show as diagram in an exported game
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagram {
+    /// Number of moves played before the position (at least one)
+    pub after_move: usize,
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource_name(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+/// Content stream of a single page
+#[derive(Default)]
+struct Page {
+    content: String,
+}
+
+impl Page {
+    fn text(&mut self, x: f32, y: f32, size: f32, font: Font, text: &str) {
+        writeln!(
+            self.content,
+            "BT /{} {} Tf {:.1} {:.1} Td ({}) Tj ET",
+            font.resource_name(),
+            size,
+            x,
+            y,
+            escape(text)
+        )
+        .unwrap();
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, gray: f32) {
+        writeln!(
+            self.content,
+            "{} g {:.1} {:.1} {:.1} {:.1} re f 0 g",
+            gray, x, y, width, height
+        )
+        .unwrap();
+    }
+
+    fn stroke_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        writeln!(
+            self.content,
+            "0 G 1 w {:.1} {:.1} {:.1} {:.1} re S",
+            x, y, width, height
+        )
+        .unwrap();
+    }
+
+    /// A piece as its letter. White pieces are drawn outlined, black ones filled.
+    fn piece(&mut self, x: f32, y: f32, piece: char) {
+        let letter = piece.to_ascii_uppercase().to_string();
+        if piece.is_ascii_uppercase() {
+            writeln!(self.content, "q 1 g 0 G 0.8 w 2 Tr").unwrap();
+            self.text(x, y, DIAGRAM_PIECE_SIZE, Font::Bold, &letter);
+            writeln!(self.content, "Q").unwrap();
+        } else {
+            self.text(x, y, DIAGRAM_PIECE_SIZE, Font::Bold, &letter);
+        }
+    }
+}
+
+/// Pages filled from top to bottom
+struct Layout {
+    pages: Vec<Page>,
+    /// Baseline of the next line on the current page
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Self {
+            pages: vec![Page::default()],
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().unwrap()
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page::default());
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Start a new page if less than `height` is left on the current one
+    fn make_room(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn line(&mut self, size: f32, font: Font, text: &str) {
+        self.make_room(size);
+        let y = self.y - size;
+        self.page().text(MARGIN, y, size, font, text);
+        self.y = y - (LINE_HEIGHT - TEXT_SIZE);
+    }
+}
+
+/// Create a printable PDF (A4) of a game with the tags as header, the moves
+/// in two columns and the given diagrams
+pub fn export_game(
+    tags: &[(String, String)],
+    result: &str,
+    moves: &[ReplayMove],
+    diagrams: &[Diagram],
+) -> Vec<u8> {
+    let mut layout = Layout::new();
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag_name, value)| tag_name == name && !value.is_empty() && value != "?")
+            .map(|(_, value)| value.as_str())
+    };
+
+    let title = format!(
+        "{} vs {}",
+        tag("White").unwrap_or("White"),
+        tag("Black").unwrap_or("Black")
+    );
+    layout.line(TITLE_SIZE, Font::Bold, &title);
+    let details: Vec<&str> = ["Event", "Site", "Date", "Round"]
+        .iter()
+        .filter_map(|name| tag(name))
+        .collect();
+    if !details.is_empty() {
+        layout.line(TEXT_SIZE, Font::Regular, &details.join(", "));
+    }
+    layout.line(TEXT_SIZE, Font::Regular, &format!("Result: {}", result));
+    layout.y -= LINE_HEIGHT;
+
+    layout_move_list(&mut layout, &move_list_lines(moves, result));
+    layout.y -= LINE_HEIGHT;
+    layout_diagrams(&mut layout, moves, diagrams);
+
+    write_document(&layout.pages)
+}
+
+/// The moves with one move number per line (e.g. "12. Nf3 Nc6")
+fn move_list_lines(moves: &[ReplayMove], result: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    // Whether the last line only has white's move yet
+    let mut open_line = false;
+    for played_move in moves {
+        match played_move.player {
+            Player::White => {
+                lines.push(format!("{}. {}", played_move.number, played_move.san));
+                open_line = true;
+            }
+            Player::Black if open_line => {
+                let line = lines.last_mut().unwrap();
+                line.push(' ');
+                line.push_str(&played_move.san);
+                open_line = false;
+            }
+            Player::Black => {
+                lines.push(format!("{}... {}", played_move.number, played_move.san));
+            }
+        }
+    }
+    lines.push(result.to_owned());
+    lines
+}
+
+fn layout_move_list(layout: &mut Layout, lines: &[String]) {
+    let column_width = (PAGE_WIDTH - 2.0 * MARGIN - COLUMN_GAP) / 2.0;
+    let mut remaining = lines;
+    while !remaining.is_empty() {
+        layout.make_room(LINE_HEIGHT);
+        let rows = ((layout.y - MARGIN) / LINE_HEIGHT) as usize;
+        let used_rows = rows.min(remaining.len());
+        for column in 0..2 {
+            let count = rows.min(remaining.len());
+            let x = MARGIN + column as f32 * (column_width + COLUMN_GAP);
+            for (row, line) in remaining[..count].iter().enumerate() {
+                let y = layout.y - TEXT_SIZE - row as f32 * LINE_HEIGHT;
+                layout.page().text(x, y, TEXT_SIZE, Font::Regular, line);
+            }
+            remaining = &remaining[count..];
+        }
+        if remaining.is_empty() {
+            layout.y -= used_rows as f32 * LINE_HEIGHT;
+        } else {
+            layout.new_page();
+        }
+    }
+}
+
+/// Diagrams are placed two per row
+fn layout_diagrams(layout: &mut Layout, moves: &[ReplayMove], diagrams: &[Diagram]) {
+    let board_size = DIAGRAM_SQUARE_SIZE * 8.0;
+    let column_width = (PAGE_WIDTH - 2.0 * MARGIN - COLUMN_GAP) / 2.0;
+    let row_height = board_size + LINE_HEIGHT * (MAX_CAPTION_LINES + 2) as f32 + 15.0;
+    let max_chars = (column_width / (TEXT_SIZE * 0.5)) as usize;
+
+    let diagrams: Vec<&Diagram> = diagrams
+        .iter()
+        .filter(|diagram| diagram.after_move >= 1 && diagram.after_move <= moves.len())
+        .collect();
+    for row in diagrams.chunks(2) {
+        layout.make_room(row_height);
+        for (column, diagram) in row.iter().enumerate() {
+            let played_move = &moves[diagram.after_move - 1];
+            let x = MARGIN + 10.0 + column as f32 * (column_width + COLUMN_GAP);
+            let board_top = layout.y - 5.0;
+            let page = layout.page();
+            draw_diagram(page, x, board_top - board_size, &played_move.fen);
+
+            let mut y = board_top - board_size - 12.0 - LINE_HEIGHT;
+            let dots = match played_move.player {
+                Player::White => ".",
+                Player::Black => "...",
+            };
+            let caption = format!("After {}{} {}", played_move.number, dots, played_move.san);
+            page.text(x, y, TEXT_SIZE, Font::Bold, &caption);
+            let note = diagram.note.as_deref().unwrap_or_default();
+            for line in wrap(note, max_chars).iter().take(MAX_CAPTION_LINES) {
+                y -= LINE_HEIGHT;
+                page.text(x, y, TEXT_SIZE, Font::Regular, line);
+            }
+        }
+        layout.y -= row_height;
+    }
+}
+
+/// Draw the board of the FEN with its bottom left corner at `x`, `y`
+fn draw_diagram(page: &mut Page, x: f32, y: f32, fen: &str) {
+    let board_size = DIAGRAM_SQUARE_SIZE * 8.0;
+    for row in 0..8 {
+        for file in 0..8 {
+            if (file + row) % 2 == 0 {
+                page.fill_rect(
+                    x + file as f32 * DIAGRAM_SQUARE_SIZE,
+                    y + row as f32 * DIAGRAM_SQUARE_SIZE,
+                    DIAGRAM_SQUARE_SIZE,
+                    DIAGRAM_SQUARE_SIZE,
+                    0.7,
+                );
+            }
+        }
+    }
+    page.stroke_rect(x, y, board_size, board_size);
+
+    let placement = fen.split(' ').next().unwrap_or_default();
+    let offset = (DIAGRAM_SQUARE_SIZE - DIAGRAM_PIECE_SIZE * 0.7) / 2.0;
+    for (rank_index, rank) in placement.split('/').take(8).enumerate() {
+        let row = 7 - rank_index;
+        let mut file = 0;
+        for piece in rank.chars() {
+            match piece.to_digit(10) {
+                Some(empty) => file += empty as usize,
+                None => {
+                    if file < 8 {
+                        page.piece(
+                            x + file as f32 * DIAGRAM_SQUARE_SIZE + offset,
+                            y + row as f32 * DIAGRAM_SQUARE_SIZE + offset,
+                            piece,
+                        );
+                    }
+                    file += 1;
+                }
+            }
+        }
+    }
+
+    for i in 0..8 {
+        let file_label = ((b'a' + i as u8) as char).to_string();
+        let center = i as f32 * DIAGRAM_SQUARE_SIZE + DIAGRAM_SQUARE_SIZE / 2.0 - 2.0;
+        page.text(x + center, y - 9.0, 7.0, Font::Regular, &file_label);
+        page.text(
+            x - 8.0,
+            y + center,
+            7.0,
+            Font::Regular,
+            &(i + 1).to_string(),
+        );
+    }
+}
+
+/// Break the text at spaces into lines of at most `max_chars` characters
+/// (longer words get a line of their own)
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= max_chars => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_owned()),
+        }
+    }
+    lines
+}
+
+/// Escape a string for a PDF literal. The standard fonts only cover
+/// ASCII reliably, so anything else is replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ if c.is_whitespace() => escaped.push(' '),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+/// Write the pages into a PDF 1.4 file using the standard Helvetica fonts
+fn write_document(pages: &[Page]) -> Vec<u8> {
+    let mut objects: Vec<String> = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+        String::new(), // Page tree, added once the page objects are known
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_owned(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold >>".to_owned(),
+    ];
+    let mut page_ids = vec![];
+    for page in pages {
+        let page_id = objects.len() + 1;
+        page_ids.push(format!("{} 0 R", page_id));
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
+            page_id + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            page.content.len(),
+            page.content
+        ));
+    }
+    objects[1] = format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        page_ids.join(" "),
+        pages.len()
+    );
+
+    let mut document = "%PDF-1.4\n".to_owned();
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(document.len());
+        write!(document, "{} 0 obj\n{}\nendobj\n", i + 1, object).unwrap();
+    }
+    let xref_offset = document.len();
+    write!(
+        document,
+        "xref\n0 {}\n0000000000 65535 f \n",
+        objects.len() + 1
+    )
+    .unwrap();
+    for offset in offsets {
+        writeln!(document, "{:010} 00000 n ", offset).unwrap();
+    }
+    write!(
+        document,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_offset
+    )
+    .unwrap();
+    document.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn played_move(number: u16, player: Player, san: &str) -> ReplayMove {
+        ReplayMove {
+            number,
+            player,
+            san: san.to_owned(),
+            fen: "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".to_owned(),
+            clock: None,
+            eval: None,
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn groups_moves_by_number() {
+        let moves = vec![
+            played_move(1, Player::White, "e4"),
+            played_move(1, Player::Black, "e5"),
+            played_move(2, Player::White, "Nf3"),
+        ];
+        assert_eq!(
+            move_list_lines(&moves, "*"),
+            vec!["1. e4 e5".to_owned(), "2. Nf3".to_owned(), "*".to_owned()]
+        );
+        let moves = vec![
+            played_move(7, Player::Black, "Nc6"),
+            played_move(8, Player::White, "O-O"),
+        ];
+        assert_eq!(
+            move_list_lines(&moves, "1-0"),
+            vec!["7... Nc6".to_owned(), "8. O-O".to_owned(), "1-0".to_owned()]
+        );
+    }
+
+    #[test]
+    fn xref_points_at_objects() {
+        let tags = vec![("White".to_owned(), "Anna (GM)".to_owned())];
+        let moves = vec![played_move(1, Player::White, "e4")];
+        let diagrams = vec![Diagram {
+            after_move: 1,
+            note: Some("Best by test".to_owned()),
+        }];
+        let document = String::from_utf8(export_game(&tags, "*", &moves, &diagrams)).unwrap();
+        assert!(document.starts_with("%PDF-1.4\n"));
+        assert!(document.ends_with("%%EOF\n"));
+        assert!(document.contains("(Anna \\(GM\\) vs Black)"));
+
+        let xref = document.find("\nxref\n").unwrap() + 1;
+        let startxref: usize = document.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert_eq!(startxref, xref);
+        for (i, line) in document[xref..].lines().skip(3).take(6).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(document[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+    }
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(escape("a(b)\\c"), "a\\(b\\)\\\\c");
+        assert_eq!(escape("Réti\tx"), "R?ti x");
+    }
+
+    #[test]
+    fn wraps_words() {
+        assert_eq!(
+            wrap("a bb ccc dddddd", 6),
+            vec!["a bb".to_owned(), "ccc".to_owned(), "dddddd".to_owned()]
+        );
+    }
+}
@@ -1,16 +1,14 @@
-use crate::game::ChessGame;
-use crate::game::Player as PlecoPlayer;
-pub use crate::game::{ChessOutcome, SQ};
+pub use crate::clock::{ClockSnapshot, TimeControl};
+pub use crate::crazyhouse::{DropPiece, Pockets};
+pub use crate::game::{ChessOutcome, PossibleMove, PromotionPiece, VariantKind, SQ};
 use crate::{Player, Square};
-use anyhow::{Context, Result};
-use chess_pgn_parser::Game;
-use pleco::tools::Searcher;
 use serde::{Deserialize, Serialize};
-use std::thread;
-use std::time::{Duration, SystemTime};
-use tokio::stream::StreamExt;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio::task;
+
+/// The game loop needs tokio and threads, which aren't there in the WASM build
+#[cfg(feature = "game-loop")]
+mod game_loop;
+#[cfg(feature = "game-loop")]
+pub use game_loop::*;
 
 #[derive(Clone, Debug)]
 pub struct ChessConfig {
@@ -18,6 +16,12 @@ pub struct ChessConfig {
     pub can_black_undo: bool,
     pub can_white_undo: bool,
     pub allow_undo_after_loose: bool,
+    pub variant: VariantKind,
+    /// `None` for games without a clock
+    pub time_control: Option<TimeControl>,
+    /// Times to continue an adjourned game with (the base time of the
+    /// time control if `None`)
+    pub starting_clock: Option<ClockSnapshot>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -25,16 +29,53 @@ pub enum ChessRequest {
     CurrentBoard,
     CurrentTotalMoves,
     CurrentOutcome,
-    MovePiece { source: Square, destination: Square },
-    Abort { message: String },
-    UndoMoves { moves: u16 },
+    CurrentVariant,
+    MovePiece {
+        source: Square,
+        destination: Square,
+        /// Piece a pawn on the last rank becomes (a queen if not given)
+        #[serde(default)]
+        promotion: Option<PromotionPiece>,
+    },
+    /// Put a piece from the own pocket onto an empty square (Crazyhouse)
+    DropPiece {
+        piece: DropPiece,
+        destination: Square,
+    },
+    Abort {
+        message: String,
+    },
+    UndoMoves {
+        moves: u16,
+    },
+    /// Give up the game
+    Resign,
+    /// Offer a draw or accept the offer of the other player. An offer
+    /// lasts until the other player moves.
+    OfferDraw,
+    /// Only the moves of the piece on `square` (e.g. the one that was
+    /// selected) instead of all of them
+    PossibleMovesFrom {
+        square: Square,
+    },
+    /// How far a bot got in searching its move, passed on to everyone as
+    /// `ChessUpdate::Thinking`. Ignored when it's not the turn of the sender.
+    ReportThinking {
+        depth: u16,
+        /// Source and destination of the best move so far
+        best_move: (Square, Square),
+        /// Score of the search in centipawns for the thinking player
+        eval: i32,
+    },
 }
 
 impl ChessRequest {
     /// Is a spectator allowed to send this request
     pub fn available_to_spectator(&self) -> bool {
         match self {
-            ChessRequest::CurrentBoard | ChessRequest::CurrentTotalMoves => true,
+            ChessRequest::CurrentBoard
+            | ChessRequest::CurrentTotalMoves
+            | ChessRequest::CurrentVariant => true,
             _ => false,
         }
     }
@@ -50,6 +91,9 @@ pub enum ChessUpdate {
         player: Player,
         moved_piece_source: Square,
         moved_piece_destination: Square,
+        /// The move in standard algebraic notation (e.g. "Nxe5+")
+        #[serde(default)]
+        san: Option<String>,
     },
     /// Signal that a new player is now playing. The boar is the
     /// most recent one which can also be retreived by requesting a
@@ -57,6 +101,8 @@ pub enum ChessUpdate {
     PlayerSwitch {
         player: Player,
         fen: String,
+        /// The king of `player` is attacked
+        in_check: bool,
     },
     MovePieceFailedResponse {
         // Response to `ChessRequest::MovePiece` when the action failed
@@ -67,7 +113,13 @@ pub enum ChessUpdate {
         outcome: Option<ChessOutcome>,
     },
     PossibleMoves {
-        possible_moves: Vec<(Square /* From */, Square /* To */)>,
+        possible_moves: Vec<PossibleMove>,
+    },
+    /// Response to `ChessRequest::PossibleMovesFrom`. Empty if it's not
+    /// the turn of the receiver.
+    PossibleMovesFromResponse {
+        square: Square,
+        possible_moves: Vec<PossibleMove>,
     },
     /// Something went wrong and the server wants to tell you about it
     GenericErrorResponse {
@@ -79,363 +131,245 @@ pub enum ChessUpdate {
     MovesUndone {
         who: Player,
         moves: u16,
+        /// Source and destination of the move that is the last one now
+        last_move: Option<(Square, Square)>,
     },
     CurrentTotalMovesReponse {
         total_moves: u16,
     },
+    CurrentVariantResponse {
+        variant: VariantKind,
+    },
+    PlayerDroppedAPiece {
+        player: Player,
+        piece: DropPiece,
+        destination: Square,
+    },
+    /// Sent whenever the pieces in hand changed (only in variants with drops)
+    Pockets {
+        pockets: Pockets,
+    },
+    /// Like `PossibleMoves` but for pieces in the pocket
+    PossibleDrops {
+        possible_drops: Vec<(DropPiece, Square)>,
+    },
+    /// `player` offers a draw (accepted by offering one as well)
+    DrawOffered {
+        player: Player,
+    },
+    /// Sent whenever the clock was pressed, stopped or switched
+    /// (only in games with a time control)
+    Clock {
+        clock: ClockSnapshot,
+    },
+    /// Everything needed to continue a running game. Sent to an
+    /// endpoint that got swapped in (see `GameControl::swap_player()`).
+    FullSync {
+        fen: String,
+        variant: VariantKind,
+        turn: Player,
+        outcome: Option<ChessOutcome>,
+        total_moves: u16,
+        /// The king of the player to move is attacked
+        in_check: bool,
+        /// Empty if it's not the turn of the receiver
+        possible_moves: Vec<PossibleMove>,
+        /// `None` if the variant has no drops
+        pockets: Option<Pockets>,
+        /// Empty if it's not the turn of the receiver
+        possible_drops: Vec<(DropPiece, Square)>,
+    },
+    /// `player` (a bot) is still searching its move. Sent after every
+    /// finished ply of the search.
+    Thinking {
+        player: Player,
+        depth: u16,
+        best_move: (Square, Square),
+        /// Score of the search in centipawns for the thinking player
+        eval: i32,
+    },
 }
 
-pub async fn create_game(
-    white: (Sender<ChessUpdate>, Receiver<ChessRequest>),
-    black: (Sender<ChessUpdate>, Receiver<ChessRequest>),
-    spectators: (Sender<ChessUpdate>, Receiver<ChessRequest>),
-    config: ChessConfig,
-) -> Result<()> {
-    let mut game = if let Some(ref fen) = config.starting_fen {
-        ChessGame::from_fen(fen)?
-    } else {
-        ChessGame::default()
-    };
-
-    let (mut white_tx, mut white_rx) = white;
-    let (mut black_tx, mut black_rx) = black;
-    let (mut spectators_tx, mut spectators_rx) = spectators;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ChessClock;
+    use std::time::Instant;
 
-    let (combined_tx, mut combined_rx) = channel::<(Option<Player>, ChessRequest)>(1024);
+    const SCHEMA: &str = include_str!("../schema/chessmarkable.proto");
 
-    macro_rules! send_to_everyone {
-        ($msg: expr) => {
-            white_tx.send($msg.clone()).await.ok();
-            black_tx.send($msg.clone()).await.ok();
-            spectators_tx.send($msg).await.ok();
-        };
-    }
-
-    // Redirect all rx streams into `combined_rx` with a supplied player for cleaner handling
-    // TODO: Shorten/cleanup code
-    let mut combined_white_tx = combined_tx.clone();
-    task::spawn(async move {
-        let player = Some(Player::White);
-        loop {
-            let update = match white_rx.next().await {
-                Some(update) => update,
-                None => {
-                    combined_white_tx
-                        .send((
-                            player,
-                            ChessRequest::Abort {
-                                message: "[Internal] Connection lost".to_owned(),
-                            },
-                        ))
-                        .await
-                        .ok();
-                    return;
-                }
-            };
-            if let Err(_) = combined_white_tx.send((player, update)).await {
-                return;
-            }
-        }
-    });
-    let mut combined_black_tx = combined_tx.clone();
-    task::spawn(async move {
-        loop {
-            let update = match black_rx.next().await {
-                Some(update) => update,
-                None => return,
-            };
-            if let Err(_) = combined_black_tx.send((Some(Player::Black), update)).await {
-                return;
-            }
-        }
-    });
-    let mut combined_spectators_tx = combined_tx;
-    task::spawn(async move {
-        loop {
-            let update = match spectators_rx.next().await {
-                Some(update) => update,
-                None => return,
-            };
-            if let Err(_) = combined_spectators_tx.send((None, update)).await {
-                return;
-            }
+    /// Field of the request in the oneof of the schema. Adding a variant
+    /// breaks the build here until it's added to the schema as well.
+    fn request_field(request: &ChessRequest) -> &'static str {
+        match request {
+            ChessRequest::CurrentBoard => "current_board",
+            ChessRequest::CurrentTotalMoves => "current_total_moves",
+            ChessRequest::CurrentOutcome => "current_outcome",
+            ChessRequest::CurrentVariant => "current_variant",
+            ChessRequest::MovePiece { .. } => "move_piece",
+            ChessRequest::DropPiece { .. } => "drop_piece",
+            ChessRequest::Abort { .. } => "abort",
+            ChessRequest::UndoMoves { .. } => "undo_moves",
+            ChessRequest::Resign => "resign",
+            ChessRequest::OfferDraw => "offer_draw",
+            ChessRequest::PossibleMovesFrom { .. } => "possible_moves_from",
+            ChessRequest::ReportThinking { .. } => "report_thinking",
         }
-    });
-
-    // Start (if not using a FEN then white starts)
-    send_to_everyone!(ChessUpdate::PlayerSwitch {
-        player: game.turn(),
-        fen: game.fen()
-    });
-    // Send the starting player his possible moves
-    let possible_moves: Vec<_> = game
-        .possible_moves()
-        .iter()
-        .map(|bit_move| (bit_move.get_src().into(), bit_move.get_dest().into()))
-        .collect();
-    match game.turn() {
-        PlecoPlayer::White => white_tx.clone(),
-        PlecoPlayer::Black => black_tx.clone(),
     }
-    .send(ChessUpdate::PossibleMoves { possible_moves })
-    .await
-    .ok();
-
-    info!("Game initialized. Handling requests...");
-
-    // Handle inputs
-    loop {
-        let (sender, request): (Option<Player>, ChessRequest) = match combined_rx.next().await {
-            Some(res) => res,
-            None => {
-                break; // No senders connected anymore
-            }
-        };
-
-        if sender.is_none() && !request.available_to_spectator() {
-            spectators_tx
-                .send(ChessUpdate::GenericErrorResponse {
-                    message: "Spectators can't send this kind of request!".to_owned(),
-                })
-                .await
-                .ok();
-            continue;
-        }
 
-        macro_rules! send_to_sender {
-            ($msg: expr) => {
-                match sender {
-                    Some(player) => match player {
-                        Player::White => white_tx.send($msg).await.ok(),
-                        Player::Black => black_tx.send($msg).await.ok(),
-                    },
-                    None => spectators_tx.send($msg).await.ok(),
-                };
-            };
-        }
-
-        macro_rules! send_to_other_player {
-            ($msg: expr) => {
-                match sender.context("Send to the other player")? {
-                    Player::White => black_tx.send($msg).await.ok(),
-                    Player::Black => white_tx.send($msg).await.ok(),
-                };
-            };
+    /// Like `request_field()` for updates
+    fn update_field(update: &ChessUpdate) -> &'static str {
+        match update {
+            ChessUpdate::Board { .. } => "board",
+            ChessUpdate::PlayerMovedAPiece { .. } => "player_moved_a_piece",
+            ChessUpdate::PlayerSwitch { .. } => "player_switch",
+            ChessUpdate::MovePieceFailedResponse { .. } => "move_piece_failed_response",
+            ChessUpdate::Outcome { .. } => "outcome",
+            ChessUpdate::PossibleMoves { .. } => "possible_moves",
+            ChessUpdate::PossibleMovesFromResponse { .. } => "possible_moves_from_response",
+            ChessUpdate::GenericErrorResponse { .. } => "generic_error_response",
+            ChessUpdate::UndoMovesFailedResponse { .. } => "undo_moves_failed_response",
+            ChessUpdate::MovesUndone { .. } => "moves_undone",
+            ChessUpdate::CurrentTotalMovesReponse { .. } => "current_total_moves_reponse",
+            ChessUpdate::CurrentVariantResponse { .. } => "current_variant_response",
+            ChessUpdate::PlayerDroppedAPiece { .. } => "player_dropped_a_piece",
+            ChessUpdate::Pockets { .. } => "pockets",
+            ChessUpdate::PossibleDrops { .. } => "possible_drops",
+            ChessUpdate::DrawOffered { .. } => "draw_offered",
+            ChessUpdate::Clock { .. } => "clock",
+            ChessUpdate::FullSync { .. } => "full_sync",
+            ChessUpdate::Thinking { .. } => "thinking",
         }
+    }
 
-        // Requests that players as well as spectators can send
-        match request {
-            ChessRequest::CurrentBoard => {
-                send_to_sender!(ChessUpdate::Board { fen: game.fen() });
-            }
-            ChessRequest::CurrentTotalMoves => {
-                send_to_sender!(ChessUpdate::CurrentTotalMovesReponse {
-                    total_moves: game.total_moves()
-                });
-            }
-            ChessRequest::CurrentOutcome => {
-                send_to_sender!(ChessUpdate::Outcome {
-                    outcome: game.outcome()
-                });
-            }
-            _ => {} // Should be handles for a player request
-        }
+    /// The oneof named `oneof` of the schema
+    fn schema_oneof(oneof: &str) -> &'static str {
+        let start = SCHEMA.find(&format!("oneof {} {{", oneof)).unwrap();
+        let end = start + SCHEMA[start..].find('}').unwrap();
+        &SCHEMA[start..end]
+    }
 
-        // Requests that only players can send
-        let sender = sender
-            .context("available_to_spectator() is probably not up to date with the handlers (message that has to be playerspecific was sent from a spectator)!!!")?;
-        match request {
+    #[test]
+    fn schema_lists_every_message() {
+        let square: Square = "E4".parse().unwrap();
+        let message = String::new();
+        let requests = vec![
+            ChessRequest::CurrentBoard,
+            ChessRequest::CurrentTotalMoves,
+            ChessRequest::CurrentOutcome,
+            ChessRequest::CurrentVariant,
             ChessRequest::MovePiece {
-                source,
-                destination,
-            } => {
-                let prev_outcome = game.outcome();
-                match game.move_piece(source, destination) {
-                    Ok(_) => {
-                        // Dunno why, but rust won't compile when using just "Ok". Error in the matrix??
-                        send_to_everyone!(ChessUpdate::PlayerMovedAPiece {
-                            player: sender,
-                            moved_piece_source: source,
-                            moved_piece_destination: destination,
-                        });
-                        let new_outcome = game.outcome();
-                        if prev_outcome != new_outcome {
-                            send_to_everyone!(ChessUpdate::Outcome {
-                                outcome: new_outcome
-                            });
-                        }
-
-                        // Signal other player that he can make his move (as long as not game over)
-                        send_to_everyone!(ChessUpdate::PlayerSwitch {
-                            player: game.turn(),
-                            fen: game.fen(),
-                        });
-
-                        if new_outcome.is_none() {
-                            // Send possible moves to player
-                            send_to_other_player!(ChessUpdate::PossibleMoves {
-                                possible_moves: game
-                                    .possible_moves()
-                                    .iter()
-                                    .map(|bit_move| (
-                                        bit_move.get_src().into(),
-                                        bit_move.get_dest().into()
-                                    ))
-                                    .collect(),
-                            });
-                        }
-                    }
-                    Err(e) => {
-                        send_to_sender!(ChessUpdate::MovePieceFailedResponse {
-                            message: format!("Denied by engine: {}", e),
-                            fen: game.fen(),
-                        });
-                    }
-                };
-            }
-            ChessRequest::Abort { .. /* message */ } => {
-                game.player_left(sender);
-                break;
+                source: square,
+                destination: square,
+                promotion: None,
             },
-            ChessRequest::UndoMoves { moves } => {
-                let player_allowed = match sender {
-                    Player::Black => config.can_black_undo,
-                    Player::White => config.can_white_undo,
-                };
-                if ! player_allowed {
-                    send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
-                        message: "You are not permitted to do that in this game.".to_owned(),
-                    });
-                } else if !(game.turn() == sender && game.outcome().is_none() || game.outcome().is_some() && config.allow_undo_after_loose) {
-                    if config.allow_undo_after_loose {
-                        send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
-                            message: "You can only undo when you are playing or it's game over.".to_owned(),
-                        });
-                    }else {
-                        send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
-                            message: "You can only undo when you are playing.".to_owned(),
-                    });
-                    }
-                }else {
-                    let prev_outcome = game.outcome();
-                    if let Err(e) = game.undo(moves) {
-                        send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
-                            message: format!("Denied by engine: {}", e),
-                        });
-                    }else {
-                        let new_outcome = game.outcome();
-                        if prev_outcome != new_outcome {
-                            send_to_everyone!(ChessUpdate::Outcome {
-                                outcome: new_outcome
-                            });
-                        }
-                        // Select current player and update board
-                        send_to_everyone!(ChessUpdate::PlayerSwitch {
-                            player: game.turn(),
-                            fen: game.fen()
-                        });
-                        // Send the starting player his possible moves
-                        let possible_moves: Vec<_> = game
-                            .possible_moves()
-                            .iter()
-                            .map(|bit_move| (bit_move.get_src().into(), bit_move.get_dest().into()))
-                            .collect();
-                        match game.turn() {
-                            PlecoPlayer::White => white_tx.clone(),
-                            PlecoPlayer::Black => black_tx.clone(),
-                        }
-                        .send(ChessUpdate::PossibleMoves { possible_moves })
-                        .await
-                        .ok();
-                        // Notify everyone of undo
-                        send_to_everyone!(ChessUpdate::MovesUndone {
-                            who: sender,
-                            moves,
-                        });
-
-                    }
-                }
-            }
-            _ => {
-                bail!("available_to_spectator() is probably not up to date with the handlers (player specific handler found a unhandled entry)!!!");
-            }
-        };
-    }
-
-    // Potential cleanup here
-    info!("Game terminated seemingly gracefully");
-    Ok(())
-}
-
-pub async fn create_bot<T: Searcher>(
-    me: Player,
-    depth: u16,
-    min_reaction_delay: Duration,
-) -> Result<(Sender<ChessUpdate>, Receiver<ChessRequest>)> {
-    let (update_tx, mut update_rx) = channel::<ChessUpdate>(256);
-    let (mut request_tx, request_rx) = channel::<ChessRequest>(256);
-
-    task::spawn(async move {
-        info!("Bot spawned for {}", me);
-        let mut current_outcome: Option<ChessOutcome> = None;
-        while let Some(update) = update_rx.recv().await {
-            match update {
-                ChessUpdate::PlayerSwitch { player, ref fen } => {
-                    if player == me && current_outcome.is_none() {
-                        let board = pleco::Board::from_fen(fen)
-                            .expect("Bot failed to parse the provided fen");
-
-                        let bit_move = task::spawn_blocking(move || {
-                            let started = SystemTime::now();
-                            let bit_move = T::best_move(board, depth);
-                            let elapsed = started.elapsed().unwrap_or(Duration::new(0, 0));
-
-                            if elapsed < min_reaction_delay {
-                                thread::sleep(min_reaction_delay - elapsed);
-                            } else {
-                                info!("Bot took a long time to think: {:?}", elapsed);
-                            }
-                            bit_move
-                        })
-                        .await
-                        .context("Blocking heavy calculation")
-                        .unwrap();
+            ChessRequest::DropPiece {
+                piece: DropPiece::Pawn,
+                destination: square,
+            },
+            ChessRequest::Abort {
+                message: message.clone(),
+            },
+            ChessRequest::UndoMoves { moves: 1 },
+            ChessRequest::Resign,
+            ChessRequest::OfferDraw,
+            ChessRequest::PossibleMovesFrom { square },
+            ChessRequest::ReportThinking {
+                depth: 1,
+                best_move: (square, square),
+                eval: 0,
+            },
+        ];
+        let updates = vec![
+            ChessUpdate::Board {
+                fen: message.clone(),
+            },
+            ChessUpdate::PlayerMovedAPiece {
+                player: Player::White,
+                moved_piece_source: square,
+                moved_piece_destination: square,
+                san: None,
+            },
+            ChessUpdate::PlayerSwitch {
+                player: Player::White,
+                fen: message.clone(),
+                in_check: false,
+            },
+            ChessUpdate::MovePieceFailedResponse {
+                message: message.clone(),
+                fen: message.clone(),
+            },
+            ChessUpdate::Outcome { outcome: None },
+            ChessUpdate::PossibleMoves {
+                possible_moves: vec![],
+            },
+            ChessUpdate::PossibleMovesFromResponse {
+                square,
+                possible_moves: vec![],
+            },
+            ChessUpdate::GenericErrorResponse {
+                message: message.clone(),
+            },
+            ChessUpdate::UndoMovesFailedResponse {
+                message: message.clone(),
+            },
+            ChessUpdate::MovesUndone {
+                who: Player::White,
+                moves: 1,
+                last_move: None,
+            },
+            ChessUpdate::CurrentTotalMovesReponse { total_moves: 0 },
+            ChessUpdate::CurrentVariantResponse {
+                variant: VariantKind::Standard,
+            },
+            ChessUpdate::PlayerDroppedAPiece {
+                player: Player::White,
+                piece: DropPiece::Pawn,
+                destination: square,
+            },
+            ChessUpdate::Pockets {
+                pockets: Pockets::default(),
+            },
+            ChessUpdate::PossibleDrops {
+                possible_drops: vec![],
+            },
+            ChessUpdate::DrawOffered {
+                player: Player::White,
+            },
+            ChessUpdate::Clock {
+                clock: ChessClock::new(TimeControl::fischer(1, 0)).snapshot(Instant::now()),
+            },
+            ChessUpdate::FullSync {
+                fen: message.clone(),
+                variant: VariantKind::Standard,
+                turn: Player::White,
+                outcome: None,
+                total_moves: 0,
+                in_check: false,
+                possible_moves: vec![],
+                pockets: None,
+                possible_drops: vec![],
+            },
+            ChessUpdate::Thinking {
+                player: Player::White,
+                depth: 1,
+                best_move: (square, square),
+                eval: 0,
+            },
+        ];
 
-                        request_tx
-                            .send(ChessRequest::MovePiece {
-                                source: bit_move.get_src().into(),
-                                destination: bit_move.get_dest().into(),
-                            })
-                            .await
-                            .expect("Bot failed to send move");
-                    }
-                }
-                ChessUpdate::MovePieceFailedResponse { message, .. } => {
-                    error!("A move from the bot was rejected: {}", message);
-                    break;
-                }
-                ChessUpdate::Outcome { outcome } => {
-                    if outcome.is_some() {
-                        info!("Bot detected that the game ended");
-                    //break;
-                    } else {
-                        info!("Game continues. Bot will continue playing.");
-                    }
-                    current_outcome = outcome;
-                }
-                _ => {}
-            }
+        let request_oneof = schema_oneof("request");
+        for (number, request) in requests.iter().enumerate() {
+            let field = format!(" {} = {};", request_field(request), number + 1);
+            assert!(request_oneof.contains(&field), "{}", field);
         }
-        info!("Bot task has ended");
-    });
-
-    Ok((update_tx, request_rx))
-}
-
-pub fn stubbed_spectator() -> (Sender<ChessUpdate>, Receiver<ChessRequest>) {
-    // Channel size doesn't matter since the channels are closed after this
-    // function returns since one side of each channel gets dropped at that point.
-    let (update_tx, _) = channel::<ChessUpdate>(1);
-    let (_, request_rx) = channel::<ChessRequest>(1);
-    (update_tx, request_rx)
+        let update_oneof = schema_oneof("update");
+        for (number, update) in updates.iter().enumerate() {
+            let field = format!(" {} = {};", update_field(update), number + 1);
+            assert!(update_oneof.contains(&field), "{}", field);
+        }
+        // Nothing in the schema that's gone from the enums
+        assert_eq!(request_oneof.matches(';').count(), requests.len());
+        assert_eq!(update_oneof.matches(';').count(), updates.len());
+    }
 }
@@ -0,0 +1,1427 @@
+//! Runs games between player endpoints (and bots) as tokio tasks
+
+use super::*;
+use crate::clock::ChessClock;
+use crate::game::ChessGame;
+use crate::game::Player as PlecoPlayer;
+use crate::search::Search;
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::stream::StreamExt;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task;
+use tokio::time::timeout;
+
+pub type PlayerEndpoint = (Sender<ChessUpdate>, Receiver<ChessRequest>);
+
+pub enum GameControlRequest {
+    /// Replace the channels of a player in a running game. The previous
+    /// endpoint gets disconnected and the new one receives a `ChessUpdate::FullSync`.
+    SwapPlayer {
+        player: Player,
+        endpoint: PlayerEndpoint,
+    },
+}
+
+/// Handle to manage a game created with `create_controllable_game()`
+#[derive(Clone)]
+pub struct GameControl {
+    control_tx: Sender<GameControlRequest>,
+}
+
+impl GameControl {
+    /// E.g. reconnect a player or let a bot take over
+    pub async fn swap_player(&mut self, player: Player, endpoint: PlayerEndpoint) -> Result<()> {
+        self.control_tx
+            .send(GameControlRequest::SwapPlayer { player, endpoint })
+            .await
+            .map_err(|_| anyhow!("The game has already ended"))
+    }
+}
+
+pub fn create_game_control() -> (GameControl, Receiver<GameControlRequest>) {
+    let (control_tx, control_rx) = channel::<GameControlRequest>(16);
+    (GameControl { control_tx }, control_rx)
+}
+
+enum GameEvent {
+    Request {
+        sender: Option<Player>,
+        /// Requests of replaced endpoints are ignored
+        endpoint_id: usize,
+        request: ChessRequest,
+    },
+    Control {
+        request: GameControlRequest,
+        /// To forward the requests of swapped in endpoints. Only the
+        /// control task holds on to one so the game still ends when
+        /// neither players, spectators nor a control handle are left.
+        combined_tx: Sender<GameEvent>,
+    },
+}
+
+/// Redirect the requests of an endpoint into `combined_tx` with a supplied player for
+/// cleaner handling. Stops with the first request received after the endpoint got
+/// replaced (or when it disconnects).
+fn forward_requests(
+    sender: Option<Player>,
+    endpoint_id: usize,
+    current_endpoint_id: Arc<AtomicUsize>,
+    mut rx: Receiver<ChessRequest>,
+    mut combined_tx: Sender<GameEvent>,
+    abort_when_disconnected: bool,
+) {
+    task::spawn(async move {
+        loop {
+            let request = match rx.next().await {
+                Some(request) => request,
+                None => {
+                    if abort_when_disconnected
+                        && current_endpoint_id.load(Ordering::SeqCst) == endpoint_id
+                    {
+                        combined_tx
+                            .send(GameEvent::Request {
+                                sender,
+                                endpoint_id,
+                                request: ChessRequest::Abort {
+                                    message: "[Internal] Connection lost".to_owned(),
+                                },
+                            })
+                            .await
+                            .ok();
+                    }
+                    return;
+                }
+            };
+            if current_endpoint_id.load(Ordering::SeqCst) != endpoint_id {
+                debug!("Stopped forwarding requests of a replaced endpoint");
+                return;
+            }
+            let event = GameEvent::Request {
+                sender,
+                endpoint_id,
+                request,
+            };
+            if combined_tx.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+pub async fn create_game(
+    white: PlayerEndpoint,
+    black: PlayerEndpoint,
+    spectators: PlayerEndpoint,
+    config: ChessConfig,
+) -> Result<()> {
+    let (_, control_rx) = create_game_control();
+    create_controllable_game(white, black, spectators, config, control_rx).await
+}
+
+/// Like `create_game()` but the players can be replaced at runtime
+/// using the `GameControl` belonging to `control_rx`.
+pub async fn create_controllable_game(
+    white: PlayerEndpoint,
+    black: PlayerEndpoint,
+    spectators: PlayerEndpoint,
+    config: ChessConfig,
+    mut control_rx: Receiver<GameControlRequest>,
+) -> Result<()> {
+    let mut game = if let Some(ref fen) = config.starting_fen {
+        ChessGame::from_fen_with_variant(fen, config.variant)?
+    } else {
+        ChessGame::with_variant(config.variant)?
+    };
+
+    let (mut white_tx, white_rx) = white;
+    let (mut black_tx, black_rx) = black;
+    let (mut spectators_tx, spectators_rx) = spectators;
+
+    let mut clock = config
+        .time_control
+        .map(|time_control| match config.starting_clock {
+            Some(times) => ChessClock::resumed(time_control, times),
+            None => ChessClock::new(time_control),
+        });
+
+    let (combined_tx, mut combined_rx) = channel::<GameEvent>(1024);
+
+    macro_rules! send_to_everyone {
+        ($msg: expr) => {
+            let msg = $msg;
+            white_tx.send(msg.clone()).await.ok();
+            black_tx.send(msg.clone()).await.ok();
+            spectators_tx.send(msg).await.ok();
+        };
+    }
+
+    /// Tell everyone about the pockets and the player to move
+    /// where pieces can be dropped (only in variants with drops)
+    macro_rules! send_pockets {
+        () => {
+            if let Some(pockets) = game.pockets().cloned() {
+                send_to_everyone!(ChessUpdate::Pockets { pockets });
+                let possible_drops = game.possible_drops();
+                match game.turn() {
+                    PlecoPlayer::White => white_tx.clone(),
+                    PlecoPlayer::Black => black_tx.clone(),
+                }
+                .send(ChessUpdate::PossibleDrops { possible_drops })
+                .await
+                .ok();
+            }
+        };
+    }
+
+    /// Tell everyone the state of the clock (only in games with a time control)
+    macro_rules! send_clock {
+        () => {
+            if let Some(ref clock) = clock {
+                send_to_everyone!(ChessUpdate::Clock {
+                    clock: clock.snapshot(Instant::now()),
+                });
+            }
+        };
+    }
+
+    /// `player` finished their turn. The clock stops when the game is over.
+    macro_rules! press_clock {
+        ($player: expr) => {
+            if let Some(ref mut clock) = clock {
+                let now = Instant::now();
+                clock.press($player, now);
+                if game.outcome().is_some() {
+                    clock.pause(now);
+                }
+            }
+            send_clock!();
+        };
+    }
+
+    /// The players decided the game (e.g. by resigning)
+    macro_rules! end_game {
+        () => {
+            if let Some(ref mut clock) = clock {
+                clock.pause(Instant::now());
+            }
+            send_to_everyone!(ChessUpdate::Outcome {
+                outcome: game.outcome()
+            });
+            send_clock!();
+        };
+    }
+
+    // Player whose draw offer is open
+    let mut draw_offered_by: Option<Player> = None;
+
+    let white_endpoint_id = Arc::new(AtomicUsize::new(0));
+    let black_endpoint_id = Arc::new(AtomicUsize::new(0));
+    let mut next_endpoint_id = 1;
+    forward_requests(
+        Some(Player::White),
+        0,
+        white_endpoint_id.clone(),
+        white_rx,
+        combined_tx.clone(),
+        true,
+    );
+    forward_requests(
+        Some(Player::Black),
+        0,
+        black_endpoint_id.clone(),
+        black_rx,
+        combined_tx.clone(),
+        false,
+    );
+    forward_requests(
+        None,
+        0,
+        Arc::new(AtomicUsize::new(0)),
+        spectators_rx,
+        combined_tx.clone(),
+        false,
+    );
+    let mut control_combined_tx = combined_tx;
+    task::spawn(async move {
+        while let Some(request) = control_rx.next().await {
+            let event = GameEvent::Control {
+                request,
+                combined_tx: control_combined_tx.clone(),
+            };
+            if control_combined_tx.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    // Start (if not using a FEN then white starts)
+    send_to_everyone!(ChessUpdate::PlayerSwitch {
+        player: game.turn(),
+        fen: game.fen(),
+        in_check: game.in_check(),
+    });
+    // Send the starting player his possible moves
+    let possible_moves = PossibleMove::from_bit_moves(&game.possible_moves());
+    match game.turn() {
+        PlecoPlayer::White => white_tx.clone(),
+        PlecoPlayer::Black => black_tx.clone(),
+    }
+    .send(ChessUpdate::PossibleMoves { possible_moves })
+    .await
+    .ok();
+    send_pockets!();
+    if let Some(ref mut clock) = clock {
+        if game.outcome().is_none() {
+            clock.switch_to(game.turn(), Instant::now());
+        }
+    }
+    send_clock!();
+
+    info!("Game initialized. Handling requests...");
+
+    // Handle inputs
+    loop {
+        // Wake up when the flag of the player to move falls
+        let flag_falls_in = clock.as_ref().and_then(|clock| {
+            let snapshot = clock.snapshot(Instant::now());
+            snapshot
+                .running
+                .map(|player| snapshot.remaining(player, Duration::default()) + snapshot.delay)
+        });
+        let event = match flag_falls_in {
+            Some(duration) => timeout(duration, combined_rx.next()).await.ok(),
+            None => Some(combined_rx.next().await),
+        };
+        if let Some(player) = clock
+            .as_ref()
+            .and_then(|clock| clock.flagged(Instant::now()))
+            .filter(|_| game.outcome().is_none())
+        {
+            info!("{} ran out of time", player);
+            game.time_over(player);
+            if let Some(ref mut clock) = clock {
+                clock.pause(Instant::now());
+            }
+            send_to_everyone!(ChessUpdate::Outcome {
+                outcome: game.outcome()
+            });
+            send_clock!();
+        }
+        let event = match event {
+            Some(event) => event,
+            None => continue, // Only woke up to check the clock
+        };
+
+        let (sender, request): (Option<Player>, ChessRequest) = match event {
+            Some(GameEvent::Request {
+                sender,
+                endpoint_id,
+                request,
+            }) => {
+                let current_endpoint_id = match sender {
+                    Some(Player::White) => white_endpoint_id.load(Ordering::SeqCst),
+                    Some(Player::Black) => black_endpoint_id.load(Ordering::SeqCst),
+                    None => 0,
+                };
+                if endpoint_id != current_endpoint_id {
+                    debug!("Ignored request from a replaced endpoint: {:?}", request);
+                    continue;
+                }
+                (sender, request)
+            }
+            Some(GameEvent::Control {
+                request: GameControlRequest::SwapPlayer { player, endpoint },
+                combined_tx,
+            }) => {
+                let (new_tx, new_rx) = endpoint;
+                let endpoint_id = next_endpoint_id;
+                next_endpoint_id += 1;
+                let (player_tx, player_endpoint_id) = match player {
+                    Player::White => (&mut white_tx, &white_endpoint_id),
+                    Player::Black => (&mut black_tx, &black_endpoint_id),
+                };
+                // Dropping the old sender disconnects the replaced endpoint
+                *player_tx = new_tx;
+                player_endpoint_id.store(endpoint_id, Ordering::SeqCst);
+                forward_requests(
+                    Some(player),
+                    endpoint_id,
+                    player_endpoint_id.clone(),
+                    new_rx,
+                    combined_tx,
+                    player == Player::White,
+                );
+
+                let (possible_moves, possible_drops) =
+                    if game.turn() == player && game.outcome().is_none() {
+                        (
+                            PossibleMove::from_bit_moves(&game.possible_moves()),
+                            game.possible_drops(),
+                        )
+                    } else {
+                        (vec![], vec![])
+                    };
+                player_tx
+                    .send(ChessUpdate::FullSync {
+                        fen: game.fen(),
+                        variant: game.variant(),
+                        turn: game.turn(),
+                        outcome: game.outcome(),
+                        total_moves: game.total_moves(),
+                        in_check: game.in_check(),
+                        possible_moves,
+                        pockets: game.pockets().cloned(),
+                        possible_drops,
+                    })
+                    .await
+                    .ok();
+                if let Some(ref clock) = clock {
+                    player_tx
+                        .send(ChessUpdate::Clock {
+                            clock: clock.snapshot(Instant::now()),
+                        })
+                        .await
+                        .ok();
+                }
+                info!("Swapped the endpoint of {}", player);
+                continue;
+            }
+            None => {
+                break; // No senders connected anymore
+            }
+        };
+
+        if sender.is_none() && !request.available_to_spectator() {
+            spectators_tx
+                .send(ChessUpdate::GenericErrorResponse {
+                    message: "Spectators can't send this kind of request!".to_owned(),
+                })
+                .await
+                .ok();
+            continue;
+        }
+
+        macro_rules! send_to_sender {
+            ($msg: expr) => {
+                match sender {
+                    Some(player) => match player {
+                        Player::White => white_tx.send($msg).await.ok(),
+                        Player::Black => black_tx.send($msg).await.ok(),
+                    },
+                    None => spectators_tx.send($msg).await.ok(),
+                };
+            };
+        }
+
+        macro_rules! send_to_other_player {
+            ($msg: expr) => {
+                match sender.context("Send to the other player")? {
+                    Player::White => black_tx.send($msg).await.ok(),
+                    Player::Black => white_tx.send($msg).await.ok(),
+                };
+            };
+        }
+
+        // Requests that players as well as spectators can send
+        match request {
+            ChessRequest::CurrentBoard => {
+                send_to_sender!(ChessUpdate::Board { fen: game.fen() });
+            }
+            ChessRequest::CurrentTotalMoves => {
+                send_to_sender!(ChessUpdate::CurrentTotalMovesReponse {
+                    total_moves: game.total_moves()
+                });
+            }
+            ChessRequest::CurrentOutcome => {
+                send_to_sender!(ChessUpdate::Outcome {
+                    outcome: game.outcome()
+                });
+            }
+            ChessRequest::CurrentVariant => {
+                send_to_sender!(ChessUpdate::CurrentVariantResponse {
+                    variant: game.variant()
+                });
+            }
+            _ => {} // Should be handles for a player request
+        }
+
+        // Requests that only players can send
+        let sender = sender
+            .context("available_to_spectator() is probably not up to date with the handlers (message that has to be playerspecific was sent from a spectator)!!!")?;
+        match request {
+            ChessRequest::MovePiece {
+                source,
+                destination,
+                promotion,
+            } => {
+                let prev_outcome = game.outcome();
+                let san = game.san(source, destination, promotion);
+                match game.move_piece_with_promotion(source, destination, promotion) {
+                    Ok(_) => {
+                        // Dunno why, but rust won't compile when using just "Ok". Error in the matrix??
+                        press_clock!(sender);
+                        // Moving declines the offer of the other player
+                        if draw_offered_by == Some(sender.other_player()) {
+                            draw_offered_by = None;
+                        }
+                        send_to_everyone!(ChessUpdate::PlayerMovedAPiece {
+                            player: sender,
+                            moved_piece_source: source,
+                            moved_piece_destination: destination,
+                            san,
+                        });
+                        let new_outcome = game.outcome();
+                        if prev_outcome != new_outcome {
+                            send_to_everyone!(ChessUpdate::Outcome {
+                                outcome: new_outcome
+                            });
+                        }
+
+                        // Signal other player that he can make his move (as long as not game over)
+                        send_to_everyone!(ChessUpdate::PlayerSwitch {
+                            player: game.turn(),
+                            fen: game.fen(),
+                            in_check: game.in_check(),
+                        });
+
+                        if new_outcome.is_none() {
+                            // Send possible moves to player
+                            send_to_other_player!(ChessUpdate::PossibleMoves {
+                                possible_moves: PossibleMove::from_bit_moves(
+                                    &game.possible_moves()
+                                ),
+                            });
+                        }
+                        send_pockets!();
+                    }
+                    Err(e) => {
+                        send_to_sender!(ChessUpdate::MovePieceFailedResponse {
+                            message: format!("Denied by engine: {}", e),
+                            fen: game.fen(),
+                        });
+                    }
+                };
+            }
+            ChessRequest::DropPiece { piece, destination } => {
+                let prev_outcome = game.outcome();
+                let result = if game.turn() != sender {
+                    Err(anyhow!("It's not your turn"))
+                } else {
+                    game.drop_piece(piece, destination)
+                };
+                match result {
+                    Ok(()) => {
+                        press_clock!(sender);
+                        send_to_everyone!(ChessUpdate::PlayerDroppedAPiece {
+                            player: sender,
+                            piece,
+                            destination,
+                        });
+                        let new_outcome = game.outcome();
+                        if prev_outcome != new_outcome {
+                            send_to_everyone!(ChessUpdate::Outcome {
+                                outcome: new_outcome
+                            });
+                        }
+                        send_to_everyone!(ChessUpdate::PlayerSwitch {
+                            player: game.turn(),
+                            fen: game.fen(),
+                            in_check: game.in_check(),
+                        });
+                        if new_outcome.is_none() {
+                            send_to_other_player!(ChessUpdate::PossibleMoves {
+                                possible_moves: PossibleMove::from_bit_moves(
+                                    &game.possible_moves()
+                                ),
+                            });
+                        }
+                        send_pockets!();
+                    }
+                    Err(e) => {
+                        send_to_sender!(ChessUpdate::MovePieceFailedResponse {
+                            message: format!("Denied by engine: {}", e),
+                            fen: game.fen(),
+                        });
+                    }
+                }
+            }
+            ChessRequest::Abort { .. /* message */ } => {
+                game.player_left(sender);
+                break;
+            },
+            ChessRequest::PossibleMovesFrom { square } => {
+                let possible_moves = if game.turn() == sender && game.outcome().is_none() {
+                    PossibleMove::from_bit_moves(&game.possible_moves_from(square))
+                } else {
+                    vec![]
+                };
+                send_to_sender!(ChessUpdate::PossibleMovesFromResponse {
+                    square,
+                    possible_moves,
+                });
+            }
+            ChessRequest::Resign => {
+                if game.outcome().is_none() {
+                    game.resign(sender);
+                    end_game!();
+                }
+            }
+            ChessRequest::OfferDraw => {
+                if game.outcome().is_some() {
+                    continue;
+                }
+                if draw_offered_by == Some(sender.other_player()) {
+                    game.agree_draw();
+                    end_game!();
+                } else if draw_offered_by.is_none() {
+                    draw_offered_by = Some(sender);
+                    send_to_everyone!(ChessUpdate::DrawOffered { player: sender });
+                }
+            }
+            ChessRequest::ReportThinking {
+                depth,
+                best_move,
+                eval,
+            } => {
+                // Might have been sent just before the move or an undo
+                if game.turn() == sender && game.outcome().is_none() {
+                    send_to_everyone!(ChessUpdate::Thinking {
+                        player: sender,
+                        depth,
+                        best_move,
+                        eval,
+                    });
+                }
+            }
+            ChessRequest::UndoMoves { moves } => {
+                let player_allowed = match sender {
+                    Player::Black => config.can_black_undo,
+                    Player::White => config.can_white_undo,
+                };
+                if ! player_allowed {
+                    send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
+                        message: "You are not permitted to do that in this game.".to_owned(),
+                    });
+                } else if !(game.turn() == sender && game.outcome().is_none() || game.outcome().is_some() && config.allow_undo_after_loose) {
+                    if config.allow_undo_after_loose {
+                        send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
+                            message: "You can only undo when you are playing or it's game over.".to_owned(),
+                        });
+                    }else {
+                        send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
+                            message: "You can only undo when you are playing.".to_owned(),
+                    });
+                    }
+                }else {
+                    let prev_outcome = game.outcome();
+                    if let Err(e) = game.undo(moves) {
+                        send_to_sender!(ChessUpdate::UndoMovesFailedResponse {
+                            message: format!("Denied by engine: {}", e),
+                        });
+                    }else {
+                        let new_outcome = game.outcome();
+                        if prev_outcome != new_outcome {
+                            send_to_everyone!(ChessUpdate::Outcome {
+                                outcome: new_outcome
+                            });
+                        }
+                        // The time of the player to move runs again
+                        if let Some(ref mut clock) = clock {
+                            let now = Instant::now();
+                            if new_outcome.is_none() {
+                                clock.resume(now);
+                            }
+                            clock.switch_to(game.turn(), now);
+                        }
+                        send_clock!();
+                        // Select current player and update board
+                        send_to_everyone!(ChessUpdate::PlayerSwitch {
+                            player: game.turn(),
+                            fen: game.fen(),
+                            in_check: game.in_check(),
+                        });
+                        // Send the starting player his possible moves
+                        let possible_moves = PossibleMove::from_bit_moves(&game.possible_moves());
+                        match game.turn() {
+                            PlecoPlayer::White => white_tx.clone(),
+                            PlecoPlayer::Black => black_tx.clone(),
+                        }
+                        .send(ChessUpdate::PossibleMoves { possible_moves })
+                        .await
+                        .ok();
+                        send_pockets!();
+                        // Notify everyone of undo
+                        send_to_everyone!(ChessUpdate::MovesUndone {
+                            who: sender,
+                            moves,
+                            last_move: game.last_move(),
+                        });
+
+                    }
+                }
+            }
+            _ => {
+                bail!("available_to_spectator() is probably not up to date with the handlers (player specific handler found a unhandled entry)!!!");
+            }
+        };
+    }
+
+    // Potential cleanup here
+    info!("Game terminated seemingly gracefully");
+    Ok(())
+}
+
+/// The bot plays the best move found so far when thinking longer
+pub const BOT_TIME_BUDGET: Duration = Duration::from_secs(30);
+/// How often the bot looks at its search and the updates of the game
+const BOT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A bot playing `me` that searches `depth` plies on `threads` threads (see
+/// `crate::search`).
+///
+/// With a `seed` the bot picks among equally good moves with it, so it plays
+/// the same moves every run (for tests and bug reports).
+///
+/// After every finished ply, the bot reports its progress with
+/// `ChessRequest::ReportThinking`.
+///
+/// While searching, the bot keeps listening to the game. An undo, the end
+/// of the game (e.g. a resignation) or a closed game cancel the search. A
+/// search exceeding `BOT_TIME_BUDGET` is given up and the best move of the
+/// deepest finished ply played instead.
+pub async fn create_bot(
+    me: Player,
+    depth: u16,
+    threads: usize,
+    min_reaction_delay: Duration,
+    seed: Option<u64>,
+) -> Result<(Sender<ChessUpdate>, Receiver<ChessRequest>)> {
+    let (update_tx, mut update_rx) = channel::<ChessUpdate>(256);
+    let (mut request_tx, request_rx) = channel::<ChessRequest>(256);
+
+    task::spawn(async move {
+        match seed {
+            Some(seed) => info!("Bot spawned for {} (seed {})", me, seed),
+            None => info!("Bot spawned for {}", me),
+        }
+        let mut current_outcome: Option<ChessOutcome> = None;
+        // Received while searching, handled after cancelling the search
+        let mut interrupting_update: Option<ChessUpdate> = None;
+        loop {
+            let update = match interrupting_update.take() {
+                Some(update) => update,
+                None => match update_rx.recv().await {
+                    Some(update) => update,
+                    None => break,
+                },
+            };
+            let fen_to_move_on = match update {
+                ChessUpdate::PlayerSwitch { player, fen, .. } if player == me => Some(fen),
+                ChessUpdate::FullSync {
+                    fen, turn, outcome, ..
+                } => {
+                    // Took over a running game
+                    current_outcome = outcome;
+                    if turn == me {
+                        Some(fen)
+                    } else {
+                        None
+                    }
+                }
+                ChessUpdate::MovePieceFailedResponse { message, .. } => {
+                    error!("A move from the bot was rejected: {}", message);
+                    break;
+                }
+                ChessUpdate::Outcome { outcome } => {
+                    if outcome.is_some() {
+                        info!("Bot detected that the game ended");
+                    //break;
+                    } else {
+                        info!("Game continues. Bot will continue playing.");
+                    }
+                    current_outcome = outcome;
+                    None
+                }
+                _ => None,
+            };
+
+            if let Some(fen) = fen_to_move_on {
+                if current_outcome.is_some() {
+                    continue;
+                }
+                let board =
+                    pleco::Board::from_fen(&fen).expect("Bot failed to parse the provided fen");
+
+                let started = SystemTime::now();
+                let search = Search::start(board, depth, threads, seed);
+                let mut game_closed = false;
+                let mut reported_depth = 0;
+                let bit_move = loop {
+                    // Before reporting, so the last ply is reported too
+                    let finished = search.is_finished();
+                    if let Some(progress) = search.best_so_far() {
+                        if progress.depth > reported_depth {
+                            reported_depth = progress.depth;
+                            request_tx
+                                .send(ChessRequest::ReportThinking {
+                                    depth: progress.depth,
+                                    best_move: (
+                                        progress.best_move.get_src().into(),
+                                        progress.best_move.get_dest().into(),
+                                    ),
+                                    eval: progress.score,
+                                })
+                                .await
+                                .ok();
+                        }
+                    }
+                    let elapsed = started.elapsed().unwrap_or(Duration::new(0, 0));
+                    if finished && elapsed >= min_reaction_delay {
+                        if elapsed > min_reaction_delay + BOT_POLL_INTERVAL {
+                            info!("Bot took a long time to think: {:?}", elapsed);
+                        }
+                        break search.best_so_far();
+                    }
+                    if elapsed >= BOT_TIME_BUDGET {
+                        if let Some(progress) = search.best_so_far() {
+                            warn!(
+                                "Bot exceeded its time budget, playing the move of depth {}",
+                                progress.depth
+                            );
+                            break Some(progress);
+                        }
+                    }
+                    match timeout(BOT_POLL_INTERVAL, update_rx.recv()).await {
+                        // Still thinking
+                        Err(_) => {}
+                        Ok(None) => {
+                            game_closed = true;
+                            break None;
+                        }
+                        Ok(Some(update)) => match update {
+                            ChessUpdate::PlayerSwitch { .. }
+                            | ChessUpdate::FullSync { .. }
+                            | ChessUpdate::Outcome { .. }
+                            | ChessUpdate::MovesUndone { .. }
+                            | ChessUpdate::MovePieceFailedResponse { .. } => {
+                                info!("Bot stopped thinking, the game went on");
+                                interrupting_update = Some(update);
+                                break None;
+                            }
+                            _ => {}
+                        },
+                    }
+                };
+                // Stops it if it's still searching
+                drop(search);
+                if game_closed {
+                    break;
+                }
+                let bit_move = match bit_move {
+                    Some(progress) => progress.best_move,
+                    // Cancelled or no legal move
+                    None => continue,
+                };
+
+                request_tx
+                    .send(ChessRequest::MovePiece {
+                        source: bit_move.get_src().into(),
+                        destination: bit_move.get_dest().into(),
+                        promotion: if bit_move.is_promo() {
+                            PromotionPiece::from_piece_type(bit_move.promo_piece())
+                        } else {
+                            None
+                        },
+                    })
+                    .await
+                    .expect("Bot failed to send move");
+            }
+        }
+        info!("Bot task has ended");
+    });
+
+    Ok((update_tx, request_rx))
+}
+
+pub fn stubbed_spectator() -> (Sender<ChessUpdate>, Receiver<ChessRequest>) {
+    // Channel size doesn't matter since the channels are closed after this
+    // function returns since one side of each channel gets dropped at that point.
+    let (update_tx, _) = channel::<ChessUpdate>(1);
+    let (_, request_rx) = channel::<ChessRequest>(1);
+    (update_tx, request_rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ClockType;
+    use std::future::Future;
+    use tokio::runtime;
+    use tokio::time::timeout;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// The game side and the client side of a player endpoint
+    fn endpoint() -> (
+        PlayerEndpoint,
+        (Sender<ChessRequest>, Receiver<ChessUpdate>),
+    ) {
+        let (update_tx, update_rx) = channel::<ChessUpdate>(256);
+        let (request_tx, request_rx) = channel::<ChessRequest>(256);
+        ((update_tx, request_rx), (request_tx, update_rx))
+    }
+
+    fn config() -> ChessConfig {
+        ChessConfig {
+            starting_fen: None,
+            can_black_undo: true,
+            can_white_undo: true,
+            allow_undo_after_loose: false,
+            variant: VariantKind::Standard,
+            time_control: None,
+            starting_clock: None,
+        }
+    }
+
+    fn move_piece(source: &str, destination: &str) -> ChessRequest {
+        ChessRequest::MovePiece {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+            promotion: None,
+        }
+    }
+
+    fn run<F: Future>(future: F) -> F::Output {
+        runtime::Builder::new()
+            .threaded_scheduler()
+            .enable_time()
+            .build()
+            .expect("Failed to create tokio runtime")
+            .block_on(future)
+    }
+
+    /// Skip updates until one matches
+    async fn wait_for<F: Fn(&ChessUpdate) -> bool>(
+        update_rx: &mut Receiver<ChessUpdate>,
+        matches: F,
+    ) -> ChessUpdate {
+        timeout(TIMEOUT, async {
+            loop {
+                let update = update_rx.recv().await.expect("Endpoint got disconnected");
+                if matches(&update) {
+                    return update;
+                }
+            }
+        })
+        .await
+        .expect("Timed out waiting for an update")
+    }
+
+    #[test]
+    fn swapped_in_endpoint_receives_full_sync() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let (black, _old_black) = endpoint();
+            let (mut control, control_rx) = create_game_control();
+            task::spawn(create_controllable_game(
+                white,
+                black,
+                stubbed_spectator(),
+                config(),
+                control_rx,
+            ));
+
+            white_tx.send(move_piece("E2", "E4")).await.unwrap();
+            wait_for(&mut white_rx, |update| match update {
+                ChessUpdate::PlayerSwitch { player, .. } => *player == Player::Black,
+                _ => false,
+            })
+            .await;
+
+            let (new_black, (_new_black_tx, mut new_black_rx)) = endpoint();
+            control.swap_player(Player::Black, new_black).await.unwrap();
+            match wait_for(&mut new_black_rx, |update| {
+                matches!(update, ChessUpdate::FullSync { .. })
+            })
+            .await
+            {
+                ChessUpdate::FullSync {
+                    variant,
+                    turn,
+                    outcome,
+                    total_moves,
+                    possible_moves,
+                    pockets,
+                    ..
+                } => {
+                    assert_eq!(variant, VariantKind::Standard);
+                    assert_eq!(pockets, None);
+                    assert_eq!(turn, Player::Black);
+                    assert_eq!(outcome, None);
+                    assert_eq!(total_moves, 1);
+                    assert_eq!(possible_moves.len(), 20);
+                }
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    #[test]
+    fn replaced_endpoint_is_disconnected_and_ignored() {
+        run(async {
+            let (white, (mut old_white_tx, mut old_white_rx)) = endpoint();
+            let (black, _black) = endpoint();
+            let (mut control, control_rx) = create_game_control();
+            task::spawn(create_controllable_game(
+                white,
+                black,
+                stubbed_spectator(),
+                config(),
+                control_rx,
+            ));
+
+            let (new_white, (mut new_white_tx, mut new_white_rx)) = endpoint();
+            control.swap_player(Player::White, new_white).await.unwrap();
+            wait_for(&mut new_white_rx, |update| {
+                matches!(update, ChessUpdate::FullSync { .. })
+            })
+            .await;
+
+            // Moves of the old endpoint don't count anymore
+            old_white_tx.send(move_piece("E2", "E4")).await.ok();
+            new_white_tx.send(ChessRequest::CurrentBoard).await.unwrap();
+            match wait_for(&mut new_white_rx, |update| {
+                matches!(update, ChessUpdate::Board { .. })
+            })
+            .await
+            {
+                ChessUpdate::Board { fen } => assert_eq!(fen, ChessGame::default().fen()),
+                _ => unreachable!(),
+            }
+
+            // The game dropped its sender to the old endpoint
+            timeout(TIMEOUT, async {
+                while old_white_rx.recv().await.is_some() {}
+            })
+            .await
+            .expect("Old endpoint is still connected");
+        });
+    }
+
+    #[test]
+    fn closing_replaced_endpoint_does_not_abort_game() {
+        run(async {
+            let (white, old_white) = endpoint();
+            let (black, _black) = endpoint();
+            let (mut control, control_rx) = create_game_control();
+            task::spawn(create_controllable_game(
+                white,
+                black,
+                stubbed_spectator(),
+                config(),
+                control_rx,
+            ));
+
+            let (new_white, (mut new_white_tx, mut new_white_rx)) = endpoint();
+            control.swap_player(Player::White, new_white).await.unwrap();
+            wait_for(&mut new_white_rx, |update| {
+                matches!(update, ChessUpdate::FullSync { .. })
+            })
+            .await;
+            drop(old_white);
+
+            new_white_tx.send(move_piece("E2", "E4")).await.unwrap();
+            wait_for(&mut new_white_rx, |update| match update {
+                ChessUpdate::PlayerMovedAPiece { player, .. } => *player == Player::White,
+                _ => false,
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn game_ends_when_swapped_in_white_disconnects() {
+        run(async {
+            let (white, _old_white) = endpoint();
+            let (black, _black) = endpoint();
+            let (mut control, control_rx) = create_game_control();
+            let game = task::spawn(create_controllable_game(
+                white,
+                black,
+                stubbed_spectator(),
+                config(),
+                control_rx,
+            ));
+
+            let (new_white, (new_white_tx, mut new_white_rx)) = endpoint();
+            control.swap_player(Player::White, new_white).await.unwrap();
+            wait_for(&mut new_white_rx, |update| {
+                matches!(update, ChessUpdate::FullSync { .. })
+            })
+            .await;
+            drop(new_white_tx);
+
+            let result = timeout(TIMEOUT, game).await.expect("Game didn't end");
+            assert!(result.expect("Game task panicked").is_ok());
+        });
+    }
+
+    #[test]
+    fn bot_can_take_over_running_game() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let (black, _old_black) = endpoint();
+            let (mut control, control_rx) = create_game_control();
+            task::spawn(create_controllable_game(
+                white,
+                black,
+                stubbed_spectator(),
+                config(),
+                control_rx,
+            ));
+
+            white_tx.send(move_piece("E2", "E4")).await.unwrap();
+            wait_for(&mut white_rx, |update| match update {
+                ChessUpdate::PlayerSwitch { player, .. } => *player == Player::Black,
+                _ => false,
+            })
+            .await;
+
+            let bot = create_bot(Player::Black, 2, 1, Duration::from_millis(0), None)
+                .await
+                .unwrap();
+            control.swap_player(Player::Black, bot).await.unwrap();
+            wait_for(&mut white_rx, |update| match update {
+                ChessUpdate::PlayerMovedAPiece { player, .. } => *player == Player::Black,
+                _ => false,
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn game_ends_when_flag_falls() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let (black, _black) = endpoint();
+            task::spawn(create_game(
+                white,
+                black,
+                stubbed_spectator(),
+                ChessConfig {
+                    time_control: Some(TimeControl {
+                        base: Duration::from_millis(200),
+                        bonus: Duration::from_secs(0),
+                        clock_type: ClockType::Fischer,
+                    }),
+                    ..config()
+                },
+            ));
+
+            white_tx.send(move_piece("E2", "E4")).await.unwrap();
+            match wait_for(&mut white_rx, |update| {
+                matches!(update, ChessUpdate::Outcome { .. })
+            })
+            .await
+            {
+                ChessUpdate::Outcome { outcome } => assert_eq!(
+                    outcome,
+                    Some(ChessOutcome::Timeout {
+                        winner: Player::White
+                    })
+                ),
+                _ => unreachable!(),
+            }
+            match wait_for(&mut white_rx, |update| {
+                matches!(update, ChessUpdate::Clock { .. })
+            })
+            .await
+            {
+                ChessUpdate::Clock { clock } => {
+                    assert_eq!(clock.running, None);
+                    assert_eq!(clock.black, Duration::from_secs(0));
+                }
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    /// The moves of the first `plies` of a game between two seeded bots
+    async fn seeded_bot_game(seed: u64, plies: usize) -> Vec<String> {
+        let white = create_bot(Player::White, 3, 2, Duration::default(), Some(seed))
+            .await
+            .unwrap();
+        let black = create_bot(Player::Black, 3, 2, Duration::default(), Some(seed))
+            .await
+            .unwrap();
+        let (spectator, (_spectator_tx, mut spectator_rx)) = endpoint();
+        task::spawn(create_game(white, black, spectator, config()));
+
+        let mut moves = vec![];
+        while moves.len() < plies {
+            match wait_for(&mut spectator_rx, |update| {
+                matches!(update, ChessUpdate::PlayerMovedAPiece { .. })
+            })
+            .await
+            {
+                ChessUpdate::PlayerMovedAPiece { san, .. } => moves.push(san.unwrap()),
+                _ => unreachable!(),
+            }
+        }
+        moves
+    }
+
+    #[test]
+    fn seeded_bots_play_the_same_game() {
+        let first = run(seeded_bot_game(1, 8));
+        let second = run(seeded_bot_game(1, 8));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn bots_with_other_seeds_play_other_games() {
+        let first = run(seeded_bot_game(1, 8));
+        assert!((2..10).any(|seed| run(seeded_bot_game(seed, 8)) != first));
+    }
+
+    #[test]
+    fn bot_reports_its_thinking() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let black = create_bot(Player::Black, 3, 1, Duration::default(), None)
+                .await
+                .unwrap();
+            task::spawn(create_game(white, black, stubbed_spectator(), config()));
+
+            white_tx.send(move_piece("E2", "E4")).await.unwrap();
+            match wait_for(&mut white_rx, |update| {
+                matches!(update, ChessUpdate::Thinking { .. })
+            })
+            .await
+            {
+                ChessUpdate::Thinking { player, depth, .. } => {
+                    assert_eq!(player, Player::Black);
+                    assert!(depth >= 1);
+                }
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    #[test]
+    fn thinking_is_ignored_when_not_to_move() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let (black, (mut black_tx, mut black_rx)) = endpoint();
+            task::spawn(create_game(white, black, stubbed_spectator(), config()));
+
+            let square = "E5".parse().unwrap();
+            black_tx
+                .send(ChessRequest::ReportThinking {
+                    depth: 1,
+                    best_move: (square, square),
+                    eval: 0,
+                })
+                .await
+                .unwrap();
+            // Handled in order, so the report was once this is rejected
+            black_tx.send(move_piece("E7", "E5")).await.unwrap();
+            wait_for(&mut black_rx, |update| {
+                matches!(update, ChessUpdate::MovePieceFailedResponse { .. })
+            })
+            .await;
+            white_tx.send(move_piece("E2", "E4")).await.unwrap();
+            let update = wait_for(&mut white_rx, |update| {
+                matches!(
+                    update,
+                    ChessUpdate::Thinking { .. } | ChessUpdate::PlayerMovedAPiece { .. }
+                )
+            })
+            .await;
+            assert!(
+                matches!(update, ChessUpdate::PlayerMovedAPiece { .. }),
+                "{:?}",
+                update
+            );
+        });
+    }
+
+    #[test]
+    fn moves_are_sent_with_san() {
+        run(async {
+            let (white, (mut white_tx, _white_rx)) = endpoint();
+            let (black, (_black_tx, mut black_rx)) = endpoint();
+            task::spawn(create_game(white, black, stubbed_spectator(), config()));
+
+            white_tx.send(move_piece("G1", "F3")).await.unwrap();
+            match wait_for(&mut black_rx, |update| {
+                matches!(update, ChessUpdate::PlayerMovedAPiece { .. })
+            })
+            .await
+            {
+                ChessUpdate::PlayerMovedAPiece { san, .. } => {
+                    assert_eq!(san.as_deref(), Some("Nf3"))
+                }
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    #[test]
+    fn possible_moves_from_one_square() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let (black, (mut black_tx, mut black_rx)) = endpoint();
+            task::spawn(create_game(white, black, stubbed_spectator(), config()));
+
+            white_tx
+                .send(ChessRequest::PossibleMovesFrom {
+                    square: "G1".parse().unwrap(),
+                })
+                .await
+                .unwrap();
+            match wait_for(&mut white_rx, |update| {
+                matches!(update, ChessUpdate::PossibleMovesFromResponse { .. })
+            })
+            .await
+            {
+                ChessUpdate::PossibleMovesFromResponse {
+                    square,
+                    possible_moves,
+                } => {
+                    assert_eq!(square, "G1".parse().unwrap());
+                    let mut destinations: Vec<Square> = possible_moves
+                        .iter()
+                        .map(|possible_move| possible_move.destination)
+                        .collect();
+                    destinations.sort_by_key(|destination| destination.to_string());
+                    assert_eq!(
+                        destinations,
+                        vec!["F3".parse().unwrap(), "H3".parse().unwrap()]
+                    );
+                }
+                _ => unreachable!(),
+            }
+
+            // Not the turn of black
+            black_tx
+                .send(ChessRequest::PossibleMovesFrom {
+                    square: "G8".parse().unwrap(),
+                })
+                .await
+                .unwrap();
+            match wait_for(&mut black_rx, |update| {
+                matches!(update, ChessUpdate::PossibleMovesFromResponse { .. })
+            })
+            .await
+            {
+                ChessUpdate::PossibleMovesFromResponse { possible_moves, .. } => {
+                    assert!(possible_moves.is_empty())
+                }
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    #[test]
+    fn draw_offered_by_both_players_ends_the_game() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let (black, (mut black_tx, mut black_rx)) = endpoint();
+            task::spawn(create_game(white, black, stubbed_spectator(), config()));
+
+            white_tx.send(ChessRequest::OfferDraw).await.unwrap();
+            match wait_for(&mut black_rx, |update| {
+                matches!(update, ChessUpdate::DrawOffered { .. })
+            })
+            .await
+            {
+                ChessUpdate::DrawOffered { player } => assert_eq!(player, Player::White),
+                _ => unreachable!(),
+            }
+            black_tx.send(ChessRequest::OfferDraw).await.unwrap();
+            match wait_for(&mut white_rx, |update| {
+                matches!(update, ChessUpdate::Outcome { .. })
+            })
+            .await
+            {
+                ChessUpdate::Outcome { outcome } => {
+                    assert_eq!(outcome, Some(ChessOutcome::Agreement))
+                }
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    #[test]
+    fn crazyhouse_pieces_can_be_dropped() {
+        run(async {
+            let (white, (mut white_tx, mut white_rx)) = endpoint();
+            let (black, (mut black_tx, _black_rx)) = endpoint();
+            task::spawn(create_game(
+                white,
+                black,
+                stubbed_spectator(),
+                ChessConfig {
+                    variant: VariantKind::Crazyhouse,
+                    ..config()
+                },
+            ));
+
+            // Each move after the previous one was played, the endpoints
+            // are forwarded independently
+            let moves = [
+                (Player::White, "E2", "E4"),
+                (Player::Black, "D7", "D5"),
+                (Player::White, "E4", "D5"),
+                (Player::Black, "D8", "D5"),
+            ];
+            for (player, source, destination) in moves.iter() {
+                let tx = match player {
+                    Player::White => &mut white_tx,
+                    Player::Black => &mut black_tx,
+                };
+                tx.send(move_piece(source, destination)).await.unwrap();
+                wait_for(&mut white_rx, |update| match update {
+                    ChessUpdate::PlayerSwitch { player: next, .. } => *next != *player,
+                    _ => false,
+                })
+                .await;
+            }
+            match wait_for(&mut white_rx, |update| {
+                matches!(update, ChessUpdate::PossibleDrops { .. })
+            })
+            .await
+            {
+                ChessUpdate::PossibleDrops { possible_drops } => {
+                    assert!(possible_drops.contains(&(DropPiece::Pawn, "E6".parse().unwrap())));
+                }
+                _ => unreachable!(),
+            }
+
+            white_tx
+                .send(ChessRequest::DropPiece {
+                    piece: DropPiece::Pawn,
+                    destination: "E6".parse().unwrap(),
+                })
+                .await
+                .unwrap();
+            wait_for(&mut white_rx, |update| match update {
+                ChessUpdate::PlayerDroppedAPiece { player, .. } => *player == Player::White,
+                _ => false,
+            })
+            .await;
+            match wait_for(&mut white_rx, |update| {
+                matches!(update, ChessUpdate::Pockets { .. })
+            })
+            .await
+            {
+                ChessUpdate::Pockets { pockets } => assert!(pockets.white.is_empty()),
+                _ => unreachable!(),
+            }
+        });
+    }
+}
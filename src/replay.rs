@@ -1,18 +1,19 @@
 use crate::game::ChessGame;
 pub use crate::game::{ChessOutcome, SQ};
-use crate::Square;
-use anyhow::Error;
-use chess_pgn_parser::File as LocalFile;
-use chess_pgn_parser::Piece as LocalPiece;
-use chess_pgn_parser::Rank as LocalRank;
+use crate::notation;
+use crate::{Player, Square};
+use anyhow::{Error, Result};
 use chess_pgn_parser::{Game, GameMove, GameTermination, Move};
-use pleco::core::Piece::{
-    BlackBishop, BlackKing, BlackKnight, BlackPawn, BlackQueen, BlackRook, WhiteBishop, WhiteKing,
-    WhiteKnight, WhitePawn, WhiteQueen, WhiteRook,
-};
-use pleco::{File, Piece, Rank};
+use pleco::bot_prelude::AlphaBetaSearcher;
+use pleco::tools::Searcher;
+use pleco::BitMove;
+use std::fmt;
+use std::time::Duration;
 
-const FEN_TAG: &str = "FEN";
+/// Search depth used to check whether a wrong guess is still a good move
+const GUESS_ENGINE_DEPTH: u16 = 3;
+/// A mate is worth more than any material
+const MATE_CENTIPAWNS: i32 = 10_000;
 
 pub struct ReplayResponse {
     pub fen: String,
@@ -21,37 +22,350 @@ pub struct ReplayResponse {
     pub last_move_to: Option<Square>,
 }
 
+/// A move of the replay as it's written down (e.g. for exports)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayMove {
+    pub number: u16,
+    pub player: Player,
+    pub san: String,
+    /// FEN of the position after the move
+    pub fen: String,
+    /// Time left of the player after the move (`[%clk ...]` in the comment)
+    pub clock: Option<Duration>,
+    /// Evaluation after the move (`[%eval ...]` in the comment)
+    pub eval: Option<CommentEval>,
+    /// The comment without its commands
+    pub comment: Option<String>,
+}
+
+/// Engine evaluation written in a comment, always from white's side
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CommentEval {
+    /// Advantage in pawns (e.g. `[%eval -1.3]`)
+    Pawns(f32),
+    /// Mate in this many moves, negative if black mates (e.g. `[%eval #-3]`)
+    Mate(i16),
+}
+
+impl CommentEval {
+    /// Mates count as `MATE_CENTIPAWNS`
+    pub fn centipawns(self) -> i32 {
+        match self {
+            CommentEval::Pawns(pawns) => (pawns * 100.0).round() as i32,
+            CommentEval::Mate(moves) if moves < 0 => -MATE_CENTIPAWNS,
+            CommentEval::Mate(_) => MATE_CENTIPAWNS,
+        }
+    }
+}
+
+impl fmt::Display for CommentEval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommentEval::Pawns(pawns) => write!(f, "{:+.1}", pawns),
+            CommentEval::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+/// A move comment split into the commands of lichess exports
+/// (`[%clk 0:03:21]` and `[%eval -1.3]`) and the rest of its text
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MoveComment {
+    pub clock: Option<Duration>,
+    pub eval: Option<CommentEval>,
+    /// `None` if the comment only had commands
+    pub text: Option<String>,
+}
+
+impl MoveComment {
+    /// Unknown commands (e.g. the arrows of `[%cal ...]`) are dropped
+    pub fn parse(comment: &str) -> Self {
+        let mut parsed = MoveComment::default();
+        let mut text = String::new();
+        let mut rest = comment;
+        while let Some(start) = rest.find("[%") {
+            text.push_str(&rest[..start]);
+            let command = &rest[start + 2..];
+            let end = match command.find(']') {
+                Some(end) => end,
+                None => {
+                    rest = &rest[start..];
+                    break;
+                }
+            };
+            let mut parts = command[..end].split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("clk"), Some(value)) => parsed.clock = parse_clock(value),
+                (Some("eval"), Some(value)) => parsed.eval = parse_eval(value),
+                _ => {}
+            }
+            rest = &command[end + 1..];
+        }
+        text.push_str(rest);
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        parsed.text = Some(text).filter(|text| !text.is_empty());
+        parsed
+    }
+}
+
+/// E.g. "0:03:21" or "0:00:09.8"
+fn parse_clock(value: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.parse().ok()?;
+        if part.is_nan() || part < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Some(Duration::from_millis((seconds * 1000.0).round() as u64))
+}
+
+/// E.g. "0.17", "#-3" or "0.17,20" (with the search depth)
+fn parse_eval(value: &str) -> Option<CommentEval> {
+    let value = value.split(',').next()?;
+    match value.strip_prefix('#') {
+        Some(moves) => moves.parse().ok().map(CommentEval::Mate),
+        None => value
+            .parse()
+            .ok()
+            .filter(|pawns: &f32| pawns.is_finite())
+            .map(CommentEval::Pawns),
+    }
+}
+
+/// A half-move of a game as walked by `positions()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayPosition {
+    /// Index of the half-move in the game (starting at 0)
+    pub ply: usize,
+    pub player: Player,
+    pub san: String,
+    /// Position before the move
+    pub fen_before: String,
+    /// Position after the move
+    pub fen: String,
+    pub bit_move: BitMove,
+}
+
+/// Iterator over the moves of a game (see `positions()`)
+pub struct Positions<'a> {
+    game: ChessGame,
+    moves: std::slice::Iter<'a, GameMove>,
+    ply: usize,
+}
+
+impl<'a> Iterator for Positions<'a> {
+    type Item = ReplayPosition;
+
+    fn next(&mut self) -> Option<ReplayPosition> {
+        let played_move = &self.moves.next()?.move_.move_;
+        let board = self.game.board();
+        let fen_before = self.game.fen();
+        let bit_move = self.game.find_pgn_move(played_move).ok()?;
+        self.game.play_pgn_move(played_move).ok()?;
+        let position = ReplayPosition {
+            ply: self.ply,
+            player: board.turn().into(),
+            san: notation::san(&board, bit_move),
+            fen_before,
+            fen: self.game.fen(),
+            bit_move,
+        };
+        self.ply += 1;
+        Some(position)
+    }
+}
+
+/// Walk the moves of `game` from its start without a `Replay` (e.g. to
+/// export or analyse it). Stops at the first move that can't be played.
+pub fn positions(game: &Game) -> Result<Positions<'_>> {
+    Ok(Positions {
+        game: ChessGame::pgn_starting_position(game)?,
+        moves: game.moves.iter(),
+        ply: 0,
+    })
+}
+
+/// How a guessed move compares to the one actually played in the replay
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GuessOutcome {
+    Exact,
+    /// Not the replay move, but what the engine would have played
+    EngineApproved,
+    Miss,
+}
+
 pub struct Replay {
     active_game: ChessGame,
     replay_info: Game,
+    /// Who played the first move of the replay
+    first_player: Player,
     replay_moves_played_offset: usize,
     player_moves_played_offset: usize,
-    is_white_turn: bool,
 }
 
 impl Replay {
-    pub fn new(replay_info: Game) -> Self {
-        let starting_fen = replay_info
-            .tags
-            .iter()
-            .find(|tag| tag.to_owned().0 == FEN_TAG);
-        let active_game_state = match starting_fen {
-            Some(fen) => ChessGame::from_fen(&fen.1).unwrap(),
-            _ => ChessGame::default(),
-        };
-        Self {
+    /// Fails if the FEN or Variant tag of the game can't be read
+    pub fn new(replay_info: Game) -> Result<Self> {
+        let active_game_state = ChessGame::pgn_starting_position(&replay_info)?;
+        Ok(Self {
+            first_player: active_game_state.turn(),
             active_game: active_game_state,
             replay_info,
             replay_moves_played_offset: 0,
             player_moves_played_offset: 0,
-            is_white_turn: true,
+        })
+    }
+
+    pub fn fen(&self) -> String {
+        self.active_game.fen()
+    }
+
+    /// Number of replay moves played. `None` if manual moves were played
+    /// (the position isn't part of the replay then).
+    pub fn replay_position(&self) -> Option<usize> {
+        if self.player_moves_played_offset > 0 {
+            None
+        } else {
+            Some(self.replay_moves_played_offset)
         }
     }
 
-    pub fn possible_moves(&self) -> pleco::MoveList {
+    pub fn possible_moves(&self) -> Vec<BitMove> {
         self.active_game.possible_moves()
     }
 
+    /// The game as read from the PGN
+    pub fn game(&self) -> &Game {
+        &self.replay_info
+    }
+
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.replay_info.tags
+    }
+
+    /// Result of the game as written in PGNs ("1-0", "0-1", "1/2-1/2" or "*")
+    pub fn result(&self) -> &'static str {
+        match self.replay_info.termination {
+            GameTermination::WhiteWins => "1-0",
+            GameTermination::BlackWins => "0-1",
+            GameTermination::DrawnGame => "1/2-1/2",
+            GameTermination::Unknown => "*",
+        }
+    }
+
+    /// All moves of the replay from its start, independent of the current
+    /// position. Stops early at a move that can't be played.
+    pub fn moves(&self) -> Vec<ReplayMove> {
+        let positions = match positions(&self.replay_info) {
+            Ok(positions) => positions,
+            Err(_) => return vec![],
+        };
+        positions
+            .map(|position| {
+                let comment = self.move_comment(position.ply);
+                ReplayMove {
+                    number: position
+                        .fen_before
+                        .split(' ')
+                        .nth(5)
+                        .and_then(|number| number.parse().ok())
+                        .unwrap_or(1),
+                    player: position.player,
+                    san: position.san,
+                    fen: position.fen,
+                    clock: comment.clock,
+                    eval: comment.eval,
+                    comment: comment.text,
+                }
+            })
+            .collect()
+    }
+
+    /// The comment of the replay move at `ply` (empty if it has none)
+    fn move_comment(&self, ply: usize) -> MoveComment {
+        self.replay_info
+            .moves
+            .get(ply)
+            .and_then(|game_move| game_move.comment.as_deref())
+            .map(MoveComment::parse)
+            .unwrap_or_default()
+    }
+
+    fn ply_player(&self, ply: usize) -> Player {
+        if ply % 2 == 0 {
+            self.first_player
+        } else {
+            self.first_player.other_player()
+        }
+    }
+
+    /// Whether the comments of the moves have clock times
+    pub fn has_clocks(&self) -> bool {
+        (0..self.replay_info.moves.len()).any(|ply| self.move_comment(ply).clock.is_some())
+    }
+
+    /// Whether the comments of the moves have evaluations
+    pub fn has_evals(&self) -> bool {
+        (0..self.replay_info.moves.len()).any(|ply| self.move_comment(ply).eval.is_some())
+    }
+
+    /// Time left of `player` after their last replay move played so far.
+    /// `None` if they haven't moved yet or the comment has no clock.
+    pub fn clock(&self, player: Player) -> Option<Duration> {
+        (0..self.replay_moves_played_offset)
+            .rev()
+            .find(|ply| self.ply_player(*ply) == player)
+            .and_then(|ply| self.move_comment(ply).clock)
+    }
+
+    /// Evaluation written after the last replay move played. `None` after
+    /// manual moves since the position isn't part of the game then.
+    pub fn eval(&self) -> Option<CommentEval> {
+        if self.player_moves_played_offset > 0 {
+            return None;
+        }
+        let ply = self.replay_moves_played_offset.checked_sub(1)?;
+        self.move_comment(ply).eval
+    }
+
+    /// The next move of the replay as written in the PGN
+    fn next_pgn_move(&self) -> Option<&Move> {
+        self.replay_info
+            .moves
+            .get(self.replay_moves_played_offset)
+            .map(|game_move| &game_move.move_.move_)
+    }
+
+    /// Source and destination of the next move in the replay without playing it.
+    /// Returns `None` if the replay is over or manual moves were played.
+    pub fn next_replay_move(&self) -> Option<(Square, Square)> {
+        if self.player_moves_played_offset > 0 {
+            return None;
+        }
+        self.active_game
+            .find_pgn_move(self.next_pgn_move()?)
+            .ok()
+            .map(|bit_move| (bit_move.get_src().into(), bit_move.get_dest().into()))
+    }
+
+    /// Compare a guessed move with the next move of the replay. The move
+    /// itself is not played. Returns `None` if there is nothing to guess.
+    pub fn guess_replay_move(&self, source: Square, destination: Square) -> Option<GuessOutcome> {
+        let (replay_source, replay_destination) = self.next_replay_move()?;
+        if source == replay_source && destination == replay_destination {
+            return Some(GuessOutcome::Exact);
+        }
+
+        let best_move = AlphaBetaSearcher::best_move(self.active_game.board(), GUESS_ENGINE_DEPTH);
+        if best_move.get_src_u8() == source.0 && best_move.get_dest_u8() == destination.0 {
+            Some(GuessOutcome::EngineApproved)
+        } else {
+            Some(GuessOutcome::Miss)
+        }
+    }
+
     pub fn play_replay_move(&mut self) -> ReplayResponse {
         let mut comment: Option<String> = None;
         let mut last_move_from: Option<Square> = None;
@@ -59,71 +373,12 @@ impl Replay {
         if self.replay_moves_played_offset + 1 <= self.replay_info.moves.len()
             && self.player_moves_played_offset == 0
         {
-            let played_move: GameMove =
-                self.replay_info.moves[self.replay_moves_played_offset].clone();
-            comment = played_move.comment;
-            let played_move = played_move.move_.move_;
-            let played_piece = match &played_move {
-                Move::BasicMove { piece, .. } => to_pleco_piece(piece, self.is_white_turn),
-                _ => {
-                    if self.is_white_turn {
-                        WhiteKing
-                    } else {
-                        BlackKing
-                    }
-                }
-            };
-            let destination = match &played_move {
-                Move::BasicMove { to, .. } => SQ::make(
-                    to_pleco_file(to.file()).unwrap(),
-                    to_pleco_rank(to.rank()).unwrap(),
-                ),
-                Move::CastleKingside => {
-                    if self.is_white_turn {
-                        SQ::make(File::H, Rank::R1)
-                    } else {
-                        SQ::make(File::H, Rank::R8)
-                    }
-                }
-                Move::CastleQueenside => {
-                    if self.is_white_turn {
-                        SQ::make(File::A, Rank::R1)
-                    } else {
-                        SQ::make(File::A, Rank::R8)
-                    }
-                }
-            };
-            let (src_col, src_row) = match &played_move {
-                Move::BasicMove { from, .. } => {
-                    (to_pleco_file(from.file()), to_pleco_rank(from.rank()))
-                }
-                Move::CastleKingside => (
-                    Some(File::E),
-                    if self.is_white_turn {
-                        Some(Rank::R1)
-                    } else {
-                        Some(Rank::R8)
-                    },
-                ),
-                Move::CastleQueenside => (
-                    Some(File::E),
-                    if self.is_white_turn {
-                        Some(Rank::R1)
-                    } else {
-                        Some(Rank::R8)
-                    },
-                ),
-            };
-            match self.active_game.move_piece_by_type(
-                played_piece,
-                Square::from(destination),
-                src_col,
-                src_row,
-            ) {
+            comment = self.move_comment(self.replay_moves_played_offset).text;
+            let played_move = self.next_pgn_move().unwrap().clone();
+            match self.active_game.play_pgn_move(&played_move) {
                 Ok((src, dest)) => {
                     last_move_from = Some(src);
                     last_move_to = Some(dest);
-                    self.is_white_turn = !self.is_white_turn;
                     self.replay_moves_played_offset = self.replay_moves_played_offset + 1;
                     if self.replay_moves_played_offset == self.replay_info.moves.len() {
                         let termination_string =
@@ -156,11 +411,12 @@ impl Replay {
             }
             Err(_) => {}
         }
+        let last_move = self.active_game.last_move();
         ReplayResponse {
             fen: self.active_game.fen(),
             comment: None,
-            last_move_from: Some(source),
-            last_move_to: Some(destination),
+            last_move_from: last_move.map(|(source, _)| source),
+            last_move_to: last_move.map(|(_, destination)| destination),
         }
     }
 
@@ -171,21 +427,42 @@ impl Replay {
         } else if self.replay_moves_played_offset > 0 {
             self.active_game.undo(1);
             self.replay_moves_played_offset = self.replay_moves_played_offset - 1;
-            self.is_white_turn = !self.is_white_turn;
         }
-        return ReplayResponse {
+        self.current_response()
+    }
+
+    /// Show the position after `position` replay moves (e.g. picked from
+    /// an evaluation graph). Manual moves are taken back.
+    pub fn go_to(&mut self, position: usize) -> ReplayResponse {
+        while self.player_moves_played_offset > 0 || self.replay_moves_played_offset > position {
+            self.undo_move();
+        }
+        let mut response = None;
+        while self.replay_moves_played_offset < position {
+            let played = self.replay_moves_played_offset;
+            response = Some(self.play_replay_move());
+            if self.replay_moves_played_offset == played {
+                break; // The PGN has an invalid move
+            }
+        }
+        response.unwrap_or_else(|| self.current_response())
+    }
+
+    fn current_response(&self) -> ReplayResponse {
+        let last_move = self.active_game.last_move();
+        ReplayResponse {
             fen: self.active_game.fen(),
             comment: None,
-            last_move_from: None,
-            last_move_to: None,
-        };
+            last_move_from: last_move.map(|(source, _)| source),
+            last_move_to: last_move.map(|(_, destination)| destination),
+        }
     }
 
     pub fn reset(&mut self) -> ReplayResponse {
-        self.active_game = ChessGame::default();
+        self.active_game = ChessGame::pgn_starting_position(&self.replay_info)
+            .expect("The starting position was read in new()");
         self.replay_moves_played_offset = 0;
         self.player_moves_played_offset = 0;
-        self.is_white_turn = true;
         return ReplayResponse {
             fen: self.active_game.fen(),
             comment: None,
@@ -195,65 +472,134 @@ impl Replay {
     }
 }
 
-fn to_pleco_piece(piece: &LocalPiece, is_white_turn: bool) -> Piece {
-    if is_white_turn {
-        match piece {
-            LocalPiece::Pawn => WhitePawn,
-            LocalPiece::Knight => WhiteKnight,
-            LocalPiece::Bishop => WhiteBishop,
-            LocalPiece::Rook => WhiteRook,
-            LocalPiece::Queen => WhiteQueen,
-            LocalPiece::King => WhiteKing,
-        }
-    } else {
-        match piece {
-            LocalPiece::Pawn => BlackPawn,
-            LocalPiece::Knight => BlackKnight,
-            LocalPiece::Bishop => BlackBishop,
-            LocalPiece::Rook => BlackRook,
-            LocalPiece::Queen => BlackQueen,
-            LocalPiece::King => BlackKing,
-        }
+fn termination_string_from(term_info: GameTermination) -> &'static str {
+    match term_info {
+        GameTermination::WhiteWins => " Game Over: White Won",
+        GameTermination::BlackWins => " Game Over: Black Won",
+        GameTermination::DrawnGame => " Game Over: Draw",
+        GameTermination::Unknown => " Game Over: Unknown",
     }
 }
 
-fn to_pleco_rank(rank: Option<LocalRank>) -> Option<Rank> {
-    match rank {
-        Some(LocalRank::R1) => Some(Rank::R1),
-        Some(LocalRank::R2) => Some(Rank::R2),
-        Some(LocalRank::R3) => Some(Rank::R3),
-        Some(LocalRank::R4) => Some(Rank::R4),
-        Some(LocalRank::R5) => Some(Rank::R5),
-        Some(LocalRank::R6) => Some(Rank::R6),
-        Some(LocalRank::R7) => Some(Rank::R7),
-        Some(LocalRank::R8) => Some(Rank::R8),
-        _ => None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(pgn: &str) -> Game {
+        chess_pgn_parser::read_games(pgn)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap()
     }
-}
 
-fn to_pleco_file(file: Option<LocalFile>) -> Option<File> {
-    if file.is_some() {
-        match file {
-            Some(LocalFile::A) => Some(File::A),
-            Some(LocalFile::B) => Some(File::B),
-            Some(LocalFile::C) => Some(File::C),
-            Some(LocalFile::D) => Some(File::D),
-            Some(LocalFile::E) => Some(File::E),
-            Some(LocalFile::F) => Some(File::F),
-            Some(LocalFile::G) => Some(File::G),
-            Some(LocalFile::H) => Some(File::H),
-            _ => None,
-        }
-    } else {
-        None
+    #[test]
+    fn positions_walk_the_game() {
+        let game = game("1. e4 e5 2. Nf3 Nc6 3. Bb5 *");
+        let positions: Vec<ReplayPosition> = positions(&game).unwrap().collect();
+        assert_eq!(positions.len(), 5);
+        assert_eq!(positions[0].ply, 0);
+        assert_eq!(positions[0].player, Player::White);
+        assert_eq!(positions[0].bit_move.stringify(), "e2e4");
+        assert_eq!(positions[0].fen_before, ChessGame::default().fen());
+        assert_eq!(positions[1].fen_before, positions[0].fen);
+        assert_eq!(positions[3].san, "Nc6");
+        assert_eq!(positions[3].player, Player::Black);
+        assert_eq!(
+            positions[4].fen,
+            "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3"
+        );
     }
-}
 
-fn termination_string_from(term_info: GameTermination) -> &'static str {
-    match term_info {
-        GameTermination::WhiteWins => " Game Over: White Won",
-        GameTermination::BlackWins => " Game Over: Black Won",
-        GameTermination::DrawnGame => " Game Over: Draw",
-        GameTermination::Unknown => " Game Over: Unknown",
+    #[test]
+    fn positions_stop_at_illegal_move() {
+        let game = game("1. e4 e5 2. Ke3 Nc6 *");
+        assert_eq!(positions(&game).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn parses_clock_and_eval_commands() {
+        let comment = MoveComment::parse(" [%eval -1.3] [%clk 0:03:21] Blunder! ");
+        assert_eq!(comment.clock, Some(Duration::from_secs(201)));
+        assert_eq!(comment.eval, Some(CommentEval::Pawns(-1.3)));
+        assert_eq!(comment.text.as_deref(), Some("Blunder!"));
+
+        let comment = MoveComment::parse("[%eval #-3,25] [%clk 1:00:09.8] [%cal Ge2e4]");
+        assert_eq!(comment.clock, Some(Duration::from_millis(3_609_800)));
+        assert_eq!(comment.eval, Some(CommentEval::Mate(-3)));
+        assert_eq!(comment.text, None);
+
+        let comment = MoveComment::parse("Only text [%clk soon]");
+        assert_eq!(comment.clock, None);
+        assert_eq!(comment.text.as_deref(), Some("Only text"));
+        assert_eq!(CommentEval::Pawns(0.17).to_string(), "+0.2");
+        assert_eq!(CommentEval::Mate(-3).to_string(), "#-3");
+    }
+
+    #[test]
+    fn replay_exposes_clocks_and_evals() {
+        let mut replay = Replay::new(game(
+            "1. e4 { [%eval 0.2] [%clk 0:03:00] } 1... e5 { [%eval 0.3] [%clk 0:02:58] } \
+             2. Qh5 { Too early [%eval -0.1] [%clk 0:02:50] } *",
+        ))
+        .unwrap();
+        assert!(replay.has_clocks());
+        assert!(replay.has_evals());
+        assert_eq!(replay.clock(Player::White), None);
+        assert_eq!(replay.eval(), None);
+
+        replay.play_replay_move();
+        replay.play_replay_move();
+        let response = replay.play_replay_move();
+        assert_eq!(
+            response.comment.as_deref(),
+            Some("Too early Game Over: Unknown")
+        );
+        assert_eq!(replay.clock(Player::White), Some(Duration::from_secs(170)));
+        assert_eq!(replay.clock(Player::Black), Some(Duration::from_secs(178)));
+        assert_eq!(replay.eval(), Some(CommentEval::Pawns(-0.1)));
+
+        replay.undo_move();
+        assert_eq!(replay.clock(Player::White), Some(Duration::from_secs(180)));
+        assert_eq!(replay.eval(), Some(CommentEval::Pawns(0.3)));
+
+        assert_eq!(CommentEval::Pawns(-0.1).centipawns(), -10);
+        assert_eq!(CommentEval::Mate(-3).centipawns(), -MATE_CENTIPAWNS);
+
+        let moves = replay.moves();
+        assert_eq!(moves[2].clock, Some(Duration::from_secs(170)));
+        assert_eq!(moves[2].comment.as_deref(), Some("Too early"));
+        assert_eq!(moves[0].comment, None);
+    }
+
+    #[test]
+    fn replay_starts_from_the_fen_tag() {
+        assert!(Replay::new(game("[FEN \"not a fen\"]\n\n1. e4 *")).is_err());
+
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let mut replay = Replay::new(game(&format!("[FEN \"{}\"]\n\n1. e4 *", fen))).unwrap();
+        assert_eq!(replay.fen(), fen);
+        replay.play_replay_move();
+        assert_eq!(replay.reset().fen, fen);
+    }
+
+    #[test]
+    fn go_to_takes_back_manual_moves() {
+        let mut replay = Replay::new(game("1. e4 e5 2. Nf3 Nc6 *")).unwrap();
+        let response = replay.go_to(3);
+        assert_eq!(replay.replay_position(), Some(3));
+        assert_eq!(response.last_move_to, Some(Square::from(SQ::F3)));
+
+        replay.player_move(Square::from(SQ::B8), Square::from(SQ::C6));
+        assert_eq!(replay.replay_position(), None);
+        let response = replay.go_to(1);
+        assert_eq!(replay.replay_position(), Some(1));
+        assert_eq!(
+            response.fen,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+
+        replay.go_to(10);
+        assert_eq!(replay.replay_position(), Some(4));
     }
 }
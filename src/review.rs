@@ -0,0 +1,219 @@
+//! Mistakes of analysed games, served back as "find the better move"
+//! exercises until each one was solved often enough.
+
+use crate::analysis::CriticalMoment;
+use crate::coach::{self, Mistake};
+use crate::game::ChessGame;
+use crate::{Player, Square};
+use anyhow::Result;
+use pleco::PieceType;
+use serde::{Deserialize, Serialize};
+
+/// Correct answers until an exercise leaves the queue
+pub const SOLVED_TIMES: u8 = 2;
+/// Smaller swings (in centipawns, from the view of the mover) aren't
+/// worth an exercise
+const MIN_SWING: i32 = 100;
+
+/// A position of a game in which the move played was a mistake
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exercise {
+    /// Position before the mistake
+    pub fen: String,
+    pub played: (Square, Square),
+    /// What the engine would have played instead
+    #[serde(default)]
+    pub best: Option<(Square, Square)>,
+    /// Where the mistake was made, e.g. "Alice - Bob, move 14"
+    pub source: String,
+    /// Correct answers so far
+    #[serde(default)]
+    pub solved: u8,
+}
+
+/// How an answer to an exercise compares to the mistake
+#[derive(Clone, Debug)]
+pub enum Verdict {
+    Better,
+    /// The move of the game again
+    SameMistake,
+    /// Not the move of the game, but no improvement either
+    AnotherMistake(Mistake),
+}
+
+impl Verdict {
+    pub fn is_correct(&self) -> bool {
+        matches!(self, Verdict::Better)
+    }
+}
+
+impl Exercise {
+    /// The side which has to find the better move
+    pub fn player(&self) -> Result<Player> {
+        Ok(ChessGame::from_fen(&self.fen)?.turn())
+    }
+
+    /// Judge the move from `source` to `destination` (promoting to a
+    /// queen). Searches with the engine unless it's the best or the played
+    /// move, so better not on the thread that draws.
+    pub fn check(&self, source: Square, destination: Square) -> Result<Verdict> {
+        if (source, destination) == self.played {
+            return Ok(Verdict::SameMistake);
+        }
+        let game = ChessGame::from_fen(&self.fen)?;
+        let answer = game
+            .possible_moves_from(source)
+            .into_iter()
+            .find(|bit_move| {
+                bit_move.get_dest() == *destination
+                    && (!bit_move.is_promo() || bit_move.promo_piece() == PieceType::Q)
+            })
+            .ok_or_else(|| anyhow!("{}{} is not a legal move", source, destination))?;
+        if self.best == Some((source, destination)) {
+            return Ok(Verdict::Better);
+        }
+        Ok(match coach::review_move(&game.board(), answer) {
+            Some(mistake) => Verdict::AnotherMistake(mistake),
+            None => Verdict::Better,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewQueue {
+    pub exercises: Vec<Exercise>,
+}
+
+impl ReviewQueue {
+    /// Queue the critical moments in which the mover spoiled the position
+    /// (with `only` just those of that side). Positions already in the
+    /// queue aren't added again. Returns how many were added.
+    pub fn add_mistakes(
+        &mut self,
+        moments: &[CriticalMoment],
+        only: Option<Player>,
+        source: &str,
+    ) -> usize {
+        let mut added = 0;
+        for moment in moments {
+            let game = match ChessGame::from_fen(&moment.fen) {
+                Ok(game) => game,
+                Err(_) => continue,
+            };
+            let mover = game.turn();
+            let swing = match mover {
+                Player::White => moment.swing,
+                Player::Black => -moment.swing,
+            };
+            if swing > -MIN_SWING || only.map_or(false, |only| only != mover) {
+                continue;
+            }
+            if self
+                .exercises
+                .iter()
+                .any(|exercise| exercise.fen == moment.fen && exercise.played == moment.played_move)
+            {
+                continue;
+            }
+            let move_number = moment.fen.split_whitespace().nth(5).unwrap_or("1");
+            self.exercises.push(Exercise {
+                fen: moment.fen.clone(),
+                played: moment.played_move,
+                best: moment.best_move,
+                source: format!("{}, move {}", source, move_number),
+                solved: 0,
+            });
+            added += 1;
+        }
+        added
+    }
+
+    /// Count a correct answer to the exercise at `index`. Returns whether
+    /// it's done and was removed from the queue.
+    pub fn mark_solved(&mut self, index: usize) -> bool {
+        let exercise = match self.exercises.get_mut(index) {
+            Some(exercise) => exercise,
+            None => return false,
+        };
+        exercise.solved += 1;
+        if exercise.solved >= SOLVED_TIMES {
+            self.exercises.remove(index);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// After 1. f3 e5 the move 2. g4?? allows Qh4#
+    const FOOLS_MATE: &str = "rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq e6 0 2";
+
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    fn moment(fen: &str, played: (&str, &str), swing: i32) -> CriticalMoment {
+        CriticalMoment {
+            ply: 2,
+            fen: fen.to_owned(),
+            played_move: (square(played.0), square(played.1)),
+            best_move: None,
+            swing,
+        }
+    }
+
+    #[test]
+    fn only_mistakes_are_queued_once() {
+        let black_to_move = "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq g3 0 2";
+        let moments = vec![
+            moment(FOOLS_MATE, ("G2", "G4"), -10_000),
+            // Good for black, so no mistake of black
+            moment(black_to_move, ("D8", "H4"), -10_000),
+            moment(FOOLS_MATE, ("E2", "E4"), -20),
+        ];
+        let mut queue = ReviewQueue::default();
+        assert_eq!(queue.add_mistakes(&moments, None, "A - B"), 1);
+        assert_eq!(queue.exercises[0].played, (square("G2"), square("G4")));
+        assert_eq!(queue.exercises[0].source, "A - B, move 2");
+        assert_eq!(queue.add_mistakes(&moments, None, "A - B"), 0);
+
+        let mut queue = ReviewQueue::default();
+        assert_eq!(
+            queue.add_mistakes(&moments, Some(Player::Black), "A - B"),
+            0
+        );
+    }
+
+    #[test]
+    fn solved_twice_leaves_the_queue() {
+        let mut queue = ReviewQueue::default();
+        queue.add_mistakes(&[moment(FOOLS_MATE, ("G2", "G4"), -10_000)], None, "");
+        assert!(!queue.mark_solved(0));
+        assert_eq!(queue.exercises[0].solved, 1);
+        assert!(queue.mark_solved(0));
+        assert!(queue.exercises.is_empty());
+        assert!(!queue.mark_solved(0));
+    }
+
+    #[test]
+    fn answers_are_judged() {
+        let mut queue = ReviewQueue::default();
+        queue.add_mistakes(&[moment(FOOLS_MATE, ("G2", "G4"), -10_000)], None, "");
+        let exercise = &queue.exercises[0];
+        assert_eq!(exercise.player().unwrap(), Player::White);
+        assert!(matches!(
+            exercise.check(square("G2"), square("G4")).unwrap(),
+            Verdict::SameMistake
+        ));
+        assert!(exercise
+            .check(square("E2"), square("E4"))
+            .unwrap()
+            .is_correct());
+        assert!(exercise.check(square("E2"), square("E5")).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+/// A scoresheet for over-the-board games: moves are written by hand into
+/// cells (like on paper) and can be read into SAN later.
+use crate::handwriting::{self, Stroke};
+use crate::notation::{parse_san, san};
+use pleco::Board;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Scoresheet {
+    /// Strokes by cell. Cell 0 is white's first move, 1 black's first move and so on.
+    pub cells: Vec<Vec<Stroke>>,
+}
+
+/// Result of reading the moves of a scoresheet
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transcription {
+    /// Moves in SAN up to the first cell that couldn't be read
+    pub moves: Vec<String>,
+    /// First cell that was written into but isn't a legal move
+    pub unreadable_cell: Option<usize>,
+}
+
+impl Scoresheet {
+    pub fn add_stroke(&mut self, cell: usize, stroke: Stroke) {
+        if self.cells.len() <= cell {
+            self.cells.resize(cell + 1, vec![]);
+        }
+        self.cells[cell].push(stroke);
+    }
+
+    /// Remove what was written into the cell. Returns false if it was empty.
+    pub fn clear_cell(&mut self, cell: usize) -> bool {
+        match self.cells.get_mut(cell) {
+            Some(strokes) if !strokes.is_empty() => {
+                strokes.clear();
+                while self
+                    .cells
+                    .last()
+                    .map_or(false, |strokes| strokes.is_empty())
+                {
+                    self.cells.pop();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Read the cells in order as long as they contain legal moves.
+    /// Stops at the first empty cell.
+    pub fn transcribe(&self) -> Transcription {
+        let mut board = Board::default();
+        let mut moves = vec![];
+        for (cell, strokes) in self.cells.iter().enumerate() {
+            if strokes.is_empty() {
+                break;
+            }
+            let candidates: Vec<String> = board
+                .generate_moves()
+                .iter()
+                .map(|bit_move| san(&board, *bit_move))
+                .collect();
+            let written = match handwriting::recognize_move(strokes, &candidates) {
+                Some(written) => written.to_owned(),
+                None => {
+                    return Transcription {
+                        moves,
+                        unreadable_cell: Some(cell),
+                    }
+                }
+            };
+            board.apply_move(parse_san(&board, &written).unwrap());
+            moves.push(written);
+        }
+        Transcription {
+            moves,
+            unreadable_cell: None,
+        }
+    }
+}
+
+/// Tags every PGN starts with (in this order)
+const SEVEN_TAG_ROSTER: &[&str] = &["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// A PGN of the moves. Tags of the seven tag roster without a value are
+/// set to "?" (the result to "*"). Other tags (e.g. "FEN") follow them if
+/// they have a value.
+pub fn to_pgn(moves: &[String], tags: &[(&str, &str)]) -> String {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag_name, value)| *tag_name == name && !value.is_empty())
+            .map(|(_, value)| *value)
+    };
+    let result = tag("Result").unwrap_or("*");
+    let mut pgn = String::new();
+    for name in &SEVEN_TAG_ROSTER[..6] {
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, tag(name).unwrap_or("?")));
+    }
+    pgn.push_str(&format!("[Result \"{}\"]\n", result));
+    for (name, value) in tags {
+        if !SEVEN_TAG_ROSTER.contains(name) && !value.is_empty() {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+    }
+    pgn.push('\n');
+
+    let mut movetext: Vec<String> = vec![];
+    for (i, written) in moves.iter().enumerate() {
+        if i % 2 == 0 {
+            movetext.push(format!("{}. {}", i / 2 + 1, written));
+        } else {
+            movetext.push(written.clone());
+        }
+    }
+    movetext.push(result.to_owned());
+    pgn.push_str(&movetext.join(" "));
+    pgn.push('\n');
+    pgn
+}
+
+/// Date in the format of PGNs (e.g. "2021.03.04") of a unix timestamp
+pub fn pgn_date(timestamp: u64) -> String {
+    // Conversion of days to the civil calendar by Howard Hinnant
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handwriting::tests::write;
+
+    #[test]
+    fn keeps_cells_in_order() {
+        let mut scoresheet = Scoresheet::default();
+        scoresheet.add_stroke(2, vec![(0.0, 0.0), (1.0, 1.0)]);
+        assert_eq!(scoresheet.cells.len(), 3);
+        assert!(scoresheet.cells[0].is_empty());
+        assert!(!scoresheet.clear_cell(0));
+        assert!(scoresheet.clear_cell(2));
+        assert!(scoresheet.is_empty());
+    }
+
+    #[test]
+    fn transcribes_until_unreadable_cell() {
+        let mut scoresheet = Scoresheet::default();
+        for (cell, written) in ["e4", "e5", "Nf3"].iter().enumerate() {
+            for stroke in write(written) {
+                scoresheet.add_stroke(cell, stroke);
+            }
+        }
+        // A scribble
+        scoresheet.add_stroke(3, vec![(100.0, 200.0), (130.0, 260.0), (160.0, 200.0)]);
+        let transcription = scoresheet.transcribe();
+        assert_eq!(transcription.moves, vec!["e4", "e5", "Nf3"]);
+        assert_eq!(transcription.unreadable_cell, Some(3));
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(pgn_date(0), "1970.01.01");
+        assert_eq!(pgn_date(951_782_400), "2000.02.29");
+        assert_eq!(pgn_date(1_614_816_000), "2021.03.04");
+    }
+
+    #[test]
+    fn writes_pgn() {
+        let moves: Vec<String> = vec!["e4".into(), "e5".into(), "Nf3".into()];
+        assert_eq!(
+            to_pgn(&moves, &[("Date", "2021.03.04"), ("White", "")]),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"2021.03.04\"]\n[Round \"?\"]\n\
+             [White \"?\"]\n[Black \"?\"]\n[Result \"*\"]\n\n1. e4 e5 2. Nf3 *\n"
+        );
+    }
+
+    #[test]
+    fn writes_result() {
+        let moves: Vec<String> = vec!["f3".into(), "e5".into(), "g4".into(), "Qh4#".into()];
+        let pgn = to_pgn(&moves, &[("Result", "0-1")]);
+        assert!(pgn.contains("[Result \"0-1\"]\n"));
+        assert!(pgn.ends_with("2. g4 Qh4# 0-1\n"));
+    }
+
+    #[test]
+    fn writes_other_tags_after_roster() {
+        let fen = "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1";
+        assert_eq!(
+            to_pgn(
+                &[],
+                &[
+                    ("SetUp", "1"),
+                    ("FEN", fen),
+                    ("Event", "Study"),
+                    ("Annotator", "")
+                ]
+            ),
+            format!(
+                "[Event \"Study\"]\n[Site \"?\"]\n[Date \"?\"]\n[Round \"?\"]\n\
+                 [White \"?\"]\n[Black \"?\"]\n[Result \"*\"]\n[SetUp \"1\"]\n\
+                 [FEN \"{}\"]\n\n*\n",
+                fen
+            )
+        );
+    }
+}
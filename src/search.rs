@@ -0,0 +1,303 @@
+//! Bot searches that can be given up on. The search deepens one ply at a
+//! time on its own thread, so the best move of the deepest finished ply is
+//! available all along, e.g. to show how far the bot got.
+//!
+//! pleco's searchers can't be stopped, so this is pleco's alpha-beta search
+//! (as strong as `AlphaBetaSearcher`) checking for a cancel at every node.
+//! The moves of the position are split between threads, which takes the
+//! place of pleco's multithreaded `JamboreeSearcher`.
+
+use pleco::core::score::{DRAW, MATE, NEG_INFINITE};
+use pleco::tools::eval::Eval;
+use pleco::tools::prng::PRNG;
+use pleco::{BitMove, Board};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Result of the deepest finished ply
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchProgress {
+    pub depth: u16,
+    pub best_move: BitMove,
+    /// Score of the search in centipawns for the side to move
+    pub score: i32,
+}
+
+#[derive(Default)]
+struct Shared {
+    progress: Mutex<Option<SearchProgress>>,
+    finished: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+/// Handle to a search running in the background. Dropping it cancels the
+/// search and waits for its threads to stop.
+pub struct Search {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Search {
+    /// Search `board` up to `max_depth` plies on `threads` threads. Of
+    /// equally good moves, the first one is played like `AlphaBetaSearcher`
+    /// does or, with a `seed`, one picked with it. The same seed and
+    /// position always give the same move (also with other `threads`),
+    /// other seeds can give another one as good.
+    pub fn start(board: Board, max_depth: u16, threads: usize, seed: Option<u64>) -> Self {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || {
+            // xorshift never leaves 0
+            let mut rng = seed.map(|seed| PRNG::init((seed ^ board.zobrist()).max(1)));
+            for depth in 1..=max_depth.max(1) {
+                let (best_moves, score) =
+                    match best_scored_moves(&board, depth, threads, &thread_shared.cancelled) {
+                        Some(result) => result,
+                        None => return,
+                    };
+                let best_move = match (best_moves.len(), rng.as_mut()) {
+                    // No legal moves
+                    (0, _) => break,
+                    (_, None) => best_moves[0],
+                    (count, Some(rng)) => best_moves[(rng.rand() % count as u64) as usize],
+                };
+                *thread_shared.progress.lock().unwrap() = Some(SearchProgress {
+                    depth,
+                    best_move,
+                    score: score.into(),
+                });
+            }
+            thread_shared.finished.store(true, Ordering::Relaxed);
+        });
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop searching. The threads notice it at the next position they look
+    /// at and the ply they were on is thrown away.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// The threads are still searching, cancelled ones only very briefly
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .map_or(false, |thread| !thread.is_finished())
+    }
+
+    /// Every ply up to `max_depth` was searched
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.load(Ordering::Relaxed)
+    }
+
+    /// `None` until the first ply was searched
+    pub fn best_so_far(&self) -> Option<SearchProgress> {
+        *self.shared.progress.lock().unwrap()
+    }
+}
+
+/// Number of threads to search on every core with
+pub fn all_cores() -> usize {
+    thread::available_parallelism().map_or(1, |count| count.get())
+}
+
+/// pleco's `alpha_beta_search()` returning `None` as soon as `stop` is set.
+/// The score is in centipawns for the side to move.
+fn alpha_beta(
+    board: &mut Board,
+    mut alpha: i16,
+    beta: i16,
+    depth: u16,
+    stop: &AtomicBool,
+) -> Option<i16> {
+    if stop.load(Ordering::Relaxed) {
+        return None;
+    }
+    if depth == 0 {
+        return Some(Eval::eval_low(board) as i16);
+    }
+    let moves = board.generate_moves();
+    if moves.is_empty() {
+        return Some(if board.in_check() { -MATE } else { DRAW } as i16);
+    }
+    for &bit_move in moves.iter() {
+        board.apply_move(bit_move);
+        let score = alpha_beta(board, -beta, -alpha, depth - 1, stop);
+        board.undo_move();
+        let score = -score?;
+        if score > alpha {
+            alpha = score;
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+    Some(alpha)
+}
+
+/// All moves with the best score at `depth` in the order pleco generates
+/// them and that score. `None` if `stop` was set meanwhile.
+fn best_scored_moves(
+    board: &Board,
+    depth: u16,
+    threads: usize,
+    stop: &AtomicBool,
+) -> Option<(Vec<BitMove>, i16)> {
+    let moves: Vec<BitMove> = board.generate_moves().iter().copied().collect();
+    let next_move = AtomicUsize::new(0);
+    let scores_per_thread = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1).min(moves.len()))
+            .map(|_| {
+                let mut board = board.shallow_clone();
+                let (moves, next_move) = (&moves, &next_move);
+                scope.spawn(move || {
+                    let mut best_score = NEG_INFINITE as i16;
+                    let mut scores = vec![];
+                    while let Some(&bit_move) = moves.get(next_move.fetch_add(1, Ordering::Relaxed))
+                    {
+                        board.apply_move(bit_move);
+                        // Scores below the best of this thread fail high for
+                        // the opponent and aren't exact, the others are. So
+                        // the moves with the best score of all threads are
+                        // found no matter which thread searched which move.
+                        let score = alpha_beta(
+                            &mut board,
+                            NEG_INFINITE as i16,
+                            1 - best_score,
+                            depth - 1,
+                            stop,
+                        );
+                        board.undo_move();
+                        let score = -score?;
+                        best_score = best_score.max(score);
+                        scores.push((bit_move, score));
+                    }
+                    Some(scores)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect::<Option<Vec<_>>>()
+    })?;
+    let scores: Vec<(BitMove, i16)> = scores_per_thread.into_iter().flatten().collect();
+    let best_score = match scores.iter().map(|&(_, score)| score).max() {
+        Some(best_score) => best_score,
+        // No legal moves
+        None => return Some((vec![], 0)),
+    };
+    let best_moves = moves
+        .into_iter()
+        .filter(|bit_move| {
+            scores
+                .iter()
+                .any(|&(scored, score)| scored == *bit_move && score == best_score)
+        })
+        .collect();
+    Some((best_moves, best_score))
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.cancel();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pleco::bot_prelude::AlphaBetaSearcher;
+    use pleco::tools::Searcher;
+    use std::time::{Duration, Instant};
+
+    /// Ra8#
+    const MATE_IN_ONE: &str = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1";
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let started = Instant::now();
+        while !condition() {
+            assert!(started.elapsed() < Duration::from_secs(10), "Timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn deepens_until_max_depth() {
+        let board = Board::from_fen(MATE_IN_ONE).unwrap();
+        let search = Search::start(board, 3, 2, None);
+        wait_until(|| search.is_finished());
+        let progress = search.best_so_far().unwrap();
+        assert_eq!(progress.depth, 3);
+        assert_eq!(progress.best_move.stringify(), "a1a8");
+        assert_eq!(progress.score, MATE);
+    }
+
+    #[test]
+    fn score_is_for_the_side_to_move() {
+        // Black is a queen up
+        let board = Board::from_fen("q3k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        let search = Search::start(board, 2, 1, None);
+        wait_until(|| search.is_finished());
+        assert!(search.best_so_far().unwrap().score > 500);
+    }
+
+    fn searched_move(board: Board, depth: u16, threads: usize, seed: Option<u64>) -> BitMove {
+        let search = Search::start(board, depth, threads, seed);
+        wait_until(|| search.is_finished());
+        search.best_so_far().unwrap().best_move
+    }
+
+    #[test]
+    fn plays_like_alpha_beta_searcher() {
+        for fen in &[
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "r3k2r/ppp2ppp/2n1bn2/3qp3/3P4/2N1BN2/PPP2PPP/R2QKB1R b KQkq - 1 8",
+            "8/5k2/8/3P4/8/8/5K2/8 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let expected = AlphaBetaSearcher::best_move(board.shallow_clone(), 3);
+            assert_eq!(searched_move(board.shallow_clone(), 3, 1, None), expected);
+            assert_eq!(searched_move(board, 3, 4, None), expected);
+        }
+    }
+
+    #[test]
+    fn seed_picks_among_equally_good_moves() {
+        let first = searched_move(Board::default(), 2, 1, Some(1));
+        assert_eq!(searched_move(Board::default(), 2, 3, Some(1)), first);
+        assert!((2..20).any(|seed| searched_move(Board::default(), 2, 1, Some(seed)) != first));
+
+        // Still finds the mate
+        let board = Board::from_fen(MATE_IN_ONE).unwrap();
+        assert_eq!(searched_move(board, 3, 2, Some(7)).stringify(), "a1a8");
+    }
+
+    #[test]
+    fn cancel_stops_the_ply_being_searched() {
+        let board = Board::default();
+        let search = Search::start(board, 20, 2, None);
+        wait_until(|| search.best_so_far().is_some());
+        search.cancel();
+        assert!(search.is_cancelled());
+        let progress = search.best_so_far().unwrap();
+        assert!(progress.depth < 20);
+        assert!(!progress.best_move.is_null());
+        // Ply 20 would take ages
+        let cancelled = Instant::now();
+        wait_until(|| !search.is_running());
+        assert!(cancelled.elapsed() < Duration::from_secs(1));
+        assert!(!search.is_finished());
+    }
+}
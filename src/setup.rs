@@ -0,0 +1,371 @@
+use crate::game::{Board, Piece};
+use crate::{Player, Square};
+use anyhow::Result;
+
+const PIECES: &[Piece] = &[
+    Piece::WhiteKing,
+    Piece::WhiteQueen,
+    Piece::WhiteRook,
+    Piece::WhiteBishop,
+    Piece::WhiteKnight,
+    Piece::WhitePawn,
+    Piece::BlackKing,
+    Piece::BlackQueen,
+    Piece::BlackRook,
+    Piece::BlackBishop,
+    Piece::BlackKnight,
+    Piece::BlackPawn,
+];
+
+/// Which castling moves are still allowed. Only the
+/// rights themselves are stored, not whether castling is
+/// possible in the position (see `PositionSetup::validate()`).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+
+impl CastlingRights {
+    pub fn none() -> Self {
+        CastlingRights {
+            white_king_side: false,
+            white_queen_side: false,
+            black_king_side: false,
+            black_queen_side: false,
+        }
+    }
+
+    pub fn all() -> Self {
+        CastlingRights {
+            white_king_side: true,
+            white_queen_side: true,
+            black_king_side: true,
+            black_queen_side: true,
+        }
+    }
+
+    /// E.g. "KQkq" or "-"
+    pub fn fen(&self) -> String {
+        let mut fen = String::new();
+        for (allowed, c) in &[
+            (self.white_king_side, 'K'),
+            (self.white_queen_side, 'Q'),
+            (self.black_king_side, 'k'),
+            (self.black_queen_side, 'q'),
+        ] {
+            if *allowed {
+                fen.push(*c);
+            }
+        }
+        if fen.is_empty() {
+            fen.push('-');
+        }
+        fen
+    }
+}
+
+/// A position that is built square by square (e.g. in an editor).
+/// Unlike a `Board` it may be illegal until it's finished.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionSetup {
+    /// Indexed like `SQ` (A1 = 0, H8 = 63)
+    squares: Vec<Piece>,
+    pub turn: Player,
+    pub castling: CastlingRights,
+    /// Square a pawn skipped in the previous move
+    pub en_passant: Option<Square>,
+}
+
+impl Default for PositionSetup {
+    /// The starting position
+    fn default() -> Self {
+        PositionSetup::from_fen(&Board::default().fen()).expect("Invalid starting position")
+    }
+}
+
+impl PositionSetup {
+    pub fn empty() -> Self {
+        PositionSetup {
+            squares: vec![Piece::None; 64],
+            turn: Player::White,
+            castling: CastlingRights::none(),
+            en_passant: None,
+        }
+    }
+
+    /// Read the first four fields of a FEN. The position doesn't need to be legal.
+    pub fn from_fen(fen: &str) -> Result<Self> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        ensure!(!fields.is_empty(), "The FEN is empty");
+        let mut setup = PositionSetup::empty();
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        ensure!(
+            ranks.len() == 8,
+            "Expected 8 ranks but found {}",
+            ranks.len()
+        );
+        for (i, rank) in ranks.iter().enumerate() {
+            let y = 7 - i;
+            let mut x = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    x += empty as usize;
+                    continue;
+                }
+                let piece = PIECES
+                    .iter()
+                    .find(|piece| piece.character_lossy() == c)
+                    .ok_or(anyhow!("Unknown piece '{}'", c))?;
+                ensure!(x < 8, "Rank {} has more than 8 squares", y + 1);
+                setup.squares[y * 8 + x] = *piece;
+                x += 1;
+            }
+            ensure!(x == 8, "Rank {} doesn't have 8 squares", y + 1);
+        }
+
+        setup.turn = match fields.get(1) {
+            None | Some(&"w") => Player::White,
+            Some(&"b") => Player::Black,
+            Some(other) => bail!("Invalid side to move \"{}\"", other),
+        };
+        let castling = fields.get(2).unwrap_or(&"-");
+        setup.castling = CastlingRights {
+            white_king_side: castling.contains('K'),
+            white_queen_side: castling.contains('Q'),
+            black_king_side: castling.contains('k'),
+            black_queen_side: castling.contains('q'),
+        };
+        setup.en_passant = match fields.get(3) {
+            None | Some(&"-") => None,
+            Some(square) => Some(
+                square
+                    .to_uppercase()
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid en passant square: {}", e))?,
+            ),
+        };
+        Ok(setup)
+    }
+
+    pub fn piece_at(&self, square: Square) -> Piece {
+        self.squares[square.0 as usize]
+    }
+
+    /// Put `piece` onto `square` (`Piece::None` removes the piece there)
+    pub fn set_piece(&mut self, square: Square, piece: Piece) {
+        self.squares[square.0 as usize] = piece;
+        if self.en_passant.is_some() && !self.en_passant_squares().contains(&square) {
+            self.en_passant = None;
+        }
+    }
+
+    pub fn fen(&self) -> String {
+        let mut placement = String::new();
+        for y in (0..8).rev() {
+            let mut empty = 0;
+            for x in 0..8 {
+                match self.squares[y * 8 + x].character() {
+                    Some(c) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(c);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if y > 0 {
+                placement.push('/');
+            }
+        }
+        format!(
+            "{} {} {} {} 0 1",
+            placement,
+            match self.turn {
+                Player::White => "w",
+                Player::Black => "b",
+            },
+            self.castling.fen(),
+            self.en_passant
+                .map(|square| square.to_string().to_lowercase())
+                .unwrap_or("-".to_owned())
+        )
+    }
+
+    /// Squares a pawn of the other player could just have skipped
+    /// with a double step (if the squares behind it are empty).
+    pub fn en_passant_squares(&self) -> Vec<Square> {
+        let (pawn, pawn_y, skipped_y, start_y): (Piece, usize, usize, usize) = match self.turn {
+            Player::White => (Piece::BlackPawn, 4, 5, 6),
+            Player::Black => (Piece::WhitePawn, 3, 2, 1),
+        };
+        (0..8)
+            .filter(|x| {
+                self.squares[pawn_y * 8 + x] == pawn
+                    && self.squares[skipped_y * 8 + x] == Piece::None
+                    && self.squares[start_y * 8 + x] == Piece::None
+            })
+            .map(|x| Square::new(x, skipped_y).unwrap())
+            .collect()
+    }
+
+    /// Checks that the position could be reached in a game and returns a board of it.
+    pub fn validate(&self) -> Result<Board> {
+        for (king, player) in &[
+            (Piece::WhiteKing, Player::White),
+            (Piece::BlackKing, Player::Black),
+        ] {
+            let kings = self.squares.iter().filter(|piece| **piece == *king).count();
+            ensure!(
+                kings == 1,
+                "{} needs exactly one king (found {})",
+                player,
+                kings
+            );
+        }
+        for (pawn, player) in &[
+            (Piece::WhitePawn, Player::White),
+            (Piece::BlackPawn, Player::Black),
+        ] {
+            let pawns = self.squares.iter().filter(|piece| **piece == *pawn).count();
+            ensure!(pawns <= 8, "{} has more than 8 pawns", player);
+        }
+        let is_white = |piece: &Piece| piece.character().map_or(false, |c| c.is_uppercase());
+        let is_black = |piece: &Piece| piece.character().map_or(false, |c| c.is_lowercase());
+        ensure!(
+            self.squares.iter().filter(|piece| is_white(piece)).count() <= 16,
+            "White has more than 16 pieces"
+        );
+        ensure!(
+            self.squares.iter().filter(|piece| is_black(piece)).count() <= 16,
+            "Black has more than 16 pieces"
+        );
+        ensure!(
+            self.squares[..8]
+                .iter()
+                .chain(self.squares[56..].iter())
+                .all(|piece| *piece != Piece::WhitePawn && *piece != Piece::BlackPawn),
+            "Pawns can't be on the first or last rank"
+        );
+
+        for (allowed, king_sq, rook_sq, king, rook, name) in &[
+            (
+                self.castling.white_king_side,
+                4usize,
+                7usize,
+                Piece::WhiteKing,
+                Piece::WhiteRook,
+                "K",
+            ),
+            (
+                self.castling.white_queen_side,
+                4,
+                0,
+                Piece::WhiteKing,
+                Piece::WhiteRook,
+                "Q",
+            ),
+            (
+                self.castling.black_king_side,
+                60,
+                63,
+                Piece::BlackKing,
+                Piece::BlackRook,
+                "k",
+            ),
+            (
+                self.castling.black_queen_side,
+                60,
+                56,
+                Piece::BlackKing,
+                Piece::BlackRook,
+                "q",
+            ),
+        ] {
+            ensure!(
+                !*allowed || (self.squares[*king_sq] == *king && self.squares[*rook_sq] == *rook),
+                "Castling \"{}\" needs the king and rook on their starting squares",
+                name
+            );
+        }
+        if let Some(en_passant) = self.en_passant {
+            ensure!(
+                self.en_passant_squares().contains(&en_passant),
+                "No pawn could have skipped {}",
+                en_passant
+            );
+        }
+
+        // The player who just moved can't have left their king in check
+        let mut other_turn = self.clone();
+        other_turn.turn = self.turn.other_player();
+        other_turn.en_passant = None;
+        let other_turn_board =
+            Board::from_fen(&other_turn.fen()).map_err(|e| anyhow!("Invalid position: {:?}", e))?;
+        ensure!(
+            !other_turn_board.in_check(),
+            "{} is in check but it's not their turn",
+            other_turn.turn
+        );
+
+        Board::from_fen(&self.fen()).map_err(|e| anyhow!("Invalid position: {:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sq(square: &str) -> Square {
+        square.parse().unwrap()
+    }
+
+    #[test]
+    fn roundtrips_fen() {
+        let fen = "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 1";
+        let setup = PositionSetup::from_fen(fen).unwrap();
+        assert_eq!(setup.piece_at(sq("E5")), Piece::WhitePawn);
+        assert_eq!(setup.en_passant, Some(sq("D6")));
+        assert_eq!(setup.fen(), fen);
+        assert!(setup.validate().is_ok());
+        assert_eq!(PositionSetup::default().fen(), Board::default().fen());
+    }
+
+    #[test]
+    fn builds_position_piece_by_piece() {
+        let mut setup = PositionSetup::empty();
+        setup.set_piece(sq("E1"), Piece::WhiteKing);
+        assert!(setup.validate().is_err());
+        setup.set_piece(sq("E8"), Piece::BlackKing);
+        setup.set_piece(sq("A7"), Piece::WhiteQueen);
+        assert_eq!(setup.fen(), "4k3/Q7/8/8/8/8/8/4K3 w - - 0 1");
+        let board = setup.validate().unwrap();
+        assert_eq!(board.generate_moves().len(), 5 + 21);
+    }
+
+    #[test]
+    fn rejects_illegal_positions() {
+        let invalid = &[
+            // Black is in check while white is to move
+            "4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1",
+            // Pawn on the last rank
+            "3Pk3/8/8/8/8/8/8/4K3 w - - 0 1",
+            // Castling without a rook
+            "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+            // No pawn could have skipped e6
+            "4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+        ];
+        for fen in invalid.iter() {
+            let setup = PositionSetup::from_fen(fen).unwrap();
+            assert!(setup.validate().is_err(), "{} should be invalid", fen);
+        }
+    }
+}
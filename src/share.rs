@@ -0,0 +1,94 @@
+//! Positions and games as short texts (e.g. for a QR code) and reading
+//! them back in.
+
+use crate::game::ChessGame;
+use anyhow::Result;
+use pleco::Board;
+
+/// A PGN of just the moves, without the seven tag roster. Only a game
+/// that doesn't start from the usual position gets its "FEN" tag.
+pub fn compact_pgn(start_fen: &str, moves: &[String]) -> String {
+    let mut pgn = String::new();
+    if start_fen != Board::default().fen() {
+        pgn.push_str(&format!("[FEN \"{}\"]\n[SetUp \"1\"]\n\n", start_fen));
+    }
+    let fields: Vec<&str> = start_fen.split_whitespace().collect();
+    let black_starts = fields.get(1) == Some(&"b");
+    let first_move_number: usize = fields.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+
+    let mut movetext: Vec<String> = vec![];
+    for (i, written) in moves.iter().enumerate() {
+        let ply = i + black_starts as usize;
+        let move_number = first_move_number + ply / 2;
+        if ply % 2 == 0 {
+            movetext.push(format!("{}. {}", move_number, written));
+        } else if i == 0 {
+            movetext.push(format!("{}... {}", move_number, written));
+        } else {
+            movetext.push(written.clone());
+        }
+    }
+    movetext.push("*".to_owned());
+    pgn.push_str(&movetext.join(" "));
+    pgn
+}
+
+/// The game of a shared text, either a FEN or a PGN. PGN moves are
+/// played (so the game continues after the last one).
+pub fn read_shared(text: &str) -> Result<ChessGame> {
+    let text = text.trim();
+    if let Ok(game) = ChessGame::from_fen(text) {
+        return Ok(game);
+    }
+    // The result is optional when typed
+    let ends_with_result = ["*", "1-0", "0-1", "1/2-1/2"]
+        .iter()
+        .any(|result| text.ends_with(result));
+    if ends_with_result {
+        ChessGame::from_pgn(text)
+    } else {
+        ChessGame::from_pgn(&format!("{} *", text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(sans: &[&str]) -> Vec<String> {
+        sans.iter().map(|san| san.to_string()).collect()
+    }
+
+    #[test]
+    fn compact_pgn_from_the_start() {
+        let start = Board::default().fen();
+        let pgn = compact_pgn(&start, &moves(&["e4", "e5", "Nf3"]));
+        assert_eq!(pgn, "1. e4 e5 2. Nf3 *");
+        assert_eq!(compact_pgn(&start, &[]), "*");
+    }
+
+    #[test]
+    fn compact_pgn_from_a_position() {
+        let start = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12";
+        let pgn = compact_pgn(start, &moves(&["Kd7", "e4", "Ke6"]));
+        assert_eq!(
+            pgn,
+            "[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 12\"]\n[SetUp \"1\"]\n\n12... Kd7 13. e4 Ke6 *"
+        );
+        assert_eq!(
+            read_shared(&pgn).unwrap().fen(),
+            "8/8/4k3/8/4P3/8/8/4K3 w - - 1 14"
+        );
+    }
+
+    #[test]
+    fn reads_fens_and_typed_moves() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        assert_eq!(read_shared(fen).unwrap().fen(), fen);
+        assert_eq!(
+            read_shared("1. e4 e5 2. Nf3").unwrap().fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        assert!(read_shared("1. e4 e5 2. Ke3").is_err());
+    }
+}
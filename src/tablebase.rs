@@ -0,0 +1,397 @@
+//! Perfect play for the basic mates: king and queen or king and rook
+//! against the lone king. The tables are built by retrograde analysis
+//! the first time they are needed (a quarter million positions for each
+//! side to move, which takes a moment on the device).
+
+use crate::Player;
+use lazy_static::lazy_static;
+use pleco::{BitMove, Board, PieceType};
+
+/// Marks draws and illegal positions in the tables
+const NOT_WON: u8 = u8::MAX;
+const POSITIONS: usize = 64 * 64 * 64;
+
+lazy_static! {
+    static ref QUEEN_TABLE: Table = Table::generate(Attacker::Queen);
+    static ref ROOK_TABLE: Table = Table::generate(Attacker::Rook);
+}
+
+/// The piece of the winning side
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attacker {
+    Queen,
+    Rook,
+}
+
+/// Outcome of a position with perfect play from both sides
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// The side with the queen or rook mates in this many plies
+    Win(u8),
+    Draw,
+}
+
+/// Squares of the three pieces. The strong side has the queen or rook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Position {
+    strong_king: u8,
+    piece: u8,
+    weak_king: u8,
+}
+
+impl Position {
+    fn index(self) -> usize {
+        (self.strong_king as usize * 64 + self.piece as usize) * 64 + self.weak_king as usize
+    }
+
+    fn from_index(index: usize) -> Self {
+        Position {
+            strong_king: (index / 64 / 64) as u8,
+            piece: (index / 64 % 64) as u8,
+            weak_king: (index % 64) as u8,
+        }
+    }
+}
+
+fn file(square: u8) -> i8 {
+    (square % 8) as i8
+}
+
+fn rank(square: u8) -> i8 {
+    (square / 8) as i8
+}
+
+fn distance(a: u8, b: u8) -> i8 {
+    (file(a) - file(b)).abs().max((rank(a) - rank(b)).abs())
+}
+
+/// The square `steps` times in `direction` (file, rank) away if it's on the board
+fn step(square: u8, direction: (i8, i8), steps: i8) -> Option<u8> {
+    let (file, rank) = (
+        file(square) + direction.0 * steps,
+        rank(square) + direction.1 * steps,
+    );
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as u8)
+    } else {
+        None
+    }
+}
+
+const KING_DIRECTIONS: [(i8, i8); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+impl Attacker {
+    fn directions(self) -> &'static [(i8, i8)] {
+        match self {
+            Attacker::Queen => &KING_DIRECTIONS,
+            Attacker::Rook => &ROOK_DIRECTIONS,
+        }
+    }
+
+    /// Whether the piece on `from` attacks `to` when `blocker` is occupied
+    fn attacks(self, from: u8, to: u8, blocker: u8) -> bool {
+        for direction in self.directions() {
+            for steps in 1..8 {
+                match step(from, *direction, steps) {
+                    Some(square) if square == to => return true,
+                    Some(square) if square != blocker => {}
+                    _ => break,
+                }
+            }
+        }
+        false
+    }
+}
+
+struct Table {
+    /// Plies to mate with the strong side to move
+    strong_to_move: Vec<u8>,
+    /// Plies to mate with the weak side to move
+    weak_to_move: Vec<u8>,
+}
+
+fn is_legal(position: Position) -> bool {
+    position.strong_king != position.piece
+        && position.weak_king != position.piece
+        && distance(position.strong_king, position.weak_king) > 1
+}
+
+fn weak_in_check(attacker: Attacker, position: Position) -> bool {
+    attacker.attacks(position.piece, position.weak_king, position.strong_king)
+}
+
+/// Positions after every move of the strong side
+fn strong_moves(attacker: Attacker, position: Position, moves: &mut Vec<Position>) {
+    moves.clear();
+    for direction in &KING_DIRECTIONS {
+        if let Some(square) = step(position.strong_king, *direction, 1) {
+            if square != position.piece && distance(square, position.weak_king) > 1 {
+                moves.push(Position {
+                    strong_king: square,
+                    ..position
+                });
+            }
+        }
+    }
+    for direction in attacker.directions() {
+        for steps in 1..8 {
+            match step(position.piece, *direction, steps) {
+                Some(square) if square != position.strong_king && square != position.weak_king => {
+                    moves.push(Position {
+                        piece: square,
+                        ..position
+                    })
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Positions after every move of the lone king, `None` if it captures
+/// the piece (a draw)
+fn weak_moves(attacker: Attacker, position: Position, moves: &mut Vec<Option<Position>>) {
+    moves.clear();
+    for direction in &KING_DIRECTIONS {
+        let square = match step(position.weak_king, *direction, 1) {
+            Some(square) => square,
+            None => continue,
+        };
+        if distance(square, position.strong_king) <= 1 {
+            continue;
+        }
+        if square == position.piece {
+            moves.push(None);
+        } else if !attacker.attacks(position.piece, square, position.strong_king) {
+            moves.push(Some(Position {
+                weak_king: square,
+                ..position
+            }));
+        }
+    }
+}
+
+impl Table {
+    fn generate(attacker: Attacker) -> Self {
+        let mut strong_to_move = vec![NOT_WON; POSITIONS];
+        let mut weak_to_move = vec![NOT_WON; POSITIONS];
+        let mut strong_positions = vec![];
+        let mut weak_positions = vec![];
+        let mut strong_pending = vec![];
+        let mut weak_pending = vec![];
+        for index in 0..POSITIONS {
+            let position = Position::from_index(index);
+            if !is_legal(position) {
+                continue;
+            }
+            let in_check = weak_in_check(attacker, position);
+            if !in_check {
+                strong_pending.push(position);
+            }
+            weak_moves(attacker, position, &mut weak_positions);
+            if weak_positions.is_empty() {
+                if in_check {
+                    weak_to_move[position.index()] = 0; // Checkmate
+                }
+            } else if !weak_positions.contains(&None) {
+                weak_pending.push(position);
+            }
+        }
+
+        // Level by level: the strong side mates in an odd number of plies
+        // if one move reaches a position lost in one ply less. The weak
+        // side is lost once all of its moves lead to won positions.
+        let mut ply: u8 = 1;
+        let mut unchanged_levels = 0;
+        while unchanged_levels < 2 {
+            let mut changed = false;
+            if ply % 2 == 1 {
+                strong_pending.retain(|position| {
+                    strong_moves(attacker, *position, &mut strong_positions);
+                    let won = strong_positions
+                        .iter()
+                        .any(|next| weak_to_move[next.index()] == ply - 1);
+                    if won {
+                        strong_to_move[position.index()] = ply;
+                        changed = true;
+                    }
+                    !won
+                });
+            } else {
+                weak_pending.retain(|position| {
+                    weak_moves(attacker, *position, &mut weak_positions);
+                    let lost = weak_positions.iter().all(|next| {
+                        next.map_or(false, |next| strong_to_move[next.index()] != NOT_WON)
+                    });
+                    if lost {
+                        weak_to_move[position.index()] = ply;
+                        changed = true;
+                    }
+                    !lost
+                });
+            }
+            unchanged_levels = if changed { 0 } else { unchanged_levels + 1 };
+            ply += 1;
+        }
+        Table {
+            strong_to_move,
+            weak_to_move,
+        }
+    }
+}
+
+fn table(attacker: Attacker) -> &'static Table {
+    match attacker {
+        Attacker::Queen => &QUEEN_TABLE,
+        Attacker::Rook => &ROOK_TABLE,
+    }
+}
+
+/// The pieces of `board` if it's covered: the attacker, who has it and
+/// their squares (the tables don't care which color the strong side has)
+fn classify(board: &Board) -> Option<(Attacker, Player, Position)> {
+    let mut kings: [Option<u8>; 2] = [None, None];
+    let mut attacker: Option<(Attacker, Player, u8)> = None;
+    for (sq, piece) in board.get_piece_locations() {
+        let player = Player::from(piece.player_lossy());
+        match piece.type_of() {
+            PieceType::K => kings[player as usize] = Some(sq.0),
+            PieceType::Q if attacker.is_none() => attacker = Some((Attacker::Queen, player, sq.0)),
+            PieceType::R if attacker.is_none() => attacker = Some((Attacker::Rook, player, sq.0)),
+            _ => return None,
+        }
+    }
+    let (attacker, strong, piece) = attacker?;
+    let position = Position {
+        strong_king: kings[strong as usize]?,
+        piece,
+        weak_king: kings[strong.other_player() as usize]?,
+    };
+    Some((attacker, strong, position))
+}
+
+/// Whether the tables have the position (without building them)
+pub fn covers(board: &Board) -> bool {
+    classify(board).is_some()
+}
+
+/// Outcome of the position with perfect play. `None` unless the board has
+/// nothing but a king with a queen or rook against the lone king.
+pub fn probe(board: &Board) -> Option<Probe> {
+    let (attacker, strong, position) = classify(board)?;
+    let table = table(attacker);
+    let plies = if Player::from(board.turn()) == strong {
+        table.strong_to_move[position.index()]
+    } else {
+        table.weak_to_move[position.index()]
+    };
+    Some(if plies == NOT_WON {
+        Probe::Draw
+    } else {
+        Probe::Win(plies)
+    })
+}
+
+/// The move of perfect play: the fastest mate for the side with the
+/// queen or rook, the longest resistance (or a draw if it's allowed) for
+/// the lone king. `None` if the position isn't covered or has no moves.
+pub fn best_move(board: &Board) -> Option<BitMove> {
+    let (_, strong, _) = classify(board)?;
+    let strong_to_move = Player::from(board.turn()) == strong;
+    let mut board = board.shallow_clone();
+    let mut best: Option<(BitMove, i32)> = None;
+    for bit_move in board.generate_moves().iter().copied() {
+        board.apply_move(bit_move);
+        // Capturing the piece leaves nothing to probe, a draw as well
+        let score = match (probe(&board), strong_to_move) {
+            (Some(Probe::Win(plies)), true) => 1000 - plies as i32,
+            (Some(Probe::Win(plies)), false) => plies as i32,
+            (_, true) => 0,
+            (_, false) => 1000,
+        };
+        board.undo_move();
+        if best.map_or(true, |(_, best_score)| score > best_score) {
+            best = Some((bit_move, score));
+        }
+    }
+    best.map(|(bit_move, _)| bit_move)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn probes_known_positions() {
+        // Rh8#
+        assert_eq!(
+            probe(&board("k7/8/1K6/8/8/8/8/7R w - - 0 1")),
+            Some(Probe::Win(1))
+        );
+        // Kb8 and Rh8#
+        assert_eq!(
+            probe(&board("k7/8/1K6/8/8/8/8/7R b - - 0 1")),
+            Some(Probe::Win(2))
+        );
+        // The king takes the undefended rook
+        assert_eq!(
+            probe(&board("8/8/8/3Rk3/8/8/8/K7 b - - 0 1")),
+            Some(Probe::Draw)
+        );
+        // Stalemate
+        assert_eq!(
+            probe(&board("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1")),
+            Some(Probe::Draw)
+        );
+        // The same with colors swapped
+        assert_eq!(
+            probe(&board("7r/8/8/8/8/1k6/8/K7 b - - 0 1")),
+            Some(Probe::Win(1))
+        );
+        assert_eq!(probe(&board("8/8/8/4k3/8/8/3P4/R3K3 w - - 0 1")), None);
+    }
+
+    #[test]
+    fn longest_mates_match_theory() {
+        for (attacker, longest) in &[(Attacker::Queen, 10), (Attacker::Rook, 16)] {
+            let max_plies = table(*attacker)
+                .strong_to_move
+                .iter()
+                .filter(|plies| **plies != NOT_WON)
+                .max()
+                .unwrap();
+            assert_eq!((*max_plies as u16 + 1) / 2, *longest);
+        }
+    }
+
+    #[test]
+    fn best_move_mates_and_defends() {
+        let mate = best_move(&board("k7/8/1K6/8/8/8/8/7R w - - 0 1")).unwrap();
+        assert_eq!(mate.stringify(), "h1h8");
+        let capture = best_move(&board("8/8/8/3Rk3/8/8/8/K7 b - - 0 1")).unwrap();
+        assert_eq!(capture.stringify(), "e5d5");
+
+        // The defense holds out as long as possible
+        let mut defending = board("8/8/8/4k3/8/8/8/R3K3 b - - 0 1");
+        let plies = match probe(&defending) {
+            Some(Probe::Win(plies)) => plies,
+            probe => panic!("Unexpected {:?}", probe),
+        };
+        defending.apply_move(best_move(&defending).unwrap());
+        assert_eq!(probe(&defending), Some(Probe::Win(plies - 1)));
+    }
+}
@@ -0,0 +1,504 @@
+//! Round-robin tournaments between the built-in engines. The games are
+//! played without the game protocol, so they run as fast as the engines
+//! can search.
+
+use crate::game::{BitMove, Board, ChessGame, ChessOutcome, PromotionPiece, VariantKind};
+use crate::middlegames::{Middlegame, MIDDLEGAMES};
+use crate::notation::san;
+use crate::scoresheet;
+use crate::Player;
+use anyhow::Result;
+use pleco::bot_prelude::{AlphaBetaSearcher, IterativeSearcher, JamboreeSearcher};
+use pleco::tools::Searcher;
+use serde::{Deserialize, Serialize};
+
+/// Games still going on after this many half-moves are drawn
+const MAX_PLIES: usize = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Engine {
+    AlphaBeta,
+    /// Multithreaded alpha-beta
+    Jamboree,
+    /// Iterative deepening
+    Iterative,
+}
+
+impl Engine {
+    pub fn label(self) -> &'static str {
+        match self {
+            Engine::AlphaBeta => "AlphaBeta",
+            Engine::Jamboree => "Jamboree",
+            Engine::Iterative => "Iterative",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Engine::AlphaBeta => Engine::Jamboree,
+            Engine::Jamboree => Engine::Iterative,
+            Engine::Iterative => Engine::AlphaBeta,
+        }
+    }
+
+    pub fn best_move(self, board: Board, depth: u16) -> BitMove {
+        match self {
+            Engine::AlphaBeta => AlphaBetaSearcher::best_move(board, depth),
+            Engine::Jamboree => JamboreeSearcher::best_move(board, depth),
+            Engine::Iterative => IterativeSearcher::best_move(board, depth),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Participant {
+    pub engine: Engine,
+    pub depth: u16,
+}
+
+impl Participant {
+    /// E.g. "AlphaBeta 4"
+    pub fn name(&self) -> String {
+        format!("{} {}", self.engine.label(), self.depth)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TournamentConfig {
+    pub participants: Vec<Participant>,
+    /// Everyone plays everyone twice, once with each color
+    pub double_round_robin: bool,
+    /// Start the games of a round from one of the bundled middlegame
+    /// positions. The engines always play the same moves otherwise.
+    pub use_openings: bool,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        Self {
+            participants: [2, 3, 4]
+                .iter()
+                .map(|depth| Participant {
+                    engine: Engine::AlphaBeta,
+                    depth: *depth,
+                })
+                .collect(),
+            double_round_robin: true,
+            use_openings: true,
+        }
+    }
+}
+
+/// Game of a tournament with the participants as indexes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pairing {
+    /// Starting at 0
+    pub round: usize,
+    pub white: usize,
+    pub black: usize,
+}
+
+/// Pairings of the circle method: everyone plays everyone once per cycle
+/// and the colors alternate. With an odd number of participants one of
+/// them sits out every round.
+pub fn round_robin(participants: usize, double: bool) -> Vec<Pairing> {
+    let mut seats: Vec<Option<usize>> = (0..participants).map(Some).collect();
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+    let rounds = seats.len().saturating_sub(1);
+    let mut pairings = vec![];
+    for round in 0..rounds {
+        for i in 0..seats.len() / 2 {
+            if let (Some(a), Some(b)) = (seats[i], seats[seats.len() - 1 - i]) {
+                let (white, black) = if (round + i) % 2 == 0 { (a, b) } else { (b, a) };
+                pairings.push(Pairing {
+                    round,
+                    white,
+                    black,
+                });
+            }
+        }
+        // Everyone but the first one moves on by one seat
+        if let Some(last) = seats.pop() {
+            seats.insert(1, last);
+        }
+    }
+    if double {
+        let first_cycle = pairings.clone();
+        pairings.extend(first_cycle.into_iter().map(|pairing| Pairing {
+            round: pairing.round + rounds,
+            white: pairing.black,
+            black: pairing.white,
+        }));
+    }
+    pairings
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWon,
+    BlackWon,
+    Draw,
+}
+
+impl GameResult {
+    /// Games without an outcome were stopped after `MAX_PLIES` and are drawn
+    pub fn from_outcome(outcome: Option<ChessOutcome>) -> Self {
+        match outcome.and_then(ChessOutcome::winner) {
+            Some(Player::White) => GameResult::WhiteWon,
+            Some(Player::Black) => GameResult::BlackWon,
+            None => GameResult::Draw,
+        }
+    }
+
+    /// As in PGNs
+    pub fn label(self) -> &'static str {
+        match self {
+            GameResult::WhiteWon => "1-0",
+            GameResult::BlackWon => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    pub fn points(self, player: Player) -> f32 {
+        match (self, player) {
+            (GameResult::Draw, _) => 0.5,
+            (GameResult::WhiteWon, Player::White) | (GameResult::BlackWon, Player::Black) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayedGame {
+    pub pairing: Pairing,
+    /// `None` for the usual starting position
+    pub starting_fen: Option<String>,
+    /// In standard algebraic notation
+    pub moves: Vec<String>,
+    /// `None` if it was drawn after `MAX_PLIES`
+    pub outcome: Option<ChessOutcome>,
+    pub result: GameResult,
+}
+
+impl PlayedGame {
+    /// Value of the PGN "Termination" tag
+    pub fn termination(&self) -> &'static str {
+        match self.outcome {
+            Some(outcome) => outcome.pgn_termination(),
+            None => "adjudication",
+        }
+    }
+}
+
+/// Play a game between two engines. `on_move` gets the position after
+/// every move and stops the game (which returns `None` then) by returning
+/// `false`. Besides the usual draws, games are drawn after `MAX_PLIES`
+/// half-moves (without an outcome).
+pub fn play_game(
+    white: &Participant,
+    black: &Participant,
+    starting_fen: Option<&str>,
+    mut on_move: impl FnMut(&str) -> bool,
+) -> Result<Option<(Vec<String>, Option<ChessOutcome>)>> {
+    let mut game = match starting_fen {
+        Some(fen) => ChessGame::from_fen(fen)?,
+        None => ChessGame::with_variant(VariantKind::Standard)?,
+    };
+    let mut moves = vec![];
+    let outcome = loop {
+        if game.outcome().is_some() || moves.len() >= MAX_PLIES {
+            break game.outcome();
+        }
+
+        let participant = match game.turn() {
+            Player::White => white,
+            Player::Black => black,
+        };
+        let board = game.board();
+        let bit_move = participant
+            .engine
+            .best_move(board.shallow_clone(), participant.depth);
+        ensure!(!bit_move.is_null(), "{} found no move", participant.name());
+        moves.push(san(&board, bit_move));
+        game.move_piece_with_promotion(
+            bit_move.get_src().into(),
+            bit_move.get_dest().into(),
+            if bit_move.is_promo() {
+                PromotionPiece::from_piece_type(bit_move.promo_piece())
+            } else {
+                None
+            },
+        )?;
+        if !on_move(&game.fen()) {
+            return Ok(None);
+        }
+    };
+    Ok(Some((moves, outcome)))
+}
+
+/// Points of every participant against every other one
+#[derive(Clone, Debug)]
+pub struct Crosstable {
+    pub names: Vec<String>,
+    /// Points of the row against the column (`None` before they played)
+    points: Vec<Vec<Option<f32>>>,
+}
+
+impl Crosstable {
+    pub fn new(names: Vec<String>) -> Self {
+        let points = vec![vec![None; names.len()]; names.len()];
+        Self { names, points }
+    }
+
+    pub fn add(&mut self, pairing: &Pairing, result: GameResult) {
+        let (white, black) = (pairing.white, pairing.black);
+        let white_points = self.points[white][black].get_or_insert(0.0);
+        *white_points += result.points(Player::White);
+        let black_points = self.points[black][white].get_or_insert(0.0);
+        *black_points += result.points(Player::Black);
+    }
+
+    /// "*" against themself, empty before they played, e.g. "1.5" otherwise
+    pub fn cell(&self, row: usize, column: usize) -> String {
+        if row == column {
+            return "*".to_owned();
+        }
+        self.points[row][column].map_or(String::new(), |points| points.to_string())
+    }
+
+    pub fn total(&self, participant: usize) -> f32 {
+        self.points[participant].iter().flatten().sum()
+    }
+
+    /// Participants with the most points first (ties keep their order)
+    pub fn standings(&self) -> Vec<usize> {
+        let mut standings: Vec<usize> = (0..self.names.len()).collect();
+        standings.sort_by(|a, b| {
+            self.total(*b)
+                .partial_cmp(&self.total(*a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        standings
+    }
+}
+
+pub struct Tournament {
+    pub config: TournamentConfig,
+    pub pairings: Vec<Pairing>,
+    pub games: Vec<PlayedGame>,
+    pub crosstable: Crosstable,
+}
+
+impl Tournament {
+    pub fn new(config: TournamentConfig) -> Self {
+        let pairings = round_robin(config.participants.len(), config.double_round_robin);
+        let crosstable = Crosstable::new(config.participants.iter().map(|p| p.name()).collect());
+        Self {
+            config,
+            pairings,
+            games: vec![],
+            crosstable,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.games.len() >= self.pairings.len()
+    }
+
+    /// Position the games of a round start from. Both games of a pair
+    /// in a double round robin get the same one.
+    pub fn opening(&self, pairing: &Pairing) -> Option<&'static Middlegame> {
+        if !self.config.use_openings {
+            return None;
+        }
+        let participants = self.config.participants.len();
+        let rounds = (participants + participants % 2).saturating_sub(1).max(1);
+        Some(&MIDDLEGAMES[pairing.round % rounds % MIDDLEGAMES.len()])
+    }
+
+    /// The game played next (`None` once all are played)
+    pub fn next_pairing(&self) -> Option<Pairing> {
+        self.pairings.get(self.games.len()).copied()
+    }
+
+    /// Play the next game (see `play_game()` for `on_move`). Returns
+    /// `false` if the tournament is finished or the game was stopped.
+    pub fn play_next(&mut self, on_move: impl FnMut(&str) -> bool) -> Result<bool> {
+        let pairing = match self.next_pairing() {
+            Some(pairing) => pairing,
+            None => return Ok(false),
+        };
+        let starting_fen = self.opening(&pairing).map(|opening| opening.fen);
+        let played = play_game(
+            &self.config.participants[pairing.white],
+            &self.config.participants[pairing.black],
+            starting_fen,
+            on_move,
+        )?;
+        let (moves, outcome) = match played {
+            Some(played) => played,
+            None => return Ok(false),
+        };
+        let result = GameResult::from_outcome(outcome);
+        self.crosstable.add(&pairing, result);
+        self.games.push(PlayedGame {
+            pairing,
+            starting_fen: starting_fen.map(str::to_owned),
+            moves,
+            outcome,
+            result,
+        });
+        Ok(true)
+    }
+
+    /// All played games (e.g. to add them to the PGN folder)
+    pub fn to_pgn(&self, date: &str) -> String {
+        self.games
+            .iter()
+            .map(|game| {
+                let round = (game.pairing.round + 1).to_string();
+                let white = self.crosstable.names[game.pairing.white].as_str();
+                let black = self.crosstable.names[game.pairing.black].as_str();
+                let fen = game.starting_fen.as_deref().unwrap_or("");
+                scoresheet::to_pgn(
+                    &game.moves,
+                    &[
+                        ("Event", "Engine tournament"),
+                        ("Site", "chessMarkable"),
+                        ("Date", date),
+                        ("Round", &round),
+                        ("White", white),
+                        ("Black", black),
+                        ("Result", game.result.label()),
+                        ("Termination", game.termination()),
+                        ("SetUp", if fen.is_empty() { "" } else { "1" }),
+                        ("FEN", fen),
+                    ],
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bot(depth: u16) -> Participant {
+        Participant {
+            engine: Engine::AlphaBeta,
+            depth,
+        }
+    }
+
+    #[test]
+    fn everyone_plays_everyone_once() {
+        for participants in 2..=7 {
+            let pairings = round_robin(participants, false);
+            assert_eq!(pairings.len(), participants * (participants - 1) / 2);
+            for a in 0..participants {
+                for b in a + 1..participants {
+                    let games = pairings
+                        .iter()
+                        .filter(|p| (p.white, p.black) == (a, b) || (p.white, p.black) == (b, a))
+                        .count();
+                    assert_eq!(games, 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn double_round_robin_swaps_colors() {
+        let pairings = round_robin(4, true);
+        assert_eq!(pairings.len(), 12);
+        let (first, second) = pairings.split_at(6);
+        for (a, b) in first.iter().zip(second) {
+            assert_eq!((a.white, a.black), (b.black, b.white));
+            assert_eq!(a.round + 3, b.round);
+        }
+    }
+
+    #[test]
+    fn nobody_plays_twice_in_a_round() {
+        let pairings = round_robin(5, false);
+        for round in 0..5 {
+            let mut playing: Vec<usize> = pairings
+                .iter()
+                .filter(|p| p.round == round)
+                .flat_map(|p| vec![p.white, p.black])
+                .collect();
+            assert_eq!(playing.len(), 4);
+            playing.sort();
+            playing.dedup();
+            assert_eq!(playing.len(), 4);
+        }
+    }
+
+    #[test]
+    fn crosstable_adds_up_points() {
+        let mut crosstable = Crosstable::new(vec!["A".into(), "B".into(), "C".into()]);
+        let pairing = |white, black| Pairing {
+            round: 0,
+            white,
+            black,
+        };
+        crosstable.add(&pairing(0, 1), GameResult::Draw);
+        crosstable.add(&pairing(1, 0), GameResult::BlackWon);
+        crosstable.add(&pairing(2, 1), GameResult::BlackWon);
+        assert_eq!(crosstable.cell(0, 1), "1.5");
+        assert_eq!(crosstable.cell(1, 0), "0.5");
+        assert_eq!(crosstable.cell(0, 2), "");
+        assert_eq!(crosstable.cell(2, 2), "*");
+        assert_eq!(crosstable.total(1), 1.5);
+        assert_eq!(crosstable.standings(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn plays_until_mate() {
+        let (moves, outcome) = play_game(
+            &bot(1),
+            &bot(1),
+            Some("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"),
+            |_| true,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(moves, vec!["Ra8#"]);
+        assert_eq!(
+            outcome,
+            Some(ChessOutcome::Checkmate {
+                winner: Player::White
+            })
+        );
+        assert_eq!(GameResult::from_outcome(outcome), GameResult::WhiteWon);
+        assert_eq!(GameResult::from_outcome(None), GameResult::Draw);
+    }
+
+    #[test]
+    fn can_be_stopped() {
+        assert!(play_game(&bot(1), &bot(1), None, |_| false)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn writes_results_to_pgn() {
+        let mut tournament = Tournament::new(TournamentConfig {
+            participants: vec![bot(1), bot(2)],
+            double_round_robin: false,
+            use_openings: true,
+        });
+        assert!(tournament.play_next(|_| true).unwrap());
+        assert!(tournament.is_finished());
+        assert!(!tournament.play_next(|_| true).unwrap());
+        let pgn = tournament.to_pgn("2021.03.04");
+        let game = &tournament.games[0];
+        assert!(pgn.contains(&format!("[Result \"{}\"]", game.result.label())));
+        assert!(pgn.contains(&format!("[Termination \"{}\"]", game.termination())));
+        assert!(pgn.contains(&format!("[FEN \"{}\"]", MIDDLEGAMES[0].fen)));
+    }
+}
@@ -0,0 +1,159 @@
+//! JavaScript bindings of the rules, replays and notation for the WASM
+//! build (feature "wasm"). Structured values are passed as JSON strings.
+
+use crate::game::{ChessGame, PossibleMove, VariantKind};
+use crate::notation;
+use crate::replay::Replay;
+use crate::Square;
+use wasm_bindgen::prelude::*;
+
+fn js_error(err: impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+fn square(name: &str) -> Result<Square, JsValue> {
+    name.to_uppercase().parse().map_err(js_error)
+}
+
+#[wasm_bindgen]
+pub struct Game {
+    game: ChessGame,
+}
+
+#[wasm_bindgen]
+impl Game {
+    /// The starting position if no FEN is given
+    #[wasm_bindgen(constructor)]
+    pub fn new(fen: Option<String>, crazyhouse: bool) -> Result<Game, JsValue> {
+        let variant = if crazyhouse {
+            VariantKind::Crazyhouse
+        } else {
+            VariantKind::Standard
+        };
+        let game = match fen {
+            Some(fen) => ChessGame::from_untrusted_fen(&fen, variant),
+            None => ChessGame::with_variant(variant),
+        }
+        .map_err(js_error)?;
+        Ok(Game { game })
+    }
+
+    /// The first game of a PGN with its moves played (they can be undone)
+    #[wasm_bindgen(js_name = fromPgn)]
+    pub fn from_pgn(pgn: &str) -> Result<Game, JsValue> {
+        let game = ChessGame::from_pgn(pgn).map_err(js_error)?;
+        Ok(Game { game })
+    }
+
+    /// With the pockets in crazyhouse (e.g. "...RNBQKBNR[Qp] w ...")
+    pub fn fen(&self) -> String {
+        self.game.fen_with_pockets()
+    }
+
+    /// "White" or "Black"
+    pub fn turn(&self) -> String {
+        self.game.turn().to_string()
+    }
+
+    #[wasm_bindgen(js_name = inCheck)]
+    pub fn in_check(&self) -> bool {
+        self.game.in_check()
+    }
+
+    /// JSON array of the possible moves (see `PossibleMove`)
+    #[wasm_bindgen(js_name = possibleMoves)]
+    pub fn possible_moves(&self) -> String {
+        let possible_moves = PossibleMove::from_bit_moves(&self.game.possible_moves());
+        serde_json::to_string(&possible_moves).unwrap()
+    }
+
+    /// JSON of the outcome or `null` while the game goes on
+    pub fn outcome(&self) -> String {
+        serde_json::to_string(&self.game.outcome()).unwrap()
+    }
+
+    /// E.g. "White is checkmated!"
+    #[wasm_bindgen(js_name = outcomeDescription)]
+    pub fn outcome_description(&self) -> Option<String> {
+        self.game.outcome().map(|outcome| outcome.description())
+    }
+
+    /// Play a move in UCI notation (e.g. "e2e4" or "e7e8q")
+    #[wasm_bindgen(js_name = moveUci)]
+    pub fn move_uci(&mut self, uci: &str) -> Result<(), JsValue> {
+        self.game.move_piece_uci(uci).map_err(js_error)
+    }
+
+    /// Play a move in standard algebraic notation (e.g. "Nf3")
+    #[wasm_bindgen(js_name = moveSan)]
+    pub fn move_san(&mut self, san: &str) -> Result<(), JsValue> {
+        let bit_move = notation::parse_san(&self.game.board(), san).map_err(js_error)?;
+        self.game
+            .move_piece_uci(&bit_move.stringify())
+            .map_err(js_error)
+    }
+
+    /// The move in standard algebraic notation (`undefined` if it isn't legal)
+    pub fn san(&self, source: &str, destination: &str) -> Result<Option<String>, JsValue> {
+        Ok(self.game.san(square(source)?, square(destination)?, None))
+    }
+
+    pub fn undo(&mut self, moves: u16) -> Result<(), JsValue> {
+        self.game.undo(moves).map_err(js_error)
+    }
+}
+
+/// Steps through the first game of a PGN
+#[wasm_bindgen(js_name = Replay)]
+pub struct WasmReplay {
+    replay: Replay,
+}
+
+#[wasm_bindgen(js_class = Replay)]
+impl WasmReplay {
+    #[wasm_bindgen(constructor)]
+    pub fn new(pgn: &str) -> Result<WasmReplay, JsValue> {
+        let game = chess_pgn_parser::read_games(pgn)
+            .map_err(|err| js_error(format!("Failed to parse PGN: {:?}", err)))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| js_error("The PGN has no games"))?;
+        Ok(WasmReplay {
+            replay: Replay::new(game).map_err(js_error)?,
+        })
+    }
+
+    pub fn fen(&self) -> String {
+        self.replay.fen()
+    }
+
+    /// Result tag of the game (e.g. "1-0")
+    pub fn result(&self) -> String {
+        self.replay.result().to_owned()
+    }
+
+    /// Play the next move of the game and return the FEN after it
+    pub fn next(&mut self) -> String {
+        self.replay.play_replay_move().fen
+    }
+
+    /// Take back the last move and return the FEN before it
+    pub fn back(&mut self) -> String {
+        self.replay.undo_move().fen
+    }
+
+    pub fn reset(&mut self) -> String {
+        self.replay.reset().fen
+    }
+
+    /// The moves of the game in standard algebraic notation
+    pub fn moves(&self) -> String {
+        let moves: Vec<String> = self
+            .replay
+            .moves()
+            .into_iter()
+            .map(|replay_move| replay_move.san)
+            .collect();
+        serde_json::to_string(&moves).unwrap()
+    }
+}